@@ -0,0 +1,148 @@
+/// What our abstraction choices cost, measured on a game small enough to
+/// best respond to exactly.
+///
+/// A strategy trained on an abstraction is exploitable for two reasons:
+/// training stopped before reaching an equilibrium, and the abstraction
+/// hides things the strategy should have played on. measure_abstraction_loss
+/// trains the same number of vector CFR iterations on a game and on an
+/// abstraction of it, then best responds to both in the full game (see
+/// best_response::abstracted_exploitability). The strategy trained on the
+/// full game is as close to its exact equilibrium as the budget gets, so
+/// whatever the abstracted one gives up on top is down to the abstraction.
+///
+/// The game is implementations::mini_auction, auction poker cut down to a
+/// 20 card deck and a few chips, abstracted the way we abstract the real
+/// thing:
+///
+///     gtcogs abstraction-loss 1000
+///
+/// Both trainings enumerate up to `max_deals` deals (sampling that many
+/// when there are more) and can sample the opponents' actions, see
+/// vector_cfr, so the loss can be checked with the settings auction poker
+/// itself would be trained with:
+///
+///     gtcogs abstraction-loss 1000 0 500 --sample-opponents
+use crate::algorithm::best_response::{abstracted_exploitability, exploitability};
+use crate::algorithm::vector_cfr::{VectorCFR, DEFAULT_MAX_DEALS};
+use crate::game_logic::action::Action;
+use crate::game_logic::state::State;
+use crate::game_logic::strategy::RegretStrategy;
+use crate::Utility;
+use rand::{rngs::StdRng, SeedableRng};
+use std::fmt;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AbstractionLoss {
+    pub iterations: usize,
+    /// Exploitability of the strategy trained on the full game
+    pub exact: Utility,
+    /// Exploitability in the full game of the one trained on the abstraction
+    pub abstracted: Utility,
+}
+
+impl AbstractionLoss {
+    /// What the abstraction costs against a best response, per hand
+    pub fn loss(&self) -> Utility {
+        self.abstracted - self.exact
+    }
+}
+
+impl fmt::Display for AbstractionLoss {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "After {} iterations, exploitable for {:.4} trained on the game and {:.4} trained on its abstraction: the abstraction costs {:+.4} a hand",
+            self.iterations,
+            self.exact,
+            self.abstracted,
+            self.loss()
+        )
+    }
+}
+
+/// How both games are trained
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Training {
+    pub iterations: usize,
+    pub seed: u64,
+    /// See VectorCFR::with_max_deals
+    pub max_deals: usize,
+    /// See VectorCFR::with_sampled_opponents
+    pub sample_opponents: bool,
+}
+
+impl Training {
+    pub fn new(iterations: usize, seed: u64) -> Self {
+        Training {
+            iterations,
+            seed,
+            max_deals: DEFAULT_MAX_DEALS,
+            sample_opponents: false,
+        }
+    }
+}
+
+fn train<A: Action, S: State<A>>(training: Training) -> Vec<Arc<RegretStrategy>> {
+    let strategies = vec![Arc::new(RegretStrategy::default()), Arc::new(RegretStrategy::default())];
+    let mut cfr = VectorCFR::<A, S>::new(strategies.clone());
+    cfr.with_max_deals(training.max_deals);
+    cfr.with_sampled_opponents(training.sample_opponents);
+    cfr.run_iterations(training.iterations, &mut StdRng::seed_from_u64(training.seed));
+    strategies
+}
+
+/// Trains `S` and `T`, an abstraction of `S` with the same actions, the
+/// same way and measures both in `S`
+pub fn measure_abstraction_loss<A: Action, S: State<A>, T: State<A>>(training: Training) -> AbstractionLoss {
+    let exact = exploitability::<A, S>(&train::<A, S>(training));
+    let abstracted = abstracted_exploitability::<A, S, T>(&train::<A, T>(training));
+    AbstractionLoss {
+        iterations: training.iterations,
+        exact,
+        abstracted,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::implementations::mini_auction::*;
+
+    #[derive(Clone, Debug)]
+    struct Tiny;
+
+    impl MiniAuctionConfig for Tiny {
+        const DECK_SIZE: u8 = 4;
+        const ANTE: u32 = 1;
+        const STACK: u32 = 1;
+        const RAISE_SIZES: &'static [u32] = &[1];
+        const MAX_RAISES: usize = 1;
+    }
+
+    /// Tiny where nobody sees their cards, only the bids
+    #[derive(Clone, Debug)]
+    struct Blind;
+
+    impl MiniAuctionConfig for Blind {
+        const DECK_SIZE: u8 = Tiny::DECK_SIZE;
+        const ANTE: u32 = Tiny::ANTE;
+        const STACK: u32 = Tiny::STACK;
+        const RAISE_SIZES: &'static [u32] = Tiny::RAISE_SIZES;
+        const MAX_RAISES: usize = Tiny::MAX_RAISES;
+        const STRENGTH_BUCKETS: u8 = 1;
+    }
+
+    #[test]
+    fn test_blind_abstraction_is_costly() {
+        type Exact = MiniAuctionState<Tiny>;
+
+        // No abstraction at all costs nothing
+        let same = measure_abstraction_loss::<MiniAuctionAction, Exact, Exact>(Training::new(300, 0));
+        assert!(same.loss().abs() < 1e-4, "{}", same);
+        assert!(same.exact > 0.0);
+
+        let blind = measure_abstraction_loss::<MiniAuctionAction, Exact, MiniAuctionState<Blind>>(Training::new(300, 0));
+        assert!(blind.loss() > 0.1, "{}", blind);
+    }
+}
@@ -0,0 +1,396 @@
+/// Training on several machines at once.
+///
+/// Every worker trains its own copy of the strategies with MCCFRParallel
+/// and after each round exchanges what changed (see StrategyDelta) with
+/// the others. Changes are purely additive, so every worker adding up the
+/// same deltas ends the round with the same strategies, whatever order
+/// they arrive in.
+///
+/// Deltas are exchanged through a coordinator over TCP
+///
+///     gtcogs coordinator <address> <workers> [<file name>]
+///     gtcogs worker <coordinator address> <rounds> <iterations per round>
+///
+/// or, with no coordinator at all, through a directory every worker can
+/// see (NFS say), each worker writing its delta of a round to a file and
+/// reading everyone else's:
+///
+///     gtcogs worker --dir <directory> <worker> <workers> <rounds> <iterations per round> [<timeout seconds>]
+///
+/// A worker gives up on the others if their deltas of a round take longer
+/// than the timeout, an hour unless given.
+///
+/// A worker deletes its file of a round once the files of the next round
+/// show everyone has read it, so only the files of the last round are left
+/// in the directory. Clear those before training in it again.
+///
+/// Workers must run the same number of rounds. With a coordinator the
+/// merged strategies are saved by the coordinator, otherwise by worker 0
+use crate::game_logic::action::{Action, GameMapper};
+use crate::game_logic::strategy::delta::StrategyDelta;
+use crate::game_logic::strategy::RegretStrategy;
+use std::fs;
+use std::io::{BufReader, BufWriter, ErrorKind};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// How long a worker waits between looks for the deltas of the others
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long a worker waits for the deltas of another before giving up on
+/// it, by default
+const WORKER_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// How a worker swaps its changes of a round for everyone else's
+pub trait Exchange {
+    /// Hands over this worker's deltas of `round`, one per player, and
+    /// returns the deltas of all the other workers added up
+    fn exchange(&mut self, round: usize, deltas: Vec<StrategyDelta>) -> Result<Vec<StrategyDelta>, String>;
+}
+
+fn write_deltas<W: std::io::Write>(writer: &mut W, deltas: &[StrategyDelta]) -> Result<(), String> {
+    writer.write_all(&(deltas.len() as u64).to_le_bytes()).map_err(|e| e.to_string())?;
+    for delta in deltas {
+        delta.write_to(writer)?;
+    }
+    Ok(())
+}
+
+/// None if the reader ended before the deltas started, as the stream of a
+/// worker that hung up does
+fn read_deltas<R: std::io::Read>(reader: &mut R) -> Result<Option<Vec<StrategyDelta>>, String> {
+    let mut count = [0u8; 8];
+    match reader.read_exact(&mut count) {
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.to_string()),
+        Ok(()) => {}
+    }
+    (0..u64::from_le_bytes(count)).map(|_| StrategyDelta::read_from(reader)).collect::<Result<_, _>>().map(Some)
+}
+
+/// The deltas of every worker but `skip` added up, player by player
+fn combine(all: &[Vec<StrategyDelta>], skip: Option<usize>) -> Vec<StrategyDelta> {
+    let players = all.iter().map(|deltas| deltas.len()).max().unwrap_or(0);
+    (0..players)
+        .map(|player| {
+            let of_player: Vec<StrategyDelta> = all
+                .iter()
+                .enumerate()
+                .filter(|(worker, _)| Some(*worker) != skip)
+                .filter_map(|(_, deltas)| deltas.get(player).cloned())
+                .collect();
+            StrategyDelta::combine(&of_player)
+        })
+        .collect()
+}
+
+/// A worker's connection to the coordinator
+pub struct TcpExchange {
+    stream: TcpStream,
+}
+
+impl TcpExchange {
+    pub fn connect(address: &str) -> Result<TcpExchange, String> {
+        let stream = TcpStream::connect(address).map_err(|e| format!("Could not reach {}: {}", address, e))?;
+        Ok(TcpExchange { stream })
+    }
+}
+
+impl Exchange for TcpExchange {
+    fn exchange(&mut self, _round: usize, deltas: Vec<StrategyDelta>) -> Result<Vec<StrategyDelta>, String> {
+        write_deltas(&mut BufWriter::new(&self.stream), &deltas)?;
+        read_deltas(&mut BufReader::new(&self.stream))?.ok_or_else(|| "The coordinator hung up".to_string())
+    }
+}
+
+/// Adds up the deltas of every worker each round and sends each the sum of
+/// the others' back, keeping a merged copy of the strategies to save
+pub struct Coordinator {
+    listener: TcpListener,
+    workers: usize,
+    pub strategies: Vec<RegretStrategy>,
+}
+
+impl Coordinator {
+    pub fn bind(address: &str, workers: usize, players: usize) -> Result<Coordinator, String> {
+        let listener = TcpListener::bind(address).map_err(|e| format!("Could not listen on {}: {}", address, e))?;
+        Ok(Coordinator {
+            listener,
+            workers,
+            strategies: (0..players).map(|_| RegretStrategy::default()).collect(),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, String> {
+        self.listener.local_addr().map_err(|e| e.to_string())
+    }
+
+    /// Waits for every worker, then merges rounds until all of them are
+    /// done (have hung up). The strategies are saved to `file_name` after
+    /// every round. Returns the number of rounds merged, or an error if a
+    /// worker's deltas break off part way
+    pub fn run<A: Action>(&mut self, file_name: Option<&str>) -> Result<usize, String> {
+        let mut streams = vec![];
+        while streams.len() < self.workers {
+            let (stream, address) = self.listener.accept().map_err(|e| e.to_string())?;
+            println!("Worker {} connected from {}", streams.len(), address);
+            streams.push(stream);
+        }
+        let mut rounds = 0;
+        loop {
+            let mut all = vec![];
+            let mut connected = vec![];
+            for stream in streams {
+                // A worker that hung up has finished its rounds
+                match read_deltas(&mut BufReader::new(&stream)) {
+                    Ok(Some(deltas)) => {
+                        all.push(deltas);
+                        connected.push(stream);
+                    }
+                    Ok(None) => println!("A worker left after {} rounds", rounds),
+                    Err(e) => return Err(format!("Lost a worker in round {}: {}", rounds, e)),
+                }
+            }
+            streams = connected;
+            if streams.is_empty() {
+                return Ok(rounds);
+            }
+            for (worker, stream) in streams.iter().enumerate() {
+                if let Err(e) = write_deltas(&mut BufWriter::new(stream), &combine(&all, Some(worker))) {
+                    println!("[WARNING] Could not send round {} to a worker: {}", rounds, e);
+                }
+            }
+            let combined = combine(&all, None);
+            for (strategy, delta) in self.strategies.iter().zip(&combined) {
+                strategy.apply(delta);
+            }
+            rounds += 1;
+            println!("Merged round {} of {} workers", rounds, all.len());
+            if let Some(file_name) = file_name {
+                self.write_to::<A>(file_name);
+            }
+        }
+    }
+
+    pub fn write_to<A: Action>(&self, file_name: &str) {
+        for (i, strategy) in self.strategies.iter().enumerate() {
+            let file = format!("{}_p{}.json", file_name, i);
+            strategy.save_table(&file, &GameMapper::<A>::new(None), A::abstraction_hash());
+        }
+    }
+}
+
+/// Exchanges deltas through files in a shared directory
+pub struct FileExchange {
+    directory: PathBuf,
+    worker: usize,
+    workers: usize,
+    timeout: Duration,
+}
+
+impl FileExchange {
+    pub fn new(directory: &str, worker: usize, workers: usize) -> Result<FileExchange, String> {
+        if worker >= workers {
+            return Err(format!("Worker {} of only {}", worker, workers));
+        }
+        fs::create_dir_all(directory).map_err(|e| format!("Could not create {}: {}", directory, e))?;
+        let exchange = FileExchange {
+            directory: PathBuf::from(directory),
+            worker,
+            workers,
+            timeout: WORKER_TIMEOUT,
+        };
+        // Files left by an earlier run would be read as this one's
+        if exchange.left_over()? {
+            return Err(format!("{} has deltas of worker {} from an earlier run, clear it first", directory, worker));
+        }
+        Ok(exchange)
+    }
+
+    /// Gives up on a worker whose deltas of a round are not in after
+    /// `timeout`, an hour by default
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn path(&self, round: usize, worker: usize) -> PathBuf {
+        self.directory.join(format!("round{}_worker{}.delta", round, worker))
+    }
+
+    /// Whether the directory has a file of this worker from any round
+    fn left_over(&self) -> Result<bool, String> {
+        let suffix = format!("_worker{}.delta", self.worker);
+        let entries = fs::read_dir(&self.directory).map_err(|e| format!("{}: {}", self.directory.display(), e))?;
+        Ok(entries.filter_map(|entry| entry.ok()).any(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with("round") && name.ends_with(&suffix)
+        }))
+    }
+
+    /// Opens the deltas of `worker` in `round` once they are there
+    fn wait_for(&self, round: usize, worker: usize) -> Result<fs::File, String> {
+        let path = self.path(round, worker);
+        let start = Instant::now();
+        loop {
+            match fs::File::open(&path) {
+                Ok(file) => return Ok(file),
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    if start.elapsed() >= self.timeout {
+                        return Err(format!(
+                            "Gave up on worker {} after waiting {:?} for its deltas of round {} in {}",
+                            worker,
+                            self.timeout,
+                            round,
+                            path.display()
+                        ));
+                    }
+                    std::thread::sleep(POLL_INTERVAL.min(self.timeout));
+                }
+                Err(e) => return Err(format!("{}: {}", path.display(), e)),
+            }
+        }
+    }
+}
+
+impl Exchange for FileExchange {
+    fn exchange(&mut self, round: usize, deltas: Vec<StrategyDelta>) -> Result<Vec<StrategyDelta>, String> {
+        // Written under another name first, the others only ever see
+        // complete files
+        let path = self.path(round, self.worker);
+        let partial = path.with_extension("partial");
+        let file = fs::File::create(&partial).map_err(|e| format!("{}: {}", partial.display(), e))?;
+        write_deltas(&mut BufWriter::new(file), &deltas)?;
+        fs::rename(&partial, &path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let mut all = vec![];
+        for worker in 0..self.workers {
+            let file = self.wait_for(round, worker)?;
+            let deltas = read_deltas(&mut BufReader::new(file))?;
+            all.push(deltas.ok_or_else(|| format!("{} is empty", self.path(round, worker).display()))?);
+        }
+
+        // Every worker wrote this round after reading the last one, so
+        // nobody needs this worker's file of the last round any more
+        if round > 0 {
+            let consumed = self.path(round - 1, self.worker);
+            match fs::remove_file(&consumed) {
+                Err(e) if e.kind() != ErrorKind::NotFound => println!("[WARNING] Could not delete {}: {}", consumed.display(), e),
+                _ => {}
+            }
+        }
+        Ok(combine(&all, Some(self.worker)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_logic::strategy::delta::DeltaEntry;
+    use crate::implementations::kuhn_poker::KuhnPokerAction;
+
+    fn deltas(worker: usize) -> Vec<StrategyDelta> {
+        let entry = DeltaEntry {
+            info_set: 9,
+            legal: 0b11,
+            length: 2,
+            regrets: Some(vec![(0, worker as f32), (1, 1.0)]),
+            policy: None,
+            visits: 1,
+        };
+        vec![
+            StrategyDelta { iterations: 10, entries: vec![entry], ..Default::default() },
+            StrategyDelta::default(),
+        ]
+    }
+
+    /// Worker `worker` gets back the deltas of the other one
+    fn check_merged(worker: usize, merged: &[StrategyDelta]) {
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].iterations, 10);
+        assert_eq!(merged[0].entries[0].regrets, deltas(1 - worker)[0].entries[0].regrets);
+        assert_eq!(merged[0].entries[0].visits, 1);
+    }
+
+    #[test]
+    fn test_workers_exchange_through_a_coordinator() {
+        let mut coordinator = Coordinator::bind("127.0.0.1:0", 2, 2).unwrap();
+        let address = coordinator.local_addr().unwrap().to_string();
+        let workers: Vec<_> = (0..2)
+            .map(|worker| {
+                let address = address.clone();
+                std::thread::spawn(move || {
+                    let mut exchange = TcpExchange::connect(&address).unwrap();
+                    for round in 0..2 {
+                        check_merged(worker, &exchange.exchange(round, deltas(worker)).unwrap());
+                    }
+                })
+            })
+            .collect();
+        assert_eq!(coordinator.run::<KuhnPokerAction>(None), Ok(2));
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(coordinator.strategies[0].regrets(&9), Some(vec![2.0, 4.0]));
+        assert_eq!(coordinator.strategies[0].iteration(), 40);
+    }
+
+    #[test]
+    fn test_workers_exchange_through_files() {
+        let directory = std::env::temp_dir().join("gtcogs_distributed_test");
+        let _ = fs::remove_dir_all(&directory);
+        let directory = directory.to_str().unwrap().to_string();
+        let workers: Vec<_> = (0..2)
+            .map(|worker| {
+                let directory = directory.clone();
+                std::thread::spawn(move || {
+                    let mut exchange = FileExchange::new(&directory, worker, 2).unwrap();
+                    for round in 0..3 {
+                        check_merged(worker, &exchange.exchange(round, deltas(worker)).unwrap());
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert!(FileExchange::new(&directory, 2, 2).is_err());
+
+        // Only the files of the last round are left, and a new run in the
+        // directory is refused until they are cleared
+        let mut left: Vec<String> = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        left.sort();
+        assert_eq!(left, vec!["round2_worker0.delta", "round2_worker1.delta"]);
+        assert!(FileExchange::new(&directory, 0, 2).is_err());
+    }
+
+    #[test]
+    fn test_file_exchange_gives_up_on_a_missing_worker() {
+        let directory = std::env::temp_dir().join("gtcogs_distributed_timeout_test");
+        let _ = fs::remove_dir_all(&directory);
+        let mut exchange = FileExchange::new(directory.to_str().unwrap(), 0, 2)
+            .unwrap()
+            .with_timeout(Duration::from_millis(50));
+        let error = exchange.exchange(0, deltas(0)).unwrap_err();
+        assert!(error.starts_with("Gave up on worker 1"), "{}", error);
+    }
+
+    #[test]
+    fn test_coordinator_fails_on_a_broken_worker() {
+        let mut coordinator = Coordinator::bind("127.0.0.1:0", 1, 2).unwrap();
+        let address = coordinator.local_addr().unwrap().to_string();
+        let worker = std::thread::spawn(move || {
+            // Says two deltas follow, then hangs up
+            let mut stream = TcpStream::connect(&address).unwrap();
+            std::io::Write::write_all(&mut stream, &2u64.to_le_bytes()).unwrap();
+        });
+        let error = coordinator.run::<KuhnPokerAction>(None).unwrap_err();
+        assert!(error.starts_with("Lost a worker in round 0"), "{}", error);
+        worker.join().unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+/// Walks the abstracted game (GameMapper applied, exactly as MCCFR sees it)
+/// without touching any strategy, to find out how many information sets
+/// training will create and roughly how much memory they will take before
+/// committing to a multi-day run.
+///
+/// The tree is far too big to enumerate, so every iteration samples a
+/// single path with uniformly random actions. The number of information
+/// sets first seen in the last tenth of the iterations tells whether the
+/// count has levelled off or is still growing. `gtcogs dry-run` runs it
+/// on auction poker
+use crate::game_logic::action::{Action, ActionIndex, GameMapper};
+use crate::game_logic::state::{ActivePlayer, State};
+use crate::game_logic::strategy::compact::CompactDistribution;
+use crate::game_logic::strategy::CondensedInfoSet;
+use crate::Game;
+use rand::Rng;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Bookkeeping a hash map spends per entry on top of its key and value
+const MAP_ENTRY_OVERHEAD: usize = 16;
+
+#[derive(Debug, Clone, Default)]
+pub struct DryRunReport {
+    pub iterations: usize,
+    pub nodes_traversed: usize,
+    /// Reachable information sets found for every player
+    pub info_sets: Vec<usize>,
+    /// Information sets first found in the last tenth of the iterations
+    pub found_late: usize,
+    /// Number of entries in every full policy and regret distribution
+    pub policy_length: usize,
+    /// Legal actions summed over every information set found, which is
+    /// what the (compacted) distributions actually store
+    pub legal_actions: usize,
+}
+
+impl DryRunReport {
+    pub fn total_info_sets(&self) -> usize {
+        self.info_sets.iter().sum()
+    }
+
+    /// Estimated memory of a RegretStrategy holding these information sets:
+    /// a policy and a regret distribution plus a visit count for each
+    pub fn estimated_bytes(&self) -> usize {
+        strategy_bytes(self.total_info_sets(), self.legal_actions)
+    }
+}
+
+impl fmt::Display for DryRunReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} sampled paths, {} nodes", self.iterations, self.nodes_traversed)?;
+        for (player_num, info_sets) in self.info_sets.iter().enumerate() {
+            writeln!(f, "player {}: {} infosets", player_num, info_sets)?;
+        }
+        writeln!(f, "{} legal actions of {} per infoset at most", self.legal_actions, self.policy_length)?;
+        writeln!(f, "{} infosets found in the last tenth of the paths", self.found_late)?;
+        write!(f, "training needs about {} MB", self.estimated_bytes() / 1024 / 1024)
+    }
+}
+
+/// Memory of a RegretStrategy holding `info_sets` information sets with
+/// `legal_actions` legal actions between them
+pub fn strategy_bytes(info_sets: usize, legal_actions: usize) -> usize {
+    let key = std::mem::size_of::<CondensedInfoSet>() + MAP_ENTRY_OVERHEAD;
+    let distributions = 2 * (info_sets * (key + CompactDistribution::size_of(0)) + 4 * legal_actions);
+    let visits = info_sets * (key + 4);
+    distributions + visits
+}
+
+pub fn dry_run<A: Action, S: State<A>, R: Rng>(game_mapper: &GameMapper<A>, iterations: usize, rng: &mut R) -> DryRunReport {
+    let mut found: Vec<HashSet<CondensedInfoSet>> = vec![];
+    let mut report = DryRunReport {
+        iterations,
+        policy_length: A::max_index() as usize,
+        ..Default::default()
+    };
+    let late = iterations - iterations / 10;
+
+    for iteration in 0..iterations {
+        let mut game = Game::<A, S>::new();
+        if found.is_empty() {
+            found = vec![HashSet::new(); game.num_regular_players()];
+        }
+        let mut depth = 0;
+        loop {
+            match game.active_player() {
+                ActivePlayer::Terminal(_) => {
+                    report.nodes_traversed += 1;
+                    break;
+                }
+                ActivePlayer::Chance(actions) => {
+                    report.nodes_traversed += 1;
+                    let (action, default_index) = actions.sample_and_index(rng);
+                    let (action, _) = game_mapper.map_and_index(action, depth, default_index as ActionIndex);
+                    game.play(&action);
+                }
+                ActivePlayer::Marker(action) => {
+                    game.play(&action);
+                }
+                ActivePlayer::Player(player_num, actions) => {
+                    report.nodes_traversed += 1;
+                    let player_num = player_num as usize;
+                    let actions = game_mapper.map_actions(&actions, depth);
+                    if found[player_num].insert(game.get_information_set(player_num)) {
+                        report.legal_actions += actions.len();
+                        if iteration >= late {
+                            report.found_late += 1;
+                        }
+                    }
+                    let action = actions[rng.gen_range(0, actions.len())].clone();
+                    game.play(&action);
+                }
+            }
+            depth += 1;
+        }
+    }
+
+    report.info_sets = found.iter().map(|info_sets| info_sets.len()).collect();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::implementations::one_card_poker::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_dry_run_finds_every_one_card_poker_info_set() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let game_mapper = GameMapper::new(None);
+        let report = dry_run::<OneCardPokerAction, OneCardPokerState<KuhnConfig>, _>(&game_mapper, 2000, &mut rng);
+        assert_eq!(report.info_sets.len(), 2);
+        assert!(report.total_info_sets() > 0);
+        // The game is tiny, everything is found long before the end
+        assert_eq!(report.found_late, 0);
+        assert!(report.legal_actions >= report.total_info_sets());
+        assert!(report.estimated_bytes() > 4 * report.legal_actions);
+        assert!(report.to_string().starts_with("2000 sampled paths"));
+    }
+}
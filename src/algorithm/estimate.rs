@@ -0,0 +1,249 @@
+/// How big the abstraction makes a strategy, worked out from the feature
+/// schema and the betting abstraction alone, so a config that can't fit is
+/// turned down before a multi-day run rather than after.
+///
+/// Two limits matter: the blueprint file we can submit (10MB) and the
+/// memory we train in (2GB). For every round the worst case is every value
+/// of every feature combined. The expected count takes out combinations
+/// that can't happen: only 169 of the rank and suitedness pairs are real
+/// hands, and with the pot and our stack known the opponent's stack is
+/// too (up to rounding), since the chips always add up to the max pot. It is
+/// still an upper bound on what training finds, see algorithm::dry_run for
+/// a count of what is actually reachable.
+///
+/// Training refuses to start on an abstraction over the limits unless
+/// GTCOGS_IGNORE_LIMITS=1 says to go ahead anyway
+use crate::algorithm::dry_run::strategy_bytes;
+use crate::constants::NUM_REGULAR_PLAYERS;
+use crate::game_logic::action::{Action, ActionIndex};
+use crate::game_logic::schema::{FeatureKind, FeatureSchema};
+use crate::game_logic::strategy::blueprint::CondensedPolicyDistribution;
+use crate::game_logic::strategy::CondensedInfoSet;
+use crate::game_logic::visibility::Round;
+use crate::implementations::auction::*;
+use std::fmt;
+
+/// Hands of two hole cards up to suits
+const STARTING_HANDS: u64 = 169;
+/// Values the opponent's stack can take given the pot and ours
+const DEPENDENT_SPREAD: u64 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub file_bytes: usize,
+    pub memory_bytes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            file_bytes: 10 * 1024 * 1024,
+            memory_bytes: 2 * 1024 * 1024 * 1024,
+        }
+    }
+}
+
+/// The sizes of the betting abstraction, see RAISE_BUCKETS and bid_buckets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BetAbstraction {
+    pub raises: usize,
+    pub bids: usize,
+}
+
+impl BetAbstraction {
+    pub fn current() -> BetAbstraction {
+        let actions: Vec<AuctionPokerAction> = (0..AuctionPokerAction::max_index())
+            .map(|index: ActionIndex| AuctionPokerAction::from(index))
+            .collect();
+        BetAbstraction {
+            raises: actions.iter().filter(|action| matches!(action, AuctionPokerAction::Raise(_))).count(),
+            bids: actions.iter().filter(|action| matches!(action, AuctionPokerAction::Bid(_))).count(),
+        }
+    }
+
+    /// Most legal actions at a decision of `round`: every bid in the
+    /// auction, otherwise every raise and two of fold, call and check
+    fn legal_actions(&self, round: &Round) -> usize {
+        match round {
+            Round::Auction => self.bids,
+            _ => 2 + self.raises,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RoundEstimate {
+    pub round: Round,
+    /// Infosets of every player if every feature value goes with every other
+    pub worst_case: u64,
+    /// Infosets of every player leaving out what can't happen
+    pub expected: u64,
+    pub legal_actions: usize,
+}
+
+impl RoundEstimate {
+    pub fn new(round: Round, bets: &BetAbstraction) -> RoundEstimate {
+        let schema = FeatureSchema::for_round(round.clone());
+        let span = |kind: FeatureKind| -> Vec<u64> {
+            schema
+                .features
+                .iter()
+                .filter(|spec| spec.kind == kind)
+                .map(|spec| (spec.max - spec.min) as u64 + 1)
+                .collect()
+        };
+        // The round slot is fixed within a round
+        let worst_case: u64 = schema
+            .features
+            .iter()
+            .filter(|spec| spec.kind != FeatureKind::Order)
+            .map(|spec| (spec.max - spec.min) as u64 + 1)
+            .product();
+
+        let mut expected = worst_case;
+        if let ([ranks], [suited]) = (&span(FeatureKind::Ranks)[..], &span(FeatureKind::Suited)[..]) {
+            expected = expected / (ranks * suited) * STARTING_HANDS;
+        }
+        let stacks = span(FeatureKind::Stack);
+        if !span(FeatureKind::Pot).is_empty() && stacks.len() == 2 {
+            expected = expected / stacks[1] * DEPENDENT_SPREAD;
+        }
+        let players = NUM_REGULAR_PLAYERS as u64;
+        RoundEstimate {
+            legal_actions: bets.legal_actions(&round),
+            round,
+            worst_case: worst_case * players,
+            expected: expected * players,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AbstractionEstimate {
+    pub bets: BetAbstraction,
+    pub rounds: Vec<RoundEstimate>,
+}
+
+impl AbstractionEstimate {
+    /// The estimate for the abstraction this binary was built with
+    pub fn current() -> AbstractionEstimate {
+        AbstractionEstimate::new(BetAbstraction::current())
+    }
+
+    pub fn new(bets: BetAbstraction) -> AbstractionEstimate {
+        let rounds = [Round::PreFlop, Round::Auction, Round::Flop, Round::Turn, Round::River];
+        AbstractionEstimate {
+            rounds: rounds.into_iter().map(|round| RoundEstimate::new(round, &bets)).collect(),
+            bets,
+        }
+    }
+
+    pub fn info_sets(&self, worst_case: bool) -> u64 {
+        self.rounds
+            .iter()
+            .map(|round| match worst_case {
+                true => round.worst_case,
+                false => round.expected,
+            })
+            .sum()
+    }
+
+    /// Memory of the regret strategies while training
+    pub fn training_bytes(&self, worst_case: bool) -> u64 {
+        self.rounds
+            .iter()
+            .map(|round| {
+                let info_sets = match worst_case {
+                    true => round.worst_case,
+                    false => round.expected,
+                };
+                let legal_actions = info_sets.saturating_mul(round.legal_actions as u64);
+                strategy_bytes(info_sets as usize, legal_actions as usize) as u64
+            })
+            .fold(0, u64::saturating_add)
+    }
+
+    /// Size of the blueprint file, every infoset with its compressed policy
+    pub fn file_bytes(&self, worst_case: bool) -> u64 {
+        let entry = std::mem::size_of::<CondensedInfoSet>() + std::mem::size_of::<CondensedPolicyDistribution>();
+        self.info_sets(worst_case).saturating_mul(entry as u64)
+    }
+
+    /// Fails if the expected strategy doesn't fit in `limits`
+    pub fn check(&self, limits: &Limits) -> Result<(), String> {
+        let file = self.file_bytes(false);
+        if file > limits.file_bytes as u64 {
+            return Err(format!(
+                "The blueprint would take about {} MB, more than the {} MB allowed",
+                file / 1024 / 1024,
+                limits.file_bytes / 1024 / 1024
+            ));
+        }
+        let memory = self.training_bytes(false);
+        if memory > limits.memory_bytes as u64 {
+            return Err(format!(
+                "Training would take about {} MB, more than the {} MB allowed",
+                memory / 1024 / 1024,
+                limits.memory_bytes / 1024 / 1024
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Whether GTCOGS_IGNORE_LIMITS lets training start over the limits
+pub fn ignore_limits() -> bool {
+    std::env::var("GTCOGS_IGNORE_LIMITS").map(|value| value == "1").unwrap_or(false)
+}
+
+impl fmt::Display for AbstractionEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} raise and {} bid buckets", self.bets.raises, self.bets.bids)?;
+        writeln!(f, "{:<10}{:>16}{:>16}{:>8}", "round", "worst case", "expected", "legal")?;
+        for round in &self.rounds {
+            let name = format!("{:?}", round.round);
+            writeln!(f, "{:<10}{:>16}{:>16}{:>8}", name, round.worst_case, round.expected, round.legal_actions)?;
+        }
+        writeln!(f, "{:<10}{:>16}{:>16}", "infosets", self.info_sets(true), self.info_sets(false))?;
+        let mb = |bytes: u64| format!("{} MB", bytes / 1024 / 1024);
+        writeln!(f, "{:<10}{:>16}{:>16}", "training", mb(self.training_bytes(true)), mb(self.training_bytes(false)))?;
+        write!(f, "{:<10}{:>16}{:>16}", "file", mb(self.file_bytes(true)), mb(self.file_bytes(false)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_follows_the_schema() {
+        let estimate = AbstractionEstimate::current();
+        let preflop = &estimate.rounds[0];
+        // 169 starting hands, every aggression and pot value, for each player
+        let schema = FeatureSchema::for_round(Round::PreFlop);
+        let rest: u64 = schema.features[3..].iter().map(|spec| (spec.max - spec.min) as u64 + 1).product();
+        assert_eq!(preflop.expected, 2 * 169 * rest);
+        assert!(preflop.worst_case > preflop.expected);
+        assert_eq!(estimate.rounds[1].worst_case, estimate.rounds[1].expected);
+        assert_eq!(estimate.rounds[1].legal_actions, estimate.bets.bids);
+
+        // More bid buckets only change the auction
+        let finer = AbstractionEstimate::new(BetAbstraction {
+            bids: estimate.bets.bids * 2,
+            ..estimate.bets
+        });
+        assert!(finer.training_bytes(false) > estimate.training_bytes(false));
+        assert_eq!(finer.file_bytes(false), estimate.file_bytes(false));
+
+        let tiny = Limits {
+            file_bytes: 1024,
+            memory_bytes: usize::MAX,
+        };
+        assert!(estimate.check(&tiny).is_err());
+        let huge = Limits {
+            file_bytes: usize::MAX,
+            memory_bytes: usize::MAX,
+        };
+        assert!(estimate.check(&huge).is_ok());
+    }
+}
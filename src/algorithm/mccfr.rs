@@ -1,15 +1,20 @@
+use crate::algorithm::placement::ThreadPlacement;
 use crate::constants::MAX_GAME_DEPTH;
+use crate::context::{default_context, Context};
 use crate::game_logic::action::GameMapper;
 use crate::game_logic::action::{Action, ActionIndex};
+use crate::game_logic::schema::FeatureSchema;
 use crate::game_logic::state::{ActivePlayer, State};
 use crate::game_logic::strategy::*;
-use crate::game_logic::visibility::{History, Feature};
+use crate::game_logic::visibility::History;
 use crate::implementations::auction::Card;
-use crate::{Categorical, Game};
+use crate::{Categorical, Game, Utility};
 use rand::Rng;
 use serde_json::json;
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
 #[derive(Clone, Debug)]
 pub struct MCCFR<A: Action, S: State<A>> {
@@ -21,6 +26,260 @@ pub struct MCCFR<A: Action, S: State<A>> {
     bonus: f32,
     exploration: f32,
     threshold: f32,
+    /// Actions of the updated player are only explored while the sampling
+    /// reach stays above this
+    min_reach: f32,
+    /// Iterations start here rather than at the start of the game
+    root: Option<SubtreeRoot<A>>,
+    chance_sampling: ChanceSampling,
+    /// Opponent decisions explored uniformly since none of their legal
+    /// actions had any regret left
+    pub masked_fallbacks: usize,
+    /// Opponent decisions explored uniformly since their regrets couldn't
+    /// be sampled from at all, see GTCOGS_STRICT_DISTRIBUTIONS
+    pub invalid_distributions: usize,
+    /// Iterations are abandoned at this depth, see with_max_depth
+    max_depth: usize,
+    /// Iterations abandoned for reaching max_depth
+    pub aborted_hands: usize,
+    /// The rules every iteration's game plays by, see with_context
+    context: Arc<Context>,
+    pub stats: RunnerStats,
+}
+
+/// What a runner has done, to tell how evenly MCCFRParallel spreads the
+/// work and how much the runners get in each other's way
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RunnerStats {
+    pub iterations: usize,
+    pub nodes: usize,
+    /// Depths of the terminal nodes reached, summed, and how many
+    pub depth_total: usize,
+    pub terminals: usize,
+    /// Spent running iterations
+    pub time: Duration,
+    /// Spent in the shared strategy maps, waiting on the other runners'
+    /// locks included
+    pub strategy_time: Duration,
+}
+
+impl RunnerStats {
+    pub fn nodes_per_second(&self) -> f64 {
+        self.nodes as f64 / self.time.as_secs_f64().max(1e-9)
+    }
+
+    pub fn average_depth(&self) -> f64 {
+        self.depth_total as f64 / self.terminals.max(1) as f64
+    }
+
+    /// How much of the runner's time went to the strategy maps
+    pub fn strategy_share(&self) -> f64 {
+        self.strategy_time.as_secs_f64() / self.time.as_secs_f64().max(1e-9)
+    }
+
+    /// What was done since `before`
+    pub fn since(&self, before: &RunnerStats) -> RunnerStats {
+        RunnerStats {
+            iterations: self.iterations - before.iterations,
+            nodes: self.nodes - before.nodes,
+            depth_total: self.depth_total - before.depth_total,
+            terminals: self.terminals - before.terminals,
+            time: self.time.saturating_sub(before.time),
+            strategy_time: self.strategy_time.saturating_sub(before.strategy_time),
+        }
+    }
+}
+
+impl fmt::Display for RunnerStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} iterations in {:.1?}, {:.0} nodes/s, depth {:.1}, {:.0}% in the strategy maps",
+            self.iterations,
+            self.time,
+            self.nodes_per_second(),
+            self.average_depth(),
+            100.0 * self.strategy_share()
+        )
+    }
+}
+
+/// `f`, adding how long it took to `time`
+fn timed<T>(time: &mut Duration, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let value = f();
+    *time += start.elapsed();
+    value
+}
+
+/// How chance outcomes are sampled during training
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChanceSampling {
+    /// By the game's chance probabilities, samples need no correction
+    Proportional,
+    /// Every outcome equally likely, so rare outcomes (say, boards a board
+    /// abstraction weighs lightly) still get trained. Each sample is
+    /// importance weighted by its true probability over its sampling
+    /// probability to keep the regret estimates unbiased
+    Uniform,
+}
+
+/// A sampled chance outcome, its index and the importance weight of the
+/// sample (the true probability of the outcome over the probability it was
+/// sampled with)
+fn sample_chance<A: Clone, R: Rng>(actions: &Categorical<A>, sampling: ChanceSampling, rng: &mut R) -> (A, usize, f32) {
+    match sampling {
+        ChanceSampling::Proportional => {
+            let (action, index) = actions.sample_and_index(rng);
+            (action, index, 1.0)
+        }
+        ChanceSampling::Uniform => {
+            let outcomes = actions.items().len();
+            let index = rng.gen_range(0, outcomes);
+            let weight = actions.probs()[index] * outcomes as f32;
+            (actions.items()[index].clone(), index, weight)
+        }
+    }
+}
+
+/// A public state to train from instead of the start of the game, to patch
+/// a specific line. `line` is every public action leading there in order
+/// (community cards, bets, bids...). The private chance outcomes along the
+/// way are sampled and each sample is weighted by how likely `blueprint`
+/// makes the opponents' actions on the line with it, so the subtree is
+/// trained against the range the blueprint actually reaches it with
+#[derive(Debug, Clone)]
+pub struct SubtreeRoot<A: Action> {
+    pub line: Vec<A>,
+    /// Policies of every player, indexed by player
+    pub blueprint: Vec<Arc<RegretStrategy>>,
+}
+
+/// Samples of private outcomes tried before giving up on reaching a root
+const MAX_ROOT_ATTEMPTS: usize = 10_000;
+
+impl<A: Action> SubtreeRoot<A> {
+    /// How likely the blueprint of `player` is to play the action of
+    /// bucket `index` at `info_set`, uniform over `legal` buckets where it
+    /// knows nothing
+    fn probability(&self, player: usize, info_set: CondensedInfoSet, index: ActionIndex, legal: usize) -> f32 {
+        let policy = self.blueprint[player].policy(&info_set);
+        let total: f32 = policy.as_ref().map_or(0.0, |policy| policy.iter().sum());
+        match policy {
+            Some(policy) if total > 0.0 => policy.get(index as usize).map_or(0.0, |p| p / total),
+            _ => 1.0 / legal as f32,
+        }
+    }
+
+    /// Plays `game` down the line, sampling every chance outcome not on it.
+    /// Returns the depth reached and the reach of the opponents of
+    /// `updated_player`, None if the samples made the line impossible
+    fn replay<S: State<A>, R: Rng>(
+        &self,
+        game: &mut Game<A, S>,
+        game_mapper: &GameMapper<A>,
+        updated_player: usize,
+        rng: &mut R,
+    ) -> Option<(usize, f32)> {
+        let mut depth = 0;
+        let mut reach = 1.0;
+        let mut line = self.line.iter().peekable();
+        while let Some(next) = line.peek() {
+            match game.active_player() {
+                ActivePlayer::Terminal(_) => return None,
+                ActivePlayer::Marker(action) => game.play(&action),
+                ActivePlayer::Chance(actions) if actions.items().contains(next) => {
+                    game.play(next);
+                    line.next();
+                }
+                ActivePlayer::Chance(actions) => {
+                    let (action, index) = actions.sample_and_index(rng);
+                    let (action, _) = game_mapper.map_and_index(action, depth, index as ActionIndex);
+                    game.play(&action);
+                }
+                ActivePlayer::Player(player_num, actions) => {
+                    if !actions.contains(next) {
+                        return None;
+                    }
+                    let player_num = player_num as usize;
+                    if player_num != updated_player {
+                        // The blueprint is indexed by the buckets the game
+                        // mapper puts the actions in, as in training
+                        let info_set = game.get_information_set(player_num);
+                        let index = game_mapper.map_action((*next).clone(), depth).index();
+                        let legal = game_mapper.map_actions(&actions, depth).len();
+                        reach *= self.probability(player_num, info_set, index, legal);
+                    }
+                    game.play(next);
+                    line.next();
+                }
+            }
+            depth += 1;
+        }
+        Some((depth, reach))
+    }
+}
+
+/// Sampling parameters for one phase of training, see average_sampling
+#[derive(Debug, Clone)]
+pub struct PhaseConfig {
+    pub exploration: f32,
+    pub bonus: f32,
+    pub threshold: f32,
+    /// Stop exploring subtrees the sampling is less likely than this to reach
+    pub min_reach: f32,
+    /// Info sets visited fewer times than this when the phase starts are
+    /// frozen for the rest of training, 0 freezes nothing
+    pub freeze_below_visits: u32,
+}
+
+impl Default for PhaseConfig {
+    fn default() -> Self {
+        PhaseConfig {
+            exploration: 0.6,
+            bonus: 100.0,
+            threshold: 10000.0,
+            min_reach: 0.0,
+            freeze_below_visits: 0,
+        }
+    }
+}
+
+/// Two phase curriculum: broad exploration for the first `explore_share` of
+/// the iterations, then exploit heavy refinement of the parts of the tree
+/// that are actually reached. See MCCFRParallel::run_config
+#[derive(Debug, Clone)]
+pub struct MccfrConfig {
+    pub explore: PhaseConfig,
+    pub refine: PhaseConfig,
+    pub explore_share: f32,
+    /// Where the parallel trainer runs its threads
+    pub placement: ThreadPlacement,
+}
+
+impl Default for MccfrConfig {
+    fn default() -> Self {
+        MccfrConfig {
+            explore: PhaseConfig::default(),
+            refine: PhaseConfig {
+                exploration: 0.05,
+                bonus: 10.0,
+                threshold: 10000.0,
+                min_reach: 1e-3,
+                freeze_below_visits: 10,
+            },
+            explore_share: 0.5,
+            placement: ThreadPlacement::default(),
+        }
+    }
+}
+
+impl MccfrConfig {
+    /// Iterations spent in each phase
+    pub fn split(&self, iterations: usize) -> (usize, usize) {
+        let explore = (iterations as f32 * self.explore_share.clamp(0.0, 1.0)).round() as usize;
+        (explore, iterations - explore)
+    }
 }
 
 /// [Neal] Represents the state information necessary to run iterations on MCCFR
@@ -47,29 +306,105 @@ impl<A: Action, S: State<A>> MCCFR<A, S> {
             bonus: 100.0, // bonus to exploration, Set to 0.0 and threshold to 1.0 for MCCFR Outcome Sampling
             exploration: 0.6,
             threshold: 10000.0,
+            min_reach: 0.0,
+            root: None,
+            chance_sampling: ChanceSampling::Proportional,
+            masked_fallbacks: 0,
+            invalid_distributions: 0,
+            max_depth: MAX_GAME_DEPTH,
+            aborted_hands: 0,
+            stats: RunnerStats::default(),
+            context: default_context(),
         }
     }
 
+    /// Train `strategies` from now on instead
+    pub fn with_strategies(&mut self, strategies: Vec<Arc<RegretStrategy>>) {
+        self.strategies = strategies;
+    }
+
+    pub fn with_chance_sampling(&mut self, chance_sampling: ChanceSampling) {
+        self.chance_sampling = chance_sampling;
+    }
+
+    /// Abandon any iteration reaching `max_depth` actions, counting it in
+    /// aborted_hands instead of following a cycling state machine into a
+    /// panic. At most MAX_GAME_DEPTH, the game mapper has no deeper maps
+    pub fn with_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth.min(MAX_GAME_DEPTH);
+    }
+
+    /// Play every iteration by `context`'s rules from now on. The game
+    /// given to new is only the first iteration's
+    pub fn with_context(&mut self, context: Arc<Context>) {
+        self.context = context;
+    }
+
+    /// Train only the subtree below `root` from now on
+    pub fn with_subtree_root(&mut self, root: SubtreeRoot<A>) {
+        self.root = Some(root);
+    }
+
+    /// Starts a new game for an iteration, played down to the subtree root
+    /// if there is one. Returns the depth the iteration starts at and the
+    /// opponents' reach of it
+    fn start_iteration<R: Rng>(&mut self, rng: &mut R, updated_player: usize) -> (usize, f32) {
+        for _ in 0..MAX_ROOT_ATTEMPTS {
+            self.game = Game::<_, _>::with_context(&self.context).with_max_actions(self.max_depth);
+            let root = match &self.root {
+                Some(root) => root,
+                None => return (0, 1.0),
+            };
+            match root.replay(&mut self.game, &self.game_mapper, updated_player, rng) {
+                Some((depth, reach)) if reach > 0.0 => return (depth, reach),
+                _ => continue,
+            }
+        }
+        panic!("Could not reach the subtree root, is the line possible at all?");
+    }
+
+    /// Sample with the parameters of `phase` from now on, all but its
+    /// exploration: that is the epsilon passed to run_iterations, which
+    /// MCCFRParallel::run_config takes from the phase
+    pub fn configure(&mut self, phase: &PhaseConfig) {
+        self.bonus = phase.bonus;
+        self.threshold = phase.threshold;
+        self.min_reach = phase.min_reach;
+    }
+
     pub fn with_game_mapper(&mut self, game_mapper: GameMapper<A>) {
         self.game_mapper = game_mapper;
     }
 
     pub fn write_to(&self, file_name: &str) {
+        let abstraction = self.abstraction_hash();
         for i in 0..self.game.num_regular_players() {
             let file = format!("{}{}", file_name.to_owned(), format!("_p{}.json", i));
-            self.strategies[i].save_table_json(&file, &self.game_mapper);
+            self.strategies[i].save_table(&file, &self.game_mapper, abstraction);
         }
     }
 
+    /// The abstraction the strategies are trained under, that of the game
+    /// of the context
+    pub fn abstraction_hash(&self) -> u64 {
+        S::with_context(&self.context).abstraction_hash()
+    }
+
     /// [Neal] Run the MCCFR iterations as specificed
     pub fn run_iterations<R: Rng>(&mut self, iterations: usize, epsilon: f32, rng: &mut R) {
         self.exploration = epsilon;
+        let start = Instant::now();
+        let nodes = self.nodes_traversed;
         for i in 0..iterations {
             for player in 0..self.game.num_regular_players() {
-                self.game = Game::<_, _>::new();
-                self.run_averaging_iteration(rng, player, 0, 1.0);
+                let (depth, reach) = self.start_iteration(rng, player);
+                self.run_averaging_iteration(rng, player, depth, 1.0, reach);
             }
             self.iterations += 1;
+            self.stats.iterations += 1;
+            for strategy in &self.strategies {
+                strategy.tick();
+            }
             if i % 1 == 0 {
                 println!(
                     "Iteration: {}, Nodes Traversed: {}, strategies[0] size: {}",
@@ -79,171 +414,290 @@ impl<A: Action, S: State<A>> MCCFR<A, S> {
                 );
             }
         }
+        self.stats.time += start.elapsed();
+        self.stats.nodes += self.nodes_traversed - nodes;
     }
 
+    /// One iteration from the current game on, returning the sampled
+    /// value of the game to `updated_player`.
+    ///
+    /// Only the decisions of the updated player explore more than one
+    /// action, everything else follows a single sampled path. Those
+    /// decisions are kept on an explicit stack of Frames rather than the
+    /// thread's stack, so the depth of the game is no concern for the
+    /// runner threads.
+    ///
+    /// `q` is the probability of sampling the path this far, which min_reach
+    /// prunes by, and `reach` the opponents' reach of the starting point
+    /// (below 1 only from a subtree root), which weighs every value
     pub fn run_averaging_iteration<R: Rng>(
         &mut self,
         rng: &mut R,
         updated_player: usize,
         depth: usize,
         q: f32, // Probability for bookkeeping a la AS MCCFR paper
-    ) -> f32 {
-
-        match self.game.active_player() {
-            ActivePlayer::Terminal(utilities) => {
-                self.nodes_traversed += 1;
-                if self.nodes_traversed % 100000 == 0 {
-                    println!("Iteration: {}, Nodes Traversed: {}", self.iterations, self.nodes_traversed);
+        reach: f32,
+    ) -> Utility {
+        let mut stack: Vec<Frame<A, S>> = vec![];
+        let (mut depth, mut q) = (depth, q);
+        let mut value: Option<Utility> = None;
+        loop {
+            if value.is_none() {
+                match self.descend(rng, updated_player, depth, q, reach) {
+                    Descent::Terminal(terminal) => value = Some(terminal),
+                    Descent::Decision(frame) => stack.push(frame),
                 }
-                utilities[updated_player] / q
             }
-            ActivePlayer::Chance(actions) => {
-                self.nodes_traversed += 1;
-                let (action, default_index) = actions.sample_and_index(rng);
-                let default_index = default_index as ActionIndex;
-                let (action, index) = self.game_mapper.map_and_index(action, depth, default_index);
-                self.game.play(&action);
-                self.run_averaging_iteration(rng, updated_player, depth + 1, q)
+            let frame = match stack.last_mut() {
+                Some(frame) => frame,
+                None => return value.expect("Nothing explored"),
+            };
+            // The action explored last is done with
+            if let Some(value) = value.take() {
+                if let Some(saved) = frame.saved.take() {
+                    self.game = saved;
+                }
+                frame.regret_updates.push(value);
             }
-            ActivePlayer::Marker(action) => {
-                self.game.play(&action);
-                self.run_averaging_iteration(rng, updated_player, depth + 1, q)
+            match self.next_child(frame, rng) {
+                Some((child_depth, child_q)) => (depth, q) = (child_depth, child_q),
+                None => {
+                    let frame = stack.pop().unwrap();
+                    value = Some(self.finish(frame));
+                }
             }
+        }
+    }
 
-            ActivePlayer::Player(player_num, actions) => {
-                self.nodes_traversed += 1;
-                if self.nodes_traversed % 100000 == 0 {
-                    println!("Iteration: {}, Nodes Traversed: {}", self.iterations, self.nodes_traversed);
+    /// Follows the single sampled path from the current game down to a
+    /// terminal node or a decision of the updated player
+    fn descend<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        updated_player: usize,
+        mut depth: usize,
+        mut q: f32,
+        reach: f32,
+    ) -> Descent<A, S> {
+        loop {
+            if depth >= self.max_depth {
+                // Worth nothing to anyone, the hand never ends
+                println!(
+                    "[WARNING] Abandoning iteration {} at depth {}, the state machine is likely cycling: {:?} to act, player {} at {}",
+                    self.iterations,
+                    depth,
+                    self.game.active_player(),
+                    updated_player,
+                    self.game.get_information_set(updated_player)
+                );
+                self.aborted_hands += 1;
+                return Descent::Terminal(0.0);
+            }
+            match self.game.active_player() {
+                ActivePlayer::Terminal(utilities) => {
+                    self.nodes_traversed += 1;
+                    self.stats.terminals += 1;
+                    self.stats.depth_total += depth;
+                    if self.nodes_traversed % 100000 == 0 {
+                        println!("Iteration: {}, Nodes Traversed: {}", self.iterations, self.nodes_traversed);
+                    }
+                    return Descent::Terminal(utilities[updated_player] * reach / q);
                 }
-                let actions = self.game_mapper.map_actions(&actions, depth);
-                let max_index = A::max_index();
-
-                let mut mask = (0..max_index).map(|_| false).collect::<Vec<bool>>();
-                let mut mapped_actions = (0..max_index)
-                    .map(|_| None)
-                    .collect::<Vec<Option<A>>>(); 
-
-                for action in &actions {
-                    mask[action.index() as usize] = true;
-                    mapped_actions[action.index() as usize] = Some(action.clone());
+                ActivePlayer::Chance(actions) => {
+                    self.nodes_traversed += 1;
+                    let (action, default_index, weight) = sample_chance(&actions, self.chance_sampling, rng);
+                    let default_index = default_index as ActionIndex;
+                    let (action, index) = self.game_mapper.map_and_index(action, depth, default_index);
+                    self.game.play(&action);
+                    // q is the probability of sampling this far relative to
+                    // reaching here, so it carries the importance weight
+                    q /= weight;
                 }
-
-                let player_num = player_num as usize;
-                let length = mask.len() as f32;
-
-                let history = self.game.get_information_set(player_num);
-                let strategy = &mut self.strategies[player_num];
-
-                let mut regrets = match strategy.regrets(&history) {
-                    Some(r) => regret_matching(&r,&mask),
-                    None => vec![1.0 / length; length as usize],
-                };
-
-                if player_num != updated_player {
-                    // Weigh actions by amount of regret accumulated
-                    // for not taking the action
-                    regrets = regrets.iter().map(|r| r / q).collect();
-                    strategy.update(history, None, Some(&regrets));
-
-                    // Discard actions that aren't legal and renormalize
-                    let distribution = Categorical::new_normalized(regrets, mapped_actions);
-                    debug_assert!(mask.iter().any(|a| *a));
-                    // TODO: it is possible that the mask removed all positive regrets
-                    // in which case we should just sample uniformly from the legal actions
-                    let distribution = distribution.with_mask(&mask);
-                    let (sampled_action, index) = distribution.sample_and_index(rng);
-
-
-                    // Sample and explore action (likelier to be one with higher regret)
-                    self.game.play(&sampled_action.unwrap());
-                    return self.run_averaging_iteration(rng, updated_player, depth + 1, q);
+                ActivePlayer::Marker(action) => {
+                    self.game.play(&action);
                 }
 
-                // Sample the policy (strategy that we've been learning)
-                if strategy.policy(&history).is_none() {
-                    let zeroes = vec![0.0; length as usize];
-                    strategy.update(history.clone(), None, Some(&zeroes));
-                }
-                let policy = strategy.policy(&history).expect("Could not get policy");
+                ActivePlayer::Player(player_num, actions) => {
+                    self.nodes_traversed += 1;
+                    if self.nodes_traversed % 100000 == 0 {
+                        println!("Iteration: {}, Nodes Traversed: {}", self.iterations, self.nodes_traversed);
+                    }
+                    let actions = self.game_mapper.map_actions(&actions, depth);
+                    let max_index = A::max_index();
 
-                let sampling_values =
-                    average_sampling(&policy, self.exploration, self.bonus, self.threshold);
+                    let mut mask = (0..max_index).map(|_| false).collect::<Vec<bool>>();
+                    let mut mapped_actions = (0..max_index)
+                        .map(|_| None)
+                        .collect::<Vec<Option<A>>>(); 
 
-                let mut regret_updates: Vec<f32> = vec![];
+                    for action in &actions {
+                        mask[action.index() as usize] = true;
+                        mapped_actions[action.index() as usize] = Some(action.clone());
+                    }
 
-                // Sample potentially many actions, and determine a
-                // counterfactual regret update for each
-                for (index, probability) in sampling_values.iter().enumerate() {
-                    if !mask[index] {
-                        regret_updates.push(0.0);
+                    let player_num = player_num as usize;
+                    let length = mask.len() as f32;
+
+                    let history = self.game.get_information_set(player_num);
+                    let strategy = &mut self.strategies[player_num];
+                    let stored = timed(&mut self.stats.strategy_time, || {
+                        strategy.visit(history);
+                        strategy.regrets(&history)
+                    });
+
+                    let mut regrets = match stored {
+                        Some(r) => regret_matching(&r,&mask),
+                        None => vec![1.0 / length; length as usize],
+                    };
+
+                    if player_num != updated_player {
+                        // Weigh actions by amount of regret accumulated
+                        // for not taking the action
+                        regrets = regrets.iter().map(|r| r * reach / q).collect();
+                        timed(&mut self.stats.strategy_time, || strategy.update(history, &mask, None, Some(&regrets)));
+
+                        // Discard actions that aren't legal and renormalize
+                        debug_assert!(mask.iter().any(|a| *a));
+                        let masked = match masked_regrets(&regrets, &mask) {
+                            Some(masked) => masked,
+                            None => {
+                                // The mask removed every positive regret
+                                self.masked_fallbacks += 1;
+                                legal_weights(&mask)
+                            }
+                        };
+                        let distribution = match Categorical::try_new_normalized(masked, mapped_actions.clone()) {
+                            Ok(distribution) => distribution,
+                            Err(error) => {
+                                let context = distribution_context(history, player_num, &actions, strategy.regrets(&history), &regrets, q, &error);
+                                if strict_distributions() {
+                                    panic!("{}", context);
+                                }
+                                println!("[WARNING] {}, exploring uniformly", context);
+                                self.invalid_distributions += 1;
+                                Categorical::new_normalized(legal_weights(&mask), mapped_actions)
+                            }
+                        };
+                        let (sampled_action, index) = distribution.sample_and_index(rng);
+
+
+                        // Sample and explore action (likelier to be one with higher regret)
+                        self.game.play(&sampled_action.unwrap());
+                        depth += 1;
                         continue;
                     }
 
-                    let will_sample = rng.gen_range(0.0, 1.0);
-                    if will_sample < *probability {
-                        // TODO: undo rather than clone
-                        let temp_game = self.game.clone();
-                        let selected_action = mapped_actions[index].as_ref().unwrap();
-                        self.game.play(selected_action);
-                        let value = self.run_averaging_iteration(
-                            rng,
-                            updated_player,
-                            depth + 1,
-                            q * probability.min(1.0),
-                        );
-                        self.game = temp_game;
-                        regret_updates.push(value);
-                    } else {
-                        regret_updates.push(0.0);
-                    }
+                    // Sample the policy (strategy that we've been learning)
+                    let policy = timed(&mut self.stats.strategy_time, || {
+                        if strategy.policy(&history).is_none() {
+                            let zeroes = vec![0.0; length as usize];
+                            strategy.update(history.clone(), &mask, None, Some(&zeroes));
+                        }
+                        strategy.policy(&history)
+                    });
+                    let policy = policy.expect("Could not get policy");
+
+                    let sampling_values =
+                        average_sampling(&policy, self.exploration, self.bonus, self.threshold);
+
+                    return Descent::Decision(Frame {
+                        depth,
+                        q,
+                        player_num,
+                        history,
+                        mask,
+                        mapped_actions,
+                        regrets,
+                        sampling_values,
+                        regret_updates: vec![],
+                        saved: None,
+                    });
                 }
+            }
+            depth += 1;
+        }
+    }
 
-                // Estimate the true value of each action using the sum of above samples
-                let counter_factual_estimation = regret_updates
-                    .iter()
-                    .zip(regrets.iter())
-                    .map(|(a, b)| a * b)
-                    .sum::<f32>();
-
-                let update_with_cfr = regret_updates
-                    .iter()
-                    .map(|a| a - counter_factual_estimation)
-                    .collect::<Vec<f32>>();
-
-                let dropped_non_actions = update_with_cfr
-                    .iter()
-                    .zip(mask.iter())
-                    .map(|(a, b)| if *b { *a } else { 0.0 })
-                    .collect::<Vec<f32>>();
-
-                let strategy = &mut self.strategies[player_num];
-                strategy.update(history, Some(&dropped_non_actions), None);
-                //if self.nodes_traversed % 10000 < 1000 && player_num ==0 {
-                    //let history : History = history.into();
-                    //if history.0.len() == 5  {
-                        //let cards = history.0[1].clone() as ActionIndex;
-                        //let feature : Feature = cards.into();
-                        //let policy = strategy.policy(&self.game.history(0)).unwrap();
-                        //let new_regrets = strategy.regrets(&self.game.history(0)).unwrap();
-
-
-                        //println!("history: {:?}", history);
-                        //println!("feature: {:?}", feature);
-                        //println!("suited: {:?}", history.0[2] == 1);
-                        //println!("policy: {:?}", policy);
-                        //println!("actions: {:?}", actions);
-                        //println!("new reg: {:?}", new_regrets);
-                        //println!("q: {}", q );
-                        //println!("Value: {}", counter_factual_estimation);
-                        //println!("Regrets updates: {:?}", dropped_non_actions);
-                    //}
-
-                //}
-
-                counter_factual_estimation
+    /// Plays the next action of `frame` to explore, if any is left, and
+    /// returns the depth and q to explore it at. Sample potentially many
+    /// actions, and determine a counterfactual regret update for each
+    fn next_child<R: Rng>(&mut self, frame: &mut Frame<A, S>, rng: &mut R) -> Option<(usize, f32)> {
+        while frame.regret_updates.len() < frame.sampling_values.len() {
+            let index = frame.regret_updates.len();
+            if !frame.mask[index] {
+                frame.regret_updates.push(0.0);
+                continue;
             }
+
+            let probability = frame.sampling_values[index];
+            let will_sample = rng.gen_range(0.0, 1.0);
+            if will_sample < probability && frame.q * probability.min(1.0) >= self.min_reach {
+                // TODO: undo rather than clone
+                frame.saved = Some(self.game.clone());
+                let selected_action = frame.mapped_actions[index].as_ref().unwrap();
+                self.game.play(selected_action);
+                return Some((frame.depth + 1, frame.q * probability.min(1.0)));
+            }
+            frame.regret_updates.push(0.0);
         }
+        None
     }
+
+    /// Updates the regrets of a decision once all its actions are
+    /// explored, returning its value
+    fn finish(&mut self, frame: Frame<A, S>) -> Utility {
+        let Frame { history, mask, regrets, regret_updates, player_num, .. } = frame;
+
+        // Estimate the true value of each action using the sum of above samples
+        let counter_factual_estimation = regret_updates
+            .iter()
+            .zip(regrets.iter())
+            .map(|(a, b)| a * b)
+            .sum::<Utility>();
+
+        let update_with_cfr = regret_updates
+            .iter()
+            .map(|a| a - counter_factual_estimation)
+            .collect::<Vec<Utility>>();
+
+        let dropped_non_actions = update_with_cfr
+            .iter()
+            .zip(mask.iter())
+            .map(|(a, b)| if *b { *a } else { 0.0 })
+            .collect::<Vec<Utility>>();
+
+        let strategy = &mut self.strategies[player_num];
+        timed(&mut self.stats.strategy_time, || strategy.update(history, &mask, Some(&dropped_non_actions), None));
+
+        counter_factual_estimation
+    }
+}
+
+/// A decision of the updated player part way through exploring its
+/// actions, see run_averaging_iteration
+struct Frame<A: Action, S: State<A>> {
+    depth: usize,
+    q: f32,
+    player_num: usize,
+    history: CondensedInfoSet,
+    mask: Vec<bool>,
+    mapped_actions: Vec<Option<A>>,
+    /// Regret matched over the legal actions, weighing the action values
+    regrets: Vec<f32>,
+    sampling_values: Vec<f32>,
+    /// Values of the actions explored so far, 0 for the rest
+    regret_updates: Vec<Utility>,
+    /// The game at the decision while one of its actions is explored
+    saved: Option<Game<A, S>>,
+}
+
+// Matched as soon as it is returned, boxing the frame would only cost an
+// allocation per decision
+#[allow(clippy::large_enum_variant)]
+enum Descent<A: Action, S: State<A>> {
+    /// The value of the terminal node reached, weighted by its q
+    Terminal(Utility),
+    Decision(Frame<A, S>),
 }
 
 /// Average sampling used in line with this paper:
@@ -258,6 +712,51 @@ fn average_sampling(policy: &[f32], e: f32, b: f32, t: f32) -> Vec<f32> {
     probabilities
 }
 
+/// Whether GTCOGS_STRICT_DISTRIBUTIONS asks for an invalid exploration
+/// distribution to stop training rather than explore uniformly
+fn strict_distributions() -> bool {
+    static STRICT: OnceLock<bool> = OnceLock::new();
+    *STRICT.get_or_init(|| std::env::var("GTCOGS_STRICT_DISTRIBUTIONS").map(|value| value == "1").unwrap_or(false))
+}
+
+/// The regrets of an opponent's decision over its legal actions, None when
+/// none of the legal actions has any regret left
+fn masked_regrets(regrets: &[f32], mask: &[bool]) -> Option<Vec<f32>> {
+    let total: f32 = regrets.iter().sum();
+    let masked: Vec<f32> = regrets.iter().zip(mask).map(|(r, m)| if *m { r / total } else { 0.0 }).collect();
+    match masked.iter().all(|r| *r < 1e-4) {
+        true => None,
+        false => Some(masked),
+    }
+}
+
+/// Equal weight on every legal action
+fn legal_weights(mask: &[bool]) -> Vec<f32> {
+    mask.iter().map(|legal| if *legal { 1.0 } else { 0.0 }).collect()
+}
+
+/// Everything known about a decision that couldn't be explored, so the
+/// warning (or panic) says where training went wrong
+fn distribution_context<A: Action>(
+    info_set: CondensedInfoSet,
+    player_num: usize,
+    actions: &[A],
+    stored: Option<RegretDistribution>,
+    matched: &[f32],
+    q: f32,
+    error: &str,
+) -> String {
+    let history: History = info_set.into();
+    let described = match FeatureSchema::for_history(&history) {
+        Some(schema) => schema.pretty(&history),
+        None => format!("{:?}", history),
+    };
+    format!(
+        "Invalid exploration distribution for player {} at infoset {} ({}) with actions {:?}, stored regrets {:?}, matched regrets {:?} at reach {}: {}",
+        player_num, info_set, described, actions, stored, matched, q, error
+    )
+}
+
 /// Weigh regrets by the relative size of that regret
 fn regret_matching(reg: &[f32], mask : &[bool]) -> Vec<f32> {
     let regp = reg.iter().map(|&v| if v >= 0.0 { v } else { 0.0 });
@@ -274,3 +773,159 @@ fn regret_matching(reg: &[f32], mask : &[bool]) -> Vec<f32> {
         vec![1.0 / l as f32; l]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_logic::visibility::Observation;
+    use crate::implementations::json_game::JsonAction;
+    use crate::implementations::one_card_poker::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_uniform_chance_samples_are_importance_weighted() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let chance = Categorical::new(vec![0.7, 0.2, 0.1], vec!['a', 'b', 'c']);
+        let mut total = 0.0;
+        let mut seen = [0; 3];
+        for _ in 0..3000 {
+            let (outcome, index, weight) = sample_chance(&chance, ChanceSampling::Uniform, &mut rng);
+            assert_eq!(outcome, chance.items()[index]);
+            assert!((weight - 3.0 * chance.probs()[index]).abs() < 1e-6);
+            seen[index] += 1;
+            total += weight;
+        }
+        // Rare outcomes come up as often as common ones, and the weights
+        // average out to 1 so nothing is biased
+        assert!(seen.iter().all(|count| *count > 900));
+        assert!((total / 3000.0 - 1.0).abs() < 0.05);
+        assert_eq!(sample_chance(&chance, ChanceSampling::Proportional, &mut rng).2, 1.0);
+    }
+
+    #[test]
+    fn test_invalid_regrets_are_reported() {
+        let mask = [true, true, false];
+        assert_eq!(masked_regrets(&[0.0, 0.0, 1.0], &mask), None);
+        assert_eq!(masked_regrets(&[1.0, 3.0, 4.0], &mask), Some(vec![0.125, 0.375, 0.0]));
+        let masked = masked_regrets(&[f32::INFINITY, 1.0, 0.0], &mask).unwrap();
+        let error = Categorical::try_new_normalized(masked, vec!['a', 'b', 'c']).unwrap_err();
+
+        let context = distribution_context(7, 1, &[OneCardPokerAction::Check], Some(vec![1e38, 1.0]), &[f32::INFINITY, 1.0], 1e-40, &error);
+        for expected in ["player 1", "infoset 7", "Check", "1e38", "inf", &error] {
+            assert!(context.contains(expected), "{}", context);
+        }
+    }
+
+    #[test]
+    fn test_subtree_root_is_weighted_by_the_blueprint() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let strategies = vec![Arc::new(RegretStrategy::default()), Arc::new(RegretStrategy::default())];
+        let root = SubtreeRoot {
+            line: vec![OneCardPokerAction::Check],
+            blueprint: strategies.clone(),
+        };
+        let mut game = Game::<OneCardPokerAction, OneCardPokerState<KuhnConfig>>::new();
+        let (depth, reach) = root.replay(&mut game, &GameMapper::new(None), 1, &mut rng).unwrap();
+        assert!(depth > 1);
+        // Nothing is known about player 0 so each of its actions is as likely
+        assert!(reach > 0.0 && reach < 1.0);
+        // The updated player's own actions don't count
+        let mut game = Game::<OneCardPokerAction, OneCardPokerState<KuhnConfig>>::new();
+        assert_eq!(root.replay(&mut game, &GameMapper::new(None), 0, &mut rng).unwrap().1, 1.0);
+
+        // Where player 0 has a policy, it is read at the index training
+        // stores the action under
+        let line = vec![OneCardPokerAction::Deal(0), OneCardPokerAction::Deal(1), OneCardPokerAction::Check];
+        let mut dealt = Game::<OneCardPokerAction, OneCardPokerState<KuhnConfig>>::new();
+        dealt.play(&line[0]);
+        dealt.play(&line[1]);
+        let max_index = OneCardPokerAction::max_index() as usize;
+        let mut mask = vec![false; max_index];
+        let mut policy = vec![0.0; max_index];
+        for (action, weight) in [(OneCardPokerAction::Check, 3.0), (OneCardPokerAction::Raise(0), 1.0)] {
+            mask[action.index() as usize] = true;
+            policy[action.index() as usize] = weight;
+        }
+        let blueprint = vec![Arc::new(RegretStrategy::default()), Arc::new(RegretStrategy::default())];
+        blueprint[0].update(dealt.get_information_set(0), &mask, None, Some(&policy));
+        let known = SubtreeRoot { line, blueprint };
+        let mut game = Game::<OneCardPokerAction, OneCardPokerState<KuhnConfig>>::new();
+        assert_eq!(known.replay(&mut game, &GameMapper::new(None), 1, &mut rng).unwrap().1, 0.75);
+
+        let mut mccfr = MCCFR::new(Game::<OneCardPokerAction, OneCardPokerState<KuhnConfig>>::new(), strategies.clone());
+        mccfr.with_subtree_root(root);
+        mccfr.run_iterations(20, 0.6, &mut rng);
+        assert!(strategies[1].size() > 0);
+        assert_eq!(mccfr.invalid_distributions, 0);
+    }
+
+    /// A game as deep as MCCFR will play, the players taking turns. Player
+    /// 0 goes on (action 0) or stops (action 1) and loses a chip, player 1
+    /// always goes on, so iterations updating player 0 explore every
+    /// depth. Nobody sees anything, every decision of a player is one
+    /// info set
+    #[derive(Clone)]
+    struct DeepState {
+        played: usize,
+        stopped: bool,
+    }
+
+    const DEEP_GAME_LENGTH: usize = MAX_GAME_DEPTH - 10;
+
+    impl State<JsonAction> for DeepState {
+        fn new() -> Self {
+            DeepState { played: 0, stopped: false }
+        }
+
+        fn observe_before(&mut self, _action: &JsonAction) -> Vec<Observation<JsonAction>> {
+            vec![]
+        }
+
+        fn active_player(&self) -> ActivePlayer<JsonAction> {
+            match (self.stopped, self.played >= DEEP_GAME_LENGTH) {
+                (true, _) => ActivePlayer::Terminal(vec![-1.0, 1.0]),
+                (false, true) => ActivePlayer::Terminal(vec![0.0, 0.0]),
+                (false, false) if self.played.is_multiple_of(2) => ActivePlayer::Player(0, vec![JsonAction(0), JsonAction(1)]),
+                (false, false) => ActivePlayer::Player(1, vec![JsonAction(0)]),
+            }
+        }
+
+        fn update(&mut self, action: JsonAction) {
+            self.stopped = action == JsonAction(1);
+            self.played += 1;
+        }
+    }
+
+    #[test]
+    fn test_deep_games_train_on_a_small_stack() {
+        // Recursing once per action, this depth took far more than the
+        // 256KB a thread gets here
+        let trainer = std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(|| {
+                let mut rng = StdRng::seed_from_u64(5);
+                let strategies = vec![Arc::new(RegretStrategy::default()), Arc::new(RegretStrategy::default())];
+                let mut mccfr = MCCFR::new(Game::<JsonAction, DeepState>::new(), strategies.clone());
+                mccfr.run_iterations(3, 1.0, &mut rng);
+                (mccfr.aborted_hands, mccfr.nodes_traversed)
+            })
+            .unwrap();
+        let (aborted, nodes) = trainer.join().unwrap();
+        assert_eq!(aborted, 0);
+        assert!(nodes > 3 * DEEP_GAME_LENGTH, "{}", nodes);
+    }
+
+    #[test]
+    fn test_iterations_are_abandoned_at_the_max_depth() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let strategies = vec![Arc::new(RegretStrategy::default()), Arc::new(RegretStrategy::default())];
+        let mut mccfr = MCCFR::new(Game::<OneCardPokerAction, OneCardPokerState<KuhnConfig>>::new(), strategies);
+        mccfr.run_iterations(10, 0.6, &mut rng);
+        assert_eq!(mccfr.aborted_hands, 0);
+
+        // Dealing alone takes two actions, so no hand gets to a showdown
+        mccfr.with_max_depth(3);
+        mccfr.run_iterations(10, 0.6, &mut rng);
+        assert!(mccfr.aborted_hands >= 10);
+    }
+}
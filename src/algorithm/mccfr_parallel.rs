@@ -1,12 +1,27 @@
-use crate::algorithm::mccfr::MCCFR;
+use crate::algorithm::distributed::Exchange;
+use crate::algorithm::mccfr::{ChanceSampling, MccfrConfig, PhaseConfig, RunnerStats, SubtreeRoot, MCCFR};
+use crate::algorithm::placement::*;
 use crate::constants::*;
+use crate::context::Context;
 use crate::game_logic::action::{Action, GameMapper};
 use crate::game_logic::game::Game;
 use crate::game_logic::state::State;
+use crate::game_logic::strategy::delta::StrategyDelta;
 use crate::game_logic::strategy::RegretStrategy;
 use rand::{rngs::SmallRng, SeedableRng};
 use std::marker::{Send, Sync};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// When and what to drop from the strategies during training,
+/// see RegretStrategy::sweep
+#[derive(Debug, Clone)]
+pub struct GarbageCollection {
+    /// Sweep after every this many batches
+    pub every_batches: usize,
+    pub min_visits: u32,
+    pub min_policy_mass: f32,
+}
 
 #[derive(Debug, Clone)]
 pub struct MCCFRParallel<A: Action, S: State<A>> {
@@ -14,6 +29,41 @@ pub struct MCCFRParallel<A: Action, S: State<A>> {
     threads: usize,
     strategies: Vec<Arc<RegretStrategy>>,
     file_name: Option<String>,
+    garbage_collection: Option<GarbageCollection>,
+    /// Batches of iterations between saves to file_name
+    checkpoint_every: usize,
+    /// Checkpoints are put off until a strategy has had this many updates
+    min_checkpoint_updates: usize,
+    /// Visits an info set needs to count as trained in the coverage report
+    coverage_threshold: u32,
+    placement: ThreadPlacement,
+    /// Directory of the delta checkpoints, see with_delta_checkpoints
+    delta_directory: Option<String>,
+    /// Delta checkpoints written so far
+    deltas_written: usize,
+    /// Iteration of each strategy at the last delta checkpoint
+    delta_iterations: Vec<u64>,
+    /// What each runner did in the last batch
+    batch_stats: Vec<RunnerStats>,
+    /// Iterations in the next batch, see with_checkpoint_ratio
+    batch_size: usize,
+    checkpoint_ratio: f64,
+}
+
+/// Iterations in a batch until a checkpoint has been timed
+pub const INITIAL_BATCH_SIZE: usize = 2000;
+pub const MIN_BATCH_SIZE: usize = 500;
+pub const MAX_BATCH_SIZE: usize = 1_000_000;
+
+/// The batch size at which training takes `ratio` times as long as a
+/// checkpoint, given the last batch of `iterations` took `trained`
+pub fn adapted_batch_size(trained: Duration, iterations: usize, checkpoint: Duration, ratio: f64) -> usize {
+    let per_iteration = trained.as_secs_f64() / iterations.max(1) as f64;
+    if per_iteration <= 0.0 {
+        return MAX_BATCH_SIZE;
+    }
+    let size = checkpoint.as_secs_f64() * ratio / per_iteration;
+    (size as usize).clamp(MIN_BATCH_SIZE, MAX_BATCH_SIZE)
 }
 
 impl<A: Action + Sync + Send + 'static, S: State<A> + Send + 'static> MCCFRParallel<A, S> {
@@ -31,53 +81,586 @@ impl<A: Action + Sync + Send + 'static, S: State<A> + Send + 'static> MCCFRParal
             threads,
             strategies: strategies.clone(),
             file_name : file_name.map(|s| s.to_string()),
+            garbage_collection: None,
+            checkpoint_every: 1,
+            min_checkpoint_updates: 0,
+            coverage_threshold: 100,
+            placement: ThreadPlacement::default(),
+            delta_directory: None,
+            deltas_written: 0,
+            delta_iterations: vec![0; strategies.len()],
+            batch_stats: vec![],
+            batch_size: INITIAL_BATCH_SIZE,
+            checkpoint_ratio: 20.0,
+        }
+    }
+
+    /// Visits an info set needs to count as trained in the coverage report
+    /// printed after training
+    pub fn with_coverage_threshold(mut self, visits: u32) -> MCCFRParallel<A, S> {
+        self.coverage_threshold = visits;
+        self
+    }
+
+    /// Pin the runners to cores and/or shard the strategies by NUMA node,
+    /// see ThreadPlacement
+    pub fn with_placement(mut self, placement: ThreadPlacement) -> MCCFRParallel<A, S> {
+        self.placement = placement;
+        self
+    }
+
+    /// At every checkpoint also write what changed in the strategies since
+    /// the previous one to `directory`, as checkpoint<n>_p<player>.delta.
+    /// Unlike the saved tables these have the regrets, so training can be
+    /// picked up again with restore_deltas. Information sets swept out
+    /// since they were written come back on restore
+    pub fn with_delta_checkpoints(mut self, directory: &str) -> MCCFRParallel<A, S> {
+        self.delta_directory = Some(directory.to_string());
+        self
+    }
+
+    /// Only save every `batches` batches, saving a big strategy takes a while
+    pub fn with_checkpoint_every(mut self, batches: usize) -> MCCFRParallel<A, S> {
+        self.checkpoint_every = batches.max(1);
+        self
+    }
+
+    /// Sample chance outcomes as `chance_sampling` says, see ChanceSampling
+    pub fn with_chance_sampling(mut self, chance_sampling: ChanceSampling) -> MCCFRParallel<A, S> {
+        for runner in &mut self.runners {
+            runner.with_chance_sampling(chance_sampling);
+        }
+        self
+    }
+
+    /// Only train the subtree below `root`, see SubtreeRoot
+    pub fn with_subtree_root(mut self, root: SubtreeRoot<A>) -> MCCFRParallel<A, S> {
+        for runner in &mut self.runners {
+            runner.with_subtree_root(root.clone());
+        }
+        self
+    }
+
+    /// Periodically sweeps rarely visited information sets out of the strategies
+    pub fn with_garbage_collection(mut self, garbage_collection: GarbageCollection) -> MCCFRParallel<A, S> {
+        self.garbage_collection = Some(garbage_collection);
+        self
+    }
+
+    /// Skip checkpoints until some strategy has had at least `updates` updates
+    /// since it was last saved, rather than rewriting an unchanged strategy
+    pub fn with_min_checkpoint_updates(mut self, updates: usize) -> MCCFRParallel<A, S> {
+        self.min_checkpoint_updates = updates;
+        self
+    }
+
+    /// Whether checkpoints depend on what the strategies updated, which
+    /// only then track their updates (see RegretStrategy::track_updates)
+    fn tracks_updates(&self) -> bool {
+        self.min_checkpoint_updates > 0 || self.delta_directory.is_some()
+    }
+
+    /// Whether enough changed since the last checkpoint to write another.
+    /// Anything unsaved is written after the last batch
+    fn checkpoint_due(&self, last_batch: bool) -> bool {
+        if !self.tracks_updates() {
+            return true;
+        }
+        let dirty = self.strategies.iter().map(|strategy| strategy.dirty()).max().unwrap_or(0);
+        if last_batch {
+            return dirty > 0;
+        }
+        if dirty < self.min_checkpoint_updates {
+            println!("Skipping checkpoint, only {} updates since the last one", dirty);
+            return false;
+        }
+        true
+    }
+
+    /// Points the runners of each node at a fresh copy of the strategies
+    /// if the strategies are sharded, returning the copies of every node
+    fn wire_shards(&mut self, slots: &[Slot]) -> Vec<Vec<Arc<RegretStrategy>>> {
+        if !self.placement.numa_shards || slots.is_empty() {
+            return vec![];
+        }
+        let nodes = slots.iter().map(|slot| slot.node).max().unwrap_or(0) + 1;
+        let shards: Vec<Vec<Arc<RegretStrategy>>> = (0..nodes)
+            .map(|_| self.strategies.iter().map(|strategy| Arc::new((**strategy).clone())).collect())
+            .collect();
+        for (runner, slot) in self.runners.iter_mut().zip(slots) {
+            runner.with_strategies(shards[slot.node].clone());
+        }
+        shards
+    }
+
+    /// Adds what every shard learned into the strategies and points the
+    /// runners back at them
+    fn merge_shards(&mut self, shards: Vec<Vec<Arc<RegretStrategy>>>) {
+        if shards.is_empty() {
+            return;
+        }
+        for (player, strategy) in self.strategies.iter_mut().enumerate() {
+            let merged = (**strategy).clone();
+            for shard in &shards {
+                merged.merge_changes(&shard[player], strategy);
+            }
+            *strategy = Arc::new(merged);
+        }
+        for runner in &mut self.runners {
+            runner.with_strategies(self.strategies.clone());
+        }
+    }
+
+    /// Opponent decisions of all runners so far that were explored
+    /// uniformly, for lack of legal regret and for invalid regrets
+    pub fn fallbacks(&self) -> (usize, usize) {
+        let masked = self.runners.iter().map(|runner| runner.masked_fallbacks).sum();
+        let invalid = self.runners.iter().map(|runner| runner.invalid_distributions).sum();
+        (masked, invalid)
+    }
+
+    /// Train the game of `context`, see MCCFR::with_context
+    pub fn with_context(mut self, context: Arc<Context>) -> MCCFRParallel<A, S> {
+        for runner in &mut self.runners {
+            runner.with_context(context.clone());
+        }
+        self
+    }
+
+    /// Abandon iterations reaching `max_depth` actions, see
+    /// MCCFR::with_max_depth
+    pub fn with_max_depth(mut self, max_depth: usize) -> MCCFRParallel<A, S> {
+        for runner in &mut self.runners {
+            runner.with_max_depth(max_depth);
+        }
+        self
+    }
+
+    /// Iterations of all runners so far abandoned at the maximum depth
+    pub fn aborted_hands(&self) -> usize {
+        self.runners.iter().map(|runner| runner.aborted_hands).sum()
+    }
+
+    /// Size the batches so that training between checkpoints takes `ratio`
+    /// times as long as a checkpoint. Measured at every checkpoint, since
+    /// saving gets slower as the strategies grow
+    pub fn with_checkpoint_ratio(mut self, ratio: f64) -> MCCFRParallel<A, S> {
+        self.checkpoint_ratio = ratio;
+        self
+    }
+
+    /// Iterations in the next batch
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    fn adapt_batch_size(&mut self, trained: Duration, iterations: usize, checkpoint: Duration) {
+        let size = adapted_batch_size(trained, iterations, checkpoint, self.checkpoint_ratio);
+        if size != self.batch_size {
+            println!(
+                "Checkpoint took {:.1?} after {:.1?} of training, batches of {} iterations from now on",
+                checkpoint, trained, size
+            );
+        }
+        self.batch_size = size;
+    }
+
+    /// What each runner did in the last batch, see RunnerStats
+    pub fn batch_stats(&self) -> &[RunnerStats] {
+        &self.batch_stats
+    }
+
+    /// The slowest runner's time over the average, 1 when the work is
+    /// spread evenly. The batch takes as long as the slowest runner
+    pub fn imbalance(&self) -> f64 {
+        let times: Vec<f64> = self.batch_stats.iter().map(|stats| stats.time.as_secs_f64()).collect();
+        let mean = times.iter().sum::<f64>() / times.len().max(1) as f64;
+        let slowest = times.iter().cloned().fold(0.0, f64::max);
+        match mean > 0.0 {
+            true => slowest / mean,
+            false => 1.0,
         }
     }
 
-    pub fn run_iterations(&mut self, iterations: usize, epsilon: f32) {
-        // Save intermediate results
-        // makes sure to pick a  good batch size
-        let batch_size = 2000;
+    fn report_batch(&self) {
+        for (i, stats) in self.batch_stats.iter().enumerate() {
+            println!("Runner {}: {}", i, stats);
+        }
+        println!("Slowest runner took {:.2}x the average", self.imbalance());
+    }
+
+    fn report_fallbacks(&self) {
+        let (masked, invalid) = self.fallbacks();
+        if masked + invalid > 0 {
+            let nodes: usize = self.runners.iter().map(|runner| runner.nodes_traversed).sum();
+            println!(
+                "Explored uniformly so far: {} decisions with no legal regret, {} with invalid regrets, of {} nodes",
+                masked, invalid, nodes
+            );
+        }
+        let aborted = self.aborted_hands();
+        if aborted > 0 {
+            println!("[WARNING] {} iterations abandoned at the maximum depth so far", aborted);
+        }
+    }
+
+    fn collect_garbage(&self, batch: usize) {
+        let gc = match &self.garbage_collection {
+            Some(gc) if gc.every_batches > 0 && batch.is_multiple_of(gc.every_batches) => gc,
+            _ => return,
+        };
+        for (i, strategy) in self.strategies.iter().enumerate() {
+            let dropped = strategy.sweep(gc.min_visits, gc.min_policy_mass);
+            println!("Dropped {} information sets of player {}, {} left", dropped, i, strategy.size());
+        }
+    }
 
-        // Total intermediate iterations 
-        let total_batches = iterations / batch_size;
+    pub fn run_iterations(&mut self, total: usize, epsilon: f32) {
+        // Save intermediate results after every batch, whose size adapts
+        // to how long saving takes, see with_checkpoint_ratio
+        let mut done = 0;
+        let mut batch = 0;
+        if self.tracks_updates() {
+            for strategy in &self.strategies {
+                strategy.track_updates();
+            }
+        }
 
-        for i in 0..total_batches {
-            let iterations = batch_size;
+        while done < total {
+            let iterations = self.batch_size.min(total - done);
+            let started = Instant::now();
         
             let mut thread_iters = vec![iterations / self.threads; self.threads];
             for i in 0..(iterations % self.threads) {
                 thread_iters[i] += 1;
             }
+            let slots = match self.placement.is_default() {
+                true => vec![],
+                false => assign_slots(self.threads, &numa_nodes()),
+            };
+            let shards = self.wire_shards(&slots);
             let mut threads = Vec::new();
             for i in 0..self.threads {
                 let mut runner = self.runners[i].clone();
                 let iters = thread_iters[i];
-                threads.push(std::thread::Builder::new().stack_size(100*1024*1024).spawn(move || {
+                let core = slots.get(i).filter(|_| self.placement.pin_cores).map(|slot| slot.core);
+                threads.push(std::thread::Builder::new().spawn(move || {
+                    if let Some(core) = core {
+                        if let Err(e) = pin_to_core(core) {
+                            println!("[WARNING] {}", e);
+                        }
+                    }
                     let mut rng = SmallRng::from_rng(&mut rand::thread_rng()).unwrap();
                     runner.run_iterations(iters, epsilon, &mut rng);
                     runner
                 }).unwrap());
             }
-            for thread in threads {
-                let runner = thread.join().unwrap();
-                self.runners.push(runner);
+            let before: Vec<RunnerStats> = self.runners.iter().map(|runner| runner.stats).collect();
+            self.runners = threads.into_iter().map(|thread| thread.join().unwrap()).collect();
+            self.batch_stats = self.runners.iter().zip(&before).map(|(runner, before)| runner.stats.since(before)).collect();
+            self.report_batch();
+            self.report_fallbacks();
+            self.merge_shards(shards);
+            done += iterations;
+            batch += 1;
+            self.collect_garbage(batch);
+            let trained = started.elapsed();
+            let last_batch = done == total;
+            if batch % self.checkpoint_every != 0 && !last_batch {
+                continue;
+            }
+            if !self.checkpoint_due(last_batch) {
+                continue;
+            }
+            let started = Instant::now();
+            if let Err(e) = self.write_deltas() {
+                println!("[WARNING] Could not write a delta checkpoint: {}", e);
             }
             if let Some(file_name) = &self.file_name {
                 println!("Saving to file {}", file_name);
-                println!("Iteration {} completed", (i+1) * batch_size);
+                println!("Iteration {} completed", done);
                 self.write_to(file_name);
             } else {
                 println!("No file name provided, not saving");
             }
+            if self.file_name.is_some() || self.delta_directory.is_some() {
+                self.adapt_batch_size(trained, iterations, started.elapsed());
+            }
+
+        }
+        for (player, strategy) in self.strategies.iter().enumerate() {
+            println!("Coverage of player {}:\n{}", player, strategy.coverage(self.coverage_threshold));
+            if !strategy.tracks_updates() {
+                continue;
+            }
+            println!(
+                "Last iteration each round was updated in, of {}: {:?}",
+                strategy.iteration(),
+                strategy.last_update_by_round()
+            );
+        }
+    }
+    fn configure(&mut self, phase: &PhaseConfig) {
+        for runner in &mut self.runners {
+            runner.configure(phase);
+        }
+        if phase.freeze_below_visits > 0 {
+            for (player, strategy) in self.strategies.iter().enumerate() {
+                let frozen = strategy.freeze(phase.freeze_below_visits);
+                println!("Froze {} info sets of player {}", frozen, player);
+            }
+        }
+    }
+
+    /// Trains in the two phases of `config`, switching from broad
+    /// exploration to refinement part way through the iterations
+    pub fn run_config(&mut self, iterations: usize, config: &MccfrConfig) {
+        let (explore, refine) = config.split(iterations);
+        self.placement = config.placement.clone();
+        println!("Exploring for {} iterations", explore);
+        self.configure(&config.explore);
+        self.run_iterations(explore, config.explore.exploration);
+        println!("Refining for {} iterations", refine);
+        self.configure(&config.refine);
+        self.run_iterations(refine, config.refine.exploration);
+    }
+
+    /// Trains `rounds` rounds of `iterations` as one of several workers,
+    /// adding in the changes of every other worker after each round, see
+    /// algorithm::distributed
+    ///
+    /// The changes of a round are taken against a merged copy of the
+    /// strategies that only ever takes the deltas every worker shares, so
+    /// the copies of all workers stay identical. The strategies the runners
+    /// train stay shared with them and take the others' changes in place,
+    /// whatever they drift from the merged copy by rounding is in the next
+    /// round's delta
+    pub fn run_distributed(
+        &mut self,
+        rounds: usize,
+        iterations: usize,
+        epsilon: f32,
+        exchange: &mut dyn Exchange,
+    ) -> Result<(), String> {
+        let merged: Vec<RegretStrategy> = self.strategies.iter().map(|strategy| (**strategy).clone()).collect();
+        for round in 0..rounds {
+            self.run_iterations(iterations, epsilon);
+            let own: Vec<StrategyDelta> = self
+                .strategies
+                .iter()
+                .zip(&merged)
+                .map(|(strategy, merged)| strategy.changes_since(merged))
+                .collect();
+            let others = exchange.exchange(round, own.clone())?;
+            for ((strategy, merged), (own, others)) in self.strategies.iter().zip(&merged).zip(own.iter().zip(&others)) {
+                merged.apply(own);
+                merged.apply(others);
+                strategy.apply(others);
+            }
+            println!("Round {} merged, {} info sets", round + 1, self.strategies[0].size());
+        }
+        Ok(())
+    }
+
+    fn delta_file(directory: &str, checkpoint: usize, player: usize) -> std::path::PathBuf {
+        std::path::Path::new(directory).join(format!("checkpoint{}_p{}.delta", checkpoint, player))
+    }
 
+    /// Writes the changes since the last delta checkpoint, if enabled.
+    /// Every file is written under another name first and moved into place
+    /// once all are complete, player 0 last, as restore_deltas takes the
+    /// file of player 0 to mean the whole checkpoint is there
+    fn write_deltas(&mut self) -> Result<(), String> {
+        let directory = match &self.delta_directory {
+            Some(directory) => directory.clone(),
+            None => return Ok(()),
+        };
+        std::fs::create_dir_all(&directory).map_err(|e| format!("{}: {}", directory, e))?;
+        let mut written = vec![];
+        for (player, strategy) in self.strategies.iter().enumerate() {
+            let delta = strategy.changed_since(self.delta_iterations[player]);
+            let path = Self::delta_file(&directory, self.deltas_written, player);
+            let partial = path.with_extension("partial");
+            let file = std::fs::File::create(&partial).map_err(|e| format!("{}: {}", partial.display(), e))?;
+            delta.write_to(&mut std::io::BufWriter::new(file))?;
+            println!("Wrote {} changed info sets of player {} to {}", delta.entries.len(), player, path.display());
+            written.push((partial, path, strategy.iteration()));
         }
+        for (player, (partial, path, iteration)) in written.into_iter().enumerate().rev() {
+            std::fs::rename(&partial, &path)
+                .map_err(|e| format!("Could not move {} to {}: {}", partial.display(), path.display(), e))?;
+            self.delta_iterations[player] = iteration;
+        }
+        self.deltas_written += 1;
+        Ok(())
+    }
+
+    /// Picks training up from the delta checkpoints in `directory`, applying
+    /// them in order. Returns how many there were
+    pub fn restore_deltas(&mut self, directory: &str) -> Result<usize, String> {
+        let mut checkpoint = 0;
+        while Self::delta_file(directory, checkpoint, 0).exists() {
+            for (player, strategy) in self.strategies.iter().enumerate() {
+                let path = Self::delta_file(directory, checkpoint, player);
+                let file = std::fs::File::open(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                strategy.apply(&StrategyDelta::read_from(&mut std::io::BufReader::new(file))?);
+                self.delta_iterations[player] = strategy.iteration();
+            }
+            checkpoint += 1;
+        }
+        if self.delta_directory.as_deref() == Some(directory) {
+            self.deltas_written = checkpoint;
+        }
+        Ok(checkpoint)
+    }
+
+    /// The strategy being trained for every player
+    pub fn strategies(&self) -> &[Arc<RegretStrategy>] {
+        &self.strategies
     }
+
     pub fn write_to(&self, file_name: &str) {
+        let abstraction = match self.runners.first() {
+            Some(runner) => runner.abstraction_hash(),
+            None => A::abstraction_hash(),
+        };
         for (i, strategy) in self.strategies.iter().enumerate() {
             let file = format!("{}{}", file_name.to_owned(), format!("_p{}.json", i));
             let game_mapper: GameMapper<A> = GameMapper::new(None);
-            strategy.save_table_json(&file, &game_mapper);
+            strategy.save_table(&file, &game_mapper, abstraction);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::implementations::json_game::*;
+    use crate::implementations::one_card_poker::*;
+
+    #[test]
+    fn test_training_resumes_from_delta_checkpoints() {
+        let directory = std::env::temp_dir().join("gtcogs_delta_checkpoints_test");
+        let _ = std::fs::remove_dir_all(&directory);
+        let directory = directory.to_str().unwrap();
+        type Kuhn = MCCFRParallel<OneCardPokerAction, OneCardPokerState<KuhnConfig>>;
+        // Checkpoints of Kuhn are so quick the batches would shrink to the
+        // minimum, keep them at 2000 and two checkpoints
+        let mut trained = Kuhn::new(2, None).with_delta_checkpoints(directory).with_checkpoint_ratio(1e9);
+        trained.run_iterations(4000, 0.2);
+        // The last batch of 2000 split between the two runners
+        let stats = trained.batch_stats();
+        assert_eq!(stats.iter().map(|stats| stats.iterations).sum::<usize>(), 2000);
+        assert!(stats.iter().all(|stats| stats.nodes > 0 && stats.average_depth() > 1.0));
+        assert!(trained.imbalance() >= 1.0);
+        assert_eq!(trained.batch_size(), MAX_BATCH_SIZE);
+        let second = Duration::from_secs(1);
+        assert_eq!(adapted_batch_size(10 * second, 2000, second, 20.0), 4000);
+        assert_eq!(adapted_batch_size(10 * second, 2000, Duration::ZERO, 20.0), MIN_BATCH_SIZE);
+        assert_eq!(adapted_batch_size(Duration::ZERO, 2000, second, 20.0), MAX_BATCH_SIZE);
+
+        // Only complete files are left behind
+        let mut files: Vec<String> = std::fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec!["checkpoint0_p0.delta", "checkpoint0_p1.delta", "checkpoint1_p0.delta", "checkpoint1_p1.delta"]);
+
+        // Without delta checkpoints nothing needs the updates tracked
+        assert!(trained.strategies.iter().all(|strategy| strategy.tracks_updates()));
+        let mut untracked = Kuhn::new(1, None);
+        untracked.run_iterations(100, 0.2);
+        assert!(untracked.strategies.iter().all(|strategy| !strategy.tracks_updates() && strategy.dirty() == 0));
+
+        let mut restored = Kuhn::new(2, None);
+        assert_eq!(restored.restore_deltas(directory), Ok(2));
+        for (trained, restored) in trained.strategies.iter().zip(&restored.strategies) {
+            assert_eq!(restored.iteration(), trained.iteration());
+            assert_eq!(restored.size(), trained.size());
+            assert_eq!(restored.changes_since(trained).entries.len(), 0);
+        }
+    }
+
+    #[test]
+    fn test_distributed_workers_end_with_the_merged_strategies() {
+        use crate::algorithm::distributed::{Coordinator, TcpExchange};
+        type Kuhn = MCCFRParallel<OneCardPokerAction, OneCardPokerState<KuhnConfig>>;
+        let mut coordinator = Coordinator::bind("127.0.0.1:0", 2, 2).unwrap();
+        let address = coordinator.local_addr().unwrap().to_string();
+        let workers: Vec<_> = (0..2)
+            .map(|_| {
+                let address = address.clone();
+                std::thread::spawn(move || {
+                    let mut worker = Kuhn::new(1, None);
+                    let mut exchange = TcpExchange::connect(&address).unwrap();
+                    worker.run_distributed(3, 500, 0.2, &mut exchange).unwrap();
+                    worker
+                })
+            })
+            .collect();
+        assert_eq!(coordinator.run::<OneCardPokerAction>(None), Ok(3));
+        for worker in workers {
+            let worker = worker.join().unwrap();
+            for (trained, merged) in worker.strategies().iter().zip(&coordinator.strategies) {
+                assert_eq!(trained.iteration(), merged.iteration());
+                let drift = trained.changes_since(merged);
+                let values = drift.entries.iter().flat_map(|entry| entry.regrets.iter().chain(&entry.policy).flatten());
+                assert!(values.clone().all(|(_, value)| value.abs() < 1e-2), "{:?}", drift);
+            }
+        }
+    }
+
+    /// Players take turns saying heads or tails twelve times and player 0
+    /// wins if there were more heads, so most histories are too long to pack
+    #[derive(Clone, Debug)]
+    struct LongGame;
+
+    fn long_game(turn: usize, heads: usize) -> String {
+        if turn == 12 {
+            let payoff = if heads > 6 { 1 } else { -1 };
+            return format!(r#"{{"type": "terminal", "payoffs": [{}, {}]}}"#, payoff, -payoff);
+        }
+        format!(
+            r#"{{"type": "player", "player": {}, "moves": [{{"action": "heads", "next": {}}}, {{"action": "tails", "next": {}}}]}}"#,
+            turn % 2,
+            long_game(turn + 1, heads + 1),
+            long_game(turn + 1, heads)
+        )
+    }
+
+    impl JsonGameSource for LongGame {
+        fn spec() -> &'static GameSpec {
+            static SPEC: std::sync::OnceLock<GameSpec> = std::sync::OnceLock::new();
+            SPEC.get_or_init(|| {
+                let description = format!(r#"{{"players": 2, "actions": ["heads", "tails"], "root": {}}}"#, long_game(0, 0));
+                GameSpec::from_json(&description).unwrap()
+            })
+        }
+    }
+
+    #[test]
+    fn test_long_histories_are_interned_and_saved() {
+        use crate::game_logic::strategy::intern::{histories_name, is_interned, load_histories, rekey};
+        use crate::game_logic::strategy::store::store_for;
+        use crate::game_logic::strategy::CondensedInfoSet;
+        use crate::game_logic::visibility::{max_features, History};
+        let mut trained = MCCFRParallel::<JsonAction, JsonGameState<LongGame>>::new(2, None);
+        trained.run_iterations(200, 0.2);
+        let file_name = std::env::temp_dir().join(format!("gtcogs_interned_{}", std::process::id()));
+        let file_name = file_name.to_str().unwrap();
+        trained.write_to(file_name);
+
+        let saved = format!("{}_p0.json", file_name);
+        let (_, policies) = store_for(&saved).read(&saved).unwrap();
+        let rekeyed = load_histories(&saved).unwrap();
+        let interned: Vec<CondensedInfoSet> = policies[0].iter().map(|(info_set, _)| *info_set).filter(|info_set| is_interned(*info_set)).collect();
+        assert!(!interned.is_empty());
+        assert!(std::path::Path::new(&histories_name(&saved)).exists());
+        for info_set in interned {
+            // Loaded by the process that trained them, so nothing moves
+            assert_eq!(rekey(info_set, &rekeyed), Ok(info_set));
+            assert!(History::from(info_set).0.len() > max_features());
         }
     }
 }
@@ -1,2 +1,9 @@
 pub mod mccfr;
 pub mod mccfr_parallel;
+pub mod dry_run;
+pub mod estimate;
+pub mod best_response;
+pub mod abstraction_loss;
+pub mod placement;
+pub mod distributed;
+pub mod vector_cfr;
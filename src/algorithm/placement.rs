@@ -0,0 +1,135 @@
+/// Where the threads of the parallel trainer run.
+///
+/// On the big training machines every runner updating the same DashMaps
+/// means most of the traffic crosses sockets. With pin_cores each runner
+/// stays on one core, spread evenly over the NUMA nodes. With numa_shards
+/// the runners of each node also train a copy of the strategies of their
+/// own, which are merged back together after every batch, see
+/// RegretStrategy::merge_changes. Shards cost one copy of the strategies
+/// per node (and one more while merging), so they only pay off where the
+/// strategies fit in memory several times over.
+///
+/// The topology is read from /sys, anywhere it can't be found everything
+/// counts as one node
+use std::fs;
+
+#[derive(Debug, Clone, Default)]
+pub struct ThreadPlacement {
+    /// Pin each runner to a core of its own
+    pub pin_cores: bool,
+    /// Give each NUMA node its own copy of the strategies
+    pub numa_shards: bool,
+}
+
+impl ThreadPlacement {
+    pub fn is_default(&self) -> bool {
+        !self.pin_cores && !self.numa_shards
+    }
+}
+
+/// The node and core a runner is placed on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    pub node: usize,
+    pub core: usize,
+}
+
+/// The cores of every NUMA node, nodes without cores are left out
+pub fn numa_nodes() -> Vec<Vec<usize>> {
+    let mut nodes = vec![];
+    if let Ok(entries) = fs::read_dir("/sys/devices/system/node") {
+        let mut numbered: Vec<(usize, Vec<usize>)> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                let number = name.strip_prefix("node")?.parse().ok()?;
+                let cpus = fs::read_to_string(entry.path().join("cpulist")).ok()?;
+                Some((number, parse_cpu_list(&cpus).ok()?))
+            })
+            .collect();
+        numbered.sort();
+        nodes = numbered.into_iter().map(|(_, cores)| cores).filter(|cores| !cores.is_empty()).collect();
+    }
+    if nodes.is_empty() {
+        let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+        nodes.push((0..cores).collect());
+    }
+    nodes
+}
+
+/// Parses the kernel's cpu list format, "0-3,8,10-11"
+pub fn parse_cpu_list(list: &str) -> Result<Vec<usize>, String> {
+    let mut cores = vec![];
+    for part in list.trim().split(',').filter(|part| !part.is_empty()) {
+        let parse = |core: &str| core.trim().parse::<usize>().map_err(|e| format!("{}: {}", part, e));
+        match part.split_once('-') {
+            Some((first, last)) => cores.extend(parse(first)?..=parse(last)?),
+            None => cores.push(parse(part)?),
+        }
+    }
+    Ok(cores)
+}
+
+/// Spreads `threads` runners over the nodes in proportion to their cores,
+/// runners of a node taking its cores in order. With more runners than
+/// cores the cores are shared round robin
+pub fn assign_slots(threads: usize, nodes: &[Vec<usize>]) -> Vec<Slot> {
+    let total: usize = nodes.iter().map(|cores| cores.len()).sum();
+    if total == 0 {
+        return vec![];
+    }
+    (0..threads)
+        .map(|thread| {
+            let mut position = thread * total / threads.max(1) % total;
+            for (node, cores) in nodes.iter().enumerate() {
+                if position < cores.len() {
+                    return Slot { node, core: cores[position] };
+                }
+                position -= cores.len();
+            }
+            unreachable!()
+        })
+        .collect()
+}
+
+/// Pins the calling thread to `core`
+#[cfg(target_os = "linux")]
+pub fn pin_to_core(core: usize) -> Result<(), String> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        libc::CPU_SET(core, &mut set);
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(format!("Could not pin to core {}: {}", core, std::io::Error::last_os_error()));
+        }
+    }
+    Ok(())
+}
+
+/// Only Linux has sched_setaffinity, elsewhere the threads go where the
+/// scheduler puts them
+#[cfg(not(target_os = "linux"))]
+pub fn pin_to_core(core: usize) -> Result<(), String> {
+    Err(format!("Could not pin to core {}: unsupported on this OS", core))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runners_spread_over_nodes() {
+        assert_eq!(parse_cpu_list("0-3,8,10-11\n"), Ok(vec![0, 1, 2, 3, 8, 10, 11]));
+        assert!(parse_cpu_list("0-a").is_err());
+
+        let nodes = vec![vec![0, 1, 2, 3], vec![4, 5, 6, 7]];
+        let slots = assign_slots(4, &nodes);
+        let placed: Vec<(usize, usize)> = slots.iter().map(|slot| (slot.node, slot.core)).collect();
+        assert_eq!(placed, vec![(0, 0), (0, 2), (1, 4), (1, 6)]);
+
+        let crowded = assign_slots(10, &nodes);
+        assert_eq!(crowded.len(), 10);
+        assert_eq!(crowded.iter().filter(|slot| slot.node == 0).count(), 5);
+        assert!(!numa_nodes().is_empty());
+    }
+}
@@ -0,0 +1,407 @@
+/// Vector CFR: one traversal of the betting tree for every private deal at
+/// once, instead of sampling a single deal per iteration.
+///
+/// The leading chance nodes of a game (the hole cards) are its private
+/// deals. A traversal carries all of them down the betting tree together,
+/// with a reach vector per player over the deals, and updates the regrets
+/// of every information set it passes for all the deals in it at once.
+/// Every action is explored, so where MCCFR samples one hand and a few
+/// lines per iteration this sees every hand against every other and the
+/// whole betting tree, which is far more sample efficient.
+///
+/// The deals are enumerated while there are at most max_deals of them
+/// (every one-card poker config), otherwise max_deals of them are sampled
+/// each iteration, which is what auction poker with its 1326 holdings per
+/// player needs. Chance nodes further down (the board, the auction card)
+/// are sampled for every deal with the same random number, so deals that
+/// can see the same cards see the same ones.
+///
+/// Following every action of both players is only feasible for small
+/// trees, for auction poker turn on with_sampled_opponents so only the
+/// updated player's actions branch.
+///
+/// The betting has to be public: at every node all the deals must have
+/// the same player to act with the same actions, which holds for every
+/// poker variant here. Strategies are stored exactly as MCCFR stores them
+use crate::distribution::sample_weighted;
+use crate::game_logic::action::Action;
+use crate::game_logic::state::{ActivePlayer, State};
+use crate::game_logic::strategy::{CondensedInfoSet, RegretStrategy};
+use crate::{Game, Utility};
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Deals to enumerate before sampling them instead
+pub const DEFAULT_MAX_DEALS: usize = 10_000;
+
+#[derive(Clone, Debug)]
+pub struct VectorCFR<A: Action, S: State<A>> {
+    strategies: Vec<Arc<RegretStrategy>>,
+    max_deals: usize,
+    /// Sample one action at the opponents' nodes instead of following all
+    sample_opponents: bool,
+    /// Every deal with its chance probability, None until enumerated or if
+    /// there are too many
+    enumerated: Option<Vec<(Game<A, S>, f32)>>,
+    pub iterations: usize,
+}
+
+/// Regret matching over the legal actions only
+fn matched(regrets: Option<Vec<f32>>, mask: &[bool]) -> Vec<f32> {
+    let positive: Vec<f32> = mask
+        .iter()
+        .enumerate()
+        .map(|(index, legal)| match (legal, &regrets) {
+            (true, Some(regrets)) => regrets[index].max(0.0),
+            _ => 0.0,
+        })
+        .collect();
+    let total: f32 = positive.iter().sum();
+    if total > 0.0 {
+        return positive.iter().map(|regret| regret / total).collect();
+    }
+    let legal = mask.iter().filter(|legal| **legal).count() as f32;
+    mask.iter().map(|is_legal| if *is_legal { 1.0 / legal } else { 0.0 }).collect()
+}
+
+/// Plays `game` through its leading chance nodes every possible way,
+/// giving up once there are more than `limit` deals
+fn enumerate_deals<A: Action, S: State<A>>(
+    game: Game<A, S>,
+    probability: f32,
+    limit: usize,
+    deals: &mut Vec<(Game<A, S>, f32)>,
+) -> bool {
+    match game.active_player() {
+        ActivePlayer::Chance(outcomes) => {
+            for (action, p) in outcomes.items().iter().zip(outcomes.probs()) {
+                if *p <= 0.0 {
+                    continue;
+                }
+                let mut next = game.clone();
+                next.play(action);
+                if !enumerate_deals(next, probability * p, limit, deals) {
+                    return false;
+                }
+            }
+            true
+        }
+        ActivePlayer::Marker(action) => {
+            let mut next = game;
+            next.play(&action);
+            enumerate_deals(next, probability, limit, deals)
+        }
+        _ => {
+            deals.push((game, probability));
+            deals.len() <= limit
+        }
+    }
+}
+
+/// Plays `game` through its leading chance nodes one random way
+fn sample_deal<A: Action, S: State<A>, R: Rng>(mut game: Game<A, S>, rng: &mut R) -> Game<A, S> {
+    loop {
+        match game.active_player() {
+            ActivePlayer::Chance(outcomes) => game.play(&outcomes.sample_rng(rng)),
+            ActivePlayer::Marker(action) => game.play(&action),
+            _ => return game,
+        }
+    }
+}
+
+/// The outcome at cumulative probability `u`, the same `u` picks the same
+/// outcome for deals with the same distribution
+fn inverse_cdf<A: Clone>(items: &[A], probabilities: &[f32], u: f32) -> A {
+    let total: f32 = probabilities.iter().sum();
+    let mut cumulative = 0.0;
+    for (item, probability) in items.iter().zip(probabilities) {
+        cumulative += probability / total;
+        if u < cumulative && *probability > 0.0 {
+            return item.clone();
+        }
+    }
+    let last = probabilities.iter().rposition(|probability| *probability > 0.0).unwrap_or(items.len() - 1);
+    items[last].clone()
+}
+
+impl<A: Action, S: State<A>> VectorCFR<A, S> {
+    pub fn new(strategies: Vec<Arc<RegretStrategy>>) -> Self {
+        VectorCFR {
+            strategies,
+            max_deals: DEFAULT_MAX_DEALS,
+            sample_opponents: false,
+            enumerated: None,
+            iterations: 0,
+        }
+    }
+
+    /// Enumerate at most `deals` deals, sample that many when there are more
+    pub fn with_max_deals(&mut self, deals: usize) {
+        self.max_deals = deals.max(1);
+        self.enumerated = None;
+    }
+
+    /// Follow a single action at the nodes of the player not being
+    /// updated, picked by the policies of all the deals there and importance
+    /// weighted for each of them. Only the updated player's own actions
+    /// still branch, which is what makes bigger trees feasible
+    pub fn with_sampled_opponents(&mut self, sample: bool) {
+        self.sample_opponents = sample;
+    }
+
+    /// The deals of an iteration and the weight of each
+    fn deals<R: Rng>(&mut self, rng: &mut R) -> Vec<(Game<A, S>, f32)> {
+        if self.enumerated.is_none() {
+            let mut deals = vec![];
+            if enumerate_deals(Game::new(), 1.0, self.max_deals, &mut deals) {
+                self.enumerated = Some(deals);
+            }
+        }
+        match &self.enumerated {
+            Some(deals) => deals.clone(),
+            None => {
+                let weight = 1.0 / self.max_deals as f32;
+                (0..self.max_deals).map(|_| (sample_deal(Game::new(), rng), weight)).collect()
+            }
+        }
+    }
+
+    pub fn run_iterations<R: Rng>(&mut self, iterations: usize, rng: &mut R) {
+        for _ in 0..iterations {
+            for player in 0..self.strategies.len() {
+                let (games, weights): (Vec<_>, Vec<_>) = self.deals(rng).into_iter().unzip();
+                let reach = vec![vec![1.0; games.len()]; self.strategies.len()];
+                self.traverse(games, &weights, reach, player, rng);
+            }
+            self.iterations += 1;
+            for strategy in &self.strategies {
+                strategy.tick();
+            }
+        }
+    }
+
+    /// Values of `updated_player` for every deal below this node. `weights`
+    /// are the chance probabilities of the deals, `reach` how likely each
+    /// player is to play to this node in each of them
+    fn traverse<R: Rng>(
+        &mut self,
+        mut games: Vec<Game<A, S>>,
+        weights: &[f32],
+        reach: Vec<Vec<f32>>,
+        updated_player: usize,
+        rng: &mut R,
+    ) -> Vec<Utility> {
+        match games[0].active_player() {
+            ActivePlayer::Terminal(_) => games
+                .iter()
+                .map(|game| match game.active_player() {
+                    ActivePlayer::Terminal(utilities) => utilities[updated_player],
+                    _ => panic!("The deals ended at different nodes, the betting isn't public"),
+                })
+                .collect(),
+            ActivePlayer::Marker(_) | ActivePlayer::Chance(_) => {
+                let u = rng.gen_range(0.0, 1.0);
+                for game in &mut games {
+                    match game.active_player() {
+                        ActivePlayer::Marker(action) => game.play(&action),
+                        ActivePlayer::Chance(outcomes) => {
+                            game.play(&inverse_cdf(outcomes.items(), outcomes.probs(), u));
+                        }
+                        _ => panic!("The deals reached different nodes, the betting isn't public"),
+                    }
+                }
+                self.traverse(games, weights, reach, updated_player, rng)
+            }
+            ActivePlayer::Player(player_num, actions) => {
+                let player = player_num as usize;
+                let mut mask = vec![false; A::max_index() as usize];
+                for action in &actions {
+                    mask[action.index() as usize] = true;
+                }
+
+                // The current policy of every information set at this node
+                let info_sets: Vec<CondensedInfoSet> =
+                    games.iter().map(|game| game.get_information_set(player)).collect();
+                let mut policies: HashMap<CondensedInfoSet, Vec<f32>> = HashMap::new();
+                for info_set in &info_sets {
+                    policies
+                        .entry(*info_set)
+                        .or_insert_with(|| matched(self.strategies[player].regrets(info_set), &mask));
+                }
+
+                if player != updated_player && self.sample_opponents {
+                    let (info_sets, policies) = (&info_sets, &policies);
+                    return self.sample_opponent(
+                        games, weights, reach, updated_player, rng, info_sets, policies, &actions,
+                    );
+                }
+
+                let mut values = vec![0.0; games.len()];
+                let mut action_values = vec![];
+                for action in &actions {
+                    let index = action.index() as usize;
+                    let mut child_reach = reach.clone();
+                    for (deal, info_set) in info_sets.iter().enumerate() {
+                        child_reach[player][deal] *= policies[info_set][index];
+                    }
+                    let children: Vec<Game<A, S>> = games
+                        .iter()
+                        .map(|game| {
+                            let mut child = game.clone();
+                            child.play(action);
+                            child
+                        })
+                        .collect();
+                    // The opponents never play into this action, its values
+                    // don't count
+                    let reached = (0..games.len()).any(|deal| {
+                        (0..child_reach.len()).filter(|p| *p != updated_player).all(|p| child_reach[p][deal] > 0.0)
+                    });
+                    let child_values = match reached {
+                        true => self.traverse(children, weights, child_reach, updated_player, rng),
+                        false => vec![0.0; games.len()],
+                    };
+                    for (deal, info_set) in info_sets.iter().enumerate() {
+                        values[deal] += policies[info_set][index] * child_values[deal];
+                    }
+                    action_values.push((index, child_values));
+                }
+
+                if player == updated_player {
+                    self.update(player, &info_sets, &policies, &mask, weights, &reach, &values, &action_values);
+                }
+                values
+            }
+        }
+    }
+
+    /// Follows one action of `actions` for all the deals, sampled from
+    /// the policies of the deals weighed by how likely they reach here
+    /// (with some uniform exploration so every action can come up). Each
+    /// deal's value is importance weighted by its own policy
+    #[allow(clippy::too_many_arguments)]
+    fn sample_opponent<R: Rng>(
+        &mut self,
+        games: Vec<Game<A, S>>,
+        weights: &[f32],
+        mut reach: Vec<Vec<f32>>,
+        updated_player: usize,
+        rng: &mut R,
+        info_sets: &[CondensedInfoSet],
+        policies: &HashMap<CondensedInfoSet, Vec<f32>>,
+        actions: &[A],
+    ) -> Vec<Utility> {
+        const EXPLORATION: f32 = 0.1;
+        let player = games[0].active_player().as_index().expect("Sampling at a node without a player");
+        let mut mixture = vec![0.0; actions.len()];
+        for (deal, info_set) in info_sets.iter().enumerate() {
+            let weight = weights[deal] * reach[player][deal];
+            for (position, action) in actions.iter().enumerate() {
+                mixture[position] += weight * policies[info_set][action.index() as usize];
+            }
+        }
+        let total: f32 = mixture.iter().sum();
+        let sampling: Vec<f32> = mixture
+            .iter()
+            .map(|weight| {
+                let policy = if total > 0.0 { weight / total } else { 0.0 };
+                let uniform = 1.0 / actions.len() as f32;
+                if total > 0.0 { (1.0 - EXPLORATION) * policy + EXPLORATION * uniform } else { uniform }
+            })
+            .collect();
+        let position = sample_weighted(&sampling, rng);
+        let action = &actions[position];
+        let index = action.index() as usize;
+
+        let ratios: Vec<f32> =
+            info_sets.iter().map(|info_set| policies[info_set][index] / sampling[position]).collect();
+        for (deal, info_set) in info_sets.iter().enumerate() {
+            reach[player][deal] *= policies[info_set][index];
+        }
+        let children = games
+            .into_iter()
+            .map(|mut game| {
+                game.play(action);
+                game
+            })
+            .collect();
+        let values = self.traverse(children, weights, reach, updated_player, rng);
+        values.iter().zip(ratios).map(|(value, ratio)| value * ratio).collect()
+    }
+
+    /// Adds the regrets and the average policy of every information set
+    /// at a node of `player`, summed over the deals in it
+    #[allow(clippy::too_many_arguments)]
+    fn update(
+        &self,
+        player: usize,
+        info_sets: &[CondensedInfoSet],
+        policies: &HashMap<CondensedInfoSet, Vec<f32>>,
+        mask: &[bool],
+        weights: &[f32],
+        reach: &[Vec<f32>],
+        values: &[Utility],
+        action_values: &[(usize, Vec<Utility>)],
+    ) {
+        let mut regrets: HashMap<CondensedInfoSet, Vec<f32>> = HashMap::new();
+        let mut average: HashMap<CondensedInfoSet, Vec<f32>> = HashMap::new();
+        for (deal, info_set) in info_sets.iter().enumerate() {
+            let opponents: f32 = (0..reach.len()).filter(|p| *p != player).map(|p| reach[p][deal]).product();
+            let counterfactual = weights[deal] * opponents;
+            let regret = regrets.entry(*info_set).or_insert_with(|| vec![0.0; mask.len()]);
+            for (index, child_values) in action_values {
+                regret[*index] += counterfactual * (child_values[deal] - values[deal]);
+            }
+            let own = weights[deal] * reach[player][deal];
+            let policy = average.entry(*info_set).or_insert_with(|| vec![0.0; mask.len()]);
+            for (index, probability) in policies[info_set].iter().enumerate() {
+                policy[index] += own * probability;
+            }
+        }
+        let strategy = &self.strategies[player];
+        for (info_set, regret) in regrets {
+            strategy.visit(info_set);
+            strategy.update(info_set, mask, Some(&regret), Some(&average[&info_set]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::best_response::exploitability;
+    use crate::implementations::one_card_poker::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_vector_cfr_solves_kuhn() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let strategies = vec![Arc::new(RegretStrategy::default()), Arc::new(RegretStrategy::default())];
+        let mut cfr = VectorCFR::<OneCardPokerAction, KuhnLikeState>::new(strategies.clone());
+        cfr.run_iterations(300, &mut rng);
+        // Far below where sampled MCCFR plateaus, see best_response
+        let exploited = exploitability::<OneCardPokerAction, KuhnLikeState>(&strategies);
+        assert!(exploited < 0.02, "Exploitability {}", exploited);
+    }
+
+    #[test]
+    fn test_sampled_deals_still_learn() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let strategies = vec![Arc::new(RegretStrategy::default()), Arc::new(RegretStrategy::default())];
+        let mut cfr = VectorCFR::<OneCardPokerAction, KuhnLikeState>::new(strategies.clone());
+        cfr.with_max_deals(4);
+        cfr.run_iterations(2000, &mut rng);
+        assert!(exploitability::<OneCardPokerAction, KuhnLikeState>(&strategies) < 0.1);
+
+        let strategies = vec![Arc::new(RegretStrategy::default()), Arc::new(RegretStrategy::default())];
+        let mut cfr = VectorCFR::<OneCardPokerAction, KuhnLikeState>::new(strategies.clone());
+        cfr.with_sampled_opponents(true);
+        cfr.run_iterations(2000, &mut rng);
+        assert!(exploitability::<OneCardPokerAction, KuhnLikeState>(&strategies) < 0.1);
+
+        assert_eq!(inverse_cdf(&[1, 2, 3], &[0.5, 0.0, 0.5], 0.5), 3);
+        assert_eq!(inverse_cdf(&[1, 2, 3], &[0.5, 0.0, 0.5], 0.2), 1);
+        assert_eq!(matched(None, &[true, false, true]), vec![0.5, 0.0, 0.5]);
+    }
+}
@@ -0,0 +1,197 @@
+/// Is a feature worth the infosets it costs? Every feature of the schema
+/// splits the infosets it is in, so a feature that tells the bot little
+/// only spreads the same training over more of them.
+///
+/// For every variant of the abstraction (the full one, then one without
+/// each feature in question) a small blueprint is trained with the
+/// features flattened out (see schema::set_ablated), and each ablated
+/// blueprint plays a match against the full one on the LocalEngine, both
+/// from the blueprint alone with no re-solving. A variant that holds its
+/// own with far fewer infosets didn't need the feature, at least not at
+/// this budget. The auction game is far too big for exact exploitability
+/// (see algorithm::best_response), so the match is all there is to go on.
+///
+/// Variants are trained one after the other, since the ablation applies to
+/// every game in the process
+use crate::algorithm::mccfr_parallel::MCCFRParallel;
+use crate::bot::agent::{Agent, AgentConfig};
+use crate::bot::decision::DecisionEngine;
+use crate::bot::local_engine::LocalEngine;
+use crate::bot::player::AuctionBot;
+use crate::bot::runner::Bot;
+use crate::game_logic::schema::{set_ablated, FeatureKind};
+use crate::game_logic::strategy::blueprint::{BlueprintStrategy, Evaluator};
+use crate::implementations::auction::*;
+use crate::units::WinRate;
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct AblationConfig {
+    /// Training iterations of every variant
+    pub iterations: usize,
+    pub threads: usize,
+    pub epsilon: f32,
+    /// Hands every ablated variant plays against the full abstraction
+    pub hands: usize,
+    /// Time each variant gets for its whole match, see LocalEngine
+    pub time_bank: Duration,
+    pub seed: u64,
+}
+
+impl Default for AblationConfig {
+    fn default() -> Self {
+        AblationConfig {
+            iterations: 20_000,
+            threads: 4,
+            epsilon: 0.2,
+            hands: 1000,
+            time_bank: Duration::from_secs(30),
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub name: String,
+    pub ablated: Vec<FeatureKind>,
+}
+
+impl Variant {
+    /// The abstraction as it is
+    pub fn full() -> Variant {
+        Variant {
+            name: "full".to_string(),
+            ablated: vec![],
+        }
+    }
+
+    pub fn without(name: &str, ablated: &[FeatureKind]) -> Variant {
+        Variant {
+            name: name.to_string(),
+            ablated: ablated.to_vec(),
+        }
+    }
+}
+
+/// The full abstraction and one variant without each of the features that
+/// blow up the infosets the most
+pub fn default_variants() -> Vec<Variant> {
+    vec![
+        Variant::full(),
+        Variant::without("no aggression", &[FeatureKind::Aggression]),
+        Variant::without("no stacks", &[FeatureKind::Stack]),
+        Variant::without("no auction", &[FeatureKind::Auction]),
+    ]
+}
+
+#[derive(Debug, Clone)]
+pub struct AblationResult {
+    pub variant: Variant,
+    /// Infosets trained for each player
+    pub info_sets: Vec<usize>,
+    /// Against the full abstraction, None for the full abstraction itself
+    pub win_rate: Option<WinRate>,
+}
+
+impl fmt::Display for AblationResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let info_sets: usize = self.info_sets.iter().sum();
+        write!(f, "{:<16}{:>10} infosets", self.variant.name, info_sets)?;
+        match &self.win_rate {
+            Some(win_rate) => write!(f, "{:>10.0} mbb/hand against full", win_rate.mbb_per_hand()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A blueprint of `variant` trained for `config.iterations`, and the
+/// number of infosets of each player
+pub fn train(config: &AblationConfig, variant: &Variant) -> (BlueprintStrategy, Vec<usize>) {
+    set_ablated(&variant.ablated);
+    let mut trainer = MCCFRParallel::<AuctionPokerAction, AuctionPokerState>::new(config.threads, None);
+    trainer.run_iterations(config.iterations, config.epsilon);
+    set_ablated(&[]);
+
+    let info_sets = trainer.strategies().iter().map(|strategy| strategy.size()).collect();
+    let policies = trainer.strategies().iter().map(|strategy| strategy.policy_table()).collect();
+    let blueprint = BlueprintStrategy::from_policies(policies)
+        .with_evaluator(Evaluator::from_schema())
+        .expect("The schemas' own fit matches them")
+        .with_ablation(&variant.ablated);
+    (blueprint, info_sets)
+}
+
+/// A bot playing `blueprint` as it is, without re-solving
+pub fn blueprint_bot(blueprint: BlueprintStrategy) -> Box<dyn Bot> {
+    let config = AgentConfig {
+        resolve: false,
+        ..Default::default()
+    };
+    Box::new(AuctionBot::with_agent(Agent::new(DecisionEngine::new(blueprint)).with_config(config)))
+}
+
+/// Trains every variant and plays all but the first against the first,
+/// which should be the full abstraction
+pub fn run_ablation(config: &AblationConfig, variants: &[Variant]) -> Vec<AblationResult> {
+    let mut results = vec![];
+    let mut full: Option<BlueprintStrategy> = None;
+    for variant in variants {
+        println!("Training the {} variant", variant.name);
+        let (blueprint, info_sets) = train(config, variant);
+        let win_rate = match &full {
+            Some(full) => {
+                let mut engine = LocalEngine::new(blueprint_bot(blueprint), blueprint_bot(full.clone()))
+                    .with_seed(config.seed)
+                    .with_time_bank(config.time_bank);
+                Some(engine.play_match(config.hands).win_rate(0))
+            }
+            None => {
+                full = Some(blueprint);
+                None
+            }
+        };
+        let result = AblationResult {
+            variant: variant.clone(),
+            info_sets,
+            win_rate,
+        };
+        println!("{}", result);
+        results.push(result);
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_logic::game::Game;
+    use crate::game_logic::visibility::History;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_ablated_blueprint_looks_up_without_the_feature() {
+        let mut game = Game::<AuctionPokerAction, AuctionPokerState>::new();
+        game.play(&AuctionPokerAction::DealHole(50, 0));
+        game.play(&AuctionPokerAction::DealHole(51, 0));
+        game.play(&AuctionPokerAction::DealHole(3, 1));
+        game.play(&AuctionPokerAction::DealHole(4, 1));
+        game.play(&AuctionPokerAction::BettingRoundStart);
+
+        // Trained without the hole card ranks, which are 0 for every infoset
+        let mut history: History = game.get_information_set(0).into();
+        assert_ne!(history.0[1], 0);
+        history.0[1] = 0;
+        let mut policies = BTreeMap::new();
+        policies.insert(history.into_condensed(), vec![0.0, 1.0]);
+        let blueprint = BlueprintStrategy::from_policies(vec![policies, BTreeMap::new()]);
+        assert!(blueprint.get_exact_policy(&game, 0).is_none());
+        let ablated = blueprint.with_ablation(&[FeatureKind::Ranks]);
+        assert!(ablated.get_exact_policy(&game, 0).is_some());
+
+        let variants = default_variants();
+        assert_eq!(variants[0], Variant::full());
+        assert!(variants[1..].iter().all(|variant| !variant.ablated.is_empty()));
+    }
+}
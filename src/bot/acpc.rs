@@ -0,0 +1,460 @@
+/// Plays a Bot against an ACPC dealer, for benchmarking heads-up hold'em
+/// against the public ACPC agents and servers.
+///
+/// The ACPC dealer sends the whole state of the hand on every line:
+///
+///     MATCHSTATE:<position>:<hand number>:<betting>:<cards>
+///
+/// where betting is one segment of f, c and r<amount> per round separated
+/// by / (c is a check or a call, raises are *to* amount counting the
+/// whole hand), and cards are "<hole 0>|<hole 1>/<flop>/<turn>/<river>"
+/// with the opponent's hole cards left empty until the showdown. When
+/// it's our turn we answer with the same line followed by :f, :c or :r<amount>.
+///
+/// The Bot itself only speaks the Runner's protocol, so every line is
+/// turned into the packet the competition engine would have sent instead
+/// (everything new since the last packet, which like the engine's is only
+/// sent when we have to act or the hand is over) and handed to a Runner,
+/// which keeps all of its panic isolation. Positions are mapped to
+/// the Runner's seats by the blinds (seat 0 is the small blind) and raise
+/// amounts are converted between the whole hand and the current round
+use crate::bot::runner::{Bot, Reply, Runner};
+use crate::eval::rank::HandRanker;
+use crate::game_logic::action::Parsable;
+use crate::implementations::auction::Card;
+use std::cmp::Ordering;
+use std::io::{self, BufRead, BufReader, BufWriter, Empty, Sink, Write};
+use std::net::TcpStream;
+
+pub const ACPC_VERSION: &str = "VERSION:2.0.0";
+
+/// The parts of an ACPC game definition the client needs, by position
+#[derive(Clone, Debug)]
+pub struct AcpcGame {
+    pub blinds: [u32; 2],
+    /// Position that acts first in each round
+    pub first_player: [usize; 4],
+}
+
+impl AcpcGame {
+    /// holdem.nolimit.2p.reverse_blinds.game, as played in the competition
+    pub fn nolimit_reverse_blinds() -> Self {
+        AcpcGame {
+            blinds: [100, 50],
+            first_player: [1, 0, 0, 0],
+        }
+    }
+
+    /// Our seat in the Runner's protocol, where 0 is the small blind
+    pub fn seat(&self, position: usize) -> usize {
+        if self.blinds[position] < self.blinds[position ^ 1] {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AcpcAction {
+    Fold,
+    Call,
+    Raise(u32),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatchState {
+    pub position: usize,
+    pub hand_number: u32,
+    /// Actions of each round so far
+    pub betting: Vec<Vec<AcpcAction>>,
+    pub hole_cards: [Vec<Card>; 2],
+    pub board: Vec<Card>,
+    line: String,
+}
+
+fn parse_cards(cards: &str) -> Result<Vec<Card>, String> {
+    let chars: Vec<char> = cards.chars().collect();
+    if !chars.len().is_multiple_of(2) {
+        return Err(format!("Bad cards {}", cards));
+    }
+    chars
+        .chunks(2)
+        .map(|card| {
+            let card: String = card.iter().collect();
+            Card::parse(&card).ok_or_else(|| format!("Bad card {}", card))
+        })
+        .collect()
+}
+
+fn parse_round(round: &str) -> Result<Vec<AcpcAction>, String> {
+    let mut actions = vec![];
+    let mut chars = round.chars().peekable();
+    while let Some(action) = chars.next() {
+        match action {
+            'f' => actions.push(AcpcAction::Fold),
+            'c' => actions.push(AcpcAction::Call),
+            'r' => {
+                let mut amount = String::new();
+                while let Some(digit) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                    amount.push(*digit);
+                    chars.next();
+                }
+                let amount = amount.parse().map_err(|_| format!("Raise without an amount in {}", round))?;
+                actions.push(AcpcAction::Raise(amount));
+            }
+            x => return Err(format!("Unknown action {} in {}", x, round)),
+        }
+    }
+    Ok(actions)
+}
+
+impl MatchState {
+    pub fn parse(line: &str) -> Result<MatchState, String> {
+        let line = line.trim();
+        let fields: Vec<&str> = line.split(':').collect();
+        if fields.len() != 5 || fields[0] != "MATCHSTATE" {
+            return Err(format!("Not a match state: {}", line));
+        }
+        let position: usize = fields[1].parse().map_err(|_| format!("Bad position in {}", line))?;
+        let hand_number = fields[2].parse().map_err(|_| format!("Bad hand number in {}", line))?;
+        let betting = fields[3].split('/').map(parse_round).collect::<Result<Vec<_>, _>>()?;
+
+        let mut cards = fields[4].split('/');
+        let holes: Vec<&str> = cards.next().unwrap_or("").split('|').collect();
+        if holes.len() != 2 || position > 1 {
+            return Err(format!("Only heads up is supported: {}", line));
+        }
+        let hole_cards = [parse_cards(holes[0])?, parse_cards(holes[1])?];
+        let mut board = vec![];
+        for round in cards {
+            board.extend(parse_cards(round)?);
+        }
+
+        Ok(MatchState {
+            position,
+            hand_number,
+            betting,
+            hole_cards,
+            board,
+            line: line.to_string(),
+        })
+    }
+
+    pub fn reply(&self, action: &AcpcAction) -> String {
+        let action = match action {
+            AcpcAction::Fold => "f".to_string(),
+            AcpcAction::Call => "c".to_string(),
+            AcpcAction::Raise(amount) => format!("r{}", amount),
+        };
+        format!("{}:{}", self.line, action)
+    }
+}
+
+/// The betting of a hand replayed action by action
+#[derive(Clone, Debug, Default)]
+struct Replay {
+    /// (position, round, action, whether it was facing a bet)
+    actions: Vec<(usize, usize, AcpcAction, bool)>,
+    /// Chips each position has put in
+    committed: [u32; 2],
+    /// What both positions had put in when each round started
+    round_start: Vec<u32>,
+    folded: Option<usize>,
+    /// Position due to act next in the current round
+    next: usize,
+}
+
+fn replay(game: &AcpcGame, state: &MatchState) -> Replay {
+    let mut replay = Replay {
+        committed: game.blinds,
+        ..Replay::default()
+    };
+    for (round, actions) in state.betting.iter().enumerate() {
+        // Blinds count towards the first round, like pips in the Runner's protocol
+        replay.round_start.push(if round == 0 { 0 } else { replay.committed[0] });
+        let mut position = game.first_player[round];
+        for action in actions {
+            let facing = replay.committed[position] < replay.committed[position ^ 1];
+            match action {
+                AcpcAction::Fold => replay.folded = Some(position),
+                AcpcAction::Call => replay.committed[position] = replay.committed[position ^ 1],
+                AcpcAction::Raise(amount) => replay.committed[position] = *amount,
+            }
+            replay.actions.push((position, round, action.clone(), facing));
+            position ^= 1;
+        }
+        replay.next = position;
+    }
+    replay
+}
+
+pub struct AcpcClient<B: Bot, R: BufRead, W: Write> {
+    runner: Runner<B, Empty, Sink>,
+    game: AcpcGame,
+    reader: R,
+    writer: W,
+    ranker: Option<HandRanker>,
+    hand_number: Option<u32>,
+    /// How much of the hand has been put into clauses
+    told_actions: usize,
+    told_board: usize,
+    /// Clauses waiting for the next packet
+    pending: Vec<String>,
+}
+
+impl<B: Bot> AcpcClient<B, BufReader<TcpStream>, BufWriter<TcpStream>> {
+    pub fn connect(bot: B, game: AcpcGame, address: &str) -> Self {
+        let stream = TcpStream::connect(address).expect("Could not connect to the dealer");
+        stream.set_nodelay(true).unwrap();
+        let reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = BufWriter::new(stream);
+        write!(writer, "{}\r\n", ACPC_VERSION).unwrap();
+        writer.flush().unwrap();
+        AcpcClient::new(bot, game, reader, writer)
+    }
+}
+
+impl<B: Bot, R: BufRead, W: Write> AcpcClient<B, R, W> {
+    pub fn new(bot: B, game: AcpcGame, reader: R, writer: W) -> Self {
+        AcpcClient {
+            runner: Runner::new(bot, io::empty(), io::sink()),
+            game,
+            reader,
+            writer,
+            ranker: None,
+            hand_number: None,
+            told_actions: 0,
+            told_board: 0,
+            pending: vec![],
+        }
+    }
+
+    pub fn runner(&self) -> &Runner<B, Empty, Sink> {
+        &self.runner
+    }
+
+    /// Plays until the dealer hangs up
+    pub fn run(&mut self) {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match self.reader.read_line(&mut line) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => {}
+            }
+            let line = line.trim();
+            // Comments and server messages
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(reply) = self.handle(line) {
+                write!(self.writer, "{}\r\n", reply).unwrap();
+                self.writer.flush().unwrap();
+            }
+        }
+    }
+
+    /// Our reply to a match state line, None when it isn't our turn
+    pub fn handle(&mut self, line: &str) -> Option<String> {
+        let state = match MatchState::parse(line) {
+            Ok(state) => state,
+            Err(error) => {
+                println!("[ERROR] {}", error);
+                return None;
+            }
+        };
+        let replay = replay(&self.game, &state);
+        let hand_over = replay.folded.is_some() || state.hole_cards.iter().all(|cards| !cards.is_empty());
+        let our_turn = !hand_over && replay.next == state.position;
+
+        self.tell(&state, &replay, hand_over);
+        if !our_turn && !hand_over {
+            return None;
+        }
+        let packet = self.pending.join(" ");
+        self.pending.clear();
+        let (reply, _) = self.runner.handle(&packet);
+        if hand_over {
+            return None;
+        }
+        Some(state.reply(&self.to_acpc(&reply, &state, &replay)))
+    }
+
+    /// Queues up clauses for everything that happened since the last line
+    fn tell(&mut self, state: &MatchState, replay: &Replay, hand_over: bool) {
+        let clauses = &mut self.pending;
+        let seat = self.game.seat(state.position);
+        if self.hand_number != Some(state.hand_number) {
+            self.hand_number = Some(state.hand_number);
+            self.told_actions = 0;
+            self.told_board = 0;
+            clauses.clear();
+            clauses.push(format!("P{}", seat));
+            clauses.push(format!("H{}", cards_string(&state.hole_cards[state.position])));
+        }
+
+        let board_for_round = |round: usize| match round {
+            0 => 0,
+            round => 2 + round,
+        };
+        for (index, (position, round, action, facing)) in replay.actions.iter().enumerate() {
+            let board = board_for_round(*round).min(state.board.len());
+            if board > self.told_board {
+                self.told_board = board;
+                clauses.push(format!("B{}", cards_string(&state.board[..board])));
+            }
+            if index < self.told_actions {
+                continue;
+            }
+            self.told_actions = index + 1;
+            if *position == state.position {
+                // The bot already knows what it did
+                continue;
+            }
+            clauses.push(match action {
+                AcpcAction::Fold => "F".to_string(),
+                AcpcAction::Call if *facing => "C".to_string(),
+                AcpcAction::Call => "K".to_string(),
+                AcpcAction::Raise(amount) => format!("R{}", amount - replay.round_start[*round]),
+            });
+        }
+        if state.board.len() > self.told_board {
+            self.told_board = state.board.len();
+            clauses.push(format!("B{}", cards_string(&state.board)));
+        }
+
+        if hand_over {
+            let opponent = &state.hole_cards[state.position ^ 1];
+            if !opponent.is_empty() {
+                self.pending.push(format!("O{}", cards_string(opponent)));
+            }
+            let delta = self.delta(state, replay);
+            self.pending.push(format!("D{}", delta));
+        }
+    }
+
+    /// Chips we won this (finished) hand
+    fn delta(&mut self, state: &MatchState, replay: &Replay) -> i64 {
+        let us = state.position;
+        let won = replay.committed[us ^ 1] as i64;
+        let lost = -(replay.committed[us] as i64);
+        if let Some(folded) = replay.folded {
+            return if folded == us { lost } else { won };
+        }
+        let ranker = self.ranker.get_or_insert_with(HandRanker::new);
+        let board: Vec<u8> = state.board.iter().map(|card| card.as_u8()).collect();
+        let hole = |position: usize| -> Vec<u8> { state.hole_cards[position].iter().map(|card| card.as_u8()).collect() };
+        match ranker.compare_showdown(&hole(us), &hole(us ^ 1), &board) {
+            Ordering::Greater => won,
+            Ordering::Less => lost,
+            Ordering::Equal => 0,
+        }
+    }
+
+    fn to_acpc(&self, reply: &Reply, state: &MatchState, replay: &Replay) -> AcpcAction {
+        match reply {
+            Reply::Fold => AcpcAction::Fold,
+            Reply::Call | Reply::Check => AcpcAction::Call,
+            Reply::Raise(amount) => {
+                let round = state.betting.len() - 1;
+                AcpcAction::Raise(replay.round_start[round] + amount)
+            }
+            Reply::Bid(_) => {
+                println!("[WARNING] There is no auction in ACPC games, calling instead");
+                AcpcAction::Call
+            }
+        }
+    }
+}
+
+fn cards_string(cards: &[Card]) -> String {
+    cards.iter().map(|card| card.to_string().unwrap()).collect::<Vec<_>>().join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::match_context::MatchContext;
+    use crate::bot::runner::{parse_packet, Clause};
+    use crate::units::WinRate;
+    use std::io::Cursor;
+
+    /// Raises once every round then calls, remembering every packet
+    struct RecordingBot {
+        clauses: Vec<Clause>,
+        raised: bool,
+    }
+
+    impl Bot for RecordingBot {
+        fn observe(&mut self, clause: &Clause) {
+            if let Clause::Board(_) = clause {
+                self.raised = false;
+            }
+            self.clauses.push(clause.clone());
+        }
+
+        fn act(&mut self, _: &MatchContext) -> Option<Reply> {
+            if self.raised {
+                return Some(Reply::Call);
+            }
+            self.raised = true;
+            Some(Reply::Raise(300))
+        }
+    }
+
+    #[test]
+    fn test_parse_match_state() {
+        let state = MatchState::parse("MATCHSTATE:0:30:cr250c/r500:9s8h|/8c8d5c").unwrap();
+        assert_eq!(state.position, 0);
+        assert_eq!(state.hand_number, 30);
+        assert_eq!(
+            state.betting,
+            vec![vec![AcpcAction::Call, AcpcAction::Raise(250), AcpcAction::Call], vec![AcpcAction::Raise(500)]]
+        );
+        assert_eq!(state.hole_cards, [vec![Card::new("9s"), Card::new("8h")], vec![]]);
+        assert_eq!(state.board.len(), 3);
+        assert_eq!(state.reply(&AcpcAction::Call), "MATCHSTATE:0:30:cr250c/r500:9s8h|/8c8d5c:c");
+        assert!(MatchState::parse("MATCHSTATE:0:30:cx:9s8h|").is_err());
+        assert!(MatchState::parse("MATCHSTATE:0:30:c:9s8x|").is_err());
+        assert!(MatchState::parse("MATCHSTATE:0:30:c:9s8é|").is_err());
+    }
+
+    #[test]
+    fn test_plays_a_hand_through_the_runner() {
+        let bot = RecordingBot {
+            clauses: vec![],
+            raised: false,
+        };
+        // We are position 0, the big blind. The small blind limps and calls
+        // our raise, we bet the flop and call their raise, then they fold
+        // to our bet on the turn
+        let input = "\
+MATCHSTATE:0:0::AsKs|
+MATCHSTATE:0:0:c:AsKs|
+MATCHSTATE:0:0:cr300:AsKs|
+MATCHSTATE:0:0:cr300c/:AsKs|/2c3d4h
+MATCHSTATE:0:0:cr300c/r600:AsKs|/2c3d4h
+MATCHSTATE:0:0:cr300c/r600r1200:AsKs|/2c3d4h
+MATCHSTATE:0:0:cr300c/r600r1200c/:AsKs|/2c3d4h/5s
+# a comment from the dealer
+MATCHSTATE:0:0:cr300c/r600r1200c/r1500:AsKs|/2c3d4h/5s
+MATCHSTATE:0:0:cr300c/r600r1200c/r1500f:AsKs|/2c3d4h/5s
+";
+        let mut output = Vec::new();
+        let mut client = AcpcClient::new(bot, AcpcGame::nolimit_reverse_blinds(), Cursor::new(input), &mut output);
+        client.run();
+        let clauses = client.runner().bot().clauses.clone();
+        assert_eq!(client.runner().context.win_rate(), WinRate::new(1200, 1));
+        drop(client);
+
+        let replies: Vec<String> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| line.rsplit(':').next().unwrap().to_string())
+            .collect();
+        assert_eq!(replies, vec!["r300", "r600", "c", "r1500"]);
+        let expected = parse_packet("P1 HAs,Ks C C B2c,3d,4h R900 B2c,3d,4h,5s F D1200");
+        assert_eq!(clauses, expected);
+    }
+}
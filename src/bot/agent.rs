@@ -0,0 +1,310 @@
+/// The bot's decision maker at the table: the blueprint where it is
+/// strong, re-solving where it isn't.
+///
+/// Preflop and in the auction the DecisionEngine's cascade decides as
+/// always. From AgentConfig::resolve_from on (the flop by default) every
+/// betting decision is re-solved from the public state of the hand (see
+/// bot::resolver), carrying the constraints of the previous re-solve of
+/// the hand when AgentConfig::safe is set. A re-solve that fails or gets
+/// through fewer than min_iterations iterations in the time we have isn't
+/// trusted and the blueprint decides after all
+use crate::bot::decision::*;
+use crate::bot::prefetch::Prefetched;
+use crate::bot::public_state::*;
+use crate::bot::resolver::*;
+use crate::distribution::Categorical;
+use crate::game_logic::visibility::Round;
+use crate::implementations::auction::*;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct AgentConfig {
+    /// Re-solve at all, without it the blueprint decides everything
+    pub resolve: bool,
+    /// First round the resolver decides, earlier ones (and every bid) are
+    /// left to the blueprint
+    pub resolve_from: Round,
+    pub resolver: ResolverConfig,
+    /// Fewest iterations of a re-solve we act on
+    pub min_iterations: usize,
+    /// Carry constraints from one re-solve to the next
+    pub safe: bool,
+    /// Most the opponent may get over the carried values in a constrained
+    /// re-solve, as a share of the pot, before we act on the blueprint
+    /// instead (see Resolver::constraint_excess)
+    pub max_constraint_excess: f32,
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        AgentConfig {
+            resolve: true,
+            resolve_from: Round::Flop,
+            resolver: ResolverConfig::default(),
+            min_iterations: 50,
+            safe: true,
+            max_constraint_excess: 0.1,
+        }
+    }
+}
+
+/// The last re-solve we acted on this hand
+struct LastSolve {
+    /// The history at its root
+    history: Vec<AuctionPokerAction>,
+    board: Deck,
+    resolver: Resolver,
+    leaf_value: Arc<EquityLeafValue>,
+}
+
+pub struct Agent {
+    engine: DecisionEngine,
+    pub config: AgentConfig,
+    last: Option<LastSolve>,
+}
+
+impl Agent {
+    pub fn new(engine: DecisionEngine) -> Self {
+        Agent {
+            engine,
+            config: AgentConfig::default(),
+            last: None,
+        }
+    }
+
+    pub fn with_config(self, config: AgentConfig) -> Self {
+        Agent { config, ..self }
+    }
+
+    pub fn engine(&self) -> &DecisionEngine {
+        &self.engine
+    }
+
+    pub fn engine_mut(&mut self) -> &mut DecisionEngine {
+        &mut self.engine
+    }
+
+    /// Decides for `seat` in `game`, reached by `history` (see
+    /// IncrementalState::history), within about `budget`
+    pub fn decide(&mut self, game: &AuctionGame, history: &[AuctionPokerAction], seat: usize, budget: Duration) -> Decision {
+        let start = Instant::now();
+        if self.resolves(game) {
+            match self.resolve(game, history, seat, budget) {
+                Ok(Some(action)) => return (action, DecisionSource::Resolved),
+                Ok(None) => {}
+                Err(error) => println!("[WARNING] Resolving failed, using the blueprint: {}", error),
+            }
+        }
+        self.last = None;
+        self.engine.decide_within(game, seat, budget.saturating_sub(start.elapsed()))
+    }
+
+    /// Same as `decide` but where the blueprint is used its lookups come
+    /// from `prefetched`, which was worked out while the opponent thought
+    pub fn decide_prefetched(
+        &mut self,
+        prefetched: &Prefetched,
+        history: &[AuctionPokerAction],
+        seat: usize,
+        budget: Duration,
+    ) -> Decision {
+        if self.resolves(&prefetched.game) {
+            return self.decide(&prefetched.game, history, seat, budget);
+        }
+        self.last = None;
+        self.engine.decide_prefetched(prefetched, seat)
+    }
+
+    fn resolves(&self, game: &AuctionGame) -> bool {
+        let round = match game.state().community_cards().len() {
+            0 => Round::PreFlop,
+            3 => Round::Flop,
+            4 => Round::Turn,
+            _ => Round::River,
+        };
+        let bidding = game
+            .active_player()
+            .actions()
+            .iter()
+            .any(|action| matches!(action, AuctionPokerAction::Bid(_)));
+        let round: usize = round.into();
+        let resolve_from: usize = self.config.resolve_from.clone().into();
+        self.config.resolve && !bidding && round >= resolve_from
+    }
+
+    /// The resolver's action, None if the re-solve can't be trusted
+    fn resolve(
+        &mut self,
+        game: &AuctionGame,
+        history: &[AuctionPokerAction],
+        seat: usize,
+        budget: Duration,
+    ) -> Result<Option<AuctionPokerAction>, String> {
+        let start = Instant::now();
+        let last = self.last.take().filter(|last| history.starts_with(&last.history));
+
+        let constraints = match &last {
+            Some(last) if self.config.safe => {
+                // What was played since, up to the end of its street
+                let line: Vec<AuctionPokerAction> = betting(&history[last.history.len()..])
+                    .take_while(|action| !matches!(action, AuctionPokerAction::DealCommunity(_)))
+                    .cloned()
+                    .collect();
+                Some(last.resolver.carry(seat, &line, last.leaf_value.as_ref())?)
+            }
+            _ => None,
+        };
+        // On a new street the constraints are where it started, so that is
+        // where we solve from, following what was played since
+        let street_start = history
+            .iter()
+            .rposition(|action| matches!(action, AuctionPokerAction::DealCommunity(_)))
+            .map(|position| position + 1);
+        let (root, since): (&[AuctionPokerAction], Vec<AuctionPokerAction>) = match (&last, street_start) {
+            (Some(last), Some(start)) if constraints.is_some() && start > last.history.len() => {
+                (&history[..start], betting(&history[start..]).cloned().collect())
+            }
+            _ => (history, vec![]),
+        };
+
+        let mut public = PublicState::from_history(root)?;
+        if let Some(constraints) = &constraints {
+            let mut range = constraints.range.clone();
+            range.block(public.board);
+            // Reach shrinks with every action carried, start over from one
+            public.ranges[seat] = range.normalized().ok_or("The board leaves nothing of our carried range")?;
+        }
+        // Leaf values only depend on the board (and the auction)
+        let leaf_value = match last {
+            Some(last) if last.board == public.board => last.leaf_value,
+            _ => Arc::new(EquityLeafValue::new(&public, self.config.resolver.strength_iterations)?),
+        };
+        let mut config = self.config.resolver.clone();
+        config.time_budget = config.time_budget.min(budget.saturating_sub(start.elapsed()));
+        let (board, pot) = (public.board, public.pot);
+        let mut resolver = Resolver::new(public, config)?;
+        if let Some(constraints) = constraints {
+            resolver = resolver.with_constraints(constraints);
+        }
+        if resolver.solve(leaf_value.as_ref()) < self.config.min_iterations {
+            return Ok(None);
+        }
+        // Giving the opponent more than the last re-solve did is exactly
+        // what the constraints are there to prevent
+        let limit = self.config.max_constraint_excess * pot as f32;
+        if resolver.constrained() && resolver.constraint_excess(leaf_value.as_ref()) > limit {
+            return Ok(None);
+        }
+
+        let (actions, policy) = resolver.policy_after(seat, &since, our_holding(history, seat)?)?;
+        let action = Categorical::new_normalized(policy, actions).sample();
+        if !game.active_player().actions().contains(&action) {
+            return Err(format!("Resolved to {:?}, which is not legal", action));
+        }
+        self.last = Some(LastSolve {
+            history: root.to_vec(),
+            board,
+            resolver,
+            leaf_value,
+        });
+        Ok(Some(action))
+    }
+}
+
+/// The betting decisions among `actions`, deals are kept to tell streets apart
+fn betting(actions: &[AuctionPokerAction]) -> impl Iterator<Item = &AuctionPokerAction> {
+    actions.iter().filter(|action| {
+        matches!(
+            action,
+            AuctionPokerAction::Fold
+                | AuctionPokerAction::Call
+                | AuctionPokerAction::Check
+                | AuctionPokerAction::Raise(_)
+                | AuctionPokerAction::DealCommunity(_)
+        )
+    })
+}
+
+/// Our first two hole cards, the ones ranges are over
+fn our_holding(history: &[AuctionPokerAction], seat: usize) -> Result<Holding, String> {
+    let cards: Vec<Card> = history
+        .iter()
+        .filter_map(|action| match action {
+            AuctionPokerAction::DealHole(card, player_num) if *player_num == seat => Some(Card::from_index(*card)),
+            _ => None,
+        })
+        .take(2)
+        .collect();
+    match cards[..] {
+        [first, second] => Ok(Holding::new(first, second)),
+        _ => Err("We don't have our hole cards yet".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::incremental::IncrementalState;
+    use crate::bot::player::to_reply;
+    use crate::bot::runner::{parse_packet, Clause, Reply};
+    use crate::game_logic::state::ActivePlayer;
+    use crate::game_logic::strategy::blueprint::BlueprintStrategy;
+
+    fn apply_packet(state: &mut IncrementalState, packet: &str) {
+        for clause in parse_packet(packet) {
+            state.apply_clause(&clause).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_blueprint_preflop_and_resolver_after() {
+        let config = AgentConfig {
+            resolver: ResolverConfig {
+                iterations: 20,
+                time_budget: Duration::from_secs(60),
+                strength_iterations: 20,
+                ..Default::default()
+            },
+            min_iterations: 10,
+            ..Default::default()
+        };
+        let mut agent = Agent::new(DecisionEngine::new(BlueprintStrategy::empty())).with_config(config);
+        let budget = Duration::from_secs(60);
+
+        // The blueprint decides preflop (an empty one falls back on heuristics)
+        let mut state = IncrementalState::new();
+        apply_packet(&mut state, "T30 P0 HAs,Ad");
+        let game = state.decision_game().unwrap();
+        let (_, source) = agent.decide(&game, state.history(), 0, budget);
+        assert_eq!(source, DecisionSource::Heuristic);
+
+        // After the auction the resolver takes over
+        let mut state = IncrementalState::new();
+        apply_packet(&mut state, "T30 P1 HAs,Ad");
+        state.apply_clause(&Clause::Call).unwrap();
+        apply_packet(&mut state, "B2s,2c,Kh");
+        state.apply_own(&Reply::Bid(10)).unwrap();
+        apply_packet(&mut state, "A20");
+        let game = state.decision_game().unwrap();
+        let (_, source) = agent.decide(&game, state.history(), 1, budget);
+        assert_eq!(source, DecisionSource::Resolved);
+        assert!(!agent.last.as_ref().unwrap().resolver.constrained());
+
+        // We check (whatever we were going to do) and the opponent raises,
+        // the next re-solve keeps to the constraints of the first
+        state.apply_own(&Reply::Check).unwrap();
+        let game = state.game().unwrap();
+        let raise = match game.active_player() {
+            ActivePlayer::Player(_, actions) => actions.into_iter().find(|action| matches!(action, AuctionPokerAction::Raise(_))),
+            _ => None,
+        };
+        let raise = raise.expect("The opponent can raise");
+        state.apply_opponent(&to_reply(&raise, game.state().pot())).unwrap();
+        let game = state.decision_game().unwrap();
+        let (action, source) = agent.decide(&game, state.history(), 1, budget);
+        assert_eq!(source, DecisionSource::Resolved);
+        assert!(game.active_player().actions().contains(&action));
+        assert!(agent.last.as_ref().unwrap().resolver.constrained());
+    }
+}
@@ -0,0 +1,269 @@
+/// What the auction is worth to each hand under a blueprint's own postflop
+/// play, and what it should bid for it.
+///
+/// The value of the third card isn't its equity: it depends on how both
+/// players go on to play the turn and river. Given the blueprints, every
+/// hand that gets to the auction is played out from there by both of
+/// them three times over: once with the player winning the card, once
+/// losing it and once tying, each for free so only the card counts.
+/// Winning less losing is what the card is worth to the hand.
+///
+/// The winner pays the loser's bid, so the auction is a second price
+/// auction and bidding what the card is worth is an equilibrium: bidding
+/// more only wins where the card costs more than it's worth, bidding less
+/// only loses where it was worth the price. (A tie, where both pay their
+/// own bid, doesn't change that much.) The report sets the blueprint's
+/// bids next to those equilibrium bids, per hand class by equity on the
+/// flop. Trained bidding should at least go up where the card is worth
+/// more:
+///
+///     gtcogs auction-values auction_poker.bp 500 8
+use crate::bot::decision::AuctionGame;
+use crate::bot::streets::{blueprint_policy, Rollouts};
+use crate::eval::bid::BidValue;
+use crate::eval::rank::HandRanker;
+use crate::game_logic::state::ActivePlayer;
+use crate::game_logic::strategy::blueprint::BlueprintStrategy;
+use crate::implementations::auction::RelativeSize::*;
+use crate::implementations::auction::*;
+use std::fmt;
+
+/// Hand classes, by flop equity in equal steps
+pub const CLASSES: usize = 5;
+/// Rollouts behind the flop equity that picks a hand's class
+const CLASS_ITERATIONS: u32 = 500;
+
+#[derive(Debug, Clone)]
+pub struct AuctionValueConfig {
+    pub hands: usize,
+    /// Rollouts behind the value of every auction outcome
+    pub samples: usize,
+    pub seed: u64,
+}
+
+impl Default for AuctionValueConfig {
+    fn default() -> Self {
+        AuctionValueConfig {
+            hands: 500,
+            samples: 8,
+            seed: 0,
+        }
+    }
+}
+
+/// Values of the auction outcomes to one player at one auction, in chips
+/// won by the end of the hand
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct OutcomeValues {
+    pub win: f32,
+    pub lose: f32,
+    pub tie: f32,
+}
+
+impl OutcomeValues {
+    /// What the third card is worth, so what to bid for it, within `stack`
+    pub fn equilibrium_bid(&self, stack: u32) -> u32 {
+        (self.win - self.lose).round().clamp(0.0, stack as f32) as u32
+    }
+}
+
+/// Every hand of a class that got to the auction, by player
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClassValues {
+    pub values: Vec<OutcomeValues>,
+    pub equilibrium_bids: Vec<u32>,
+    /// What the blueprint bids with the hand on average
+    pub blueprint_bids: Vec<f32>,
+}
+
+/// `quantile` of `values`, 0 if there are none
+fn quantile(values: &[f32], quantile: f32) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    sorted[((sorted.len() - 1) as f32 * quantile).round() as usize]
+}
+
+fn mean(values: impl Iterator<Item = f32>) -> f32 {
+    let (total, count) = values.fold((0.0, 0), |(total, count), value| (total + value, count + 1));
+    total / count.max(1) as f32
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AuctionValueReport {
+    pub hands: usize,
+    /// Hands that got to the auction
+    pub auctions: usize,
+    pub classes: [ClassValues; CLASSES],
+}
+
+impl AuctionValueReport {
+    /// Correlation of the blueprint's bids with the equilibrium bids over
+    /// every hand, positive if it bids more where the card is worth more
+    pub fn correlation(&self) -> f32 {
+        let pairs: Vec<(f32, f32)> = self
+            .classes
+            .iter()
+            .flat_map(|class| class.equilibrium_bids.iter().map(|bid| *bid as f32).zip(class.blueprint_bids.iter().cloned()))
+            .collect();
+        let (x, y) = (mean(pairs.iter().map(|(x, _)| *x)), mean(pairs.iter().map(|(_, y)| *y)));
+        let covariance: f32 = pairs.iter().map(|(a, b)| (a - x) * (b - y)).sum();
+        let spread = |deviations: f32| deviations.sqrt();
+        let (sx, sy) = (
+            spread(pairs.iter().map(|(a, _)| (a - x) * (a - x)).sum()),
+            spread(pairs.iter().map(|(_, b)| (b - y) * (b - y)).sum()),
+        );
+        if sx == 0.0 || sy == 0.0 {
+            return 0.0;
+        }
+        covariance / (sx * sy)
+    }
+}
+
+impl fmt::Display for AuctionValueReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} of {} hands got to the auction, chips by hand class (both players' hands)", self.auctions, self.hands)?;
+        writeln!(
+            f,
+            "{:<12}{:>7}{:>8}{:>8}{:>8}{:>20}{:>12}",
+            "equity", "hands", "win", "lose", "tie", "bid 25/50/75%", "blueprint"
+        )?;
+        for (class, values) in self.classes.iter().enumerate() {
+            let bids: Vec<f32> = values.equilibrium_bids.iter().map(|bid| *bid as f32).collect();
+            writeln!(
+                f,
+                "{:<12}{:>7}{:>+8.1}{:>+8.1}{:>+8.1}{:>20}{:>12.1}",
+                format!("{}-{}%", 100 * class / CLASSES, 100 * (class + 1) / CLASSES),
+                values.values.len(),
+                mean(values.values.iter().map(|value| value.win)),
+                mean(values.values.iter().map(|value| value.lose)),
+                mean(values.values.iter().map(|value| value.tie)),
+                format!("{:.0}/{:.0}/{:.0}", quantile(&bids, 0.25), quantile(&bids, 0.5), quantile(&bids, 0.75)),
+                mean(values.blueprint_bids.iter().cloned())
+            )?;
+        }
+        write!(f, "Correlation of the blueprint's bids with the equilibrium bids: {:+.2}", self.correlation())
+    }
+}
+
+/// The hand class of `player_num` at the auction in `game`
+fn class_of(ranker: &HandRanker, game: &AuctionGame, player_num: usize) -> usize {
+    let state = game.state();
+    let value = BidValue::rollout(ranker, &state.hand(player_num).as_u8(), &state.community_cards().as_u8(), CLASS_ITERATIONS);
+    let equity = (value.win + value.loss) / 2.0;
+    ((equity * CLASSES as f64) as usize).min(CLASSES - 1)
+}
+
+/// The values to `player_num` of winning, losing and tying the auction
+/// about to start in `game`, with nobody paying for the card
+fn outcome_values(rollouts: &mut Rollouts, game: &AuctionGame, player_num: usize) -> OutcomeValues {
+    let mut value = |bids: [u32; 2]| {
+        let mut game = game.clone();
+        // Player 1 bids first
+        for bidder in [1, 0] {
+            game.play(&AuctionPokerAction::Bid(Amount(bids[bidder])));
+        }
+        match player_num {
+            0 => rollouts.value(&game),
+            _ => -rollouts.value(&game),
+        }
+    };
+    let (mut win, mut lose) = ([0, 0], [0, 0]);
+    win[player_num] = 1;
+    lose[player_num ^ 1] = 1;
+    OutcomeValues {
+        win: value(win),
+        lose: value(lose),
+        tie: value([0, 0]),
+    }
+}
+
+/// Plays `config.hands` hands of the blueprints against each other and
+/// values the auction for both players wherever one is reached
+pub fn auction_values(blueprints: [&BlueprintStrategy; 2], config: &AuctionValueConfig) -> AuctionValueReport {
+    let mut rollouts = Rollouts::new(blueprints, config.samples, config.seed);
+    let ranker = HandRanker::new();
+    let mut report = AuctionValueReport::default();
+    for _ in 0..config.hands {
+        report.hands += 1;
+        let mut game = AuctionGame::new();
+        loop {
+            if let ActivePlayer::Player(_, legal) = game.active_player() {
+                if legal.iter().all(|action| matches!(action, AuctionPokerAction::Bid(_))) {
+                    break;
+                }
+            }
+            match rollouts.next_action(&game) {
+                Some(action) => game.play(&action),
+                None => break,
+            }
+        }
+        let legal = match game.active_player() {
+            ActivePlayer::Player(_, legal) => legal,
+            _ => continue,
+        };
+        report.auctions += 1;
+
+        for (player_num, blueprint) in blueprints.iter().enumerate() {
+            let values = outcome_values(&mut rollouts, &game, player_num);
+            let stack = game.state().stacks()[player_num];
+            // Only the first bidder's policy can be looked up at this node,
+            // the second bids from the same information
+            let mut bidding = game.clone();
+            if player_num == 0 {
+                bidding.play(&legal[0]);
+            }
+            let bids = match bidding.active_player() {
+                ActivePlayer::Player(_, bids) => bids,
+                _ => unreachable!("Both players bid"),
+            };
+            let blueprint_bid = blueprint_policy(blueprint, &bidding, player_num, &bids)
+                .iter()
+                .map(|(action, probability)| match action {
+                    AuctionPokerAction::Bid(size) => size.to_amount(game.state().pot()) as f32 * probability,
+                    _ => 0.0,
+                })
+                .sum();
+
+            let class = &mut report.classes[class_of(&ranker, &game, player_num)];
+            class.equilibrium_bids.push(values.equilibrium_bid(stack));
+            class.values.push(values);
+            class.blueprint_bids.push(blueprint_bid);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auction_values_per_class() {
+        let values = OutcomeValues {
+            win: 30.0,
+            lose: -10.0,
+            tie: 5.0,
+        };
+        assert_eq!(values.equilibrium_bid(400), 40);
+        assert_eq!(values.equilibrium_bid(25), 25);
+        assert_eq!(OutcomeValues { win: -20.0, ..values }.equilibrium_bid(400), 0);
+
+        let blueprint = BlueprintStrategy::empty();
+        let config = AuctionValueConfig {
+            hands: 4,
+            samples: 1,
+            seed: 0,
+        };
+        let report = auction_values([&blueprint, &blueprint], &config);
+        assert_eq!(report.hands, 4);
+        assert!(report.auctions > 0, "{}", report);
+        let classes = &report.classes;
+        assert_eq!(classes.iter().map(|class| class.values.len()).sum::<usize>(), 2 * report.auctions);
+        assert!(classes.iter().all(|class| class.blueprint_bids.len() == class.values.len()));
+        // Bidding uniformly over the bid buckets, somewhere in the middle
+        assert!(classes.iter().flat_map(|class| &class.blueprint_bids).all(|bid| (10.0..390.0).contains(bid)), "{}", report);
+    }
+}
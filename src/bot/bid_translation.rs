@@ -0,0 +1,99 @@
+/// Opponent bids as abstract bids, the way an observed raise would be
+/// translated back into the blueprint's grid.
+///
+/// The engine tells us the opponent's exact bid, but the blueprint only
+/// knows the bid buckets, each standing for the bid in its middle. Just
+/// taking the bucket a bid falls in rounds every bid to that middle, so an
+/// opponent who always bids near the top (or bottom) of a bucket looks
+/// systematically cheaper (or dearer) than they are. Instead the bid is
+/// split between the two nearest bucket middles in proportion to how
+/// close it is to each, and translate_bid samples one of them. On average
+/// the translated bid is then the bid itself.
+///
+/// The tracked hand (IncrementalState) still plays the exact bid, since
+/// the auction winner pays the loser's bid and the chips have to stay in
+/// step with the engine. Bids never enter an infoset directly, what gets
+/// the translated bid is the opponent model (see AuctionBot::observe), so
+/// the bid quantiles our bids are sized against sit on the same grid as
+/// our own bids
+use crate::game_logic::action::{Action, ActionIndex};
+use crate::implementations::auction::RelativeSize::*;
+use crate::implementations::auction::*;
+use rand::Rng;
+
+fn is_bid(index: ActionIndex) -> bool {
+    index < AuctionPokerAction::max_index() && matches!(AuctionPokerAction::from(index), AuctionPokerAction::Bid(_))
+}
+
+/// The bid a bucket stands for
+fn middle(index: ActionIndex) -> u32 {
+    match AuctionPokerAction::from(index) {
+        AuctionPokerAction::Bid(Amount(amount)) => amount,
+        action => unreachable!("{:?} is not a bid", action),
+    }
+}
+
+/// The bid buckets `bid` translates to and how likely each is, one bucket
+/// if the bid is its middle or lies beyond the first or last middle
+pub fn bid_translation(bid: u32) -> Vec<(ActionIndex, f32)> {
+    let bucket = AuctionPokerAction::Bid(Amount(bid)).index();
+    let neighbour = match bid.cmp(&middle(bucket)) {
+        std::cmp::Ordering::Less => bucket.checked_sub(1),
+        std::cmp::Ordering::Greater => bucket.checked_add(1),
+        std::cmp::Ordering::Equal => None,
+    };
+    match neighbour.filter(|index| is_bid(*index)) {
+        Some(neighbour) => {
+            let (here, there) = (middle(bucket) as f32, middle(neighbour) as f32);
+            let towards = (bid as f32 - here) / (there - here);
+            vec![(bucket, 1.0 - towards), (neighbour, towards)]
+        }
+        None => vec![(bucket, 1.0)],
+    }
+}
+
+/// The opponent's `bid` as the abstract bid of one of its nearest buckets,
+/// see bid_translation
+pub fn translate_bid<R: Rng>(bid: u32, rng: &mut R) -> AuctionPokerAction {
+    let translation = bid_translation(bid);
+    let mut pick = rng.gen_range(0.0, 1.0);
+    for (index, probability) in &translation {
+        pick -= probability;
+        if pick < 0.0 {
+            return AuctionPokerAction::from(*index);
+        }
+    }
+    AuctionPokerAction::from(translation[translation.len() - 1].0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::STACK_SIZE;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_translated_bids_are_unbiased() {
+        for bid in 0..=STACK_SIZE {
+            let translation = bid_translation(bid);
+            let total: f32 = translation.iter().map(|(_, probability)| probability).sum();
+            assert!((total - 1.0).abs() < 1e-5, "{} {:?}", bid, translation);
+            assert!(translation.iter().all(|(index, probability)| is_bid(*index) && *probability >= 0.0));
+
+            // Between the first and last middles the translation averages out to the bid
+            let first = middle(AuctionPokerAction::Bid(Amount(0)).index());
+            let last = middle(AuctionPokerAction::Bid(Amount(STACK_SIZE)).index());
+            if (first..=last).contains(&bid) {
+                let expected: f32 = translation.iter().map(|(index, probability)| middle(*index) as f32 * probability).sum();
+                assert!((expected - bid as f32).abs() < 1e-2, "{} {:?}", bid, translation);
+            }
+        }
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let bucket = AuctionPokerAction::Bid(Amount(25)).index();
+        let bid = (middle(bucket) + middle(bucket + 1)) / 2;
+        let translated: Vec<ActionIndex> = (0..200).map(|_| translate_bid(bid, &mut rng).index()).collect();
+        assert!(translated.iter().all(|index| *index == bucket || *index == bucket + 1));
+        assert!(translated.contains(&bucket) && translated.contains(&(bucket + 1)));
+    }
+}
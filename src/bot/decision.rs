@@ -0,0 +1,535 @@
+use crate::bot::heuristics::*;
+use crate::bot::memo::LookupMemo;
+use crate::bot::opponent_model::OpponentModel;
+use crate::bot::prefetch::*;
+use crate::bot::reload::*;
+use crate::bot::sizing::*;
+use crate::distribution::Categorical;
+use crate::eval::equity::*;
+use crate::eval::rank::HandRanker;
+use crate::game_logic::action::{Action, ActionIndex};
+use crate::game_logic::game::Game;
+use crate::game_logic::state::ActivePlayer;
+use crate::game_logic::strategy::blueprint::BlueprintStrategy;
+use crate::implementations::auction::RelativeSize::*;
+use crate::implementations::auction::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub type AuctionGame = Game<AuctionPokerAction, AuctionPokerState>;
+
+/// Which stage of the fallback cascade produced a decision
+#[derive(Clone, Debug, PartialEq)]
+pub enum DecisionSource {
+    /// Called or folded an all in by its exact pot odds
+    AllInEquity,
+    ExactBlueprint,
+    BestFitBlueprint,
+    Heuristic,
+    /// Re-solved at the table, see bot::agent
+    Resolved,
+    /// Nothing was computed before the deadline
+    Timeout,
+}
+
+/// [Neal] Turns the current game into a concrete action for the engine.
+///
+/// Facing an all in, the call is decided by exact equity against the
+/// opponent's range whenever that can be enumerated, the range of all ins
+/// the opponent model has seen them show down (uniform until it has seen
+/// enough). Otherwise decisions
+/// go through a fallback cascade:
+///     1. The exact infoset in the blueprint
+///     2. The closest fitting infoset according to the blueprint's Evaluator
+///     3. Rule-based heuristics (bid sizer for the auction, check/fold otherwise)
+///
+/// With exploit_bids set, whichever bid the cascade chose is then shaded
+/// against the opponent's observed bid distribution. With continuous_sizing
+/// set, raises taken from the exact blueprint are moved within their bucket
+/// (experimental, see bot::sizing)
+///
+/// With prefetching enabled, call `prefetch` whenever we hand the turn over
+/// and `take_prefetched` once the next action is known
+///
+/// The blueprint can be swapped while the engine runs, see bot::reload.
+/// Lookups are remembered until `new_hand`, see bot::memo
+pub struct DecisionEngine {
+    blueprint: SharedBlueprint,
+    memo: Arc<LookupMemo>,
+    /// Only held, the watch stops when it is dropped
+    _watch: Option<BlueprintWatch>,
+    bid_sizer: BidSizer,
+    opponent_model: OpponentModel,
+    prefetcher: Option<Prefetcher>,
+    pub exploit_bids: bool,
+    pub continuous_sizing: bool,
+}
+
+impl DecisionEngine {
+    pub fn new(blueprint: BlueprintStrategy) -> Self {
+        DecisionEngine {
+            blueprint: SharedBlueprint::new(blueprint),
+            memo: Arc::new(LookupMemo::default()),
+            _watch: None,
+            bid_sizer: BidSizer::default(),
+            opponent_model: OpponentModel::default(),
+            prefetcher: None,
+            exploit_bids: false,
+            continuous_sizing: false,
+        }
+    }
+
+    /// Starts a background worker exploring up to `max_branches`
+    /// continuations every time `prefetch` is called
+    pub fn with_prefetch(self, max_branches: usize) -> Self {
+        let prefetcher = Prefetcher::new(self.blueprint.clone(), max_branches);
+        DecisionEngine {
+            prefetcher: Some(prefetcher),
+            ..self
+        }
+    }
+
+    /// Reloads the blueprint from `file_name` whenever it changes, checking every `interval`
+    pub fn with_blueprint_watch(self, file_name: &str, interval: Duration) -> Self {
+        let watch = BlueprintWatch::spawn(file_name, self.blueprint.clone(), interval);
+        DecisionEngine {
+            _watch: Some(watch),
+            ..self
+        }
+    }
+
+    /// Prefetch our next decision while waiting on the opponent or the deal.
+    /// Does nothing if prefetching is disabled
+    pub fn prefetch(&self, game: &AuctionGame, player_num: usize) {
+        if let Some(prefetcher) = &self.prefetcher {
+            prefetcher.prefetch(game, player_num);
+        }
+    }
+
+    /// The game after `action` (and any following markers) along with its
+    /// blueprint lookups, if the worker got to it in time
+    pub fn take_prefetched(&self, action: &AuctionPokerAction) -> Option<Prefetched> {
+        self.prefetcher.as_ref().and_then(|prefetcher| prefetcher.take(action))
+    }
+
+    pub fn memo(&self) -> &LookupMemo {
+        &self.memo
+    }
+
+    /// Forget the blueprint lookups of the last hand
+    pub fn new_hand(&self) {
+        self.memo.clear();
+    }
+
+    pub fn opponent_model(&self) -> &OpponentModel {
+        &self.opponent_model
+    }
+
+    /// Record the opponent's bid once the engine reveals it
+    pub fn observe_opponent_bid(&mut self, pot: u32, bid: u32) {
+        self.opponent_model.observe_bid(pot, bid);
+    }
+
+    /// Record the cards the opponent showed down after we called their all
+    /// in, `state` being the end of the hand
+    pub fn observe_all_in_showdown(&mut self, state: &AuctionPokerState, player_num: usize, opponent_cards: &[Card]) {
+        let hand = state.hand(player_num).cards;
+        let ranker = HandRanker::new();
+        let strengths = hand_strengths(&ranker, hand, state.community_cards(), opponent_cards.len(), MAX_SHOWDOWNS);
+        let shown: Deck = opponent_cards.iter().cloned().collect();
+        if let Some(strength) = strengths.and_then(|strengths| strengths.get(&shown.0).cloned()) {
+            self.opponent_model.observe_all_in(strength);
+        }
+    }
+
+    /// How bids are sized when the blueprint has none to give
+    pub fn with_bid_sizer(self, bid_sizer: BidSizer) -> Self {
+        DecisionEngine { bid_sizer, ..self }
+    }
+
+    pub fn decide(&self, game: &AuctionGame, player_num: usize) -> Decision {
+        self.decide_with(game, player_num, None)
+    }
+
+    /// Same as `decide` but reuses the blueprint lookups of a prefetched continuation
+    pub fn decide_prefetched(&self, prefetched: &Prefetched, player_num: usize) -> Decision {
+        self.decide_with(&prefetched.game, player_num, Some(&prefetched.lookups))
+    }
+
+    fn decide_with(&self, game: &AuctionGame, player_num: usize, lookups: Option<&BlueprintLookups>) -> Decision {
+        let (memo, model) = (&self.memo, &self.opponent_model);
+        let decision = run_cascade(&self.blueprint.current(), memo, &self.bid_sizer, model, game, player_num, lookups, None);
+        self.shade(game, player_num, decision)
+    }
+
+    /// Same as `decide` but never takes (much) longer than `budget`.
+    ///
+    /// The cascade runs on a worker thread. It reports the heuristic action
+    /// before starting the slow best fit search, so when the deadline passes
+    /// we play the last action it managed to compute (or check/fold if it
+    /// got nowhere, e.g. because it panicked). A worker that overruns is
+    /// told to stop at its next stage and its result is thrown away
+    pub fn decide_within(&self, game: &AuctionGame, player_num: usize, budget: Duration) -> Decision {
+        let deadline = Instant::now() + budget;
+        let mut latest = (safe_action(game.active_player().actions()), DecisionSource::Timeout);
+
+        let (sender, receiver) = channel::<(bool, Decision)>();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let worker_cancelled = cancelled.clone();
+        let blueprint = self.blueprint.current();
+        let memo = self.memo.clone();
+        let bid_sizer = self.bid_sizer.clone();
+        let opponent_model = self.opponent_model.clone();
+        let worker_game = game.clone();
+        thread::spawn(move || {
+            let interim_sender = sender.clone();
+            let interim = move |decision: Decision| {
+                let _ = interim_sender.send((false, decision));
+            };
+            let stages = Stages {
+                interim: &interim,
+                cancelled: &worker_cancelled,
+            };
+            let decision = run_cascade(&blueprint, &memo, &bid_sizer, &opponent_model, &worker_game, player_num, None, Some(stages));
+            let _ = sender.send((true, decision));
+        });
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match receiver.recv_timeout(remaining) {
+                Ok((true, decision)) => {
+                    latest = decision;
+                    break;
+                }
+                Ok((false, decision)) => latest = decision,
+                Err(RecvTimeoutError::Timeout) => {
+                    println!("[WARNING] Decision ran past its {:?} budget, playing {:?}", budget, latest);
+                    cancelled.store(true, Ordering::SeqCst);
+                    break;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        self.shade(game, player_num, latest)
+    }
+
+    /// Shade bids against the opponent model if exploiting, refine raise sizes if enabled
+    fn shade(&self, game: &AuctionGame, player_num: usize, decision: Decision) -> Decision {
+        match decision {
+            (action @ AuctionPokerAction::Raise(_), DecisionSource::ExactBlueprint) if self.continuous_sizing => {
+                let policy = self.memo.exact(&self.blueprint.current(), game, player_num).unwrap_or_default();
+                let active = game.active_player();
+                (refine_raise(&NeighborInterpolation, &action, &policy, active.actions()), DecisionSource::ExactBlueprint)
+            }
+            (AuctionPokerAction::Bid(Amount(bid)), source) if self.exploit_bids => {
+                let pot = game.state().pot();
+                (AuctionPokerAction::Bid(Amount(self.opponent_model.shade_bid(pot, bid))), source)
+            }
+            decision => decision,
+        }
+    }
+}
+
+pub type Decision = (AuctionPokerAction, DecisionSource);
+
+/// Hooks for running the cascade against a deadline
+struct Stages<'a> {
+    /// Receives the heuristic action before the slow stages start
+    interim: &'a dyn Fn(Decision),
+    /// Set once nobody is waiting on the result anymore
+    cancelled: &'a AtomicBool,
+}
+
+/// The fallback cascade described on DecisionEngine
+#[allow(clippy::too_many_arguments)]
+fn run_cascade(
+    blueprint: &Arc<BlueprintStrategy>,
+    memo: &LookupMemo,
+    bid_sizer: &BidSizer,
+    opponent_model: &OpponentModel,
+    game: &AuctionGame,
+    player_num: usize,
+    lookups: Option<&BlueprintLookups>,
+    stages: Option<Stages>,
+) -> Decision {
+    let actions = match game.active_player() {
+        ActivePlayer::Player(p, actions) if p as usize == player_num => actions,
+        x => panic!("Asked to decide for player {} but got {:?}", player_num, x),
+    };
+
+    if let Some(action) = all_in_decision(game.state(), player_num, &actions, opponent_model) {
+        return (action, DecisionSource::AllInEquity);
+    }
+
+    let exact = match lookups {
+        Some(lookups) => lookups.exact.clone(),
+        None => memo.exact(blueprint, game, player_num),
+    };
+    if let Some(action) = exact.and_then(|policy| sample_policy(&policy, &actions)) {
+        return (action, DecisionSource::ExactBlueprint);
+    }
+
+    let mut fallback = None;
+    if let Some(stages) = &stages {
+        let decision = (heuristic(bid_sizer, game, player_num, &actions), DecisionSource::Heuristic);
+        (stages.interim)(decision.clone());
+        if stages.cancelled.load(Ordering::SeqCst) {
+            return decision;
+        }
+        fallback = Some(decision);
+    }
+
+    let best = match lookups {
+        Some(lookups) => lookups.best.clone(),
+        None => memo.best(blueprint, game, player_num),
+    };
+    if let Some(action) = best.and_then(|policy| sample_policy(&policy, &actions)) {
+        return (action, DecisionSource::BestFitBlueprint);
+    }
+
+    fallback.unwrap_or_else(|| (heuristic(bid_sizer, game, player_num, &actions), DecisionSource::Heuristic))
+}
+
+/// Call or fold when the opponent is all in, None if they aren't or the
+/// showdown is too far away to enumerate. The opponent could be holding
+/// anything we haven't seen, a third card too if they took the auction,
+/// weighted by how the hands `model` saw them go all in with compare
+fn all_in_decision(
+    state: &AuctionPokerState,
+    player_num: usize,
+    actions: &[AuctionPokerAction],
+    model: &OpponentModel,
+) -> Option<AuctionPokerAction> {
+    let opponent = player_num ^ 1;
+    if state.stacks()[opponent] != 0 || !actions.contains(&AuctionPokerAction::Call) || !actions.contains(&AuctionPokerAction::Fold) {
+        return None;
+    }
+    let opponent_cards = match state.winner() {
+        Some(Winner::Player(winner)) if winner != opponent => 2,
+        Some(_) => 3,
+        None => 2,
+    };
+    let hand = state.hand(player_num).cards;
+    let board = state.community_cards();
+    let ranker = HandRanker::new();
+    let strengths = match model.all_in_count() >= model.min_samples {
+        true => Some(hand_strengths(&ranker, hand, board, opponent_cards, MAX_SHOWDOWNS)?),
+        false => None,
+    };
+    let range = |cards: &[u8]| match &strengths {
+        Some(strengths) => {
+            let deck: Deck = cards.iter().map(|card| Card(*card)).collect();
+            strengths.get(&deck.0).map_or(1.0, |strength| model.all_in_weight(*strength))
+        }
+        None => 1.0,
+    };
+    let equity = exact_equity(&ranker, hand, board, opponent_cards, &range, MAX_SHOWDOWNS)?;
+
+    let pips = state.pips();
+    let to_call = pips[opponent].saturating_sub(pips[player_num]) as f64;
+    let pot_after_call = state.pot() as f64 + to_call;
+    if equity.share() * pot_after_call > to_call {
+        Some(AuctionPokerAction::Call)
+    } else {
+        Some(AuctionPokerAction::Fold)
+    }
+}
+
+fn heuristic(bid_sizer: &BidSizer, game: &AuctionGame, player_num: usize, actions: &[AuctionPokerAction]) -> AuctionPokerAction {
+    let is_bidding = actions
+        .iter()
+        .all(|action| matches!(action, AuctionPokerAction::Bid(_)));
+    if is_bidding {
+        let bid = bid_sizer.bid_for(game.state(), player_num);
+        AuctionPokerAction::Bid(Amount(bid))
+    } else {
+        check_fold(actions)
+    }
+}
+
+/// Something we can always play without any computation:
+/// check/fold when betting, the smallest bid in the auction
+pub fn safe_action(actions: &[AuctionPokerAction]) -> AuctionPokerAction {
+    let is_bidding = actions
+        .iter()
+        .all(|action| matches!(action, AuctionPokerAction::Bid(_)));
+    if is_bidding {
+        AuctionPokerAction::Bid(Amount(0))
+    } else {
+        check_fold(actions)
+    }
+}
+
+/// The same decision without growing the pot: raises become calls (or
+/// checks when there's nothing to call). Used to protect a lead
+pub fn low_variance(action: &AuctionPokerAction, actions: &[AuctionPokerAction]) -> AuctionPokerAction {
+    match action {
+        AuctionPokerAction::Raise(_) if actions.contains(&AuctionPokerAction::Call) => AuctionPokerAction::Call,
+        AuctionPokerAction::Raise(_) if actions.contains(&AuctionPokerAction::Check) => AuctionPokerAction::Check,
+        _ => action.clone(),
+    }
+}
+
+/// Sample an abstract action from a blueprint policy and resolve it
+/// to a concrete legal action. Abstract actions with no legal
+/// counterpart are dropped before sampling
+pub fn sample_policy(policy: &[(ActionIndex, f32)], legal: &[AuctionPokerAction]) -> Option<AuctionPokerAction> {
+    let (indices, probabilities): (Vec<ActionIndex>, Vec<f32>) = policy
+        .iter()
+        .filter(|(index, _)| resolve_index(*index, legal).is_some())
+        .cloned()
+        .unzip();
+    if indices.is_empty() {
+        return None;
+    }
+    let index = Categorical::new_normalized(probabilities, indices).sample();
+    resolve_index(index, legal)
+}
+
+/// The median legal action belonging to the abstract action `index`
+pub fn resolve_index(index: ActionIndex, legal: &[AuctionPokerAction]) -> Option<AuctionPokerAction> {
+    let group: Vec<&AuctionPokerAction> = legal.iter().filter(|action| action.index() == index).collect();
+    group.get(group.len() / 2).map(|action| (*action).clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_logic::state::State;
+
+    #[test]
+    fn test_empty_blueprint_falls_back_to_heuristics() {
+        let mut game = AuctionGame::new();
+        game.play(&AuctionPokerAction::DealHole(0, 0));
+        game.play(&AuctionPokerAction::DealHole(2, 0));
+        game.play(&AuctionPokerAction::DealHole(3, 1));
+        game.play(&AuctionPokerAction::DealHole(4, 1));
+        game.play(&AuctionPokerAction::BettingRoundStart);
+        let engine = DecisionEngine::new(BlueprintStrategy::empty());
+        let (action, source) = engine.decide(&game, 0);
+        assert_eq!(source, DecisionSource::Heuristic);
+        assert_eq!(action, AuctionPokerAction::Fold);
+    }
+
+    #[test]
+    fn test_decide_within_budget() {
+        let mut game = AuctionGame::new();
+        game.play(&AuctionPokerAction::DealHole(0, 0));
+        game.play(&AuctionPokerAction::DealHole(2, 0));
+        game.play(&AuctionPokerAction::DealHole(3, 1));
+        game.play(&AuctionPokerAction::DealHole(4, 1));
+        game.play(&AuctionPokerAction::BettingRoundStart);
+        let engine = DecisionEngine::new(BlueprintStrategy::empty());
+        let (action, source) = engine.decide_within(&game, 0, Duration::from_secs(10));
+        assert_eq!(source, DecisionSource::Heuristic);
+        assert_eq!(action, AuctionPokerAction::Fold);
+
+        // No time at all, fall back on the safe action
+        let (action, _) = engine.decide_within(&game, 0, Duration::ZERO);
+        assert_eq!(action, AuctionPokerAction::Fold);
+    }
+
+    #[test]
+    fn test_resolve_index_picks_legal_member() {
+        let legal = vec![
+            AuctionPokerAction::Call,
+            AuctionPokerAction::Raise(DeciPercent(100)),
+            AuctionPokerAction::Raise(DeciPercent(200)),
+            AuctionPokerAction::Raise(DeciPercent(250)),
+        ];
+        let index = AuctionPokerAction::Raise(DeciPercent(200)).index();
+        assert_eq!(resolve_index(index, &legal), Some(AuctionPokerAction::Raise(DeciPercent(200))));
+        assert_eq!(resolve_index(AuctionPokerAction::Check.index(), &legal), None);
+        let policy = vec![(AuctionPokerAction::Check.index(), 0.9), (AuctionPokerAction::Call.index(), 0.1)];
+        assert_eq!(sample_policy(&policy, &legal), Some(AuctionPokerAction::Call));
+    }
+
+    #[test]
+    fn test_low_variance_never_raises() {
+        let raise = AuctionPokerAction::Raise(DeciPercent(200));
+        let facing_bet = vec![AuctionPokerAction::Fold, AuctionPokerAction::Call, raise.clone()];
+        let unopened = vec![AuctionPokerAction::Check, raise.clone()];
+        assert_eq!(low_variance(&raise, &facing_bet), AuctionPokerAction::Call);
+        assert_eq!(low_variance(&raise, &unopened), AuctionPokerAction::Check);
+        assert_eq!(low_variance(&AuctionPokerAction::Fold, &facing_bet), AuctionPokerAction::Fold);
+    }
+
+    /// Plays `actions` on a bare state (no features), dealing `cards` in
+    /// order at chance nodes and playing markers as they come
+    fn play_state(state: &mut AuctionPokerState, cards: &mut Vec<&str>, actions: &[AuctionPokerAction]) {
+        let mut actions = actions.iter();
+        loop {
+            match state.active_player() {
+                ActivePlayer::Marker(marker) => state.update(marker),
+                ActivePlayer::Chance(distribution) => {
+                    let card = Card::new(cards.remove(0)).as_u8() as CardIndex;
+                    let deal = distribution
+                        .items()
+                        .iter()
+                        .find(|deal| matches!(deal, AuctionPokerAction::DealHole(c, _) | AuctionPokerAction::DealCommunity(c) if *c == card))
+                        .unwrap()
+                        .clone();
+                    state.update(deal);
+                }
+                ActivePlayer::Player(..) => match actions.next() {
+                    Some(action) => state.update(action.clone()),
+                    None => return,
+                },
+                ActivePlayer::Terminal(_) => return,
+            }
+        }
+    }
+
+    /// We check it down with `hand` and the opponent shoves the river
+    fn river_all_in(hand: [&str; 2]) -> AuctionPokerState {
+        let mut state = AuctionPokerState::new();
+        let mut cards = vec![hand[0], hand[1], "4h", "5h", "Qs", "Js", "Ts", "6c", "7c", "2c", "3d"];
+        let check = AuctionPokerAction::Check;
+        let bid = AuctionPokerAction::Bid(Amount(0));
+        let preflop = [AuctionPokerAction::Call];
+        play_state(&mut state, &mut cards, &preflop);
+        play_state(&mut state, &mut cards, &[bid.clone(), bid, check.clone(), check.clone(), check.clone(), check]);
+        let shove = state.active_player().actions().iter().max_by_key(|action| match action {
+            AuctionPokerAction::Raise(size) => size.to_amount(state.pot()),
+            _ => 0,
+        }).unwrap().clone();
+        play_state(&mut state, &mut cards, &[shove]);
+        state
+    }
+
+    #[test]
+    fn test_all_in_is_called_by_exact_equity() {
+        let state = river_all_in(["As", "Ks"]);
+        assert_eq!(state.stacks()[1], 0);
+        let actions = state.active_player().actions().to_vec();
+        assert_eq!(all_in_decision(&state, 0, &actions, &OpponentModel::default()), Some(AuctionPokerAction::Call));
+
+        let state = river_all_in(["8d", "2s"]);
+        let actions = state.active_player().actions().to_vec();
+        assert_eq!(all_in_decision(&state, 0, &actions, &OpponentModel::default()), Some(AuctionPokerAction::Fold));
+
+        // Not an all in yet, and a preflop all in is too big to enumerate
+        let mut state = AuctionPokerState::new();
+        play_state(&mut state, &mut vec!["As", "Ks", "4h", "5h"], &[]);
+        let actions = state.active_player().actions().to_vec();
+        assert_eq!(all_in_decision(&state, 0, &actions, &OpponentModel::default()), None);
+    }
+
+    #[test]
+    fn test_all_in_range_comes_from_the_opponent_model() {
+        let state = river_all_in(["Qh", "Qd"]);
+        let actions = state.active_player().actions().to_vec();
+        let uniform = OpponentModel::default();
+        assert_eq!(all_in_decision(&state, 0, &actions, &uniform), Some(AuctionPokerAction::Call));
+
+        // Against someone who only ever shoves the nuts, a set is a fold
+        let mut nutted = OpponentModel::default();
+        for _ in 0..nutted.min_samples {
+            nutted.observe_all_in(0.98);
+        }
+        assert_eq!(all_in_decision(&state, 0, &actions, &nutted), Some(AuctionPokerAction::Fold));
+    }
+}
@@ -0,0 +1,158 @@
+/// The last check on every action before it goes to the engine.
+///
+/// Whatever decided the action (blueprint, resolver, heuristics, sizing),
+/// a bug there mustn't reach the table: an illegal action is played by the
+/// engine as a check or a fold, and some legal actions are never right. So
+/// the action is checked against the legal set and a few rules that always
+/// hold, and replaced by the closest action that passes:
+///
+///   folding when checking is free becomes a check
+///   a raise or bid of more than we have goes all in instead
+///   a raise outside of the legal interval (see auction::raise_interval)
+///   goes to its nearest end
+///   any other illegal action becomes the nearest legal one
+use crate::implementations::auction::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Correction {
+    /// Folded when checking was free
+    FreeFold,
+    /// Raised or bid more chips than we have
+    OverStack,
+    /// Not in the legal set
+    Illegal,
+}
+
+/// `action` if it is legal and sane for `seat` in `state`, otherwise its
+/// replacement and why it was replaced
+pub fn guard(
+    action: &AuctionPokerAction,
+    legal: &[AuctionPokerAction],
+    state: &AuctionPokerState,
+    seat: usize,
+) -> (AuctionPokerAction, Option<Correction>) {
+    let pot = state.pot();
+    let behind = state.stacks()[seat] + state.pips()[seat];
+    let correction = match action {
+        AuctionPokerAction::Fold if legal.contains(&AuctionPokerAction::Check) => Correction::FreeFold,
+        AuctionPokerAction::Raise(size) if size.to_amount(pot) > behind => Correction::OverStack,
+        AuctionPokerAction::Raise(size) if !is_legal_raise(state, seat, size.to_amount(pot)) => Correction::Illegal,
+        AuctionPokerAction::Bid(size) if size.to_amount(pot) > state.stacks()[seat] => Correction::OverStack,
+        _ if legal.contains(action) => return (action.clone(), None),
+        _ => Correction::Illegal,
+    };
+    (nearest(action, legal, pot), Some(correction))
+}
+
+fn is_legal_raise(state: &AuctionPokerState, seat: usize, to: u32) -> bool {
+    state.raise_interval(seat).is_some_and(|interval| interval.contains(to))
+}
+
+/// The legal action closest to `action`: raises and bids of the nearest
+/// amount, otherwise the cheapest way to go on
+fn nearest(action: &AuctionPokerAction, legal: &[AuctionPokerAction], pot: u32) -> AuctionPokerAction {
+    let amount = |action: &AuctionPokerAction| match action {
+        AuctionPokerAction::Raise(size) | AuctionPokerAction::Bid(size) => Some(size.to_amount(pot)),
+        _ => None,
+    };
+    let same_kind = |other: &&AuctionPokerAction| std::mem::discriminant(*other) == std::mem::discriminant(action);
+    if let Some(wanted) = amount(action) {
+        let closest = legal
+            .iter()
+            .filter(same_kind)
+            .min_by_key(|other| amount(other).unwrap().abs_diff(wanted));
+        if let Some(closest) = closest {
+            return closest.clone();
+        }
+    }
+    let fallbacks = match action {
+        // A raise we can't make is still a wish to stay in the hand
+        AuctionPokerAction::Raise(_) | AuctionPokerAction::Call => [AuctionPokerAction::Call, AuctionPokerAction::Check],
+        _ => [AuctionPokerAction::Check, AuctionPokerAction::Fold],
+    };
+    fallbacks
+        .into_iter()
+        .chain([AuctionPokerAction::Check, AuctionPokerAction::Fold])
+        .find(|fallback| legal.contains(fallback))
+        .unwrap_or_else(|| legal[0].clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_logic::state::{ActivePlayer, State};
+    use crate::implementations::auction::RelativeSize::*;
+
+    fn facing_raise() -> AuctionPokerState {
+        let mut state = AuctionPokerState::new();
+        advance(&mut state);
+        state.update(AuctionPokerAction::Raise(Amount(10)));
+        advance(&mut state);
+        state
+    }
+
+    fn legal(state: &AuctionPokerState) -> Vec<AuctionPokerAction> {
+        match state.active_player() {
+            ActivePlayer::Player(_, actions) => actions,
+            x => panic!("Expected a decision, got {:?}", x),
+        }
+    }
+
+    /// Plays markers and deals until someone has to decide
+    fn advance(state: &mut AuctionPokerState) {
+        loop {
+            match state.active_player() {
+                ActivePlayer::Marker(marker) => state.update(marker),
+                ActivePlayer::Chance(distribution) => state.update(distribution.items()[0].clone()),
+                _ => return,
+            }
+        }
+    }
+
+    #[test]
+    fn test_guard_keeps_sane_actions_and_fixes_the_rest() {
+        let state = facing_raise();
+        let legal = legal(&state);
+        let pot = state.pot();
+        let call = AuctionPokerAction::Call;
+        assert_eq!(guard(&call, &legal, &state, 1), (call.clone(), None));
+        let fold = AuctionPokerAction::Fold;
+        assert_eq!(guard(&fold, &legal, &state, 1), (fold, None));
+
+        // Checking isn't possible facing a raise
+        let (action, correction) = guard(&AuctionPokerAction::Check, &legal, &state, 1);
+        assert_eq!((action, correction), (AuctionPokerAction::Fold, Some(Correction::Illegal)));
+
+        // More than our stack goes all in
+        let (action, correction) = guard(&AuctionPokerAction::Raise(Amount(10_000)), &legal, &state, 1);
+        assert_eq!(correction, Some(Correction::OverStack));
+        match action {
+            AuctionPokerAction::Raise(size) => assert_eq!(size.to_amount(pot), state.stacks()[1] + state.pips()[1]),
+            x => panic!("Expected an all in, got {:?}", x),
+        }
+
+        // Under the min raise goes to the min raise
+        let (action, correction) = guard(&AuctionPokerAction::Raise(Amount(11)), &legal, &state, 1);
+        assert_eq!(correction, Some(Correction::Illegal));
+        let smallest = legal.iter().find(|action| matches!(action, AuctionPokerAction::Raise(_)));
+        assert_eq!(Some(&action), smallest);
+
+        // A bid of more than we have is all in
+        let mut state = state;
+        state.update(AuctionPokerAction::Call);
+        advance(&mut state);
+        let legal = self::legal(&state);
+        let (action, correction) = guard(&AuctionPokerAction::Bid(Amount(10_000)), &legal, &state, 1);
+        assert_eq!(correction, Some(Correction::OverStack));
+        assert_eq!(action, AuctionPokerAction::Bid(Amount(state.stacks()[1])));
+
+        // Nobody bet on the flop, so we check rather than fold
+        state.update(AuctionPokerAction::Bid(Amount(0)));
+        advance(&mut state);
+        state.update(AuctionPokerAction::Bid(Amount(0)));
+        advance(&mut state);
+        let legal = self::legal(&state);
+        let (action, correction) = guard(&AuctionPokerAction::Fold, &legal, &state, 1);
+        assert_eq!((action, correction), (AuctionPokerAction::Check, Some(Correction::FreeFold)));
+    }
+}
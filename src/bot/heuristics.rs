@@ -0,0 +1,77 @@
+/// Rule-based decisions used when the blueprint has nothing to say
+/// (or as a simple baseline agent)
+use crate::eval::bid::BidValue;
+use crate::eval::rank::HandRanker;
+use crate::implementations::auction::*;
+use std::sync::Arc;
+
+#[derive(Clone, Debug)]
+pub struct BidSizer {
+    /// How much we expect the pot to grow between the auction and showdown
+    pub pot_growth: f64,
+    /// Fraction of the full value of winning that we are willing to bid.
+    /// The winner pays the loser's bid, so bidding close to true value is sound
+    pub shade: f64,
+    pub iterations: u32,
+    /// Loaded once, bids are sized on every auction
+    ranker: Arc<HandRanker>,
+}
+
+impl Default for BidSizer {
+    fn default() -> Self {
+        BidSizer {
+            pot_growth: 2.0,
+            shade: 0.9,
+            iterations: 2_000,
+            ranker: Arc::new(HandRanker::new()),
+        }
+    }
+}
+
+impl BidSizer {
+    /// Bid as a function of the value of winning, the pot and the effective stack
+    pub fn bid(&self, value: &BidValue, pot: u32, effective_stack: u32) -> u32 {
+        let expected_pot = pot as f64 * self.pot_growth;
+        let bid = value.chips(expected_pot.round() as u32) * self.shade;
+        let bid = bid.max(0.0).round() as u32;
+        bid.min(effective_stack)
+    }
+
+    /// Bid for `player_num` in the current (pre-auction) state
+    pub fn bid_for(&self, state: &AuctionPokerState, player_num: usize) -> u32 {
+        let hand = state.hand(player_num).as_u8();
+        let board = state.community_cards().as_u8();
+        let value = BidValue::rollout(&self.ranker, &hand, &board, self.iterations);
+        // Bids are capped by our own stack, not the opponent's
+        let stack = state.stacks()[player_num];
+        self.bid(&value, state.pot(), stack)
+    }
+}
+
+/// The cheapest way to continue: check if possible, otherwise fold
+pub fn check_fold(actions: &[AuctionPokerAction]) -> AuctionPokerAction {
+    if actions.contains(&AuctionPokerAction::Check) {
+        AuctionPokerAction::Check
+    } else if actions.contains(&AuctionPokerAction::Fold) {
+        AuctionPokerAction::Fold
+    } else {
+        actions[0].clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bid_grows_with_value_and_respects_stack() {
+        let sizer = BidSizer::default();
+        let weak = BidValue { win: 0.50, loss: 0.45 };
+        let strong = BidValue { win: 0.70, loss: 0.40 };
+        assert!(sizer.bid(&weak, 100, 400) < sizer.bid(&strong, 100, 400));
+        assert_eq!(sizer.bid(&strong, 1000, 25), 25);
+        // Rollout noise can make the marginal value negative
+        let noisy = BidValue { win: 0.40, loss: 0.41 };
+        assert_eq!(sizer.bid(&noisy, 100, 400), 0);
+    }
+}
@@ -0,0 +1,488 @@
+/// Keeps an AuctionPokerState (well, a whole Game so we have our infoset)
+/// up to date from the engine's per-packet deltas, instead of rebuilding it
+/// from the full action history every time we need to act.
+///
+/// The engine never tells us the opponent's hole cards, but the state has
+/// to deal them before it can continue. So the opponent gets placeholder
+/// cards that nobody has seen, and if a placeholder later shows up for real
+/// (on the board or in our hand) it is swapped for another unseen card.
+/// The opponent's features are nonsense as a result, but we only ever read
+/// our own infoset.
+///
+/// Every delta is checked against the state: it must be the right player's
+/// turn, the action must be legal and the cards must agree with what we've
+/// already seen. Anything else is reported as an error rather than applied
+///
+/// The engine doesn't always tell us the opponent's bid. When the auction
+/// is over without it, infer_auction works out the winner from what we do
+/// know: a third hole card means we won (or tied), none means we lost.
+/// Losing costs us nothing and the opponent our bid, so any bid above ours
+/// gets the chips right. Winning costs us their bid, which could be
+/// anything up to ours, so we assume a tie: it is the most the auction can
+/// have cost us and keeps us from raising chips we might not have
+use crate::bot::decision::AuctionGame;
+use crate::bot::runner::{Clause, Reply};
+use crate::game_logic::state::ActivePlayer;
+use crate::implementations::auction::RelativeSize::*;
+use crate::implementations::auction::*;
+
+#[derive(Clone, Debug)]
+pub struct IncrementalState {
+    game: Option<AuctionGame>,
+    seat: Option<usize>,
+    /// Cards the engine has shown us that the state hasn't dealt yet
+    pending_hole: Vec<Card>,
+    pending_board: Vec<Card>,
+    /// Cards that are really where the state says they are
+    seen: Deck,
+    /// Our bid, when the state wants the opponent's bid first
+    pending_bid: Option<u32>,
+    /// Every action played on the game, markers and the opponent's
+    /// placeholder cards included
+    history: Vec<AuctionPokerAction>,
+}
+
+impl IncrementalState {
+    pub fn new() -> Self {
+        IncrementalState {
+            game: None,
+            seat: None,
+            pending_hole: vec![],
+            pending_board: vec![],
+            seen: Deck::empty(),
+            pending_bid: None,
+            history: vec![],
+        }
+    }
+
+    pub fn game(&self) -> Option<&AuctionGame> {
+        self.game.as_ref()
+    }
+
+    pub fn seat(&self) -> Option<usize> {
+        self.seat
+    }
+
+    pub fn history(&self) -> &[AuctionPokerAction] {
+        &self.history
+    }
+
+    /// Whether the engine is waiting on a decision from us, bidding before
+    /// the state has the opponent's bid included
+    pub fn needs_decision(&self) -> bool {
+        match (self.decision_game(), self.seat) {
+            (Some(game), Some(seat)) => match game.active_player() {
+                ActivePlayer::Player(player_num, _) => {
+                    player_num as usize == seat && self.pending_bid.is_none()
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// The game to decide from. In the auction the state takes player 1's
+    /// bid first, so if we are player 0 the opponent's bid is filled in
+    /// with a placeholder (bids are never part of an infoset)
+    pub fn decision_game(&self) -> Option<AuctionGame> {
+        let mut game = self.game.clone()?;
+        let seat = self.seat?;
+        if let ActivePlayer::Player(player_num, actions) = game.active_player() {
+            let is_bidding = matches!(actions.first(), Some(AuctionPokerAction::Bid(_)));
+            if is_bidding && player_num as usize != seat {
+                game.play(&AuctionPokerAction::Bid(Amount(0)));
+            }
+        }
+        Some(game)
+    }
+
+    /// Applies a single clause from the engine, which are always about the opponent
+    pub fn apply_clause(&mut self, clause: &Clause) -> Result<(), String> {
+        match clause {
+            Clause::Seat(seat) => {
+                self.new_hand(*seat);
+                Ok(())
+            }
+            Clause::Hand(cards) => self.hole_cards(cards),
+            Clause::Board(cards) => self.board(cards),
+            Clause::Fold => self.infer_auction().and_then(|_| self.apply_opponent(&Reply::Fold)),
+            Clause::Call => self.infer_auction().and_then(|_| self.apply_opponent(&Reply::Call)),
+            Clause::Check => self.infer_auction().and_then(|_| self.apply_opponent(&Reply::Check)),
+            Clause::Raise(amount) => self.infer_auction().and_then(|_| self.apply_opponent(&Reply::Raise(*amount))),
+            Clause::Bid(amount) => self.apply_opponent(&Reply::Bid(*amount)),
+            Clause::Delta(_) | Clause::Quit => {
+                self.game = None;
+                Ok(())
+            }
+            Clause::Time(_) | Clause::Opponent(_) => Ok(()),
+        }
+    }
+
+    /// Our bid, if it is in and the state is still waiting on the opponent's
+    fn awaiting_opponent_bid(&self) -> Option<u32> {
+        if self.pending_bid.is_some() {
+            return self.pending_bid;
+        }
+        let (game, seat) = (self.game.as_ref()?, self.seat?);
+        match game.active_player() {
+            ActivePlayer::Player(player_num, actions)
+                if player_num as usize != seat && matches!(actions.first(), Some(AuctionPokerAction::Bid(_))) =>
+            {
+                match self.history.iter().rev().find(|action| matches!(action, AuctionPokerAction::Bid(_))) {
+                    Some(AuctionPokerAction::Bid(Amount(bid))) => Some(*bid),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Settles an auction the engine has moved on from without telling us
+    /// the opponent's bid, see the module docs. Call it once a packet is
+    /// in, the bid may come after the cards it decided. Does nothing if
+    /// we haven't bid or the auction is already settled
+    pub fn infer_auction(&mut self) -> Result<(), String> {
+        let ours = match self.awaiting_opponent_bid() {
+            Some(ours) => ours,
+            None => return Ok(()),
+        };
+        let theirs = match self.pending_hole.is_empty() {
+            true => ours + 1,
+            false => ours,
+        };
+        self.apply_opponent(&Reply::Bid(theirs)).map_err(|error| {
+            format!("The auction is over but no bid of the opponent explains it (ours was {}): {}", ours, error)
+        })
+    }
+
+    pub fn new_hand(&mut self, seat: usize) {
+        *self = IncrementalState::new();
+        self.seat = Some(seat);
+    }
+
+    /// Our hole cards so far (all of them, not just the new ones)
+    pub fn hole_cards(&mut self, cards: &[Card]) -> Result<(), String> {
+        let seat = self.seat.ok_or("Hole cards before we know our seat")?;
+        let game = self.game.get_or_insert_with(AuctionGame::new);
+        let hand = game.state().hand(seat).cards;
+        for card in hand.iter() {
+            if !cards.contains(&card) {
+                return Err(format!("{:?} has disappeared from our hand {:?}", card, cards));
+            }
+        }
+        for card in cards {
+            if !hand.contains(*card) && !self.pending_hole.contains(card) {
+                self.pending_hole.push(*card);
+            }
+        }
+        self.advance()
+    }
+
+    /// The board so far (all of it, not just the new cards)
+    pub fn board(&mut self, cards: &[Card]) -> Result<(), String> {
+        let game = self.game.as_ref().ok_or("Board before the hand started")?;
+        let board = game.state().community_cards();
+        for card in board.iter() {
+            if !cards.contains(&card) {
+                return Err(format!("{:?} has disappeared from the board {:?}", card, cards));
+            }
+        }
+        for card in cards {
+            if !board.contains(*card) && !self.pending_board.contains(card) {
+                self.pending_board.push(*card);
+            }
+        }
+        self.advance()
+    }
+
+    pub fn apply_opponent(&mut self, reply: &Reply) -> Result<(), String> {
+        let seat = self.seat.ok_or("Opponent acted before we know our seat")?;
+        self.apply(seat ^ 1, reply)?;
+        if let Some(bid) = self.pending_bid.take() {
+            self.apply(seat, &Reply::Bid(bid))?;
+        }
+        Ok(())
+    }
+
+    pub fn apply_own(&mut self, reply: &Reply) -> Result<(), String> {
+        let seat = self.seat.ok_or("Acting before we know our seat")?;
+        let game = self.game.as_ref().ok_or("Acting before the hand started")?;
+        // The state wants the opponent's bid first, hold on to ours
+        if let (Reply::Bid(bid), ActivePlayer::Player(player_num, _)) = (reply, game.active_player()) {
+            if player_num as usize != seat {
+                self.pending_bid = Some(*bid);
+                return Ok(());
+            }
+        }
+        self.apply(seat, reply)
+    }
+
+    fn apply(&mut self, player: usize, reply: &Reply) -> Result<(), String> {
+        let game = self.game.as_mut().ok_or("Action before the hand started")?;
+        let actions = match game.active_player() {
+            ActivePlayer::Player(player_num, actions) if player_num as usize == player => actions,
+            active_player => {
+                return Err(format!(
+                    "Player {} played {:?} but the state is waiting on {:?}",
+                    player, reply, active_player
+                ))
+            }
+        };
+        let action = match reply {
+            Reply::Fold => AuctionPokerAction::Fold,
+            Reply::Call => AuctionPokerAction::Call,
+            Reply::Check => AuctionPokerAction::Check,
+            Reply::Raise(amount) => {
+                let percent = Amount(*amount).to_percent(game.state().pot());
+                AuctionPokerAction::Raise(DeciPercent(percent))
+            }
+            Reply::Bid(amount) => AuctionPokerAction::Bid(Amount(*amount)),
+        };
+        if !actions.contains(&action) {
+            return Err(format!("{:?} is not legal for player {}", reply, player));
+        }
+        game.play(&action);
+        self.history.push(action);
+        self.advance()
+    }
+
+    /// Plays markers and deals any cards we know (or can make up)
+    /// until the state needs something from the engine
+    fn advance(&mut self) -> Result<(), String> {
+        let seat = self.seat.ok_or("No seat")?;
+        loop {
+            let game = self.game.as_mut().ok_or("No hand in progress")?;
+            let deal = match game.active_player() {
+                ActivePlayer::Marker(marker) => {
+                    game.play(&marker);
+                    self.history.push(marker);
+                    continue;
+                }
+                ActivePlayer::Chance(distribution) => distribution.items()[0].clone(),
+                _ => return Ok(()),
+            };
+            match deal {
+                AuctionPokerAction::DealHole(_, player_num) if player_num == seat => {
+                    if self.pending_hole.is_empty() {
+                        return Ok(());
+                    }
+                    let card = self.pending_hole.remove(0);
+                    self.deal(card, AuctionPokerAction::DealHole(card.as_u8() as CardIndex, seat))?;
+                }
+                AuctionPokerAction::DealHole(_, player_num) => {
+                    let card = self.unseen_card()?;
+                    let action = AuctionPokerAction::DealHole(card.as_u8() as CardIndex, player_num);
+                    self.game.as_mut().unwrap().play(&action);
+                    self.history.push(action);
+                }
+                AuctionPokerAction::DealCommunity(_) => {
+                    if self.pending_board.is_empty() {
+                        return Ok(());
+                    }
+                    let card = self.pending_board.remove(0);
+                    self.deal(card, AuctionPokerAction::DealCommunity(card.as_u8() as CardIndex))?;
+                }
+                x => return Err(format!("Unexpected chance action {:?}", x)),
+            }
+        }
+    }
+
+    /// Deals a card the engine showed us, moving a placeholder out of the way if needed
+    fn deal(&mut self, card: Card, action: AuctionPokerAction) -> Result<(), String> {
+        if self.seen.contains(card) {
+            return Err(format!("{:?} was dealt twice", card));
+        }
+        let seat = self.seat.ok_or("No seat")?;
+        let replacement = self.unseen_card_except(card)?;
+        let game = self.game.as_mut().unwrap();
+        if game.state().dealt().contains(card) {
+            // Must be one of the opponent's placeholders
+            game.state_mut().swap_hole_card(seat ^ 1, card, replacement);
+            let placeholder = AuctionPokerAction::DealHole(card.as_u8() as CardIndex, seat ^ 1);
+            for dealt in self.history.iter_mut().filter(|dealt| **dealt == placeholder) {
+                *dealt = AuctionPokerAction::DealHole(replacement.as_u8() as CardIndex, seat ^ 1);
+            }
+        }
+        game.play(&action);
+        self.history.push(action);
+        self.seen.insert(card);
+        Ok(())
+    }
+
+    fn unseen_card(&self) -> Result<Card, String> {
+        let game = self.game.as_ref().ok_or("No hand in progress")?;
+        let taken = game.state().dealt().union(self.seen);
+        let pending: Deck = self.pending_hole.iter().chain(self.pending_board.iter()).cloned().collect();
+        // Take from the end of the deck (the deuces) first
+        taken
+            .union(pending)
+            .complement()
+            .iter()
+            .last()
+            .ok_or_else(|| "Ran out of cards".to_string())
+    }
+
+    fn unseen_card_except(&self, card: Card) -> Result<Card, String> {
+        let mut state = self.clone();
+        state.pending_hole.push(card);
+        state.unseen_card()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::public_state::PublicState;
+    use crate::bot::runner::parse_packet;
+
+    fn apply_packet(state: &mut IncrementalState, packet: &str) {
+        for clause in parse_packet(packet) {
+            state.apply_clause(&clause).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_follows_a_hand_from_deltas() {
+        let mut state = IncrementalState::new();
+        // We are the big blind
+        apply_packet(&mut state, "T30 P1 HAs,Ad");
+        assert!(!state.needs_decision());
+        // A call from the small blind closes the preflop
+        state.apply_clause(&Clause::Call).unwrap();
+        assert!(!state.needs_decision());
+
+        // The flop includes cards the opponent placeholders were using
+        apply_packet(&mut state, "B2s,2c,Kh");
+        let game = state.game().unwrap();
+        assert_eq!(game.state().community_cards().len(), 3);
+        assert_eq!(game.state().hand(0).cards.len(), 2);
+        assert!(!game.state().hand(0).cards.contains(Card::new("2s")));
+        assert!(state.needs_decision());
+
+        // We bid first (player 1 bids first), the opponent outbids us
+        state.apply_own(&Reply::Bid(10)).unwrap();
+        apply_packet(&mut state, "A20");
+        assert!(state.needs_decision());
+        let game = state.game().unwrap();
+        assert_eq!(game.state().winner(), Some(Winner::Player(0)));
+        assert_eq!(game.state().hand(0).cards.len(), 3);
+
+        // Raising to an amount the state doesn't allow is an error
+        assert!(state.apply_own(&Reply::Raise(1)).is_err());
+        state.apply_own(&Reply::Check).unwrap();
+        assert!(state.apply_clause(&Clause::Raise(30)).is_ok());
+        assert!(state.needs_decision());
+
+        // The history replays to the same public state
+        let public = PublicState::from_history(state.history()).unwrap();
+        let game = state.game().unwrap();
+        assert_eq!(public.pot, game.state().pot());
+        assert_eq!(public.board, game.state().community_cards());
+        assert_eq!(public.to_act, Some(1));
+    }
+
+    #[test]
+    fn test_player_zero_bid_waits_for_opponent() {
+        let mut state = IncrementalState::new();
+        apply_packet(&mut state, "P0 HKs,Kd");
+        state.apply_own(&Reply::Call).unwrap();
+        apply_packet(&mut state, "B3c,4c,5c");
+        // The state takes player 1's bid first
+        assert_eq!(state.game().unwrap().active_player().player_num(), 1);
+        let game = state.decision_game().unwrap();
+        assert_eq!(game.active_player().player_num(), 0);
+        assert!(state.needs_decision());
+
+        state.apply_own(&Reply::Bid(50)).unwrap();
+        assert!(!state.needs_decision());
+        apply_packet(&mut state, "A5 HKs,Kd,Qh");
+        let game = state.game().unwrap();
+        assert_eq!(game.state().winner(), Some(Winner::Player(0)));
+        assert!(game.state().hand(0).cards.contains(Card::new("Qh")));
+    }
+
+    /// Plays a hand from packets written the way the engine sends them,
+    /// `>` lines being our replies, settling the auction after every packet
+    /// the way AuctionBot does
+    fn replay_packets(packets: &str) -> IncrementalState {
+        let mut state = IncrementalState::new();
+        for line in packets.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            match line.strip_prefix('>') {
+                Some(reply) => {
+                    let reply = match Clause::parse(reply.trim()) {
+                        Clause::Call => Reply::Call,
+                        Clause::Check => Reply::Check,
+                        Clause::Bid(amount) => Reply::Bid(amount),
+                        clause => panic!("Not one of our replies: {:?}", clause),
+                    };
+                    state.apply_own(&reply).unwrap();
+                }
+                None => {
+                    apply_packet(&mut state, line);
+                    state.infer_auction().unwrap();
+                }
+            }
+        }
+        state
+    }
+
+    #[test]
+    fn test_auction_is_inferred_when_the_bid_is_missing() {
+        let stacks = |state: &IncrementalState| state.game().unwrap().state().stacks();
+        let winner = |state: &IncrementalState| state.game().unwrap().state().winner();
+        // Player 1's bid comes first
+        let bids = |state: &IncrementalState| -> Vec<u32> {
+            state
+                .history()
+                .iter()
+                .filter_map(|action| match action {
+                    AuctionPokerAction::Bid(Amount(bid)) => Some(*bid),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        // The bid is there, nothing to infer
+        let state = replay_packets("T30 P1 HAs,Ad\n C B2s,2c,Kh\n > A10\n T29 A5 HAs,Ad,Qh");
+        assert_eq!(bids(&state), [10, 5]);
+        assert_eq!(winner(&state), Some(Winner::Player(1)));
+        assert_eq!(stacks(&state), [398, 393]);
+
+        // No third card, we lost and the opponent paid our bid
+        let state = replay_packets("T30 P1 HAs,Ad\n C B2s,2c,Kh\n > A10\n T29");
+        assert_eq!(bids(&state), [10, 11]);
+        assert_eq!(winner(&state), Some(Winner::Player(0)));
+        assert_eq!(stacks(&state), [388, 398]);
+        assert!(state.needs_decision());
+
+        // A third card, we won or tied and paid at most our bid
+        let state = replay_packets("T30 P1 HAs,Ad\n C B2s,2c,Kh\n > A10\n T29 HAs,Ad,Qh");
+        assert_eq!(bids(&state), [10, 10]);
+        assert_eq!(winner(&state), Some(Winner::Tie));
+        assert_eq!(stacks(&state), [388, 388]);
+        assert_eq!(state.game().unwrap().state().hand(1).cards.len(), 3);
+
+        // As the small blind our bid waits on theirs, the opponent checking
+        // after the auction settles it
+        let state = replay_packets("T30 P0 HKs,Kd\n > C\n T29 B3c,4c,5c\n > A50\n T28 K");
+        assert_eq!(bids(&state), [51, 50]);
+        assert_eq!(winner(&state), Some(Winner::Player(1)));
+        assert_eq!(stacks(&state), [398, 348]);
+        assert!(state.needs_decision());
+
+        // Nobody outbids a whole stack
+        let mut state = replay_packets("T30 P1 HAs,Ad\n C B2s,2c,Kh\n > A398");
+        apply_packet(&mut state, "T29");
+        assert!(state.infer_auction().is_err());
+    }
+
+    #[test]
+    fn test_inconsistent_board_is_an_error() {
+        let mut state = IncrementalState::new();
+        apply_packet(&mut state, "P1 HAs,Ad");
+        state.apply_clause(&Clause::Call).unwrap();
+        apply_packet(&mut state, "B2s,2c,Kh");
+        assert!(state.board(&[Card::new("2s"), Card::new("Kh"), Card::new("Qh")]).is_err());
+        assert!(state.apply_clause(&Clause::Check).is_err());
+    }
+}
@@ -0,0 +1,468 @@
+/// Local best response (LBR): a lower bound on how exploitable a blueprint
+/// is, for a game far too big for best_response.
+///
+/// The LBR player sits across from the blueprint and, at each of its betting
+/// decisions, only looks at the current street. Facing the blueprint's range,
+/// it assumes the hand is checked down once it stops betting. Calling
+/// wins its equity against the range. A raise picks up the pot whenever
+/// the blueprint folds to it, and otherwise its equity against the hands
+/// that continue. The LBR player plays whichever of fold, check or call and
+/// the raises in raise_sizes is worth the most. It bids as the blueprint
+/// would. It never sees the blueprint's cards, so what it wins on average
+/// is something a real opponent could win too, a lower bound on the
+/// blueprint's exploitability.
+///
+/// The blueprint's range is tracked over a sample of `particles` holdings
+/// it could have. Each holding is played along in a game of its own, so
+/// the blueprint's policy can be looked up holding it, and weighted by how
+/// likely the blueprint was to play what it did. A holding the board or
+/// our cards rule out is dropped. An auction card the blueprint wins is
+/// dealt at random in every game. Equities are exact from the flop on,
+/// every runout is enumerated, and sampled preflop.
+///
+/// LBR takes both seats in turn. At about a second a hand it is meant for
+/// running overnight:
+///
+///     gtcogs lbr auction_poker.bp 10000 32
+use crate::bot::decision::{resolve_index, AuctionGame};
+use crate::bot::public_state::{is_marker, Holding, Range};
+use crate::bot::streets::blueprint_policy;
+use crate::eval::equity::{choose, for_each_combination};
+use crate::eval::rank::HandRanker;
+use crate::game_logic::action::Action;
+use crate::game_logic::state::ActivePlayer;
+use crate::game_logic::strategy::blueprint::BlueprintStrategy;
+use crate::implementations::auction::RelativeSize::*;
+use crate::implementations::auction::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Runouts we enumerate for an equity, more are sampled (the flop has at
+/// most 990 left)
+const EXACT_RUNOUTS: u64 = 1000;
+
+#[derive(Debug, Clone)]
+pub struct LbrConfig {
+    pub hands: usize,
+    /// Holdings of the blueprint's range that are tracked
+    pub particles: usize,
+    /// Runouts sampled for an equity where there are too many to enumerate
+    pub samples: usize,
+    /// Raises the LBR player tries, DeciPercent of the pot. Going all in is
+    /// always tried as well
+    pub raise_sizes: Vec<u32>,
+    pub seed: u64,
+}
+
+impl Default for LbrConfig {
+    fn default() -> Self {
+        LbrConfig {
+            hands: 1000,
+            particles: 32,
+            samples: 100,
+            raise_sizes: vec![500, 1000],
+            seed: 0,
+        }
+    }
+}
+
+/// Chips won by the LBR player, from its own side
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LbrReport {
+    /// Hands played in each seat
+    pub hands: [usize; 2],
+    pub won: [f32; 2],
+    /// Sum of the squared winnings of every hand, for the standard error
+    pub squares: f32,
+}
+
+impl LbrReport {
+    pub fn total_hands(&self) -> usize {
+        self.hands[0] + self.hands[1]
+    }
+
+    /// What LBR wins a hand, the lower bound on exploitability
+    pub fn per_hand(&self) -> f32 {
+        (self.won[0] + self.won[1]) / self.total_hands().max(1) as f32
+    }
+
+    pub fn standard_error(&self) -> f32 {
+        let hands = self.total_hands() as f32;
+        if hands < 2.0 {
+            return 0.0;
+        }
+        let mean = self.per_hand();
+        let variance = (self.squares / hands - mean * mean).max(0.0) * hands / (hands - 1.0);
+        (variance / hands).sqrt()
+    }
+
+    fn add(&mut self, seat: usize, won: f32) {
+        self.hands[seat] += 1;
+        self.won[seat] += won;
+        self.squares += won * won;
+    }
+}
+
+impl fmt::Display for LbrReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let per_hand = |seat: usize| self.won[seat] / self.hands[seat].max(1) as f32;
+        write!(
+            f,
+            "{} hands, local best response wins {:+.2} ± {:.2} chips a hand ({:+.2} as player 0, {:+.2} as player 1), the blueprint is at least that exploitable",
+            self.total_hands(),
+            self.per_hand(),
+            self.standard_error(),
+            per_hand(0),
+            per_hand(1)
+        )
+    }
+}
+
+/// A holding the blueprint could have, played along in a game of its own
+#[derive(Debug, Clone)]
+struct Particle {
+    game: AuctionGame,
+    hole: (Card, Card),
+    weight: f32,
+}
+
+impl Particle {
+    /// Plays markers until the game needs a deal or a decision
+    fn advance(&mut self) {
+        while let ActivePlayer::Marker(marker) = self.game.active_player() {
+            self.game.play(&marker);
+        }
+    }
+
+    /// The blueprint's cards in this particle's game
+    fn cards(&self, opponent: usize) -> Deck {
+        self.game.state().hand(opponent).cards
+    }
+}
+
+struct LocalBestResponse<'a> {
+    blueprint: &'a BlueprintStrategy,
+    config: &'a LbrConfig,
+    ranker: HandRanker,
+    rng: StdRng,
+}
+
+impl<'a> LocalBestResponse<'a> {
+    /// Plays `action` of the real hand in a particle's game. Markers are
+    /// played as they come up, the blueprint's hole cards are the
+    /// particle's and its auction card is dealt at random. The particle
+    /// is dropped (weight 0) if the action isn't possible in its game
+    fn follow(&mut self, particle: &mut Particle, action: &AuctionPokerAction, opponent: usize) {
+        if is_marker(action) || particle.weight <= 0.0 {
+            return;
+        }
+        particle.advance();
+        let action = match (action, particle.game.active_player()) {
+            (AuctionPokerAction::DealHole(_, player_num), ActivePlayer::Chance(chance)) if *player_num == opponent => {
+                match particle.cards(opponent).len() {
+                    0 => AuctionPokerAction::DealHole(particle.hole.0.as_u8() as CardIndex, opponent),
+                    1 => AuctionPokerAction::DealHole(particle.hole.1.as_u8() as CardIndex, opponent),
+                    _ => chance.sample_rng(&mut self.rng),
+                }
+            }
+            (action, ActivePlayer::Chance(chance)) if chance.items().contains(action) => action.clone(),
+            (action, ActivePlayer::Player(_, legal)) if legal.contains(action) => action.clone(),
+            _ => {
+                particle.weight = 0.0;
+                return;
+            }
+        };
+        particle.game.play(&action);
+    }
+
+    /// Up to config.particles holdings the blueprint could have after
+    /// `line`, the hand so far, each played along it
+    fn particles(&mut self, line: &[AuctionPokerAction], lbr: usize) -> Vec<Particle> {
+        let opponent = lbr ^ 1;
+        let mut range = Range::uniform();
+        range.block(
+            line.iter()
+                .filter_map(|action| match action {
+                    AuctionPokerAction::DealHole(card, player_num) if *player_num == lbr => Some(Card::from_index(*card)),
+                    AuctionPokerAction::DealCommunity(card) => Some(Card::from_index(*card)),
+                    _ => None,
+                })
+                .collect(),
+        );
+        let mut holdings: Vec<Holding> = range.holdings().map(|(holding, _)| holding).collect();
+        let count = self.config.particles.min(holdings.len());
+        for i in 0..count {
+            let pick = i + self.rng.gen_range(0, (holdings.len() - i) as u32) as usize;
+            holdings.swap(i, pick);
+        }
+
+        let mut particles = vec![];
+        for holding in holdings.into_iter().take(count) {
+            let mut particle = Particle {
+                game: AuctionGame::new(),
+                hole: holding.cards(),
+                weight: 1.0,
+            };
+            for action in line {
+                self.follow(&mut particle, action, opponent);
+            }
+            if particle.weight > 0.0 {
+                particles.push(particle);
+            }
+        }
+        particles
+    }
+
+    /// Share of the pot `hand` takes against `opponent` on `board` once
+    /// it is dealt out, exact when the runouts can be enumerated
+    fn equity(&mut self, hand: Deck, board: Deck, opponent: Deck) -> f32 {
+        let live = hand.union(board).union(opponent).complement().as_u8();
+        let (hand, opponent) = (hand.as_u8(), opponent.as_u8());
+        let missing = 5 - board.len();
+        let share = |runout: &[u8], ranker: &HandRanker| {
+            let full_board: Vec<u8> = board.as_u8().into_iter().chain(runout.iter().cloned()).collect();
+            match ranker.compare_showdown(&hand, &opponent, &full_board) {
+                Ordering::Greater => 1.0,
+                Ordering::Equal => 0.5,
+                Ordering::Less => 0.0,
+            }
+        };
+        let (mut total, mut runouts) = (0.0, 0);
+        if choose(live.len(), missing) <= EXACT_RUNOUTS {
+            let ranker = &self.ranker;
+            for_each_combination(&live, missing, &mut |runout| {
+                total += share(runout, ranker);
+                runouts += 1;
+            });
+        } else {
+            let mut cards = live.clone();
+            for _ in 0..self.config.samples.max(1) {
+                for i in 0..missing {
+                    let pick = i + self.rng.gen_range(0, (cards.len() - i) as u32) as usize;
+                    cards.swap(i, pick);
+                }
+                total += share(&cards[..missing], &self.ranker);
+                runouts += 1;
+            }
+        }
+        total / runouts.max(1) as f32
+    }
+
+    /// The best response of the LBR player to the blueprint's range
+    /// `particles` at its decision in `game`, see the module docs
+    fn respond(
+        &mut self,
+        game: &AuctionGame,
+        lbr: usize,
+        legal: &[AuctionPokerAction],
+        particles: &[Particle],
+    ) -> AuctionPokerAction {
+        let opponent = lbr ^ 1;
+        let total: f32 = particles.iter().map(|particle| particle.weight).sum();
+        let is_bidding = legal.iter().all(|action| matches!(action, AuctionPokerAction::Bid(_)));
+        if is_bidding || total <= 0.0 {
+            let policy = blueprint_policy(self.blueprint, game, lbr, legal);
+            return self.sample(&policy);
+        }
+
+        let state = game.state();
+        let (hand, board) = (state.hand(lbr).cards, state.community_cards());
+        let equities: Vec<f32> = particles.iter().map(|particle| self.equity(hand, board, particle.cards(opponent))).collect();
+        let equity = particles.iter().zip(&equities).map(|(particle, equity)| particle.weight * equity).sum::<f32>() / total;
+
+        let pot = state.pot() as f32;
+        let to_call = state.pips()[opponent].saturating_sub(state.pips()[lbr]) as f32;
+        let mut best = match legal.contains(&AuctionPokerAction::Check) {
+            true => (AuctionPokerAction::Check, equity * pot),
+            false => (AuctionPokerAction::Fold, 0.0),
+        };
+        if legal.contains(&AuctionPokerAction::Call) {
+            let value = equity * (pot + to_call) - to_call;
+            if value > best.1 {
+                best = (AuctionPokerAction::Call, value);
+            }
+        }
+
+        for raise in self.raises(legal) {
+            let mut raised = game.clone();
+            raised.play(&raise);
+            let pips = raised.state().pips();
+            let cost = (pips[lbr] - state.pips()[lbr]) as f32;
+            let call = pips[lbr].saturating_sub(pips[opponent]) as f32;
+            let mut value = 0.0;
+            for (particle, equity) in particles.iter().zip(&equities) {
+                let folds = self.folds_to(particle, &raise, opponent);
+                value += particle.weight * (folds * pot + (1.0 - folds) * (equity * (pot + cost + call) - cost));
+            }
+            value /= total;
+            if value > best.1 {
+                best = (raise, value);
+            }
+        }
+        best.0
+    }
+
+    /// The legal raises of raise_sizes and the largest legal raise
+    fn raises(&self, legal: &[AuctionPokerAction]) -> Vec<AuctionPokerAction> {
+        let mut raises: Vec<AuctionPokerAction> = self
+            .config
+            .raise_sizes
+            .iter()
+            .filter_map(|size| resolve_index(AuctionPokerAction::Raise(DeciPercent(*size)).index(), legal))
+            .collect();
+        let all_in = legal.iter().filter(|action| matches!(action, AuctionPokerAction::Raise(_))).max_by_key(|action| action.index());
+        raises.extend(all_in.cloned());
+        raises.dedup();
+        raises
+    }
+
+    /// How likely the blueprint is to fold to `raise` holding the cards
+    /// of `particle`
+    fn folds_to(&self, particle: &Particle, raise: &AuctionPokerAction, opponent: usize) -> f32 {
+        let mut particle = particle.clone();
+        particle.advance();
+        match particle.game.active_player() {
+            ActivePlayer::Player(_, legal) if legal.contains(raise) => particle.game.play(raise),
+            _ => return 0.0,
+        }
+        particle.advance();
+        match particle.game.active_player() {
+            ActivePlayer::Player(player_num, legal) if player_num as usize == opponent => {
+                probability(&blueprint_policy(self.blueprint, &particle.game, opponent, &legal), &AuctionPokerAction::Fold)
+            }
+            _ => 0.0,
+        }
+    }
+
+    fn sample(&mut self, policy: &[(AuctionPokerAction, f32)]) -> AuctionPokerAction {
+        let mut pick = self.rng.gen_range(0.0, 1.0);
+        for (action, probability) in policy {
+            pick -= probability;
+            if pick < 0.0 {
+                return action.clone();
+            }
+        }
+        policy[policy.len() - 1].0.clone()
+    }
+
+    /// Plays a hand of the LBR player in seat `lbr` against the blueprint,
+    /// returning what LBR won
+    fn play_hand(&mut self, lbr: usize) -> f32 {
+        let opponent = lbr ^ 1;
+        let mut game = AuctionGame::new();
+        let mut line = vec![];
+        while let ActivePlayer::Chance(chance) = game.active_player() {
+            let deal = chance.sample_rng(&mut self.rng);
+            game.play(&deal);
+            line.push(deal);
+        }
+        let mut particles = self.particles(&line, lbr);
+        loop {
+            let action = match game.active_player() {
+                ActivePlayer::Terminal(utilities) => return utilities[lbr],
+                ActivePlayer::Marker(action) => action,
+                ActivePlayer::Chance(chance) => chance.sample_rng(&mut self.rng),
+                ActivePlayer::Player(player_num, legal) if player_num as usize == lbr => {
+                    self.respond(&game, lbr, &legal, &particles)
+                }
+                ActivePlayer::Player(_, legal) => {
+                    let action = self.sample(&blueprint_policy(self.blueprint, &game, opponent, &legal));
+                    self.reweigh(&mut particles, &action, opponent);
+                    action
+                }
+            };
+            game.play(&action);
+            for particle in particles.iter_mut() {
+                self.follow(particle, &action, opponent);
+            }
+            particles.retain(|particle| particle.weight > 0.0);
+        }
+    }
+
+    /// Weighs every particle by how likely the blueprint was to play
+    /// `action` holding it. If no particle could have played it, the
+    /// range is back to every particle alike
+    fn reweigh(&self, particles: &mut [Particle], action: &AuctionPokerAction, opponent: usize) {
+        for particle in particles.iter_mut() {
+            particle.advance();
+            particle.weight *= match particle.game.active_player() {
+                ActivePlayer::Player(_, legal) => {
+                    probability(&blueprint_policy(self.blueprint, &particle.game, opponent, &legal), action)
+                }
+                _ => 0.0,
+            };
+        }
+        if particles.iter().all(|particle| particle.weight <= 0.0) {
+            particles.iter_mut().for_each(|particle| particle.weight = 1.0);
+        }
+    }
+}
+
+/// How often `policy` plays the abstract action of `action`
+fn probability(policy: &[(AuctionPokerAction, f32)], action: &AuctionPokerAction) -> f32 {
+    let index = action.index();
+    policy.iter().filter(|(played, _)| played.index() == index).map(|(_, probability)| probability).sum()
+}
+
+/// Plays `config.hands` hands of local best response against `blueprint`,
+/// alternating seats
+pub fn local_best_response(blueprint: &BlueprintStrategy, config: &LbrConfig) -> LbrReport {
+    let mut lbr = LocalBestResponse {
+        blueprint,
+        config,
+        ranker: HandRanker::new(),
+        rng: StdRng::seed_from_u64(config.seed),
+    };
+    let mut report = LbrReport::default();
+    for hand in 0..config.hands {
+        let seat = hand % 2;
+        let won = lbr.play_hand(seat);
+        report.add(seat, won);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::implementations::notation::parse_hand;
+
+    #[test]
+    fn test_lbr_bets_the_nuts_and_tracks_the_range() {
+        let blueprint = BlueprintStrategy::empty();
+        let config = LbrConfig {
+            hands: 2,
+            particles: 8,
+            samples: 10,
+            ..LbrConfig::default()
+        };
+        let mut lbr = LocalBestResponse {
+            blueprint: &blueprint,
+            config: &config,
+            ranker: HandRanker::new(),
+            rng: StdRng::seed_from_u64(0),
+        };
+
+        // Player 1 has a royal flush and acts first after the auction
+        let line = parse_hand("4h5h|AsKs c / QsJsTs b10 b3 +9c").unwrap();
+        let mut game = AuctionGame::new();
+        for action in &line {
+            game.play(action);
+        }
+        let legal = game.active_player().actions().to_vec();
+        let particles = lbr.particles(&line, 1);
+        assert_eq!(particles.len(), 8);
+        let blocked = Deck::empty().union(game.state().hand(1).cards).union(game.state().community_cards());
+        assert!(particles.iter().all(|particle| particle.cards(0).len() == 2 && particle.cards(0).0 & blocked.0 == 0));
+        assert_eq!(lbr.equity(game.state().hand(1).cards, game.state().community_cards(), particles[0].cards(0)), 1.0);
+
+        // Checking wins the pot, betting wins more whenever the blueprint calls
+        let action = lbr.respond(&game, 1, &legal, &particles);
+        assert!(matches!(action, AuctionPokerAction::Raise(_)), "{:?}", action);
+
+        let report = local_best_response(&blueprint, &config);
+        assert_eq!(report.hands, [1, 1]);
+        assert!(report.per_hand().is_finite() && report.standard_error() >= 0.0, "{}", report);
+    }
+}
@@ -0,0 +1,380 @@
+/// A stand-in for the competition engine, so two bots can play a match
+/// locally through the exact same Runner path they use in a real game.
+///
+/// The engine deals from its own AuctionPokerState and only ever tells a
+/// bot what the real engine would: its seat and cards, the board, the
+/// opponent's actions and (once both are in) the opponent's bid. Clauses
+/// are queued up per bot and delivered in a single packet whenever the bot
+/// has to act, or when the hand is over.
+///
+/// Like the real engine it forgives nothing: an illegal action is played
+/// as a check (or a fold if checking isn't legal), an illegal bid as a bid
+/// of 0, and a bot that has run through its time bank check/folds for the
+/// rest of the match
+use crate::bot::decision::safe_action;
+use crate::bot::match_context::MatchContext;
+use crate::bot::runner::{Bot, Reply, Runner};
+use crate::game_logic::action::Parsable;
+use crate::game_logic::state::{ActivePlayer, State};
+use crate::implementations::auction::RelativeSize::*;
+use crate::implementations::auction::*;
+use crate::units::WinRate;
+use crate::Utility;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use std::io::{self, Empty, Sink};
+use std::time::{Duration, Instant};
+
+type LocalRunner = Runner<Box<dyn Bot>, Empty, Sink>;
+
+#[derive(Clone, Debug, Default)]
+pub struct MatchResult {
+    pub hands: usize,
+    /// Chips won by each bot (indexed by bot, not seat)
+    pub scores: [i64; 2],
+    pub illegal_actions: [usize; 2],
+    pub panics: [usize; 2],
+    pub time_used: [Duration; 2],
+    pub timed_out: [bool; 2],
+}
+
+impl MatchResult {
+    /// How much `bot` won, for reporting in bb/100
+    pub fn win_rate(&self, bot: usize) -> WinRate {
+        WinRate::new(self.scores[bot], self.hands)
+    }
+}
+
+pub struct LocalEngine {
+    runners: [LocalRunner; 2],
+    /// Time each bot gets for the whole match
+    time_bank: Duration,
+    rng: StdRng,
+}
+
+fn cards_string(cards: &[Card]) -> String {
+    cards.iter().map(|card| card.to_string().unwrap()).collect::<Vec<_>>().join(",")
+}
+
+fn to_action(reply: &Reply, pot: u32) -> AuctionPokerAction {
+    match reply {
+        Reply::Fold => AuctionPokerAction::Fold,
+        Reply::Call => AuctionPokerAction::Call,
+        Reply::Check => AuctionPokerAction::Check,
+        Reply::Raise(amount) => AuctionPokerAction::Raise(DeciPercent(Amount(*amount).to_percent(pot))),
+        Reply::Bid(amount) => AuctionPokerAction::Bid(Amount(*amount)),
+    }
+}
+
+/// What the opponent is told about an action, bids are held back until both are in
+fn to_clause(action: &AuctionPokerAction, pot: u32) -> Option<String> {
+    match action {
+        AuctionPokerAction::Fold => Some("F".to_string()),
+        AuctionPokerAction::Call => Some("C".to_string()),
+        AuctionPokerAction::Check => Some("K".to_string()),
+        AuctionPokerAction::Raise(size) => Some(format!("R{}", size.to_amount(pot))),
+        _ => None,
+    }
+}
+
+/// Everything about a single hand, indexed by seat
+struct HandInProgress {
+    state: AuctionPokerState,
+    pending: [Vec<String>; 2],
+    told_hand: [usize; 2],
+    told_board: [usize; 2],
+    bids: [Option<u32>; 2],
+    folded: bool,
+}
+
+impl HandInProgress {
+    fn new() -> Self {
+        HandInProgress {
+            state: AuctionPokerState::new(),
+            pending: [vec![], vec![]],
+            told_hand: [0, 0],
+            told_board: [0, 0],
+            bids: [None, None],
+            folded: false,
+        }
+    }
+
+    /// Queues up any cards a seat hasn't been told about yet
+    fn tell_cards(&mut self, seat: usize) {
+        let hand = self.state.hand(seat).cards();
+        if hand.len() != self.told_hand[seat] {
+            self.told_hand[seat] = hand.len();
+            self.pending[seat].push(format!("H{}", cards_string(&hand)));
+        }
+        let board: Vec<Card> = self.state.community_cards().iter().collect();
+        if board.len() != self.told_board[seat] {
+            self.told_board[seat] = board.len();
+            self.pending[seat].push(format!("B{}", cards_string(&board)));
+        }
+    }
+
+    fn packet(&mut self, seat: usize, time_left: Duration) -> String {
+        let mut clauses = vec![format!("T{:.3}", time_left.as_secs_f32())];
+        clauses.append(&mut self.pending[seat]);
+        clauses.join(" ")
+    }
+}
+
+impl LocalEngine {
+    pub fn new(bot0: Box<dyn Bot>, bot1: Box<dyn Bot>) -> Self {
+        LocalEngine {
+            runners: [
+                Runner::new(bot0, io::empty(), io::sink()),
+                Runner::new(bot1, io::empty(), io::sink()),
+            ],
+            time_bank: Duration::from_secs(30),
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Deals the same cards every time for the same seed
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Gives each bot `time_bank` for the whole match instead of the
+    /// engine's 30 seconds
+    pub fn with_time_bank(mut self, time_bank: Duration) -> Self {
+        self.time_bank = time_bank;
+        self
+    }
+
+    /// Plays `hands` hands, switching seats every hand
+    pub fn play_match(&mut self, hands: usize) -> MatchResult {
+        let mut result = MatchResult::default();
+        for runner in self.runners.iter_mut() {
+            runner.context = MatchContext::new(hands);
+        }
+        for hand_num in 0..hands {
+            // bots[seat] is the bot sitting in that seat
+            let bots = [hand_num % 2, (hand_num + 1) % 2];
+            let deltas = self.play_hand(bots, &mut result);
+            for seat in 0..2 {
+                result.scores[bots[seat]] += deltas[seat] as i64;
+            }
+            result.hands += 1;
+        }
+        for bot in 0..2 {
+            self.runners[bot].handle("Q");
+            result.panics[bot] = self.runners[bot].panics;
+        }
+        result
+    }
+
+    fn time_left(&self, bot: usize, result: &MatchResult) -> Duration {
+        self.time_bank.saturating_sub(result.time_used[bot])
+    }
+
+    /// Hands a packet to a bot, charging it for the time it takes
+    fn send(&mut self, bot: usize, packet: &str, result: &mut MatchResult) -> Reply {
+        let start = Instant::now();
+        let (reply, _) = self.runners[bot].handle(packet);
+        result.time_used[bot] += start.elapsed();
+        if result.time_used[bot] >= self.time_bank && !result.timed_out[bot] {
+            println!("[WARNING] Bot {} ran out of time, it will check/fold from now on", bot);
+            result.timed_out[bot] = true;
+        }
+        reply
+    }
+
+    /// Plays a single hand and returns each seat's winnings
+    fn play_hand(&mut self, bots: [usize; 2], result: &mut MatchResult) -> [i32; 2] {
+        let mut hand = HandInProgress::new();
+        for seat in 0..2 {
+            hand.pending[seat].push(format!("P{}", seat));
+        }
+
+        loop {
+            let (seat, actions) = match hand.state.active_player() {
+                ActivePlayer::Marker(marker) => {
+                    hand.state.update(marker);
+                    continue;
+                }
+                ActivePlayer::Chance(distribution) => {
+                    let card = distribution.sample_ref_rng(&mut self.rng).clone();
+                    hand.state.update(card);
+                    continue;
+                }
+                ActivePlayer::Terminal(deltas) => {
+                    return self.finish_hand(hand, bots, &deltas, result);
+                }
+                ActivePlayer::Player(seat, actions) => (seat as usize, actions),
+            };
+            let bot = bots[seat];
+            let pot = hand.state.pot();
+            // Cards are told in the order they are dealt, before any action after them
+            hand.tell_cards(0);
+            hand.tell_cards(1);
+
+            let reply = if result.timed_out[bot] {
+                None
+            } else {
+                let packet = hand.packet(seat, self.time_left(bot, result));
+                let reply = self.send(bot, &packet, result);
+                Some(reply).filter(|_| !result.timed_out[bot])
+            };
+            let action = match reply.map(|reply| to_action(&reply, pot)) {
+                Some(action) if actions.contains(&action) => action,
+                Some(action) => {
+                    println!("[WARNING] Bot {} played {:?}, which is illegal", bot, action);
+                    result.illegal_actions[bot] += 1;
+                    safe_action(&actions)
+                }
+                None => safe_action(&actions),
+            };
+
+            if let Some(clause) = to_clause(&action, pot) {
+                hand.pending[seat ^ 1].push(clause);
+            }
+            match action {
+                AuctionPokerAction::Fold => hand.folded = true,
+                AuctionPokerAction::Bid(Amount(amount)) => {
+                    hand.bids[seat] = Some(amount);
+                    if let [Some(bid0), Some(bid1)] = hand.bids {
+                        hand.pending[0].push(format!("A{}", bid1));
+                        hand.pending[1].push(format!("A{}", bid0));
+                    }
+                }
+                _ => {}
+            }
+            hand.state.update(action);
+        }
+    }
+
+    fn finish_hand(
+        &mut self,
+        mut hand: HandInProgress,
+        bots: [usize; 2],
+        deltas: &[Utility],
+        result: &mut MatchResult,
+    ) -> [i32; 2] {
+        let deltas = [deltas[0].round() as i32, deltas[1].round() as i32];
+        for seat in 0..2 {
+            hand.tell_cards(seat);
+            if !hand.folded {
+                let opponent = hand.state.hand(seat ^ 1).cards();
+                hand.pending[seat].push(format!("O{}", cards_string(&opponent)));
+            }
+            hand.pending[seat].push(format!("D{}", deltas[seat]));
+            let packet = hand.packet(seat, self.time_left(bots[seat], result));
+            self.send(bots[seat], &packet, result);
+        }
+        deltas
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::incremental::IncrementalState;
+    use crate::bot::runner::Clause;
+
+    /// Calls (or checks) everything and bids a fixed amount, keeping
+    /// track of the hand to make sure the engine's packets make sense
+    struct CallingBot {
+        state: IncrementalState,
+        bid: u32,
+        deltas: Vec<i32>,
+    }
+
+    impl CallingBot {
+        fn new(bid: u32) -> Self {
+            CallingBot {
+                state: IncrementalState::new(),
+                bid,
+                deltas: vec![],
+            }
+        }
+    }
+
+    impl Bot for CallingBot {
+        fn observe(&mut self, clause: &Clause) {
+            if let Clause::Delta(delta) = clause {
+                self.deltas.push(*delta);
+            }
+            self.state.apply_clause(clause).unwrap();
+        }
+
+        fn act(&mut self, _: &MatchContext) -> Option<Reply> {
+            if !self.state.needs_decision() {
+                return None;
+            }
+            let game = self.state.decision_game().unwrap();
+            let actions = game.active_player().actions().to_vec();
+            let reply = if matches!(actions[0], AuctionPokerAction::Bid(_)) {
+                Reply::Bid(self.bid)
+            } else if actions.contains(&AuctionPokerAction::Check) {
+                Reply::Check
+            } else {
+                Reply::Call
+            };
+            self.state.apply_own(&reply).unwrap();
+            Some(reply)
+        }
+    }
+
+    /// Always raises by an amount the engine won't accept
+    struct IllegalBot;
+
+    impl Bot for IllegalBot {
+        fn observe(&mut self, _: &Clause) {}
+
+        fn act(&mut self, _: &MatchContext) -> Option<Reply> {
+            Some(Reply::Raise(1))
+        }
+    }
+
+    #[test]
+    fn test_calling_bots_play_a_match() {
+        let mut engine = LocalEngine::new(Box::new(CallingBot::new(5)), Box::new(CallingBot::new(10))).with_seed(7);
+        let result = engine.play_match(6);
+        assert_eq!(result.hands, 6);
+        // Nobody ever folds, so nobody's view of the hand went wrong
+        assert_eq!(result.panics, [0, 0]);
+        assert_eq!(result.illegal_actions, [0, 0]);
+        assert_eq!(result.scores[0], -result.scores[1]);
+        assert_eq!(result.win_rate(0).bb_per_100(), -result.win_rate(1).bb_per_100());
+        assert_eq!(engine.runners[0].context.win_rate(), result.win_rate(0));
+    }
+
+    /// Takes a while to check whenever it is asked
+    struct SlowBot;
+
+    impl Bot for SlowBot {
+        fn observe(&mut self, _: &Clause) {}
+
+        fn act(&mut self, _: &MatchContext) -> Option<Reply> {
+            std::thread::sleep(Duration::from_millis(100));
+            Some(Reply::Check)
+        }
+    }
+
+    #[test]
+    fn test_bots_out_of_time_check_fold() {
+        let mut engine =
+            LocalEngine::new(Box::new(SlowBot), Box::new(IllegalBot)).with_seed(7).with_time_bank(Duration::from_millis(250));
+        let result = engine.play_match(6);
+        assert_eq!(result.hands, 6);
+        assert_eq!(result.timed_out, [true, false]);
+        // Its checks stop counting once it is out of time
+        assert!(result.illegal_actions[0] <= 2);
+    }
+
+    #[test]
+    fn test_illegal_actions_are_check_folded() {
+        let mut engine = LocalEngine::new(Box::new(IllegalBot), Box::new(CallingBot::new(0))).with_seed(3);
+        let result = engine.play_match(2);
+        assert!(result.illegal_actions[0] >= 2);
+        assert_eq!(result.illegal_actions[1], 0);
+        assert_eq!(result.panics[1], 0);
+        // Folds the small blind, then checks the big blind down to a showdown
+        // where only the blinds are in the pot
+        assert!(result.scores[0] >= -3 && result.scores[0] <= 1);
+    }
+}
@@ -0,0 +1,204 @@
+/// How the match as a whole is going, as opposed to the current hand.
+///
+/// The runner records every hand's result here and hands it to the bot
+/// along with each decision. Once we are far enough ahead that the
+/// opponent would need a (statistically) unlikely run of hands to catch
+/// up, there is nothing to gain from big pots anymore and the bot should
+/// prefer low variance lines. Further still, when the opponent can't
+/// catch up even if we check/fold every remaining hand, that's exactly
+/// what we do
+use crate::constants::{BIG_BLIND, LITTLE_BLIND, STACK_SIZE};
+use crate::units::WinRate;
+
+pub const MATCH_HANDS: usize = 1000;
+
+/// How many standard deviations of the remaining hands our lead has to
+/// cover before we start protecting it
+pub const SAFE_LEAD_DEVIATIONS: f32 = 3.0;
+
+/// Hands needed before the variance estimate means anything
+pub const MIN_HANDS_FOR_VARIANCE: usize = 20;
+
+#[derive(Clone, Debug)]
+pub struct MatchContext {
+    total_hands: usize,
+    deltas: Vec<i32>,
+    bankroll: i64,
+    /// Variance is computed over (at most) this many of the latest hands
+    pub window: usize,
+}
+
+impl Default for MatchContext {
+    fn default() -> Self {
+        MatchContext::new(MATCH_HANDS)
+    }
+}
+
+impl MatchContext {
+    pub fn new(total_hands: usize) -> Self {
+        MatchContext {
+            total_hands,
+            deltas: vec![],
+            bankroll: 0,
+            window: 200,
+        }
+    }
+
+    /// Records the result of a hand
+    pub fn record(&mut self, delta: i32) {
+        self.deltas.push(delta);
+        self.bankroll += delta as i64;
+    }
+
+    pub fn hands_played(&self) -> usize {
+        self.deltas.len()
+    }
+
+    /// The match so far, for the summary at the end of it
+    pub fn win_rate(&self) -> WinRate {
+        WinRate::new(self.bankroll, self.hands_played())
+    }
+
+    /// Includes the hand in progress, if any
+    pub fn hands_remaining(&self) -> usize {
+        self.total_hands.saturating_sub(self.hands_played())
+    }
+
+    fn recent(&self) -> &[i32] {
+        let start = self.deltas.len().saturating_sub(self.window);
+        &self.deltas[start..]
+    }
+
+    /// Mean result per hand over the window
+    pub fn mean(&self) -> f32 {
+        let recent = self.recent();
+        if recent.is_empty() {
+            return 0.0;
+        }
+        recent.iter().map(|delta| *delta as f32).sum::<f32>() / recent.len() as f32
+    }
+
+    /// Sample variance of a hand's result over the window, None until
+    /// there are enough hands to say
+    pub fn variance(&self) -> Option<f32> {
+        let recent = self.recent();
+        if recent.len() < MIN_HANDS_FOR_VARIANCE {
+            return None;
+        }
+        let mean = self.mean();
+        let squares: f32 = recent.iter().map(|delta| (*delta as f32 - mean).powi(2)).sum();
+        Some(squares / (recent.len() - 1) as f32)
+    }
+
+    /// Our lead in standard deviations of the sum of the remaining hands
+    pub fn lead_in_deviations(&self) -> Option<f32> {
+        let variance = self.variance()?;
+        let remaining = self.hands_remaining() as f32;
+        let spread = (variance * remaining).sqrt();
+        if spread == 0.0 {
+            return Some(if self.bankroll > 0 { f32::INFINITY } else { 0.0 });
+        }
+        Some(self.bankroll as f32 / spread)
+    }
+
+    /// Whether we're far enough ahead to trade expected value for variance
+    pub fn should_reduce_variance(&self) -> bool {
+        match self.lead_in_deviations() {
+            Some(deviations) => deviations >= SAFE_LEAD_DEVIATIONS,
+            None => false,
+        }
+    }
+
+    /// The most we can lose by check/folding from now until the end of the
+    /// match, sitting in `seat` with `stack` chips left in the current hand.
+    /// Check/folding never puts another chip in, so that's what we have in
+    /// the pot now plus a blind for every hand after this one (seats switch
+    /// every hand)
+    pub fn check_fold_loss(&self, seat: usize, stack: u32) -> i64 {
+        let committed = (STACK_SIZE - stack) as i64;
+        let later_hands = self.hands_remaining().saturating_sub(1) as i64;
+        let blind = |seat: usize| if seat == 0 { LITTLE_BLIND } else { BIG_BLIND } as i64;
+        let other_seat = (later_hands + 1) / 2;
+        let same_seat = later_hands / 2;
+        committed + other_seat * blind(seat ^ 1) + same_seat * blind(seat)
+    }
+
+    /// Whether check/folding the rest of the match is guaranteed to win it.
+    /// Finishing level is a tie and not a win, so the lead has to be strictly bigger
+    pub fn check_fold_wins(&self, seat: usize, stack: u32) -> bool {
+        self.bankroll > self.check_fold_loss(seat, stack)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracks_bankroll_and_variance() {
+        let mut context = MatchContext::new(100);
+        for delta in [3, -1].iter().cycle().take(MIN_HANDS_FOR_VARIANCE - 1) {
+            context.record(*delta);
+        }
+        assert_eq!(context.variance(), None);
+        assert!(!context.should_reduce_variance());
+
+        context.record(-1);
+        assert_eq!(context.win_rate(), WinRate::new(20, 20));
+        assert_eq!(context.hands_remaining(), 80);
+        assert!((context.mean() - 1.0).abs() < 1e-4);
+        let variance = context.variance().unwrap();
+        assert!((variance - 4.0 * 20.0 / 19.0).abs() < 1e-4);
+        // 80 more hands could easily swing 20 chips
+        assert!(!context.should_reduce_variance());
+    }
+
+    #[test]
+    fn test_safe_lead_depends_on_hands_remaining() {
+        let mut context = MatchContext::new(30);
+        for delta in [3, -1].iter().cycle().take(MIN_HANDS_FOR_VARIANCE) {
+            context.record(*delta);
+        }
+        // 10 hands with a standard deviation of ~2 won't swing 20 chips
+        assert!(context.should_reduce_variance());
+        context.window = 2;
+        assert_eq!(context.variance(), None);
+    }
+
+    #[test]
+    fn test_check_fold_loss_counts_blinds_by_seat() {
+        let mut context = MatchContext::new(5);
+        // Small blind in hand 1 of 5: 1 + (2 + 1 + 2 + 1)
+        assert_eq!(context.check_fold_loss(0, STACK_SIZE - LITTLE_BLIND), 7);
+        // Big blind: 2 + (1 + 2 + 1 + 2)
+        assert_eq!(context.check_fold_loss(1, STACK_SIZE - BIG_BLIND), 8);
+
+        // Four hands left (an even number), the seat doesn't matter for later hands
+        context.record(0);
+        assert_eq!(context.check_fold_loss(0, STACK_SIZE - LITTLE_BLIND), 1 + 2 + 1 + 2);
+        assert_eq!(context.check_fold_loss(1, STACK_SIZE - BIG_BLIND), 2 + 1 + 2 + 1);
+        // Chips already put into this hand are lost too
+        assert_eq!(context.check_fold_loss(1, STACK_SIZE - 30), 30 + 1 + 2 + 1);
+    }
+
+    #[test]
+    fn test_check_fold_to_victory_needs_a_strict_lead() {
+        let mut context = MatchContext::new(3);
+        context.record(9);
+        // Last two hands: the big blind now then the small blind loses at most 3
+        assert!(context.check_fold_wins(1, STACK_SIZE - BIG_BLIND));
+        context.record(-6);
+        assert_eq!(context.win_rate().chips, 3);
+        assert_eq!(context.check_fold_loss(0, STACK_SIZE - LITTLE_BLIND), 1);
+        assert!(context.check_fold_wins(0, STACK_SIZE - LITTLE_BLIND));
+        // Losing exactly our lead would be a tie
+        assert!(!context.check_fold_wins(0, STACK_SIZE - 3));
+        assert!(!context.check_fold_wins(0, STACK_SIZE - 4));
+
+        // The odd chip: level with 1 hand left in the big blind is a loss
+        let mut context = MatchContext::new(2);
+        context.record(2);
+        assert!(!context.check_fold_wins(1, STACK_SIZE - BIG_BLIND));
+        assert!(context.check_fold_wins(0, STACK_SIZE - LITTLE_BLIND));
+    }
+}
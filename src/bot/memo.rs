@@ -0,0 +1,144 @@
+/// Blueprint lookups remembered for the rest of the hand.
+///
+/// Within a hand the same infoset can be looked up several times: the
+/// cascade and then continuous sizing both want the exact policy, a
+/// decision that ran out of time is taken again, logging and sanity checks
+/// ask what the blueprint would have done. The exact lookup is a BTreeMap
+/// search but the best fit walks every neighbour in range, so the engine
+/// keeps what it found for each condensed infoset until the hand is over.
+///
+/// A lookup only depends on the infoset and the blueprint, so the memo also
+/// forgets everything when the blueprint is swapped (see bot::reload)
+use crate::bot::decision::AuctionGame;
+use crate::bot::prefetch::Policy;
+use crate::game_logic::strategy::blueprint::BlueprintStrategy;
+use crate::game_logic::strategy::CondensedInfoSet;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+#[derive(Clone, Debug, Default)]
+struct Entry {
+    exact: Option<Option<Policy>>,
+    best: Option<Option<Policy>>,
+}
+
+#[derive(Default)]
+struct Memo {
+    /// The blueprint the entries were looked up in
+    blueprint: Weak<BlueprintStrategy>,
+    entries: HashMap<(usize, CondensedInfoSet), Entry>,
+}
+
+#[derive(Default)]
+pub struct LookupMemo {
+    memo: Mutex<Memo>,
+    hits: AtomicUsize,
+    lookups: AtomicUsize,
+}
+
+impl LookupMemo {
+    /// Forgets every lookup, call when a new hand starts
+    pub fn clear(&self) {
+        self.memo.lock().unwrap().entries.clear();
+    }
+
+    /// Lookups answered from memory since the engine started
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Lookups asked for since the engine started, answered from memory or not
+    pub fn lookups(&self) -> usize {
+        self.lookups.load(Ordering::Relaxed)
+    }
+
+    /// BlueprintStrategy::get_exact_policy, remembered
+    pub fn exact(&self, blueprint: &Arc<BlueprintStrategy>, game: &AuctionGame, player_num: usize) -> Option<Policy> {
+        let key = (player_num, game.get_information_set(player_num));
+        self.remember(blueprint, key, |entry| &mut entry.exact, || blueprint.get_exact_policy(game, player_num))
+    }
+
+    /// BlueprintStrategy::get_best_policy, remembered
+    pub fn best(&self, blueprint: &Arc<BlueprintStrategy>, game: &AuctionGame, player_num: usize) -> Option<Policy> {
+        let key = (player_num, game.get_information_set(player_num));
+        self.remember(blueprint, key, |entry| &mut entry.best, || blueprint.get_best_policy(game, player_num))
+    }
+
+    fn remember(
+        &self,
+        blueprint: &Arc<BlueprintStrategy>,
+        key: (usize, CondensedInfoSet),
+        field: impl Fn(&mut Entry) -> &mut Option<Option<Policy>>,
+        lookup: impl FnOnce() -> Option<Policy>,
+    ) -> Option<Policy> {
+        self.lookups.fetch_add(1, Ordering::Relaxed);
+        {
+            let mut memo = self.memo.lock().unwrap();
+            if !Weak::ptr_eq(&memo.blueprint, &Arc::downgrade(blueprint)) {
+                memo.blueprint = Arc::downgrade(blueprint);
+                memo.entries.clear();
+            }
+            if let Some(policy) = memo.entries.get_mut(&key).and_then(|entry| field(entry).clone()) {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return policy;
+            }
+        }
+        // Not holding the lock, the best fit search can take a while
+        let policy = lookup();
+        let mut memo = self.memo.lock().unwrap();
+        if Weak::ptr_eq(&memo.blueprint, &Arc::downgrade(blueprint)) {
+            *field(memo.entries.entry(key).or_default()) = Some(policy.clone());
+        }
+        policy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::implementations::auction::*;
+    use std::collections::BTreeMap;
+
+    fn preflop_game() -> AuctionGame {
+        let mut game = AuctionGame::new();
+        game.play(&AuctionPokerAction::DealHole(0, 0));
+        game.play(&AuctionPokerAction::DealHole(2, 0));
+        game.play(&AuctionPokerAction::DealHole(3, 1));
+        game.play(&AuctionPokerAction::DealHole(4, 1));
+        game.play(&AuctionPokerAction::BettingRoundStart);
+        game
+    }
+
+    #[test]
+    fn test_memo_remembers_until_the_hand_or_blueprint_changes() {
+        let game = preflop_game();
+        let mut policies = BTreeMap::new();
+        policies.insert(game.get_information_set(0), vec![0.0, 1.0]);
+        let blueprint = Arc::new(BlueprintStrategy::from_policies(vec![policies, BTreeMap::new()]));
+        let memo = LookupMemo::default();
+
+        let policy = memo.exact(&blueprint, &game, 0);
+        assert!(policy.is_some());
+        assert_eq!((memo.hits(), memo.lookups()), (0, 1));
+        assert_eq!(memo.exact(&blueprint, &game, 0), policy);
+        assert_eq!((memo.hits(), memo.lookups()), (1, 2));
+
+        // The best fit is looked up on its own
+        memo.best(&blueprint, &game, 0);
+        assert_eq!(memo.hits(), 1);
+        memo.best(&blueprint, &game, 0);
+        assert_eq!((memo.hits(), memo.lookups()), (2, 4));
+
+        // A new blueprint starts over
+        let empty = Arc::new(BlueprintStrategy::empty());
+        assert_eq!(memo.exact(&empty, &game, 0), None);
+        assert_eq!(memo.hits(), 2);
+        assert_eq!(memo.exact(&empty, &game, 0), None);
+        assert_eq!(memo.hits(), 3);
+
+        memo.clear();
+        memo.exact(&empty, &game, 0);
+        assert_eq!((memo.hits(), memo.lookups()), (3, 7));
+    }
+}
@@ -0,0 +1,25 @@
+pub mod ablation;
+pub mod acpc;
+pub mod agent;
+pub mod auction_values;
+pub mod bid_translation;
+pub mod decision;
+pub mod guard;
+pub mod heuristics;
+pub mod incremental;
+pub mod lbr;
+pub mod local_engine;
+pub mod match_context;
+pub mod memo;
+pub mod opponent_model;
+pub mod player;
+pub mod prefetch;
+pub mod public_state;
+pub mod ranges;
+pub mod resolver;
+pub mod reload;
+pub mod runner;
+pub mod sanity;
+pub mod sizing;
+pub mod streets;
+pub mod what_if;
@@ -0,0 +1,138 @@
+/// What we have learned about the opponent over the course of a match
+use std::collections::BTreeMap;
+
+/// Opponent bids are grouped by the pot size at the auction
+const POT_BUCKET_SIZE: u32 = 20;
+
+/// Strengths of the hands the opponent went all in with are counted in this
+/// many equal bins
+const STRENGTH_BINS: usize = 5;
+
+#[derive(Clone, Debug)]
+pub struct OpponentModel {
+    bids: BTreeMap<u32, Vec<u32>>, // Pot bucket -> observed bids (sorted)
+    /// Strengths (see eval::equity::hand_strengths) of the hands the
+    /// opponent showed down after going all in, by bin
+    all_in_strengths: [usize; STRENGTH_BINS],
+    /// Quantile of the opponent's bids we aim to just outbid
+    pub exploit_quantile: f32,
+    /// Observations needed in a pot bucket before we trust it
+    pub min_samples: usize,
+}
+
+impl Default for OpponentModel {
+    fn default() -> Self {
+        OpponentModel {
+            bids: BTreeMap::new(),
+            all_in_strengths: [0; STRENGTH_BINS],
+            exploit_quantile: 0.9,
+            min_samples: 20,
+        }
+    }
+}
+
+impl OpponentModel {
+    fn bucket(pot: u32) -> u32 {
+        pot / POT_BUCKET_SIZE
+    }
+
+    pub fn observe_bid(&mut self, pot: u32, bid: u32) {
+        let bids = self.bids.entry(Self::bucket(pot)).or_default();
+        let position = bids.partition_point(|b| *b <= bid);
+        bids.insert(position, bid);
+    }
+
+    pub fn bid_count(&self, pot: u32) -> usize {
+        self.bids.get(&Self::bucket(pot)).map_or(0, |bids| bids.len())
+    }
+
+    /// Empirical quantile of the opponent's bids at this pot size
+    pub fn bid_quantile(&self, pot: u32, quantile: f32) -> Option<u32> {
+        let bids = self.bids.get(&Self::bucket(pot))?;
+        if bids.is_empty() {
+            return None;
+        }
+        let position = ((bids.len() - 1) as f32 * quantile.clamp(0.0, 1.0)).round() as usize;
+        Some(bids[position])
+    }
+
+    /// Record the strength of a hand the opponent went all in with, seen at
+    /// showdown on the final board. Only hands we called are ever seen, so
+    /// this learns the range we call into
+    pub fn observe_all_in(&mut self, strength: f64) {
+        self.all_in_strengths[Self::strength_bin(strength)] += 1;
+    }
+
+    pub fn all_in_count(&self) -> usize {
+        self.all_in_strengths.iter().sum()
+    }
+
+    fn strength_bin(strength: f64) -> usize {
+        ((strength.clamp(0.0, 1.0) * STRENGTH_BINS as f64) as usize).min(STRENGTH_BINS - 1)
+    }
+
+    /// How much likelier the opponent is to go all in with a hand of
+    /// `strength` than with one picked at random, as a weight of their
+    /// range. 1 for every strength until there are min_samples all ins to
+    /// go by, and the counts are smoothed so no strength is ever ruled out
+    pub fn all_in_weight(&self, strength: f64) -> f64 {
+        let count = self.all_in_count();
+        if count < self.min_samples {
+            return 1.0;
+        }
+        let seen = self.all_in_strengths[Self::strength_bin(strength)] as f64;
+        (seen + 1.0) / (count + STRENGTH_BINS) as f64 * STRENGTH_BINS as f64
+    }
+
+    /// Shade our bid down to just above the opponent's usual bids.
+    ///
+    /// The winner pays the loser's bid, so once we outbid nearly all of the
+    /// opponent's distribution, bidding any higher only adds exposure
+    /// (ties cost both players their bid). Returns the bid unchanged when
+    /// we don't have enough data or the opponent usually outbids us anyway
+    pub fn shade_bid(&self, pot: u32, bid: u32) -> u32 {
+        if self.bid_count(pot) < self.min_samples {
+            return bid;
+        }
+        match self.bid_quantile(pot, self.exploit_quantile) {
+            Some(high) if high + 1 < bid => high + 1,
+            _ => bid,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shades_bid_above_opponent_distribution() {
+        let mut model = OpponentModel::default();
+        assert_eq!(model.shade_bid(40, 100), 100, "No data, no shading");
+        for bid in 0..40 {
+            model.observe_bid(45, bid % 20);
+        }
+        assert_eq!(model.bid_count(40), 40);
+        assert_eq!(model.bid_quantile(40, 0.0), Some(0));
+        assert_eq!(model.bid_quantile(40, 1.0), Some(19));
+        let shaded = model.shade_bid(40, 100);
+        assert!((18..100).contains(&shaded));
+        // Never bid more than we intended
+        assert_eq!(model.shade_bid(40, 10), 10);
+        // Other pot sizes are tracked separately
+        assert_eq!(model.shade_bid(200, 100), 100);
+    }
+
+    #[test]
+    fn test_all_in_range_follows_the_hands_shown() {
+        let mut model = OpponentModel::default();
+        assert_eq!(model.all_in_weight(0.1), 1.0, "No data, uniform range");
+        for i in 0..30 {
+            model.observe_all_in(0.8 + 0.2 * (i % 10) as f64 / 10.0);
+        }
+        assert_eq!(model.all_in_count(), 30);
+        assert!(model.all_in_weight(0.9) > 4.0);
+        assert!(model.all_in_weight(0.1) > 0.0 && model.all_in_weight(0.1) < 0.2);
+        assert_eq!(model.all_in_weight(1.0), model.all_in_weight(0.9));
+    }
+}
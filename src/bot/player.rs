@@ -0,0 +1,296 @@
+/// The auction poker bot as the runner sees it: engine clauses go into an
+/// IncrementalState and decisions come out of the Agent
+use crate::bot::agent::Agent;
+use crate::bot::bid_translation::translate_bid;
+use crate::bot::decision::*;
+use crate::bot::guard::guard;
+use crate::bot::incremental::IncrementalState;
+use crate::bot::match_context::MatchContext;
+use crate::bot::prefetch::Prefetched;
+use crate::bot::runner::{Bot, Clause, Reply};
+use crate::bot::sizing::jitter_raise;
+use crate::game_logic::state::ActivePlayer;
+use crate::implementations::auction::*;
+use crate::implementations::notation::format_hand;
+use rand::{rngs::StdRng, FromEntropy};
+use std::time::Duration;
+
+pub struct AuctionBot {
+    agent: Agent,
+    state: IncrementalState,
+    /// Time allowed for a single decision
+    pub budget: Duration,
+    /// How far raises are randomly moved within their bucket, see sizing::jitter_raise
+    pub raise_jitter: f32,
+    /// Decisions the guard had to replace, see bot::guard
+    pub corrections: usize,
+    /// Decisions made from a prefetched continuation
+    pub prefetch_hits: usize,
+    rng: StdRng,
+    /// Length of the history when we last prefetched, the action after it
+    /// is the one the prefetched continuations are keyed by
+    prefetched_from: Option<usize>,
+    /// We called an all in this hand, so the cards the opponent shows down
+    /// teach the opponent model their all in range
+    called_all_in: bool,
+}
+
+impl AuctionBot {
+    pub fn new(engine: DecisionEngine) -> Self {
+        AuctionBot::with_agent(Agent::new(engine))
+    }
+
+    pub fn with_agent(agent: Agent) -> Self {
+        AuctionBot {
+            agent,
+            state: IncrementalState::new(),
+            budget: Duration::from_millis(500),
+            raise_jitter: 0.0,
+            corrections: 0,
+            prefetch_hits: 0,
+            rng: StdRng::from_entropy(),
+            prefetched_from: None,
+            called_all_in: false,
+        }
+    }
+
+    pub fn engine(&self) -> &DecisionEngine {
+        self.agent.engine()
+    }
+
+    /// While the opponent is to act, has the engine work out our decision
+    /// after each of their likeliest moves (if it prefetches, see bot::prefetch)
+    fn prefetch(&mut self) {
+        let (game, seat) = match (self.state.game(), self.state.seat()) {
+            (Some(game), Some(seat)) => (game, seat),
+            _ => return,
+        };
+        // Both bid at once, so there is nothing to wait on in the auction
+        let waiting = match game.active_player() {
+            ActivePlayer::Player(player_num, actions) => {
+                player_num as usize != seat && !matches!(actions.first(), Some(AuctionPokerAction::Bid(_)))
+            }
+            _ => false,
+        };
+        let from = self.state.history().len();
+        if waiting && self.prefetched_from != Some(from) {
+            self.agent.engine().prefetch(game, seat);
+            self.prefetched_from = Some(from);
+        }
+    }
+
+    /// What was prefetched for the move the opponent actually made, as long
+    /// as it led to the decision we are facing
+    fn take_prefetched(&mut self, game: &AuctionGame, seat: usize) -> Option<Prefetched> {
+        let from = self.prefetched_from.take()?;
+        let action = self.state.history().get(from)?;
+        let prefetched = self.agent.engine().take_prefetched(action)?;
+        if prefetched.game.get_information_set(seat) != game.get_information_set(seat) {
+            return None;
+        }
+        Some(prefetched)
+    }
+
+    /// The hand so far in the notation of implementations::notation, for
+    /// bug reports (the opponent's cards are placeholders until we see them)
+    fn hand_notation(&self) -> String {
+        format_hand(self.state.history()).unwrap_or_else(|error| format!("(unreadable: {})", error))
+    }
+}
+
+/// The engine's version of an action, amounts in chips
+pub fn to_reply(action: &AuctionPokerAction, pot: u32) -> Reply {
+    match action {
+        AuctionPokerAction::Fold => Reply::Fold,
+        AuctionPokerAction::Call => Reply::Call,
+        AuctionPokerAction::Check => Reply::Check,
+        AuctionPokerAction::Raise(size) => Reply::Raise(size.to_amount(pot)),
+        AuctionPokerAction::Bid(size) => Reply::Bid(size.to_amount(pot)),
+        x => panic!("{:?} is not something a player can do", x),
+    }
+}
+
+impl Bot for AuctionBot {
+    fn observe(&mut self, clause: &Clause) {
+        if let Clause::Seat(_) = clause {
+            self.agent.engine().new_hand();
+            self.prefetched_from = None;
+            self.called_all_in = false;
+        }
+        if let Clause::Opponent(cards) = clause {
+            if let (true, Some(game), Some(seat)) = (self.called_all_in, self.state.game(), self.state.seat()) {
+                self.agent.engine_mut().observe_all_in_showdown(game.state(), seat, cards);
+            }
+        }
+        if let Clause::Bid(bid) = clause {
+            // The opponent model learns bids on the blueprint's grid, the
+            // hand itself goes on with the exact bid
+            let pot = self.state.game().map(|game| game.state().pot()).unwrap_or(0);
+            let bid = match translate_bid(*bid, &mut self.rng) {
+                AuctionPokerAction::Bid(size) => size.to_amount(pot),
+                _ => *bid,
+            };
+            self.agent.engine_mut().observe_opponent_bid(pot, bid);
+        }
+        if let Err(error) = self.state.apply_clause(clause) {
+            panic!("Lost track of the hand {}: {}", self.hand_notation(), error);
+        }
+        self.prefetch();
+    }
+
+    fn act(&mut self, context: &MatchContext) -> Option<Reply> {
+        if let Err(error) = self.state.infer_auction() {
+            panic!("Lost track of the hand {}: {}", self.hand_notation(), error);
+        }
+        if !self.state.needs_decision() {
+            return None;
+        }
+        let game = self.state.decision_game()?;
+        let seat = self.state.seat()?;
+        let stack = game.state().stacks()[seat];
+        if context.check_fold_wins(seat, stack) {
+            // Nothing the opponent does can stop us now
+            let reply = to_reply(&safe_action(game.active_player().actions()), game.state().pot());
+            self.state.apply_own(&reply).unwrap();
+            return Some(reply);
+        }
+        let (mut action, source) = match self.take_prefetched(&game, seat) {
+            Some(prefetched) => {
+                self.prefetch_hits += 1;
+                self.agent.decide_prefetched(&prefetched, self.state.history(), seat, self.budget)
+            }
+            None => self.agent.decide(&game, self.state.history(), seat, self.budget),
+        };
+        if context.should_reduce_variance() {
+            action = low_variance(&action, game.active_player().actions());
+        }
+        if self.raise_jitter > 0.0 {
+            action = jitter_raise(&action, game.active_player().actions(), self.raise_jitter, &mut self.rng);
+        }
+        let (guarded, correction) = guard(&action, game.active_player().actions(), game.state(), seat);
+        if let Some(correction) = correction {
+            println!("[WARNING] {:?} from {:?} played as {:?} ({:?})", action, source, guarded, correction);
+            self.corrections += 1;
+            action = guarded;
+        }
+        if matches!((&source, &action), (DecisionSource::AllInEquity, AuctionPokerAction::Call)) {
+            self.called_all_in = true;
+        }
+        let reply = to_reply(&action, game.state().pot());
+        if let Err(error) = self.state.apply_own(&reply) {
+            panic!("Decided on {:?} from {:?} in {} but: {}", reply, source, self.hand_notation(), error);
+        }
+        self.prefetch();
+        Some(reply)
+    }
+
+    fn safe_reply(&self) -> Reply {
+        match self.state.decision_game() {
+            Some(game) => {
+                let actions = game.active_player().actions().to_vec();
+                if actions.is_empty() {
+                    return Reply::Check;
+                }
+                to_reply(&safe_action(&actions), game.state().pot())
+            }
+            None => Reply::Check,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::agent::AgentConfig;
+    use crate::bot::local_engine::LocalEngine;
+    use crate::game_logic::strategy::blueprint::BlueprintStrategy;
+    use crate::implementations::auction::RelativeSize::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Lets the test look at a bot the LocalEngine owns
+    struct Shared(Arc<Mutex<AuctionBot>>);
+
+    impl Bot for Shared {
+        fn observe(&mut self, clause: &Clause) {
+            self.0.lock().unwrap().observe(clause)
+        }
+
+        fn act(&mut self, context: &MatchContext) -> Option<Reply> {
+            self.0.lock().unwrap().act(context)
+        }
+    }
+
+    /// Raises whenever it can, taking its time about it
+    struct SlowRaiser(IncrementalState);
+
+    impl Bot for SlowRaiser {
+        fn observe(&mut self, clause: &Clause) {
+            self.0.apply_clause(clause).unwrap();
+        }
+
+        fn act(&mut self, _: &MatchContext) -> Option<Reply> {
+            if !self.0.needs_decision() {
+                return None;
+            }
+            std::thread::sleep(Duration::from_millis(300));
+            let game = self.0.decision_game().unwrap();
+            let actions = game.active_player().actions().to_vec();
+            let action = match actions.iter().find(|action| matches!(action, AuctionPokerAction::Raise(_))) {
+                Some(raise) => raise.clone(),
+                None if actions.contains(&AuctionPokerAction::Check) => AuctionPokerAction::Check,
+                None => actions[0].clone(),
+            };
+            let reply = to_reply(&action, game.state().pot());
+            self.0.apply_own(&reply).unwrap();
+            Some(reply)
+        }
+    }
+
+    #[test]
+    fn test_decides_from_prefetched_continuations() {
+        let engine = DecisionEngine::new(BlueprintStrategy::empty()).with_prefetch(4);
+        let config = AgentConfig {
+            resolve: false,
+            ..Default::default()
+        };
+        let bot = Arc::new(Mutex::new(AuctionBot::with_agent(Agent::new(engine).with_config(config))));
+        let result = LocalEngine::new(Box::new(Shared(bot.clone())), Box::new(SlowRaiser(IncrementalState::new())))
+            .with_seed(5)
+            .play_match(6);
+        assert_eq!((result.panics, result.illegal_actions), ([0, 0], [0, 0]));
+        // While the raiser thinks, our reply to its raise is worked out
+        assert!(bot.lock().unwrap().prefetch_hits > 0);
+    }
+
+    #[test]
+    fn test_to_reply_uses_chips() {
+        assert_eq!(to_reply(&AuctionPokerAction::Raise(DeciPercent(500)), 40), Reply::Raise(20));
+        assert_eq!(to_reply(&AuctionPokerAction::Bid(Amount(7)), 40), Reply::Bid(7));
+    }
+
+    #[test]
+    fn test_opponent_bids_are_learned_on_the_bid_grid() {
+        use crate::bot::bid_translation::bid_translation;
+        use crate::bot::runner::parse_packet;
+        let mut bot = AuctionBot::new(DecisionEngine::new(BlueprintStrategy::empty()));
+        for clause in parse_packet("P0 HKs,Kd") {
+            bot.observe(&clause);
+        }
+        bot.state.apply_own(&Reply::Call).unwrap();
+        bot.observe(&Clause::Board(vec![Card::new("3c"), Card::new("4c"), Card::new("5c")]));
+        bot.state.apply_own(&Reply::Bid(50)).unwrap();
+        let pot = bot.state.game().unwrap().state().pot();
+        bot.observe(&Clause::Bid(13));
+
+        let learned = bot.engine().opponent_model().bid_quantile(pot, 0.5).unwrap();
+        let middles: Vec<u32> = bid_translation(13)
+            .iter()
+            .map(|(index, _)| to_reply(&AuctionPokerAction::from(*index), pot))
+            .map(|reply| match reply {
+                Reply::Bid(bid) => bid,
+                reply => panic!("{:?} is not a bid", reply),
+            })
+            .collect();
+        assert!(middles.contains(&learned), "{} is not one of {:?}", learned, middles);
+    }
+}
@@ -0,0 +1,217 @@
+/// Background prefetching of the next decision while the opponent thinks.
+///
+/// Most of the time spent on a decision is not the blueprint at all but
+/// the feature rollouts that run as the game advances, followed by the
+/// best fit search. Both only depend on what happens next, so while we
+/// wait for the opponent (or the next card) a worker thread plays out the
+/// likeliest continuations and caches the resulting game along with its
+/// blueprint lookups. When the real action arrives and matches one of them
+/// we can skip straight to sampling an action.
+use crate::bot::decision::AuctionGame;
+use crate::bot::reload::SharedBlueprint;
+use crate::game_logic::action::ActionIndex;
+use crate::game_logic::state::ActivePlayer;
+use crate::game_logic::strategy::blueprint::BlueprintStrategy;
+use crate::implementations::auction::*;
+use rand::prelude::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub type Policy = Vec<(ActionIndex, f32)>;
+
+/// Blueprint lookups for a single decision, computed ahead of time
+#[derive(Clone, Debug)]
+pub struct BlueprintLookups {
+    pub exact: Option<Policy>,
+    pub best: Option<Policy>,
+}
+
+impl BlueprintLookups {
+    pub fn compute(blueprint: &BlueprintStrategy, game: &AuctionGame, player_num: usize) -> Self {
+        BlueprintLookups {
+            exact: blueprint.get_exact_policy(game, player_num),
+            best: blueprint.get_best_policy(game, player_num),
+        }
+    }
+}
+
+/// A continuation of the game that ends at our next decision
+#[derive(Clone, Debug)]
+pub struct Prefetched {
+    pub game: AuctionGame,
+    pub lookups: BlueprintLookups,
+}
+
+struct Job {
+    generation: usize,
+    game: AuctionGame,
+    player_num: usize,
+}
+
+#[derive(Default)]
+struct Cache {
+    generation: usize,
+    entries: HashMap<AuctionPokerAction, Prefetched>,
+}
+
+pub struct Prefetcher {
+    jobs: Sender<Job>,
+    generation: Arc<AtomicUsize>,
+    cache: Arc<Mutex<Cache>>,
+}
+
+impl Prefetcher {
+    /// Spawns the worker thread, which exits once the Prefetcher is dropped.
+    /// At most `max_branches` continuations are explored per request
+    pub fn new(blueprint: SharedBlueprint, max_branches: usize) -> Self {
+        let (jobs, receiver) = channel::<Job>();
+        let generation = Arc::new(AtomicUsize::new(0));
+        let cache = Arc::new(Mutex::new(Cache::default()));
+
+        let worker_generation = generation.clone();
+        let worker_cache = cache.clone();
+        thread::spawn(move || {
+            for job in receiver {
+                let blueprint = blueprint.current();
+                for action in likely_next_actions(&job.game, max_branches) {
+                    // A newer request arrived, this one is no longer useful
+                    if worker_generation.load(Ordering::SeqCst) != job.generation {
+                        break;
+                    }
+                    let game = match play_until_decision(&job.game, &action, job.player_num) {
+                        Some(game) => game,
+                        None => continue,
+                    };
+                    let lookups = BlueprintLookups::compute(&blueprint, &game, job.player_num);
+                    let mut cache = worker_cache.lock().unwrap();
+                    if cache.generation == job.generation {
+                        cache.entries.insert(action, Prefetched { game, lookups });
+                    }
+                }
+            }
+        });
+
+        Prefetcher {
+            jobs,
+            generation,
+            cache,
+        }
+    }
+
+    /// Start working on the continuations of `game` that lead back to a
+    /// decision for `player_num`. Anything prefetched earlier is discarded
+    pub fn prefetch(&self, game: &AuctionGame, player_num: usize) {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        {
+            let mut cache = self.cache.lock().unwrap();
+            cache.generation = generation;
+            cache.entries.clear();
+        }
+        let job = Job {
+            generation,
+            game: game.clone(),
+            player_num,
+        };
+        self.jobs.send(job).expect("Prefetch worker has stopped");
+    }
+
+    /// The prefetched continuation for the action that actually happened, if any
+    pub fn take(&self, action: &AuctionPokerAction) -> Option<Prefetched> {
+        self.cache.lock().unwrap().entries.remove(action)
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.cache.lock().unwrap().entries.len()
+    }
+}
+
+/// The continuations worth prefetching, most likely first. We don't know the
+/// opponent's policy, so passive actions are assumed to be the most common and
+/// folds are skipped (there is no decision after them). Cards are all equally
+/// likely so a random subset of them is taken
+fn likely_next_actions(game: &AuctionGame, max_branches: usize) -> Vec<AuctionPokerAction> {
+    let mut actions = match game.active_player() {
+        ActivePlayer::Player(_, actions) => {
+            let mut actions: Vec<AuctionPokerAction> = actions
+                .into_iter()
+                .filter(|action| !matches!(action, AuctionPokerAction::Fold))
+                .collect();
+            actions.sort_by_key(|action| match action {
+                AuctionPokerAction::Check => 0,
+                AuctionPokerAction::Call => 1,
+                AuctionPokerAction::Raise(size) => 2 + size.to_amount(game.state().pot()),
+                AuctionPokerAction::Bid(size) => 2 + size.to_amount(game.state().pot()),
+                _ => u32::MAX,
+            });
+            actions
+        }
+        ActivePlayer::Chance(distribution) => {
+            let mut cards = distribution.items().clone();
+            cards.shuffle(&mut thread_rng());
+            cards
+        }
+        ActivePlayer::Marker(action) => vec![action],
+        ActivePlayer::Terminal(_) => vec![],
+    };
+    actions.truncate(max_branches);
+    actions
+}
+
+/// Plays `action` and then any markers that follow, returning the game
+/// only if it ends up waiting on a decision from `player_num`
+fn play_until_decision(game: &AuctionGame, action: &AuctionPokerAction, player_num: usize) -> Option<AuctionGame> {
+    let mut game = game.clone();
+    game.play(action);
+    loop {
+        match game.active_player() {
+            ActivePlayer::Marker(marker) => game.play(&marker),
+            ActivePlayer::Player(p, _) if p as usize == player_num => return Some(game),
+            _ => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_prefetches_opponent_responses() {
+        let mut game = AuctionGame::new();
+        game.play(&AuctionPokerAction::DealHole(0, 0));
+        game.play(&AuctionPokerAction::DealHole(2, 0));
+        game.play(&AuctionPokerAction::DealHole(3, 1));
+        game.play(&AuctionPokerAction::DealHole(4, 1));
+        game.play(&AuctionPokerAction::BettingRoundStart);
+        // Player 0 (small blind) calls, now it's player 1's turn
+        game.play(&AuctionPokerAction::Call);
+        game.play(&AuctionPokerAction::PlayerActionEnd(0));
+
+        let prefetcher = Prefetcher::new(SharedBlueprint::new(BlueprintStrategy::empty()), 2);
+        prefetcher.prefetch(&game, 0);
+
+        let start = Instant::now();
+        while prefetcher.len() == 0 && start.elapsed() < Duration::from_secs(10) {
+            thread::sleep(Duration::from_millis(5));
+        }
+        // Player 1 raising hands the decision back to player 0
+        let raise = likely_next_actions(&game, 2)
+            .into_iter()
+            .find(|action| matches!(action, AuctionPokerAction::Raise(_)))
+            .expect("Player 1 should be able to raise");
+        let start = Instant::now();
+        let mut prefetched = None;
+        while prefetched.is_none() && start.elapsed() < Duration::from_secs(10) {
+            prefetched = prefetcher.take(&raise);
+            thread::sleep(Duration::from_millis(5));
+        }
+        let prefetched = prefetched.expect("Raise should have been prefetched");
+        assert_eq!(prefetched.game.active_player().player_num(), 0);
+        assert!(prefetched.lookups.exact.is_none());
+    }
+}
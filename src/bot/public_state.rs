@@ -0,0 +1,308 @@
+/// What everyone at the table knows about a hand: the board, the pot, the
+/// betting line and how the auction went, plus a range for each player, the
+/// weight of every holding they could have given what they did.
+///
+/// This is the state a resolver works from. It never holds anyone's hole
+/// cards, only the weights, so the same PublicState serves both players and
+/// a subgame rooted at it can be solved for every holding at once (see
+/// algorithm::vector_cfr).
+///
+/// Ranges are over the two hole cards. The auction winner's third card is
+/// private too but isn't tracked, it counts as any card nobody has seen
+use crate::game_logic::state::{ActivePlayer, State};
+use crate::implementations::auction::*;
+use std::sync::OnceLock;
+
+/// Number of two card holdings, 52 choose 2
+pub const NUM_HOLDINGS: usize = 1326;
+
+/// A pair of hole cards, indexed so that the holdings of the lower cards
+/// come first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Holding(pub u16);
+
+impl Holding {
+    pub fn new(first: Card, second: Card) -> Holding {
+        assert_ne!(first, second, "A holding needs two different cards");
+        let (low, high) = match first.as_u8() < second.as_u8() {
+            true => (first.as_u8() as u16, second.as_u8() as u16),
+            false => (second.as_u8() as u16, first.as_u8() as u16),
+        };
+        Holding(high * (high - 1) / 2 + low)
+    }
+
+    /// The two cards, lowest index first
+    pub fn cards(&self) -> (Card, Card) {
+        static CARDS: OnceLock<Vec<(Card, Card)>> = OnceLock::new();
+        let cards = CARDS.get_or_init(|| {
+            (1..52u8).flat_map(|high| (0..high).map(move |low| (Card(low), Card(high)))).collect()
+        });
+        cards[self.0 as usize]
+    }
+
+    pub fn deck(&self) -> Deck {
+        let (low, high) = self.cards();
+        [low, high].into_iter().collect()
+    }
+
+    pub fn all() -> impl Iterator<Item = Holding> {
+        (0..NUM_HOLDINGS as u16).map(Holding)
+    }
+}
+
+/// Weight of every holding of one player, not necessarily normalized
+#[derive(Debug, Clone, PartialEq)]
+pub struct Range {
+    weights: Vec<f32>,
+}
+
+impl Range {
+    pub fn uniform() -> Range {
+        Range { weights: vec![1.0; NUM_HOLDINGS] }
+    }
+
+    pub fn from_weights(weights: Vec<f32>) -> Range {
+        assert_eq!(weights.len(), NUM_HOLDINGS, "A range has a weight for every holding");
+        Range { weights }
+    }
+
+    pub fn weights(&self) -> &[f32] {
+        &self.weights
+    }
+
+    pub fn total(&self) -> f32 {
+        self.weights.iter().sum()
+    }
+
+    /// Removes every holding with one of `cards`
+    pub fn block(&mut self, cards: Deck) {
+        self.reweigh(|holding| {
+            let (low, high) = holding.cards();
+            match cards.contains(low) || cards.contains(high) {
+                true => 0.0,
+                false => 1.0,
+            }
+        });
+    }
+
+    /// Multiplies every weight by `likelihood`, e.g. by how likely the
+    /// player was to take the action we just saw holding it
+    pub fn reweigh(&mut self, likelihood: impl Fn(Holding) -> f32) {
+        for holding in Holding::all() {
+            self.weights[holding.0 as usize] *= likelihood(holding);
+        }
+    }
+
+    /// Scales the weights to sum to one, None if the range is empty
+    pub fn normalized(&self) -> Option<Range> {
+        let total = self.total();
+        if total <= 0.0 {
+            return None;
+        }
+        Some(Range {
+            weights: self.weights.iter().map(|weight| weight / total).collect(),
+        })
+    }
+
+    /// Holdings with any weight, with their weight
+    pub fn holdings(&self) -> impl Iterator<Item = (Holding, f32)> + '_ {
+        self.weights
+            .iter()
+            .enumerate()
+            .filter(|(_, weight)| **weight > 0.0)
+            .map(|(index, weight)| (Holding(index as u16), *weight))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PublicState {
+    pub board: Deck,
+    pub pot: u32,
+    pub stacks: [u32; 2],
+    pub pips: [u32; 2],
+    /// Result of the auction, None until both bids are in
+    pub auction: Option<Winner>,
+    /// Every public action so far in order: the decisions of the players,
+    /// the board cards and the auction result
+    pub line: Vec<AuctionPokerAction>,
+    /// Whose turn it is, None at chance nodes and once the hand is over
+    pub to_act: Option<usize>,
+    pub ranges: [Range; 2],
+}
+
+/// Whether everyone at the table sees `action`
+fn is_public(action: &AuctionPokerAction) -> bool {
+    matches!(
+        action,
+        AuctionPokerAction::Fold
+            | AuctionPokerAction::Call
+            | AuctionPokerAction::Check
+            | AuctionPokerAction::Raise(_)
+            | AuctionPokerAction::Bid(_)
+            | AuctionPokerAction::DealCommunity(_)
+            | AuctionPokerAction::Auction(_)
+    )
+}
+
+impl PublicState {
+    /// The public part of `state`, reached by `line`. Both ranges are
+    /// uniform over the holdings the board leaves possible
+    pub fn from_state(state: &AuctionPokerState, line: Vec<AuctionPokerAction>) -> PublicState {
+        let board = state.community_cards();
+        let mut range = Range::uniform();
+        range.block(board);
+        let to_act = match state.active_player() {
+            ActivePlayer::Player(player_num, _) => Some(player_num as usize),
+            _ => None,
+        };
+        PublicState {
+            board,
+            pot: state.pot(),
+            stacks: state.stacks(),
+            pips: state.pips(),
+            auction: state.winner(),
+            line: line.into_iter().filter(is_public).collect(),
+            to_act,
+            ranges: [range.clone(), range],
+        }
+    }
+
+    /// Replays the actions of a hand, deals included, and keeps what is
+    /// public. Markers are played as they come up and may be left out of
+    /// `history`. Fails on the first action that isn't legal where it is
+    /// played
+    pub fn from_history(history: &[AuctionPokerAction]) -> Result<PublicState, String> {
+        let (state, line) = replay(history, false)?;
+        Ok(PublicState::from_state(&state, line))
+    }
+
+    /// A full state with this public history. Hole cards are made up (any
+    /// cards not on the board), so only the betting of the state means
+    /// anything
+    pub fn to_state(&self) -> Result<AuctionPokerState, String> {
+        replay(&self.line, true).map(|(state, _)| state)
+    }
+
+}
+
+/// Plays `history` from the start of a hand, returning the state and
+/// every action played, markers included. With `placeholders` the hole
+/// cards are dealt by us rather than taken from `history`
+fn replay(
+    history: &[AuctionPokerAction],
+    placeholders: bool,
+) -> Result<(AuctionPokerState, Vec<AuctionPokerAction>), String> {
+    let mut state = AuctionPokerState::new();
+    let mut line = vec![];
+    let board: Deck = history
+        .iter()
+        .filter_map(|action| match action {
+            AuctionPokerAction::DealCommunity(card) => Some(Card::from_index(*card)),
+            _ => None,
+        })
+        .collect();
+    let mut actions = history.iter().filter(|action| !is_marker(action));
+    loop {
+        let legal = match state.active_player() {
+            ActivePlayer::Marker(marker) => {
+                line.push(marker.clone());
+                state.update(marker);
+                continue;
+            }
+            ActivePlayer::Chance(distribution) => {
+                let placeholder = distribution.items().iter().find(|deal| match deal {
+                    AuctionPokerAction::DealHole(card, _) => !board.contains(Card::from_index(*card)),
+                    _ => false,
+                });
+                if let (true, Some(deal)) = (placeholders, placeholder) {
+                    state.update(deal.clone());
+                    continue;
+                }
+                distribution.items().clone()
+            }
+            ActivePlayer::Player(_, actions) => actions,
+            ActivePlayer::Terminal(_) => vec![],
+        };
+        let action = match actions.next() {
+            Some(action) => action,
+            None => return Ok((state, line)),
+        };
+        if !legal.contains(action) {
+            let played = line.iter().filter(|action| is_public(action)).count();
+            return Err(format!("{:?} is not legal after {} public actions", action, played));
+        }
+        line.push(action.clone());
+        state.update(action.clone());
+    }
+}
+
+pub fn is_marker(action: &AuctionPokerAction) -> bool {
+    matches!(
+        action,
+        AuctionPokerAction::BettingRoundStart
+            | AuctionPokerAction::BettingRoundEnd
+            | AuctionPokerAction::AuctionStart
+            | AuctionPokerAction::PlayerActionEnd(_)
+            | AuctionPokerAction::Auction(_)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::implementations::notation::parse_hand;
+
+    #[test]
+    fn test_holdings_round_trip() {
+        let mut seen = vec![false; NUM_HOLDINGS];
+        for first in 0..52u8 {
+            for second in (first + 1)..52 {
+                let holding = Holding::new(Card(second), Card(first));
+                assert_eq!(holding.cards(), (Card(first), Card(second)));
+                seen[holding.0 as usize] = true;
+            }
+        }
+        assert!(seen.iter().all(|seen| *seen));
+    }
+
+    #[test]
+    fn test_public_state_keeps_only_public_information() {
+        let history = parse_hand("AsKs|4h5h c / QsJsTs b10 b3 +9c").unwrap();
+        let public = PublicState::from_history(&history).unwrap();
+
+        assert_eq!(public.board.len(), 3);
+        assert_eq!(public.auction, Some(Winner::Player(1)));
+        assert!(public.line.iter().all(is_public));
+        assert!(!public.line.iter().any(|action| matches!(action, AuctionPokerAction::DealHole(..))));
+        // The call and the bids, the flop and the auction's result
+        assert_eq!(public.line.len(), 3 + 3 + 1);
+        assert_eq!(public.pot, 4 + 3);
+        assert_eq!(public.to_act, Some(1));
+
+        // Hole cards stay possible, board cards don't
+        let range = &public.ranges[1];
+        assert_eq!(range.holdings().count(), 49 * 48 / 2);
+        let weight = |first: &str, second: &str| range.weights()[Holding::new(Card::new(first), Card::new(second)).0 as usize];
+        assert!(weight("As", "Ks") > 0.0);
+        assert_eq!(weight("Qs", "2c"), 0.0);
+
+        let mut reweighed = range.clone();
+        reweighed.reweigh(|holding| match holding.deck().contains(Card::new("As")) {
+            true => 1.0,
+            false => 0.0,
+        });
+        assert_eq!(reweighed.holdings().count(), 48);
+        let normalized = reweighed.normalized().unwrap();
+        assert!((normalized.total() - 1.0).abs() < 1e-4);
+
+        let state = public.to_state().unwrap();
+        assert_eq!(state.pot(), public.pot);
+        assert_eq!(state.community_cards(), public.board);
+        assert!(matches!(state.active_player(), ActivePlayer::Player(1, _)));
+
+        let mut illegal = history.clone();
+        illegal.push(AuctionPokerAction::Fold);
+        illegal.push(AuctionPokerAction::Fold);
+        assert!(PublicState::from_history(&illegal).is_err());
+    }
+}
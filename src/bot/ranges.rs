@@ -0,0 +1,238 @@
+/// A blueprint's ranges and action frequencies as JSON, for the range
+/// explorer (a D3 page drawing the 13x13 hand grid, each cell colored by
+/// how it plays).
+///
+/// A public state is a line in the hand notation of
+/// implementations::notation without the hole cards, e.g. `c / QsJsTs b10
+/// b3 +9c` for player 1's first decision after the auction. Every holding
+/// the player to act could have there is played along the line with the
+/// opponent holding any other cards, which only the opponent's own
+/// decisions see. The holding's reach is how likely the blueprint was to
+/// play the player's own decisions of the line holding it, and its
+/// frequencies are the blueprint's policy at the end of it (uniform where
+/// there is none). Each cell of the grid averages its holdings weighted by
+/// reach, so it shows how the hands that actually get there play:
+///
+///     gtcogs ranges auction_poker.bp ranges.json "" "c / QsJsTs b10 b3 +9c"
+use crate::bot::decision::AuctionGame;
+use crate::bot::public_state::Holding;
+use crate::bot::streets::{blueprint_policy, round_of};
+use crate::game_logic::action::{Action, ActionIndex, Parsable};
+use crate::game_logic::state::ActivePlayer;
+use crate::game_logic::strategy::blueprint::BlueprintStrategy;
+use crate::implementations::auction::RelativeSize::*;
+use crate::implementations::auction::*;
+use crate::implementations::notation::parse_hand;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// One cell of the hand grid. Pairs are on the diagonal, suited hands
+/// above it and offsuit hands below, aces in the first row and column
+#[derive(Debug, Clone, Serialize)]
+pub struct RangeCell {
+    pub hand: String,
+    pub row: usize,
+    pub col: usize,
+    /// Holdings of the cell the public cards leave possible
+    pub combos: usize,
+    /// Mean reach of the combos, 0 where the line never gets to with them
+    pub reach: f32,
+    /// How often the cell plays each of RangeExport::actions (a plain
+    /// average of the combos where none of them gets here)
+    pub frequencies: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RangeExport {
+    pub line: String,
+    pub street: String,
+    pub board: Vec<String>,
+    pub player: usize,
+    pub pot: u32,
+    pub actions: Vec<String>,
+    pub cells: Vec<RangeCell>,
+}
+
+fn card_name(card: Card) -> String {
+    card.to_string().unwrap()
+}
+
+/// The name of an abstract action in the viewer
+fn label(action: &AuctionPokerAction) -> String {
+    match action {
+        AuctionPokerAction::Fold => "fold".to_string(),
+        AuctionPokerAction::Call => "call".to_string(),
+        AuctionPokerAction::Check => "check".to_string(),
+        AuctionPokerAction::Raise(DeciPercent(size)) => format!("raise {}%", size / 10),
+        AuctionPokerAction::Raise(Amount(chips)) => format!("raise {}", chips),
+        AuctionPokerAction::Bid(size) => format!("bid {}", size.to_amount(0)),
+        action => format!("{:?}", action),
+    }
+}
+
+/// The grid cell of `holding`, as (row, column, name)
+fn cell_of(holding: Holding) -> (usize, usize, String) {
+    let (first, second) = holding.cards();
+    let (high, low) = match first.value_index() <= second.value_index() {
+        true => (first.value_index(), second.value_index()),
+        false => (second.value_index(), first.value_index()),
+    };
+    let name = format!("{}{}", VALUE_CHARS[high], VALUE_CHARS[low]);
+    if high == low {
+        (high, low, name)
+    } else if first.suit_index() == second.suit_index() {
+        (high, low, name + "s")
+    } else {
+        (low, high, name + "o")
+    }
+}
+
+/// The cards written in `line`, the board and any auction cards
+fn line_cards(line: &str) -> Deck {
+    line.split_whitespace()
+        .map(|token| token.trim_start_matches('+'))
+        .filter(|token| token.len() % 2 == 0)
+        .filter_map(|token| (0..token.len()).step_by(2).map(|i| Card::parse(&token[i..i + 2])).collect::<Option<Vec<Card>>>())
+        .flatten()
+        .collect()
+}
+
+/// Plays `line` with `holding` as the hole cards of `player_num`, returning
+/// the game at its end and the reach of the holding
+fn play_line(blueprint: &BlueprintStrategy, line: &str, player_num: usize, holding: Holding) -> Result<(AuctionGame, f32), String> {
+    let (first, second) = holding.cards();
+    let mut others = holding.deck().union(line_cards(line)).complement().iter();
+    let ours = format!("{}{}", card_name(first), card_name(second));
+    let theirs = match (others.next(), others.next()) {
+        (Some(first), Some(second)) => format!("{}{}", card_name(first), card_name(second)),
+        _ => return Err("No cards left for the opponent".to_string()),
+    };
+    let holes = match player_num {
+        0 => format!("{}|{}", ours, theirs),
+        _ => format!("{}|{}", theirs, ours),
+    };
+    let mut game = AuctionGame::new();
+    let mut reach = 1.0;
+    for action in parse_hand(&format!("{} {}", holes, line))? {
+        if let ActivePlayer::Player(actor, legal) = game.active_player() {
+            if actor as usize == player_num {
+                let index = action.index();
+                reach *= blueprint_policy(blueprint, &game, player_num, &legal)
+                    .iter()
+                    .filter(|(played, _)| played.index() == index)
+                    .map(|(_, probability)| probability)
+                    .sum::<f32>();
+            }
+        }
+        game.play(&action);
+    }
+    Ok((game, reach))
+}
+
+/// The range and action frequencies of the player to act after `line`
+pub fn export_range(blueprint: &BlueprintStrategy, line: &str) -> Result<RangeExport, String> {
+    let blocked = line_cards(line);
+    let holdings: Vec<Holding> = Holding::all().filter(|holding| holding.deck().0 & blocked.0 == 0).collect();
+    let (game, _) = play_line(blueprint, line, 0, holdings[0])?;
+    let player_num = match game.active_player() {
+        ActivePlayer::Player(player_num, _) => player_num as usize,
+        _ => return Err(format!("Nobody has a decision after '{}'", line)),
+    };
+
+    let mut indices: Vec<ActionIndex> = vec![];
+    // By (row, column), frequencies weighted by reach until normalized
+    let mut cells: BTreeMap<(usize, usize), RangeCell> = BTreeMap::new();
+    for holding in holdings {
+        let (game, reach) = play_line(blueprint, line, player_num, holding)?;
+        let legal = match game.active_player() {
+            ActivePlayer::Player(_, legal) => legal,
+            _ => return Err(format!("Nobody has a decision after '{}'", line)),
+        };
+        let policy = blueprint_policy(blueprint, &game, player_num, &legal);
+        if indices.is_empty() {
+            indices = policy.iter().map(|(action, _)| action.index()).collect();
+        }
+
+        let (row, col, hand) = cell_of(holding);
+        let cell = cells.entry((row, col)).or_insert_with(|| RangeCell {
+            hand,
+            row,
+            col,
+            combos: 0,
+            reach: 0.0,
+            frequencies: vec![0.0; indices.len()],
+        });
+        cell.combos += 1;
+        cell.reach += reach;
+        for (action, probability) in policy {
+            if let Some(slot) = indices.iter().position(|index| *index == action.index()) {
+                cell.frequencies[slot] += probability * reach.max(f32::MIN_POSITIVE);
+            }
+        }
+    }
+    let cells: Vec<RangeCell> = cells
+        .into_values()
+        .map(|mut cell| {
+            let total: f32 = cell.frequencies.iter().sum();
+            if total > 0.0 {
+                cell.frequencies.iter_mut().for_each(|frequency| *frequency /= total);
+            }
+            cell.reach /= cell.combos as f32;
+            cell
+        })
+        .collect();
+
+    let state = game.state();
+    Ok(RangeExport {
+        line: line.to_string(),
+        street: format!("{:?}", round_of(&game)),
+        board: state.community_cards().iter().map(card_name).collect(),
+        player: player_num,
+        pot: state.pot(),
+        actions: indices.iter().map(|index| label(&AuctionPokerAction::from(*index))).collect(),
+        cells,
+    })
+}
+
+/// Writes the ranges after every one of `lines` to `file_name` as a JSON
+/// array, the range explorer's input
+pub fn write_ranges(blueprint: &BlueprintStrategy, lines: &[String], file_name: &str) -> Result<usize, String> {
+    let exports = lines.iter().map(|line| export_range(blueprint, line)).collect::<Result<Vec<_>, String>>()?;
+    let contents = serde_json::to_string(&exports).map_err(|error| format!("Could not write the ranges: {}", error))?;
+    std::fs::write(file_name, contents).map_err(|error| format!("Could not write {}: {}", file_name, error))?;
+    Ok(exports.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preflop_grid() {
+        let blueprint = BlueprintStrategy::empty();
+        let export = export_range(&blueprint, "").unwrap();
+        assert_eq!((export.street.as_str(), export.player, export.pot), ("PreFlop", 0, 3));
+        assert_eq!(export.cells.len(), 169);
+        assert_eq!(export.cells.iter().map(|cell| cell.combos).sum::<usize>(), 1326);
+
+        let aces = &export.cells[0];
+        assert_eq!((aces.hand.as_str(), aces.combos), ("AA", 6));
+        let suited = export.cells.iter().find(|cell| cell.hand == "AKs").unwrap();
+        let offsuit = export.cells.iter().find(|cell| cell.hand == "AKo").unwrap();
+        assert_eq!((suited.row, suited.col, suited.combos), (0, 1, 4));
+        assert_eq!((offsuit.row, offsuit.col, offsuit.combos), (1, 0, 12));
+
+        // Nothing has been played yet, so everything gets here and plays uniformly
+        for cell in &export.cells {
+            assert_eq!(cell.reach, 1.0);
+            assert_eq!(cell.frequencies.len(), export.actions.len());
+            assert!((cell.frequencies.iter().sum::<f32>() - 1.0).abs() < 1e-4);
+        }
+        assert!(export.actions.contains(&"fold".to_string()));
+
+        // A call leaves the big blind without a decision
+        assert!(export_range(&blueprint, "c").is_err());
+        let json = serde_json::to_string(&export).unwrap();
+        assert!(json.contains("\"hand\":\"AKs\""));
+    }
+}
@@ -0,0 +1,122 @@
+/// Swapping in a new blueprint without restarting the bot, e.g. after a
+/// quick exploitative retrain between rounds of a tournament.
+///
+/// Everything that reads the blueprint holds a SharedBlueprint and asks it
+/// for the current blueprint once per decision, so a swap never changes
+/// the blueprint under a decision that is already running. A
+/// BlueprintWatch polls the strategy file and swaps in its contents
+/// whenever it changes. Write the new file elsewhere and rename it over the
+/// old one; a file that can't be loaded (say, one still being written) is
+/// ignored until it changes again and the old blueprint stays in use
+use crate::game_logic::strategy::blueprint::BlueprintStrategy;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+#[derive(Clone)]
+pub struct SharedBlueprint(Arc<RwLock<Arc<BlueprintStrategy>>>);
+
+impl SharedBlueprint {
+    pub fn new(blueprint: BlueprintStrategy) -> Self {
+        SharedBlueprint(Arc::new(RwLock::new(Arc::new(blueprint))))
+    }
+
+    pub fn current(&self) -> Arc<BlueprintStrategy> {
+        self.0.read().unwrap().clone()
+    }
+
+    /// Decisions started from now on use `blueprint`. It keeps the
+    /// evaluator and the cutoff of the blueprint it replaces
+    pub fn swap(&self, blueprint: BlueprintStrategy) {
+        let mut current = self.0.write().unwrap();
+        let blueprint = blueprint
+            .with_evaluator(current.evaluator().clone())
+            .expect("The evaluator in use was validated")
+            .with_cutoff(current.cutoff());
+        *current = Arc::new(blueprint);
+    }
+}
+
+fn modified(file_name: &str) -> Option<SystemTime> {
+    std::fs::metadata(file_name).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Reloads `file_name` into a SharedBlueprint whenever it changes.
+/// Polls on a background thread that stops once this is dropped
+pub struct BlueprintWatch {
+    stopped: Arc<AtomicBool>,
+}
+
+impl BlueprintWatch {
+    pub fn spawn(file_name: &str, blueprint: SharedBlueprint, interval: Duration) -> Self {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let worker_stopped = stopped.clone();
+        let file_name = file_name.to_owned();
+        thread::spawn(move || {
+            // Whatever is there now is what we started with
+            let mut last_modified = modified(&file_name);
+            while !worker_stopped.load(Ordering::SeqCst) {
+                thread::sleep(interval);
+                let now_modified = modified(&file_name);
+                if now_modified.is_none() || now_modified == last_modified {
+                    continue;
+                }
+                last_modified = now_modified;
+                match BlueprintStrategy::try_load(&file_name) {
+                    Ok(loaded) => {
+                        println!("Reloaded blueprint from {}", file_name);
+                        blueprint.swap(loaded);
+                    }
+                    Err(error) => println!("[WARNING] Could not reload {}: {}", file_name, error),
+                }
+            }
+        });
+        BlueprintWatch { stopped }
+    }
+}
+
+impl Drop for BlueprintWatch {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_swapped_blueprint_is_seen_by_new_readers() {
+        let shared = SharedBlueprint::new(BlueprintStrategy::empty());
+        let before = shared.current();
+        shared.swap(BlueprintStrategy::empty());
+        assert!(!Arc::ptr_eq(&before, &shared.current()));
+        // Readers that already have a blueprint keep it
+        assert_eq!(Arc::strong_count(&before), 1);
+    }
+
+    #[test]
+    fn test_watch_reloads_a_changed_file() {
+        let file_name = std::env::temp_dir().join(format!("gtcogs_reload_test_{}.bp", std::process::id()));
+        let file_name = file_name.to_str().unwrap();
+        BlueprintStrategy::empty().save(file_name);
+        let shared = SharedBlueprint::new(BlueprintStrategy::empty());
+        let before = shared.current();
+        let _watch = BlueprintWatch::spawn(file_name, shared.clone(), Duration::from_millis(5));
+
+        // Broken files are skipped
+        thread::sleep(Duration::from_millis(20));
+        std::fs::write(file_name, b"half written").unwrap();
+        thread::sleep(Duration::from_millis(40));
+        assert!(Arc::ptr_eq(&before, &shared.current()));
+
+        BlueprintStrategy::empty().save(file_name);
+        let reloaded = (0..200).any(|_| {
+            thread::sleep(Duration::from_millis(5));
+            !Arc::ptr_eq(&before, &shared.current())
+        });
+        std::fs::remove_file(file_name).unwrap();
+        assert!(reloaded);
+    }
+}
@@ -0,0 +1,827 @@
+/// Re-solves the rest of the current street at decision time, for every
+/// holding at once, starting from a PublicState.
+///
+/// The tree is the betting of the current street only, with a few raise
+/// sizes (ResolverConfig::raise_sizes, plus all in). Once the betting of the
+/// street closes the hand isn't played out to showdown, the leaf is valued
+/// by a LeafValue instead. The default, EquityLeafValue, gives each holding
+/// its equity against the opponent's range reaching the leaf times the pot,
+/// which keeps flop and turn re-solves within the per-decision time budget.
+/// Raises beyond max_raises below the root are left out, bounding the depth
+/// within the street as well.
+///
+/// Solved with CFR+ over range vectors, alternating updates and averaging
+/// the policy linearly, until config.iterations or config.time_budget runs
+/// out. Only after the auction: bids are left to the blueprint
+///
+/// Re-solving every decision on its own can leave us more exploitable than
+/// the strategy we were following. To stay safe each re-solve hands the
+/// next one Constraints (see Resolver::carry): our range where play went
+/// and the opponent's value there for each of their holdings. The next
+/// re-solve lets the opponent take that value instead of playing on
+/// (the re-solving gadget of Burch et al.), so whatever it settles on,
+/// no holding of theirs gets more out of the subgame than it did before
+use crate::bot::public_state::*;
+use crate::constants::STACK_SIZE;
+use crate::eval::rank::{AuctionOutcome, HandRanker, RolloutKind, StreetRound};
+use crate::game_logic::state::{ActivePlayer, State};
+use crate::implementations::auction::RelativeSize::*;
+use crate::implementations::auction::*;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct ResolverConfig {
+    /// Raises considered besides all in, in DeciPercent of the pot. Each
+    /// becomes the legal raise closest to it
+    pub raise_sizes: Vec<u32>,
+    /// Raises allowed below the root
+    pub max_raises: usize,
+    pub iterations: usize,
+    pub time_budget: Duration,
+    /// Rollouts per holding for EquityLeafValue
+    pub strength_iterations: u32,
+}
+
+impl Default for ResolverConfig {
+    fn default() -> Self {
+        ResolverConfig {
+            raise_sizes: vec![500, 1000, 2000],
+            max_raises: 2,
+            iterations: 1000,
+            time_budget: Duration::from_millis(300),
+            strength_iterations: 200,
+        }
+    }
+}
+
+/// Where the tree stops before the end of the hand
+#[derive(Debug, Clone, PartialEq)]
+pub struct Leaf {
+    pub pot: u32,
+    /// Chips each player has put in over the whole hand
+    pub contributions: [u32; 2],
+    /// Whether the hand is over (the river betting closed) rather than
+    /// cut off at the end of the street
+    pub showdown: bool,
+}
+
+/// What a re-solve carries into the next one, street to street or
+/// decision to decision
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constraints {
+    /// The player we are not solving for
+    pub opponent: usize,
+    /// Our range where play went, to replace ours in the next PublicState
+    pub range: Range,
+    /// Upper bound on the opponent's value of each holding, per unit of
+    /// our range it doesn't block
+    pub values: Vec<f32>,
+}
+
+/// Values the leaves of a depth limited tree
+pub trait LeafValue: Send + Sync {
+    /// Value in chips of every holding of `player` at `leaf`, summed over
+    /// the opponent's holdings weighted by `opponent`, their reach of it
+    fn values(&self, leaf: &Leaf, player: usize, opponent: &[f32]) -> Vec<f32>;
+}
+
+/// Weight of a set of holdings, in total and on each card, so the weight
+/// of the holdings that don't share a card with a given one is quick to
+/// work out
+#[derive(Clone)]
+struct Blockers {
+    total: f32,
+    cards: [f32; 52],
+}
+
+impl Blockers {
+    fn new() -> Blockers {
+        Blockers { total: 0.0, cards: [0.0; 52] }
+    }
+
+    fn of(weights: &[f32]) -> Blockers {
+        let mut blockers = Blockers::new();
+        for (holding, weight) in Holding::all().zip(weights) {
+            blockers.add(holding, *weight);
+        }
+        blockers
+    }
+
+    fn add(&mut self, holding: Holding, weight: f32) {
+        let (low, high) = holding.cards();
+        self.total += weight;
+        self.cards[low.as_u8() as usize] += weight;
+        self.cards[high.as_u8() as usize] += weight;
+    }
+
+    /// Weight of the holdings that don't share a card with `holding`,
+    /// where `own` is the weight of `holding` itself if it was added
+    fn compatible(&self, holding: Holding, own: f32) -> f32 {
+        let (low, high) = holding.cards();
+        self.total - self.cards[low.as_u8() as usize] - self.cards[high.as_u8() as usize] + own
+    }
+}
+
+/// Equity of every holding against the opponent's range times the pot,
+/// less what the player put in.
+///
+/// Equity is estimated from a strength per holding (its rollout equity
+/// against a random hand on the current board) by counting the opponent's
+/// holdings it is stronger than, taking card removal into account
+pub struct EquityLeafValue {
+    strengths: [Vec<f64>; 2],
+    /// Holdings of each player from weakest to strongest
+    order: [Vec<Holding>; 2],
+}
+
+impl EquityLeafValue {
+    pub fn new(public: &PublicState, iterations: u32) -> Result<EquityLeafValue, String> {
+        let street = match public.board.len() {
+            3 => StreetRound::Flop,
+            4 => StreetRound::Turn,
+            5 => StreetRound::River,
+            cards => return Err(format!("No street has {} board cards", cards)),
+        };
+        let strengths = [0, 1].map(|player| {
+            let outcome = match &public.auction {
+                Some(Winner::Player(winner)) if *winner == player => Some(AuctionOutcome::Won),
+                Some(Winner::Player(_)) => Some(AuctionOutcome::Lost),
+                Some(Winner::Tie) => Some(AuctionOutcome::Tie),
+                None => None,
+            };
+            outcome.map(|outcome| rollout_strengths(public.board, street, outcome, iterations))
+        });
+        match strengths {
+            [Some(first), Some(second)] => Ok(EquityLeafValue::from_strengths([first, second])),
+            _ => Err("Leaves can only be valued after the auction".to_string()),
+        }
+    }
+
+    /// From any strength per holding, higher is better
+    pub fn from_strengths(strengths: [Vec<f64>; 2]) -> EquityLeafValue {
+        let order = [0, 1].map(|player| {
+            let mut order: Vec<Holding> = Holding::all().collect();
+            order.sort_by(|a, b| strengths[player][a.0 as usize].total_cmp(&strengths[player][b.0 as usize]));
+            order
+        });
+        EquityLeafValue { strengths, order }
+    }
+}
+
+/// Rollout equity of every holding on `board`, 0 for the holdings the
+/// board blocks. A player who gets a third card gets one we haven't seen,
+/// a different one for each holding
+fn rollout_strengths(board: Deck, street: StreetRound, outcome: AuctionOutcome, iterations: u32) -> Vec<f64> {
+    let holdings: Vec<Holding> = Holding::all()
+        .filter(|holding| {
+            let (low, high) = holding.cards();
+            !board.contains(low) && !board.contains(high)
+        })
+        .collect();
+    let ranker = HandRanker::new();
+    let board_cards = board.as_u8();
+    let mut strengths = vec![0.0; NUM_HOLDINGS];
+    for holding in holdings {
+        let mut hand = holding.deck().as_u8();
+        if outcome != AuctionOutcome::Lost {
+            let unseen = board.union(holding.deck()).complement().as_u8();
+            hand.push(unseen[holding.0 as usize % unseen.len()]);
+        }
+        strengths[holding.0 as usize] = ranker.rollout(RolloutKind::Street(street, outcome), &hand, &board_cards, iterations);
+    }
+    strengths
+}
+
+impl LeafValue for EquityLeafValue {
+    fn values(&self, leaf: &Leaf, player: usize, opponent: &[f32]) -> Vec<f32> {
+        let own = &self.strengths[player];
+        let theirs = &self.strengths[player ^ 1];
+        let their_order = &self.order[player ^ 1];
+        let everyone = Blockers::of(opponent);
+        let mut weaker = Blockers::new();
+        let mut no_stronger = Blockers::new();
+        let (mut next_weaker, mut next_no_stronger) = (0, 0);
+
+        let mut values = vec![0.0; NUM_HOLDINGS];
+        for holding in &self.order[player] {
+            let strength = own[holding.0 as usize];
+            while next_weaker < their_order.len() && theirs[their_order[next_weaker].0 as usize] < strength {
+                let other = their_order[next_weaker];
+                weaker.add(other, opponent[other.0 as usize]);
+                next_weaker += 1;
+            }
+            while next_no_stronger < their_order.len() && theirs[their_order[next_no_stronger].0 as usize] <= strength {
+                let other = their_order[next_no_stronger];
+                no_stronger.add(other, opponent[other.0 as usize]);
+                next_no_stronger += 1;
+            }
+            let same = opponent[holding.0 as usize];
+            let their_strength = theirs[holding.0 as usize];
+            let wins = weaker.compatible(*holding, if their_strength < strength { same } else { 0.0 });
+            let ties = no_stronger.compatible(*holding, if their_strength <= strength { same } else { 0.0 }) - wins;
+            let all = everyone.compatible(*holding, same);
+            values[holding.0 as usize] =
+                (wins + ties / 2.0) * leaf.pot as f32 - leaf.contributions[player] as f32 * all;
+        }
+        values
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Decision {
+        player: usize,
+        actions: Vec<AuctionPokerAction>,
+        children: Vec<usize>,
+        /// Index of the node's regrets and policy
+        table: usize,
+        pot: u32,
+    },
+    Fold {
+        folder: usize,
+        contributions: [u32; 2],
+    },
+    Leaf(Leaf),
+}
+
+/// The actions of the tree out of `legal`: fold, call and check, and with
+/// `raises` the legal raise closest to each size plus all in
+fn abstract_actions(legal: &[AuctionPokerAction], pot: u32, sizes: &[u32], raises: bool) -> Vec<AuctionPokerAction> {
+    let mut actions: Vec<AuctionPokerAction> = legal
+        .iter()
+        .filter(|action| matches!(action, AuctionPokerAction::Fold | AuctionPokerAction::Call | AuctionPokerAction::Check))
+        .cloned()
+        .collect();
+    let legal_raises: Vec<(u32, &AuctionPokerAction)> = legal
+        .iter()
+        .filter_map(|action| match action {
+            AuctionPokerAction::Raise(size) => Some((size.to_amount(pot), action)),
+            _ => None,
+        })
+        .collect();
+    if !raises || legal_raises.is_empty() {
+        return actions;
+    }
+    let mut chosen: Vec<&AuctionPokerAction> = sizes
+        .iter()
+        .map(|size| {
+            let amount = DeciPercent(*size).to_amount(pot);
+            legal_raises.iter().min_by_key(|(legal, _)| legal.abs_diff(amount)).unwrap().1
+        })
+        .collect();
+    chosen.push(legal_raises.iter().max_by_key(|(amount, _)| *amount).unwrap().1);
+    for raise in chosen {
+        if !actions.contains(raise) {
+            actions.push(raise.clone());
+        }
+    }
+    actions
+}
+
+/// Position in `actions` of the one most like `action`: itself, or for a
+/// raise the raise of the closest size
+fn closest_action(actions: &[AuctionPokerAction], action: &AuctionPokerAction, pot: u32) -> Option<usize> {
+    if let Some(position) = actions.iter().position(|candidate| candidate == action) {
+        return Some(position);
+    }
+    let size = match action {
+        AuctionPokerAction::Raise(size) => size.to_amount(pot),
+        _ => return None,
+    };
+    actions
+        .iter()
+        .enumerate()
+        .filter_map(|(position, candidate)| match candidate {
+            AuctionPokerAction::Raise(candidate) => Some((position, candidate.to_amount(pot).abs_diff(size))),
+            _ => None,
+        })
+        .min_by_key(|(_, distance)| *distance)
+        .map(|(position, _)| position)
+}
+
+fn contributions(state: &AuctionPokerState) -> [u32; 2] {
+    state.stacks().map(|stack| STACK_SIZE - stack)
+}
+
+/// Regret matching over the `actions` rows of `regrets`
+fn current_policy(regrets: &[f32], actions: usize) -> Vec<f32> {
+    let mut policy = vec![1.0 / actions as f32; regrets.len()];
+    for holding in 0..NUM_HOLDINGS {
+        let total: f32 = (0..actions).map(|action| regrets[action * NUM_HOLDINGS + holding].max(0.0)).sum();
+        if total > 0.0 {
+            for action in 0..actions {
+                policy[action * NUM_HOLDINGS + holding] = regrets[action * NUM_HOLDINGS + holding].max(0.0) / total;
+            }
+        }
+    }
+    policy
+}
+
+/// The average policy out of the `actions` rows of `policy` sums
+fn normalize_policy(sums: &[f32], actions: usize) -> Vec<f32> {
+    let mut policy = vec![1.0 / actions as f32; sums.len()];
+    for holding in 0..NUM_HOLDINGS {
+        let total: f32 = (0..actions).map(|action| sums[action * NUM_HOLDINGS + holding]).sum();
+        if total > 0.0 {
+            for action in 0..actions {
+                policy[action * NUM_HOLDINGS + holding] = sums[action * NUM_HOLDINGS + holding] / total;
+            }
+        }
+    }
+    policy
+}
+
+fn scaled(reach: &[f32], policy: &[f32]) -> Vec<f32> {
+    reach.iter().zip(policy).map(|(reach, probability)| reach * probability).collect()
+}
+
+pub struct Resolver {
+    public: PublicState,
+    config: ResolverConfig,
+    nodes: Vec<Node>,
+    /// Regrets and policy sums of every decision node, one row of
+    /// NUM_HOLDINGS per action
+    regrets: Vec<Vec<f32>>,
+    policies: Vec<Vec<f32>>,
+    iterations: usize,
+    /// Constraints of the last re-solve with the regrets of the
+    /// opponent's choice at the root, follow first, then take the value
+    gadget: Option<(Constraints, Vec<f32>)>,
+}
+
+impl Resolver {
+    /// Builds the tree of the rest of the street. The root must be a
+    /// betting decision
+    pub fn new(public: PublicState, config: ResolverConfig) -> Result<Resolver, String> {
+        let state = public.to_state()?;
+        match state.active_player() {
+            ActivePlayer::Player(_, actions) if !actions.iter().any(|action| matches!(action, AuctionPokerAction::Bid(_))) => {}
+            _ => return Err("Resolving starts at a betting decision".to_string()),
+        }
+        let mut resolver = Resolver {
+            public,
+            config,
+            nodes: vec![],
+            regrets: vec![],
+            policies: vec![],
+            iterations: 0,
+            gadget: None,
+        };
+        resolver.build(state, None, 0);
+        Ok(resolver)
+    }
+
+    fn build(&mut self, mut state: AuctionPokerState, last: Option<(usize, AuctionPokerAction)>, raises: usize) -> usize {
+        while let ActivePlayer::Marker(marker) = state.active_player() {
+            state.update(marker);
+        }
+        let node = match state.active_player() {
+            ActivePlayer::Player(player, legal) => {
+                let allow_raises = raises < self.config.max_raises;
+                let actions = abstract_actions(&legal, state.pot(), &self.config.raise_sizes, allow_raises);
+                let index = self.nodes.len();
+                let table = self.regrets.len();
+                self.regrets.push(vec![0.0; actions.len() * NUM_HOLDINGS]);
+                self.policies.push(vec![0.0; actions.len() * NUM_HOLDINGS]);
+                self.nodes.push(Node::Decision {
+                    player: player as usize,
+                    actions: actions.clone(),
+                    children: vec![],
+                    table,
+                    pot: state.pot(),
+                });
+                let children: Vec<usize> = actions
+                    .iter()
+                    .map(|action| {
+                        let mut next = state.clone();
+                        next.update(action.clone());
+                        let raised = matches!(action, AuctionPokerAction::Raise(_)) as usize;
+                        self.build(next, Some((player as usize, action.clone())), raises + raised)
+                    })
+                    .collect();
+                if let Node::Decision { children: slot, .. } = &mut self.nodes[index] {
+                    *slot = children;
+                }
+                return index;
+            }
+            ActivePlayer::Terminal(_) => match last {
+                Some((folder, AuctionPokerAction::Fold)) => Node::Fold {
+                    folder,
+                    contributions: contributions(&state),
+                },
+                _ => Node::Leaf(Leaf {
+                    pot: state.pot(),
+                    contributions: contributions(&state),
+                    showdown: true,
+                }),
+            },
+            _ => Node::Leaf(Leaf {
+                pot: state.pot(),
+                contributions: contributions(&state),
+                showdown: false,
+            }),
+        };
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    /// Keeps the opponent's value of every holding under `constraints`,
+    /// as carried from the previous re-solve. The public state should
+    /// already have our range from them
+    pub fn with_constraints(mut self, constraints: Constraints) -> Self {
+        self.gadget = Some((constraints, vec![0.0; 2 * NUM_HOLDINGS]));
+        self
+    }
+
+    /// Runs CFR+ until the iterations or the time budget run out,
+    /// returning the number of iterations run
+    pub fn solve(&mut self, leaf_value: &dyn LeafValue) -> usize {
+        let deadline = Instant::now() + self.config.time_budget;
+        let ranges = [self.public.ranges[0].weights().to_vec(), self.public.ranges[1].weights().to_vec()];
+        let start = self.iterations;
+        while self.iterations - start < self.config.iterations && Instant::now() < deadline {
+            self.iterations += 1;
+            for player in 0..2 {
+                let (opponent, follow) = match &self.gadget {
+                    Some((constraints, regrets)) => (constraints.opponent, current_policy(regrets, 2)),
+                    None => {
+                        self.train(0, player, &ranges[player], &ranges[player ^ 1], leaf_value);
+                        continue;
+                    }
+                };
+                // The opponent only reaches the subgame with the holdings
+                // that follow rather than take their value
+                let following = scaled(&ranges[opponent], &follow[..NUM_HOLDINGS]);
+                if player != opponent {
+                    self.train(0, player, &ranges[player], &following, leaf_value);
+                    continue;
+                }
+                let values = self.train(0, player, &following, &ranges[player ^ 1], leaf_value);
+                let ours = Blockers::of(&ranges[player ^ 1]);
+                let (constraints, regrets) = self.gadget.as_mut().unwrap();
+                for holding in Holding::all() {
+                    let index = holding.0 as usize;
+                    let take = constraints.values[index] * ours.compatible(holding, ranges[player ^ 1][index]);
+                    let value = follow[index] * values[index] + follow[NUM_HOLDINGS + index] * take;
+                    regrets[index] = (regrets[index] + values[index] - value).max(0.0);
+                    regrets[NUM_HOLDINGS + index] = (regrets[NUM_HOLDINGS + index] + take - value).max(0.0);
+                }
+            }
+        }
+        self.iterations - start
+    }
+
+    /// Counterfactual values of `player`'s holdings below `node`, updating
+    /// their regrets and policy sums on the way
+    fn train(&mut self, node: usize, player: usize, own: &[f32], opponent: &[f32], leaf_value: &dyn LeafValue) -> Vec<f32> {
+        let (actor, children, table) = match &self.nodes[node] {
+            Node::Decision { player, children, table, .. } => (*player, children.clone(), *table),
+            terminal => return terminal_values(terminal, player, opponent, leaf_value),
+        };
+        let policy = current_policy(&self.regrets[table], children.len());
+        let mut values = vec![0.0; NUM_HOLDINGS];
+        if actor != player {
+            for (action, child) in children.iter().enumerate() {
+                let reach = scaled(opponent, &policy[action * NUM_HOLDINGS..(action + 1) * NUM_HOLDINGS]);
+                let child_values = self.train(*child, player, own, &reach, leaf_value);
+                values.iter_mut().zip(child_values).for_each(|(value, child)| *value += child);
+            }
+            return values;
+        }
+
+        let mut action_values = vec![];
+        for (action, child) in children.iter().enumerate() {
+            let row = &policy[action * NUM_HOLDINGS..(action + 1) * NUM_HOLDINGS];
+            let child_values = self.train(*child, player, &scaled(own, row), opponent, leaf_value);
+            for holding in 0..NUM_HOLDINGS {
+                values[holding] += row[holding] * child_values[holding];
+            }
+            action_values.push(child_values);
+        }
+        let weight = self.iterations as f32;
+        for (action, child_values) in action_values.iter().enumerate() {
+            for holding in 0..NUM_HOLDINGS {
+                let cell = action * NUM_HOLDINGS + holding;
+                let regret = &mut self.regrets[table][cell];
+                *regret = (*regret + child_values[holding] - values[holding]).max(0.0);
+                self.policies[table][cell] += weight * own[holding] * policy[cell];
+            }
+        }
+        values
+    }
+
+    /// The node reached by the public actions `line` from the root, with
+    /// our range there. Actions that aren't in the tree count as the
+    /// closest one that is
+    fn follow(&self, us: usize, line: &[AuctionPokerAction]) -> Result<(usize, Vec<f32>), String> {
+        let mut ours = self.public.ranges[us].weights().to_vec();
+        let mut node = 0;
+        for action in line {
+            let (actor, actions, children, table, pot) = match &self.nodes[node] {
+                Node::Decision { player, actions, children, table, pot } => (*player, actions, children, *table, *pot),
+                _ => return Err(format!("{:?} comes after the end of the street", action)),
+            };
+            let taken = closest_action(actions, action, pot)
+                .ok_or_else(|| format!("{:?} is not like anything in the tree", action))?;
+            if actor == us {
+                let policy = normalize_policy(&self.policies[table], actions.len());
+                ours = scaled(&ours, &policy[taken * NUM_HOLDINGS..(taken + 1) * NUM_HOLDINGS]);
+            }
+            node = children[taken];
+        }
+        Ok((node, ours))
+    }
+
+    /// The actions of `us` after `line` from the root and the probability
+    /// of each holding `holding`, averaged over the iterations so far
+    pub fn policy_after(
+        &self,
+        us: usize,
+        line: &[AuctionPokerAction],
+        holding: Holding,
+    ) -> Result<(Vec<AuctionPokerAction>, Vec<f32>), String> {
+        match &self.nodes[self.follow(us, line)?.0] {
+            Node::Decision { player, actions, table, .. } if *player == us => {
+                let policy = normalize_policy(&self.policies[*table], actions.len());
+                let probabilities = (0..actions.len()).map(|action| policy[action * NUM_HOLDINGS + holding.0 as usize]).collect();
+                Ok((actions.clone(), probabilities))
+            }
+            _ => Err(format!("Player {} has no decision after {:?}", us, line)),
+        }
+    }
+
+    /// The Constraints for the next re-solve of `us`, after the public
+    /// actions `line` from the root, see follow. `line` may run to a leaf
+    /// (the end of the street), a new street's deal isn't part of it
+    pub fn carry(&self, us: usize, line: &[AuctionPokerAction], leaf_value: &dyn LeafValue) -> Result<Constraints, String> {
+        let (node, ours) = self.follow(us, line)?;
+        // What the opponent could get against what we actually play
+        let values = self.best_response(node, us ^ 1, &ours, leaf_value);
+        let blockers = Blockers::of(&ours);
+        let values = Holding::all()
+            .map(|holding| {
+                let reach = blockers.compatible(holding, ours[holding.0 as usize]);
+                match reach > 0.0 {
+                    true => values[holding.0 as usize] / reach,
+                    false => 0.0,
+                }
+            })
+            .collect();
+        Ok(Constraints {
+            opponent: us ^ 1,
+            range: Range::from_weights(ours),
+            values,
+        })
+    }
+
+    /// Value of every holding of `player` at the root when they play the
+    /// best response to the average policy of the other, who reaches the
+    /// root with `opponent`
+    pub fn best_response_values(&self, player: usize, opponent: &[f32], leaf_value: &dyn LeafValue) -> Vec<f32> {
+        self.best_response(0, player, opponent, leaf_value)
+    }
+
+    fn best_response(&self, node: usize, player: usize, opponent: &[f32], leaf_value: &dyn LeafValue) -> Vec<f32> {
+        let (actor, children, table) = match &self.nodes[node] {
+            Node::Decision { player, children, table, .. } => (*player, children, *table),
+            terminal => return terminal_values(terminal, player, opponent, leaf_value),
+        };
+        if actor == player {
+            let mut values = vec![f32::MIN; NUM_HOLDINGS];
+            for child in children {
+                let child_values = self.best_response(*child, player, opponent, leaf_value);
+                values.iter_mut().zip(child_values).for_each(|(value, child)| *value = value.max(child));
+            }
+            return values;
+        }
+        let policy = normalize_policy(&self.policies[table], children.len());
+        let mut values = vec![0.0; NUM_HOLDINGS];
+        for (action, child) in children.iter().enumerate() {
+            let row = &policy[action * NUM_HOLDINGS..(action + 1) * NUM_HOLDINGS];
+            let child_values = self.best_response(*child, player, &scaled(opponent, row), leaf_value);
+            values.iter_mut().zip(child_values).for_each(|(value, child)| *value += child);
+        }
+        values
+    }
+
+    /// Whether the re-solve keeps to Constraints from an earlier one
+    pub fn constrained(&self) -> bool {
+        self.gadget.is_some()
+    }
+
+    /// How much more than the carried values the opponent's best response
+    /// gets out of the re-solve, on average over the holdings of their
+    /// range. 0 if the re-solve isn't constrained
+    pub fn constraint_excess(&self, leaf_value: &dyn LeafValue) -> f32 {
+        let constraints = match &self.gadget {
+            Some((constraints, _)) => constraints,
+            None => return 0.0,
+        };
+        let ours = self.public.ranges[constraints.opponent ^ 1].weights();
+        let blockers = Blockers::of(ours);
+        let best = self.best_response_values(constraints.opponent, ours, leaf_value);
+        let (mut total, mut count) = (0.0, 0);
+        for (holding, _) in self.public.ranges[constraints.opponent].holdings() {
+            let index = holding.0 as usize;
+            let reach = blockers.compatible(holding, ours[index]);
+            if reach > 0.0 {
+                total += (best[index] / reach - constraints.values[index]).max(0.0);
+                count += 1;
+            }
+        }
+        total / count.max(1) as f32
+    }
+}
+
+fn terminal_values(node: &Node, player: usize, opponent: &[f32], leaf_value: &dyn LeafValue) -> Vec<f32> {
+    match node {
+        Node::Fold { folder, contributions } => {
+            let utility = match *folder == player {
+                true => -(contributions[player] as f32),
+                false => contributions[player ^ 1] as f32,
+            };
+            let blockers = Blockers::of(opponent);
+            Holding::all()
+                .map(|holding| utility * blockers.compatible(holding, opponent[holding.0 as usize]))
+                .collect()
+        }
+        Node::Leaf(leaf) => leaf_value.values(leaf, player, opponent),
+        Node::Decision { .. } => unreachable!("Decisions are not terminal"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root_actions(resolver: &Resolver) -> &[AuctionPokerAction] {
+        match &resolver.nodes[0] {
+            Node::Decision { actions, .. } => actions,
+            _ => unreachable!("The root is a decision"),
+        }
+    }
+
+    /// Probability of each root action holding `holding`, averaged over
+    /// the iterations so far
+    fn root_policy(resolver: &Resolver, holding: Holding) -> Vec<f32> {
+        let actions = root_actions(resolver).len();
+        let policy = normalize_policy(&resolver.policies[0], actions);
+        (0..actions).map(|action| policy[action * NUM_HOLDINGS + holding.0 as usize]).collect()
+    }
+
+    /// Counterfactual value of every holding of `player` at the root when
+    /// both players follow the average policy
+    fn root_values(resolver: &Resolver, player: usize, leaf_value: &dyn LeafValue) -> Vec<f32> {
+        let opponent = resolver.public.ranges[player ^ 1].weights().to_vec();
+        average_values(resolver, 0, player, &opponent, leaf_value)
+    }
+
+    fn average_values(resolver: &Resolver, node: usize, player: usize, opponent: &[f32], leaf_value: &dyn LeafValue) -> Vec<f32> {
+        let (actor, children, table) = match &resolver.nodes[node] {
+            Node::Decision { player, children, table, .. } => (*player, children, *table),
+            terminal => return terminal_values(terminal, player, opponent, leaf_value),
+        };
+        let policy = normalize_policy(&resolver.policies[table], children.len());
+        let mut values = vec![0.0; NUM_HOLDINGS];
+        for (action, child) in children.iter().enumerate() {
+            let row = &policy[action * NUM_HOLDINGS..(action + 1) * NUM_HOLDINGS];
+            if actor == player {
+                let child_values = average_values(resolver, *child, player, opponent, leaf_value);
+                for holding in 0..NUM_HOLDINGS {
+                    values[holding] += row[holding] * child_values[holding];
+                }
+            } else {
+                let child_values = average_values(resolver, *child, player, &scaled(opponent, row), leaf_value);
+                values.iter_mut().zip(child_values).for_each(|(value, child)| *value += child);
+            }
+        }
+        values
+    }
+
+    fn deal_hole(name: &str, player_num: usize) -> AuctionPokerAction {
+        AuctionPokerAction::DealHole(Card::new(name).as_u8() as CardIndex, player_num)
+    }
+
+    fn deal_board(name: &str) -> AuctionPokerAction {
+        AuctionPokerAction::DealCommunity(Card::new(name).as_u8() as CardIndex)
+    }
+
+    /// Up to player 1's first decision on the flop, after they won the auction
+    fn flop() -> Vec<AuctionPokerAction> {
+        vec![
+            deal_hole("As", 0),
+            deal_hole("Ks", 0),
+            deal_hole("4h", 1),
+            deal_hole("5h", 1),
+            AuctionPokerAction::Call,
+            deal_board("Qs"),
+            deal_board("Js"),
+            deal_board("2c"),
+            AuctionPokerAction::Bid(Amount(10)),
+            AuctionPokerAction::Bid(Amount(3)),
+            deal_hole("9c", 1),
+        ]
+    }
+
+    /// Player 0 facing a pot sized raise on the flop
+    fn facing_raise() -> PublicState {
+        let mut history = flop();
+        let state = PublicState::from_history(&history).unwrap().to_state().unwrap();
+        let legal = state.active_player().actions().to_vec();
+        history.push(abstract_actions(&legal, state.pot(), &[1000], true)[1].clone());
+        PublicState::from_history(&history).unwrap()
+    }
+
+    /// Holdings are only as strong as their index
+    fn by_index() -> EquityLeafValue {
+        let strength: Vec<f64> = (0..NUM_HOLDINGS).map(|holding| holding as f64).collect();
+        EquityLeafValue::from_strengths([strength.clone(), strength])
+    }
+
+    #[test]
+    fn test_resolver_folds_the_bottom_of_the_range() {
+        let public = facing_raise();
+        assert_eq!(public.to_act, Some(0));
+        let config = ResolverConfig {
+            iterations: 200,
+            time_budget: Duration::from_secs(60),
+            ..Default::default()
+        };
+        let mut resolver = Resolver::new(public.clone(), config).unwrap();
+        let actions = root_actions(&resolver).to_vec();
+        assert!(actions.contains(&AuctionPokerAction::Fold) && actions.contains(&AuctionPokerAction::Call));
+
+        let leaf_value = by_index();
+        assert_eq!(resolver.solve(&leaf_value), 200);
+
+        let (weakest, _) = public.ranges[0].holdings().next().unwrap();
+        let (strongest, _) = public.ranges[0].holdings().last().unwrap();
+        let fold = actions.iter().position(|action| *action == AuctionPokerAction::Fold).unwrap();
+        assert!(root_policy(&resolver, weakest)[fold] > 0.9);
+        assert!(root_policy(&resolver, strongest)[fold] < 0.01);
+        assert!((root_policy(&resolver, strongest).iter().sum::<f32>() - 1.0).abs() < 1e-4);
+
+        // Folding the weakest holding loses exactly what is in already
+        let values = root_values(&resolver, 0, &leaf_value);
+        let opponent = Blockers::of(public.ranges[1].weights());
+        let in_already = (STACK_SIZE - public.stacks[0]) as f32;
+        let fold_value = -in_already * opponent.compatible(weakest, 0.0);
+        assert!((values[weakest.0 as usize] - fold_value).abs() < 0.05 * fold_value.abs());
+        assert!(values[strongest.0 as usize] > 0.0);
+    }
+
+    #[test]
+    fn test_resolving_keeps_the_opponent_under_carried_values() {
+        let config = ResolverConfig {
+            iterations: 300,
+            time_budget: Duration::from_secs(60),
+            ..Default::default()
+        };
+        let leaf_value = by_index();
+        let public = PublicState::from_history(&flop()).unwrap();
+        let mut first = Resolver::new(public, config.clone()).unwrap();
+        first.solve(&leaf_value);
+
+        // We (player 1) check, and re-solve the opponent's turn
+        let check = AuctionPokerAction::Check;
+        let constraints = first.carry(1, std::slice::from_ref(&check), &leaf_value).unwrap();
+        assert_eq!(constraints.opponent, 0);
+        assert!(first.carry(1, &[check.clone(), check.clone(), check.clone()], &leaf_value).is_err());
+        let mut history = flop();
+        history.push(check);
+        let mut public = PublicState::from_history(&history).unwrap();
+        public.ranges[1] = constraints.range.clone();
+        // The public range of the opponent is way off, re-solving without
+        // the constraints plays right into their hands
+        public.ranges[0].reweigh(|holding| if holding.0 > 1000 { 1.0 } else { 0.01 });
+
+        let mut safe = Resolver::new(public.clone(), config.clone()).unwrap().with_constraints(constraints.clone());
+        safe.solve(&leaf_value);
+        assert!(safe.constrained());
+        let mut unconstrained = Resolver::new(public.clone(), config).unwrap();
+        unconstrained.solve(&leaf_value);
+        assert_eq!(unconstrained.constraint_excess(&leaf_value), 0.0);
+        // Measured against the same constraints, which it never kept to
+        let unconstrained = unconstrained.with_constraints(constraints);
+        let excess = safe.constraint_excess(&leaf_value);
+        assert!(excess < 0.1 * public.pot as f32);
+        assert!(excess < unconstrained.constraint_excess(&leaf_value) / 4.0);
+    }
+
+    #[test]
+    fn test_equity_leaf_value_ranks_holdings() {
+        let public = facing_raise();
+        let leaf_value = EquityLeafValue::new(&public, 50).unwrap();
+        let nuts = Holding::new(Card::new("Ks"), Card::new("Ts")).0 as usize;
+        let air = Holding::new(Card::new("7d"), Card::new("3h")).0 as usize;
+        for player in 0..2 {
+            assert!(leaf_value.strengths[player][nuts] > leaf_value.strengths[player][air]);
+        }
+        let blocked = Holding::new(Card::new("Qs"), Card::new("3h")).0 as usize;
+        assert_eq!(leaf_value.strengths[0][blocked], 0.0);
+
+        let mut before_auction = public.clone();
+        before_auction.auction = None;
+        assert!(EquityLeafValue::new(&before_auction, 50).is_err());
+    }
+}
@@ -0,0 +1,303 @@
+/// Talks to the competition engine on behalf of a Bot.
+///
+/// The engine sends one packet per line, each a space separated list of
+/// clauses, and expects exactly one reply per packet:
+///
+///     T<seconds>      time left in our time bank
+///     P<seat>         our seat for this hand (0 is the small blind)
+///     H<card,...>     our hole cards (a third card arrives after winning the auction)
+///     F C K           the opponent folded, called, checked
+///     R<amount>       the opponent raised *to* amount
+///     A<amount>       the opponent's bid, once both bids are in
+///     B<card,...>     the board so far
+///     O<card,...>     the opponent's cards at showdown
+///     D<delta>        chips won (or lost) this hand, the hand is over
+///     Q               the match is over
+///
+/// Replies use the same letters (F, C, K, R#, A#). Packets that don't need
+/// a decision (e.g. the end of a hand) are acknowledged with a check.
+///
+/// A panic anywhere in a bot must not take the whole match down with it,
+/// so every packet is handled in isolation: if the bot panics we report it,
+/// reply with the bot's safe action and ignore the bot until the next hand
+/// (its view of the hand can't be trusted anymore)
+///
+/// The runner also keeps the MatchContext, every hand's result is recorded
+/// there (even if the bot is broken) and it is passed along with each decision
+use crate::bot::match_context::MatchContext;
+use crate::implementations::auction::Card;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::net::TcpStream;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Clause {
+    Time(f32),
+    Seat(usize),
+    Hand(Vec<Card>),
+    Fold,
+    Call,
+    Check,
+    Raise(u32),
+    Bid(u32),
+    Board(Vec<Card>),
+    Opponent(Vec<Card>),
+    Delta(i32),
+    Quit,
+}
+
+fn parse_cards(cards: &str) -> Vec<Card> {
+    cards
+        .split(',')
+        .filter(|card| !card.is_empty())
+        .map(Card::new)
+        .collect()
+}
+
+impl Clause {
+    /// Panics on anything malformed, the runner catches it
+    pub fn parse(token: &str) -> Clause {
+        let (kind, rest) = token.split_at(1);
+        match kind {
+            "T" => Clause::Time(rest.parse().unwrap()),
+            "P" => Clause::Seat(rest.parse().unwrap()),
+            "H" => Clause::Hand(parse_cards(rest)),
+            "F" => Clause::Fold,
+            "C" => Clause::Call,
+            "K" => Clause::Check,
+            "R" => Clause::Raise(rest.parse().unwrap()),
+            "A" => Clause::Bid(rest.parse().unwrap()),
+            "B" => Clause::Board(parse_cards(rest)),
+            "O" => Clause::Opponent(parse_cards(rest)),
+            "D" => Clause::Delta(rest.parse().unwrap()),
+            "Q" => Clause::Quit,
+            _ => panic!("Unknown clause {}", token),
+        }
+    }
+}
+
+pub fn parse_packet(packet: &str) -> Vec<Clause> {
+    packet.split_whitespace().map(Clause::parse).collect()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Reply {
+    Fold,
+    Call,
+    Check,
+    Raise(u32),
+    Bid(u32),
+}
+
+impl Reply {
+    pub fn to_message(&self) -> String {
+        match self {
+            Reply::Fold => "F".to_string(),
+            Reply::Call => "C".to_string(),
+            Reply::Check => "K".to_string(),
+            Reply::Raise(amount) => format!("R{}", amount),
+            Reply::Bid(amount) => format!("A{}", amount),
+        }
+    }
+}
+
+pub trait Bot {
+    /// Called with every clause of a packet, in order
+    fn observe(&mut self, clause: &Clause);
+
+    /// Our action once a packet has been observed, None if the packet
+    /// doesn't call for a decision (it's then acknowledged with a check)
+    fn act(&mut self, context: &MatchContext) -> Option<Reply>;
+
+    /// Played instead of `act` when the bot can't be trusted. The engine
+    /// treats an illegal check as a fold, so checking is always safe
+    fn safe_reply(&self) -> Reply {
+        Reply::Check
+    }
+}
+
+impl<B: Bot + ?Sized> Bot for Box<B> {
+    fn observe(&mut self, clause: &Clause) {
+        (**self).observe(clause)
+    }
+
+    fn act(&mut self, context: &MatchContext) -> Option<Reply> {
+        (**self).act(context)
+    }
+
+    fn safe_reply(&self) -> Reply {
+        (**self).safe_reply()
+    }
+}
+
+pub struct Runner<B: Bot, R: BufRead, W: Write> {
+    bot: B,
+    reader: R,
+    writer: W,
+    /// Set after a panic, cleared at the end of the hand
+    broken: bool,
+    pub panics: usize,
+    pub context: MatchContext,
+}
+
+impl<B: Bot> Runner<B, BufReader<TcpStream>, BufWriter<TcpStream>> {
+    pub fn connect(bot: B, address: &str) -> Self {
+        let stream = TcpStream::connect(address).expect("Could not connect to the engine");
+        stream.set_nodelay(true).unwrap();
+        let reader = BufReader::new(stream.try_clone().unwrap());
+        let writer = BufWriter::new(stream);
+        Runner::new(bot, reader, writer)
+    }
+}
+
+impl<B: Bot, R: BufRead, W: Write> Runner<B, R, W> {
+    pub fn new(bot: B, reader: R, writer: W) -> Self {
+        Runner {
+            bot,
+            reader,
+            writer,
+            broken: false,
+            panics: 0,
+            context: MatchContext::default(),
+        }
+    }
+
+    pub fn bot(&self) -> &B {
+        &self.bot
+    }
+
+    /// Plays until the engine quits or hangs up
+    pub fn run(&mut self) {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match self.reader.read_line(&mut line) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => {}
+            }
+            let (reply, quit) = self.handle(line.trim());
+            if quit {
+                return;
+            }
+            writeln!(self.writer, "{}", reply.to_message()).unwrap();
+            self.writer.flush().unwrap();
+        }
+    }
+
+    /// The reply to a single packet and whether the match is over
+    pub fn handle(&mut self, packet: &str) -> (Reply, bool) {
+        let delta = packet.split_whitespace().find_map(|token| token.strip_prefix('D'));
+        let hand_over = delta.is_some();
+        if let Some(delta) = delta.and_then(|delta| delta.parse().ok()) {
+            self.context.record(delta);
+        }
+        let quit = packet.split_whitespace().any(|token| token == "Q");
+
+        let reply = if self.broken {
+            self.safe_reply()
+        } else {
+            let bot = &mut self.bot;
+            let context = &self.context;
+            let result = catch_unwind(AssertUnwindSafe(|| {
+                for clause in parse_packet(packet) {
+                    bot.observe(&clause);
+                }
+                bot.act(context)
+            }));
+            match result {
+                Ok(reply) => reply.unwrap_or(Reply::Check),
+                Err(error) => {
+                    self.panics += 1;
+                    self.broken = true;
+                    println!(
+                        "[ERROR] Bot panicked handling '{}': {}, playing safe until the hand is over",
+                        packet,
+                        panic_message(&error)
+                    );
+                    self.safe_reply()
+                }
+            }
+        };
+
+        if hand_over {
+            self.broken = false;
+        }
+        (reply, quit)
+    }
+
+    fn safe_reply(&self) -> Reply {
+        let bot = &self.bot;
+        catch_unwind(AssertUnwindSafe(|| bot.safe_reply())).unwrap_or(Reply::Check)
+    }
+}
+
+fn panic_message(error: &Box<dyn std::any::Any + Send>) -> String {
+    if let Some(message) = error.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = error.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::WinRate;
+    use std::io::Cursor;
+
+    /// Calls everything, but panics whenever the opponent bids
+    struct FragileBot {
+        clauses: Vec<Clause>,
+    }
+
+    impl Bot for FragileBot {
+        fn observe(&mut self, clause: &Clause) {
+            if let Clause::Bid(_) = clause {
+                panic!("Did not expect a bid");
+            }
+            self.clauses.push(clause.clone());
+        }
+
+        fn act(&mut self, _: &MatchContext) -> Option<Reply> {
+            Some(Reply::Call)
+        }
+
+        fn safe_reply(&self) -> Reply {
+            Reply::Fold
+        }
+    }
+
+    #[test]
+    fn test_parse_packet() {
+        let clauses = parse_packet("T29.5 P1 HAs,Kd R4 B2c,3h,Td A12 D-10");
+        assert_eq!(
+            clauses,
+            vec![
+                Clause::Time(29.5),
+                Clause::Seat(1),
+                Clause::Hand(vec![Card::new("As"), Card::new("Kd")]),
+                Clause::Raise(4),
+                Clause::Board(vec![Card::new("2c"), Card::new("3h"), Card::new("Td")]),
+                Clause::Bid(12),
+                Clause::Delta(-10),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_panics_are_isolated_to_the_hand() {
+        let input = "T30 P0 HAs,Kd\nA10\nK\nD5\nT29 P1 H2c,3c\nXnonsense\nQ\n";
+        let mut output = Vec::new();
+        let mut runner = Runner::new(FragileBot { clauses: vec![] }, Cursor::new(input), &mut output);
+        runner.run();
+        assert_eq!(runner.panics, 2);
+        assert_eq!(runner.context.win_rate(), WinRate::new(5, 1));
+        drop(runner);
+        let replies = String::from_utf8(output).unwrap();
+        // Calls, then plays safe for the rest of the hand, then calls again
+        // in the next hand until it can't parse a packet
+        assert_eq!(replies, "C\nF\nF\nF\nC\nF\n");
+    }
+}
@@ -0,0 +1,246 @@
+/// Cheap rules no blueprint should break, checked before it goes to the
+/// competition.
+///
+/// Some decisions are right whatever the cards: never fold when checking
+/// is free (say, with nobody betting yet after the flop), and never give up a pot
+/// for a call that only has to win a few percent of the time to pay. A
+/// blueprint breaking them is either undertrained where it counts or
+/// reading the wrong features, and either way it is cheaper to find out
+/// here than at the table.
+///
+/// check_sanity plays hands with the blueprint in both seats (uniform
+/// where it has no policy) and checks the blueprint's policy at every
+/// decision it passes against each rule. Every infoset breaking a rule is
+/// reported once, with the policy that broke it. The legal actions met on
+/// the way are run through action_check::check_action_space as well, so
+/// an abstraction that can't hold them is caught with the blueprint:
+///
+///     gtcogs sanity auction_poker.bp 1000
+use crate::bot::decision::AuctionGame;
+use crate::game_logic::action::{Action, ActionIndex};
+use crate::game_logic::action_check::{check_action_space, ActionSpaceReport};
+use crate::game_logic::state::ActivePlayer;
+use crate::game_logic::strategy::blueprint::BlueprintStrategy;
+use crate::game_logic::strategy::CondensedInfoSet;
+use crate::implementations::auction::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+/// Folding less often than this is noise, not a decision
+const FOLD_TOLERANCE: f32 = 0.01;
+/// A call needing less equity than this is too good to ever fold
+const ABSURD_ODDS: f32 = 0.05;
+/// Folding at least this often counts as always folding
+const ALWAYS: f32 = 0.99;
+
+#[derive(Debug, Clone)]
+pub struct Violation {
+    pub rule: &'static str,
+    pub player_num: usize,
+    pub info_set: CondensedInfoSet,
+    pub policy: Vec<(ActionIndex, f32)>,
+    /// What made the decision easy, e.g. the odds offered
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SanityReport {
+    pub hands: usize,
+    pub decisions: usize,
+    /// Decisions the blueprint had a policy for, the only ones checked
+    pub checked: usize,
+    /// The first violation of every infoset and rule
+    pub violations: Vec<Violation>,
+    pub by_rule: BTreeMap<&'static str, usize>,
+    /// The legal actions of every decision against the action abstraction
+    pub action_space: ActionSpaceReport,
+}
+
+impl SanityReport {
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty() && self.action_space.is_ok()
+    }
+}
+
+impl fmt::Display for SanityReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} hands, {} of {} decisions had a policy, {} infosets break a rule",
+            self.hands,
+            self.checked,
+            self.decisions,
+            self.violations.len()
+        )?;
+        for (rule, count) in &self.by_rule {
+            write!(f, "\n  {:<24}{:>8} infosets", rule, count)?;
+        }
+        for violation in &self.violations {
+            write!(
+                f,
+                "\n  {}: player {} at {} ({}) plays {:?}",
+                violation.rule, violation.player_num, violation.info_set, violation.detail, violation.policy
+            )?;
+        }
+        write!(
+            f,
+            "\n{} distinct legal actions checked against the abstraction, {} problems",
+            self.action_space.samples_checked,
+            self.action_space.problems.len()
+        )?;
+        for problem in &self.action_space.problems {
+            write!(f, "\n  {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+/// How often `policy` plays `action`
+fn frequency(policy: &[(ActionIndex, f32)], action: AuctionPokerAction) -> f32 {
+    let index = action.index();
+    policy.iter().filter(|(i, _)| *i == index).map(|(_, probability)| probability).sum()
+}
+
+/// The rules `policy` breaks at the decision of `player_num` in `state`,
+/// with what made each one easy
+pub fn broken_rules(
+    state: &AuctionPokerState,
+    player_num: usize,
+    legal: &[AuctionPokerAction],
+    policy: &[(ActionIndex, f32)],
+) -> Vec<(&'static str, String)> {
+    let mut broken = vec![];
+    let fold = frequency(policy, AuctionPokerAction::Fold);
+    if fold > FOLD_TOLERANCE && legal.contains(&AuctionPokerAction::Check) {
+        broken.push(("folds instead of checking", format!("folds {:.0}%", 100.0 * fold)));
+    }
+    let pips = state.pips();
+    let to_call = pips[player_num ^ 1].saturating_sub(pips[player_num]);
+    if to_call > 0 && fold >= ALWAYS {
+        let equity = to_call as f32 / (state.pot() + to_call) as f32;
+        if equity < ABSURD_ODDS {
+            let detail = format!("{} to call into {} needs {:.1}% equity", to_call, state.pot(), 100.0 * equity);
+            broken.push(("always folds at long odds", detail));
+        }
+    }
+    broken
+}
+
+/// Plays `hands` hands of `blueprint` against itself and reports every
+/// infoset whose policy breaks one of the rules of broken_rules
+pub fn check_sanity(blueprint: &BlueprintStrategy, hands: usize, seed: u64) -> SanityReport {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut report = SanityReport::default();
+    let mut reported: HashSet<(&'static str, usize, CondensedInfoSet)> = HashSet::new();
+    let mut seen: HashSet<AuctionPokerAction> = HashSet::new();
+    for _ in 0..hands {
+        let mut game = AuctionGame::new();
+        loop {
+            let action = match game.active_player() {
+                ActivePlayer::Terminal(_) => break,
+                ActivePlayer::Chance(chance) => chance.sample_rng(&mut rng),
+                ActivePlayer::Marker(action) => action,
+                ActivePlayer::Player(player_num, legal) => {
+                    let player_num = player_num as usize;
+                    report.decisions += 1;
+                    seen.extend(legal.iter().cloned());
+                    let policy = match blueprint.get_exact_policy(&game, player_num) {
+                        Some(policy) => policy,
+                        None => {
+                            game.play(&legal[rng.gen_range(0, legal.len() as u32) as usize]);
+                            continue;
+                        }
+                    };
+                    report.checked += 1;
+                    let info_set = game.get_information_set(player_num);
+                    for (rule, detail) in broken_rules(game.state(), player_num, &legal, &policy) {
+                        if reported.insert((rule, player_num, info_set)) {
+                            *report.by_rule.entry(rule).or_insert(0) += 1;
+                            report.violations.push(Violation {
+                                rule,
+                                player_num,
+                                info_set,
+                                policy: policy.clone(),
+                                detail,
+                            });
+                        }
+                    }
+                    sample(&policy, &legal, &mut rng)
+                }
+            };
+            game.play(&action);
+        }
+        report.hands += 1;
+    }
+    report.action_space = check_action_space(&seen.into_iter().collect::<Vec<_>>(), None);
+    report
+}
+
+/// A legal action the policy picks, any legal one if it only plays
+/// actions that aren't
+fn sample(policy: &[(ActionIndex, f32)], legal: &[AuctionPokerAction], rng: &mut StdRng) -> AuctionPokerAction {
+    let mut pick = rng.gen_range(0.0, 1.0);
+    for (index, probability) in policy {
+        pick -= probability;
+        if pick < 0.0 {
+            if let Some(action) = legal.iter().find(|action| action.index() == *index) {
+                return action.clone();
+            }
+        }
+    }
+    legal[rng.gen_range(0, legal.len() as u32) as usize].clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::implementations::auction::RelativeSize::*;
+
+    /// Plays `actions` from the start, dealing the first card offered
+    fn play(actions: &[AuctionPokerAction]) -> AuctionGame {
+        let mut game = AuctionGame::new();
+        let mut actions = actions.iter();
+        loop {
+            let action = match game.active_player() {
+                ActivePlayer::Chance(chance) => chance.items()[0].clone(),
+                ActivePlayer::Marker(action) => action,
+                _ => match actions.next() {
+                    Some(action) => action.clone(),
+                    None => return game,
+                },
+            };
+            game.play(&action);
+        }
+    }
+
+    #[test]
+    fn test_dominated_folds_are_flagged() {
+        let fold = AuctionPokerAction::Fold.index();
+        let check = AuctionPokerAction::Check.index();
+
+        // Nobody has bet after the flop, the big blind can check for free
+        let limped = play(&[AuctionPokerAction::Call, AuctionPokerAction::Bid(Amount(0)), AuctionPokerAction::Bid(Amount(0))]);
+        let legal = limped.active_player().actions().to_vec();
+        assert!(broken_rules(limped.state(), 1, &legal, &[(check, 1.0)]).is_empty());
+        let broken = broken_rules(limped.state(), 1, &legal, &[(fold, 0.5), (check, 0.5)]);
+        assert_eq!(broken.iter().map(|(rule, _)| *rule).collect::<Vec<_>>(), ["folds instead of checking"]);
+
+        // Both bid big, then a min bet into the pot only needs ~1% to call
+        let mut line = vec![AuctionPokerAction::Call, AuctionPokerAction::Bid(Amount(100)), AuctionPokerAction::Bid(Amount(100))];
+        let bets = play(&line).active_player().actions().to_vec();
+        line.push(bets.iter().filter(|action| matches!(action, AuctionPokerAction::Raise(_))).min_by_key(|action| action.index()).cloned().unwrap());
+        let game = play(&line);
+        let player_num = game.active_player().player_num() as usize;
+        let legal = game.active_player().actions().to_vec();
+        let broken = broken_rules(game.state(), player_num, &legal, &[(fold, 1.0)]);
+        assert_eq!(broken.iter().map(|(rule, _)| *rule).collect::<Vec<_>>(), ["always folds at long odds"]);
+        assert!(broken_rules(game.state(), player_num, &legal, &[(fold, 0.9), (check, 0.1)]).is_empty());
+
+        // Without policies there is nothing to check
+        let report = check_sanity(&BlueprintStrategy::empty(), 10, 0);
+        assert_eq!((report.hands, report.checked), (10, 0));
+        assert!(report.action_space.samples_checked > 0);
+        assert!(report.is_ok(), "{}", report);
+    }
+}
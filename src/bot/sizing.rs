@@ -0,0 +1,170 @@
+/// Experimental: raise sizes refined within their bucket instead of always
+/// playing the middle of it.
+///
+/// The blueprint only knows raise buckets (the DeciPercent ranges of
+/// Into<ActionIndex>), so every raise we make lands in the middle of one,
+/// leaving the grid wide open to exploitation. Here the bucket still comes
+/// from the blueprint, but a SizeModel picks where in the bucket to raise.
+/// NeighborInterpolation treats the blueprint's weight on the neighbouring
+/// buckets as evidence of where the ideal size lies: a policy that also
+/// likes the next bucket up raises near the top of the chosen one.
+///
+/// Off by default, continuous_sizing in the config's [play] turns it on,
+/// see DecisionEngine::continuous_sizing
+///
+/// Separately, jitter_raise randomizes the size within the bucket right
+/// before it's sent, so observant opponents can't read our grid off the
+/// sizes we keep repeating. See AuctionBot::raise_jitter
+use crate::game_logic::action::{Action, ActionIndex};
+use crate::implementations::auction::RelativeSize::*;
+use crate::implementations::auction::*;
+use rand::Rng;
+
+pub trait SizeModel {
+    /// Where in `bucket` to raise given the blueprint policy, from 0.0 (the
+    /// smallest legal raise in the bucket) to 1.0 (the largest)
+    fn position(&self, bucket: ActionIndex, policy: &[(ActionIndex, f32)]) -> f32;
+}
+
+/// Leans towards whichever neighbouring bucket the policy prefers
+pub struct NeighborInterpolation;
+
+impl SizeModel for NeighborInterpolation {
+    fn position(&self, bucket: ActionIndex, policy: &[(ActionIndex, f32)]) -> f32 {
+        let weight = |index: Option<ActionIndex>| {
+            index
+                .and_then(|index| policy.iter().find(|(i, _)| *i == index))
+                .map_or(0.0, |(_, probability)| *probability)
+        };
+        let below = weight(bucket.checked_sub(1).filter(|index| is_raise(*index)));
+        let above = weight(bucket.checked_add(1).filter(|index| is_raise(*index)));
+        let total = below + weight(Some(bucket)) + above;
+        if total <= 0.0 {
+            return 0.5;
+        }
+        (0.5 + 0.5 * (above - below) / total).clamp(0.0, 1.0)
+    }
+}
+
+fn is_raise(index: ActionIndex) -> bool {
+    index < AuctionPokerAction::max_index() && matches!(AuctionPokerAction::from(index), AuctionPokerAction::Raise(_))
+}
+
+fn deci_percent(action: &AuctionPokerAction) -> Option<u32> {
+    match action {
+        AuctionPokerAction::Raise(DeciPercent(size)) => Some(*size),
+        _ => None,
+    }
+}
+
+/// Legal raises in the same bucket as `chosen`, smallest first
+fn bucket_raises<'a>(chosen: &AuctionPokerAction, legal: &'a [AuctionPokerAction]) -> Vec<&'a AuctionPokerAction> {
+    let bucket = chosen.index();
+    let mut group: Vec<&AuctionPokerAction> = legal
+        .iter()
+        .filter(|action| deci_percent(action).is_some() && action.index() == bucket)
+        .collect();
+    group.sort_by_key(|action| deci_percent(action));
+    group
+}
+
+/// A random legal raise near `chosen` in the same bucket. `fraction` is how
+/// far it may move, as a fraction of the bucket either way: 0.0 keeps the
+/// size, 1.0 allows anywhere in the bucket. Only legal raises are picked,
+/// so the min raise and the stacks are always respected
+pub fn jitter_raise<R: Rng>(
+    chosen: &AuctionPokerAction,
+    legal: &[AuctionPokerAction],
+    fraction: f32,
+    rng: &mut R,
+) -> AuctionPokerAction {
+    let size = match deci_percent(chosen) {
+        Some(size) => size,
+        None => return chosen.clone(),
+    };
+    let group = bucket_raises(chosen, legal);
+    let position = match group.iter().position(|action| deci_percent(action) >= Some(size)) {
+        Some(position) => position,
+        None if group.is_empty() => return chosen.clone(),
+        None => group.len() - 1,
+    };
+    let radius = (fraction.clamp(0.0, 1.0) * group.len() as f32).round() as usize;
+    let low = position.saturating_sub(radius);
+    let high = (position + radius).min(group.len() - 1);
+    group[rng.gen_range(low, high + 1)].clone()
+}
+
+/// `chosen` moved within its bucket to where `model` says, among the legal
+/// raises of that bucket. Anything but a raise is returned as it is
+pub fn refine_raise(
+    model: &dyn SizeModel,
+    chosen: &AuctionPokerAction,
+    policy: &[(ActionIndex, f32)],
+    legal: &[AuctionPokerAction],
+) -> AuctionPokerAction {
+    if deci_percent(chosen).is_none() {
+        return chosen.clone();
+    }
+    let group = bucket_raises(chosen, legal);
+    if group.is_empty() {
+        return chosen.clone();
+    }
+    let position = model.position(chosen.index(), policy);
+    let index = (position * (group.len() - 1) as f32).round() as usize;
+    group[index.min(group.len() - 1)].clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raises(pot: u32, amounts: std::ops::RangeInclusive<u32>) -> Vec<AuctionPokerAction> {
+        amounts.map(|amount| AuctionPokerAction::Raise(DeciPercent(Amount(amount).to_percent(pot)))).collect()
+    }
+
+    #[test]
+    fn test_neighbors_pull_the_size_within_the_bucket() {
+        let pot = 100;
+        let legal = raises(pot, 2..=400);
+        let chosen = AuctionPokerAction::Raise(DeciPercent(1800));
+        let bucket = chosen.index();
+        let group: Vec<u32> = legal
+            .iter()
+            .filter(|action| action.index() == bucket)
+            .filter_map(deci_percent)
+            .collect();
+        let (smallest, largest) = (*group.iter().min().unwrap(), *group.iter().max().unwrap());
+
+        let likes_bigger = vec![(bucket, 0.5), (bucket + 1, 0.5)];
+        let refined = refine_raise(&NeighborInterpolation, &chosen, &likes_bigger, &legal);
+        assert_eq!(refined.index(), bucket);
+        assert!(deci_percent(&refined).unwrap() > (smallest + largest) / 2);
+
+        let likes_smaller = vec![(bucket - 1, 1.0), (bucket, 1.0)];
+        let refined = refine_raise(&NeighborInterpolation, &chosen, &likes_smaller, &legal);
+        assert!(deci_percent(&refined).unwrap() < (smallest + largest) / 2);
+
+        let alone = vec![(bucket, 1.0)];
+        assert_eq!(NeighborInterpolation.position(bucket, &alone), 0.5);
+        assert_eq!(refine_raise(&NeighborInterpolation, &AuctionPokerAction::Call, &alone, &legal), AuctionPokerAction::Call);
+    }
+
+    #[test]
+    fn test_jitter_stays_legal_and_in_the_bucket() {
+        use rand::{rngs::StdRng, SeedableRng};
+        let mut rng = StdRng::seed_from_u64(3);
+        let pot = 10;
+        let legal = raises(pot, 4..=400);
+        let chosen = AuctionPokerAction::Raise(DeciPercent(15000));
+        let mut seen = std::collections::HashSet::new();
+        for _ in 0..200 {
+            let jittered = jitter_raise(&chosen, &legal, 1.0, &mut rng);
+            assert_eq!(jittered.index(), chosen.index());
+            assert!(legal.contains(&jittered));
+            seen.insert(deci_percent(&jittered));
+        }
+        assert!(seen.len() > 1);
+        assert_eq!(jitter_raise(&chosen, &legal, 0.0, &mut rng), chosen);
+        assert_eq!(jitter_raise(&AuctionPokerAction::Check, &legal, 1.0, &mut rng), AuctionPokerAction::Check);
+    }
+}
@@ -0,0 +1,300 @@
+/// Where a blueprint wins and loses its chips, street by street.
+///
+/// Blueprint plays blueprint, and along every hand the value of the hand
+/// to player 0 is estimated by rollouts (both seats playing on by their
+/// blueprints) before and after every step. A step's change in value is
+/// put down to whoever made it: a decision to the player who took it, a
+/// card to luck, each in the round it happened. The changes add up to
+/// the chips the hand ended with, less the value of the hand before the
+/// cards were dealt.
+///
+/// At every decision the value of each abstract action is estimated too,
+/// and the leak of a round is how much more than its blueprint a player
+/// would have got on average by playing the best of them, one decision
+/// at a time. The round with the biggest leak is where the abstraction
+/// or re-solving helps the most:
+///
+///     gtcogs streets auction_poker.bp 200 4
+use crate::bot::decision::{resolve_index, AuctionGame};
+use crate::game_logic::action::{Action, ActionIndex};
+use crate::game_logic::state::ActivePlayer;
+use crate::game_logic::strategy::blueprint::BlueprintStrategy;
+use crate::game_logic::visibility::Round;
+use crate::implementations::auction::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub struct StreetConfig {
+    pub hands: usize,
+    /// Rollouts behind every value estimate
+    pub samples: usize,
+    pub seed: u64,
+}
+
+impl Default for StreetConfig {
+    fn default() -> Self {
+        StreetConfig {
+            hands: 200,
+            samples: 4,
+            seed: 0,
+        }
+    }
+}
+
+/// Totals over all hands, in chips, each player's from their own side
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StreetValue {
+    pub decisions: [usize; 2],
+    /// What each player's decisions changed the value of the hand by
+    pub value: [f32; 2],
+    /// How much better the best abstract action was than the blueprint
+    pub leak: [f32; 2],
+    /// What the cards changed the value by, to player 0
+    pub luck: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StreetReport {
+    pub hands: usize,
+    /// The value to player 0 before any card is dealt, summed over hands
+    pub baseline: f32,
+    /// What player 0 actually won, summed over hands
+    pub won: f32,
+    /// Indexed by Round
+    pub streets: [StreetValue; 5],
+}
+
+impl StreetReport {
+    /// The round where `player_num` leaks the most chips a hand
+    pub fn leakiest(&self, player_num: usize) -> Round {
+        let most = (0..self.streets.len()).max_by(|a, b| {
+            self.streets[*a].leak[player_num].total_cmp(&self.streets[*b].leak[player_num])
+        });
+        Round::from(most.unwrap_or(0))
+    }
+}
+
+impl fmt::Display for StreetReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let per_hand = |chips: f32| chips / self.hands.max(1) as f32;
+        writeln!(
+            f,
+            "{} hands, player 0 won {:+.2} a hand from a baseline of {:+.2}, chips a hand by street",
+            self.hands,
+            per_hand(self.won),
+            per_hand(self.baseline)
+        )?;
+        writeln!(f, "{:<8}{:>10}{:>10}{:>10}{:>10}{:>10}", "", "value 0", "leak 0", "value 1", "leak 1", "luck")?;
+        for (round, street) in self.streets.iter().enumerate() {
+            writeln!(
+                f,
+                "{:<8}{:>+10.2}{:>10.2}{:>+10.2}{:>10.2}{:>+10.2}",
+                format!("{:?}", Round::from(round)),
+                per_hand(street.value[0]),
+                per_hand(street.leak[0]),
+                per_hand(street.value[1]),
+                per_hand(street.leak[1]),
+                per_hand(street.luck)
+            )?;
+        }
+        write!(f, "Leakiest street: {:?} for player 0, {:?} for player 1", self.leakiest(0), self.leakiest(1))
+    }
+}
+
+/// The index of the round `game` is in, bidding counting as the auction
+fn street_of(game: &AuctionGame) -> usize {
+    round_of(game).into()
+}
+
+pub fn round_of(game: &AuctionGame) -> Round {
+    if let ActivePlayer::Player(_, legal) = game.active_player() {
+        if legal.iter().any(|action| matches!(action, AuctionPokerAction::Bid(_))) {
+            return Round::Auction;
+        }
+    }
+    match game.state().community_cards().len() {
+        0 => Round::PreFlop,
+        3 => Round::Flop,
+        4 => Round::Turn,
+        _ => Round::River,
+    }
+}
+
+/// +1 for player 0, -1 for player 1, turning player 0's value into theirs
+fn side(player_num: usize) -> f32 {
+    match player_num {
+        0 => 1.0,
+        _ => -1.0,
+    }
+}
+
+/// One legal action for every abstract action of `legal` and how often
+/// the blueprint plays it, uniform where it has no policy
+pub fn blueprint_policy(
+    blueprint: &BlueprintStrategy,
+    game: &AuctionGame,
+    player_num: usize,
+    legal: &[AuctionPokerAction],
+) -> Vec<(AuctionPokerAction, f32)> {
+    let mut indices: Vec<ActionIndex> = legal.iter().map(|action| action.index()).collect();
+    indices.sort_unstable();
+    indices.dedup();
+    let actions: Vec<AuctionPokerAction> = indices.iter().filter_map(|index| resolve_index(*index, legal)).collect();
+    if let Some(policy) = blueprint.get_exact_policy(game, player_num) {
+        let weighted: Vec<(AuctionPokerAction, f32)> = actions
+            .iter()
+            .map(|action| {
+                let probability = policy.iter().find(|(index, _)| *index == action.index()).map(|(_, p)| *p);
+                (action.clone(), probability.unwrap_or(0.0))
+            })
+            .collect();
+        let total: f32 = weighted.iter().map(|(_, probability)| probability).sum();
+        if total > 0.0 {
+            return weighted.into_iter().map(|(action, probability)| (action, probability / total)).collect();
+        }
+    }
+    let uniform = 1.0 / actions.len() as f32;
+    actions.into_iter().map(|action| (action, uniform)).collect()
+}
+
+/// Both blueprints playing hands out from wherever they are
+pub struct Rollouts<'a> {
+    blueprints: [&'a BlueprintStrategy; 2],
+    samples: usize,
+    rng: StdRng,
+}
+
+impl<'a> Rollouts<'a> {
+    /// `samples` rollouts behind every value
+    pub fn new(blueprints: [&'a BlueprintStrategy; 2], samples: usize, seed: u64) -> Self {
+        Rollouts {
+            blueprints,
+            samples,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    fn policy(&self, game: &AuctionGame, player_num: usize, legal: &[AuctionPokerAction]) -> Vec<(AuctionPokerAction, f32)> {
+        blueprint_policy(self.blueprints[player_num], game, player_num, legal)
+    }
+
+    fn sample(&mut self, policy: &[(AuctionPokerAction, f32)]) -> AuctionPokerAction {
+        let mut pick = self.rng.gen_range(0.0, 1.0);
+        for (action, probability) in policy {
+            pick -= probability;
+            if pick < 0.0 {
+                return action.clone();
+            }
+        }
+        policy[policy.len() - 1].0.clone()
+    }
+
+    /// The next action of the blueprints, None once the hand is over
+    pub fn next_action(&mut self, game: &AuctionGame) -> Option<AuctionPokerAction> {
+        match game.active_player() {
+            ActivePlayer::Terminal(_) => None,
+            ActivePlayer::Chance(chance) => Some(chance.sample_rng(&mut self.rng)),
+            ActivePlayer::Marker(action) => Some(action),
+            ActivePlayer::Player(player_num, legal) => {
+                let policy = self.policy(game, player_num as usize, &legal);
+                Some(self.sample(&policy))
+            }
+        }
+    }
+
+    /// The value of `game` to player 0, exact once the hand is over
+    pub fn value(&mut self, game: &AuctionGame) -> f32 {
+        if let ActivePlayer::Terminal(utilities) = game.active_player() {
+            return utilities[0];
+        }
+        let mut total = 0.0;
+        for _ in 0..self.samples.max(1) {
+            let mut rollout = game.clone();
+            while let Some(action) = self.next_action(&rollout) {
+                rollout.play(&action);
+            }
+            if let ActivePlayer::Terminal(utilities) = rollout.active_player() {
+                total += utilities[0];
+            }
+        }
+        total / self.samples.max(1) as f32
+    }
+
+    fn play_hand(&mut self, report: &mut StreetReport) {
+        let mut game = AuctionGame::new();
+        let mut value = self.value(&game);
+        report.baseline += value;
+        loop {
+            match game.active_player() {
+                ActivePlayer::Terminal(utilities) => {
+                    report.won += utilities[0];
+                    return;
+                }
+                ActivePlayer::Marker(action) => game.play(&action),
+                ActivePlayer::Chance(chance) => {
+                    game.play(&chance.sample_rng(&mut self.rng));
+                    let after = self.value(&game);
+                    report.streets[street_of(&game)].luck += after - value;
+                    value = after;
+                }
+                ActivePlayer::Player(player_num, legal) => {
+                    let player_num = player_num as usize;
+                    let street = &mut report.streets[street_of(&game)];
+                    let policy = self.policy(&game, player_num, &legal);
+                    let mut values = vec![];
+                    for (action, _) in &policy {
+                        let mut next = game.clone();
+                        next.play(action);
+                        values.push(self.value(&next));
+                    }
+                    let expected: f32 = policy.iter().zip(&values).map(|((_, probability), value)| probability * value).sum();
+                    let best = values.iter().map(|value| side(player_num) * value).fold(f32::MIN, f32::max);
+                    street.leak[player_num] += best - side(player_num) * expected;
+
+                    let action = self.sample(&policy);
+                    let chosen = policy.iter().position(|(candidate, _)| *candidate == action).unwrap();
+                    street.value[player_num] += side(player_num) * (values[chosen] - value);
+                    street.decisions[player_num] += 1;
+                    value = values[chosen];
+                    game.play(&action);
+                }
+            }
+        }
+    }
+}
+
+/// Plays `config.hands` hands of `blueprints[0]` in seat 0 against
+/// `blueprints[1]` in seat 1 and splits the value of every hand by street
+pub fn street_values(blueprints: [&BlueprintStrategy; 2], config: &StreetConfig) -> StreetReport {
+    let mut rollouts = Rollouts::new(blueprints, config.samples, config.seed);
+    let mut report = StreetReport::default();
+    for _ in 0..config.hands {
+        rollouts.play_hand(&mut report);
+        report.hands += 1;
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_street_values_add_up() {
+        let blueprint = BlueprintStrategy::empty();
+        let config = StreetConfig {
+            hands: 1,
+            samples: 1,
+            seed: 1,
+        };
+        let report = street_values([&blueprint, &blueprint], &config);
+        assert_eq!(report.hands, 1);
+
+        // Every change in value is someone's, so together they are the winnings
+        let streets: f32 = report.streets.iter().map(|street| street.value[0] - street.value[1] + street.luck).sum();
+        assert!((report.baseline + streets - report.won).abs() < 1e-2, "{}", report);
+        assert!(report.streets.iter().all(|street| street.leak[0] >= -1e-3 && street.leak[1] >= -1e-3));
+        assert!(report.streets[0].decisions[0] > 0);
+    }
+}
@@ -0,0 +1,205 @@
+/// What a new blueprint would have done in hands we already played, and
+/// what that would have been worth.
+///
+/// Hands are read one per line in the notation of
+/// implementations::notation, as the bot writes them in its reports. A
+/// line may start with the seat whose decisions to re-evaluate, `0:` or
+/// `1:`, otherwise both players' are. At every such decision the value of
+/// the action that was played and of every action of the new blueprint's
+/// is estimated by rollouts, the new blueprint playing both seats from
+/// there on. The decision's delta is what the new blueprint's policy is
+/// worth less what was played, so a positive total is an improvement on
+/// the strategy that played the hands:
+///
+///     gtcogs what-if new_blueprint.bp hands.txt 8
+///
+/// Whatever cards a hand doesn't show (the opponent's, if they didn't go
+/// to showdown) are the placeholders the notation has for them, so the
+/// deltas of decisions that depend on them are only as good as those.
+use crate::bot::decision::AuctionGame;
+use crate::bot::streets::{blueprint_policy, round_of, Rollouts};
+use crate::game_logic::action::Action;
+use crate::game_logic::state::ActivePlayer;
+use crate::game_logic::strategy::blueprint::BlueprintStrategy;
+use crate::game_logic::visibility::Round;
+use crate::implementations::auction::*;
+use crate::implementations::notation::parse_hand;
+use std::fmt;
+
+/// Decisions listed as the biggest regressions and improvements
+const LISTED: usize = 10;
+
+#[derive(Debug, Clone)]
+pub struct DecisionDelta {
+    /// Line of the hand in the file, from 1
+    pub line: usize,
+    pub player_num: usize,
+    pub round: Round,
+    pub played: AuctionPokerAction,
+    /// The new blueprint's most likely action
+    pub preferred: AuctionPokerAction,
+    /// How often the new blueprint plays what was played
+    pub agreement: f32,
+    /// The new blueprint's policy less what was played, in chips
+    pub delta: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WhatIfReport {
+    pub hands: usize,
+    pub decisions: Vec<DecisionDelta>,
+}
+
+impl WhatIfReport {
+    pub fn total_delta(&self) -> f32 {
+        self.decisions.iter().map(|decision| decision.delta).sum()
+    }
+
+    /// How often the new blueprint plays what was played, over all decisions
+    pub fn agreement(&self) -> f32 {
+        self.decisions.iter().map(|decision| decision.agreement).sum::<f32>() / self.decisions.len().max(1) as f32
+    }
+
+    /// Decisions and total delta of every round, indexed by Round
+    pub fn by_round(&self) -> [(usize, f32); 5] {
+        let mut rounds = [(0, 0.0); 5];
+        for decision in &self.decisions {
+            let round: usize = decision.round.clone().into();
+            rounds[round].0 += 1;
+            rounds[round].1 += decision.delta;
+        }
+        rounds
+    }
+}
+
+impl fmt::Display for WhatIfReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} decisions in {} hands, the new blueprint plays what was played {:.1}% of the time and gains {:+.2} chips a hand",
+            self.decisions.len(),
+            self.hands,
+            100.0 * self.agreement(),
+            self.total_delta() / self.hands.max(1) as f32
+        )?;
+        for (round, (decisions, delta)) in self.by_round().iter().enumerate() {
+            writeln!(f, "  {:<10}{:>8} decisions{:>+12.2} chips", format!("{:?}", Round::from(round)), decisions, delta)?;
+        }
+        let mut sorted: Vec<&DecisionDelta> = self.decisions.iter().collect();
+        sorted.sort_by(|a, b| a.delta.total_cmp(&b.delta));
+        let listed = |f: &mut fmt::Formatter, decisions: Vec<&&DecisionDelta>| -> fmt::Result {
+            for decision in decisions {
+                writeln!(
+                    f,
+                    "  line {} player {} {:?}: played {:?}, new plays {:?} ({:.0}% agreement), {:+.2} chips",
+                    decision.line,
+                    decision.player_num,
+                    decision.round,
+                    decision.played,
+                    decision.preferred,
+                    100.0 * decision.agreement,
+                    decision.delta
+                )?;
+            }
+            Ok(())
+        };
+        writeln!(f, "Biggest regressions:")?;
+        listed(f, sorted.iter().take(LISTED).filter(|decision| decision.delta < 0.0).collect())?;
+        writeln!(f, "Biggest improvements:")?;
+        listed(f, sorted.iter().rev().take(LISTED).filter(|decision| decision.delta > 0.0).collect())
+    }
+}
+
+/// The seats to re-evaluate and the hand of one line of a hands file
+fn parse_line(line: &str) -> Result<(Vec<usize>, Vec<AuctionPokerAction>), String> {
+    let (seats, hand) = match line.split_once(':') {
+        Some(("0", hand)) => (vec![0], hand),
+        Some(("1", hand)) => (vec![1], hand),
+        Some((seat, _)) => return Err(format!("{} is not a seat", seat)),
+        None => (vec![0, 1], line),
+    };
+    Ok((seats, parse_hand(hand.trim())?))
+}
+
+/// The value of `action` in `game` to `player_num`
+fn value_of(rollouts: &mut Rollouts, game: &AuctionGame, action: &AuctionPokerAction, player_num: usize) -> f32 {
+    let mut next = game.clone();
+    next.play(action);
+    match player_num {
+        0 => rollouts.value(&next),
+        _ => -rollouts.value(&next),
+    }
+}
+
+/// Re-evaluates every decision of the hands in `contents` (the lines of a
+/// hands file) under `blueprint`, `samples` rollouts behind every value.
+/// Blank lines and lines starting with # are skipped
+pub fn what_if(blueprint: &BlueprintStrategy, contents: &str, samples: usize, seed: u64) -> Result<WhatIfReport, String> {
+    let mut rollouts = Rollouts::new([blueprint, blueprint], samples, seed);
+    let mut report = WhatIfReport::default();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (seats, history) = parse_line(line).map_err(|error| format!("Line {}: {}", number + 1, error))?;
+        let mut game = AuctionGame::new();
+        for action in history {
+            if let ActivePlayer::Player(player_num, legal) = game.active_player() {
+                let player_num = player_num as usize;
+                if seats.contains(&player_num) {
+                    let policy = blueprint_policy(blueprint, &game, player_num, &legal);
+                    let played = value_of(&mut rollouts, &game, &action, player_num);
+                    let mut expected = 0.0;
+                    for (candidate, probability) in &policy {
+                        expected += probability * value_of(&mut rollouts, &game, candidate, player_num);
+                    }
+                    let preferred = policy.iter().max_by(|a, b| a.1.total_cmp(&b.1)).map(|(action, _)| action.clone());
+                    report.decisions.push(DecisionDelta {
+                        line: number + 1,
+                        player_num,
+                        round: round_of(&game),
+                        played: action.clone(),
+                        preferred: preferred.unwrap_or_else(|| action.clone()),
+                        agreement: policy.iter().filter(|(candidate, _)| candidate.index() == action.index()).map(|(_, p)| p).sum(),
+                        delta: expected - played,
+                    });
+                }
+            }
+            game.play(&action);
+        }
+        report.hands += 1;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_what_if_reevaluates_every_decision() {
+        let blueprint = BlueprintStrategy::empty();
+        let hand = "AsKs|4h5h r6 f";
+        let contents = format!("# Two hands\n{}\n\n1: {}\n", hand, hand);
+        let report = what_if(&blueprint, &contents, 1, 0).unwrap();
+        assert_eq!(report.hands, 2);
+
+        // Every decision of the first hand, only player 1's of the second
+        let first: Vec<&DecisionDelta> = report.decisions.iter().filter(|decision| decision.line == 2).collect();
+        let second: Vec<&DecisionDelta> = report.decisions.iter().filter(|decision| decision.line == 4).collect();
+        assert_eq!((first.len(), second.len()), (2, 1));
+        assert!(second.iter().all(|decision| decision.player_num == 1));
+        assert_eq!(first[0].played, AuctionPokerAction::Raise(RelativeSize::DeciPercent(RelativeSize::Amount(6).to_percent(3))));
+        assert_eq!(first[1].played, AuctionPokerAction::Fold);
+        assert!(matches!(first[1].round, Round::PreFlop));
+
+        // Without a policy the blueprint plays everything alike
+        assert!(report.decisions.iter().all(|decision| decision.agreement > 0.0 && decision.agreement < 1.0));
+        assert!(report.total_delta().is_finite());
+        assert_eq!(report.by_round().iter().map(|(decisions, _)| decisions).sum::<usize>(), 3);
+
+        assert!(what_if(&blueprint, "2: AsKs|4h5h", 1, 0).is_err());
+        assert!(what_if(&blueprint, "AsKs|4h5h k", 1, 0).unwrap_err().starts_with("Line 1"));
+    }
+}
@@ -0,0 +1,505 @@
+/// Everything worth tuning between runs, read from one file (bot.toml, or
+/// whatever GTCOGS_CONFIG names) by the CLI and the runner instead of being
+/// edited into the source.
+///
+/// The file is TOML (see toml.rs for the part of it we read), deserialized
+/// by serde into the structs below; a file ending in .json is read as JSON
+/// instead. Every section and key is optional and falls back to the
+/// default below, so an empty file (or none at all) is the bot as it
+/// always was. Unknown keys and values out of range are turned down with
+/// the line or the section they came from:
+///
+///     [training]
+///     threads = 8
+///     iterations = 200_000
+///
+///     [evaluator.auction]
+///     fit = ["exact", "range -2 2", "range -2 2", "range -2 2", "difference"]
+///     top_k = 3
+///
+/// The rules of the game (blinds, stacks, the raise buckets, how often a
+/// round may be raised) stay in constants.rs: a strategy only makes sense
+/// under the rules it was trained with, see Action::abstraction_hash. The bid buckets are the
+/// one exception, they are hashed into every strategy all the same.
+/// Other rules are played with a context::Context, built from this
+/// config and a GameConfig
+use crate::algorithm::estimate::Limits;
+use crate::bot::agent::AgentConfig;
+use crate::bot::heuristics::BidSizer;
+use crate::constants::MAX_GAME_DEPTH;
+use crate::implementations::auction::{check_bid_buckets, DEFAULT_BID_BUCKETS};
+use crate::game_logic::schema::FeatureSchema;
+use crate::game_logic::strategy::blueprint::{Evaluator, FitFunction, FitOptions, LossShape, TieBreak, DEFAULT_CUTOFF};
+use crate::game_logic::visibility::Round;
+use crate::toml;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrainingConfig {
+    pub threads: usize,
+    pub iterations: usize,
+    pub epsilon: f32,
+    /// Training between checkpoints takes this many times as long as the
+    /// checkpoint, see MCCFRParallel::with_checkpoint_ratio
+    pub checkpoint_ratio: f64,
+    /// Iterations reaching this many actions are abandoned, see
+    /// MCCFR::with_max_depth
+    pub max_depth: usize,
+}
+
+impl Default for TrainingConfig {
+    fn default() -> Self {
+        TrainingConfig {
+            threads: 12,
+            iterations: 110_000,
+            epsilon: 0.2,
+            checkpoint_ratio: 20.0,
+            max_depth: MAX_GAME_DEPTH,
+        }
+    }
+}
+
+/// What the abstraction may grow to, see algorithm::estimate
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AbstractionConfig {
+    pub max_file_mb: usize,
+    pub max_memory_mb: usize,
+    /// Train even if the estimate is over the limits
+    pub ignore_limits: bool,
+    /// Largest bid of each bid bucket, see auction::set_bid_buckets and
+    /// `gtcogs bid-buckets` to fit them to the bids of some hands
+    pub bid_buckets: Vec<u32>,
+}
+
+impl Default for AbstractionConfig {
+    fn default() -> Self {
+        let limits = Limits::default();
+        AbstractionConfig {
+            max_file_mb: limits.file_bytes / 1024 / 1024,
+            max_memory_mb: limits.memory_bytes / 1024 / 1024,
+            ignore_limits: false,
+            bid_buckets: DEFAULT_BID_BUCKETS.to_vec(),
+        }
+    }
+}
+
+/// How one round finds a stand in for an infoset the blueprint doesn't
+/// have, see Evaluator
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoundFitConfig {
+    /// One of "exact", "difference" or "range <below> <above>" per feature
+    /// of the schema. Left out for the schema's own, empty to not fit the
+    /// round at all
+    pub fit: Option<Vec<String>>,
+    pub weights: Vec<f32>,
+    /// "linear", "quadratic" or "capped <cap>"
+    pub shape: String,
+    /// "lowest_key" or "conservative"
+    pub tie_break: String,
+    pub top_k: usize,
+}
+
+impl Default for RoundFitConfig {
+    fn default() -> Self {
+        RoundFitConfig {
+            fit: None,
+            weights: vec![],
+            shape: "linear".to_string(),
+            tie_break: "lowest_key".to_string(),
+            top_k: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EvaluatorConfig {
+    pub normalize: bool,
+    pub preflop: RoundFitConfig,
+    pub auction: RoundFitConfig,
+    pub flop_onwards: RoundFitConfig,
+}
+
+impl Default for EvaluatorConfig {
+    fn default() -> Self {
+        EvaluatorConfig {
+            normalize: true,
+            preflop: RoundFitConfig::default(),
+            auction: RoundFitConfig::default(),
+            flop_onwards: RoundFitConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathsConfig {
+    /// Prefix of the per player strategy files training writes
+    pub strategy: String,
+    /// The blueprint converted from them, and played
+    pub blueprint: String,
+}
+
+impl Default for PathsConfig {
+    fn default() -> Self {
+        PathsConfig {
+            strategy: "auction_poker".to_string(),
+            blueprint: "auction_poker.bp".to_string(),
+        }
+    }
+}
+
+/// How the bot spends its time at the table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeConfig {
+    /// Time allowed for a single decision
+    pub decision_ms: u64,
+    /// Re-solve from the flop on, see bot::agent
+    pub resolve: bool,
+    /// Time a re-solve may take, within the decision's
+    pub resolve_ms: u64,
+    /// Fewest iterations of a re-solve we act on
+    pub min_resolve_iterations: usize,
+    /// Continuations of the opponent's turn explored ahead of our next
+    /// decision while we wait on them, 0 to not prefetch, see bot::prefetch
+    pub prefetch_branches: usize,
+    /// How often the blueprint file is checked for a new version during a
+    /// match, 0 to never reload it, see bot::reload
+    pub reload_ms: u64,
+}
+
+impl Default for TimeConfig {
+    fn default() -> Self {
+        let agent = AgentConfig::default();
+        TimeConfig {
+            decision_ms: 500,
+            resolve: agent.resolve,
+            resolve_ms: agent.resolver.time_budget.as_millis() as u64,
+            min_resolve_iterations: agent.min_iterations,
+            prefetch_branches: 4,
+            reload_ms: 1000,
+        }
+    }
+}
+
+/// How the bot plays on top of what the blueprint says
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlayConfig {
+    /// Shade our bids against the opponent's observed bids, see
+    /// DecisionEngine::exploit_bids
+    pub exploit_bids: bool,
+    /// Refine raise sizes within their bucket, see bot::sizing
+    pub continuous_sizing: bool,
+    /// How far our raises are randomly moved within their bucket, from 0
+    /// (never) to 1 (anywhere in it), see AuctionBot::raise_jitter
+    pub raise_jitter: f32,
+    /// Actions the blueprint plays with at most this probability are
+    /// never played, see BlueprintStrategy::with_cutoff
+    pub blueprint_cutoff: f32,
+    /// How the heuristic sizes a bid the blueprint has nothing to say
+    /// about, see BidSizer
+    pub bid_pot_growth: f64,
+    pub bid_shade: f64,
+    pub bid_iterations: u32,
+}
+
+impl Default for PlayConfig {
+    fn default() -> Self {
+        PlayConfig {
+            exploit_bids: false,
+            continuous_sizing: false,
+            raise_jitter: 0.0,
+            blueprint_cutoff: DEFAULT_CUTOFF,
+            bid_pot_growth: 2.0,
+            bid_shade: 0.9,
+            bid_iterations: 2_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BotConfig {
+    pub training: TrainingConfig,
+    pub abstraction: AbstractionConfig,
+    pub evaluator: EvaluatorConfig,
+    pub paths: PathsConfig,
+    pub time: TimeConfig,
+    pub play: PlayConfig,
+}
+
+/// The config file to read, GTCOGS_CONFIG or else bot.toml
+pub fn config_file() -> String {
+    std::env::var("GTCOGS_CONFIG").unwrap_or_else(|_| "bot.toml".to_string())
+}
+
+impl BotConfig {
+    /// Reads and checks `file_name`, as JSON if it ends in .json and as
+    /// TOML otherwise
+    pub fn load(file_name: &str) -> Result<BotConfig, String> {
+        let text = std::fs::read_to_string(file_name).map_err(|error| format!("Cannot read {}: {}", file_name, error))?;
+        let config = match file_name.ends_with(".json") {
+            true => BotConfig::parse_json(&text),
+            false => BotConfig::parse(&text),
+        };
+        config.map_err(|error| format!("{}: {}", file_name, error))
+    }
+
+    /// The config in config_file, the defaults if there is no such file
+    /// and GTCOGS_CONFIG didn't ask for it
+    pub fn from_environment() -> Result<BotConfig, String> {
+        let file_name = config_file();
+        if std::env::var("GTCOGS_CONFIG").is_err() && !std::path::Path::new(&file_name).exists() {
+            return Ok(BotConfig::default());
+        }
+        BotConfig::load(&file_name)
+    }
+
+    /// A config from the text of a TOML file
+    pub fn parse(text: &str) -> Result<BotConfig, String> {
+        let config: BotConfig = serde_json::from_value(toml::parse(text)?).map_err(|error| error.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// A config from the text of a JSON file
+    pub fn parse_json(text: &str) -> Result<BotConfig, String> {
+        if text.trim().is_empty() {
+            return Ok(BotConfig::default());
+        }
+        let config: BotConfig = serde_json::from_str(text).map_err(|error| error.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks every value is one the bot can work with
+    pub fn validate(&self) -> Result<(), String> {
+        let training = &self.training;
+        if training.threads == 0 {
+            return Err("training: threads must be at least 1".to_string());
+        }
+        if training.iterations == 0 {
+            return Err("training: iterations must be at least 1".to_string());
+        }
+        if !(0.0..=1.0).contains(&training.epsilon) {
+            return Err(format!("training: epsilon must be within 0 and 1, not {}", training.epsilon));
+        }
+        if training.checkpoint_ratio.is_nan() || training.checkpoint_ratio <= 0.0 {
+            return Err(format!("training: checkpoint_ratio must be positive, not {}", training.checkpoint_ratio));
+        }
+        if !(1..=MAX_GAME_DEPTH).contains(&training.max_depth) {
+            return Err(format!("training: max_depth must be within 1 and {}, not {}", MAX_GAME_DEPTH, training.max_depth));
+        }
+        if self.abstraction.max_file_mb == 0 || self.abstraction.max_memory_mb == 0 {
+            return Err("abstraction: limits must be at least 1 MB".to_string());
+        }
+        check_bid_buckets(&self.abstraction.bid_buckets).map_err(|error| format!("abstraction: {}", error))?;
+        if self.paths.strategy.is_empty() || self.paths.blueprint.is_empty() {
+            return Err("paths: cannot be empty".to_string());
+        }
+        if self.time.decision_ms == 0 {
+            return Err("time: decision_ms must be at least 1".to_string());
+        }
+        if self.time.resolve_ms > self.time.decision_ms {
+            return Err(format!(
+                "time: resolve_ms ({}) is longer than the whole decision ({})",
+                self.time.resolve_ms, self.time.decision_ms
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.play.raise_jitter) {
+            return Err(format!("play: raise_jitter must be within 0 and 1, not {}", self.play.raise_jitter));
+        }
+        if !(0.0..1.0).contains(&self.play.blueprint_cutoff) {
+            return Err(format!("play: blueprint_cutoff must be at least 0 and below 1, not {}", self.play.blueprint_cutoff));
+        }
+        if !(1.0..).contains(&self.play.bid_pot_growth) {
+            return Err(format!("play: bid_pot_growth must be at least 1, not {}", self.play.bid_pot_growth));
+        }
+        if !(0.0..=1.0).contains(&self.play.bid_shade) {
+            return Err(format!("play: bid_shade must be within 0 and 1, not {}", self.play.bid_shade));
+        }
+        if self.play.bid_iterations == 0 {
+            return Err("play: bid_iterations must be at least 1".to_string());
+        }
+        self.evaluator()?;
+        Ok(())
+    }
+
+    pub fn limits(&self) -> Limits {
+        Limits {
+            file_bytes: self.abstraction.max_file_mb * 1024 * 1024,
+            memory_bytes: self.abstraction.max_memory_mb * 1024 * 1024,
+        }
+    }
+
+    pub fn evaluator(&self) -> Result<Evaluator, String> {
+        let config = &self.evaluator;
+        let (preflop, preflop_options) = round_fit(Round::PreFlop, "preflop", &config.preflop)?;
+        let (auction, auction_options) = round_fit(Round::Auction, "auction", &config.auction)?;
+        let (flop_onwards, flop_onwards_options) = round_fit(Round::Flop, "flop_onwards", &config.flop_onwards)?;
+        let evaluator = Evaluator {
+            preflop,
+            auction,
+            flop_onwards,
+            preflop_weights: config.preflop.weights.clone(),
+            auction_weights: config.auction.weights.clone(),
+            flop_onwards_weights: config.flop_onwards.weights.clone(),
+            normalize: config.normalize,
+            preflop_options,
+            auction_options,
+            flop_onwards_options,
+        };
+        evaluator.validate().map_err(|error| format!("evaluator: {}", error))?;
+        Ok(evaluator)
+    }
+
+    pub fn agent_config(&self) -> AgentConfig {
+        let mut agent = AgentConfig {
+            resolve: self.time.resolve,
+            min_iterations: self.time.min_resolve_iterations,
+            ..Default::default()
+        };
+        agent.resolver.time_budget = Duration::from_millis(self.time.resolve_ms);
+        agent
+    }
+
+    pub fn bid_sizer(&self) -> BidSizer {
+        let mut sizer = BidSizer::default();
+        sizer.pot_growth = self.play.bid_pot_growth;
+        sizer.shade = self.play.bid_shade;
+        sizer.iterations = self.play.bid_iterations;
+        sizer
+    }
+
+    pub fn decision_budget(&self) -> Duration {
+        Duration::from_millis(self.time.decision_ms)
+    }
+
+    /// None if the blueprint shouldn't be reloaded
+    pub fn reload_interval(&self) -> Option<Duration> {
+        Some(Duration::from_millis(self.time.reload_ms)).filter(|interval| !interval.is_zero())
+    }
+}
+
+fn round_fit(round: Round, name: &str, config: &RoundFitConfig) -> Result<(Vec<FitFunction>, FitOptions), String> {
+    let error = |message: String| format!("evaluator.{}: {}", name, message);
+    let fit = match &config.fit {
+        Some(fit) => fit.iter().map(|function| parse_fit(function)).collect::<Result<_, _>>().map_err(error)?,
+        None => FeatureSchema::for_round(round).default_fit(),
+    };
+    let shape = match config.shape.split_whitespace().collect::<Vec<_>>()[..] {
+        ["linear"] => LossShape::Linear,
+        ["quadratic"] => LossShape::Quadratic,
+        ["capped", cap] => match cap.parse::<f32>() {
+            Ok(cap) if cap > 0.0 => LossShape::Capped(cap),
+            _ => return Err(error(format!("'{}' needs a positive cap", config.shape))),
+        },
+        _ => return Err(error(format!("shape '{}' is not linear, quadratic or capped <cap>", config.shape))),
+    };
+    let tie_break = match config.tie_break.as_str() {
+        "lowest_key" => TieBreak::LowestKey,
+        "conservative" => TieBreak::Conservative,
+        other => return Err(error(format!("tie_break '{}' is not lowest_key or conservative", other))),
+    };
+    let options = FitOptions {
+        shape,
+        tie_break,
+        top_k: config.top_k,
+    };
+    Ok((fit, options))
+}
+
+fn parse_fit(function: &str) -> Result<FitFunction, String> {
+    let words: Vec<&str> = function.split_whitespace().collect();
+    match words[..] {
+        ["exact"] => Ok(FitFunction::Exact),
+        ["difference"] => Ok(FitFunction::Difference),
+        ["range", below, above] => match (below.parse::<i32>(), above.parse::<i32>()) {
+            (Ok(below), Ok(above)) if below <= 0 && above >= 0 => Ok(FitFunction::Range(above, below)),
+            _ => Err(format!("'{}' needs a range like 'range -3 3'", function)),
+        },
+        _ => Err(format!("fit '{}' is not exact, difference or range <below> <above>", function)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_file() {
+        assert_eq!(BotConfig::parse("").unwrap(), BotConfig::default());
+        assert_eq!(BotConfig::parse_json("{}").unwrap(), BotConfig::default());
+        assert_eq!(BotConfig::parse(include_str!("../bot.toml")).unwrap(), BotConfig::default());
+
+        let config = BotConfig::parse(
+            r#"
+            [training]
+            threads = 4
+            epsilon = 0.1
+
+            [evaluator.auction]
+            fit = ["exact", "range -2 2", "range -2 2", "range -2 2", "difference"]
+            shape = "capped 0.5"
+            top_k = 3
+
+            [time]
+            decision_ms = 1000
+            reload_ms = 0
+
+            [play]
+            exploit_bids = true
+            continuous_sizing = true
+            raise_jitter = 0.5
+            blueprint_cutoff = 0.05
+            bid_shade = 0.8
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.training.threads, 4);
+        assert_eq!(config.training.iterations, TrainingConfig::default().iterations);
+        assert_eq!(config.decision_budget(), Duration::from_millis(1000));
+        assert_eq!(config.reload_interval(), None);
+        assert!(config.play.exploit_bids && !BotConfig::default().play.exploit_bids);
+        assert!(config.play.continuous_sizing && !BotConfig::default().play.continuous_sizing);
+        assert_eq!(config.play.raise_jitter, 0.5);
+        assert_eq!(config.play.blueprint_cutoff, 0.05);
+        assert_eq!(BotConfig::default().play.blueprint_cutoff, DEFAULT_CUTOFF);
+        assert_eq!(config.bid_sizer().shade, 0.8);
+        assert_eq!(config.bid_sizer().iterations, BidSizer::default().iterations);
+        assert_eq!(BotConfig::default().reload_interval(), Some(Duration::from_secs(1)));
+        let evaluator = config.evaluator().unwrap();
+        assert_eq!(evaluator.auction[1], FitFunction::Range(2, -2));
+        assert_eq!(evaluator.auction_options.shape, LossShape::Capped(0.5));
+        assert_eq!(evaluator.auction_options.top_k, 3);
+        assert_eq!(evaluator.preflop, FeatureSchema::for_round(Round::PreFlop).default_fit());
+
+        // JSON still reads the same
+        let json = BotConfig::parse_json(r#"{ "training": { "threads": 4, "epsilon": 0.1 } }"#).unwrap();
+        assert_eq!(json.training, BotConfig::parse("training = { threads = 4, epsilon = 0.1 }").unwrap().training);
+
+        // Mistakes say where they are
+        let error = BotConfig::parse("[training]\nthread = 4").unwrap_err();
+        assert!(error.contains("thread"), "{}", error);
+        let error = BotConfig::parse("[training]\nthreads = \"four\"").unwrap_err();
+        assert!(error.contains("string"), "{}", error);
+        assert!(BotConfig::parse("[training]\nthreads = 0").unwrap_err().contains("training"));
+        assert!(BotConfig::parse("[training]\n\nthreads 4").unwrap_err().contains("line 3"));
+        assert!(BotConfig::parse_json("{\n\n\"training\" 4 }").unwrap_err().contains("line 3"));
+        let error = BotConfig::parse("[evaluator.auction]\nfit = [\"exact\"]").unwrap_err();
+        assert!(error.contains("Auction"), "{}", error);
+        assert!(BotConfig::parse("[time]\ndecision_ms = 100\nresolve_ms = 200").is_err());
+        assert!(BotConfig::parse("[play]\nraise_jitter = 1.5").unwrap_err().contains("play"));
+        assert!(BotConfig::parse("[play]\nblueprint_cutoff = 1.0").unwrap_err().contains("play"));
+        assert!(BotConfig::parse("[play]\nbid_pot_growth = 0.5").unwrap_err().contains("play"));
+        assert!(BotConfig::parse("[play]\nbid_iterations = 0").unwrap_err().contains("play"));
+        let error = BotConfig::parse("[abstraction]\nbid_buckets = [0, 50, 40, 400]").unwrap_err();
+        assert!(error.contains("abstraction") && error.contains("50"), "{}", error);
+    }
+}
@@ -0,0 +1,229 @@
+/// The rules a game is played by and what plays it, in one immutable
+/// Context shared by Arc between the states, games and trainers built with
+/// it.
+///
+/// constants.rs holds the rules of the game the bot plays, and a state made
+/// with State::new still plays by them (default_context). One made with
+/// State::with_context plays by its Context instead, so games with other
+/// blinds, stacks or raise limits can be played and trained side by side
+/// in one process:
+///
+///     let short = Context::new(GameConfig { stack_size: 100, ..GameConfig::default() }, config)?;
+///     let game = Game::<AuctionPokerAction, AuctionPokerState>::with_context(&short);
+///     mccfr.with_context(short.clone());
+///
+/// Not everything reads the context yet. The action abstraction (the raise
+/// and bid buckets) is still process wide, as actions turn into
+/// ActionIndex and back without a state to ask, and so is the bot's
+/// bookkeeping of a match (bot::match_context), which only ever plays the
+/// real game
+use crate::config::{AbstractionConfig, BotConfig};
+use crate::constants::*;
+use crate::game_logic::action::check_index_round_trip;
+use crate::game_logic::schema::FeatureSchema;
+use crate::game_logic::strategy::blueprint::Evaluator;
+use crate::game_logic::visibility::Round;
+use crate::implementations::auction::{abstraction_hash, AuctionPokerAction};
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+/// The rules of auction poker, constants.rs by default
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GameConfig {
+    pub little_blind: u32,
+    pub big_blind: u32,
+    pub stack_size: u32,
+    /// Raises allowed in a round
+    pub aggression_limit: usize,
+    /// Rollouts behind every EV feature (half of it on the river)
+    pub ev_iterations: u32,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            little_blind: LITTLE_BLIND,
+            big_blind: BIG_BLIND,
+            stack_size: STACK_SIZE,
+            aggression_limit: AGGRESSION_LIMIT,
+            ev_iterations: EV_ITERATIONS,
+        }
+    }
+}
+
+impl GameConfig {
+    /// Every chip in play
+    pub fn max_pot(&self) -> u32 {
+        2 * self.stack_size
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        if self.big_blind == 0 || self.little_blind > self.big_blind {
+            return Err(format!("Blinds of {}/{} are not a game", self.little_blind, self.big_blind));
+        }
+        if self.stack_size < self.big_blind {
+            return Err(format!("A stack of {} can't post a big blind of {}", self.stack_size, self.big_blind));
+        }
+        if self.ev_iterations == 0 {
+            return Err("EV features need at least one rollout".to_string());
+        }
+        Ok(())
+    }
+}
+
+pub struct Context {
+    pub game: GameConfig,
+    pub abstraction: AbstractionConfig,
+    /// The features of every round, indexed by Round
+    pub schemas: Vec<FeatureSchema>,
+    pub evaluator: Evaluator,
+    /// See abstraction_hash
+    hash: OnceLock<u64>,
+}
+
+impl fmt::Debug for Context {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Context")
+            .field("game", &self.game)
+            .field("abstraction", &self.abstraction)
+            .field("evaluator", &self.evaluator)
+            .finish()
+    }
+}
+
+impl Context {
+    /// A context playing by `game`, with everything else from `config`
+    pub fn new(game: GameConfig, config: &BotConfig) -> Result<Arc<Context>, String> {
+        game.check()?;
+        // Every bucket has to decode to an action of its own, or strategies
+        // would be trained and hashed under a broken abstraction
+        check_index_round_trip::<AuctionPokerAction>()?;
+        Ok(Arc::new(Context {
+            game,
+            abstraction: config.abstraction.clone(),
+            schemas: (0..5).map(|round| FeatureSchema::for_round(Round::from(round))).collect(),
+            evaluator: config.evaluator()?,
+            hash: OnceLock::new(),
+        }))
+    }
+
+    /// The game of constants.rs, with everything else from `config`
+    pub fn from_config(config: &BotConfig) -> Result<Arc<Context>, String> {
+        Context::new(GameConfig::default(), config)
+    }
+
+    pub fn schema(&self, round: Round) -> &FeatureSchema {
+        let index: usize = round.into();
+        &self.schemas[index]
+    }
+
+    /// Fingerprint of the abstraction of auction poker under these rules
+    /// and schemas, saved with every strategy trained under them so that
+    /// one trained under other rules doesn't load
+    pub fn abstraction_hash(&self) -> u64 {
+        *self.hash.get_or_init(|| abstraction_hash(self))
+    }
+}
+
+/// The context of states made with State::new: the game of constants.rs
+/// and the default config
+pub fn default_context() -> Arc<Context> {
+    static DEFAULT: OnceLock<Arc<Context>> = OnceLock::new();
+    DEFAULT
+        .get_or_init(|| Context::from_config(&BotConfig::default()).expect("The default config is valid"))
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_logic::action::Action;
+    use crate::game_logic::state::{ActivePlayer, State};
+    use crate::implementations::auction::*;
+    use crate::units::Chips;
+    use crate::Game;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_differently_configured_games() {
+        let bad = GameConfig { little_blind: 3, ..GameConfig::default() };
+        assert!(Context::new(bad, &BotConfig::default()).is_err());
+
+        let short = Context::new(
+            GameConfig {
+                little_blind: 5,
+                big_blind: 10,
+                stack_size: 100,
+                aggression_limit: 1,
+                ev_iterations: 100,
+            },
+            &BotConfig::default(),
+        )
+        .unwrap();
+        assert!(matches!(short.schema(Round::Flop).round, Round::Flop));
+
+        // Strategies trained under either don't load into the other
+        assert_eq!(default_context().abstraction_hash(), AuctionPokerAction::abstraction_hash());
+        assert_ne!(short.abstraction_hash(), AuctionPokerAction::abstraction_hash());
+        assert_eq!(AuctionPokerState::with_context(&short).abstraction_hash(), short.abstraction_hash());
+
+        // Both games are dealt and played in the same process
+        let settle = |game: &mut Game<AuctionPokerAction, AuctionPokerState>| loop {
+            let action = match game.active_player() {
+                ActivePlayer::Chance(actions) => actions.sample(),
+                ActivePlayer::Marker(action) => action,
+                _ => break,
+            };
+            game.play(&action);
+        };
+        let mut games = [
+            Game::<AuctionPokerAction, AuctionPokerState>::new(),
+            Game::<AuctionPokerAction, AuctionPokerState>::with_context(&short),
+        ];
+        games.iter_mut().for_each(settle);
+        let [normal, short_game] = &games;
+        assert_eq!((normal.state().pot(), normal.state().stacks()), (3, [399, 398]));
+        assert_eq!((short_game.state().pot(), short_game.state().stacks()), (15, [95, 90]));
+        let bounds = |game: &Game<AuctionPokerAction, AuctionPokerState>| game.state().utility_bounds().unwrap();
+        assert!(bounds(short_game).max < bounds(normal).max);
+
+        // One raise is all the short game allows
+        let raise = |game: &Game<AuctionPokerAction, AuctionPokerState>| match game.active_player() {
+            ActivePlayer::Player(_, actions) => actions.into_iter().find(|action| matches!(action, AuctionPokerAction::Raise(_))),
+            _ => None,
+        };
+        let mut raised = games.clone();
+        for game in raised.iter_mut() {
+            let action = raise(game).unwrap();
+            game.play(&action);
+            settle(game);
+        }
+        assert!(raise(&raised[0]).is_some());
+        assert!(raise(&raised[1]).is_none());
+
+        // Folding to it loses the short game's big blind, not the real one's
+        let mut folded = raised[1].clone();
+        folded.play(&AuctionPokerAction::Fold);
+        settle(&mut folded);
+        let big_blind = Chips(short.game.big_blind as f32).utility();
+        assert_eq!(folded.active_player(), ActivePlayer::Terminal(vec![big_blind, -big_blind]));
+
+        // Calling and playing on at random, every hand pays out within the short stacks
+        let mut rng = StdRng::seed_from_u64(0);
+        let most = Chips(short.game.stack_size as f32).utility();
+        for _ in 0..5 {
+            let mut game = raised[1].clone();
+            game.play(&AuctionPokerAction::Call);
+            let utilities = loop {
+                settle(&mut game);
+                match game.active_player() {
+                    ActivePlayer::Player(_, actions) => game.play(&actions[rng.gen_range(0, actions.len() as u32) as usize]),
+                    ActivePlayer::Terminal(utilities) => break utilities,
+                    _ => unreachable!(),
+                }
+            };
+            assert_eq!(utilities[0] + utilities[1], 0.0);
+            assert!(utilities[0].abs() <= most, "{:?}", utilities);
+        }
+    }
+}
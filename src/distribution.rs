@@ -3,6 +3,8 @@ use rand::{
     distributions::{Distribution, WeightedIndex},
     thread_rng, Rng,
 };
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 #[derive(Clone, Debug)]
 pub struct Categorical<T>(Vec<f32>, WeightedIndex<f32>, Vec<T>);
@@ -13,6 +15,24 @@ impl<T: PartialEq> PartialEq for Categorical<T> {
     }
 }
 
+/// Serialized as its probabilities and items, the sampler is built again
+impl<T: Serialize> Serialize for Categorical<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (&self.0, &self.2).serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Categorical<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (probs, items): (Vec<f32>, Vec<T>) = Deserialize::deserialize(deserializer)?;
+        if probs.len() != items.len() {
+            return Err(D::Error::custom(format!("{} probabilities for {} items", probs.len(), items.len())));
+        }
+        let sampler = WeightedIndex::new(&probs).map_err(|error| D::Error::custom(format!("{:?}: {}", probs, error)))?;
+        Ok(Categorical(probs, sampler, items))
+    }
+}
+
 impl<T> Categorical<T> {
     #[inline]
     pub fn items<'a>(&'a self) -> &'a Vec<T> {
@@ -67,13 +87,37 @@ impl<T> Categorical<T> {
         debug_assert!((ps.iter().sum::<f32>() - 1.0) < 1e-4, 
                       "invalid distribution: {:?}", ps);
         
-        let wi = WeightedIndex::new(&ps);
+        match Self::try_new(ps, is) {
+            Ok(categorical) => categorical,
+            Err(error) => panic!("new categorical: {}", error),
+        }
+    }
+
+    /// Like new, but the probabilities that can't be sampled from (all
+    /// zero, negative, NaN or infinite) are an error rather than a panic
+    pub fn try_new<IT: Into<Vec<T>>, IP: Into<Vec<f32>>>(probs: IP, items: IT) -> Result<Self, String> {
+        let ps: Vec<f32> = probs.into();
+        let is: Vec<T> = items.into();
+        if ps.len() != is.len() {
+            return Err(format!("{} probabilities for {} items", ps.len(), is.len()));
+        }
+        if let Some(p) = ps.iter().find(|p| !p.is_finite()) {
+            return Err(format!("{:?}: probability {} is not finite", ps, p));
+        }
+        match WeightedIndex::new(&ps) {
+            Ok(w) => Ok(Categorical(ps, w, is)),
+            Err(error) => Err(format!("{:?}: {}", ps, error)),
+        }
+    }
 
-        let w = match wi {
-            Err(w) => panic!("new categorical: {:?}", ps),
-            Ok(w) => w,
-        };
-        Categorical(ps, w, is)
+    /// Like new_normalized, see try_new
+    pub fn try_new_normalized<IT: Into<Vec<T>>, IP: Into<Vec<f32>>>(probs: IP, items: IT) -> Result<Self, String> {
+        let ps: Vec<f32> = probs.into();
+        let s: f32 = ps.iter().sum();
+        if s <= 0.0 || !s.is_finite() {
+            return Err(format!("{:?}: cannot normalize a total of {}", ps, s));
+        }
+        Self::try_new(ps.iter().map(|p| p / s).collect::<Vec<f32>>(), items)
     }
 
     pub fn with_mask(self, mask: &[bool]) -> Self {
@@ -135,3 +179,20 @@ pub fn sample_weighted<R: Rng>(ps: &[f32], rng: &mut R) -> usize {
     }
     return ps.len() - 1;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_probabilities_are_errors() {
+        assert!(Categorical::try_new(vec![0.5, 0.5], vec!['a', 'b']).is_ok());
+        let error = Categorical::try_new(vec![0.0, 0.0], vec!['a', 'b']).unwrap_err();
+        assert!(error.contains("[0.0, 0.0]"), "{}", error);
+        assert!(Categorical::try_new(vec![f32::NAN, 1.0], vec!['a', 'b']).is_err());
+        assert!(Categorical::try_new(vec![1.0], vec!['a', 'b']).is_err());
+        assert!(Categorical::try_new_normalized(vec![f32::INFINITY, 1.0], vec!['a', 'b']).is_err());
+        assert!(Categorical::try_new_normalized(vec![-1.0, 0.0], vec!['a', 'b']).is_err());
+        assert_eq!(Categorical::try_new_normalized(vec![3.0, 1.0], vec!['a', 'b']).unwrap().probs(), &vec![0.75, 0.25]);
+    }
+}
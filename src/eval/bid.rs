@@ -0,0 +1,60 @@
+use crate::eval::rank::HandRanker;
+
+/// Showdown equity of a hand on the flop depending on the
+/// outcome of the upcoming auction
+#[derive(Clone, Debug, PartialEq)]
+pub struct BidValue {
+    pub win: f64,  // Equity holding 3 cards against 2
+    pub loss: f64, // Equity holding 2 cards against 3
+}
+
+impl BidValue {
+    /// Estimate both auction outcomes with rollouts from the
+    /// current flop (hand must have 2 cards, board must have 3)
+    pub fn rollout(ranker: &HandRanker, hand: &[u8], board: &[u8], iterations: u32) -> BidValue {
+        debug_assert_eq!(hand.len(), 2, "Bid values are computed before the auction");
+        debug_assert_eq!(board.len(), 3, "The auction happens on the flop");
+        BidValue {
+            win: ranker.rollout_bid_win(hand, board, iterations),
+            loss: ranker.rollout_bid_loss(hand, board, iterations),
+        }
+    }
+
+    /// Marginal equity of receiving the third card.
+    /// Can be (slightly) negative due to rollout noise
+    pub fn marginal(&self) -> f64 {
+        self.win - self.loss
+    }
+
+    /// Value of winning the auction in chips, given the pot
+    /// we expect to be contested at showdown
+    pub fn chips(&self, pot: u32) -> f64 {
+        self.marginal() * pot as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_logic::action::Parsable;
+    use crate::implementations::auction::Card;
+
+    fn to_u8(cards: &[&str]) -> Vec<u8> {
+        cards
+            .iter()
+            .map(|c| Card::new(c).to_usize().unwrap() as u8)
+            .collect()
+    }
+
+    #[test]
+    fn test_drawing_hand_values_third_card() {
+        let ranker = HandRanker::new();
+        let hand = to_u8(&["9h", "8h"]);
+        let board = to_u8(&["Th", "7c", "2h"]);
+        let value = BidValue::rollout(&ranker, &hand, &board, 10_000);
+        assert!((0.0..=1.0).contains(&value.win) && (0.0..=1.0).contains(&value.loss), "{:?}", value);
+        // A third card is worth a lot to a straight and flush draw
+        assert!(value.marginal() > 0.2, "{:?}", value);
+        assert!((value.chips(100) - value.marginal() * 100.0).abs() < 1e-9);
+    }
+}
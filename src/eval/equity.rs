@@ -0,0 +1,236 @@
+/// Exact showdown equity against a range, by enumerating every opponent
+/// holding and every runout instead of sampling them. Only feasible late
+/// in the hand, so callers pass a limit on the number of showdowns and
+/// get None when the enumeration would be bigger than that
+///
+/// Used when the opponent is all in: there are no decisions left after
+/// ours, so equity against their range is all there is to know, and it's
+/// exactly the spot where rollout noise costs the most chips
+use crate::eval::rank::HandRanker;
+use crate::implementations::auction::*;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Equity {
+    pub win: f64,
+    pub tie: f64,
+}
+
+impl Equity {
+    /// Share of the pot we expect at showdown
+    pub fn share(&self) -> f64 {
+        self.win + self.tie / 2.0
+    }
+}
+
+/// Showdowns we can afford to enumerate for a single decision
+pub const MAX_SHOWDOWNS: u64 = 250_000;
+
+pub fn choose(n: usize, k: usize) -> u64 {
+    if k > n {
+        return 0;
+    }
+    (0..k).fold(1, |total, i| total * (n - i) as u64 / (i + 1) as u64)
+}
+
+/// Calls `f` with every k-subset of `items`
+pub fn for_each_combination(items: &[u8], k: usize, f: &mut dyn FnMut(&[u8])) {
+    fn recurse(items: &[u8], k: usize, chosen: &mut Vec<u8>, f: &mut dyn FnMut(&[u8])) {
+        if chosen.len() == k {
+            f(chosen);
+            return;
+        }
+        let needed = k - chosen.len();
+        for i in 0..=(items.len() - needed) {
+            chosen.push(items[i]);
+            recurse(&items[i + 1..], k, chosen, f);
+            chosen.pop();
+        }
+    }
+    if k <= items.len() {
+        recurse(items, k, &mut Vec::with_capacity(k), f);
+    }
+}
+
+/// Number of showdowns `exact_equity` would have to evaluate
+pub fn showdowns(hand: Deck, board: Deck, opponent_cards: usize) -> u64 {
+    let live = 52 - hand.len() - board.len();
+    choose(live, opponent_cards) * choose(live - opponent_cards, 5 - board.len())
+}
+
+/// Equity of `hand` on `board` against an opponent holding `opponent_cards`
+/// cards, weighted by `range` (1.0 for every holding is a uniform range).
+/// None if it would take more than `limit` showdowns or the range is empty
+pub fn exact_equity(
+    ranker: &HandRanker,
+    hand: Deck,
+    board: Deck,
+    opponent_cards: usize,
+    range: &dyn Fn(&[u8]) -> f64,
+    limit: u64,
+) -> Option<Equity> {
+    if showdowns(hand, board, opponent_cards) > limit {
+        return None;
+    }
+    let live = hand.union(board).complement().as_u8();
+    let mut holdings = vec![];
+    for_each_combination(&live, opponent_cards, &mut |cards| {
+        let weight = range(cards);
+        if weight > 0.0 {
+            let deck: Deck = cards.iter().map(|card| Card(*card)).collect();
+            holdings.push((deck, cards.to_vec(), weight));
+        }
+    });
+
+    let hand = hand.as_u8();
+    let (mut win, mut tie, mut total) = (0.0, 0.0, 0.0);
+    for_each_combination(&live, 5 - board.len(), &mut |runout| {
+        let runout_deck: Deck = runout.iter().map(|card| Card(*card)).collect();
+        let full_board = board.union(runout_deck).as_u8();
+        let ours = ranker.rank_showdown(&hand, &full_board);
+        for (deck, cards, weight) in holdings.iter() {
+            if deck.0 & runout_deck.0 != 0 {
+                continue;
+            }
+            match ours.cmp(&ranker.rank_showdown(cards, &full_board)) {
+                Ordering::Greater => win += weight,
+                Ordering::Equal => tie += weight,
+                Ordering::Less => {}
+            }
+            total += weight;
+        }
+    });
+
+    if total == 0.0 {
+        return None;
+    }
+    Some(Equity {
+        win: win / total,
+        tie: tie / total,
+    })
+}
+
+/// The strength of every holding of `opponent_cards` cards the opponent
+/// could have against `hand` on `board`: the share of those holdings it
+/// beats at showdown (ties counting half), averaged over the runouts. A
+/// holding picked at random is equally likely to have any strength, so
+/// strengths compare hands across boards. Keyed by the holding's Deck. None
+/// if it would take more than `limit` showdowns, as exact_equity
+pub fn hand_strengths(
+    ranker: &HandRanker,
+    hand: Deck,
+    board: Deck,
+    opponent_cards: usize,
+    limit: u64,
+) -> Option<HashMap<u64, f64>> {
+    if showdowns(hand, board, opponent_cards) > limit {
+        return None;
+    }
+    let live = hand.union(board).complement().as_u8();
+    let mut holdings = vec![];
+    for_each_combination(&live, opponent_cards, &mut |cards| {
+        let deck: Deck = cards.iter().map(|card| Card(*card)).collect();
+        holdings.push((deck, cards.to_vec()));
+    });
+
+    // Summed strengths and the runouts they are over, by holding
+    let mut totals = vec![(0.0, 0); holdings.len()];
+    for_each_combination(&live, 5 - board.len(), &mut |runout| {
+        let runout_deck: Deck = runout.iter().map(|card| Card(*card)).collect();
+        let full_board = board.union(runout_deck).as_u8();
+        let ranks: Vec<(usize, u32)> = holdings
+            .iter()
+            .enumerate()
+            .filter(|(_, (deck, _))| deck.0 & runout_deck.0 == 0)
+            .map(|(index, (_, cards))| (index, ranker.rank_showdown(cards, &full_board)))
+            .collect();
+        let mut sorted: Vec<u32> = ranks.iter().map(|(_, rank)| *rank).collect();
+        sorted.sort_unstable();
+        let others = (sorted.len() - 1).max(1) as f64;
+        for (index, rank) in ranks {
+            let below = sorted.partition_point(|other| *other < rank);
+            let ties = sorted.partition_point(|other| *other <= rank) - below - 1;
+            totals[index].0 += (below as f64 + ties as f64 / 2.0) / others;
+            totals[index].1 += 1;
+        }
+    });
+    Some(
+        holdings
+            .iter()
+            .zip(totals)
+            .filter(|(_, (_, runouts))| *runouts > 0)
+            .map(|((deck, _), (total, runouts))| (deck.0, total / runouts as f64))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deck(cards: &[&str]) -> Deck {
+        cards.iter().map(|card| Card::new(card)).collect()
+    }
+
+    #[test]
+    fn test_combinations() {
+        let mut seen = vec![];
+        for_each_combination(&[0, 1, 2, 3], 2, &mut |cards| seen.push(cards.to_vec()));
+        assert_eq!(seen.len() as u64, choose(4, 2));
+        assert_eq!(seen[0], vec![0, 1]);
+        assert_eq!(seen[5], vec![2, 3]);
+        assert_eq!(choose(47, 2), 1081);
+    }
+
+    #[test]
+    fn test_exact_equity_on_the_river() {
+        let ranker = HandRanker::new();
+        let uniform = |_: &[u8]| 1.0;
+
+        // The nuts can't lose
+        let equity = exact_equity(&ranker, deck(&["As", "Ks"]), deck(&["Qs", "Js", "Ts", "2c", "3d"]), 2, &uniform, MAX_SHOWDOWNS);
+        assert_eq!(equity, Some(Equity { win: 1.0, tie: 0.0 }));
+
+        // Nor can the board
+        let equity = exact_equity(&ranker, deck(&["2c", "3d"]), deck(&["As", "Ks", "Qs", "Js", "Ts"]), 3, &uniform, MAX_SHOWDOWNS);
+        assert_eq!(equity, Some(Equity { win: 0.0, tie: 1.0 }));
+
+        // Against a range of exactly one better hand
+        let aces = deck(&["Ah", "Ac"]);
+        let only_aces = move |cards: &[u8]| if cards.iter().all(|card| aces.contains(Card(*card))) { 1.0 } else { 0.0 };
+        let equity = exact_equity(&ranker, deck(&["Kh", "Kc"]), deck(&["2s", "7d", "9h", "Jc", "4s"]), 2, &only_aces, MAX_SHOWDOWNS);
+        assert_eq!(equity.unwrap().share(), 0.0);
+    }
+
+    #[test]
+    fn test_enumeration_is_limited() {
+        let ranker = HandRanker::new();
+        let uniform = |_: &[u8]| 1.0;
+        // Preflop is far too big to enumerate
+        assert!(showdowns(deck(&["As", "Ks"]), Deck::empty(), 2) > MAX_SHOWDOWNS);
+        assert_eq!(exact_equity(&ranker, deck(&["As", "Ks"]), Deck::empty(), 2, &uniform, MAX_SHOWDOWNS), None);
+        // The turn against two cards is not
+        let turn = deck(&["2s", "7d", "9h", "Jc"]);
+        assert!(showdowns(deck(&["As", "Ks"]), turn, 2) <= MAX_SHOWDOWNS);
+        assert_eq!(hand_strengths(&ranker, deck(&["As", "Ks"]), Deck::empty(), 2, MAX_SHOWDOWNS), None);
+    }
+
+    #[test]
+    fn test_hand_strengths_spread_over_the_holdings() {
+        let ranker = HandRanker::new();
+        let board = deck(&["2s", "7d", "9h", "Jc", "4s"]);
+        let strengths = hand_strengths(&ranker, deck(&["Kh", "Kc"]), board, 2, MAX_SHOWDOWNS).unwrap();
+        assert_eq!(strengths.len() as u64, choose(45, 2));
+        let mean = strengths.values().sum::<f64>() / strengths.len() as f64;
+        assert!((mean - 0.5).abs() < 0.01, "{}", mean);
+        // A set of jacks loses only to the straights, five high to nearly everything
+        assert!(strengths[&deck(&["Jh", "Jd"]).0] > 0.97);
+        assert!(strengths[&deck(&["3h", "5d"]).0] < 0.05);
+
+        // On the turn the river is averaged over
+        let turn = deck(&["2s", "7d", "9h", "Jc"]);
+        let strengths = hand_strengths(&ranker, deck(&["Kh", "Kc"]), turn, 2, MAX_SHOWDOWNS).unwrap();
+        assert!(strengths[&deck(&["Jh", "Jd"]).0] > strengths[&deck(&["Ah", "Ad"]).0]);
+    }
+}
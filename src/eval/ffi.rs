@@ -0,0 +1,269 @@
+/// The rank library (librank.so, built from SKPokerEval outside of this
+/// crate) through FFI, with the `ffi-eval` feature. Ranks and rollouts are
+/// the same as eval::native's, only faster. See eval::loader for where the
+/// library is looked for
+use crate::eval::loader;
+use libloading::{Library, Symbol};
+
+type Rank7 = unsafe extern "C" fn(u8, u8, u8, u8, u8, u8, u8) -> u32;
+type Rank8 = unsafe extern "C" fn(u8, u8, u8, u8, u8, u8, u8, u8) -> u32;
+
+pub struct HandRanker {
+    /// Looked up once in `load`, they point into `library` and so only
+    /// live as long as it does
+    rank7: Rank7,
+    rank8: Rank8,
+    library: Library,
+}
+
+impl HandRanker {
+    pub fn new() -> HandRanker {
+        match HandRanker::load() {
+            Ok(ranker) => ranker,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    pub fn load() -> Result<HandRanker, String> {
+        let library = loader::load("rank")?;
+        let missing = |error: libloading::Error| format!("The rank library is missing a function: {}", error);
+        unsafe {
+            Ok(HandRanker {
+                rank7: *library.get::<Rank7>(b"get_rank7").map_err(missing)?,
+                rank8: *library.get::<Rank8>(b"get_rank8").map_err(missing)?,
+                library: library,
+            })
+        }
+    }
+
+    pub fn rank7(&self, cards: &[u8]) -> u32 {
+        unsafe { (self.rank7)(cards[0], cards[1], cards[2], cards[3], cards[4], cards[5], cards[6]) }
+    }
+
+    pub fn rank8(&self, cards: &[u8]) -> u32 {
+        unsafe { (self.rank8)(cards[0], cards[1], cards[2], cards[3], cards[4], cards[5], cards[6], cards[7]) }
+    }
+
+    /// The ranks of two hands of 7 or 8 cards
+    pub fn rank_pair(&self, first: &[u8], second: &[u8]) -> (u32, u32) {
+        let rank = |cards: &[u8]| match cards.len() {
+            7 => self.rank7(cards),
+            8 => self.rank8(cards),
+            length => panic!("Invalid hand length {}", length),
+        };
+        (rank(first), rank(second))
+    }
+
+    // Preflop equity, only the tests need it
+    #[cfg(test)]
+    pub fn rollout_2_7(&self, cards: &[u8], iterations: u32) -> f64 {
+        unsafe {
+            let func: Symbol<unsafe extern "C" fn(u8, u8, u32) -> f64> =
+                self.library.get(b"rollout_2_7").unwrap();
+            func(cards[0], cards[1], iterations)
+        }
+    }
+
+    #[cfg(test)]
+    pub fn rollout_2_8(&self, cards: &[u8], iterations: u32) -> f64 {
+        unsafe {
+            let func: Symbol<unsafe extern "C" fn(u8, u8, u32) -> f64> =
+                self.library.get(b"rollout_2_8").unwrap();
+            func(cards[0], cards[1], iterations)
+        }
+    }
+
+    pub fn rollout_bid_win(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        unsafe {
+            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u32) -> f64> =
+                self.library.get(b"rollout_bid_win").unwrap();
+            func(
+                hand[0],
+                hand[1],
+                community_cards[0],
+                community_cards[1],
+                community_cards[2],
+                iterations,
+            )
+        }
+    }
+    pub fn rollout_bid_loss(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        unsafe {
+            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u32) -> f64> =
+                self.library.get(b"rollout_bid_loss").unwrap();
+            func(
+                hand[0],
+                hand[1],
+                community_cards[0],
+                community_cards[1],
+                community_cards[2],
+                iterations,
+            )
+        }
+    }
+
+    pub fn rollout_bid_tie(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        unsafe {
+            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u32) -> f64> =
+                self.library.get(b"rollout_bid_tie").unwrap();
+            func(
+                hand[0],
+                hand[1],
+                community_cards[0],
+                community_cards[1],
+                community_cards[2],
+                iterations,
+            )
+        }
+    }
+
+    pub fn rollout_flop_won(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        unsafe {
+            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u8, u32) -> f64> =
+                self.library.get(b"rollout_flop_won").unwrap();
+            func(
+                hand[0],
+                hand[1],
+                hand[2],
+                community_cards[0],
+                community_cards[1],
+                community_cards[2],
+                iterations,
+            )
+        }
+    }
+
+    pub fn rollout_flop_lost(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        unsafe {
+            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u32) -> f64> =
+                self.library.get(b"rollout_flop_lost").unwrap();
+            func(
+                hand[0],
+                hand[1],
+                community_cards[0],
+                community_cards[1],
+                community_cards[2],
+                iterations,
+            )
+        }
+    }
+
+    pub fn rollout_flop_tie(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        unsafe {
+            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u8, u32) -> f64> =
+                self.library.get(b"rollout_flop_tie").unwrap();
+            func(
+                hand[0],
+                hand[1],
+                hand[2],
+                community_cards[0],
+                community_cards[1],
+                community_cards[2],
+                iterations,
+            )
+        }
+    }
+
+    pub fn rollout_turn_won(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        unsafe {
+            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u8, u8, u32) -> f64> =
+                self.library.get(b"rollout_turn_won").unwrap();
+            func(
+                hand[0],
+                hand[1],
+                hand[2],
+                community_cards[0],
+                community_cards[1],
+                community_cards[2],
+                community_cards[3],
+                iterations,
+            )
+        }
+    }
+
+    pub fn rollout_turn_lost(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        unsafe {
+            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u8, u32) -> f64> =
+                self.library.get(b"rollout_turn_lost").unwrap();
+            func(
+                hand[0],
+                hand[1],
+                community_cards[0],
+                community_cards[1],
+                community_cards[2],
+                community_cards[3],
+                iterations,
+            )
+        }
+    }
+
+    pub fn rollout_turn_tie(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        unsafe {
+            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u8, u8, u32) -> f64> =
+                self.library.get(b"rollout_turn_tie").unwrap();
+            func(
+                hand[0],
+                hand[1],
+                hand[2],
+                community_cards[0],
+                community_cards[1],
+                community_cards[2],
+                community_cards[3],
+                iterations,
+            )
+        }
+    }
+
+    pub fn rollout_river_won(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        unsafe {
+            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u8, u8, u8, u32) -> f64> =
+                self.library.get(b"rollout_river_won").unwrap();
+            func(
+                hand[0],
+                hand[1],
+                hand[2],
+                community_cards[0],
+                community_cards[1],
+                community_cards[2],
+                community_cards[3],
+                community_cards[4],
+                iterations,
+            )
+        }
+    }
+
+    pub fn rollout_river_lost(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        unsafe {
+            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u8, u8, u32) -> f64> =
+                self.library.get(b"rollout_river_lost").unwrap();
+            func(
+                hand[0],
+                hand[1],
+                community_cards[0],
+                community_cards[1],
+                community_cards[2],
+                community_cards[3],
+                community_cards[4],
+                iterations,
+            )
+        }
+    }
+
+    pub fn rollout_river_tie(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        unsafe {
+            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u8, u8, u8, u32) -> f64> =
+                self.library.get(b"rollout_river_tie").unwrap();
+            func(
+                hand[0],
+                hand[1],
+                hand[2],
+                community_cards[0],
+                community_cards[1],
+                community_cards[2],
+                community_cards[3],
+                community_cards[4],
+                iterations,
+            )
+        }
+    }
+}
@@ -0,0 +1,91 @@
+/// Finding the native libraries of the `ffi-eval` build on any platform.
+///
+/// A library is named by its stem ("rank" for the rank library), which
+/// becomes librank.so on Linux, librank.dylib on macOS and rank.dll on
+/// Windows. It is looked for, in order:
+///
+/// - at GTCOGS_<STEM>_LIBRARY if set, a full path (GTCOGS_RANK_LIBRARY)
+/// - in every directory of GTCOGS_LIBRARY_PATH, separated as PATH is
+/// - in the working directory, then next to the executable, then in the
+///   crate directory it was built from
+///
+/// and if none loads the error lists every place tried and why
+use libloading::Library;
+use std::path::PathBuf;
+
+/// librank.so, librank.dylib or rank.dll
+pub fn file_name(stem: &str) -> String {
+    format!("{}{}{}", std::env::consts::DLL_PREFIX, stem, std::env::consts::DLL_SUFFIX)
+}
+
+/// The variable naming a library's path outright
+pub fn override_variable(stem: &str) -> String {
+    format!("GTCOGS_{}_LIBRARY", stem.to_uppercase())
+}
+
+/// The places to look for `stem`, in order
+pub fn candidates(stem: &str) -> Vec<PathBuf> {
+    let mut directories: Vec<PathBuf> = vec![];
+    if let Some(paths) = std::env::var_os("GTCOGS_LIBRARY_PATH") {
+        directories.extend(std::env::split_paths(&paths));
+    }
+    directories.push(PathBuf::from("."));
+    if let Some(directory) = std::env::current_exe().ok().and_then(|exe| exe.parent().map(PathBuf::from)) {
+        directories.push(directory);
+    }
+    directories.push(PathBuf::from(env!("CARGO_MANIFEST_DIR")));
+
+    let explicit = std::env::var_os(override_variable(stem)).map(PathBuf::from);
+    search(explicit, &directories, &file_name(stem))
+}
+
+fn search(explicit: Option<PathBuf>, directories: &[PathBuf], file_name: &str) -> Vec<PathBuf> {
+    let mut candidates: Vec<PathBuf> = explicit.into_iter().collect();
+    for directory in directories {
+        let candidate = directory.join(file_name);
+        if !candidates.contains(&candidate) {
+            candidates.push(candidate);
+        }
+    }
+    candidates
+}
+
+/// The first of `candidates(stem)` that loads
+pub fn load(stem: &str) -> Result<Library, String> {
+    let mut failures = vec![];
+    for candidate in candidates(stem) {
+        if !candidate.exists() {
+            failures.push(format!("{}: not found", candidate.display()));
+            continue;
+        }
+        match unsafe { Library::new(&candidate) } {
+            Ok(library) => return Ok(library),
+            Err(error) => failures.push(format!("{}: {}", candidate.display(), error)),
+        }
+    }
+    Err(format!(
+        "Cannot load {} (set {} or GTCOGS_LIBRARY_PATH, or build without ffi-eval). Tried\n  {}",
+        file_name(stem),
+        override_variable(stem),
+        failures.join("\n  ")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_library_search() {
+        assert!(file_name("rank").contains("rank"));
+        assert_eq!(override_variable("rank"), "GTCOGS_RANK_LIBRARY");
+
+        let directories = [PathBuf::from("."), PathBuf::from("lib"), PathBuf::from(".")];
+        let found = search(Some(PathBuf::from("/opt/rank.dll")), &directories, "librank.so");
+        assert_eq!(found, vec![PathBuf::from("/opt/rank.dll"), PathBuf::from("./librank.so"), PathBuf::from("lib/librank.so")]);
+
+        let error = load("no_such_library").unwrap_err();
+        assert!(error.contains("GTCOGS_NO_SUCH_LIBRARY_LIBRARY"), "{}", error);
+        assert!(error.contains("not found"), "{}", error);
+    }
+}
@@ -1 +1,9 @@
+pub mod bid;
+pub mod equity;
+#[cfg(feature = "ffi-eval")]
+pub mod ffi;
+#[cfg(feature = "ffi-eval")]
+pub mod loader;
+#[cfg(feature = "native-eval")]
+pub mod native;
 pub mod rank;
@@ -0,0 +1,328 @@
+/// The rank library in plain Rust, for builds without `ffi-eval` where no
+/// .so can be loaded.
+///
+/// A hand of 5 to 8 cards is reduced to the key of its best five: the
+/// category in the top bits, then the ranks that break ties between hands
+/// of that category, most important first. Keys order hands the way poker
+/// does, so numbering the 7462 keys of distinct five card hands from the
+/// worst up gives SKPokerEval's ranks exactly.
+///
+/// Rollouts deal the rest of the board and the opponent's hole cards at
+/// random, counting a tie as half a win. Whoever won the auction holds a
+/// third hole card; in the bid rollouts ours is dealt too, since we don't
+/// have it yet
+use rand::{thread_rng, Rng};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+const HIGH_CARD: u32 = 0;
+const PAIR: u32 = 1;
+const TWO_PAIR: u32 = 2;
+const TRIPS: u32 = 3;
+const STRAIGHT: u32 = 4;
+const FLUSH: u32 = 5;
+const FULL_HOUSE: u32 = 6;
+const QUADS: u32 = 7;
+const STRAIGHT_FLUSH: u32 = 8;
+
+/// Poker strength of a card, 0 for a two up to 12 for an ace (card indices
+/// put aces first, see implementations::cards)
+fn strength(card: u8) -> u32 {
+    12 - (card / 4) as u32
+}
+
+fn key(category: u32, ranks: impl IntoIterator<Item = u32>) -> u32 {
+    let mut ranks = ranks.into_iter();
+    (0..5).fold(category, |key, _| key << 4 | ranks.next().unwrap_or(0))
+}
+
+/// Highest card of the best straight in `mask` (bit i for strength i)
+fn straight(mask: u32) -> Option<u32> {
+    // The ace also plays low
+    let mask = mask << 1 | (mask >> 12 & 1);
+    (4..=13).rev().find(|top| mask >> (top - 4) & 0b11111 == 0b11111).map(|top| top - 1)
+}
+
+/// The strengths in `mask`, highest first
+fn highest(mask: u32) -> impl Iterator<Item = u32> {
+    (0..13).rev().filter(move |rank| mask >> rank & 1 == 1)
+}
+
+/// Key of the best five of `cards`
+fn best_key(cards: &[u8]) -> u32 {
+    let mut counts = [0u8; 13];
+    let mut suits = [0u32; 4];
+    for &card in cards {
+        counts[strength(card) as usize] += 1;
+        suits[(card % 4) as usize] |= 1 << strength(card);
+    }
+
+    // With eight cards a flush can still lose to quads or a full house
+    let flush = suits.iter().find(|mask| mask.count_ones() >= 5).copied();
+    if let Some(top) = flush.and_then(straight) {
+        return key(STRAIGHT_FLUSH, [top]);
+    }
+
+    // The strengths held at least `count` times, as a mask
+    let held = |count: u8| -> u32 { (0..13).filter(|&rank| counts[rank as usize] >= count).fold(0, |mask, rank| mask | 1 << rank) };
+    let (all, quads) = (held(1), held(4));
+    let (trips, pairs) = (held(3) & !quads, held(2) & !held(3));
+    let kickers = |except: u32, count: usize| highest(all & !except).take(count);
+
+    if let Some(quad) = highest(quads).next() {
+        return key(QUADS, std::iter::once(quad).chain(kickers(1 << quad, 1)));
+    }
+    if let Some(trip) = highest(trips).next() {
+        // The pair of a full house may be the lower of two trips
+        if let Some(pair) = highest((trips | pairs) & !(1 << trip)).next() {
+            return key(FULL_HOUSE, [trip, pair]);
+        }
+    }
+    if let Some(flush) = flush {
+        return key(FLUSH, highest(flush).take(5));
+    }
+    if let Some(top) = straight(all) {
+        return key(STRAIGHT, [top]);
+    }
+    if let Some(trip) = highest(trips).next() {
+        return key(TRIPS, std::iter::once(trip).chain(kickers(1 << trip, 2)));
+    }
+    let mut paired = highest(pairs);
+    match (paired.next(), paired.next()) {
+        (Some(high), Some(low)) => key(TWO_PAIR, [high, low].into_iter().chain(kickers(1 << high | 1 << low, 1))),
+        (Some(pair), None) => key(PAIR, std::iter::once(pair).chain(kickers(1 << pair, 3))),
+        _ => key(HIGH_CARD, highest(all).take(5)),
+    }
+}
+
+/// SKPokerEval's rank of every five card key, 1 for the worst hand
+fn ranks() -> &'static HashMap<u32, u32> {
+    static RANKS: OnceLock<HashMap<u32, u32>> = OnceLock::new();
+    RANKS.get_or_init(|| {
+        let mut keys = vec![];
+        let mut hand = [0u8; 5];
+        // One hand of every multiset of strengths, and one suited hand of
+        // every set of five strengths
+        fn deal(keys: &mut Vec<u32>, hand: &mut [u8; 5], dealt: usize, lowest: u8) {
+            if dealt == 5 {
+                let mut used = [0u8; 13];
+                let mut offsuit: Vec<u8> = hand
+                    .iter()
+                    .map(|&value| {
+                        used[value as usize] += 1;
+                        value * 4 + used[value as usize] - 1
+                    })
+                    .collect();
+                // Five different values would all be of the first suit
+                if used.iter().all(|count| *count <= 1) {
+                    offsuit[4] += 1;
+                }
+                keys.push(best_key(&offsuit));
+                if used.iter().all(|count| *count <= 1) {
+                    let suited: Vec<u8> = hand.iter().map(|value| value * 4).collect();
+                    keys.push(best_key(&suited));
+                }
+                return;
+            }
+            for value in lowest..13 {
+                let count = hand[..dealt].iter().filter(|dealt| **dealt == value).count();
+                if count < 4 {
+                    hand[dealt] = value;
+                    deal(keys, hand, dealt + 1, value);
+                }
+            }
+        }
+        deal(&mut keys, &mut hand, 0, 0);
+        keys.sort_unstable();
+        keys.dedup();
+        debug_assert_eq!(keys.len(), 7462);
+        keys.into_iter().enumerate().map(|(index, key)| (key, index as u32 + 1)).collect()
+    })
+}
+
+pub struct HandRanker;
+
+impl HandRanker {
+    pub fn new() -> HandRanker {
+        HandRanker
+    }
+
+    /// SKPokerEval's rank of the best five of `cards`
+    pub fn rank(&self, cards: &[u8]) -> u32 {
+        ranks()[&best_key(cards)]
+    }
+
+    pub fn rank7(&self, cards: &[u8]) -> u32 {
+        self.rank(&cards[..7])
+    }
+
+    pub fn rank8(&self, cards: &[u8]) -> u32 {
+        self.rank(&cards[..8])
+    }
+
+    /// The ranks of two hands of 7 or 8 cards
+    pub fn rank_pair(&self, first: &[u8], second: &[u8]) -> (u32, u32) {
+        let ranks = ranks();
+        (ranks[&best_key(first)], ranks[&best_key(second)])
+    }
+
+    /// Our equity holding `hand` plus `extra` more cards, against an
+    /// opponent holding `opponent` cards, once the board is dealt out
+    fn equity(&self, hand: &[u8], extra: usize, board: &[u8], opponent: usize, iterations: u32) -> f64 {
+        let mut rng = thread_rng();
+        let mut deck = [0u8; 52];
+        let mut size = 0;
+        for card in (0..52).filter(|card| !hand.contains(card) && !board.contains(card)) {
+            deck[size] = card;
+            size += 1;
+        }
+        let deck = &mut deck[..size];
+        let missing = 5 - board.len();
+        let dealt = extra + missing + opponent;
+        // Both hands start with the board, the runout and hole cards go after it
+        let mut ours = [0u8; 8];
+        let mut theirs = [0u8; 8];
+        ours[..board.len()].copy_from_slice(board);
+        theirs[..board.len()].copy_from_slice(board);
+        ours[5..][..hand.len()].copy_from_slice(hand);
+        let our_length = 5 + hand.len() + extra;
+        let mut won = 0.0;
+        for _ in 0..iterations {
+            // Whatever order the deck was left in, a partial shuffle deals
+            // `dealt` cards at random from it
+            for index in 0..dealt {
+                let pick = rng.gen_range(index as u32, deck.len() as u32) as usize;
+                deck.swap(index, pick);
+            }
+            let (runout, rest) = deck[..dealt].split_at(missing);
+            let (our_extra, their_hole) = rest.split_at(extra);
+            ours[board.len()..5].copy_from_slice(runout);
+            theirs[board.len()..5].copy_from_slice(runout);
+            ours[5 + hand.len()..our_length].copy_from_slice(our_extra);
+            theirs[5..5 + opponent].copy_from_slice(their_hole);
+            won += match self.rank(&ours[..our_length]).cmp(&self.rank(&theirs[..5 + opponent])) {
+                std::cmp::Ordering::Greater => 1.0,
+                std::cmp::Ordering::Equal => 0.5,
+                std::cmp::Ordering::Less => 0.0,
+            };
+        }
+        won / iterations.max(1) as f64
+    }
+
+    // Preflop equity, only the tests need it
+    #[cfg(test)]
+    pub fn rollout_2_7(&self, cards: &[u8], iterations: u32) -> f64 {
+        self.equity(&cards[..2], 0, &[], 2, iterations)
+    }
+
+    #[cfg(test)]
+    pub fn rollout_2_8(&self, cards: &[u8], iterations: u32) -> f64 {
+        self.equity(&cards[..2], 1, &[], 2, iterations)
+    }
+
+    pub fn rollout_bid_win(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        self.equity(&hand[..2], 1, &community_cards[..3], 2, iterations)
+    }
+
+    pub fn rollout_bid_loss(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        self.equity(&hand[..2], 0, &community_cards[..3], 3, iterations)
+    }
+
+    pub fn rollout_bid_tie(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        self.equity(&hand[..2], 1, &community_cards[..3], 3, iterations)
+    }
+
+    pub fn rollout_flop_won(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        self.equity(&hand[..3], 0, &community_cards[..3], 2, iterations)
+    }
+
+    pub fn rollout_flop_lost(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        self.equity(&hand[..2], 0, &community_cards[..3], 3, iterations)
+    }
+
+    pub fn rollout_flop_tie(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        self.equity(&hand[..3], 0, &community_cards[..3], 3, iterations)
+    }
+
+    pub fn rollout_turn_won(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        self.equity(&hand[..3], 0, &community_cards[..4], 2, iterations)
+    }
+
+    pub fn rollout_turn_lost(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        self.equity(&hand[..2], 0, &community_cards[..4], 3, iterations)
+    }
+
+    pub fn rollout_turn_tie(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        self.equity(&hand[..3], 0, &community_cards[..4], 3, iterations)
+    }
+
+    pub fn rollout_river_won(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        self.equity(&hand[..3], 0, &community_cards[..5], 2, iterations)
+    }
+
+    pub fn rollout_river_lost(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        self.equity(&hand[..2], 0, &community_cards[..5], 3, iterations)
+    }
+
+    pub fn rollout_river_tie(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        self.equity(&hand[..3], 0, &community_cards[..5], 3, iterations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::implementations::auction::Card;
+
+    fn cards(names: &[&str]) -> Vec<u8> {
+        names.iter().map(|name| Card::new(name).as_u8()).collect()
+    }
+
+    #[test]
+    fn test_native_ranks_are_skpokereval_ranks() {
+        let ranker = HandRanker::new();
+        assert_eq!(ranks().len(), 7462);
+        // Royal flush, then the worst hand there is (7 5 4 3 2 offsuit)
+        assert_eq!(ranker.rank(&cards(&["As", "Ks", "Qs", "Js", "Ts"])), 7462);
+        assert_eq!(ranker.rank(&cards(&["7h", "5d", "4c", "3s", "2h"])), 1);
+        // The wheel is the lowest straight, and two trips make a full house
+        let wheel = ranker.rank(&cards(&["Ah", "2d", "3c", "4s", "5h", "9d", "9c"]));
+        let six_high = ranker.rank(&cards(&["6h", "2d", "3c", "4s", "5h", "9d", "9c"]));
+        assert!(wheel < six_high);
+        let trips = cards(&["9h", "9d", "9c", "4s", "4h", "4d", "Kc"]);
+        assert_eq!(ranker.rank7(&trips), ranker.rank(&cards(&["9h", "9d", "9c", "4s", "4h"])));
+        let flush_and_quads = cards(&["2h", "5h", "8h", "Jh", "Kh", "Kd", "Kc", "Ks"]);
+        assert_eq!(ranker.rank8(&flush_and_quads), ranker.rank(&cards(&["Kh", "Kd", "Kc", "Ks", "Jh"])));
+    }
+
+    /// Ranks of hands dealt at random agree with the rank library's. It
+    /// has no five card entry, so the best five of seven cards ranked here
+    /// stand in for it
+    #[cfg(feature = "ffi-eval")]
+    #[test]
+    fn test_native_ranks_match_the_rank_library() {
+        use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+        let native = HandRanker::new();
+        let library = crate::eval::ffi::HandRanker::new();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut deck: Vec<u8> = (0..52).collect();
+        for _ in 0..2_000 {
+            deck.shuffle(&mut rng);
+            let (seven, eight) = (&deck[..7], &deck[..8]);
+            assert_eq!(native.rank7(seven), library.rank7(seven), "{:?}", seven);
+            assert_eq!(native.rank8(eight), library.rank8(eight), "{:?}", eight);
+            let best_five = (0..7)
+                .flat_map(|first| (first + 1..7).map(move |second| (first, second)))
+                .map(|(first, second)| {
+                    let mut five = [0u8; 5];
+                    let kept = (0..7).filter(|index| *index != first && *index != second);
+                    for (card, index) in five.iter_mut().zip(kept) {
+                        *card = seven[index];
+                    }
+                    native.rank(&five)
+                })
+                .max();
+            assert_eq!(best_five, Some(library.rank7(seven)), "{:?}", seven);
+        }
+    }
+}
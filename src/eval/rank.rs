@@ -1,253 +1,129 @@
-use crate::game_logic::action::Parsable;
-use crate::implementations::auction::Card;
-use libloading::{Library, Symbol};
-use std::time::{Duration, Instant};
+/// Hand ranks and equity rollouts, by the rank library through FFI with
+/// the `ffi-eval` feature (see eval::ffi) and in plain Rust otherwise (see
+/// eval::native), for sandboxes that won't load a .so. Either way a rank
+/// is SKPokerEval's, higher is better
+use crate::game_logic::visibility::Round;
+use std::cmp::Ordering;
+use std::fmt;
 
-pub struct HandRanker {
-    library: Library,
-}
-
-impl HandRanker {
-    pub fn new() -> HandRanker {
-        unsafe {
-            let library = Library::new("./librank.so").unwrap();
-            HandRanker { library }
-        }
-    }
-
-    pub fn rank7(&self, cards: &[u8]) -> u32 {
-        unsafe {
-            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u8, u8) -> u32> =
-                self.library.get(b"get_rank7").unwrap();
-            func(
-                cards[0], cards[1], cards[2], cards[3], cards[4], cards[5], cards[6],
-            )
-        }
-    }
-
-    pub fn rank8(&self, cards: &[u8]) -> u32 {
-        unsafe {
-            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u8, u8, u8) -> u32> =
-                self.library.get(b"get_rank8").unwrap();
-            func(
-                cards[0], cards[1], cards[2], cards[3], cards[4], cards[5], cards[6], cards[7],
-            )
-        }
-    }
-
-    pub fn rollout_2_7(&self, cards: &[u8], iterations: u32) -> f64 {
-        unsafe {
-            let func: Symbol<unsafe extern "C" fn(u8, u8, u32) -> f64> =
-                self.library.get(b"rollout_2_7").unwrap();
-            func(cards[0], cards[1], iterations)
-        }
-    }
-    pub fn rollout_2_8(&self, cards: &[u8], iterations: u32) -> f64 {
-        unsafe {
-            let func: Symbol<unsafe extern "C" fn(u8, u8, u32) -> f64> =
-                self.library.get(b"rollout_2_8").unwrap();
-            func(cards[0], cards[1], iterations)
-        }
-    }
+#[cfg(feature = "ffi-eval")]
+pub use crate::eval::ffi::HandRanker;
+#[cfg(not(feature = "ffi-eval"))]
+pub use crate::eval::native::HandRanker;
 
-    pub fn rollout_bid_win(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
-        unsafe {
-            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u32) -> f64> =
-                self.library.get(b"rollout_bid_win").unwrap();
-            func(
-                hand[0],
-                hand[1],
-                community_cards[0],
-                community_cards[1],
-                community_cards[2],
-                iterations,
-            )
-        }
-    }
-    pub fn rollout_bid_loss(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
-        unsafe {
-            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u32) -> f64> =
-                self.library.get(b"rollout_bid_loss").unwrap();
-            func(
-                hand[0],
-                hand[1],
-                community_cards[0],
-                community_cards[1],
-                community_cards[2],
-                iterations,
-            )
-        }
-    }
+#[cfg(not(any(feature = "ffi-eval", feature = "native-eval")))]
+compile_error!("Enable native-eval or ffi-eval to rank hands");
 
-    pub fn rollout_bid_tie(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
-        unsafe {
-            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u32) -> f64> =
-                self.library.get(b"rollout_bid_tie").unwrap();
-            func(
-                hand[0],
-                hand[1],
-                community_cards[0],
-                community_cards[1],
-                community_cards[2],
-                iterations,
-            )
-        }
-    }
+/// How the auction went for the player the rollout is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuctionOutcome {
+    Won,
+    Lost,
+    Tie,
+}
 
-    pub fn rollout_flop_won(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
-        unsafe {
-            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u8, u32) -> f64> =
-                self.library.get(b"rollout_flop_won").unwrap();
-            func(
-                hand[0],
-                hand[1],
-                hand[2],
-                community_cards[0],
-                community_cards[1],
-                community_cards[2],
-                iterations,
-            )
-        }
-    }
+/// Which rollout of the rank library to run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RolloutKind {
+    /// Before the auction, equity if it goes as given
+    Bid(AuctionOutcome),
+    /// Equity on the flop, turn or river after the auction
+    Street(StreetRound, AuctionOutcome),
+}
 
-    pub fn rollout_flop_lost(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
-        unsafe {
-            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u32) -> f64> =
-                self.library.get(b"rollout_flop_lost").unwrap();
-            func(
-                hand[0],
-                hand[1],
-                community_cards[0],
-                community_cards[1],
-                community_cards[2],
-                iterations,
-            )
-        }
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreetRound {
+    Flop,
+    Turn,
+    River,
+}
 
-    pub fn rollout_flop_tie(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
-        unsafe {
-            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u8, u32) -> f64> =
-                self.library.get(b"rollout_flop_tie").unwrap();
-            func(
-                hand[0],
-                hand[1],
-                hand[2],
-                community_cards[0],
-                community_cards[1],
-                community_cards[2],
-                iterations,
-            )
+impl StreetRound {
+    pub fn from_round(round: &Round) -> Option<StreetRound> {
+        match round {
+            Round::Flop => Some(StreetRound::Flop),
+            Round::Turn => Some(StreetRound::Turn),
+            Round::River => Some(StreetRound::River),
+            _ => None,
         }
     }
+}
 
-    pub fn rollout_turn_won(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
-        unsafe {
-            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u8, u8, u32) -> f64> =
-                self.library.get(b"rollout_turn_won").unwrap();
-            func(
-                hand[0],
-                hand[1],
-                hand[2],
-                community_cards[0],
-                community_cards[1],
-                community_cards[2],
-                community_cards[3],
-                iterations,
-            )
-        }
+/// The FFI ranker holds the loaded library, which has nothing to show
+impl fmt::Debug for HandRanker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HandRanker")
     }
+}
 
-    pub fn rollout_turn_lost(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
-        unsafe {
-            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u8, u32) -> f64> =
-                self.library.get(b"rollout_turn_lost").unwrap();
-            func(
-                hand[0],
-                hand[1],
-                community_cards[0],
-                community_cards[1],
-                community_cards[2],
-                community_cards[3],
-                iterations,
-            )
+impl HandRanker {
+    /// Rank the best hand made from hole cards and board, where the hole
+    /// cards may be 2 or 3 cards depending on the auction outcome
+    pub fn rank_showdown(&self, hole_cards: &[u8], board: &[u8]) -> u32 {
+        let (cards, length) = showdown_cards(hole_cards, board);
+        match length {
+            7 => self.rank7(&cards),
+            _ => self.rank8(&cards),
         }
     }
 
-    pub fn rollout_turn_tie(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
-        unsafe {
-            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u8, u8, u32) -> f64> =
-                self.library.get(b"rollout_turn_tie").unwrap();
-            func(
-                hand[0],
-                hand[1],
-                hand[2],
-                community_cards[0],
-                community_cards[1],
-                community_cards[2],
-                community_cards[3],
-                iterations,
-            )
-        }
+    /// The ranks of the showdown hands of player 0 and player 1 on one
+    /// board, each holding 2 or 3 hole cards. Both are ranked in one call
+    /// to the backend, without building a hand on the heap
+    pub fn rank_showdown_pair(&self, hand0: &[u8], hand1: &[u8], board: &[u8]) -> (u32, u32) {
+        let (cards0, length0) = showdown_cards(hand0, board);
+        let (cards1, length1) = showdown_cards(hand1, board);
+        self.rank_pair(&cards0[..length0], &cards1[..length1])
     }
 
-    pub fn rollout_river_won(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
-        unsafe {
-            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u8, u8, u8, u32) -> f64> =
-                self.library.get(b"rollout_river_won").unwrap();
-            func(
-                hand[0],
-                hand[1],
-                hand[2],
-                community_cards[0],
-                community_cards[1],
-                community_cards[2],
-                community_cards[3],
-                community_cards[4],
-                iterations,
-            )
-        }
+    /// Compare the showdown hands of player 0 and player 1, each holding
+    /// 2 or 3 hole cards. Greater means player 0 wins
+    pub fn compare_showdown(&self, hand0: &[u8], hand1: &[u8], board: &[u8]) -> Ordering {
+        let (rank0, rank1) = self.rank_showdown_pair(hand0, hand1, board);
+        rank0.cmp(&rank1)
     }
 
-    pub fn rollout_river_lost(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
-        unsafe {
-            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u8, u8, u32) -> f64> =
-                self.library.get(b"rollout_river_lost").unwrap();
-            func(
-                hand[0],
-                hand[1],
-                community_cards[0],
-                community_cards[1],
-                community_cards[2],
-                community_cards[3],
-                community_cards[4],
-                iterations,
-            )
-        }
+    /// The rollout `kind` names
+    pub fn rollout(&self, kind: RolloutKind, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
+        use AuctionOutcome::*;
+        use StreetRound::*;
+        let rollout = match kind {
+            RolloutKind::Bid(Won) => HandRanker::rollout_bid_win,
+            RolloutKind::Bid(Lost) => HandRanker::rollout_bid_loss,
+            RolloutKind::Bid(Tie) => HandRanker::rollout_bid_tie,
+            RolloutKind::Street(Flop, Won) => HandRanker::rollout_flop_won,
+            RolloutKind::Street(Flop, Lost) => HandRanker::rollout_flop_lost,
+            RolloutKind::Street(Flop, Tie) => HandRanker::rollout_flop_tie,
+            RolloutKind::Street(Turn, Won) => HandRanker::rollout_turn_won,
+            RolloutKind::Street(Turn, Lost) => HandRanker::rollout_turn_lost,
+            RolloutKind::Street(Turn, Tie) => HandRanker::rollout_turn_tie,
+            RolloutKind::Street(River, Won) => HandRanker::rollout_river_won,
+            RolloutKind::Street(River, Lost) => HandRanker::rollout_river_lost,
+            RolloutKind::Street(River, Tie) => HandRanker::rollout_river_tie,
+        };
+        rollout(self, hand, community_cards, iterations)
     }
+}
 
-    pub fn rollout_river_tie(&self, hand: &[u8], community_cards: &[u8], iterations: u32) -> f64 {
-        unsafe {
-            let func: Symbol<unsafe extern "C" fn(u8, u8, u8, u8, u8, u8, u8, u8, u32) -> f64> =
-                self.library.get(b"rollout_river_tie").unwrap();
-            func(
-                hand[0],
-                hand[1],
-                hand[2],
-                community_cards[0],
-                community_cards[1],
-                community_cards[2],
-                community_cards[3],
-                community_cards[4],
-                iterations,
-            )
-        }
+/// The hole cards followed by the board, and how many of them there are
+fn showdown_cards(hole_cards: &[u8], board: &[u8]) -> ([u8; 8], usize) {
+    debug_assert_eq!(board.len(), 5, "Showdowns happen with a full board");
+    let length = hole_cards.len() + board.len();
+    if length != 7 && length != 8 {
+        panic!("Invalid hand + community length {}", length);
     }
+    let mut cards = [0; 8];
+    cards[..hole_cards.len()].copy_from_slice(hole_cards);
+    cards[hole_cards.len()..length].copy_from_slice(board);
+    (cards, length)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::game_logic::action::Parsable;
+    use crate::implementations::auction::Card;
+    use std::time::Instant;
     #[test]
     fn test_tie_win_loss_strengths() {
         let hand_ranker = HandRanker::new();
@@ -400,6 +276,30 @@ mod tests {
         assert_eq!(rank, rank7);
     }
 
+    #[test]
+    fn test_compare_showdown_asymmetric_hands() {
+        let hand_ranker = HandRanker::new();
+        let to_u8 = |cards: &[&str]| -> Vec<u8> {
+            cards
+                .iter()
+                .map(|c| Card::new(c).to_usize().unwrap() as u8)
+                .collect()
+        };
+        let board = to_u8(&["2c", "7d", "9h", "Js", "4c"]);
+        let pair = to_u8(&["Kc", "Kd"]);
+        let trips = to_u8(&["Ah", "Ad", "Ac"]);
+        let junk = to_u8(&["3h", "5d", "8s"]);
+        assert_eq!(hand_ranker.compare_showdown(&trips, &pair, &board), Ordering::Greater);
+        assert_eq!(hand_ranker.compare_showdown(&junk, &pair, &board), Ordering::Less);
+        assert_eq!(hand_ranker.compare_showdown(&pair, &pair, &board), Ordering::Equal);
+
+        // Ranked together or one by one, the ranks are the same
+        for (hand0, hand1) in [(&trips, &pair), (&pair, &junk), (&pair, &pair), (&trips, &junk)] {
+            let apart = (hand_ranker.rank_showdown(hand0, &board), hand_ranker.rank_showdown(hand1, &board));
+            assert_eq!(hand_ranker.rank_showdown_pair(hand0, hand1, &board), apart);
+        }
+    }
+
     #[test]
     fn test_rank8() {
         let hand_ranker = HandRanker::new();
@@ -503,4 +403,16 @@ mod tests {
         assert!(hand_ranker.rank7(&royal_flush) > hand_ranker.rank7(&lower_high_card));
         // If these tests pass, you're probably using the SKPokerEval library correctly!
     }
+
+    #[test]
+    fn test_rollout_kinds() {
+        let cards = |names: &[&str]| -> Vec<u8> { names.iter().map(|name| Card::new(name).to_usize().unwrap() as u8).collect() };
+        let hand_ranker = HandRanker::new();
+        let board = cards(&["Qs", "Js", "Ts", "2c", "3d"]);
+        let river = |outcome| RolloutKind::Street(StreetRound::River, outcome);
+        assert!(hand_ranker.rollout(river(AuctionOutcome::Lost), &cards(&["As", "Ks"]), &board, 200) > 0.99);
+        assert!(hand_ranker.rollout(river(AuctionOutcome::Won), &cards(&["As", "Ks", "2h"]), &board, 200) > 0.99);
+        assert!(hand_ranker.rollout(river(AuctionOutcome::Lost), &cards(&["4h", "5h"]), &board, 200) < 0.5);
+        assert_eq!(StreetRound::from_round(&Round::Auction), None);
+    }
 }
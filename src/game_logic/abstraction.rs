@@ -0,0 +1,87 @@
+/// Fingerprints of the abstraction a strategy was trained under.
+///
+/// A strategy file is only meaningful alongside the exact action buckets
+/// and feature layout used to train it: an ActionIndex or feature value
+/// from one abstraction means something entirely different in another,
+/// and nothing in the file itself would tell us. So every saved strategy
+/// carries a hash of its abstraction, and loading checks it.
+///
+/// The hash has to be the same across runs, machines and compiler
+/// versions, so we use FNV-1a over explicit little endian bytes rather
+/// than std's DefaultHasher.
+pub struct AbstractionHasher(u64);
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+impl AbstractionHasher {
+    pub fn new() -> Self {
+        AbstractionHasher(FNV_OFFSET)
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    /// Strings are length prefixed so ("ab", "c") and ("a", "bc") differ
+    pub fn write_str(&mut self, value: &str) {
+        self.write_u64(value.len() as u64);
+        self.write_bytes(value.as_bytes());
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Compares the abstraction a strategy was saved with against the one we
+/// are running. Strategies saved before hashes existed only get a warning,
+/// but a strategy from a different abstraction is refused outright
+pub fn check_abstraction(file_name: &str, expected: u64, found: Option<u64>) {
+    match found {
+        Some(found) if found == expected => {}
+        Some(found) => panic!(
+            "{} was trained under abstraction {:016x} but this build uses {:016x}, refusing to load it",
+            file_name, found, expected
+        ),
+        None => println!(
+            "[WARNING] {} has no abstraction hash, cannot check it matches {:016x}",
+            file_name, expected
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_is_stable() {
+        // Known FNV-1a values, if these change every saved strategy is invalidated
+        assert_eq!(AbstractionHasher::new().finish(), 0xcbf29ce484222325);
+        let mut hasher = AbstractionHasher::new();
+        hasher.write_bytes(b"a");
+        assert_eq!(hasher.finish(), 0xaf63dc4c8601ec8c);
+
+        let mut split0 = AbstractionHasher::new();
+        split0.write_str("ab");
+        split0.write_str("c");
+        let mut split1 = AbstractionHasher::new();
+        split1.write_str("a");
+        split1.write_str("bc");
+        assert_ne!(split0.finish(), split1.finish());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_mismatched_abstraction_is_refused() {
+        check_abstraction("test.json", 1, Some(2));
+    }
+}
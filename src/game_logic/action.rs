@@ -13,6 +13,36 @@ pub trait Action: Clone + Debug + Filterable + Into<ActionIndex> + From<ActionIn
     fn index(&self) -> ActionIndex {
         self.clone().into()
     }
+
+    /// Fingerprint of the abstraction (action buckets, features...) this
+    /// game is trained under, saved alongside every strategy.
+    /// Games without any abstraction can leave this as 0
+    fn abstraction_hash() -> u64 {
+        0
+    }
+
+    /// Size of the action as a fraction of the pot, for actions that have
+    /// one. Used to pick between the raw actions of an abstract group
+    fn pot_fraction(&self) -> Option<f32> {
+        None
+    }
+}
+
+/// Checks that every index below max_index decodes to an action that
+/// encodes back to the same index, i.e. that From<ActionIndex> and
+/// Into<ActionIndex> agree on the abstraction
+pub fn check_index_round_trip<A: Action>() -> Result<(), String> {
+    for index in 0..A::max_index() {
+        let action = A::from(index);
+        let round_trip = action.index();
+        if round_trip != index {
+            return Err(format!(
+                "Index {} decodes to {:?} which encodes to {}",
+                index, action, round_trip
+            ));
+        }
+    }
+    Ok(())
 }
 
 pub type ActionFilter<A> = (Filter<A>, A);
@@ -20,23 +50,37 @@ pub type ActionFilter<A> = (Filter<A>, A);
 #[derive(Debug, Clone)]
 pub struct ActionMapper<A: Action> {
     filters: Vec<ActionFilter<A>>,
+    /// The filters above compiled once, they are what actions are matched
+    /// against since a mapper is consulted for every action of every node
+    compiled: Vec<CompiledFilter<A>>,
 }
 
 impl<A: Action> ActionMapper<A> {
     pub fn new() -> Self {
         ActionMapper {
             filters: Vec::new(),
+            compiled: Vec::new(),
         }
     }
     pub fn add_filter(&mut self, filter: Filter<A>, action: A) {
+        self.compiled.push(filter.compile());
         self.filters.push((filter, action));
     }
 
+    fn matching(&self, action: &A) -> Option<usize> {
+        self.compiled.iter().position(|filter| filter.accepts(action))
+    }
+
+    /// The action `action` maps to and the index of the filter that took
+    /// it, None if no filter accepts it
+    pub fn find(&self, action: &A) -> Option<(A, ActionIndex)> {
+        self.matching(action)
+            .map(|index| (self.filters[index].1.clone(), index as ActionIndex))
+    }
+
     pub fn map_and_index(&self, action: A) -> (A, ActionIndex) {
-        for (index, (filter, mapped_action)) in self.filters.iter().enumerate() {
-            if filter.accepts(&action) {
-                return (mapped_action.clone(), index as ActionIndex);
-            }
+        if let Some(found) = self.find(&action) {
+            return found;
         }
         panic!(
             "No filter matched action, check that your filters span the entire action space!! {:?}",
@@ -49,15 +93,14 @@ impl<A: Action> ActionMapper<A> {
     /// all actions must map to
     /// the same action index after filtering
     pub fn map(&self, action: A) -> A {
-        for (filter, mapped_action) in &self.filters {
-            if filter.accepts(&action) {
-                debug_assert!({
-                    let mapped_action_index: ActionIndex = mapped_action.clone().into();
-                    let action_index: ActionIndex = action.clone().into();
-                    mapped_action_index == action_index
-                });
-                return mapped_action.clone();
-            }
+        if let Some(index) = self.matching(&action) {
+            let mapped_action = &self.filters[index].1;
+            debug_assert!({
+                let mapped_action_index: ActionIndex = mapped_action.clone().into();
+                let action_index: ActionIndex = action.clone().into();
+                mapped_action_index == action_index
+            });
+            return mapped_action.clone();
         }
         panic!(
             "No filter matched action, check that your filters span the entire action space!! {:?}",
@@ -66,10 +109,8 @@ impl<A: Action> ActionMapper<A> {
     }
 
     pub fn to_index(&self, action: A) -> ActionIndex {
-        for (index, (filter, _)) in self.filters.iter().enumerate() {
-            if filter.accepts(&action) {
-                return index as ActionIndex;
-            }
+        if let Some(index) = self.matching(&action) {
+            return index as ActionIndex;
         }
         panic!(
             "No filter matched action, check that your filters span the entire action space!! {:?}",
@@ -82,6 +123,27 @@ impl<A: Action> ActionMapper<A> {
     }
 }
 
+/// Which member of an abstract group map_actions keeps, i.e. which raw
+/// action gets played when the abstraction picks a bucket
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Representative {
+    /// One of the few members around the median, at random
+    Median,
+    /// The member closest to the geometric mean of the group's pot fractions
+    GeometricMean,
+    /// The member closest to the pot fraction the bucket's index decodes to
+    PotFraction,
+}
+
+/// The member of `group` whose pot fraction is closest to `target`
+fn closest_to<A: Action>(group: &[A], target: f32) -> Option<A> {
+    group
+        .iter()
+        .filter_map(|action| action.pot_fraction().map(|fraction| ((fraction - target).abs(), action)))
+        .min_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, action)| action.clone())
+}
+
 /// May contain a filter for each depth of the game
 /// if no filter is present for a given depth, actions
 /// are mapped to themselves
@@ -90,6 +152,7 @@ pub struct GameMapper<A: Filterable + Action> {
     depth_specific_maps: Vec<Option<ActionMapper<A>>>,
     recall_depth: usize,
     max_encoding_size: usize,
+    representative: Representative,
 }
 
 /// TODO: The indexing is weird (don't know if game_mapper indexes correctly)
@@ -102,7 +165,8 @@ impl<A: Filterable + Action> GameMapper<A> {
         GameMapper {
             depth_specific_maps: vec![None; MAX_GAME_DEPTH],
             recall_depth,
-            max_encoding_size: HOT_ENCODING_SIZE,
+            max_encoding_size: Self::passthrough_encoding_size(),
+            representative: Representative::Median,
         }
     }
     /// Create a GameMapper with a given default mapping for all depths
@@ -113,9 +177,54 @@ impl<A: Filterable + Action> GameMapper<A> {
             depth_specific_maps: vec![Some(default_map); MAX_GAME_DEPTH],
             recall_depth,
             max_encoding_size: encoding_size,
+            representative: Representative::Median,
         }
     }
 
+    /// Choose how map_actions picks the raw action of each group,
+    /// Representative::Median by default
+    pub fn with_representative(mut self, representative: Representative) -> Self {
+        self.representative = representative;
+        self
+    }
+
+    fn pick(&self, index: ActionIndex, group: &[A]) -> A {
+        let chosen = match self.representative {
+            Representative::Median => None,
+            Representative::GeometricMean => {
+                let fractions: Vec<f32> = group.iter().filter_map(|action| action.pot_fraction()).collect();
+                let log_mean = fractions.iter().map(|fraction| fraction.max(1e-6).ln()).sum::<f32>()
+                    / fractions.len().max(1) as f32;
+                closest_to(group, log_mean.exp())
+            }
+            Representative::PotFraction => A::from(index).pot_fraction().and_then(|target| closest_to(group, target)),
+        };
+        // Groups without sizes fall back to the median
+        chosen.unwrap_or_else(|| {
+            // Add "jitter" to the groups
+            // so that the median action is not always the same
+            let median_index = group.len() / 2;
+            let selection_group_low = (median_index as i32 - 2).max(0);
+            let selection_group_high = (median_index as i32 + 2).min(group.len() as i32 - 1);
+            let selection_group = &group[selection_group_low as usize..=selection_group_high as usize];
+            let selection_index = rand::thread_rng().gen_range(0, selection_group.len());
+            selection_group[selection_index].clone()
+        })
+    }
+
+    /// The raw action among `actions` (the legal actions of a node, so for
+    /// its pot) that bucket `index` resolves to, None if none fall in it
+    pub fn resolve(&self, actions: &Vec<A>, depth: usize, index: ActionIndex) -> Option<A> {
+        self.map_actions(actions, depth)
+            .into_iter()
+            .find(|action| action.index() == index)
+    }
+
+    /// Depths without a mapper encode every action of the game
+    fn passthrough_encoding_size() -> usize {
+        A::max_index() as usize
+    }
+
     /// Create a GameMapper to operate a specific depth of the game
     pub fn update_depth(&mut self, mapper: Option<ActionMapper<A>>, depth: usize) {
         self.depth_specific_maps[depth] = mapper;
@@ -128,11 +237,18 @@ impl<A: Filterable + Action> GameMapper<A> {
                         self.max_encoding_size = mapper.num_groups();
                     }
                 }
-                None => self.max_encoding_size = HOT_ENCODING_SIZE,
+                None => {
+                    self.max_encoding_size = self.max_encoding_size.max(Self::passthrough_encoding_size())
+                }
             }
         }
     }
 
+    /// The mapper used at `depth`, None if actions pass through unchanged
+    pub fn mapper(&self, depth: usize) -> Option<&ActionMapper<A>> {
+        self.depth_specific_maps.get(depth).and_then(|mapper| mapper.as_ref())
+    }
+
     pub fn map_and_index(&self, action: A, depth: usize, index: ActionIndex) -> (A, ActionIndex) {
         let mapper = &self.depth_specific_maps[depth];
         match mapper {
@@ -168,19 +284,11 @@ impl<A: Filterable + Action> GameMapper<A> {
             grouped[action.index() as usize].push(action);
         }
 
-        // Add "jitter" to the groups
-        // so that the median action is not always the same
         let mut median_actions: Vec<A> = vec![];
         let mut last_set_index = None;
         for (index, group) in grouped.iter().enumerate() {
             if group.len() > 0 {
-                // Randomly select one of the median actions
-                let median_index = group.len() / 2;
-                let selection_group_low = (median_index as i32 - 2).max(0);
-                let selection_group_high = (median_index as i32 + 2).min(group.len() as i32 - 1);
-                let selection_group = &group[selection_group_low as usize..=selection_group_high as usize];
-                let selection_index = rand::thread_rng().gen_range(0, selection_group.len());
-                median_actions.push(selection_group[selection_index].clone());
+                median_actions.push(self.pick(index as ActionIndex, group));
                 last_set_index = Some(index);
             }
         };
@@ -344,6 +452,43 @@ where
     pub fn accepts(&self, raw: &T) -> bool {
         self.apply_on(&vec![raw.clone()]).len() > 0
     }
+
+    /// A predicate equivalent to `accepts` (for the default
+    /// Filterable::filter) that is cheap to call over and over: regexes are
+    /// compiled here once and nothing is allocated per call
+    pub fn compile(&self) -> CompiledFilter<T> {
+        match self {
+            Filter::And(clause) => CompiledFilter::And(Box::new(clause.left.compile()), Box::new(clause.right.compile())),
+            Filter::Or(clause) => CompiledFilter::Or(Box::new(clause.left.compile()), Box::new(clause.right.compile())),
+            Filter::Not(filter) => CompiledFilter::Not(Box::new(filter.compile())),
+            Filter::BaseCase(Primitive::Raw(raw)) => CompiledFilter::Raw(raw.clone()),
+            Filter::BaseCase(Primitive::Regex(details)) => CompiledFilter::Regex(regex::Regex::new(&details.regex).unwrap()),
+            Filter::BaseCase(Primitive::Range(details)) => CompiledFilter::Range(details.range.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum CompiledFilter<T> {
+    And(Box<CompiledFilter<T>>, Box<CompiledFilter<T>>),
+    Or(Box<CompiledFilter<T>>, Box<CompiledFilter<T>>),
+    Not(Box<CompiledFilter<T>>),
+    Raw(T),
+    Regex(regex::Regex),
+    Range(StdRange<usize>),
+}
+
+impl<T: Parsable> CompiledFilter<T> {
+    pub fn accepts(&self, raw: &T) -> bool {
+        match self {
+            CompiledFilter::And(left, right) => left.accepts(raw) && right.accepts(raw),
+            CompiledFilter::Or(left, right) => left.accepts(raw) || right.accepts(raw),
+            CompiledFilter::Not(filter) => !filter.accepts(raw),
+            CompiledFilter::Raw(value) => raw == value,
+            CompiledFilter::Regex(re) => raw.to_string().is_some_and(|s| re.is_match(&s)),
+            CompiledFilter::Range(range) => raw.to_usize().is_some_and(|n| range.contains(&n)),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -352,6 +497,87 @@ mod tests {
     use crate::implementations::auction::{AuctionPokerAction, RelativeSize};
     use std::collections::HashSet;
 
+    #[test]
+    fn test_representative_policies() {
+        use AuctionPokerAction::*;
+        use RelativeSize::*;
+        let mut actions: Vec<AuctionPokerAction> = (100..=300).step_by(10).map(|size| Raise(DeciPercent(size))).collect();
+        // The largest group always keeps its largest member, so give it one
+        actions.push(Raise(DeciPercent(5000)));
+        let bucket = Raise(DeciPercent(100)).index();
+
+        let pot_fraction = GameMapper::new(None).with_representative(Representative::PotFraction);
+        let decoded = AuctionPokerAction::from(bucket);
+        assert_eq!(pot_fraction.resolve(&actions, 0, bucket), Some(decoded));
+
+        let geometric = GameMapper::new(None).with_representative(Representative::GeometricMean);
+        let resolved = geometric.resolve(&actions, 0, bucket).unwrap();
+        let size = resolved.pot_fraction().unwrap();
+        assert!(size > 0.15 && size < 0.2, "{:?}", resolved);
+
+        assert_eq!(geometric.resolve(&actions, 0, Fold.index()), None);
+        assert_eq!(geometric.map_actions(&vec![Fold, Check], 0), vec![Fold, Check]);
+    }
+
+    #[test]
+    fn test_encoding_size_follows_the_game() {
+        use crate::implementations::one_card_poker::OneCardPokerAction;
+        let mut game_mapper: GameMapper<OneCardPokerAction> = GameMapper::new(None);
+        assert_eq!(game_mapper.encoding_size(), OneCardPokerAction::max_index() as usize);
+
+        let mut mapper = ActionMapper::new();
+        mapper.add_filter(Filter::new(OneCardPokerAction::Fold), OneCardPokerAction::Fold);
+        mapper.add_filter(Filter::new(OneCardPokerAction::Fold).not(), OneCardPokerAction::Call);
+        let game_mapper_from_default = GameMapper::from_default(mapper.clone(), None);
+        assert_eq!(game_mapper_from_default.encoding_size(), 2);
+
+        // Unmapped depths still need room for every action
+        game_mapper.update_depth(Some(mapper), 0);
+        assert_eq!(game_mapper.encoding_size(), OneCardPokerAction::max_index() as usize);
+        assert_eq!(
+            AuctionPokerAction::max_index() as usize,
+            GameMapper::<AuctionPokerAction>::new(None).encoding_size()
+        );
+    }
+
+    /// A kind and a size, for filters of both strings and numbers
+    #[derive(Debug, Clone, PartialEq)]
+    struct Labeled(&'static str, usize);
+
+    impl Parsable for Labeled {
+        fn to_string(&self) -> Option<String> {
+            Some(self.0.to_string())
+        }
+        fn to_usize(&self) -> Option<usize> {
+            Some(self.1)
+        }
+    }
+
+    impl Filterable for Labeled {}
+
+    #[test]
+    fn test_compiled_filters_agree_with_filters() {
+        use crate::util::*;
+        let kind = |kind: &str| Filter::<Labeled>::regex(&format!("^{}$", kind));
+        let filters = vec![
+            kind("raise").and(Filter::range(0..=500)).or(kind("check")),
+            not(Filter::range(10..=20)).and(kind("bid")),
+            is(Labeled("fold", 0)).or(not(kind("raise"))),
+        ];
+        let actions: Vec<Labeled> = (0..1200)
+            .step_by(7)
+            .map(|size| Labeled("raise", size))
+            .chain((0..40).map(|amount| Labeled("bid", amount)))
+            .chain(vec![Labeled("fold", 0), Labeled("call", 0), Labeled("check", 0), Labeled("end", 3)])
+            .collect();
+        for filter in filters {
+            let compiled = filter.compile();
+            for action in &actions {
+                assert_eq!(compiled.accepts(action), filter.accepts(action), "{:?}", action);
+            }
+        }
+    }
+
     #[test]
     pub fn test_default_behavior() {
         // Should map elements that have the same ActionIndex
@@ -399,16 +625,18 @@ mod tests {
             "The mapped actions should be the same as the original actions"
         );
 
-        // Make sure the test is still valid (certain actions map to equivalent indices)
+        // Make sure the test is still valid (certain actions map to equivalent indices).
+        // The raise groups fall in the first three buckets of RAISE_BUCKETS:
+        // up to 30% of the pot, up to 50% and up to 60%
         let action_group_1 = vec![Fold];
         let action_group_2 = vec![Call];
         let action_group_3 = vec![Raise(DeciPercent(50))];
-        let action_group_4 = vec![Raise(DeciPercent(51)), Raise(DeciPercent(52)), Raise(DeciPercent(53))];
+        let action_group_4 = vec![Raise(DeciPercent(310)), Raise(DeciPercent(320)), Raise(DeciPercent(330))];
         let action_group_5 = vec![
-            Raise(DeciPercent(101)),
-            Raise(DeciPercent(102)),
-            Raise(DeciPercent(103)),
-            Raise(DeciPercent(104)),
+            Raise(DeciPercent(510)),
+            Raise(DeciPercent(520)),
+            Raise(DeciPercent(530)),
+            Raise(DeciPercent(540)),
         ];
 
         let groups = vec![
@@ -467,31 +695,18 @@ mod tests {
             "There should be 5 distinct action groups after mapping"
         );
 
-        // Because median is ill defined for even groups,
-        // can be Raise(102) or Raise(103) for the test, test that
-        // it's either
-        let possible_mapping_1 = vec![
-            Fold,
-            Call,
-            Raise(DeciPercent(50)),
-            Raise(DeciPercent(52)),
-            Raise(DeciPercent(102)),
-        ];
-        let possible_mapping_2 = vec![
-            Fold,
-            Call,
-            Raise(DeciPercent(50)),
-            Raise(DeciPercent(52)),
-            Raise(DeciPercent(103)),
-        ];
-
-        // Convert to sets to make sure that the order doesn't matter
-        assert!(
-            mapped.iter().collect::<HashSet<_>>()
-                == possible_mapping_1.iter().collect::<HashSet<_>>()
-                || mapped.iter().collect::<HashSet<_>>()
-                    == possible_mapping_2.iter().collect::<HashSet<_>>(),
-            "The mapped actions should be one of two possible mappings"
-        );
+        // Each group maps to an action of its own, picked near its median
+        // (with some jitter, see GameMapper::pick)
+        for group in &groups {
+            assert_eq!(
+                1,
+                mapped.iter().filter(|action| group.contains(action)).count(),
+                "{:?} should map to one of its own actions",
+                group
+            );
+        }
+        // except the highest, which keeps its largest action so that an
+        // all in maps to itself (see map_actions)
+        assert!(mapped.contains(&Raise(DeciPercent(540))));
     }
 }
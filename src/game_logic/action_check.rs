@@ -0,0 +1,200 @@
+/// Sanity checks for an Action implementation, the same for every game.
+///
+/// A new game has to get a handful of things right before training means
+/// anything: max_index has to cover every index in use, an index has to
+/// decode to an action that encodes back to it, no two indices may decode
+/// to the same action and the filters of a GameMapper have to cover every
+/// action they can be handed. check_action_space runs all of these and
+/// reports every problem it finds instead of stopping at the first one
+/// (the round trip is action::check_index_round_trip, which does stop at
+/// its first). A conversion that panics is reported as a problem too
+use crate::constants::MAX_GAME_DEPTH;
+use crate::game_logic::action::*;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+#[derive(Debug, Clone, Default)]
+pub struct ActionSpaceReport {
+    pub indices_checked: usize,
+    pub samples_checked: usize,
+    pub problems: Vec<String>,
+}
+
+impl ActionSpaceReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    pub fn into_result(self) -> Result<(), String> {
+        match self.is_ok() {
+            true => Ok(()),
+            false => Err(self.problems.join("\n")),
+        }
+    }
+}
+
+/// `f`, or what it panicked with; conversions signal bad input by panicking
+fn caught<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    catch_unwind(AssertUnwindSafe(f)).map_err(|panic| match panic.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => panic.downcast_ref::<String>().cloned().unwrap_or_else(|| "a panic".to_string()),
+    })
+}
+
+/// Checks the action space of `A`. `samples` should be actions the game can
+/// actually produce (legal actions from a few states, every bet size...),
+/// they are checked against the index space and against `game_mapper` if
+/// there is one, at every depth it maps
+pub fn check_action_space<A: Action>(samples: &[A], game_mapper: Option<&GameMapper<A>>) -> ActionSpaceReport {
+    let mut report = ActionSpaceReport::default();
+    let max_index = A::max_index();
+    if max_index == 0 {
+        report.problems.push("max_index is 0, there are no actions".to_string());
+    }
+
+    // Every index round trips and decodes to an action of its own
+    match caught(check_index_round_trip::<A>) {
+        Ok(Ok(())) => {}
+        Ok(Err(problem)) => report.problems.push(problem),
+        Err(panic) => report.problems.push(format!("The index round trip panicked: {}", panic)),
+    }
+    let mut decoded: Vec<(ActionIndex, A)> = vec![];
+    for index in 0..max_index {
+        report.indices_checked += 1;
+        let action = match caught(|| A::from(index)) {
+            Ok(action) => action,
+            Err(panic) => {
+                report.problems.push(format!("Index {} is below max_index but does not decode: {}", index, panic));
+                continue;
+            }
+        };
+        if let Some((other, _)) = decoded.iter().find(|(_, seen)| *seen == action) {
+            report.problems.push(format!("Indices {} and {} both decode to {:?}", other, index, action));
+        }
+        decoded.push((index, action));
+    }
+
+    // Every sample lands in the index space, and its index decodes to
+    // something in the same bucket
+    for action in samples {
+        report.samples_checked += 1;
+        let index = match caught(|| action.index()) {
+            Ok(index) => index,
+            Err(panic) => {
+                report.problems.push(format!("{:?} does not encode: {}", action, panic));
+                continue;
+            }
+        };
+        if index >= max_index {
+            report.problems.push(format!(
+                "{:?} encodes to {} but max_index is {}",
+                action, index, max_index
+            ));
+        }
+    }
+
+    // Every sample is taken by some filter, and mapped within its bucket
+    if let Some(game_mapper) = game_mapper {
+        for depth in 0..MAX_GAME_DEPTH {
+            let mapper = match game_mapper.mapper(depth) {
+                Some(mapper) => mapper,
+                None => continue,
+            };
+            for action in samples {
+                match caught(|| mapper.find(action)) {
+                    Ok(Some((mapped, _))) => match caught(|| mapped.index() == action.index()) {
+                        Ok(true) => {}
+                        Ok(false) => report.problems.push(format!(
+                            "{:?} is mapped to {:?} at depth {}, which has another index",
+                            action, mapped, depth
+                        )),
+                        Err(panic) => report.problems.push(format!(
+                            "{:?} is mapped to {:?} at depth {}, which does not encode: {}",
+                            action, mapped, depth, panic
+                        )),
+                    },
+                    Ok(None) => report.problems.push(format!("No filter at depth {} takes {:?}", depth, action)),
+                    Err(panic) => report.problems.push(format!("Mapping {:?} at depth {} panicked: {}", action, depth, panic)),
+                }
+            }
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::implementations::auction::{AuctionPokerAction, RelativeSize::*};
+    use crate::implementations::one_card_poker::{OneCardPokerAction, MAX_RAISE_SIZES};
+
+    #[test]
+    fn test_shipped_games_pass() {
+        let raises = (0..=1_000_000).step_by(50).map(|size| AuctionPokerAction::Raise(DeciPercent(size)));
+        let bids = (0..=400).map(|amount| AuctionPokerAction::Bid(Amount(amount)));
+        let samples: Vec<AuctionPokerAction> = raises.chain(bids).collect();
+        let report = check_action_space(&samples, None);
+        assert!(report.is_ok(), "{:?}", report.problems);
+        assert_eq!(report.indices_checked, AuctionPokerAction::max_index() as usize);
+
+        let samples: Vec<OneCardPokerAction> = (0..MAX_RAISE_SIZES as u8).map(OneCardPokerAction::Raise).collect();
+        assert_eq!(check_action_space(&samples, None).into_result(), Ok(()));
+    }
+
+    #[test]
+    fn test_gaps_in_the_filters_are_reported() {
+        let mut mapper = ActionMapper::new();
+        mapper.add_filter(Filter::new(OneCardPokerAction::Fold), OneCardPokerAction::Fold);
+        mapper.add_filter(Filter::new(OneCardPokerAction::Call), OneCardPokerAction::Check);
+        let mut game_mapper = GameMapper::new(None);
+        game_mapper.update_depth(Some(mapper), 1);
+
+        let samples = vec![OneCardPokerAction::Fold, OneCardPokerAction::Call, OneCardPokerAction::Raise(3)];
+        let report = check_action_space(&samples, Some(&game_mapper));
+        assert_eq!(report.problems.len(), 2, "{:?}", report.problems);
+        assert!(report.problems[0].contains("another index"));
+        assert!(report.problems[1].contains("No filter at depth 1"));
+    }
+
+    /// Index 2 encodes back as 1 and index 3 doesn't decode at all
+    #[derive(Debug, Clone, PartialEq)]
+    struct Broken(u8);
+
+    impl From<ActionIndex> for Broken {
+        fn from(index: ActionIndex) -> Self {
+            assert!(index != 3, "no action has index 3");
+            Broken(index)
+        }
+    }
+
+    impl From<Broken> for ActionIndex {
+        fn from(action: Broken) -> Self {
+            action.0.min(1)
+        }
+    }
+
+    impl Parsable for Broken {
+        fn to_string(&self) -> Option<String> {
+            None
+        }
+        fn to_usize(&self) -> Option<usize> {
+            Some(self.0 as usize)
+        }
+    }
+
+    impl Filterable for Broken {}
+    impl Action for Broken {
+        fn max_index() -> ActionIndex {
+            4
+        }
+    }
+
+    #[test]
+    fn test_broken_conversions_are_reported() {
+        let report = check_action_space::<Broken>(&[], None);
+        assert_eq!(report.indices_checked, 4);
+        assert_eq!(report.problems.len(), 2, "{:?}", report.problems);
+        assert!(report.problems[0].contains("Index 2 decodes to Broken(2) which encodes to 1"));
+        assert!(report.problems[1].contains("Index 3") && report.problems[1].contains("no action has index 3"));
+    }
+}
@@ -0,0 +1,212 @@
+/// Can the strategy see everything that shapes its legal actions?
+///
+/// A strategy picks from a policy keyed by the infoset, so two states
+/// with the same infoset had better offer the same abstract actions.
+/// When they don't, something the state machine enforces (the aggression
+/// limit, the pips to call, the stacks bounding a raise) is missing from
+/// the features, and the policy ends up with weight on actions that are
+/// illegal in half the states it is used in.
+///
+/// check_constraints plays random games and remembers the abstract legal
+/// actions of every infoset it passes. Each infoset found with two
+/// different sets is a conflict, and the named constraints that differed
+/// between the two states say which one the features miss:
+///
+///     gtcogs constraints 200
+use crate::constants::{AGGRESSION_LIMIT, MAX_GAME_DEPTH};
+use crate::game_logic::action::{Action, ActionIndex};
+use crate::game_logic::game::Game;
+use crate::game_logic::state::{ActivePlayer, State};
+use crate::game_logic::strategy::CondensedInfoSet;
+use crate::implementations::auction::AuctionPokerState;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+/// The constraints a state puts on the legal actions of a player, by name
+pub type Constraints = Vec<(&'static str, String)>;
+
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub player_num: usize,
+    pub info_set: CondensedInfoSet,
+    /// The abstract legal actions the first time, then this time
+    pub legal: [Vec<ActionIndex>; 2],
+    /// The constraints that differed, as "name: first vs this time"
+    pub differing: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ConstraintReport {
+    pub decisions: usize,
+    pub info_sets: usize,
+    /// The first conflict of every infoset that has one
+    pub conflicts: Vec<Conflict>,
+    /// How many conflicts each constraint differed in, "unexplained" when
+    /// none of them did
+    pub by_constraint: BTreeMap<&'static str, usize>,
+}
+
+impl ConstraintReport {
+    pub fn is_ok(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+impl fmt::Display for ConstraintReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} decisions at {} infosets, {} infosets offer different actions",
+            self.decisions,
+            self.info_sets,
+            self.conflicts.len()
+        )?;
+        for (name, count) in &self.by_constraint {
+            write!(f, "\n  {:<16}{:>8} infosets cannot see it", name, count)?;
+            let example = self.conflicts.iter().find(|conflict| match *name {
+                "unexplained" => conflict.differing.is_empty(),
+                _ => conflict.differing.iter().any(|differing| differing.starts_with(name)),
+            });
+            if let Some(conflict) = example {
+                write!(
+                    f,
+                    ", e.g. player {} at {}: {:?} vs {:?} ({})",
+                    conflict.player_num,
+                    conflict.info_set,
+                    conflict.legal[0],
+                    conflict.legal[1],
+                    conflict.differing.join(", ")
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What shapes the legal actions in auction poker, for check_constraints
+pub fn auction_constraints(state: &AuctionPokerState, player_num: usize) -> Constraints {
+    let pips = state.pips();
+    let raises = match state.raise_interval(player_num) {
+        Some(interval) => format!("{}..={}", interval.min, interval.max),
+        None => "none".to_string(),
+    };
+    vec![
+        ("aggression", format!("{}/{}", state.aggression(), AGGRESSION_LIMIT)),
+        ("to call", (pips[player_num ^ 1] - pips[player_num].min(pips[player_num ^ 1])).to_string()),
+        ("raises", raises),
+    ]
+}
+
+/// Plays `games` random games of `S` and reports every infoset offering
+/// different abstract actions in different states, naming which of the
+/// `constraints` of the player to act differed
+pub fn check_constraints<A: Action, S: State<A>>(
+    games: usize,
+    seed: u64,
+    constraints: impl Fn(&S, usize) -> Constraints,
+) -> ConstraintReport {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut report = ConstraintReport::default();
+    let mut seen: HashMap<(usize, CondensedInfoSet), (Vec<ActionIndex>, Constraints)> = HashMap::new();
+    let mut conflicted: HashSet<(usize, CondensedInfoSet)> = HashSet::new();
+    for _ in 0..games {
+        let mut game = Game::<A, S>::new();
+        for _ in 0..MAX_GAME_DEPTH {
+            let action = match game.active_player() {
+                ActivePlayer::Terminal(_) => break,
+                ActivePlayer::Chance(chance) => chance.sample_rng(&mut rng),
+                ActivePlayer::Marker(action) => action,
+                ActivePlayer::Player(player_num, legal) => {
+                    let player_num = player_num as usize;
+                    let mut indices: Vec<ActionIndex> = legal.iter().map(|action| action.index()).collect();
+                    indices.sort_unstable();
+                    indices.dedup();
+                    let key = (player_num, game.get_information_set(player_num));
+                    let now = constraints(game.state(), player_num);
+                    report.decisions += 1;
+                    match seen.get(&key) {
+                        None => {
+                            seen.insert(key, (indices, now));
+                        }
+                        Some((first, _)) if *first == indices || conflicted.contains(&key) => {}
+                        Some((first, before)) => {
+                            let changed: Vec<(&'static str, &String, &String)> = before
+                                .iter()
+                                .zip(&now)
+                                .filter(|((_, a), (_, b))| a != b)
+                                .map(|((name, a), (_, b))| (*name, a, b))
+                                .collect();
+                            if changed.is_empty() {
+                                *report.by_constraint.entry("unexplained").or_insert(0) += 1;
+                            }
+                            for (name, _, _) in &changed {
+                                *report.by_constraint.entry(name).or_insert(0) += 1;
+                            }
+                            let differing = changed.iter().map(|(name, a, b)| format!("{}: {} vs {}", name, a, b)).collect();
+                            report.conflicts.push(Conflict {
+                                player_num,
+                                info_set: key.1,
+                                legal: [first.clone(), indices],
+                                differing,
+                            });
+                            conflicted.insert(key);
+                        }
+                    }
+                    legal[rng.gen_range(0, legal.len())].clone()
+                }
+            };
+            game.play(&action);
+        }
+    }
+    report.info_sets = seen.len();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_logic::visibility::{Information, Observation};
+    use crate::implementations::kuhn_poker::*;
+
+    /// Kuhn where a bet looks like a check to everyone, so the player
+    /// after it can't tell whether they face a bet
+    #[derive(Clone, Debug)]
+    struct QuietBets(KuhnPokerState, bool);
+
+    impl State<KuhnPokerAction> for QuietBets {
+        fn new() -> Self {
+            QuietBets(KuhnPokerState::new(), false)
+        }
+
+        fn observe_before(&mut self, action: &KuhnPokerAction) -> Vec<Observation<KuhnPokerAction>> {
+            match action {
+                KuhnPokerAction::Bet => vec![Observation::Public(Information::Action(KuhnPokerAction::Check))],
+                _ => self.0.observe_before(action),
+            }
+        }
+
+        fn active_player(&self) -> ActivePlayer<KuhnPokerAction> {
+            self.0.active_player()
+        }
+
+        fn update(&mut self, action: KuhnPokerAction) {
+            self.1 = action == KuhnPokerAction::Bet;
+            self.0.update(action)
+        }
+    }
+
+    #[test]
+    fn test_constraints_missing_from_infosets() {
+        let report = check_constraints::<KuhnPokerAction, KuhnPokerState>(100, 0, |_, _| vec![]);
+        assert!(report.is_ok(), "{}", report);
+        assert!(report.info_sets > 0);
+
+        let report = check_constraints::<KuhnPokerAction, QuietBets>(100, 0, |state, _| {
+            vec![("facing a bet", state.1.to_string())]
+        });
+        assert!(!report.is_ok());
+        assert_eq!(report.by_constraint.keys().collect::<Vec<_>>(), [&"facing a bet"]);
+        assert!(report.to_string().contains("facing a bet: "), "{}", report);
+    }
+}
@@ -1,19 +1,31 @@
 use crate::constants::*;
+use crate::context::Context;
 use crate::game_logic::state::State;
 use crate::game_logic::strategy::CondensedInfoSet;
-use crate::game_logic::visibility::ObservationTracker;
+use crate::game_logic::visibility::{Observation, ObservationTracker, Observed};
 use std::fmt::Debug;
 use std::hash::Hash;
+use std::sync::Arc;
 
 use crate::game_logic::action::{Action, ActionIndex};
 use crate::game_logic::state::ActivePlayer;
 
+/// An observation with the number of actions before it and who acted
+type Logged<A> = (usize, Option<usize>, Observation<A>);
+
 #[derive(Clone, Debug)]
 pub struct Game<A: Action, S: State<A>>
 where
     S: Clone,
 {
     observation_tracker: ObservationTracker,
+    /// Every observation, kept only when asked for with
+    /// `with_observation_log`
+    log: Option<Vec<Logged<A>>>,
+    played: usize,
+    /// Actions a game may take before play gives up on it, see
+    /// with_max_actions
+    max_actions: usize,
     state: S,
     action: std::marker::PhantomData<A>,
 }
@@ -27,20 +39,90 @@ where
     }
 
     pub fn new() -> Self {
+        Game::from_state(S::new())
+    }
+
+    /// A game playing by `context`'s rules, see State::with_context
+    pub fn with_context(context: &Arc<Context>) -> Self {
+        Game::from_state(S::with_context(context))
+    }
+
+    fn from_state(state: S) -> Self {
         Game {
-            state: S::new(),
+            state,
             observation_tracker: ObservationTracker::new(),
+            log: None,
+            played: 0,
+            max_actions: MAX_GAME_DEPTH,
             action: std::marker::PhantomData,
         }
     }
 
     /// Advance the game by a single Action
+    ///
+    /// Panics once the game has taken `max_actions` actions without
+    /// ending, which only a state machine going round in circles (a
+    /// marker leading back to itself, a round that never closes) does.
+    /// Better a panic naming the action than a stack overflow
     pub fn play(&mut self, action: &A) {
-        let active_player = self.state.active_player();
-        let observations = self.state.get_observations_after(action);
-        self.observation_tracker
-            .observe_all(observations, active_player.as_index());
+        if self.played >= self.max_actions {
+            panic!(
+                "Game still going after {} actions, the state machine is likely cycling: {:?} played with {:?} to act",
+                self.played,
+                action,
+                self.state.active_player()
+            );
+        }
+        let player = self.state.active_player().as_index();
+        let before = self.state.observe_before(action);
+        self.record(&before, player);
+        self.observation_tracker.observe_all(before, player);
         self.state.update(action.clone());
+        let after = self.state.observe_after(action);
+        self.record(&after, player);
+        self.observation_tracker.observe_all(after, player);
+        self.played += 1;
+    }
+
+    /// Give up on the game after `max_actions` actions, MAX_GAME_DEPTH by
+    /// default
+    pub fn with_max_actions(mut self, max_actions: usize) -> Self {
+        self.max_actions = max_actions;
+        self
+    }
+
+    /// Actions played so far
+    pub fn played(&self) -> usize {
+        self.played
+    }
+
+    fn record(&mut self, observations: &[Observation<A>], player: Option<usize>) {
+        if let Some(log) = &mut self.log {
+            log.extend(observations.iter().map(|observation| (self.played, player, observation.clone())));
+        }
+    }
+
+    /// Keep every observation from here on so `observations_for` can
+    /// replay them. Off by default, the solvers clone games constantly
+    pub fn with_observation_log(mut self) -> Self {
+        self.log = Some(vec![]);
+        self
+    }
+
+    /// Everything `player` has been allowed to see, in order, or None
+    /// without `with_observation_log`. This is all a client bot in that
+    /// seat can know, anything else in its decisions is a leak
+    pub fn observations_for(&self, player: usize) -> Option<Vec<Observed<A>>> {
+        let log = self.log.as_ref()?;
+        let observed = log
+            .iter()
+            .filter(|(_, acting, observation)| observation.visible_to(player, *acting))
+            .map(|(sequence, _, observation)| Observed {
+                sequence: *sequence,
+                information: observation.information().clone(),
+            })
+            .collect();
+        Some(observed)
     }
 
     pub fn get_information_set(&self, player: usize) -> CondensedInfoSet {
@@ -49,7 +131,143 @@ where
             .into_condensed()
     }
 
+    /// Checks the utilities of terminal nodes in debug builds, see
+    /// UtilityBounds
     pub fn active_player(&self) -> ActivePlayer<A> {
-        self.state.active_player()
+        let active = self.state.active_player();
+        #[cfg(debug_assertions)]
+        if let ActivePlayer::Terminal(utilities) = &active {
+            if let Err(error) = self.state.utility_bounds().map_or(Ok(()), |bounds| bounds.check(utilities)) {
+                panic!("Bad terminal utilities after {} actions: {}", self.played, error);
+            }
+        }
+        active
+    }
+
+    /// The full (omniscient) underlying state of the game
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    /// Mutable access to the underlying state, bypassing observations.
+    /// Only for corrections that no player is supposed to observe
+    pub fn state_mut(&mut self) -> &mut S {
+        &mut self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_logic::state::UtilityBounds;
+    use crate::game_logic::visibility::{Information, Observation};
+    use crate::implementations::kuhn_poker::*;
+
+    /// Kuhn, but every action is followed by a private Check for
+    /// whoever took it, to see where the after phase ends up
+    #[derive(Clone, Debug)]
+    struct Echo(KuhnPokerState);
+
+    impl State<KuhnPokerAction> for Echo {
+        fn new() -> Self {
+            Echo(KuhnPokerState::new())
+        }
+
+        fn observe_before(&mut self, action: &KuhnPokerAction) -> Vec<Observation<KuhnPokerAction>> {
+            self.0.observe_before(action)
+        }
+
+        fn observe_after(&mut self, _action: &KuhnPokerAction) -> Vec<Observation<KuhnPokerAction>> {
+            vec![Observation::Private(Information::Action(KuhnPokerAction::Check))]
+        }
+
+        fn active_player(&self) -> ActivePlayer<KuhnPokerAction> {
+            self.0.active_player()
+        }
+
+        fn update(&mut self, action: KuhnPokerAction) {
+            self.0.update(action)
+        }
+    }
+
+    #[test]
+    fn test_observation_phases() {
+        let mut game = Game::<KuhnPokerAction, Echo>::new();
+        while let ActivePlayer::Chance(chance) = game.active_player() {
+            game.play(&chance.items()[0]);
+        }
+        let before: Vec<_> = (0..2).map(|player| game.observation_tracker.get_history(player).0).collect();
+        let bettor = game.active_player().player_num();
+        game.play(&KuhnPokerAction::Bet);
+
+        // The after phase comes after the action and belongs to the
+        // bettor, though the other player is active by then
+        let bet: ActionIndex = KuhnPokerAction::Bet.into();
+        let check: ActionIndex = KuhnPokerAction::Check.into();
+        let after: Vec<_> = (0..2).map(|player| game.observation_tracker.get_history(player).0).collect();
+        assert_eq!(after[bettor], [before[bettor].clone(), vec![bet, check]].concat());
+        assert_eq!(after[1 - bettor], [before[1 - bettor].clone(), vec![bet]].concat());
+    }
+
+    #[test]
+    fn test_observations_for() {
+        let mut game = Game::<KuhnPokerAction, KuhnPokerState>::new();
+        assert!(game.observations_for(0).is_none());
+        game = game.with_observation_log();
+        for action in [KuhnPokerAction::Deal(2), KuhnPokerAction::Deal(0), KuhnPokerAction::Check] {
+            game.play(&action);
+        }
+        let seen = |player| -> Vec<(usize, String)> {
+            let observed = game.observations_for(player).unwrap();
+            observed.iter().map(|observed| (observed.sequence, format!("{:?}", observed.information))).collect()
+        };
+        // Each player sees their own card and the public check, never the other card
+        assert_eq!(seen(0), [(0, "Action(Deal(2))".to_string()), (2, "Action(Check)".to_string())]);
+        assert_eq!(seen(1), [(1, "Action(Deal(0))".to_string()), (2, "Action(Check)".to_string())]);
+    }
+
+    /// Kuhn where player 0 is paid an extra chip at every showdown
+    #[derive(Clone, Debug)]
+    struct Skewed(KuhnPokerState);
+
+    impl State<KuhnPokerAction> for Skewed {
+        fn new() -> Self {
+            Skewed(KuhnPokerState::new())
+        }
+
+        fn observe_before(&mut self, action: &KuhnPokerAction) -> Vec<Observation<KuhnPokerAction>> {
+            self.0.observe_before(action)
+        }
+
+        fn active_player(&self) -> ActivePlayer<KuhnPokerAction> {
+            match self.0.active_player() {
+                ActivePlayer::Terminal(utilities) => ActivePlayer::Terminal(vec![utilities[0] + 1.0, utilities[1]]),
+                active => active,
+            }
+        }
+
+        fn update(&mut self, action: KuhnPokerAction) {
+            self.0.update(action)
+        }
+
+        fn utility_bounds(&self) -> Option<UtilityBounds> {
+            self.0.utility_bounds()
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "zero-sum game add up to 1")]
+    fn test_terminal_utilities_are_checked() {
+        let bounds = UtilityBounds::zero_sum(2.0);
+        assert!(bounds.check(&[2.0, -2.0]).is_ok());
+        assert!(bounds.check(&[3.0, -3.0]).unwrap_err().contains("outside"));
+        assert!(bounds.check(&[f32::NAN, 0.0]).is_err());
+
+        let mut game = Game::<KuhnPokerAction, Skewed>::new();
+        for action in [KuhnPokerAction::Deal(2), KuhnPokerAction::Deal(0), KuhnPokerAction::Check, KuhnPokerAction::Check] {
+            game.play(&action);
+        }
+        game.active_player();
     }
 }
@@ -0,0 +1,225 @@
+/// Finding information leaks in a State implementation.
+///
+/// A player's information set must not depend on anything they cannot
+/// see. check_leaks plays random games, then replays each one with the
+/// chance outcomes hidden from the subject (the opponent's cards) swapped
+/// for other hidden outcomes and everything else the same. The subject's
+/// information sets and legal actions at each of their decisions have to
+/// be identical between the two, otherwise some observation told them
+/// about the opponent's cards. This catches a wrong Observation variant
+/// long before it shows up as a strategy that is strangely good.
+///
+/// Only built for tests, which run it against the games whose chance
+/// outcomes it can tell apart by their ActionIndex
+use crate::constants::MAX_GAME_DEPTH;
+use crate::game_logic::action::Action;
+use crate::game_logic::game::Game;
+use crate::game_logic::state::{ActivePlayer, State};
+use crate::game_logic::strategy::CondensedInfoSet;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+#[derive(Debug, Clone, Default)]
+pub struct LeakReport {
+    /// Games replayed with the subject's hidden outcomes swapped
+    pub pairs_checked: usize,
+    /// Games with nothing hidden from the subject, or no replay possible
+    pub skipped: usize,
+    pub problems: Vec<String>,
+}
+
+impl LeakReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    pub fn into_result(self) -> Result<(), String> {
+        match self.is_ok() {
+            true => Ok(()),
+            false => Err(self.problems.join("\n")),
+        }
+    }
+}
+
+/// What the subject knows at one of their decisions
+type Decision = (CondensedInfoSet, Vec<u8>);
+
+/// Whether every observation of `action` in `state` is hidden from
+/// `subject`. Outcomes nobody observes are not hidden, they have to
+/// match in the replay like any public one
+fn hidden_from<A: Action, S: State<A>>(state: &S, action: &A, subject: usize) -> bool {
+    let mut state = state.clone();
+    let mut observations = state.observe_before(action);
+    state.update(action.clone());
+    observations.extend(state.observe_after(action));
+    !observations.is_empty() && observations.iter().all(|observation| !observation.visible_to(subject, None))
+}
+
+fn decision<A: Action, S: State<A>>(game: &Game<A, S>, subject: usize, actions: &[A]) -> Decision {
+    let indices = actions.iter().map(|action| action.index()).collect();
+    (game.get_information_set(subject), indices)
+}
+
+/// A random game: its actions, where the outcomes hidden from `subject`
+/// were and what `subject` knew at each decision
+fn play<A: Action, S: State<A>>(subject: usize, rng: &mut StdRng) -> (Vec<A>, Vec<usize>, Vec<Decision>) {
+    let mut game = Game::<A, S>::new();
+    let (mut actions, mut hidden, mut decisions) = (vec![], vec![], vec![]);
+    while actions.len() < MAX_GAME_DEPTH {
+        let action = match game.active_player() {
+            ActivePlayer::Terminal(_) => break,
+            ActivePlayer::Chance(chance) => {
+                let action = chance.sample_rng(rng);
+                if hidden_from(game.state(), &action, subject) {
+                    hidden.push(actions.len());
+                }
+                action
+            }
+            ActivePlayer::Marker(action) => action,
+            ActivePlayer::Player(player_num, legal) => {
+                if player_num as usize == subject {
+                    decisions.push(decision(&game, subject, &legal));
+                }
+                legal[rng.gen_range(0, legal.len())].clone()
+            }
+        };
+        game.play(&action);
+        actions.push(action);
+    }
+    (actions, hidden, decisions)
+}
+
+/// `actions` again with a different hidden outcome at each of `hidden`,
+/// None if a public or subject outcome is no longer possible
+fn replay<A: Action, S: State<A>>(
+    subject: usize,
+    actions: &[A],
+    hidden: &[usize],
+    rng: &mut StdRng,
+) -> Option<Vec<Decision>> {
+    let mut game = Game::<A, S>::new();
+    let mut decisions = vec![];
+    for (step, wanted) in actions.iter().enumerate() {
+        let same = |choices: &[A]| choices.iter().find(|action| action.index() == wanted.index()).cloned();
+        let action = match game.active_player() {
+            ActivePlayer::Terminal(_) => break,
+            ActivePlayer::Chance(chance) if hidden.contains(&step) => {
+                let swaps: Vec<A> = chance
+                    .items()
+                    .iter()
+                    .filter(|action| action.index() != wanted.index())
+                    .filter(|action| hidden_from(game.state(), *action, subject))
+                    .cloned()
+                    .collect();
+                if swaps.is_empty() {
+                    return None;
+                }
+                swaps[rng.gen_range(0, swaps.len())].clone()
+            }
+            ActivePlayer::Chance(chance) => same(chance.items())?,
+            ActivePlayer::Marker(action) => action,
+            ActivePlayer::Player(player_num, legal) => {
+                if player_num as usize == subject {
+                    decisions.push(decision(&game, subject, &legal));
+                }
+                match same(&legal) {
+                    Some(action) => action,
+                    // Different legal actions already differ from the original
+                    None if player_num as usize == subject => break,
+                    None => return None,
+                }
+            }
+        };
+        game.play(&action);
+    }
+    Some(decisions)
+}
+
+/// Plays `games` random games of `S` and replays each with the outcomes
+/// hidden from `subject` changed, reporting every game where `subject`
+/// could tell the difference
+pub fn check_leaks<A: Action, S: State<A>>(subject: usize, games: usize, seed: u64) -> LeakReport {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut report = LeakReport::default();
+    for game in 0..games {
+        let (actions, hidden, original) = play::<A, S>(subject, &mut rng);
+        if hidden.is_empty() {
+            report.skipped += 1;
+            continue;
+        }
+        let replayed = match replay::<A, S>(subject, &actions, &hidden, &mut rng) {
+            Some(replayed) => replayed,
+            None => {
+                report.skipped += 1;
+                continue;
+            }
+        };
+        report.pairs_checked += 1;
+        if let Some(at) = (0..original.len().max(replayed.len())).find(|&i| original.get(i) != replayed.get(i)) {
+            report.problems.push(format!(
+                "Game {}: player {} can tell hidden outcomes apart at their decision {} ({:?} vs {:?}) in {:?}",
+                game,
+                subject,
+                at,
+                original.get(at),
+                replayed.get(at),
+                actions
+            ));
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_logic::visibility::{Information, Observation};
+    use crate::implementations::kuhn_poker::*;
+    use crate::implementations::one_card_poker::*;
+
+    /// Kuhn where player 0 is told player 1's card along with the first
+    /// bet or check, the way a feature computed from the whole state would
+    #[derive(Clone, Debug)]
+    struct Leaky(KuhnPokerState, Vec<KuhnPokerAction>);
+
+    impl State<KuhnPokerAction> for Leaky {
+        fn new() -> Self {
+            Leaky(KuhnPokerState::new(), vec![])
+        }
+
+        fn observe_before(&mut self, action: &KuhnPokerAction) -> Vec<Observation<KuhnPokerAction>> {
+            let mut observations = self.0.observe_before(action);
+            if self.1.len() == 2 {
+                observations.push(Observation::Shared(Information::Action(self.1[1]), vec![0]));
+            }
+            observations
+        }
+
+        fn active_player(&self) -> ActivePlayer<KuhnPokerAction> {
+            self.0.active_player()
+        }
+
+        fn update(&mut self, action: KuhnPokerAction) {
+            self.1.push(action);
+            self.0.update(action)
+        }
+    }
+
+    #[test]
+    fn test_leak_check() {
+        for subject in 0..2 {
+            let report = check_leaks::<KuhnPokerAction, KuhnPokerState>(subject, 50, 0);
+            assert!(report.pairs_checked > 0);
+            report.into_result().unwrap();
+
+            let report = check_leaks::<OneCardPokerAction, OneCardPokerState<KuhnConfig>>(subject, 50, 0);
+            assert!(report.pairs_checked > 0);
+            report.into_result().unwrap();
+        }
+
+        // Player 0 learns player 1's card, player 1 still knows nothing of player 0's
+        let report = check_leaks::<KuhnPokerAction, Leaky>(0, 50, 0);
+        assert!(!report.is_ok());
+        assert!(report.problems[0].contains("player 0"), "{}", report.problems[0]);
+        check_leaks::<KuhnPokerAction, Leaky>(1, 50, 0).into_result().unwrap();
+    }
+}
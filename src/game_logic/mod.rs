@@ -1,5 +1,11 @@
+pub mod abstraction;
 pub mod action;
+pub mod action_check;
+pub mod constraint_check;
 pub mod game;
+#[cfg(test)]
+pub mod leak_check;
+pub mod schema;
 pub mod state;
 pub mod strategy;
 pub mod visibility;
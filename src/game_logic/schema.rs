@@ -0,0 +1,335 @@
+/// The layout of the feature vector seen by a player in each round.
+///
+/// The features for a round are built in a few different places in
+/// the auction game (card features for the preflop, the pre-bid rollouts
+/// for the auction and the round features after that), and anything that
+/// reads a feature history (the Evaluator, the live bot, debugging output)
+/// has to agree with them on the exact order. This is the one place that
+/// order is written down, so keep it in sync when adding features!
+use crate::constants::AGGRESSION_LIMIT;
+use crate::game_logic::action::ActionIndex;
+use crate::game_logic::strategy::blueprint::FitFunction;
+use crate::game_logic::strategy::CondensedInfoSet;
+use crate::game_logic::visibility::*;
+use std::sync::atomic::{AtomicU16, Ordering};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FeatureKind {
+    Order,
+    Ranks,
+    Suited,
+    EV,
+    WinValue,
+    Pot,
+    Stack,
+    Aggression,
+    Auction,
+}
+
+const KINDS: [FeatureKind; 9] = [
+    FeatureKind::Order,
+    FeatureKind::Ranks,
+    FeatureKind::Suited,
+    FeatureKind::EV,
+    FeatureKind::WinValue,
+    FeatureKind::Pot,
+    FeatureKind::Stack,
+    FeatureKind::Aggression,
+    FeatureKind::Auction,
+];
+
+impl FeatureKind {
+    pub fn of(feature: &Feature) -> FeatureKind {
+        match feature {
+            Feature::Order(_) => FeatureKind::Order,
+            Feature::Ranks(_, _) => FeatureKind::Ranks,
+            Feature::Suited(_) => FeatureKind::Suited,
+            Feature::EV(_) => FeatureKind::EV,
+            Feature::WinValue(_) => FeatureKind::WinValue,
+            Feature::Pot(_) => FeatureKind::Pot,
+            Feature::Stack(_) => FeatureKind::Stack,
+            Feature::Aggression(_) => FeatureKind::Aggression,
+            Feature::Auction(_) => FeatureKind::Auction,
+        }
+    }
+}
+
+/// Radix of the round slot of every schema, so that a packed feature
+/// history can be unpacked before its schema is known (see
+/// History::into_condensed)
+pub const ROUND_RADIX: CondensedInfoSet = 5;
+
+/// Feature kinds left out of every feature history, see set_ablated
+static ABLATED: AtomicU16 = AtomicU16::new(0);
+
+/// Builds every feature history from now on without the features of
+/// `kinds` (see FeatureSchema::ablate), to train a variant of the
+/// abstraction that doesn't have them. Only for training, a blueprint
+/// trained this way plays with the same ablation, see
+/// BlueprintStrategy::with_ablation
+pub fn set_ablated(kinds: &[FeatureKind]) {
+    let mask = kinds.iter().fold(0, |mask, kind| mask | 1 << *kind as u16);
+    ABLATED.store(mask, Ordering::SeqCst);
+}
+
+pub fn ablated() -> Vec<FeatureKind> {
+    let mask = ABLATED.load(Ordering::SeqCst);
+    KINDS.into_iter().filter(|kind| mask & 1 << *kind as u16 != 0).collect()
+}
+
+/// Applies the ablation of set_ablated to a feature history
+pub fn ablate_history(history: &mut History) {
+    if ABLATED.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    if let Some(schema) = FeatureSchema::for_history(history) {
+        schema.ablate(history, &ablated());
+    }
+}
+
+/// A single slot of the feature vector
+#[derive(Clone, Copy, Debug)]
+pub struct FeatureSpec {
+    pub name: &'static str,
+    pub kind: FeatureKind,
+    /// Inclusive range of the feature's ActionIndex
+    pub min: ActionIndex,
+    pub max: ActionIndex,
+    /// How the Evaluator compares this slot by default
+    pub fit: FitFunction,
+}
+
+const fn spec(
+    name: &'static str,
+    kind: FeatureKind,
+    min: ActionIndex,
+    max: ActionIndex,
+    fit: FitFunction,
+) -> FeatureSpec {
+    FeatureSpec {
+        name,
+        kind,
+        min,
+        max,
+        fit,
+    }
+}
+
+use FeatureKind as K;
+use FitFunction::*;
+
+const PREFLOP: [FeatureSpec; 5] = [
+    spec("round", K::Order, 0, 0, Exact),
+    spec("ranks", K::Ranks, 0, 168, Exact), // 13 * high + low
+    spec("suited", K::Suited, 0, 1, Exact),
+    spec("aggression", K::Aggression, 0, AGGRESSION_LIMIT as ActionIndex, Exact),
+    spec("pot", K::Pot, 0, 100, Difference),
+];
+
+const AUCTION: [FeatureSpec; 5] = [
+    spec("round", K::Order, 1, 1, Exact),
+    spec("ev_if_lost", K::EV, 0, 30, Range(3, -3)),
+    spec("ev_if_won", K::EV, 0, 30, Range(3, -3)),
+    spec("win_value", K::WinValue, 0, 30, Range(3, -3)),
+    spec("pot", K::Pot, 0, 20, Difference),
+];
+
+const POSTFLOP: [FeatureSpec; 7] = [
+    spec("round", K::Order, 2, 4, Exact),
+    spec("ev", K::EV, 0, 50, Range(5, -5)),
+    spec("aggression", K::Aggression, 0, AGGRESSION_LIMIT as ActionIndex, Exact),
+    spec("auction", K::Auction, 0, 2, Exact),
+    spec("pot", K::Pot, 0, 100, Difference),
+    spec("stack", K::Stack, 0, 30, Difference),
+    spec("opponent_stack", K::Stack, 0, 30, Difference),
+];
+
+#[derive(Clone, Debug)]
+pub struct FeatureSchema {
+    pub round: Round,
+    pub features: &'static [FeatureSpec],
+}
+
+impl FeatureSchema {
+    pub fn for_round(round: Round) -> FeatureSchema {
+        let features: &'static [FeatureSpec] = match round {
+            Round::PreFlop => &PREFLOP,
+            Round::Auction => &AUCTION,
+            Round::Flop | Round::Turn | Round::River => &POSTFLOP,
+        };
+        FeatureSchema { round, features }
+    }
+
+    /// The schema a history was built with, read from its leading round feature
+    pub fn for_history(history: &History) -> Option<FeatureSchema> {
+        let round = *history.0.first()? as usize;
+        match round {
+            0..=4 => Some(FeatureSchema::for_round(round.into())),
+            _ => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.features.len()
+    }
+
+    /// Whether `history` is one of this schema's, every slot in its range
+    pub fn fits(&self, history: &History) -> bool {
+        history.0.len() == self.len()
+            && self
+                .features
+                .iter()
+                .zip(history.0.iter())
+                .all(|(spec, value)| spec.min <= *value && *value <= spec.max)
+    }
+
+    /// Number of values each slot can take when packed, the round first
+    pub fn radices(&self) -> impl Iterator<Item = CondensedInfoSet> + '_ {
+        self.features.iter().map(|spec| match spec.kind {
+            FeatureKind::Order => ROUND_RADIX,
+            _ => spec.max as CondensedInfoSet + 1,
+        })
+    }
+
+    pub fn default_fit(&self) -> Vec<FitFunction> {
+        self.features.iter().map(|spec| spec.fit).collect()
+    }
+
+    /// Checks a set of fit functions lines up with this schema
+    pub fn validate_fit(&self, fit: &[FitFunction]) -> Result<(), String> {
+        if fit.len() != self.len() {
+            return Err(format!(
+                "{:?} has {} features but {} fit functions were given",
+                self.round,
+                self.len(),
+                fit.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks a feature vector has the right kinds in the right order
+    /// and that every value is within its declared range
+    pub fn validate(&self, features: &[Feature]) -> Result<(), String> {
+        if features.len() != self.len() {
+            return Err(format!(
+                "{:?} expects {} features, got {}: {:?}",
+                self.round,
+                self.len(),
+                features.len(),
+                features
+            ));
+        }
+        for (feature, spec) in features.iter().zip(self.features.iter()) {
+            let kind = FeatureKind::of(feature);
+            if kind != spec.kind {
+                return Err(format!(
+                    "{:?} feature '{}' should be {:?}, got {:?}",
+                    self.round, spec.name, spec.kind, feature
+                ));
+            }
+            let index: ActionIndex = feature.clone().into();
+            if index < spec.min || index > spec.max {
+                return Err(format!(
+                    "{:?} feature '{}' = {} is outside [{}, {}]",
+                    self.round, spec.name, index, spec.min, spec.max
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// `history` without the features of `kinds`: each is set to the
+    /// lowest value it can take, so infosets that only differ in them
+    /// become one. The round is always kept
+    pub fn ablate(&self, history: &mut History, kinds: &[FeatureKind]) {
+        for (spec, value) in self.features.iter().zip(history.0.iter_mut()) {
+            if spec.kind != FeatureKind::Order && kinds.contains(&spec.kind) {
+                *value = spec.min;
+            }
+        }
+    }
+
+    /// Human readable version of a feature history, one `name=value` per slot
+    pub fn pretty(&self, history: &History) -> String {
+        self.features
+            .iter()
+            .zip(history.0.iter())
+            .map(|(spec, value)| match spec.kind {
+                FeatureKind::Order => format!("{}={:?}", spec.name, Round::from(*value as usize)),
+                FeatureKind::Ranks => format!("{}=({}, {})", spec.name, value / 13, value % 13),
+                _ => format!("{}={}", spec.name, value),
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+/// Validates a freshly built feature vector against the schema for its round
+pub fn validate_features(features: &[Feature]) -> Result<(), String> {
+    let round = match features.first() {
+        Some(Feature::Order(round)) => round.clone(),
+        _ => return Err(format!("Features must start with the round: {:?}", features)),
+    };
+    FeatureSchema::for_round(round).validate(features)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_logic::strategy::blueprint::Evaluator;
+
+    #[test]
+    fn test_schema_validates_features() {
+        let features = vec![
+            Feature::Order(Round::PreFlop),
+            Feature::Ranks(0, 1),
+            Feature::Suited(true),
+            Feature::Aggression(AGGRESSION_LIMIT),
+            Feature::Pot(1),
+        ];
+        assert!(validate_features(&features).is_ok());
+
+        let out_of_order = vec![
+            Feature::Order(Round::PreFlop),
+            Feature::Suited(true),
+            Feature::Ranks(0, 1),
+            Feature::Aggression(0),
+            Feature::Pot(1),
+        ];
+        assert!(validate_features(&out_of_order).is_err());
+
+        let too_short = vec![Feature::Order(Round::Flop), Feature::EV(10)];
+        assert!(validate_features(&too_short).is_err());
+    }
+
+    #[test]
+    fn test_evaluator_from_schema_is_valid() {
+        assert!(Evaluator::from_schema().validate().is_ok());
+        let bad = Evaluator {
+            preflop: vec![FitFunction::Exact],
+            auction: vec![],
+            flop_onwards: vec![],
+            ..Default::default()
+        };
+        assert!(bad.validate().is_err());
+    }
+
+    #[test]
+    fn test_ablation_flattens_features() {
+        let schema = FeatureSchema::for_round(Round::Flop);
+        let mut history = History(vec![2, 25, 3, 1, 50, 20, 12]);
+        schema.ablate(&mut history, &[FeatureKind::Stack, FeatureKind::Auction, FeatureKind::Order]);
+        assert_eq!(history.0, vec![2, 25, 3, 0, 50, 0, 0]);
+    }
+
+    #[test]
+    fn test_pretty_history() {
+        let schema = FeatureSchema::for_round(Round::PreFlop);
+        let history = History(vec![0, 14, 0, 2, 3]);
+        assert_eq!(
+            schema.pretty(&history),
+            "round=PreFlop ranks=(1, 1) suited=0 aggression=2 pot=3"
+        );
+    }
+}
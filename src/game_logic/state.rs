@@ -1,9 +1,12 @@
+use crate::context::Context;
 use crate::game_logic::action::Action;
 use crate::game_logic::visibility::Observation;
 use crate::{Categorical, Utility};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 /// [Neal] Defines a player in the game currently about to take a turn
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum ActivePlayer<A: Action> {
     Player(u32, Vec<A>), // Label (P1, P2, etc) and the possible actions they can take
     Chance(Categorical<A>), // A random event that can occur (for example, a card deal)
@@ -46,16 +49,71 @@ impl<A: Action> ActivePlayer<A> {
     }
 }
 
+/// What the terminal utilities of a game can be. Game checks every
+/// terminal node against them in debug builds, which catches a payoff
+/// built wrong (a showdown paying the pot instead of the contribution,
+/// a delta with the wrong sign) where it happens
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UtilityBounds {
+    pub min: Utility,
+    pub max: Utility,
+    /// The utilities of every terminal node add up to 0
+    pub zero_sum: bool,
+}
+
+impl UtilityBounds {
+    /// A zero-sum game nobody wins or loses more than `most` in
+    pub fn zero_sum(most: Utility) -> Self {
+        UtilityBounds {
+            min: -most,
+            max: most,
+            zero_sum: true,
+        }
+    }
+
+    /// Whether `utilities` can be the utilities of a terminal node, up to
+    /// rounding
+    pub fn check(&self, utilities: &[Utility]) -> Result<(), String> {
+        let tolerance = 1e-4 * self.min.abs().max(self.max.abs()).max(1.0);
+        let range = (self.min - tolerance)..=(self.max + tolerance);
+        if let Some(utility) = utilities.iter().find(|utility| !range.contains(*utility)) {
+            return Err(format!("Utility {} of {:?} is outside {}..={}", utility, utilities, self.min, self.max));
+        }
+        let sum: Utility = utilities.iter().sum();
+        if self.zero_sum && sum.abs() > tolerance {
+            return Err(format!("Utilities {:?} of a zero-sum game add up to {}", utilities, sum));
+        }
+        Ok(())
+    }
+}
+
 pub trait State<A: Action>: Clone {
 
 
-    /// Given a current state, determine a given action's visibility
-    /// with respect to the active player.
+    /// Observing an action happens in two phases around `update`, and
+    /// Game::play always calls them in this order:
+    ///
+    ///     observe_before(action)  on the state the action is taken in
+    ///     update(action)
+    ///     observe_after(action)   on the state the action led to
+    ///
+    /// Both phases are attributed to the player who took the action.
+    /// Each observation is one of
     ///
     ///     Public: The action is visible to all players
     ///     Private: The action is only visible to the active player
     ///     Shared(Vec<_>): The action is visible to the players in the vector
-    fn get_observations_after(&mut self, action: &A) -> Vec<Observation<A>>;
+    ///
+    /// observe_before sees the state before the action is applied, so
+    /// anything derived from pot, stacks or cards is as they were when
+    /// the action was chosen
+    fn observe_before(&mut self, action: &A) -> Vec<Observation<A>>;
+
+    /// Observations that depend on the outcome of `action`, seen from
+    /// the state after `update`. Empty unless a game needs it
+    fn observe_after(&mut self, _action: &A) -> Vec<Observation<A>> {
+        vec![]
+    }
 
     //fn get_features<B : Into<ActionIndex> + Clone>(&self, action: &A) -> Vec<Visibility<B>>;
     /// Returns the current player in a given state
@@ -65,4 +123,26 @@ pub trait State<A: Action>: Clone {
     fn update(&mut self, action: A);
     /// Initialize a new state
     fn new() -> Self;
+
+    /// Initialize a new state playing by `context`'s rules. Games with
+    /// nothing to configure are the game of new
+    fn with_context(_context: &Arc<Context>) -> Self
+    where
+        Self: Sized,
+    {
+        Self::new()
+    }
+
+    /// Fingerprint of the abstraction this state is played under, saved
+    /// with every strategy trained on it. Action::abstraction_hash unless
+    /// the rules of the state change what the actions and features mean
+    fn abstraction_hash(&self) -> u64 {
+        A::abstraction_hash()
+    }
+
+    /// What the utilities at Terminal nodes can be, None if the game
+    /// makes no promises
+    fn utility_bounds(&self) -> Option<UtilityBounds> {
+        None
+    }
 }
@@ -4,6 +4,10 @@ use crate::implementations::auction::*;
 use crate::game_logic::action::*;
 use crate::game_logic::game::*;
 use crate::game_logic::visibility::*;
+use crate::game_logic::schema::{FeatureKind, FeatureSchema};
+use crate::game_logic::abstraction::check_abstraction;
+use crate::game_logic::strategy::store::store_for;
+use crate::game_logic::strategy::intern::{load_histories, rekey, save_histories};
 
 use crate::game_logic::strategy::CondensedInfoSet;
 use crate::game_logic::strategy::PolicyDistribution;
@@ -14,10 +18,7 @@ use crate::game_logic::strategy::PolicyMap;
 use crate::constants::*;
 
 use std::ops::Bound::Included;
-
-
-use serde::{Deserialize, Serialize};
-use std::io::{Write, Read};
+use std::sync::OnceLock;
 
 const MAX_POLICY_LENGTH : usize = 90;  // The maximum number of items in a policy distribution
 
@@ -31,23 +32,92 @@ const MAX_FIT : usize = CHOSEN_COMPRESSION_BITS / MAX_VALUE_SIZE_BITS;
 
 const ARRAY_SIZE : usize =  MAX_POLICY_LENGTH / MAX_FIT;
 
+/// Longest policy that can be compressed into a CondensedPolicyDistribution
+pub const COMPRESSED_POLICY_LENGTH : usize = ARRAY_SIZE * MAX_FIT;
+
 const FAIL_CUTOFF : i32  = 1000;
+
+/// Actions a policy plays with at most this probability are dropped from
+/// it, unless the config says otherwise (see with_cutoff)
+pub const DEFAULT_CUTOFF : f32 = 0.01;
+
+/// Added to the loss before inverting it when averaging policies, so an
+/// exact fit weighs a lot rather than infinitely much
+const AVERAGE_EPSILON : f32 = 0.01;
                                             
-type CondensedPolicyDistribution = [u128; ARRAY_SIZE];
+pub type CondensedPolicyDistribution = [u128; ARRAY_SIZE];
 
 
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, PartialEq)]
 pub enum FitFunction {
     Range(i32, i32),
     Difference,
     Exact,
 }
 
+/// How the (normalized, see Evaluator) difference of a feature turns into
+/// loss before it is weighted
+#[derive(Clone, Debug, Copy, Default, PartialEq)]
+pub enum LossShape {
+    #[default]
+    Linear,
+    /// Many small differences beat one large one
+    Quadratic,
+    /// Past the cap a feature is as wrong as it gets
+    Capped(f32),
+}
+
+impl LossShape {
+    fn apply(&self, difference : f32) -> f32 {
+        match self {
+            LossShape::Linear => difference,
+            LossShape::Quadratic => difference * difference,
+            LossShape::Capped(cap) => difference.min(*cap),
+        }
+    }
+}
+
+/// Which of several infosets with the same loss is used. The blueprint
+/// has no visit counts, so how a policy plays is all there is to go on
+#[derive(Clone, Debug, Copy, Default, PartialEq)]
+pub enum TieBreak {
+    /// The first in infoset order
+    #[default]
+    LowestKey,
+    /// The one raising least often, then the first in infoset order
+    Conservative,
+}
+
+#[derive(Clone, Debug, Copy, Default, PartialEq)]
+pub struct FitOptions {
+    pub shape : LossShape,
+    pub tie_break : TieBreak,
+    /// How many of the nearest infosets are averaged into the policy, each
+    /// weighted by the inverse of its loss. Smooths over the steps of the
+    /// feature grid. 0 or 1 takes the nearest as it is
+    pub top_k : usize,
+}
+
+/// Which infoset of the blueprint stands in for one it doesn't have: the
+/// fit functions of each round pick the candidates and add up their loss.
+///
+/// Features are on very different scales (EV in 0-30 or 0-50, the pot in
+/// 0-20 or 0-100), so with `normalize` each difference is taken as a
+/// fraction of its feature's range in the schema. On top of that every
+/// feature can be weighted, an empty list of weights counts each as 1.
+/// The shape of the loss and how ties are broken are set per round too
 #[derive(Clone, Debug, Default)]
 pub struct Evaluator  {
     pub preflop : Vec<FitFunction>,
     pub auction : Vec<FitFunction>,
-    pub flop_onwards: Vec<FitFunction>
+    pub flop_onwards: Vec<FitFunction>,
+    pub preflop_weights : Vec<f32>,
+    pub auction_weights : Vec<f32>,
+    pub flop_onwards_weights : Vec<f32>,
+    pub normalize : bool,
+    pub preflop_options : FitOptions,
+    pub auction_options : FitOptions,
+    pub flop_onwards_options : FitOptions,
 }
 
 
@@ -55,26 +125,71 @@ pub struct Evaluator  {
 
 
 impl Evaluator {
+    /// An evaluator using the default fit function of every schema, with
+    /// differences normalized
+    pub fn from_schema() -> Evaluator {
+        Evaluator {
+            preflop: FeatureSchema::for_round(Round::PreFlop).default_fit(),
+            auction: FeatureSchema::for_round(Round::Auction).default_fit(),
+            flop_onwards: FeatureSchema::for_round(Round::Flop).default_fit(),
+            normalize: true,
+            ..Default::default()
+        }
+    }
+
+    /// Checks the configured fit functions and weights match the feature
+    /// schemas. An empty list is allowed and means the round is not fit at
+    /// all (or, for weights, that every feature weighs the same)
+    pub fn validate(&self) -> Result<(), String> {
+        let rounds = [
+            (Round::PreFlop, &self.preflop, &self.preflop_weights),
+            (Round::Auction, &self.auction, &self.auction_weights),
+            (Round::Flop, &self.flop_onwards, &self.flop_onwards_weights),
+        ];
+        for (round, fit, weights) in rounds {
+            let schema = FeatureSchema::for_round(round.clone());
+            if !fit.is_empty() {
+                schema.validate_fit(fit)?;
+            }
+            if !weights.is_empty() && weights.len() != schema.len() {
+                return Err(format!("{:?} has {} weights for {} features", round, weights.len(), schema.len()));
+            }
+            if weights.iter().any(|weight| weight.is_nan() || *weight < 0.0) {
+                return Err(format!("{:?} has a negative weight: {:?}", round, weights));
+            }
+        }
+        Ok(())
+    }
 
-    fn loss ( target : &History, test : &History, functions : &Vec<FitFunction> ) -> i32 {
-        let target = target.0.clone();
-        let test = test.0.clone();
-        let mut loss = 0;
-        for ((&function, &target), &test) in functions.iter().zip(target.iter()).zip(test.iter()) {
+    fn fit_for(&self, round : Round) -> (&Vec<FitFunction>, &Vec<f32>, FitOptions) {
+        match round {
+            Round::PreFlop => (&self.preflop, &self.preflop_weights, self.preflop_options),
+            Round::Auction => (&self.auction, &self.auction_weights, self.auction_options),
+            Round::Flop | Round::Turn | Round::River => (&self.flop_onwards, &self.flop_onwards_weights, self.flop_onwards_options),
+        }
+    }
+
+    fn loss (&self, round : Round, target : &History, test : &History) -> f32 {
+        let (functions, weights, options) = self.fit_for(round.clone());
+        let schema = FeatureSchema::for_round(round);
+        let mut loss = 0.0;
+        for (index, ((&function, &target), &test)) in functions.iter().zip(target.0.iter()).zip(test.0.iter()).enumerate() {
+            let difference = (test as i32 - target as i32).abs() as f32;
             let dl = match function {
-                FitFunction::Range( _ , _) => {
-                    //TODO: can make it nonlinear loss 
-                    (test as i32- target as i32).abs()
+                FitFunction::Range( _ , _) | FitFunction::Difference => {
+                    let spec = &schema.features[index];
+                    let span = match self.normalize {
+                        true => (spec.max - spec.min).max(1) as f32,
+                        false => 1.0,
+                    };
+                    weights.get(index).copied().unwrap_or(1.0) * options.shape.apply(difference / span)
                 }
                 FitFunction::Exact => { 
                     match test == target {
-                        true => 0,
-                        false => FAIL_CUTOFF,
+                        true => 0.0,
+                        false => FAIL_CUTOFF as f32,
                     }
                 }
-                FitFunction::Difference => {
-                    (test as i32- target as i32).abs()
-                }
             };
 
             loss += dl;
@@ -83,8 +198,10 @@ impl Evaluator {
         loss
     }
 
-    fn get_min_max( target_value : u8 , function : FitFunction) -> (u8, u8) {
-        match function {
+    /// The values a slot can take and still fit `target_value`, within the
+    /// slot's `bounds` so that the ends pack like any other feature history
+    fn get_min_max( target_value : u8 , function : FitFunction, bounds : (u8, u8)) -> (u8, u8) {
+        let (min, max) = match function {
             FitFunction::Range(pos_delta , neg_delta) => {
                 let value = target_value as i32;
                 let max_index = Feature::max_index() as i32;
@@ -100,24 +217,48 @@ impl Evaluator {
             FitFunction::Difference => {
                 (0, Feature::max_index() as u8)
             }
-        }
+        };
+        (min.clamp(bounds.0, bounds.1), max.clamp(bounds.0, bounds.1))
     }
     fn get_best(&self, map : &BTreeMap<CondensedInfoSet, CondensedPolicyDistribution>, target : CondensedInfoSet) -> Option<CondensedInfoSet> {
+        self.ranked(map, target, 1).first().map(|(key, _)| *key)
+    }
+
+    /// The infosets of `map` nearest to `target` with their loss, as many
+    /// as the round's top_k asks for, nearest first
+    pub fn nearest(&self, map : &BTreeMap<CondensedInfoSet, CondensedPolicyDistribution>, target : CondensedInfoSet) -> Vec<(CondensedInfoSet, f32)> {
+        let history : History = target.clone().into();
+        let top_k = match history.0.first() {
+            Some(&round) => self.fit_for((round as usize).into()).2.top_k.max(1),
+            None => 1,
+        };
+        self.ranked(map, target, top_k)
+    }
+
+    /// The `count` infosets with the least loss, ties broken by the round's
+    /// tie break and then infoset order
+    fn ranked(&self, map : &BTreeMap<CondensedInfoSet, CondensedPolicyDistribution>, target : CondensedInfoSet, count : usize) -> Vec<(CondensedInfoSet, f32)> {
 
         let history : History = target.clone().into();
         let history  = history.0;
+        if history.is_empty() {
+            return vec![];
+        }
         let round : Round = (history[0] as usize).into();
 
-        let evaluator = match round {
-            Round::PreFlop => self.preflop.clone(),
-            Round::Auction => self.auction.clone(),
-            Round::Flop | Round::Turn | Round::River => self.flop_onwards.clone(),
-        };
+        let evaluator = self.fit_for(round.clone()).0.clone();
+
+        // No fit functions configured for this round, so nothing can fit
+        if evaluator.is_empty() {
+            return vec![];
+        }
 
         debug_assert_eq!(evaluator.len(), history.len(), "History does not match the evaluation
         array");
 
-        let ranges = history.iter().zip(evaluator.iter()).map(|(x, func)| Evaluator::get_min_max( *x , *func));
+        let schema = FeatureSchema::for_round(round.clone());
+        let ranges = history.iter().zip(evaluator.iter()).zip(schema.features.iter())
+            .map(|((x, func), spec)| Evaluator::get_min_max( *x , *func, (spec.min, spec.max)));
 
         let min_values :  Vec<u8> = ranges.clone().map( |(min, _)|  min).collect();
         let max_values :  Vec<u8> = ranges.clone().map( |(_, max)|  max).collect();
@@ -129,30 +270,57 @@ impl Evaluator {
         let max_info_set = History(max_values).into_condensed();
 
         let possible_values = map.range((Included(min_info_set) , Included(max_info_set)));
-
-        let mut min_loss = FAIL_CUTOFF;
-        let mut min_key = None;
+        let tie_break = self.fit_for(round.clone()).2.tie_break;
 
         let target : History = target.into();
-        for (&key, _) in possible_values {
+        let mut candidates : Vec<(CondensedInfoSet, f32, f32)> = possible_values.filter_map(|(&key, policy)| {
             let test : History = key.into();
-            let loss = Evaluator::loss(&target, &test, &evaluator) ;
-            if loss < min_loss{
-                min_loss = loss;
-                min_key = Some(key);
+            let loss = self.loss(round.clone(), &target, &test);
+            if loss >= FAIL_CUTOFF as f32 - 1e-6 {
+                return None;
             }
-        };
+            let raising = match tie_break {
+                TieBreak::LowestKey => 0.0,
+                TieBreak::Conservative => raise_frequency(policy),
+            };
+            Some((key, loss, raising))
+        }).collect();
+
+        // Candidates come in infoset order and the sort is stable, so a tie
+        // keeps the first unless the tie break prefers a later one. Losses
+        // within 1e-6 of each other count as tied
+        candidates.sort_by(|(_, loss, raising), (_, other_loss, other_raising)| {
+            let tied = (loss - other_loss).abs() <= 1e-6;
+            match tied {
+                true => raising.total_cmp(other_raising),
+                false => loss.total_cmp(other_loss),
+            }
+        });
+        candidates.truncate(count);
 
+        let min_loss = candidates.first().map(|(_, loss, _)| *loss).unwrap_or(FAIL_CUTOFF as f32);
         println!("[STATS] For the curious, min loss for this policy: {:?}", min_loss);
-        min_key
+        candidates.into_iter().map(|(key, loss, _)| (key, loss)).collect()
 
 
     }
 }
 
 
+/// How often `policy` raises, whatever the size
+pub fn raise_frequency(policy : &CondensedPolicyDistribution) -> f32 {
+    decompress_policy(policy)
+        .iter()
+        .enumerate()
+        .take(AuctionPokerAction::max_index() as usize)
+        .filter(|(index, _)| matches!(AuctionPokerAction::from(*index as ActionIndex), AuctionPokerAction::Raise(_)))
+        .map(|(_, probability)| probability)
+        .sum()
+}
+
 pub fn compress(value : f32) -> u128 {
-    let result = (value * 999.0) as u128;
+    // Rounded so that decompressing and compressing again is lossless
+    let result = (value * 999.0).round() as u128;
     result
 }
 
@@ -162,7 +330,15 @@ pub fn decompress(value : u128) -> f32 {
     result
 }
 
+/// Packs `policy` into a CondensedPolicyDistribution, to the nearest
+/// 0.001. Panics if it has more than COMPRESSED_POLICY_LENGTH actions
 pub fn compress_policy(policy : &PolicyDistribution) -> CondensedPolicyDistribution {
+    assert!(
+        policy.len() <= COMPRESSED_POLICY_LENGTH,
+        "A policy of {} actions doesn't compress into {}",
+        policy.len(),
+        COMPRESSED_POLICY_LENGTH
+    );
     let mut result = [0; ARRAY_SIZE];
     for (i, chunks) in policy.chunks(MAX_FIT).enumerate() {
         let mut total = 0;
@@ -189,138 +365,342 @@ pub fn decompress_policy(policy : &CondensedPolicyDistribution) -> PolicyDistrib
     result
 }
 
-pub fn analyze_policy(info_set: CondensedInfoSet , policy : &PolicyDistribution) {
+type PolicyTable = BTreeMap<CondensedInfoSet, CondensedPolicyDistribution>;
+/// One list of (info set, policy) per player, as handed to a StrategyStore
+pub type SavedPolicies = Vec<Vec<(CondensedInfoSet, CondensedPolicyDistribution)>>;
+
+/// File name of each round's shard, indexed by Round
+pub const SHARD_NAMES : [&str; 5] = ["preflop", "auction", "flop", "turn", "river"];
+
+/// Where convert writes the shards of the blueprint in `file_name`
+pub fn shard_directory(file_name : &str) -> String {
+    format!("{}.shards", file_name)
+}
+
+/// Whether every shard of the blueprint in `file_name` was written after it
+pub fn shards_are_current(file_name : &str) -> bool {
+    let modified = |file_name : &str| std::fs::metadata(file_name).and_then(|metadata| metadata.modified()).ok();
+    let directory = shard_directory(file_name);
+    SHARD_NAMES.iter().all(|name| {
+        match (modified(&format!("{}/{}.bp", directory, name)), modified(file_name)) {
+            (Some(shard), Some(blueprint)) => shard >= blueprint,
+            _ => false,
+        }
+    })
+}
+
+/// The policies (one table per player) for every info set of a single round.
+/// A shard backed by a file is only read the first time it is needed,
+/// live play hardly ever gets to the river so that shard may never be loaded
+#[derive(Clone, Debug, Default)]
+struct Shard {
+    file_name : Option<String>,
+    policies : OnceLock<Vec<PolicyTable>>,
+}
 
-    //TODO: need 
-    
-    let history : History  = info_set.into();
-    let ev = history.0[1];
-    if history.0.len() <6 || ev != 70{
-        return;
+impl Shard {
+    fn loaded(policies : Vec<PolicyTable>) -> Shard {
+        Shard {
+            file_name : None,
+            policies : OnceLock::from(policies),
+        }
     }
-    let v : Vec<f32> = policy.into_iter().map(|x| if *x < 0.02 { 0.0 } else { *x }).collect();
-    println!("{:?}",history);
-    println!("{:?}", v);
-    for (i, &value) in v.iter().enumerate() {
-        let i : AuctionPokerAction  = (i as ActionIndex).into();
-        if value > 1e-3 {
-            print!("{:?}: {:?} \n", i, value);
+
+    fn lazy(file_name : String) -> Shard {
+        Shard {
+            file_name : Some(file_name),
+            policies : OnceLock::new(),
         }
     }
-    println!();
 
-}
+    /// A shard that can't be read (missing, damaged or from another
+    /// abstraction) is first noticed here, in the middle of a match,
+    /// so it plays on without a policy for that round rather than panicking
+    fn policies(&self) -> &Vec<PolicyTable> {
+        self.policies.get_or_init(|| match &self.file_name {
+            Some(file_name) => match try_read_policies(file_name) {
+                Ok(policies) => to_tables(policies),
+                Err(e) => {
+                    println!("[WARNING] Cannot load shard {}, playing its round without a blueprint: {}", file_name, e);
+                    vec![BTreeMap::new(); NUM_REGULAR_PLAYERS]
+                }
+            },
+            None => vec![BTreeMap::new(); NUM_REGULAR_PLAYERS],
+        })
+    }
 
-#[derive(Clone, Debug)]
-pub struct BlueprintStrategy {
-    policies : Vec<BTreeMap<CondensedInfoSet, CondensedPolicyDistribution>>,
-    evaluator : Evaluator,
+    fn is_loaded(&self) -> bool {
+        self.policies.get().is_some()
+    }
+}
 
+/// The round an info set belongs to, read from its leading feature
+fn round_index(info_set : CondensedInfoSet) -> Option<usize> {
+    let history : History = info_set.into();
+    match history.0.first() {
+        Some(&round) if (round as usize) < SHARD_NAMES.len() => Some(round as usize),
+        _ => None,
+    }
 }
 
-#[derive(Deserialize)]
-#[serde(transparent)]
-pub struct SavedStrategy {
-    pub information : Vec<(CondensedInfoSet, PolicyDistribution)>,
+fn read_policies(file_name : &str) -> SavedPolicies {
+    let (abstraction, policies) = store_for(file_name).read(file_name).unwrap();
+    check_abstraction(file_name, AuctionPokerAction::abstraction_hash(), abstraction);
+    rekeyed(file_name, policies).unwrap()
 }
 
-pub fn load(file_name : &str) -> SavedStrategy {
-    let file = std::fs::File::open(file_name).unwrap();
-    let reader = std::io::BufReader::new(file);
-    let strategy : SavedStrategy = serde_json::from_reader(reader).unwrap();
-    strategy
+/// Like `read_policies` but a file from another abstraction is an error
+fn try_read_policies(file_name : &str) -> Result<SavedPolicies, String> {
+    let (abstraction, policies) = store_for(file_name).read(file_name)?;
+    let expected = AuctionPokerAction::abstraction_hash();
+    match abstraction {
+        Some(found) if found != expected => {
+            Err(format!("{} was saved under abstraction {:016x}, expected {:016x}", file_name, found, expected))
+        }
+        found => {
+            check_abstraction(file_name, expected, found);
+            rekeyed(file_name, policies)
+        }
+    }
 }
 
-impl BlueprintStrategy {
-    pub fn load_from_json(player0_file : &str , player1_file : &str) -> BlueprintStrategy {
-        println!("Loading player 0 strategy from {}", player0_file);
-        let time = std::time::Instant::now();
-        let strategy0 = load(player0_file);
-        println!("Time to load player 0 {:?}", time.elapsed());
+/// Moves the interned info sets of `file_name` to their ids in this process
+fn rekeyed(file_name : &str, policies : SavedPolicies) -> Result<SavedPolicies, String> {
+    let rekeyed = load_histories(file_name)?;
+    policies.into_iter().map(|player| {
+        player.into_iter().map(|(info_set, policy)| Ok((rekey(info_set, &rekeyed)?, policy))).collect()
+    }).collect()
+}
 
-        println!("Loading player 1 strategy from {}", player1_file);
-        let time = std::time::Instant::now();
-        let strategy1 = load(player1_file);
-        println!("Time to load player 1 {:?}", time.elapsed());
+fn write_policies(file_name : &str, policies : &SavedPolicies) {
+    store_for(file_name).write(file_name, AuctionPokerAction::abstraction_hash(), policies).unwrap();
+    save_histories(file_name, policies.iter().flatten().map(|(info_set, _)| info_set)).unwrap();
+}
 
-        let mut policy0 = BTreeMap::new();
-        let mut policy1 = BTreeMap::new();
+fn to_tables(saved : SavedPolicies) -> Vec<PolicyTable> {
+    saved.into_iter().map(|player| player.into_iter().collect()).collect()
+}
 
-        println!("Merging strategies");
-        let time = std::time::Instant::now();
-        for (info_set, policy) in strategy0.information {
-            let history : History = info_set.clone().into();
-            policy0.insert(info_set, compress_policy(&policy));
+/// Splits per player tables into one set of per player tables for each round
+fn split_by_round(policies : Vec<PolicyTable>) -> Vec<Shard> {
+    let mut shards = vec![vec![BTreeMap::new(); policies.len()]; SHARD_NAMES.len()];
+    for (player_num, policy) in policies.into_iter().enumerate() {
+        for (info_set, distribution) in policy {
+            let round = round_index(info_set).expect("Blueprint info sets must start with the round");
+            shards[round][player_num].insert(info_set, distribution);
         }
-        println!("Time to merge (0) {:?}", time.elapsed());
-        for (info_set, policy) in strategy1.information {
-            policy1.insert(info_set, compress_policy(&policy));
+    }
+    shards.into_iter().map(Shard::loaded).collect()
+}
+
+#[derive(Clone, Debug)]
+pub struct BlueprintStrategy {
+    shards : Vec<Shard>, // Indexed by Round
+    evaluator : Evaluator,
+    ablated : Vec<FeatureKind>, // See with_ablation
+    cutoff : f32, // See with_cutoff
+
+}
+
+impl BlueprintStrategy {
+    /// Builds a blueprint from uncompressed per player policies, e.g. ones
+    /// converted from another tool's format
+    pub fn from_policies(policies : Vec<BTreeMap<CondensedInfoSet, PolicyDistribution>>) -> BlueprintStrategy {
+        let policies = policies.into_iter().map(|policy| {
+            policy.into_iter().map(|(info_set, policy)| (info_set, compress_policy(&policy))).collect()
+        }).collect();
+        BlueprintStrategy {
+            shards : split_by_round(policies),
+            evaluator : Evaluator::default(),
+            ablated : vec![],
+            cutoff : DEFAULT_CUTOFF,
         }
-        println!("Time to merge (1) {:?}", time.elapsed());
+    }
 
+    /// A blueprint without any policies, every lookup returns None
+    pub fn empty() -> BlueprintStrategy {
         BlueprintStrategy {
-            policies : vec![policy0, policy1],
+            shards : vec![Shard::default(); SHARD_NAMES.len()],
             evaluator : Evaluator::default(),
+            ablated : vec![],
+            cutoff : DEFAULT_CUTOFF,
         }
     }
 
+    pub fn evaluator(&self) -> &Evaluator {
+        &self.evaluator
+    }
 
-    pub fn with_evaluator(self, evaluator : Evaluator) -> BlueprintStrategy{
-        BlueprintStrategy {
-            policies : self.policies,
+    /// The blueprint looking up infosets it doesn't have with `evaluator`,
+    /// as long as it fits the feature schemas
+    pub fn with_evaluator(self, evaluator : Evaluator) -> Result<BlueprintStrategy, String> {
+        evaluator.validate().map_err(|message| format!("Evaluator does not match the feature schema: {}", message))?;
+        Ok(BlueprintStrategy {
             evaluator,
+            ..self
+        })
+    }
+
+    pub fn cutoff(&self) -> f32 {
+        self.cutoff
+    }
+
+    /// The blueprint dropping actions it plays with a probability of at
+    /// most `cutoff`, the rest are scaled back up to sum to 1
+    pub fn with_cutoff(self, cutoff : f32) -> BlueprintStrategy {
+        BlueprintStrategy {
+            cutoff,
+            ..self
         }
     }
 
-    pub fn save_bincode(&self, file_name : &str) {
+    /// A blueprint trained without the features of `kinds` (see
+    /// schema::set_ablated), which looks infosets up without them too
+    pub fn with_ablation(self, kinds : &[FeatureKind]) -> BlueprintStrategy {
+        BlueprintStrategy {
+            ablated : kinds.to_vec(),
+            ..self
+        }
+    }
+
+    /// The infoset of `game` as this blueprint was trained on it
+    fn info_set_of(&self, game : &Game<AuctionPokerAction, AuctionPokerState>, player_num : usize) -> CondensedInfoSet {
+        let info_set = game.get_information_set(player_num);
+        if self.ablated.is_empty() {
+            return info_set;
+        }
+        let mut history : History = info_set.into();
+        if let Some(schema) = FeatureSchema::for_history(&history) {
+            schema.ablate(&mut history, &self.ablated);
+        }
+        history.into_condensed()
+    }
+
+    /// Every policy for `player_num` across all rounds (loads every shard)
+    fn player_policies(&self, player_num : usize) -> impl Iterator<Item = (&CondensedInfoSet, &CondensedPolicyDistribution)> {
+        self.shards.iter().flat_map(move |shard| shard.policies()[player_num].iter())
+    }
+
+    fn saved_policies(&self, shard : &Shard) -> SavedPolicies {
+        shard.policies().iter().map(|policy| {
+            policy.iter().map(|(info_set, policy)| {
+                (*info_set, *policy)
+            }).collect()
+        }).collect()
+    }
+
+    /// Saves every player to a single file, in the format picked by its extension
+    pub fn save(&self, file_name : &str) {
         println!("Saving strategy to {}", file_name);
-        let file = std::fs::File::create(file_name).unwrap();
-        let writer = std::io::BufWriter::new(file);
         
         let time = std::time::Instant::now();
-        let vecs: Vec<Vec<(CondensedInfoSet, CondensedPolicyDistribution)>> = self.policies.iter().map(|policy| {
-            policy.iter().map(|(info_set, policy)| {
+        let vecs: SavedPolicies = (0..NUM_REGULAR_PLAYERS).map(|player_num| {
+            self.player_policies(player_num).map(|(info_set, policy)| {
                 (*info_set, *policy)
             }).collect()
         }).collect();
         println!("Time to convert {:?}", time.elapsed());
 
         let time = std::time::Instant::now();
-        bincode::serialize_into(writer, &vecs).unwrap();
+        write_policies(file_name, &vecs);
         println!("Time to save {:?}", time.elapsed());
     }
 
-    pub fn load_bincode(file_name : &str) -> BlueprintStrategy {
+    pub fn load(file_name : &str) -> BlueprintStrategy {
         println!("Loading strategy from {}", file_name);
         let time = std::time::Instant::now();
-        let file = std::fs::File::open(file_name).unwrap();
-        let reader = std::io::BufReader::new(file);
-        let strategy : Vec<Vec<(CondensedInfoSet, CondensedPolicyDistribution)>> = bincode::deserialize_from(reader).unwrap();
+        let strategy = read_policies(file_name);
         println!("Time to load {:?}", time.elapsed());
-        let mut policies = Vec::new();
         let time = std::time::Instant::now();
-        for player in strategy {
-            let mut policy = BTreeMap::new();
-            for (info_set, policy_distribution) in player {
-                policy.insert(info_set, policy_distribution);
-            }
-            policies.push(policy);
-        }
+        let shards = split_by_round(to_tables(strategy));
         println!("Time to convert {:?}", time.elapsed());
         BlueprintStrategy {
-            policies,
+            shards,
+            evaluator : Evaluator::default(),
+            ablated : vec![],
+            cutoff : DEFAULT_CUTOFF,
+        }
+    }
+
+    /// Like `load` but a missing, damaged or mismatched file is an error
+    /// rather than a panic, for reloading while a match is running
+    pub fn try_load(file_name : &str) -> Result<BlueprintStrategy, String> {
+        let (abstraction, strategy) = store_for(file_name).read(file_name)?;
+        let expected = AuctionPokerAction::abstraction_hash();
+        if abstraction != Some(expected) {
+            return Err(format!("{} was saved under abstraction {:?}, expected {:016x}", file_name, abstraction, expected));
+        }
+        Ok(BlueprintStrategy {
+            shards : split_by_round(to_tables(rekeyed(file_name, strategy)?)),
             evaluator : Evaluator::default(),
+            ablated : vec![],
+            cutoff : DEFAULT_CUTOFF,
+        })
+    }
+
+    /// Saves every round to its own file `directory/<round>.bp`
+    pub fn save_sharded(&self, directory : &str) {
+        std::fs::create_dir_all(directory).unwrap();
+        for round in 0..SHARD_NAMES.len() {
+            self.save_shard(directory, round.into());
         }
     }
 
+    /// Saves a single round, so that retraining (say) the auction
+    /// doesn't mean rewriting the whole blueprint
+    fn save_shard(&self, directory : &str, round : Round) {
+        let round : usize = round.into();
+        let file_name = format!("{}/{}.bp", directory, SHARD_NAMES[round]);
+        println!("Saving {} shard to {}", SHARD_NAMES[round], file_name);
+        write_policies(&file_name, &self.saved_policies(&self.shards[round]));
+    }
+
+    /// The blueprint in `file_name` from its shards (see shard_directory) if
+    /// convert wrote them after the file, so rounds are only read once they
+    /// come up, and from the file itself otherwise
+    pub fn load_lazily(file_name : &str) -> BlueprintStrategy {
+        if !shards_are_current(file_name) {
+            return BlueprintStrategy::load(file_name);
+        }
+        let directory = shard_directory(file_name);
+        println!("Loading strategy lazily from {}", directory);
+        BlueprintStrategy::load_sharded(&directory)
+    }
+
+    /// Points every round at its shard in `directory` without reading any of them,
+    /// each shard is loaded the first time one of its info sets is looked up
+    pub fn load_sharded(directory : &str) -> BlueprintStrategy {
+        let shards = SHARD_NAMES.iter().map(|name| {
+            Shard::lazy(format!("{}/{}.bp", directory, name))
+        }).collect();
+        BlueprintStrategy {
+            shards,
+            evaluator : Evaluator::default(),
+            ablated : vec![],
+            cutoff : DEFAULT_CUTOFF,
+        }
+    }
+
+    /// Every policy of `player_num` in `round` (loads its shard)
+    pub fn round_policies(&self, round : Round, player_num : usize) -> &BTreeMap<CondensedInfoSet, CondensedPolicyDistribution> {
+        let round : usize = round.into();
+        &self.shards[round].policies()[player_num]
+    }
+
+    /// The policies of `player_num` for the round `info_set` belongs to
+    fn policies_for(&self, info_set : CondensedInfoSet, player_num : usize) -> Option<&PolicyTable> {
+        let round = round_index(info_set)?;
+        Some(&self.shards[round].policies()[player_num])
+    }
+
     fn normalize_policy(&self,  condensed_policy: &Option<CondensedPolicyDistribution>) -> Option<Vec<(ActionIndex, f32)>> {
         let policy = match condensed_policy {
             Some(policy) => decompress_policy(policy),
-            None => panic!("No policy found!"),
+            None => return None,
         };
 
         let filtered_policy : Vec<(ActionIndex, f32)>= policy.iter().enumerate().filter_map( | (action_index, probability) |{
-            match *probability > BLUEPRINT_CUTOFF   {
+            match *probability > self.cutoff   {
                 true => Some((action_index as ActionIndex, *probability)),
                 false => None
             }
@@ -353,12 +733,46 @@ impl BlueprintStrategy {
     ///
     /// returns None if unable to find a suitable normalized strategy
     pub fn get_best_policy(&self, game: &Game<AuctionPokerAction, AuctionPokerState>, player_num: usize) -> Option<Vec<(ActionIndex, f32)>> {
-        let current_info_set = game.get_information_set(player_num);
-        let history : History = current_info_set.clone().into();
-        println!("Current history set {:?}", history);
-        let best_info_set  = self.evaluator.get_best(&self.policies[player_num], current_info_set);
-        let policy = best_info_set.map(|info_set| self.policies[player_num][&info_set]);
-        self.normalize_policy(&policy)
+        let current_info_set = self.info_set_of(game, player_num);
+        let policies = self.policies_for(current_info_set, player_num)?;
+        let nearest = self.evaluator.nearest(policies, current_info_set);
+        if nearest.len() <= 1 {
+            let policy = nearest.first().map(|(info_set, _)| policies[info_set]);
+            return self.normalize_policy(&policy);
+        }
+        self.average_policies(policies, &nearest)
+    }
+
+    /// The policies of `nearest` averaged, each weighted by the inverse of
+    /// its loss, so an infoset that fits well counts for more
+    fn average_policies(&self, policies : &BTreeMap<CondensedInfoSet, CondensedPolicyDistribution>, nearest : &[(CondensedInfoSet, f32)]) -> Option<Vec<(ActionIndex, f32)>> {
+        let mut average = vec![0.0; COMPRESSED_POLICY_LENGTH];
+        let mut total_weight = 0.0;
+        for (info_set, loss) in nearest {
+            let policy = match self.normalize_policy(&Some(policies[info_set])) {
+                Some(policy) => policy,
+                None => continue,
+            };
+            let weight = 1.0 / (loss + AVERAGE_EPSILON);
+            total_weight += weight;
+            for (action_index, probability) in policy {
+                average[action_index as usize] += weight * probability;
+            }
+        }
+        if total_weight == 0.0 {
+            return None;
+        }
+
+        // Actions only a far infoset plays can fall under the cutoff
+        let filtered : Vec<(ActionIndex, f32)> = average.iter().enumerate()
+            .map(|(action_index, probability)| (action_index as ActionIndex, probability / total_weight))
+            .filter(|(_, probability)| *probability > self.cutoff)
+            .collect();
+        let sum : f32 = filtered.iter().map(|(_, probability)| probability).sum();
+        if sum < 1e-5 {
+            return None;
+        }
+        Some(filtered.into_iter().map(|(action_index, probability)| (action_index, probability / sum)).collect())
     }
 
     /// Returns a probability distribution over
@@ -366,8 +780,13 @@ impl BlueprintStrategy {
     ///
     /// returns None if unable to find a suitable normalized strategy
     pub fn get_exact_policy(&self, game : &Game<AuctionPokerAction, AuctionPokerState>, player_num: usize) -> Option<Vec<(ActionIndex, f32)>> {
-        let info_set = game.get_information_set(player_num);
-        let condensed_policy = self.policies[player_num].get(&info_set).map(|policy| *policy);
+        let info_set = self.info_set_of(game, player_num);
+        self.get_info_set_policy(info_set, player_num)
+    }
+
+    /// The normalized policy stored for exactly `info_set`, if any
+    pub fn get_info_set_policy(&self, info_set : CondensedInfoSet, player_num: usize) -> Option<Vec<(ActionIndex, f32)>> {
+        let condensed_policy = self.policies_for(info_set, player_num)?.get(&info_set).map(|policy| *policy);
         self.normalize_policy(&condensed_policy)
     }
 }
@@ -385,7 +804,7 @@ mod tests {
         g.play(&AuctionPokerAction::DealHole(3, 1));
         g.play(&AuctionPokerAction::DealHole(4, 1));
         g.play(&AuctionPokerAction::BettingRoundStart);
-        let strategy = BlueprintStrategy::load_bincode("auction_poker.bp");
+        let strategy = BlueprintStrategy::load("auction_poker.bp");
 
         let preflop_evaluator = Evaluator {
             preflop : vec![
@@ -397,9 +816,10 @@ mod tests {
             ],
             auction : vec![],
             flop_onwards : vec![],
+            ..Default::default()
         };
 
-        let strategy = strategy.with_evaluator(preflop_evaluator);
+        let strategy = strategy.with_evaluator(preflop_evaluator).unwrap();
 
         let policy = strategy.get_best_policy(&g, 0);
         let policy2 = strategy.get_exact_policy(&g, 0);
@@ -415,7 +835,7 @@ mod tests {
         g.play(&AuctionPokerAction::DealHole(3, 1));
         g.play(&AuctionPokerAction::DealHole(8, 1));
         g.play(&AuctionPokerAction::BettingRoundStart);
-        let strategy = BlueprintStrategy::load_bincode("auction_poker.bp");
+        let strategy = BlueprintStrategy::load("auction_poker.bp");
         let policy = strategy.get_exact_policy(&g, 0);
         assert!(policy.is_some());
         println!("For the curious, the policy for a pair of Aces: {:?}", policy);
@@ -429,8 +849,9 @@ mod tests {
             ],
             auction : vec![],
             flop_onwards : vec![],
+            ..Default::default()
         };
-        let strategy = strategy.with_evaluator(preflop_evaluator);
+        let strategy = strategy.with_evaluator(preflop_evaluator).unwrap();
         let bet_size = Amount(15);
         g.play(&AuctionPokerAction::Raise(bet_size.clone()));
         g.play(&AuctionPokerAction::PlayerActionEnd(0));
@@ -443,11 +864,11 @@ mod tests {
     #[test]
     pub fn test_model_knows_when_to_fold() {
         // Assumes that there is a model named "auction_poker.bp" in the current directory
-        let strategy = BlueprintStrategy::load_bincode("auction_poker.bp");
+        let strategy = BlueprintStrategy::load("auction_poker.bp");
 
         let mut folded = 0;
-        for policy in strategy.policies.clone() {
-            for (info_set, policy) in policy.iter() {
+        for player_num in 0..NUM_REGULAR_PLAYERS {
+            for (info_set, policy) in strategy.player_policies(player_num) {
                 let decompressed = decompress_policy(policy);
                 let history : History = (*info_set).into();
                 let round = history.0[0];
@@ -464,6 +885,164 @@ mod tests {
     }
 
 
+    #[test]
+    pub fn test_evaluator_loss_is_weighted_and_normalized() {
+        let target = History(vec![2, 25, 0, 1, 50, 10, 10]);
+        // Off by 4 of the 50 EV buckets, or by 6 of the 100 pot buckets
+        let off_in_ev = History(vec![2, 29, 0, 1, 50, 10, 10]);
+        let off_in_pot = History(vec![2, 25, 0, 1, 56, 10, 10]);
+
+        let mut evaluator = Evaluator {
+            flop_onwards : FeatureSchema::for_round(Round::Flop).default_fit(),
+            ..Default::default()
+        };
+        let raw = |evaluator : &Evaluator, test : &History| evaluator.loss(Round::Flop, &target, test);
+        assert!(raw(&evaluator, &off_in_ev) < raw(&evaluator, &off_in_pot));
+
+        evaluator.normalize = true;
+        assert!(raw(&evaluator, &off_in_pot) < raw(&evaluator, &off_in_ev));
+        assert!((raw(&evaluator, &off_in_ev) - 0.08).abs() < 1e-6);
+
+        // Caring a lot about the pot turns it around again
+        evaluator.flop_onwards_weights = vec![1.0, 1.0, 1.0, 1.0, 3.0, 1.0, 1.0];
+        assert!(evaluator.validate().is_ok());
+        assert!(raw(&evaluator, &off_in_ev) < raw(&evaluator, &off_in_pot));
+
+        evaluator.flop_onwards_weights = vec![1.0; 3];
+        assert!(evaluator.validate().is_err());
+        let error = BlueprintStrategy::empty().with_evaluator(evaluator).err().unwrap();
+        assert!(error.contains("feature schema"), "{}", error);
+    }
+
+    #[test]
+    pub fn test_loss_shapes_and_tie_breaks() {
+        let target = History(vec![2, 25, 0, 1, 50, 10, 10]);
+        let off_twice = History(vec![2, 27, 0, 1, 52, 10, 10]);
+        let off_once = History(vec![2, 25, 0, 1, 54, 10, 10]);
+        let mut evaluator = Evaluator {
+            flop_onwards : vec![FitFunction::Exact, FitFunction::Difference, FitFunction::Exact, FitFunction::Exact,
+                                FitFunction::Difference, FitFunction::Exact, FitFunction::Exact],
+            ..Default::default()
+        };
+        let loss = |evaluator : &Evaluator, test : &History| evaluator.loss(Round::Flop, &target, test);
+        assert_eq!(loss(&evaluator, &off_twice), loss(&evaluator, &off_once));
+        evaluator.flop_onwards_options.shape = LossShape::Quadratic;
+        assert!(loss(&evaluator, &off_twice) < loss(&evaluator, &off_once));
+        evaluator.flop_onwards_options.shape = LossShape::Capped(3.0);
+        assert!(loss(&evaluator, &off_once) < loss(&evaluator, &off_twice));
+
+        // Same loss either way, the conservative tie break takes the one
+        // that raises less even though it comes later
+        let raise_index = (0..AuctionPokerAction::max_index() as usize)
+            .find(|index| matches!(AuctionPokerAction::from(*index as ActionIndex), AuctionPokerAction::Raise(_)))
+            .unwrap();
+        let mut aggressive = vec![0.0; raise_index + 1];
+        aggressive[raise_index] = 1.0;
+        let passive = vec![0.0, 1.0];
+        let low = History(vec![2, 25, 0, 1, 48, 10, 10]).into_condensed();
+        let high = History(vec![2, 25, 0, 1, 52, 10, 10]).into_condensed();
+        let mut map = BTreeMap::new();
+        map.insert(low, compress_policy(&aggressive));
+        map.insert(high, compress_policy(&passive));
+        assert!(low < high);
+        let target_key = target.clone().into_condensed();
+        evaluator.flop_onwards_options = FitOptions::default();
+        assert_eq!(evaluator.get_best(&map, target_key), Some(low));
+        evaluator.flop_onwards_options.tie_break = TieBreak::Conservative;
+        assert_eq!(evaluator.get_best(&map, target_key), Some(high));
+    }
+
+    #[test]
+    pub fn test_nearest_policies_are_averaged() {
+        let target = History(vec![2, 25, 0, 1, 50, 10, 10]);
+        let near = History(vec![2, 25, 0, 1, 51, 10, 10]).into_condensed();
+        let far = History(vec![2, 25, 0, 1, 54, 10, 10]).into_condensed();
+        let mut map = BTreeMap::new();
+        map.insert(near, compress_policy(&vec![1.0, 0.0]));
+        map.insert(far, compress_policy(&vec![0.0, 1.0]));
+        let mut evaluator = Evaluator {
+            flop_onwards : vec![FitFunction::Exact, FitFunction::Exact, FitFunction::Exact, FitFunction::Exact,
+                                FitFunction::Difference, FitFunction::Exact, FitFunction::Exact],
+            ..Default::default()
+        };
+        let target_key = target.into_condensed();
+        assert_eq!(evaluator.nearest(&map, target_key), vec![(near, 1.0)]);
+
+        evaluator.flop_onwards_options.top_k = 3;
+        let nearest = evaluator.nearest(&map, target_key);
+        assert_eq!(nearest, vec![(near, 1.0), (far, 4.0)]);
+
+        // Both play, the nearer one more often
+        let policy = BlueprintStrategy::empty().average_policies(&map, &nearest).unwrap();
+        assert_eq!(policy.len(), 2);
+        assert!(policy[0].1 > policy[1].1);
+        assert!((policy[0].1 + policy[1].1 - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    pub fn test_round_trip_checks_abstraction() {
+        let file_name = std::env::temp_dir().join("gtcogs_abstraction_test.bp");
+        let file_name = file_name.to_str().unwrap();
+        BlueprintStrategy::empty().save(file_name);
+        let strategy = BlueprintStrategy::load(file_name);
+        assert_eq!(strategy.shards.len(), SHARD_NAMES.len());
+
+        // A blueprint saved under a different abstraction must be refused
+        let file = std::fs::File::create(file_name).unwrap();
+        let policies : Vec<Vec<(CondensedInfoSet, CondensedPolicyDistribution)>> = vec![vec![], vec![]];
+        bincode::serialize_into(file, &(AuctionPokerAction::abstraction_hash() ^ 1, policies)).unwrap();
+        let result = std::panic::catch_unwind(|| BlueprintStrategy::load(file_name));
+        std::fs::remove_file(file_name).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    pub fn test_sharded_blueprint_loads_lazily() {
+        let preflop = History(vec![0, 14, 0, 0, 3]).into_condensed();
+        let river = History(vec![4, 25, 0, 2, 10, 20, 20]).into_condensed();
+        let mut policy0 = BTreeMap::new();
+        policy0.insert(preflop, compress_policy(&vec![0.5, 0.5]));
+        policy0.insert(river, compress_policy(&vec![1.0]));
+        let strategy = BlueprintStrategy {
+            shards : split_by_round(vec![policy0, BTreeMap::new()]),
+            evaluator : Evaluator::default(),
+            ablated : vec![],
+            cutoff : DEFAULT_CUTOFF,
+        };
+
+        let directory = std::env::temp_dir().join("gtcogs_sharded_test");
+        let directory = directory.to_str().unwrap();
+        strategy.save_sharded(directory);
+        let loaded = BlueprintStrategy::load_sharded(directory);
+        assert!(!loaded.shards[Round::PreFlop as usize].is_loaded());
+        assert!(loaded.policies_for(preflop, 0).unwrap().contains_key(&preflop));
+        assert!(loaded.shards[Round::PreFlop as usize].is_loaded());
+        assert!(!loaded.shards[Round::River as usize].is_loaded());
+        assert!(!loaded.policies_for(river, 1).unwrap().contains_key(&river));
+        assert!(loaded.policies_for(river, 0).unwrap().contains_key(&river));
+
+        // A shard that went missing leaves its round empty instead of panicking
+        std::fs::remove_file(format!("{}/flop.bp", directory)).unwrap();
+        std::fs::write(format!("{}/turn.bp", directory), b"not a blueprint").unwrap();
+        let broken = BlueprintStrategy::load_sharded(directory);
+        assert!(broken.round_policies(Round::Flop, 0).is_empty());
+        assert!(broken.round_policies(Round::Turn, 0).is_empty());
+        assert!(broken.policies_for(preflop, 0).unwrap().contains_key(&preflop));
+        std::fs::remove_dir_all(directory).unwrap();
+
+        // A blueprint is only loaded lazily once its shards are written
+        let file_name = std::env::temp_dir().join("gtcogs_sharded_test.bp");
+        let file_name = file_name.to_str().unwrap();
+        let _ = std::fs::remove_dir_all(shard_directory(file_name));
+        strategy.save(file_name);
+        assert!(BlueprintStrategy::load_lazily(file_name).shards[Round::PreFlop as usize].is_loaded());
+        strategy.save_sharded(&shard_directory(file_name));
+        let lazy = BlueprintStrategy::load_lazily(file_name);
+        assert!(!lazy.shards[Round::PreFlop as usize].is_loaded());
+        assert!(lazy.policies_for(river, 0).unwrap().contains_key(&river));
+        std::fs::remove_dir_all(shard_directory(file_name)).unwrap();
+    }
+
     #[test]
     pub fn decompress_compress() {
         let mut policy = vec![0.0; 40];
@@ -0,0 +1,168 @@
+/// Policy and regret distributions stored over the legal actions only.
+///
+/// Training indexes distributions by ActionIndex, so a full distribution
+/// has a slot for every action of the game (40 for auction poker) even
+/// though most info sets only have a handful of legal actions. A
+/// CompactDistribution keeps the legal actions as a bit mask and a value
+/// for each of them, the full vector is only built when asked for
+use crate::game_logic::action::ActionIndex;
+
+/// Bits in the legal action mask, the most actions a game can have
+pub const MAX_COMPACT_ACTIONS: usize = 128;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompactDistribution {
+    legal: u128,
+    /// Length of the full distribution
+    length: u8,
+    /// One value for every legal action, in ActionIndex order
+    values: Box<[f32]>,
+}
+
+/// The actions set in `mask` as bits
+fn mask_bits(mask: &[bool]) -> u128 {
+    assert!(mask.len() <= MAX_COMPACT_ACTIONS, "Too many actions for a CompactDistribution");
+    mask.iter()
+        .enumerate()
+        .filter(|(_, legal)| **legal)
+        .fold(0u128, |legal, (index, _)| legal | 1 << index)
+}
+
+impl CompactDistribution {
+    /// All zeroes over the actions set in `mask`
+    pub fn zeroes(mask: &[bool]) -> Self {
+        let legal = mask_bits(mask);
+        CompactDistribution {
+            legal,
+            length: mask.len() as u8,
+            values: vec![0.0; legal.count_ones() as usize].into_boxed_slice(),
+        }
+    }
+
+    /// All zeroes over the actions set in the bits of `legal`, the form
+    /// legal_bits gives
+    pub fn from_bits(legal: u128, length: u8) -> Self {
+        let mask: Vec<bool> = (0..length as usize).map(|index| legal & 1 << index != 0).collect();
+        CompactDistribution::zeroes(&mask)
+    }
+
+    /// The legal actions as bits and the length of the full distribution
+    pub fn legal_bits(&self) -> (u128, u8) {
+        (self.legal, self.length)
+    }
+
+    pub fn is_legal(&self, index: ActionIndex) -> bool {
+        (index as usize) < MAX_COMPACT_ACTIONS && self.legal & 1 << index != 0
+    }
+
+    fn legal_indices(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.length as usize).filter(move |index| self.legal & 1 << index != 0)
+    }
+
+    /// Makes the actions set in `mask` legal as well, keeping the values
+    /// of the ones that already were. False if they all were
+    pub fn widen(&mut self, mask: &[bool]) -> bool {
+        let legal = self.legal | mask_bits(mask);
+        let length = self.length.max(mask.len() as u8);
+        if (legal, length) == (self.legal, self.length) {
+            return false;
+        }
+        let full = self.expand();
+        let mut widened = CompactDistribution::from_bits(legal, length);
+        widened.add(&full);
+        *self = widened;
+        true
+    }
+
+    /// Adds a full distribution, entries of illegal actions are ignored
+    pub fn add(&mut self, full: &[f32]) {
+        let legal = self.legal;
+        let indices = (0..self.length as usize).filter(move |index| legal & 1 << index != 0);
+        for (value, index) in self.values.iter_mut().zip(indices) {
+            if let Some(delta) = full.get(index) {
+                *value += delta;
+            }
+        }
+    }
+
+    /// Adds values given as (index, value), entries of illegal actions are
+    /// ignored
+    pub fn add_sparse(&mut self, sparse: &[(ActionIndex, f32)]) {
+        for (index, delta) in sparse {
+            if !self.is_legal(*index) {
+                continue;
+            }
+            // Legal actions below this one come first in values
+            let position = (self.legal & ((1u128 << index) - 1)).count_ones() as usize;
+            self.values[position] += delta;
+        }
+    }
+
+    /// The full distribution, zero for every illegal action
+    pub fn expand(&self) -> Vec<f32> {
+        let mut full = vec![0.0; self.length as usize];
+        for (value, index) in self.values.iter().zip(self.legal_indices()) {
+            full[index] = *value;
+        }
+        full
+    }
+
+    /// Which actions are legal, what the distribution was made from
+    pub fn mask(&self) -> Vec<bool> {
+        (0..self.length as usize).map(|index| self.legal & 1 << index != 0).collect()
+    }
+
+    /// The values of the legal actions
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    /// Bytes this takes up, for memory estimates
+    pub fn size_of(legal_actions: usize) -> usize {
+        std::mem::size_of::<CompactDistribution>() + 4 * legal_actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_legal_actions_are_stored() {
+        let mut mask = vec![false; 83];
+        mask[0] = true;
+        mask[3] = true;
+        mask[82] = true;
+        let mut distribution = CompactDistribution::zeroes(&mask);
+        assert_eq!(distribution.values().len(), 3);
+
+        let mut full = vec![1.0; 83];
+        full[3] = 2.0;
+        distribution.add(&full);
+        distribution.add(&full);
+        assert_eq!(distribution.values(), &[2.0, 4.0, 2.0]);
+        assert!(distribution.is_legal(82));
+        assert!(!distribution.is_legal(1));
+        assert_eq!(distribution.mask(), mask);
+
+        let (legal, length) = distribution.legal_bits();
+        let mut copy = CompactDistribution::from_bits(legal, length);
+        copy.add_sparse(&[(0, 2.0), (3, 4.0), (82, 2.0), (1, 7.0)]);
+        assert_eq!(copy, distribution);
+
+        let expanded = distribution.expand();
+        assert_eq!(expanded.len(), 83);
+        assert_eq!(expanded[3], 4.0);
+        assert_eq!(expanded[1], 0.0);
+        assert_eq!(expanded.iter().sum::<f32>(), 8.0);
+
+        // Another legal action later keeps what was there
+        let mut wider = mask.clone();
+        wider[1] = true;
+        assert!(distribution.widen(&wider));
+        assert!(!distribution.widen(&mask));
+        distribution.add(&full);
+        assert_eq!(distribution.values(), &[3.0, 1.0, 6.0, 3.0]);
+        assert_eq!(distribution.mask(), wider);
+    }
+}
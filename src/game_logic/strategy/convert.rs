@@ -0,0 +1,224 @@
+/// Converts strategy files between the StrategyStore formats, e.g. the
+/// per player JSON the trainers write into one bincode blueprint and that
+/// into a mapped file for the bot:
+///
+///     gtcogs convert auction_poker_p0.json,auction_poker_p1.json auction_poker.bp auction_poker.mmap
+///
+/// Every step leaves a manifest `<output>.convert.json` with checksums of
+/// its inputs and output. Running the pipeline again skips every step whose
+/// inputs and output still match their manifest, so an interrupted
+/// conversion picks up where it stopped and a finished one is free. Outputs
+/// are written to `<output>.partial`, read back and compared against what
+/// was written before being moved into place, so a crash never leaves a
+/// truncated file behind under the real name.
+///
+/// Entries stream from the inputs into the output and back out for the
+/// comparison (see StrategyStore::open_entries), so converting between the
+/// binary formats never holds more than one player of a strategy in memory
+use crate::game_logic::abstraction::AbstractionHasher;
+use crate::game_logic::strategy::intern::copy_histories;
+use crate::game_logic::strategy::store::{store_for, Entries, Entry, StrategyStore};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Checksummed {
+    file: String,
+    checksum: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Manifest {
+    abstraction: u64,
+    inputs: Vec<Checksummed>,
+    output: Checksummed,
+    info_sets: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepReport {
+    pub output: String,
+    /// The output was already up to date
+    pub skipped: bool,
+    /// Number of info sets of every player in the output
+    pub info_sets: Vec<usize>,
+}
+
+/// FNV-1a of the file contents, read in chunks so big files never sit in memory
+pub fn checksum(file_name: &str) -> Result<u64, String> {
+    let mut file = std::fs::File::open(file_name).map_err(|error| format!("Could not open {}: {}", file_name, error))?;
+    let mut hasher = AbstractionHasher::new();
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        let read = file.read(&mut buffer).map_err(|error| format!("Could not read {}: {}", file_name, error))?;
+        if read == 0 {
+            return Ok(hasher.finish());
+        }
+        hasher.write_bytes(&buffer[..read]);
+    }
+}
+
+fn manifest_name(output: &str) -> String {
+    format!("{}.convert.json", output)
+}
+
+fn read_manifest(output: &str) -> Option<Manifest> {
+    let contents = std::fs::read_to_string(manifest_name(output)).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+/// Order independent fingerprint of the entries of every player, to check
+/// an output against its inputs without holding either in memory
+#[derive(Debug, Clone, PartialEq)]
+struct EntryDigest(Vec<(usize, u64)>);
+
+impl EntryDigest {
+    fn new(num_players: usize) -> Self {
+        EntryDigest(vec![(0, 0); num_players])
+    }
+
+    fn add(&mut self, (player_num, info_set, policy): &Entry) -> Result<(), String> {
+        let mut hasher = AbstractionHasher::new();
+        hasher.write_bytes(&info_set.to_le_bytes());
+        for value in policy.iter() {
+            hasher.write_bytes(&value.to_le_bytes());
+        }
+        let (count, sum) = self.0.get_mut(*player_num).ok_or_else(|| format!("No player {}", player_num))?;
+        *count += 1;
+        *sum = sum.wrapping_add(hasher.finish());
+        Ok(())
+    }
+}
+
+/// Players of every input one after the other, so per player files merge
+/// into one strategy. Inputs saved under another abstraction than
+/// `abstraction` are refused
+fn open_inputs(inputs: &[String], abstraction: u64) -> Result<(Vec<usize>, Entries), String> {
+    let mut counts = vec![];
+    let mut entries: Entries = Box::new(std::iter::empty());
+    for input in inputs {
+        let reader = store_for(input).open_entries(input)?;
+        match reader.abstraction {
+            Some(found) if found != abstraction => {
+                return Err(format!("{} was saved under abstraction {:016x}, expected {:016x}", input, found, abstraction));
+            }
+            Some(_) => {}
+            None => println!("[WARNING] {} has no abstraction hash, assuming {:016x}", input, abstraction),
+        }
+        let first_player = counts.len();
+        counts.extend(reader.counts);
+        let renumbered = reader.entries.map(move |entry| entry.map(|(player_num, info_set, policy)| (first_player + player_num, info_set, policy)));
+        entries = Box::new(entries.chain(renumbered));
+    }
+    Ok((counts, entries))
+}
+
+fn digest_of(store: &dyn StrategyStore, file_name: &str) -> Result<(Option<u64>, EntryDigest), String> {
+    let reader = store.open_entries(file_name)?;
+    let mut digest = EntryDigest::new(reader.counts.len());
+    for entry in reader.entries {
+        digest.add(&entry?)?;
+    }
+    Ok((reader.abstraction, digest))
+}
+
+/// Converts `inputs` into `output`, unless the manifest says that's already been done
+pub fn convert(inputs: &[String], output: &str, abstraction: u64) -> Result<StepReport, String> {
+    let checksummed = inputs
+        .iter()
+        .map(|file| Ok(Checksummed { file: file.clone(), checksum: checksum(file)? }))
+        .collect::<Result<Vec<_>, String>>()?;
+    if let Some(manifest) = read_manifest(output) {
+        if manifest.abstraction == abstraction && manifest.inputs == checksummed && checksum(output).ok() == Some(manifest.output.checksum) {
+            println!("{} is up to date", output);
+            return Ok(StepReport {
+                output: output.to_owned(),
+                skipped: true,
+                info_sets: manifest.info_sets,
+            });
+        }
+    }
+
+    println!("Converting {} to {}", inputs.join(", "), output);
+    let time = std::time::Instant::now();
+    let (info_sets, entries) = open_inputs(inputs, abstraction)?;
+    let partial = format!("{}.partial", output);
+    let mut digest = EntryDigest::new(info_sets.len());
+    let mut entries = entries.map(|entry| {
+        let entry = entry?;
+        digest.add(&entry)?;
+        Ok(entry)
+    });
+    let store = store_for(output);
+    store.write_entries(&partial, abstraction, &info_sets, &mut entries)?;
+    drop(entries);
+    if digest_of(store.as_ref(), &partial)? != (Some(abstraction), digest) {
+        return Err(format!("{} does not read back as what was written", partial));
+    }
+    std::fs::rename(&partial, output).map_err(|error| format!("Could not move {} to {}: {}", partial, output, error))?;
+    copy_histories(inputs, output)?;
+
+    let manifest = Manifest {
+        abstraction,
+        inputs: checksummed,
+        output: Checksummed { file: output.to_owned(), checksum: checksum(output)? },
+        info_sets: info_sets.clone(),
+    };
+    let contents = serde_json::to_string_pretty(&manifest).unwrap();
+    std::fs::write(manifest_name(output), contents).map_err(|error| format!("Could not write the manifest of {}: {}", output, error))?;
+    println!("Converted {} in {:?}", output, time.elapsed());
+
+    Ok(StepReport {
+        output: output.to_owned(),
+        skipped: false,
+        info_sets,
+    })
+}
+
+/// Converts `inputs` into the first of `outputs`, that into the second and so on
+pub fn convert_pipeline(inputs: &[String], outputs: &[String], abstraction: u64) -> Result<Vec<StepReport>, String> {
+    let mut reports = vec![];
+    let mut inputs = inputs.to_vec();
+    for output in outputs {
+        reports.push(convert(&inputs, output, abstraction)?);
+        inputs = vec![output.clone()];
+    }
+    Ok(reports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_logic::strategy::blueprint::compress_policy;
+    use crate::game_logic::strategy::store::JsonStore;
+
+    #[test]
+    fn test_pipeline_converts_and_resumes() {
+        let directory = std::env::temp_dir().join("gtcogs_convert_test");
+        std::fs::create_dir_all(&directory).unwrap();
+        let file = |name: &str| directory.join(name).to_str().unwrap().to_owned();
+        let inputs = vec![file("p0.json"), file("p1.json")];
+        JsonStore.write(&inputs[0], 42, &vec![vec![(9, compress_policy(&vec![1.0])), (3, compress_policy(&vec![0.5, 0.5]))]]).unwrap();
+        JsonStore.write(&inputs[1], 42, &vec![vec![(4, compress_policy(&vec![0.0, 1.0]))]]).unwrap();
+        let outputs = vec![file("strategy.bp"), file("strategy.mmap")];
+
+        let reports = convert_pipeline(&inputs, &outputs, 42).unwrap();
+        assert!(reports.iter().all(|report| !report.skipped));
+        assert_eq!(reports[1].info_sets, vec![2, 1]);
+        let (_, mapped) = store_for(&outputs[1]).read(&outputs[1]).unwrap();
+        assert_eq!(mapped[0][0].0, 3);
+
+        // Nothing changed, nothing to do
+        let reports = convert_pipeline(&inputs, &outputs, 42).unwrap();
+        assert!(reports.iter().all(|report| report.skipped));
+
+        // A damaged output is redone, the same bincode means the next step still isn't
+        std::fs::write(&outputs[0], b"damaged").unwrap();
+        let reports = convert_pipeline(&inputs, &outputs, 42).unwrap();
+        assert!(!reports[0].skipped);
+        assert!(reports[1].skipped);
+
+        assert!(convert_pipeline(&inputs, &outputs[..1], 7).is_err());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
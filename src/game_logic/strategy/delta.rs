@@ -0,0 +1,178 @@
+/// What changed in a strategy, in a form that can be sent to or saved for
+/// another copy of it, without the whole multi-GB table.
+///
+/// Only changed information sets are listed, and of those only the actions
+/// whose value changed, as (ActionIndex, value) pairs. The legal actions
+/// travel as a bit mask so a copy that hasn't seen the information set can
+/// create it.
+///
+/// A delta either adds to the values (DeltaKind::Add) or replaces them
+/// (DeltaKind::Replace):
+///
+///     Add      The changes since a common base copy. Regrets, policies and
+///              visits only ever accumulate, so Add deltas sum up the same
+///              in any order and the copies of several trainers stay in
+///              step by exchanging them, see algorithm::distributed
+///     Replace  The current values of everything updated since some
+///              iteration, which needs no base copy. A full save followed
+///              by Replace deltas of what changed since each previous one
+///              restores the strategy, see RegretStrategy::changed_since
+use crate::game_logic::action::ActionIndex;
+use crate::game_logic::strategy::CondensedInfoSet;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+/// (action, value) of the actions that have a value
+pub type SparseValues = Vec<(ActionIndex, f32)>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DeltaKind {
+    #[default]
+    Add,
+    Replace,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct StrategyDelta {
+    pub kind: DeltaKind,
+    /// Training iterations the changes are from
+    pub iterations: u64,
+    pub entries: Vec<DeltaEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DeltaEntry {
+    pub info_set: CondensedInfoSet,
+    /// Bits of the legal actions, see CompactDistribution::legal_bits
+    pub legal: u128,
+    /// Length of the full distributions
+    pub length: u8,
+    /// None if the map has no entry for the info set
+    pub regrets: Option<SparseValues>,
+    pub policy: Option<SparseValues>,
+    pub visits: u32,
+}
+
+/// The entries of `full` that are not zero
+pub fn sparse(full: &[f32]) -> SparseValues {
+    full.iter()
+        .enumerate()
+        .filter(|(_, value)| **value != 0.0)
+        .map(|(index, value)| (index as ActionIndex, *value))
+        .collect()
+}
+
+impl StrategyDelta {
+    /// The changes of all `deltas` together, which must be of the same
+    /// kind. Replace deltas are taken to be in order, the last value of an
+    /// information set wins
+    pub fn combine(deltas: &[StrategyDelta]) -> StrategyDelta {
+        let mut combined = StrategyDelta {
+            kind: deltas.first().map_or(DeltaKind::Add, |delta| delta.kind),
+            ..Default::default()
+        };
+        let mut positions = HashMap::new();
+        for delta in deltas {
+            assert_eq!(delta.kind, combined.kind, "Only deltas of the same kind can be combined");
+            combined.iterations += delta.iterations;
+            for entry in &delta.entries {
+                match positions.get(&entry.info_set) {
+                    Some(position) if combined.kind == DeltaKind::Add => {
+                        let existing: &mut DeltaEntry = &mut combined.entries[*position];
+                        add_into(&mut existing.regrets, &entry.regrets);
+                        add_into(&mut existing.policy, &entry.policy);
+                        existing.visits += entry.visits;
+                    }
+                    Some(position) => combined.entries[*position] = entry.clone(),
+                    None => {
+                        positions.insert(entry.info_set, combined.entries.len());
+                        combined.entries.push(entry.clone());
+                    }
+                }
+            }
+        }
+        combined
+    }
+
+    /// Writes the delta with its length in front, so several can follow
+    /// each other on a stream
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), String> {
+        let bytes = bincode::serialize(self).map_err(|e| e.to_string())?;
+        writer.write_all(&(bytes.len() as u64).to_le_bytes()).map_err(|e| e.to_string())?;
+        writer.write_all(&bytes).map_err(|e| e.to_string())?;
+        writer.flush().map_err(|e| e.to_string())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<StrategyDelta, String> {
+        let mut length = [0u8; 8];
+        reader.read_exact(&mut length).map_err(|e| e.to_string())?;
+        let mut bytes = vec![0u8; u64::from_le_bytes(length) as usize];
+        reader.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+        bincode::deserialize(&bytes).map_err(|e| format!("Not a strategy delta: {}", e))
+    }
+}
+
+fn add_into(into: &mut Option<SparseValues>, other: &Option<SparseValues>) {
+    match (into.as_mut(), other) {
+        (Some(values), Some(others)) => {
+            for (index, other) in others {
+                match values.iter_mut().find(|(i, _)| i == index) {
+                    Some((_, value)) => *value += other,
+                    None => values.push((*index, *other)),
+                }
+            }
+            values.sort_by_key(|(index, _)| *index);
+        }
+        (None, Some(others)) => *into = Some(others.clone()),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(info_set: CondensedInfoSet, regrets: &[f32], visits: u32) -> DeltaEntry {
+        DeltaEntry {
+            info_set,
+            legal: (1 << regrets.len()) - 1,
+            length: regrets.len() as u8,
+            regrets: Some(sparse(regrets)),
+            policy: None,
+            visits,
+        }
+    }
+
+    #[test]
+    fn test_deltas_combine_and_round_trip() {
+        assert_eq!(sparse(&[0.0, 2.0, 0.0, -1.0]), vec![(1, 2.0), (3, -1.0)]);
+        let first = StrategyDelta {
+            iterations: 10,
+            entries: vec![entry(1, &[1.0, 0.0, 2.0], 3), entry(2, &[1.0], 1)],
+            ..Default::default()
+        };
+        let second = StrategyDelta {
+            iterations: 5,
+            entries: vec![entry(1, &[-1.0, 1.0, 0.0], 1)],
+            ..Default::default()
+        };
+        let combined = StrategyDelta::combine(&[first.clone(), second.clone()]);
+        assert_eq!(combined.iterations, 15);
+        assert_eq!(combined.entries.len(), 2);
+        assert_eq!(combined.entries[0].regrets, Some(vec![(0, 0.0), (1, 1.0), (2, 2.0)]));
+        assert_eq!(combined.entries[0].visits, 4);
+
+        let replace = |delta: &StrategyDelta| StrategyDelta { kind: DeltaKind::Replace, ..delta.clone() };
+        let replaced = StrategyDelta::combine(&[replace(&first), replace(&second)]);
+        assert_eq!(replaced.entries[0], entry(1, &[-1.0, 1.0, 0.0], 1));
+
+        let mut bytes = vec![];
+        first.write_to(&mut bytes).unwrap();
+        combined.write_to(&mut bytes).unwrap();
+        let mut reader = bytes.as_slice();
+        assert_eq!(StrategyDelta::read_from(&mut reader).unwrap(), first);
+        assert_eq!(StrategyDelta::read_from(&mut reader).unwrap(), combined);
+        assert!(StrategyDelta::read_from(&mut reader).is_err());
+    }
+}
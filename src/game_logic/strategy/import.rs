@@ -0,0 +1,316 @@
+/// Converts strategies computed by other tools into a BlueprintStrategy,
+/// so externally trained baselines can be played against our own.
+///
+/// Other tools know nothing about our abstraction, so the caller provides
+/// the mapping: which player and CondensedInfoSet an external info set
+/// string corresponds to, and which ActionIndex each external action is.
+/// Anything either mapping returns None for is skipped. When several
+/// external info sets map to the same info set of ours their policies
+/// are averaged.
+///
+/// Supported formats:
+///     OpenSpiel   JSON of a tabular policy's dictionary, i.e.
+///                 {"<info state>": [[<action>, <probability>], ...], ...}
+///                 with actions given by their OpenSpiel action ids
+///     ACPC        One info set per line, "<info set> <fold> <call> <raise>"
+///                 (probabilities in the order of the ACPC action letters
+///                 f, c and r, which are passed to the action mapping).
+///                 Blank lines and lines starting with # are ignored
+///
+/// From the command line (`gtcogs import`) the mapping is a JSON file,
+/// see ImportMapping
+use crate::game_logic::action::ActionIndex;
+use crate::game_logic::strategy::blueprint::*;
+use crate::game_logic::strategy::{CondensedInfoSet, PolicyDistribution};
+use crate::game_logic::visibility::History;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Our player and info set for an external info set string
+pub type InfoSetMapping<'a> = &'a dyn Fn(&str) -> Option<(usize, CondensedInfoSet)>;
+/// Our action for an external action label (given the external info set)
+pub type ActionMapping<'a> = &'a dyn Fn(&str, &str) -> Option<ActionIndex>;
+
+const ACPC_ACTIONS: [&str; 3] = ["f", "c", "r"];
+
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub imported: usize,
+    pub skipped_info_sets: usize,
+    pub skipped_actions: usize,
+}
+
+/// Sums up the policies of every info set before they are normalized
+struct Importer {
+    policies: Vec<HashMap<CondensedInfoSet, PolicyDistribution>>,
+    summary: ImportSummary,
+}
+
+impl Importer {
+    fn new(num_players: usize) -> Self {
+        Importer {
+            policies: vec![HashMap::new(); num_players],
+            summary: ImportSummary::default(),
+        }
+    }
+
+    fn add(
+        &mut self,
+        info_set_map: InfoSetMapping,
+        action_map: ActionMapping,
+        external: &str,
+        actions: &[(String, f32)],
+    ) -> Result<(), String> {
+        let (player_num, info_set) = match info_set_map(external) {
+            Some(mapped) => mapped,
+            None => {
+                self.summary.skipped_info_sets += 1;
+                return Ok(());
+            }
+        };
+        if player_num >= self.policies.len() {
+            return Err(format!("{} was mapped to player {}", external, player_num));
+        }
+        let history: History = info_set.into();
+        match history.0.first() {
+            Some(&round) if (round as usize) < SHARD_NAMES.len() => {}
+            _ => return Err(format!("{} was mapped to {:?}, which doesn't start with a round", external, history)),
+        }
+
+        let mut policy = vec![0.0; COMPRESSED_POLICY_LENGTH];
+        for (action, probability) in actions {
+            if !(0.0..=1.0).contains(probability) {
+                return Err(format!("{} has probability {} for {}", external, probability, action));
+            }
+            match action_map(external, action) {
+                Some(index) if (index as usize) < COMPRESSED_POLICY_LENGTH => policy[index as usize] += probability,
+                Some(index) => return Err(format!("{} in {} was mapped to out of range index {}", action, external, index)),
+                None => self.summary.skipped_actions += 1,
+            }
+        }
+        let total: f32 = policy.iter().sum();
+        if total <= 0.0 {
+            self.summary.skipped_info_sets += 1;
+            return Ok(());
+        }
+        let summed = self.policies[player_num]
+            .entry(info_set)
+            .or_insert_with(|| vec![0.0; COMPRESSED_POLICY_LENGTH]);
+        for (sum, probability) in summed.iter_mut().zip(policy) {
+            *sum += probability / total;
+        }
+        self.summary.imported += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> (BlueprintStrategy, ImportSummary) {
+        let policies = self
+            .policies
+            .into_iter()
+            .map(|policies| {
+                policies
+                    .into_iter()
+                    .map(|(info_set, policy)| {
+                        let total: f32 = policy.iter().sum();
+                        (info_set, policy.iter().map(|p| p / total).collect())
+                    })
+                    .collect::<BTreeMap<_, _>>()
+            })
+            .collect();
+        (BlueprintStrategy::from_policies(policies), self.summary)
+    }
+}
+
+pub fn parse_openspiel(
+    contents: &str,
+    num_players: usize,
+    info_set_map: InfoSetMapping,
+    action_map: ActionMapping,
+) -> Result<(BlueprintStrategy, ImportSummary), String> {
+    let table: BTreeMap<String, Vec<(i64, f32)>> =
+        serde_json::from_str(contents).map_err(|error| format!("Not an OpenSpiel tabular policy: {}", error))?;
+    let mut importer = Importer::new(num_players);
+    for (external, actions) in table {
+        let actions: Vec<(String, f32)> = actions
+            .into_iter()
+            .map(|(action, probability)| (action.to_string(), probability))
+            .collect();
+        importer.add(info_set_map, action_map, &external, &actions)?;
+    }
+    Ok(importer.finish())
+}
+
+pub fn parse_acpc(
+    contents: &str,
+    num_players: usize,
+    info_set_map: InfoSetMapping,
+    action_map: ActionMapping,
+) -> Result<(BlueprintStrategy, ImportSummary), String> {
+    let mut importer = Importer::new(num_players);
+    for (line_num, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 1 + ACPC_ACTIONS.len() {
+            return Err(format!("Line {}: expected an info set and {} probabilities", line_num + 1, ACPC_ACTIONS.len()));
+        }
+        let mut actions = vec![];
+        for (action, field) in ACPC_ACTIONS.iter().zip(&fields[1..]) {
+            let probability: f32 = field
+                .parse()
+                .map_err(|_| format!("Line {}: {} is not a probability", line_num + 1, field))?;
+            actions.push((action.to_string(), probability));
+        }
+        importer.add(info_set_map, action_map, fields[0], &actions)?;
+    }
+    Ok(importer.finish())
+}
+
+fn read(file_name: &str) -> Result<String, String> {
+    std::fs::read_to_string(file_name).map_err(|error| format!("Could not read {}: {}", file_name, error))
+}
+
+pub fn import_openspiel(
+    file_name: &str,
+    num_players: usize,
+    info_set_map: InfoSetMapping,
+    action_map: ActionMapping,
+) -> Result<BlueprintStrategy, String> {
+    let (strategy, summary) = parse_openspiel(&read(file_name)?, num_players, info_set_map, action_map)?;
+    println!("Imported {} from {}: {:?}", summary.imported, file_name, summary);
+    Ok(strategy)
+}
+
+pub fn import_acpc(
+    file_name: &str,
+    num_players: usize,
+    info_set_map: InfoSetMapping,
+    action_map: ActionMapping,
+) -> Result<BlueprintStrategy, String> {
+    let (strategy, summary) = parse_acpc(&read(file_name)?, num_players, info_set_map, action_map)?;
+    println!("Imported {} from {}: {:?}", summary.imported, file_name, summary);
+    Ok(strategy)
+}
+
+/// The mappings of an import written down, for when there's no code to
+/// provide them:
+///
+///     {"info_sets": {"<external info set>": [<player>, [<history>...]], ...},
+///      "actions": {"<external action>": <action index>, ...}}
+///
+/// where the history is that of our info set, starting with its round
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImportMapping {
+    pub info_sets: HashMap<String, (usize, Vec<u8>)>,
+    pub actions: HashMap<String, ActionIndex>,
+}
+
+impl ImportMapping {
+    pub fn load(file_name: &str) -> Result<ImportMapping, String> {
+        serde_json::from_str(&read(file_name)?).map_err(|error| format!("{} is not an import mapping: {}", file_name, error))
+    }
+
+    pub fn info_set(&self, external: &str) -> Option<(usize, CondensedInfoSet)> {
+        let (player_num, history) = self.info_sets.get(external)?;
+        Some((*player_num, History(history.clone()).into_condensed()))
+    }
+
+    pub fn action(&self, _external: &str, action: &str) -> Option<ActionIndex> {
+        self.actions.get(action).copied()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportFormat {
+    OpenSpiel,
+    Acpc,
+}
+
+impl ImportFormat {
+    pub fn parse(name: &str) -> Result<ImportFormat, String> {
+        match name {
+            "openspiel" => Ok(ImportFormat::OpenSpiel),
+            "acpc" => Ok(ImportFormat::Acpc),
+            x => Err(format!("Unknown import format {}, expected openspiel or acpc", x)),
+        }
+    }
+}
+
+/// Imports `file_name` with the mappings of `mapping`
+pub fn import_with(
+    format: ImportFormat,
+    file_name: &str,
+    num_players: usize,
+    mapping: &ImportMapping,
+) -> Result<BlueprintStrategy, String> {
+    let info_set_map = |external: &str| mapping.info_set(external);
+    let action_map = |external: &str, action: &str| mapping.action(external, action);
+    match format {
+        ImportFormat::OpenSpiel => import_openspiel(file_name, num_players, &info_set_map, &action_map),
+        ImportFormat::Acpc => import_acpc(file_name, num_players, &info_set_map, &action_map),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Preflop info sets of a made up game keyed by their last character
+    fn info_set_map(external: &str) -> Option<(usize, CondensedInfoSet)> {
+        let player_num = external.len() % 2;
+        let card = external.chars().last()?.to_digit(10)? as ActionIndex;
+        Some((player_num, History(vec![0, card]).into_condensed()))
+    }
+
+    #[test]
+    fn test_import_openspiel_policy() {
+        let contents = r#"{"1": [[0, 0.25], [1, 0.75]], "2": [[0, 1.0], [2, 0.0]], "x": [[0, 1.0]]}"#;
+        let action_map = |_: &str, action: &str| action.parse().ok().filter(|index: &ActionIndex| *index < 2);
+        let (strategy, summary) = parse_openspiel(contents, 2, &info_set_map, &action_map).unwrap();
+        assert_eq!(summary.imported, 2);
+        assert_eq!(summary.skipped_info_sets, 1);
+        assert_eq!(summary.skipped_actions, 1);
+
+        let policy = strategy.get_info_set_policy(History(vec![0, 1]).into_condensed(), 1).unwrap();
+        assert_eq!(policy.len(), 2);
+        assert!((policy[0].1 - 0.25).abs() < 1e-2);
+        let policy = strategy.get_info_set_policy(History(vec![0, 2]).into_condensed(), 1).unwrap();
+        assert_eq!(policy, vec![(0, 1.0)]);
+        assert!(strategy.get_info_set_policy(History(vec![0, 2]).into_condensed(), 0).is_none());
+    }
+
+    #[test]
+    fn test_import_acpc_averages_merged_info_sets() {
+        let contents = "# player 1\n1 0.0 1.0 0.0\n\n11 0.0 0.0 1.0\n";
+        // Everything maps to the same info set of player 1
+        let info_set_map = |_: &str| Some((1, History(vec![0, 1]).into_condensed()));
+        let action_map = |_: &str, action: &str| ACPC_ACTIONS.iter().position(|a| *a == action).map(|i| i as ActionIndex);
+        let (strategy, summary) = parse_acpc(contents, 2, &info_set_map, &action_map).unwrap();
+        assert_eq!(summary.imported, 2);
+        let policy = strategy.get_info_set_policy(History(vec![0, 1]).into_condensed(), 1).unwrap();
+        assert_eq!(policy.iter().map(|(index, _)| *index).collect::<Vec<_>>(), vec![1, 2]);
+        assert!((policy[0].1 - 0.5).abs() < 1e-2);
+
+        assert!(parse_acpc("1 0.5 0.5", 2, &info_set_map, &action_map).is_err());
+    }
+
+    #[test]
+    fn test_import_with_a_mapping_file() {
+        let directory = std::env::temp_dir();
+        let strategy_file = directory.join("gtcogs_import_test.txt");
+        let mapping_file = directory.join("gtcogs_import_test.json");
+        std::fs::write(&strategy_file, "Kc 0.0 0.25 0.75\nQc 1.0 0.0 0.0\n").unwrap();
+        std::fs::write(&mapping_file, r#"{"info_sets": {"Kc": [0, [0, 7]]}, "actions": {"c": 1, "r": 2}}"#).unwrap();
+
+        let mapping = ImportMapping::load(mapping_file.to_str().unwrap()).unwrap();
+        let strategy = import_with(ImportFormat::parse("acpc").unwrap(), strategy_file.to_str().unwrap(), 2, &mapping);
+        std::fs::remove_file(&strategy_file).unwrap();
+        std::fs::remove_file(&mapping_file).unwrap();
+        let policy = strategy.unwrap().get_info_set_policy(History(vec![0, 7]).into_condensed(), 0).unwrap();
+        assert_eq!(policy.iter().map(|(index, _)| *index).collect::<Vec<_>>(), vec![1, 2]);
+        assert!(ImportFormat::parse("pluribus").is_err());
+    }
+}
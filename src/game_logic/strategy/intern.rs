@@ -0,0 +1,229 @@
+/// Interning of info set keys: every distinct key is stored once and
+/// referred to by a dense u32 id everywhere else.
+///
+/// A CondensedInfoSet packs the whole feature history into a u64, which
+/// stops working once histories get long (see max_features). Such
+/// histories are interned in the process wide HISTORIES instead, and their
+/// CondensedInfoSet is the id under the INTERNED bit, so the policy and
+/// regret maps stay keyed by a u64 that hashes in a single step whatever
+/// the length of the history.
+///
+/// Ids only mean something in the process that handed them out, so a table
+/// with interned info sets is saved with its histories next to it
+/// (`<file>.histories`, see save_histories) and they are interned again,
+/// and the table rekeyed, when it is loaded (see load_histories)
+use crate::game_logic::action::ActionIndex;
+use crate::game_logic::strategy::CondensedInfoSet;
+use crate::game_logic::visibility::History;
+use dashmap::DashMap;
+use std::collections::BTreeMap;
+use std::hash::Hash;
+use std::sync::{Arc, LazyLock, RwLock};
+
+pub type InfoSetId = u32;
+
+/// Set in the CondensedInfoSet of an interned history, whose lowest bit is
+/// clear like that of a history packed in base MAX_ACTIONS, and those never
+/// get this high (see max_features)
+pub const INTERNED: CondensedInfoSet = 1 << 63;
+
+/// The histories interned by this process, shared by every trainer
+pub static HISTORIES: LazyLock<HistoryInterner> = LazyLock::new(HistoryInterner::new);
+
+#[derive(Debug, Default)]
+pub struct Interner<K: Hash + Eq + Clone> {
+    ids: DashMap<K, InfoSetId>,
+    keys: RwLock<Vec<K>>,
+}
+
+impl<K: Hash + Eq + Clone> Interner<K> {
+    pub fn new() -> Self {
+        Interner {
+            ids: DashMap::new(),
+            keys: RwLock::new(vec![]),
+        }
+    }
+
+    /// The id of `key`, giving it the next free id if it hasn't been seen
+    pub fn intern(&self, key: &K) -> InfoSetId {
+        if let Some(id) = self.ids.get(key) {
+            return *id;
+        }
+        // The entry stays locked until the key is in the table, so two
+        // threads interning the same key can't both give it an id
+        *self.ids.entry(key.clone()).or_insert_with(|| {
+            let mut keys = self.keys.write().unwrap();
+            let id = InfoSetId::try_from(keys.len()).expect("More info sets than fit in an InfoSetId");
+            keys.push(key.clone());
+            id
+        })
+    }
+
+    pub fn key(&self, id: InfoSetId) -> Option<K> {
+        self.keys.read().unwrap().get(id as usize).cloned()
+    }
+}
+
+/// Histories are shared between the id map and the key table
+pub type HistoryInterner = Interner<Arc<[ActionIndex]>>;
+
+impl HistoryInterner {
+    /// The CondensedInfoSet of `history` as an interned history
+    pub fn condense(&self, history: &History) -> CondensedInfoSet {
+        let id = self.intern(&Arc::from(history.0.as_slice()));
+        INTERNED | (id as CondensedInfoSet) << 1
+    }
+
+    /// The history behind an info set from `condense`
+    pub fn history(&self, info_set: CondensedInfoSet) -> Option<History> {
+        if !is_interned(info_set) {
+            return None;
+        }
+        let id = InfoSetId::try_from((info_set & !INTERNED) >> 1).ok()?;
+        self.key(id).map(|key| History(key.to_vec()))
+    }
+}
+
+pub fn is_interned(info_set: CondensedInfoSet) -> bool {
+    info_set & (INTERNED | 1) == INTERNED
+}
+
+pub fn histories_name(file_name: &str) -> String {
+    format!("{}.histories", file_name)
+}
+
+/// Writes the histories of the interned ones of `info_sets` next to
+/// `file_name`, or removes a stale file if there are none
+pub fn save_histories<'a>(file_name: &str, info_sets: impl IntoIterator<Item = &'a CondensedInfoSet>) -> Result<(), String> {
+    let histories: BTreeMap<CondensedInfoSet, Vec<ActionIndex>> = info_sets
+        .into_iter()
+        .filter(|info_set| is_interned(**info_set))
+        .map(|info_set| match HISTORIES.history(*info_set) {
+            Some(history) => Ok((*info_set, history.0)),
+            None => Err(format!("{:016x} was not interned by this process", info_set)),
+        })
+        .collect::<Result<_, String>>()?;
+    write_histories(file_name, &histories)
+}
+
+fn write_histories(file_name: &str, histories: &BTreeMap<CondensedInfoSet, Vec<ActionIndex>>) -> Result<(), String> {
+    let name = histories_name(file_name);
+    if histories.is_empty() {
+        let _ = std::fs::remove_file(&name);
+        return Ok(());
+    }
+    let contents = serde_json::to_string(histories).unwrap();
+    std::fs::write(&name, contents).map_err(|error| format!("Could not write {}: {}", name, error))
+}
+
+fn read_histories(file_name: &str) -> Result<BTreeMap<CondensedInfoSet, Vec<ActionIndex>>, String> {
+    let name = histories_name(file_name);
+    match std::fs::read_to_string(&name) {
+        Ok(contents) => serde_json::from_str(&contents).map_err(|error| format!("{} is damaged: {}", name, error)),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(error) => Err(format!("Could not read {}: {}", name, error)),
+    }
+}
+
+/// Interns the histories saved next to `file_name` in this process, giving
+/// the info set each interned info set of the file is now known as
+pub fn load_histories(file_name: &str) -> Result<BTreeMap<CondensedInfoSet, CondensedInfoSet>, String> {
+    load_histories_into(file_name, &HISTORIES)
+}
+
+fn load_histories_into(file_name: &str, interner: &HistoryInterner) -> Result<BTreeMap<CondensedInfoSet, CondensedInfoSet>, String> {
+    Ok(read_histories(file_name)?
+        .into_iter()
+        .map(|(saved, history)| (saved, interner.condense(&History(history))))
+        .collect())
+}
+
+/// The info set `info_set` of a file is now known as, see load_histories.
+/// An interned info set the file has no history for is an error
+pub fn rekey(info_set: CondensedInfoSet, rekeyed: &BTreeMap<CondensedInfoSet, CondensedInfoSet>) -> Result<CondensedInfoSet, String> {
+    if !is_interned(info_set) {
+        return Ok(info_set);
+    }
+    rekeyed
+        .get(&info_set)
+        .copied()
+        .ok_or_else(|| format!("No saved history for the interned info set {:016x}", info_set))
+}
+
+/// Carries the histories of `inputs` over to `output`, for conversions that
+/// copy info sets as they are. The inputs must come from one process (like
+/// the per player files of a training run), so no id means two histories
+pub fn copy_histories(inputs: &[String], output: &str) -> Result<(), String> {
+    let mut histories = BTreeMap::new();
+    for input in inputs {
+        for (info_set, history) in read_histories(input)? {
+            if histories.get(&info_set).is_some_and(|known| *known != history) {
+                return Err(format!("{} gives the interned info set {:016x} another history", input, info_set));
+            }
+            histories.insert(info_set, history);
+        }
+    }
+    write_histories(output, &histories)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histories_are_interned_once() {
+        let interner = HistoryInterner::new();
+        let flop = History(vec![2, 14, 0, 1, 5, 7]);
+        let river = History(vec![4, 25, 0, 2, 10, 20, 20]);
+        assert_eq!(interner.condense(&flop), INTERNED);
+        assert_eq!(interner.condense(&river), INTERNED | 2);
+        assert_eq!(interner.condense(&flop), INTERNED);
+        assert_eq!(interner.history(INTERNED | 2).unwrap().0, river.0);
+        assert!(interner.history(INTERNED | 4).is_none());
+        assert!(interner.history(river.into_condensed()).is_none());
+    }
+
+    #[test]
+    fn test_interning_from_many_threads_gives_dense_ids() {
+        let interner = Interner::<u64>::new();
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for key in 0..1000u64 {
+                        interner.intern(&key);
+                    }
+                });
+            }
+        });
+        let mut ids: Vec<InfoSetId> = (0..1000u64).map(|key| interner.intern(&key)).collect();
+        ids.sort();
+        assert_eq!(ids, (0..1000).collect::<Vec<_>>());
+        assert!(interner.key(1000).is_none());
+    }
+
+    #[test]
+    fn test_histories_survive_a_save_into_another_process() {
+        let long = History((0..12).collect());
+        let info_set = long.clone().into_condensed();
+        assert!(is_interned(info_set));
+        assert_eq!(History::from(info_set).0, long.0);
+
+        let file_name = std::env::temp_dir().join(format!("gtcogs_intern_{}.json", std::process::id()));
+        let file_name = file_name.to_str().unwrap();
+        let short = History(vec![0, 1]).into_condensed();
+        save_histories(file_name, &[short, info_set]).unwrap();
+
+        // Another process hands out its ids in another order
+        let elsewhere = HistoryInterner::new();
+        elsewhere.condense(&History(vec![7; 20]));
+        let rekeyed = load_histories_into(file_name, &elsewhere).unwrap();
+        let moved = rekey(info_set, &rekeyed).unwrap();
+        assert_ne!(moved, info_set);
+        assert_eq!(elsewhere.history(moved).unwrap().0, long.0);
+        assert_eq!(rekey(short, &rekeyed), Ok(short));
+        assert!(rekey(INTERNED | 8, &rekeyed).is_err());
+
+        save_histories(file_name, &[short]).unwrap();
+        assert!(!std::path::Path::new(&histories_name(file_name)).exists());
+    }
+}
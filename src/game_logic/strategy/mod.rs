@@ -1,5 +1,12 @@
 pub mod regret;
 pub mod blueprint;
+pub mod compact;
+pub mod convert;
+pub mod delta;
+pub mod import;
+pub mod intern;
+pub mod report;
+pub mod store;
 pub use regret::*;
 pub use blueprint::*;
 
@@ -8,5 +15,5 @@ use dashmap::DashMap;
 pub type CondensedInfoSet = u64;
 pub type PolicyDistribution = Vec<f32>;
 pub type RegretDistribution = Vec<f32>;
-pub type PolicyMap = DashMap<CondensedInfoSet, PolicyDistribution>;
-pub type RegretMap = DashMap<CondensedInfoSet, RegretDistribution>;
+pub type PolicyMap = DashMap<CondensedInfoSet, compact::CompactDistribution>;
+pub type RegretMap = DashMap<CondensedInfoSet, compact::CompactDistribution>;
@@ -1,22 +1,53 @@
 use crate::game_logic::action::GameMapper;
-use crate::game_logic::action::Action;
-use dashmap::DashMap;
-
-use std::fs::File;
-use std::io::Write;
+use crate::game_logic::action::{Action, ActionIndex};
+use crate::game_logic::visibility::History;
+use dashmap::{DashMap, DashSet};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 
 use crate::game_logic::strategy::CondensedInfoSet;
 use crate::game_logic::strategy::PolicyDistribution;
 use crate::game_logic::strategy::RegretDistribution;
 use crate::game_logic::strategy::RegretMap;
 use crate::game_logic::strategy::PolicyMap;
+use crate::game_logic::strategy::compact::CompactDistribution;
+use crate::game_logic::strategy::delta::{sparse, DeltaEntry, DeltaKind, SparseValues, StrategyDelta};
+use crate::game_logic::strategy::store::{parallel_chunks, write_full_table};
+use crate::game_logic::strategy::intern::save_histories;
 
 
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct RegretStrategy {
     //iterations: AtomicCell<usize>,
     policy_map: PolicyMap,
     regret_map: RegretMap,
+    visits: DashMap<CondensedInfoSet, u32>,
+    /// Information sets whose regrets and policy no longer change, see freeze
+    frozen: DashSet<CondensedInfoSet>,
+    /// Whether update counts into dirty and last_updated, see track_updates
+    tracking: AtomicBool,
+    /// Updates since the strategy was last saved
+    dirty: AtomicUsize,
+    /// Training iterations run on the strategy so far, see tick
+    iteration: AtomicU64,
+    /// Iteration each information set was last updated in
+    last_updated: DashMap<CondensedInfoSet, u64>,
+}
+
+impl Clone for RegretStrategy {
+    fn clone(&self) -> Self {
+        RegretStrategy {
+            policy_map: self.policy_map.clone(),
+            regret_map: self.regret_map.clone(),
+            visits: self.visits.clone(),
+            frozen: self.frozen.clone(),
+            tracking: AtomicBool::new(self.tracks_updates()),
+            dirty: AtomicUsize::new(self.dirty()),
+            iteration: AtomicU64::new(self.iteration()),
+            last_updated: self.last_updated.clone(),
+        }
+    }
 }
 
 impl Default for RegretStrategy {
@@ -25,6 +56,12 @@ impl Default for RegretStrategy {
             //iterations: 0,
             policy_map: DashMap::new(),
             regret_map: DashMap::new(),
+            visits: DashMap::new(),
+            frozen: DashSet::new(),
+            tracking: AtomicBool::new(false),
+            dirty: AtomicUsize::new(0),
+            iteration: AtomicU64::new(0),
+            last_updated: DashMap::new(),
         }
     }
 }
@@ -35,8 +72,7 @@ impl RegretStrategy {
         // TODO: speeeeeeeeeeeeeeeeed get rid of the clone somehow
         self.regret_map
             .get(information_set)
-            .map(|r| (*r).clone())
-            .map(|v| Vec::from(v))
+            .map(|r| r.expand())
     }
 
     pub fn policy(&self, information_set: &CondensedInfoSet) -> Option<PolicyDistribution> {
@@ -44,56 +80,104 @@ impl RegretStrategy {
         // TODO: speeeeeeeeeeeeeeeeed, get rid of the clone somehow
         self.policy_map
             .get(information_set)
-            .map(|r| (*r).clone())
-            .map(|v| Vec::from(v))
+            .map(|r| r.expand())
     }
 
-    pub fn save_table_json<A: Action>(&self, file_name: &str, action_mapper: &GameMapper<A>) {
-        let mut file = File::create(file_name).unwrap();
-        let mut table = Vec::new();
+    /// Saves the average strategy of this player under `abstraction` (see
+    /// State::abstraction_hash) as JSON, see store::write_full_table.
+    /// Policies are normalized on every core and saved at full precision,
+    /// along with the histories of interned info sets
+    pub fn save_table<A: Action>(&self, file_name: &str, action_mapper: &GameMapper<A>, abstraction: u64) {
         println!("Saving table to {}", file_name);
-        for reference in self.policy_map.iter() {
-            let (information_set, strategy) = reference.pair();
+        let info_sets: Vec<CondensedInfoSet> = self.policy_map.iter().map(|reference| *reference.key()).collect();
+        let table = parallel_chunks(&info_sets, |chunk| {
+            chunk
+                .iter()
+                .filter_map(|information_set| {
+                    let strategy = self.policy_map.get(information_set)?.expand();
 
-            // Optimization: only if there is a non-zero value in the strategy, add it to the table
-            if strategy.iter().all(|&x| x < 0.0001) {
-                continue;
-            }
+                    // Optimization: only if there is a non-zero value in the strategy, add it to the table
+                    if strategy.iter().all(|&x| x < 0.0001) {
+                        return None;
+                    }
+                    Some((*information_set, normalized(strategy)))
+                })
+                .collect::<Vec<_>>()
+        });
+        let table = table.concat();
+        write_full_table(file_name, abstraction, &table).unwrap();
+        save_histories(file_name, table.iter().map(|(info_set, _)| info_set)).unwrap();
+        self.dirty.store(0, Ordering::SeqCst);
+    }
 
-            let strategy = normalized(strategy.clone());
-            table.push((information_set.clone(), strategy.clone()));
-        }
-        let json = serde_json::to_string(&table).unwrap();
-        file.write_all(json.as_bytes()).unwrap();
+    /// The normalized average strategy of every information set that has
+    /// one, what save_table writes
+    pub fn policy_table(&self) -> BTreeMap<CondensedInfoSet, PolicyDistribution> {
+        self.policy_map
+            .iter()
+            .map(|reference| (*reference.key(), reference.value().expand()))
+            .filter(|(_, strategy)| !strategy.iter().all(|&x| x < 0.0001))
+            .map(|(information_set, strategy)| (information_set, normalized(strategy)))
+            .collect()
+    }
+
+    /// Number of updates since the last save_table, counted only while
+    /// updates are tracked
+    pub fn dirty(&self) -> usize {
+        self.dirty.load(Ordering::SeqCst)
+    }
+
+    /// From now on count every update into dirty and last_updated. Off by
+    /// default, as on every update of every runner it costs a write to a
+    /// counter and a map all the runners share, and only checkpoints
+    /// skipped or written as deltas by how much changed need it
+    pub fn track_updates(&self) {
+        self.tracking.store(true, Ordering::Relaxed);
+    }
+
+    pub fn tracks_updates(&self) -> bool {
+        self.tracking.load(Ordering::Relaxed)
     }
 
     ///[Neal] Update the policy distribution of an information set based on the regrets
     /// and current strategy
+    ///
+    /// Only the actions set in `mask` are stored. An information set seen
+    /// with other legal actions than before (see constraint_check) stores the
+    /// union of them, so no regret is ever dropped
     pub fn update(
         &self,
         info_set: CondensedInfoSet,
+        mask: &[bool],
         d_reg: Option<&[f32]>, // [Neal] Observed current regrets at a terminal history
         d_strat: Option<&[f32]>, // [Neal] Observed current strategy at a terminal history TODO: ?
     ) {
-        let len = d_reg
-            .or(d_strat)
-            .expect("Pass at least one of d_reg, d_strat to update")
-            .len();
-        if let Some(d) = d_strat {
-            let entry = self.policy_map.entry(info_set.clone());
-            let mut val = entry.or_insert_with(|| vec![0.0; len]);
-            if len != d.len() {
+        if let (Some(d_reg), Some(d_strat)) = (d_reg, d_strat) {
+            if d_reg.len() != d_strat.len() {
                 panic!("Passed d_reg and d_strat must have same length.")
             }
-            for (ve, de) in val.iter_mut().zip(d) {
-                *ve += de;
+        }
+        // Frozen information sets still get an (empty) entry so there is
+        // always a policy to read after an update
+        let frozen = self.frozen.contains(&info_set);
+        if !frozen && self.tracks_updates() {
+            self.dirty.fetch_add(1, Ordering::Relaxed);
+            self.last_updated.insert(info_set, self.iteration());
+        }
+        if let Some(d) = d_strat {
+            let entry = self.policy_map.entry(info_set.clone());
+            let mut distribution = entry.or_insert_with(|| CompactDistribution::zeroes(mask));
+            if !frozen {
+                distribution.widen(mask);
+                distribution.add(d);
             }
         }
         if let Some(d) = d_reg {
             let entry = self.regret_map.entry(info_set.clone());
-            let mut val = entry.or_insert_with(|| vec![0.0; len]);
-            for (ve, de) in val.iter_mut().zip(d) {
-                *ve += de;
+            let mut distribution = entry.or_insert_with(|| CompactDistribution::zeroes(mask));
+            if !frozen {
+                distribution.widen(mask);
+                distribution.add(d);
             }
         }
     }
@@ -101,6 +185,379 @@ impl RegretStrategy {
     pub fn size(&self) -> usize {
         self.policy_map.len()
     }
+
+    /// Counts a training iteration, every runner training the strategy
+    /// calls this once per iteration
+    pub fn tick(&self) {
+        self.iteration.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn iteration(&self) -> u64 {
+        self.iteration.load(Ordering::Relaxed)
+    }
+
+    /// The iteration an information set was last updated in, if updates
+    /// were tracked then
+    pub fn last_updated(&self, info_set: &CondensedInfoSet) -> Option<u64> {
+        self.last_updated.get(info_set).map(|iteration| *iteration)
+    }
+
+    /// Iterations since an information set was last updated, for discounting
+    /// or pruning by age
+    pub fn age(&self, info_set: &CondensedInfoSet) -> Option<u64> {
+        self.last_updated(info_set).map(|iteration| self.iteration().saturating_sub(iteration))
+    }
+
+    /// The latest iteration any information set of each round (leading
+    /// feature) was updated in, to check late training still reaches them
+    pub fn last_update_by_round(&self) -> BTreeMap<ActionIndex, u64> {
+        let mut rounds = BTreeMap::new();
+        for entry in self.last_updated.iter() {
+            if let Some(round) = History::from(*entry.key()).0.first() {
+                let latest = rounds.entry(*round).or_insert(0);
+                *latest = (*latest).max(*entry.value());
+            }
+        }
+        rounds
+    }
+
+    /// Counts a traversal through an information set
+    pub fn visit(&self, info_set: CondensedInfoSet) {
+        *self.visits.entry(info_set).or_insert(0) += 1;
+    }
+
+    pub fn visits(&self, info_set: &CondensedInfoSet) -> u32 {
+        self.visits.get(info_set).map_or(0, |visits| *visits)
+    }
+
+    /// Histogram of the visit counts and the share of the policy mass in
+    /// information sets visited at least `threshold` times
+    pub fn coverage(&self, threshold: u32) -> CoverageReport {
+        let mut report = CoverageReport {
+            threshold,
+            ..Default::default()
+        };
+        let (mut covered, mut total) = (0.0, 0.0);
+        for entry in self.visits.iter() {
+            let (info_set, visits) = (*entry.key(), *entry.value());
+            report.info_sets += 1;
+            add_to_histogram(&mut report.histogram, visits);
+            if let Some(round) = History::from(info_set).0.first() {
+                add_to_histogram(report.by_round.entry(*round).or_default(), visits);
+            }
+            let mass: f32 = self.policy_map.get(&info_set).map_or(0.0, |policy| policy.values().iter().sum());
+            total += mass;
+            if visits >= threshold {
+                covered += mass;
+            }
+        }
+        report.covered_mass = if total > 0.0 { covered / total } else { 0.0 };
+        report
+    }
+
+    /// The `limit` information sets of each round (leading feature) with the
+    /// most positive regret per visit, highest first. These are where the
+    /// strategy is least converged, so where more iterations or a finer
+    /// abstraction would help most
+    pub fn hot_spots(&self, limit: usize) -> BTreeMap<ActionIndex, Vec<HotSpot>> {
+        let mut rounds: BTreeMap<ActionIndex, Vec<HotSpot>> = BTreeMap::new();
+        for entry in self.regret_map.iter() {
+            let info_set = *entry.key();
+            let round = match History::from(info_set).0.first() {
+                Some(round) => *round,
+                None => continue,
+            };
+            let visits = self.visits(&info_set);
+            let positive: f32 = entry.value().values().iter().map(|regret| regret.max(0.0)).sum();
+            rounds.entry(round).or_default().push(HotSpot {
+                info_set,
+                visits,
+                regret: positive / visits.max(1) as f32,
+            });
+        }
+        for hot_spots in rounds.values_mut() {
+            hot_spots.sort_by(|a, b| b.regret.total_cmp(&a.regret));
+            hot_spots.truncate(limit);
+        }
+        rounds
+    }
+
+    /// Stops updating every information set visited fewer than `min_visits`
+    /// times so far, their policy stays as it is. Information sets first
+    /// visited later are not frozen. Returns how many were frozen
+    pub fn freeze(&self, min_visits: u32) -> usize {
+        let mut count = 0;
+        for entry in self.visits.iter() {
+            if *entry.value() < min_visits && self.frozen.insert(*entry.key()) {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    pub fn is_frozen(&self, info_set: &CondensedInfoSet) -> bool {
+        self.frozen.contains(info_set)
+    }
+
+    /// Adds what training changed in `shard` since it was copied from
+    /// `base`. Regrets, policies and visits only ever accumulate, so the
+    /// changes of any number of shards copied from the same base can be
+    /// added into one strategy in any order. Must not run while iterations
+    /// are updating any of them
+    pub fn merge_changes(&self, shard: &RegretStrategy, base: &RegretStrategy) {
+        merge_map(&self.policy_map, &shard.policy_map, &base.policy_map);
+        merge_map(&self.regret_map, &shard.regret_map, &base.regret_map);
+        for entry in shard.visits.iter() {
+            let added = entry.value().saturating_sub(base.visits(entry.key()));
+            *self.visits.entry(*entry.key()).or_insert(0) += added;
+        }
+        for info_set in shard.frozen.iter() {
+            self.frozen.insert(*info_set);
+        }
+        let ticks = shard.iteration().saturating_sub(base.iteration());
+        let start = self.iteration.fetch_add(ticks, Ordering::Relaxed);
+        // Iterations are numbered from the base in every shard, so an update
+        // in a shard lands after whatever was merged before it
+        for entry in shard.last_updated.iter() {
+            if base.last_updated(entry.key()) == Some(*entry.value()) {
+                continue;
+            }
+            let iteration = start + entry.value().saturating_sub(base.iteration());
+            let mut latest = self.last_updated.entry(*entry.key()).or_insert(0);
+            *latest = (*latest).max(iteration);
+        }
+        let updates = shard.dirty().saturating_sub(base.dirty());
+        self.dirty.fetch_add(updates, Ordering::Relaxed);
+    }
+
+    /// What training changed since the strategy was copied from `base`,
+    /// for adding into other copies of `base` with apply
+    pub fn changes_since(&self, base: &RegretStrategy) -> StrategyDelta {
+        let entries = self
+            .info_sets()
+            .into_iter()
+            .filter_map(|info_set| {
+                let regrets = change(&self.regret_map, &base.regret_map, &info_set);
+                let policy = change(&self.policy_map, &base.policy_map, &info_set);
+                let visits = self.visits(&info_set).saturating_sub(base.visits(&info_set));
+                let unchanged = |values: &Option<SparseValues>| values.as_ref().is_none_or(|v| v.is_empty());
+                let known = base.regret_map.contains_key(&info_set) || base.policy_map.contains_key(&info_set);
+                if known && visits == 0 && unchanged(&regrets) && unchanged(&policy) {
+                    return None;
+                }
+                self.delta_entry(info_set, regrets, policy, visits)
+            })
+            .collect();
+        StrategyDelta {
+            kind: DeltaKind::Add,
+            iterations: self.iteration().saturating_sub(base.iteration()),
+            entries,
+        }
+    }
+
+    /// The current values of every information set updated in `iteration`
+    /// or later, for saving only what changed since an earlier checkpoint
+    /// taken when the strategy was at `iteration`
+    pub fn changed_since(&self, iteration: u64) -> StrategyDelta {
+        let entries = self
+            .info_sets()
+            .into_iter()
+            .filter(|info_set| self.last_updated(info_set).is_some_and(|updated| updated >= iteration))
+            .filter_map(|info_set| {
+                let regrets = self.regret_map.get(&info_set).map(|regrets| sparse(&regrets.expand()));
+                let policy = self.policy_map.get(&info_set).map(|policy| sparse(&policy.expand()));
+                self.delta_entry(info_set, regrets, policy, self.visits(&info_set))
+            })
+            .collect();
+        StrategyDelta {
+            kind: DeltaKind::Replace,
+            iterations: self.iteration().saturating_sub(iteration),
+            entries,
+        }
+    }
+
+    /// Every information set with a policy or regrets, in order
+    fn info_sets(&self) -> Vec<CondensedInfoSet> {
+        let mut info_sets: Vec<CondensedInfoSet> = self.policy_map.iter().map(|entry| *entry.key()).collect();
+        info_sets.extend(self.regret_map.iter().map(|entry| *entry.key()));
+        info_sets.sort();
+        info_sets.dedup();
+        info_sets
+    }
+
+    fn delta_entry(
+        &self,
+        info_set: CondensedInfoSet,
+        regrets: Option<SparseValues>,
+        policy: Option<SparseValues>,
+        visits: u32,
+    ) -> Option<DeltaEntry> {
+        let (legal, length) = self
+            .regret_map
+            .get(&info_set)
+            .or_else(|| self.policy_map.get(&info_set))
+            .map(|distribution| distribution.legal_bits())?;
+        Some(DeltaEntry { info_set, legal, length, regrets, policy, visits })
+    }
+
+    /// Adds (or for a Replace delta, overwrites with) the values of `delta`,
+    /// made on another copy of the strategy. Frozen information sets take
+    /// them too, another copy may have trained them before they were
+    /// frozen here
+    pub fn apply(&self, delta: &StrategyDelta) {
+        let iteration = self.iteration.fetch_add(delta.iterations, Ordering::Relaxed) + delta.iterations;
+        let replace = delta.kind == DeltaKind::Replace;
+        let apply_to = |map: &DashMap<CondensedInfoSet, CompactDistribution>, entry: &DeltaEntry, values| {
+            let empty = || CompactDistribution::from_bits(entry.legal, entry.length);
+            let mut distribution = map.entry(entry.info_set).or_insert_with(empty);
+            if replace {
+                *distribution = empty();
+            }
+            distribution.add_sparse(values);
+        };
+        for entry in &delta.entries {
+            if let Some(regrets) = &entry.regrets {
+                apply_to(&self.regret_map, entry, regrets);
+            }
+            if let Some(policy) = &entry.policy {
+                apply_to(&self.policy_map, entry, policy);
+            }
+            let mut visits = self.visits.entry(entry.info_set).or_insert(0);
+            *visits = if replace { entry.visits } else { *visits + entry.visits };
+            self.last_updated.insert(entry.info_set, iteration);
+        }
+        self.dirty.fetch_add(delta.entries.len(), Ordering::Relaxed);
+    }
+
+    /// Forgets every information set visited fewer than `min_visits` times or
+    /// whose accumulated policy sums to less than `min_policy_mass`. These
+    /// are mostly uniform noise that would bloat the blueprint, and one that
+    /// turns out to matter is simply relearned from scratch. Information
+    /// sets without a visit count (loaded from somewhere that doesn't keep
+    /// them) are kept, nothing says they are noise.
+    /// Must not run while iterations are updating the strategy.
+    /// Returns the number of information sets dropped
+    pub fn sweep(&self, min_visits: u32, min_policy_mass: f32) -> usize {
+        let dropped: Vec<CondensedInfoSet> = self
+            .policy_map
+            .iter()
+            .map(|reference| *reference.key())
+            .chain(self.regret_map.iter().map(|reference| *reference.key()))
+            .filter(|info_set| {
+                let visits = match self.visits.get(info_set) {
+                    Some(visits) => *visits,
+                    None => return false,
+                };
+                let mass: f32 = self.policy_map.get(info_set).map_or(0.0, |policy| policy.values().iter().sum());
+                visits < min_visits || mass < min_policy_mass
+            })
+            .collect();
+        let mut count = 0;
+        for info_set in dropped {
+            let policy = self.policy_map.remove(&info_set);
+            let regrets = self.regret_map.remove(&info_set);
+            self.visits.remove(&info_set);
+            self.frozen.remove(&info_set);
+            self.last_updated.remove(&info_set);
+            if policy.is_some() || regrets.is_some() {
+                count += 1;
+            }
+        }
+        count
+    }
+}
+
+/// How much an info set's distribution in `map` grew over `base`
+fn change(
+    map: &DashMap<CondensedInfoSet, CompactDistribution>,
+    base: &DashMap<CondensedInfoSet, CompactDistribution>,
+    info_set: &CondensedInfoSet,
+) -> Option<SparseValues> {
+    let mut values = map.get(info_set)?.expand();
+    if let Some(before) = base.get(info_set) {
+        for (value, old) in values.iter_mut().zip(before.expand()) {
+            *value -= old;
+        }
+    }
+    Some(sparse(&values))
+}
+
+fn merge_map(
+    into: &DashMap<CondensedInfoSet, CompactDistribution>,
+    shard: &DashMap<CondensedInfoSet, CompactDistribution>,
+    base: &DashMap<CondensedInfoSet, CompactDistribution>,
+) {
+    for entry in shard.iter() {
+        let mut change = entry.value().expand();
+        if let Some(before) = base.get(entry.key()) {
+            for (value, old) in change.iter_mut().zip(before.expand()) {
+                *value -= old;
+            }
+        }
+        let mask = entry.value().mask();
+        into.entry(*entry.key())
+            .or_insert_with(|| CompactDistribution::zeroes(&mask))
+            .add(&change);
+    }
+}
+
+/// How thoroughly the visited information sets were trained, see
+/// RegretStrategy::coverage
+#[derive(Debug, Clone, Default)]
+pub struct CoverageReport {
+    /// Information sets by visit count, entry i counts those visited
+    /// between 2^i and 2^(i+1) - 1 times
+    pub histogram: Vec<usize>,
+    /// The same histogram for each round (the leading feature of the
+    /// information set)
+    pub by_round: BTreeMap<ActionIndex, Vec<usize>>,
+    pub threshold: u32,
+    /// Share of the accumulated policy mass in information sets visited at
+    /// least `threshold` times. Low means most of the strategy is noise
+    pub covered_mass: f32,
+    pub info_sets: usize,
+}
+
+fn add_to_histogram(histogram: &mut Vec<usize>, visits: u32) {
+    let bucket = (u32::BITS - visits.max(1).leading_zeros() - 1) as usize;
+    if histogram.len() <= bucket {
+        histogram.resize(bucket + 1, 0);
+    }
+    histogram[bucket] += 1;
+}
+
+fn format_histogram(histogram: &[usize]) -> String {
+    histogram
+        .iter()
+        .enumerate()
+        .map(|(bucket, count)| format!("{}+: {}", 1u64 << bucket, count))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl fmt::Display for CoverageReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} info sets visited", self.info_sets)?;
+        writeln!(f, "Visits: {}", format_histogram(&self.histogram))?;
+        for (round, histogram) in &self.by_round {
+            writeln!(f, "Round {} visits: {}", round, format_histogram(histogram))?;
+        }
+        write!(
+            f,
+            "{:.1}% of the policy mass is in info sets visited at least {} times",
+            100.0 * self.covered_mass,
+            self.threshold
+        )
+    }
+}
+
+/// An information set that is far from converged, see RegretStrategy::hot_spots
+#[derive(Debug, Clone, PartialEq)]
+pub struct HotSpot {
+    pub info_set: CondensedInfoSet,
+    pub visits: u32,
+    /// Positive regret accumulated per visit
+    pub regret: f32,
 }
 
 pub fn normalized(v: Vec<f32>) -> Vec<f32> {
@@ -117,3 +574,198 @@ pub fn normalized(v: Vec<f32>) -> Vec<f32> {
     }
     normalized
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hot_spots_are_ranked_by_regret_per_visit() {
+        let strategy = RegretStrategy::default();
+        let flop = |card| History(vec![3, card]).into_condensed();
+        for _ in 0..4 {
+            strategy.visit(flop(1));
+            strategy.update(flop(1), &[true, true], Some(&[2.0, -5.0]), None);
+        }
+        strategy.visit(flop(2));
+        strategy.update(flop(2), &[true, true], Some(&[3.0, 1.0]), None);
+        strategy.update(flop(3), &[true, true], Some(&[-1.0, 0.0]), None);
+        strategy.update(History(vec![0, 1]).into_condensed(), &[true], Some(&[1.0]), None);
+
+        let hot_spots = strategy.hot_spots(2);
+        assert_eq!(hot_spots.len(), 2);
+        let flop_spots = &hot_spots[&3];
+        assert_eq!(flop_spots.len(), 2);
+        assert_eq!(flop_spots[0], HotSpot { info_set: flop(2), visits: 1, regret: 4.0 });
+        assert_eq!(flop_spots[1], HotSpot { info_set: flop(1), visits: 4, regret: 2.0 });
+    }
+
+    #[test]
+    fn test_shard_changes_add_up() {
+        let preflop = History(vec![0, 7]).into_condensed();
+        let flop = History(vec![3, 7]).into_condensed();
+        let base = RegretStrategy::default();
+        base.track_updates();
+        base.visit(preflop);
+        base.update(preflop, &[true, false, true], Some(&[1.0, 0.0, 2.0]), Some(&[1.0, 0.0, 0.0]));
+        base.tick();
+
+        let (left, right) = (base.clone(), base.clone());
+        left.visit(preflop);
+        left.update(preflop, &[true, false, true], Some(&[1.0, 0.0, -1.0]), Some(&[0.0, 0.0, 1.0]));
+        left.tick();
+        right.visit(flop);
+        right.update(flop, &[true, true], Some(&[3.0, 4.0]), None);
+        right.tick();
+        right.tick();
+
+        let merged = base.clone();
+        merged.merge_changes(&left, &base);
+        merged.merge_changes(&right, &base);
+        assert_eq!(merged.regrets(&preflop), Some(vec![2.0, 0.0, 1.0]));
+        assert_eq!(merged.policy(&preflop), Some(vec![1.0, 0.0, 1.0]));
+        assert_eq!(merged.regrets(&flop), Some(vec![3.0, 4.0]));
+        assert_eq!(merged.visits(&preflop), 2);
+        assert_eq!(merged.visits(&flop), 1);
+        assert_eq!(merged.iteration(), 4);
+        assert_eq!(merged.last_updated(&preflop), Some(1));
+        assert_eq!(merged.last_updated(&flop), Some(2));
+        assert_eq!(merged.dirty(), 3);
+    }
+
+    #[test]
+    fn test_deltas_carry_changes_to_other_copies() {
+        let preflop = History(vec![0, 7]).into_condensed();
+        let flop = History(vec![3, 7]).into_condensed();
+        let base = RegretStrategy::default();
+        base.update(preflop, &[true, true], Some(&[1.0, 2.0]), Some(&[1.0, 0.0]));
+        base.update(flop, &[true, true], Some(&[1.0, 1.0]), None);
+
+        let trained = base.clone();
+        trained.visit(flop);
+        trained.update(flop, &[true, true], Some(&[2.0, -1.0]), None);
+        trained.update(History(vec![4, 7]).into_condensed(), &[false, true], None, Some(&[0.0, 1.0]));
+        trained.tick();
+        let delta = trained.changes_since(&base);
+        assert_eq!(delta.iterations, 1);
+        assert_eq!(delta.entries.len(), 2);
+
+        let copy = base.clone();
+        copy.apply(&delta);
+        for info_set in [preflop, flop, History(vec![4, 7]).into_condensed()] {
+            assert_eq!(copy.regrets(&info_set), trained.regrets(&info_set));
+            assert_eq!(copy.policy(&info_set), trained.policy(&info_set));
+            assert_eq!(copy.visits(&info_set), trained.visits(&info_set));
+        }
+        assert_eq!(copy.iteration(), 1);
+        assert_eq!(copy.last_updated(&flop), Some(1));
+    }
+
+    #[test]
+    fn test_checkpoint_deltas_restore_the_strategy() {
+        let preflop = History(vec![0, 7]).into_condensed();
+        let flop = History(vec![3, 7]).into_condensed();
+        let strategy = RegretStrategy::default();
+        strategy.track_updates();
+        strategy.visit(preflop);
+        strategy.update(preflop, &[true, true, true], Some(&[1.0, 0.0, 2.0]), Some(&[1.0, 0.0, 0.0]));
+        strategy.tick();
+        let full = strategy.changed_since(0);
+        assert_eq!(full.entries[0].regrets, Some(vec![(0, 1.0), (2, 2.0)]));
+
+        strategy.update(flop, &[true, true], Some(&[3.0, 0.0]), None);
+        strategy.visit(flop);
+        strategy.tick();
+        let changed = strategy.changed_since(1);
+        assert_eq!(changed.entries.len(), 1);
+        assert_eq!(changed.entries[0].info_set, flop);
+
+        let restored = RegretStrategy::default();
+        restored.apply(&full);
+        restored.apply(&changed);
+        // Applying a checkpoint twice changes nothing
+        restored.apply(&StrategyDelta { iterations: 0, ..changed });
+        for info_set in [preflop, flop] {
+            assert_eq!(restored.regrets(&info_set), strategy.regrets(&info_set));
+            assert_eq!(restored.policy(&info_set), strategy.policy(&info_set));
+            assert_eq!(restored.visits(&info_set), strategy.visits(&info_set));
+        }
+        assert_eq!(restored.iteration(), 2);
+    }
+
+    #[test]
+    fn test_last_updates_are_tracked_by_iteration() {
+        let strategy = RegretStrategy::default();
+        let preflop = History(vec![0, 7]).into_condensed();
+        let river = History(vec![5, 7]).into_condensed();
+        // Nothing is tracked until asked for
+        strategy.update(river, &[true], Some(&[1.0]), None);
+        assert_eq!((strategy.last_updated(&river), strategy.dirty()), (None, 0));
+        strategy.track_updates();
+        strategy.update(preflop, &[true], Some(&[1.0]), None);
+        strategy.tick();
+        strategy.tick();
+        strategy.update(river, &[true], Some(&[1.0]), None);
+        strategy.tick();
+        assert_eq!(strategy.iteration(), 3);
+        assert_eq!(strategy.last_updated(&preflop), Some(0));
+        assert_eq!(strategy.age(&river), Some(1));
+        assert_eq!(strategy.age(&12), None);
+        assert_eq!(strategy.last_update_by_round(), BTreeMap::from([(0, 0), (5, 2)]));
+    }
+
+    #[test]
+    fn test_frozen_info_sets_stop_changing() {
+        let strategy = RegretStrategy::default();
+        strategy.track_updates();
+        strategy.visit(1);
+        for _ in 0..3 {
+            strategy.visit(2);
+        }
+        assert_eq!(strategy.freeze(2), 1);
+        assert!(strategy.is_frozen(&1));
+        strategy.update(1, &[true, true], Some(&[1.0, 0.0]), Some(&[0.5, 0.5]));
+        strategy.update(2, &[true, true], Some(&[1.0, 0.0]), Some(&[0.5, 0.5]));
+        assert_eq!(strategy.policy(&1).unwrap().iter().sum::<f32>(), 0.0);
+        assert!(strategy.policy(&2).unwrap().iter().sum::<f32>() > 0.0);
+        assert_eq!(strategy.dirty(), 1);
+    }
+
+    #[test]
+    fn test_sweep_drops_rare_and_empty_info_sets() {
+        let strategy = RegretStrategy::default();
+        strategy.track_updates();
+        for _ in 0..5 {
+            strategy.visit(1);
+            strategy.update(1, &[true, true], Some(&[1.0, -1.0]), Some(&[0.5, 0.5]));
+        }
+        strategy.visit(2);
+        strategy.update(2, &[true, true], Some(&[1.0, -1.0]), Some(&[0.5, 0.5]));
+        for _ in 0..5 {
+            strategy.visit(3);
+            strategy.update(3, &[true, true], Some(&[1.0, -1.0]), Some(&[0.0, 0.0]));
+        }
+        assert_eq!(strategy.visits(&1), 5);
+        assert_eq!(strategy.dirty(), 11);
+
+        let coverage = strategy.coverage(5);
+        assert_eq!(coverage.info_sets, 3);
+        assert_eq!(coverage.histogram, vec![1, 0, 2]);
+        // Info set 3 never put mass on its policy
+        assert_eq!(coverage.covered_mass, 5.0 / 6.0);
+
+        let river = History(vec![5, 14, 0]).into_condensed();
+        strategy.visit(river);
+        assert_eq!(strategy.coverage(5).by_round[&5], vec![1]);
+
+        // Updated without ever being counted, as after loading a table
+        strategy.update(4, &[true, true], Some(&[1.0, -1.0]), Some(&[0.0, 0.0]));
+
+        assert_eq!(strategy.sweep(2, 0.1), 2);
+        assert_eq!(strategy.size(), 2);
+        assert!(strategy.policy(&1).is_some());
+        assert!(strategy.policy(&4).is_some());
+        assert!(strategy.regrets(&2).is_none());
+        assert_eq!(strategy.visits(&3), 0);
+    }
+}
@@ -0,0 +1,179 @@
+/// Summaries of a blueprint that a person can read before trusting it at
+/// the table.
+///
+/// The blueprint is a table of hundreds of thousands of infosets, none of
+/// which says much on its own. A report groups one player's infosets of a
+/// round by a few features of the schema (say ev_if_won and pot in the
+/// auction) into buckets of `width` values, and gives for each bucket how
+/// many infosets fell into it, how often they raise and the chips they bid
+/// on average. A bucket that bids more with a worse EV, or raises every
+/// hand with nothing, stands out at a glance:
+///
+///     gtcogs report auction_poker.bp auction ev_if_won,pot 5
+use crate::game_logic::action::{Action, ActionIndex};
+use crate::game_logic::schema::FeatureSchema;
+use crate::game_logic::strategy::blueprint::*;
+use crate::game_logic::strategy::PolicyDistribution;
+use crate::game_logic::visibility::{History, Round};
+use crate::implementations::auction::*;
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BucketStats {
+    pub infosets: usize,
+    /// Mean over the infosets of how often they raise, whatever the size
+    pub raise_frequency: f32,
+    /// Mean expected bid in chips, over the infosets that bid at all
+    pub average_bid: Option<f32>,
+    bidding: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct StrategyReport {
+    pub round: Round,
+    /// Names of the features the infosets are grouped by
+    pub features: Vec<&'static str>,
+    pub width: u8,
+    /// Keyed by the lowest value of each feature in the bucket
+    pub buckets: BTreeMap<Vec<u8>, BucketStats>,
+}
+
+/// The expected bid of `policy` in chips, None if it never bids
+pub fn expected_bid(policy: &PolicyDistribution) -> Option<f32> {
+    let bids: Vec<(u32, f32)> = policy
+        .iter()
+        .enumerate()
+        .take(AuctionPokerAction::max_index() as usize)
+        .filter_map(|(index, &probability)| match AuctionPokerAction::from(index as ActionIndex) {
+            AuctionPokerAction::Bid(RelativeSize::Amount(chips)) if probability > 0.0 => Some((chips, probability)),
+            _ => None,
+        })
+        .collect();
+    let total: f32 = bids.iter().map(|(_, probability)| probability).sum();
+    if total <= 0.0 {
+        return None;
+    }
+    Some(bids.iter().map(|(chips, probability)| *chips as f32 * probability).sum::<f32>() / total)
+}
+
+/// Groups the infosets of `player_num` in `round` by the features named in
+/// `group_by`, `width` values of each to a bucket
+pub fn report(
+    blueprint: &BlueprintStrategy,
+    player_num: usize,
+    round: Round,
+    group_by: &[&str],
+    width: u8,
+) -> Result<StrategyReport, String> {
+    let schema = FeatureSchema::for_round(round.clone());
+    let slots = group_by
+        .iter()
+        .map(|name| {
+            schema
+                .features
+                .iter()
+                .position(|spec| spec.name == *name)
+                .ok_or_else(|| format!("{:?} has no feature '{}'", round, name))
+        })
+        .collect::<Result<Vec<usize>, String>>()?;
+    let width = width.max(1);
+
+    let mut sums: BTreeMap<Vec<u8>, (BucketStats, f32, f32)> = BTreeMap::new();
+    for (&info_set, condensed) in blueprint.round_policies(round.clone(), player_num) {
+        let history: History = info_set.into();
+        let key: Vec<u8> = slots.iter().map(|&slot| history.0[slot] / width * width).collect();
+        let policy = decompress_policy(condensed);
+        let (stats, raising, bid) = sums.entry(key).or_default();
+        stats.infosets += 1;
+        *raising += raise_frequency(condensed);
+        if let Some(chips) = expected_bid(&policy) {
+            stats.bidding += 1;
+            *bid += chips;
+        }
+    }
+
+    let buckets = sums
+        .into_iter()
+        .map(|(key, (stats, raising, bid))| {
+            let average_bid = match stats.bidding {
+                0 => None,
+                bidding => Some(bid / bidding as f32),
+            };
+            let stats = BucketStats {
+                raise_frequency: raising / stats.infosets as f32,
+                average_bid,
+                ..stats
+            };
+            (key, stats)
+        })
+        .collect();
+    Ok(StrategyReport {
+        round,
+        features: slots.iter().map(|&slot| schema.features[slot].name).collect(),
+        width,
+        buckets,
+    })
+}
+
+impl fmt::Display for StrategyReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:?}, buckets of {}", self.round, self.width)?;
+        for name in &self.features {
+            write!(f, "{:>16}", name)?;
+        }
+        writeln!(f, "{:>10}{:>8}{:>10}", "infosets", "raise", "bid")?;
+        for (key, stats) in &self.buckets {
+            for value in key {
+                let bucket = match self.width {
+                    1 => value.to_string(),
+                    width => format!("{}-{}", value, value.saturating_add(width - 1)),
+                };
+                write!(f, "{:>16}", bucket)?;
+            }
+            let bid = match stats.average_bid {
+                Some(bid) => format!("{:.1}", bid),
+                None => "-".to_string(),
+            };
+            writeln!(f, "{:>10}{:>8.3}{:>10}", stats.infosets, stats.raise_frequency, bid)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index_of(action: AuctionPokerAction) -> usize {
+        action.index() as usize
+    }
+
+    #[test]
+    fn test_report_buckets_bids_and_raises() {
+        let bid = |chips: u32| {
+            let mut policy = vec![0.0; index_of(AuctionPokerAction::Bid(RelativeSize::Amount(chips))) + 1];
+            policy[index_of(AuctionPokerAction::Bid(RelativeSize::Amount(chips)))] = 1.0;
+            policy
+        };
+        let mut policies = BTreeMap::new();
+        // round, ev_if_lost, ev_if_won, win_value, pot
+        policies.insert(History(vec![1, 10, 20, 10, 4]).into_condensed(), bid(0));
+        policies.insert(History(vec![1, 10, 21, 10, 4]).into_condensed(), bid(50));
+        policies.insert(History(vec![1, 10, 27, 10, 4]).into_condensed(), bid(400));
+        let blueprint = BlueprintStrategy::from_policies(vec![policies, BTreeMap::new()]);
+
+        let report = report(&blueprint, 0, Round::Auction, &["ev_if_won"], 5).unwrap();
+        assert_eq!(report.buckets.len(), 2);
+        let low = report.buckets[&vec![20]];
+        assert_eq!(low.infosets, 2);
+        assert_eq!(low.raise_frequency, 0.0);
+        let low_bid = low.average_bid.unwrap();
+        let high_bid = report.buckets[&vec![25]].average_bid.unwrap();
+        assert!(low_bid < high_bid);
+        assert!(report.to_string().contains("20-24"));
+
+        assert!(super::report(&blueprint, 0, Round::Auction, &["stack"], 5).is_err());
+        assert!(super::report(&blueprint, 1, Round::Auction, &["pot"], 1).unwrap().buckets.is_empty());
+    }
+}
@@ -0,0 +1,805 @@
+/// Where strategies live on disk. Every format stores the same thing, the
+/// abstraction hash and one table of (info set, compressed policy) pairs
+/// per player, so the blueprint and the trainers only deal with a
+/// StrategyStore and the format is picked from the file extension:
+///
+///     .json   Human readable and what older tools expect. A single player
+///             is saved as {"abstraction", "information"} (the format the
+///             trainers have always written), several players as
+///             {"abstraction", "players"}. Bare lists from before
+///             abstraction hashes are still read
+///     .mmap   Fixed size little endian records sorted by info set, which
+///             MappedPolicies reads in place without loading the file
+///             (on unix, elsewhere the file is read whole)
+///     other   bincode, the compact default (.bp). Headerless files from
+///             before abstraction hashes are still read
+///
+/// Both binary formats can also be read and written one entry at a time
+/// (open_entries, write_entries), which is how convert moves strategies
+/// too big to hold in memory twice
+use crate::game_logic::strategy::blueprint::*;
+use crate::game_logic::strategy::{CondensedInfoSet, PolicyDistribution};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+
+/// One policy of a saved strategy: (player, info set, policy)
+pub type Entry = (usize, CondensedInfoSet, CondensedPolicyDistribution);
+pub type Entries = Box<dyn Iterator<Item = Result<Entry, String>>>;
+
+/// A saved strategy opened to be read one entry at a time
+pub struct EntryReader {
+    pub abstraction: Option<u64>,
+    /// Number of entries of every player
+    pub counts: Vec<usize>,
+    /// Every entry of player 0, then of player 1 and so on
+    pub entries: Entries,
+}
+
+impl EntryReader {
+    fn from_policies(abstraction: Option<u64>, policies: SavedPolicies) -> EntryReader {
+        EntryReader {
+            abstraction,
+            counts: policies.iter().map(|player| player.len()).collect(),
+            entries: Box::new(policies.into_iter().enumerate().flat_map(|(player_num, player)| {
+                player.into_iter().map(move |(info_set, policy)| Ok((player_num, info_set, policy)))
+            })),
+        }
+    }
+}
+
+pub trait StrategyStore {
+    fn write(&self, file_name: &str, abstraction: u64, policies: &SavedPolicies) -> Result<(), String>;
+    /// The abstraction hash is None for files saved before it was recorded
+    fn read(&self, file_name: &str) -> Result<(Option<u64>, SavedPolicies), String>;
+
+    /// Opens `file_name` to read its entries in order. By default the whole
+    /// file is read first, the binary formats stream it
+    fn open_entries(&self, file_name: &str) -> Result<EntryReader, String> {
+        let (abstraction, policies) = self.read(file_name)?;
+        Ok(EntryReader::from_policies(abstraction, policies))
+    }
+
+    /// Writes `entries`, which are `counts[player]` entries of every player
+    /// in order. By default they are collected and written whole, the binary
+    /// formats hold at most one player in memory
+    fn write_entries(
+        &self,
+        file_name: &str,
+        abstraction: u64,
+        counts: &[usize],
+        entries: &mut dyn Iterator<Item = Result<Entry, String>>,
+    ) -> Result<(), String> {
+        let mut policies: SavedPolicies = counts.iter().map(|count| Vec::with_capacity(*count)).collect();
+        for entry in entries {
+            let (player_num, info_set, policy) = entry?;
+            policies
+                .get_mut(player_num)
+                .ok_or_else(|| format!("Entry of player {} but {} has {} players", player_num, file_name, counts.len()))?
+                .push((info_set, policy));
+        }
+        self.write(file_name, abstraction, &policies)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StoreFormat {
+    Json,
+    Bincode,
+    Mmap,
+}
+
+impl StoreFormat {
+    pub fn from_extension(file_name: &str) -> StoreFormat {
+        match std::path::Path::new(file_name).extension().and_then(|extension| extension.to_str()) {
+            Some("json") => StoreFormat::Json,
+            Some("mmap") => StoreFormat::Mmap,
+            _ => StoreFormat::Bincode,
+        }
+    }
+
+    pub fn store(&self) -> Box<dyn StrategyStore> {
+        match self {
+            StoreFormat::Json => Box::new(JsonStore),
+            StoreFormat::Bincode => Box::new(BincodeStore),
+            StoreFormat::Mmap => Box::new(MmapStore),
+        }
+    }
+}
+
+pub fn store_for(file_name: &str) -> Box<dyn StrategyStore> {
+    StoreFormat::from_extension(file_name).store()
+}
+
+fn create(file_name: &str) -> Result<BufWriter<File>, String> {
+    let file = File::create(file_name).map_err(|error| format!("Could not create {}: {}", file_name, error))?;
+    Ok(BufWriter::new(file))
+}
+
+fn open(file_name: &str) -> Result<BufReader<File>, String> {
+    let file = File::open(file_name).map_err(|error| format!("Could not open {}: {}", file_name, error))?;
+    Ok(BufReader::new(file))
+}
+
+pub struct JsonStore;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonFormat {
+    Players {
+        abstraction: Option<u64>,
+        players: Vec<Vec<(CondensedInfoSet, PolicyDistribution)>>,
+    },
+    Single {
+        abstraction: Option<u64>,
+        information: Vec<(CondensedInfoSet, PolicyDistribution)>,
+    },
+    Legacy(Vec<(CondensedInfoSet, PolicyDistribution)>),
+}
+
+/// Applies `f` to roughly equal chunks of `items` on every core, results in order.
+/// Saving millions of info sets on a single thread takes minutes
+pub fn parallel_chunks<T: Sync, U: Send>(items: &[T], f: impl Fn(&[T]) -> U + Sync) -> Vec<U> {
+    let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    let chunk_size = items.len().div_ceil(threads);
+    if chunk_size == 0 {
+        return vec![];
+    }
+    let f = &f;
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = items.chunks(chunk_size).map(|chunk| scope.spawn(move || f(chunk))).collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+/// One player's table as a JSON list, stringified in parallel. Trailing
+/// zeroes of the policies are left out
+fn json_table<P: Sync>(player: &[(CondensedInfoSet, P)], expand: impl Fn(&P) -> PolicyDistribution + Sync) -> String {
+    let chunks = parallel_chunks(player, |chunk| {
+        let entries: Vec<String> = chunk
+            .iter()
+            .map(|(info_set, policy)| {
+                let mut policy = expand(policy);
+                while policy.last() == Some(&0.0) {
+                    policy.pop();
+                }
+                serde_json::to_string(&(info_set, policy)).unwrap()
+            })
+            .collect();
+        entries.join(",")
+    });
+    format!("[{}]", chunks.join(","))
+}
+
+fn write_json(file_name: &str, abstraction: u64, tables: Vec<String>) -> Result<(), String> {
+    let saved = match tables.len() {
+        1 => format!(r#"{{"abstraction":{},"information":{}}}"#, abstraction, tables[0]),
+        _ => format!(r#"{{"abstraction":{},"players":[{}]}}"#, abstraction, tables.join(",")),
+    };
+    let mut writer = create(file_name)?;
+    writer.write_all(saved.as_bytes()).map_err(|error| format!("Could not write {}: {}", file_name, error))?;
+    writer.flush().map_err(|error| format!("Could not write {}: {}", file_name, error))
+}
+
+/// Writes one player's policies as they are, in the single player format
+/// JsonStore reads. The trainers save their tables this way so that
+/// nothing is rounded off before convert compresses them into a blueprint
+pub fn write_full_table(file_name: &str, abstraction: u64, table: &[(CondensedInfoSet, PolicyDistribution)]) -> Result<(), String> {
+    write_json(file_name, abstraction, vec![json_table(table, |policy| policy.clone())])
+}
+
+impl StrategyStore for JsonStore {
+    fn write(&self, file_name: &str, abstraction: u64, policies: &SavedPolicies) -> Result<(), String> {
+        let tables = policies.iter().map(|player| json_table(player, decompress_policy)).collect();
+        write_json(file_name, abstraction, tables)
+    }
+
+    fn read(&self, file_name: &str) -> Result<(Option<u64>, SavedPolicies), String> {
+        let saved: JsonFormat =
+            serde_json::from_reader(open(file_name)?).map_err(|error| format!("{} is not a saved strategy: {}", file_name, error))?;
+        let (abstraction, players) = match saved {
+            JsonFormat::Players { abstraction, players } => (abstraction, players),
+            JsonFormat::Single { abstraction, information } => (abstraction, vec![information]),
+            JsonFormat::Legacy(information) => (None, vec![information]),
+        };
+        let mut policies = vec![];
+        for player in players {
+            let mut table = vec![];
+            for (info_set, policy) in player {
+                if policy.len() > COMPRESSED_POLICY_LENGTH {
+                    return Err(format!("{} has a policy of {} actions for {}", file_name, policy.len(), info_set));
+                }
+                table.push((info_set, compress_policy(&policy)));
+            }
+            policies.push(table);
+        }
+        Ok((abstraction, policies))
+    }
+}
+
+pub struct BincodeStore;
+
+impl StrategyStore for BincodeStore {
+    fn write(&self, file_name: &str, abstraction: u64, policies: &SavedPolicies) -> Result<(), String> {
+        let reader = EntryReader::from_policies(Some(abstraction), policies.clone());
+        let mut entries = reader.entries;
+        self.write_entries(file_name, abstraction, &reader.counts, &mut entries)
+    }
+
+    fn read(&self, file_name: &str) -> Result<(Option<u64>, SavedPolicies), String> {
+        let reader = self.open_entries(file_name)?;
+        let mut policies: SavedPolicies = reader.counts.iter().map(|count| Vec::with_capacity(*count)).collect();
+        for entry in reader.entries {
+            let (player_num, info_set, policy) = entry?;
+            policies[player_num].push((info_set, policy));
+        }
+        Ok((reader.abstraction, policies))
+    }
+
+    /// Blueprints saved before abstraction hashes are bare policies, they
+    /// are told apart by which of the two layouts adds up to the file
+    fn open_entries(&self, file_name: &str) -> Result<EntryReader, String> {
+        let mut file = File::open(file_name).map_err(|error| format!("Could not open {}: {}", file_name, error))?;
+        let length = file.metadata().map_err(|error| format!("Could not read {}: {}", file_name, error))?.len();
+        let (abstraction, counts, start) = match bincode_layout(&mut file, length, true) {
+            Some(layout) => layout,
+            None => match bincode_layout(&mut file, length, false) {
+                Some(layout) => {
+                    println!("[WARNING] {} is in the format from before abstraction hashes, resave it", file_name);
+                    layout
+                }
+                None => return Err(format!("{} is not a saved strategy", file_name)),
+            },
+        };
+        file.seek(SeekFrom::Start(start)).map_err(|error| format!("Could not read {}: {}", file_name, error))?;
+        Ok(EntryReader {
+            abstraction,
+            counts: counts.clone(),
+            entries: Box::new(RecordReader::new(file_name, BufReader::new(file), counts, 8)),
+        })
+    }
+
+    fn write_entries(
+        &self,
+        file_name: &str,
+        abstraction: u64,
+        counts: &[usize],
+        entries: &mut dyn Iterator<Item = Result<Entry, String>>,
+    ) -> Result<(), String> {
+        let mut writer = create(file_name)?;
+        let mut write = |bytes: &[u8]| writer.write_all(bytes).map_err(|error| format!("Could not write {}: {}", file_name, error));
+        // What bincode makes of (u64, Vec<Vec<(u64, [u128; N])>>)
+        write(&abstraction.to_le_bytes())?;
+        write(&(counts.len() as u64).to_le_bytes())?;
+        let mut entries = PlayerEntries::new(file_name, counts, entries);
+        for (player_num, count) in counts.iter().enumerate() {
+            write(&(*count as u64).to_le_bytes())?;
+            for _ in 0..*count {
+                let (info_set, policy) = entries.next(player_num)?;
+                write(&record(info_set, &policy))?;
+            }
+        }
+        entries.finish()?;
+        writer.flush().map_err(|error| format!("Could not write {}: {}", file_name, error))
+    }
+}
+
+fn read_u64_at(file: &mut File, offset: u64) -> Option<u64> {
+    let mut bytes = [0; 8];
+    file.seek(SeekFrom::Start(offset)).ok()?;
+    file.read_exact(&mut bytes).ok()?;
+    Some(u64::from_le_bytes(bytes))
+}
+
+/// The abstraction hash, number of entries of every player and offset of
+/// the first entry of a bincode file `with_hash` or without, if the
+/// lengths in it add up to exactly `length` bytes
+fn bincode_layout(file: &mut File, length: u64, with_hash: bool) -> Option<(Option<u64>, Vec<usize>, u64)> {
+    let mut offset = 0;
+    let abstraction = match with_hash {
+        true => {
+            offset = 8;
+            Some(read_u64_at(file, 0)?)
+        }
+        false => None,
+    };
+    let num_players = read_u64_at(file, offset)?;
+    offset += 8;
+    // Past the length of the first player
+    let start = offset + 8;
+    let mut counts = vec![];
+    for _ in 0..num_players {
+        let count = read_u64_at(file, offset)?;
+        offset = count.checked_mul(RECORD_LENGTH as u64)?.checked_add(offset + 8).filter(|end| *end <= length)?;
+        counts.push(count as usize);
+    }
+    (offset == length).then_some((abstraction, counts, start))
+}
+
+/// The bytes of an entry in both binary formats, the info set followed by
+/// the policy, little endian
+fn record(info_set: CondensedInfoSet, policy: &CondensedPolicyDistribution) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(RECORD_LENGTH);
+    bytes.extend_from_slice(&info_set.to_le_bytes());
+    for value in policy.iter() {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes
+}
+
+fn from_record(bytes: &[u8]) -> (CondensedInfoSet, CondensedPolicyDistribution) {
+    let mut policy = CondensedPolicyDistribution::default();
+    for (value, chunk) in policy.iter_mut().zip(bytes[8..RECORD_LENGTH].chunks_exact(16)) {
+        *value = u128::from_le_bytes(chunk.try_into().unwrap());
+    }
+    (read_u64(bytes, 0), policy)
+}
+
+/// Streams the records of a binary file, skipping `gap` bytes (bincode's
+/// length of the next player) between players
+struct RecordReader<R: Read> {
+    file_name: String,
+    reader: R,
+    counts: Vec<usize>,
+    gap: usize,
+    player_num: usize,
+    read: usize,
+}
+
+impl<R: Read> RecordReader<R> {
+    fn new(file_name: &str, reader: R, counts: Vec<usize>, gap: usize) -> Self {
+        RecordReader {
+            file_name: file_name.to_owned(),
+            reader,
+            counts,
+            gap,
+            player_num: 0,
+            read: 0,
+        }
+    }
+
+    fn read_exact(&mut self, bytes: &mut [u8]) -> Result<(), String> {
+        self.reader.read_exact(bytes).map_err(|error| format!("Could not read {}: {}", self.file_name, error))
+    }
+}
+
+impl<R: Read> Iterator for RecordReader<R> {
+    type Item = Result<Entry, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.player_num == self.counts.len() {
+                return None;
+            }
+            if self.read < self.counts[self.player_num] {
+                break;
+            }
+            self.player_num += 1;
+            self.read = 0;
+            if self.player_num < self.counts.len() {
+                let mut gap = vec![0; self.gap];
+                if let Err(error) = self.read_exact(&mut gap) {
+                    self.player_num = self.counts.len();
+                    return Some(Err(error));
+                }
+            }
+        }
+        let mut bytes = [0; RECORD_LENGTH];
+        if let Err(error) = self.read_exact(&mut bytes) {
+            self.player_num = self.counts.len();
+            return Some(Err(error));
+        }
+        self.read += 1;
+        let (info_set, policy) = from_record(&bytes);
+        Some(Ok((self.player_num, info_set, policy)))
+    }
+}
+
+/// Entries handed to a streaming writer, checked to come player by player
+/// in the numbers promised
+struct PlayerEntries<'a> {
+    file_name: &'a str,
+    counts: &'a [usize],
+    entries: &'a mut dyn Iterator<Item = Result<Entry, String>>,
+}
+
+impl<'a> PlayerEntries<'a> {
+    fn new(file_name: &'a str, counts: &'a [usize], entries: &'a mut dyn Iterator<Item = Result<Entry, String>>) -> Self {
+        PlayerEntries { file_name, counts, entries }
+    }
+
+    fn next(&mut self, player_num: usize) -> Result<(CondensedInfoSet, CondensedPolicyDistribution), String> {
+        match self.entries.next() {
+            Some(Ok((found, info_set, policy))) if found == player_num => Ok((info_set, policy)),
+            Some(Ok((found, _, _))) => Err(format!(
+                "Expected {} entries of player {} for {}, got one of player {}",
+                self.counts[player_num], player_num, self.file_name, found
+            )),
+            Some(Err(error)) => Err(error),
+            None => Err(format!("Ran out of entries of player {} for {}", player_num, self.file_name)),
+        }
+    }
+
+    fn finish(self) -> Result<(), String> {
+        match self.entries.next() {
+            None => Ok(()),
+            Some(Err(error)) => Err(error),
+            Some(Ok(_)) => Err(format!("More entries than {:?} for {}", self.counts, self.file_name)),
+        }
+    }
+}
+
+const MMAP_MAGIC: &[u8; 8] = b"GTCOGSMM";
+/// Magic, abstraction hash and number of players, followed by the number of
+/// records of every player
+const MMAP_HEADER_LENGTH: usize = 24;
+const POLICY_BYTES: usize = std::mem::size_of::<CondensedPolicyDistribution>();
+const RECORD_LENGTH: usize = 8 + POLICY_BYTES;
+
+pub struct MmapStore;
+
+impl StrategyStore for MmapStore {
+    fn write(&self, file_name: &str, abstraction: u64, policies: &SavedPolicies) -> Result<(), String> {
+        let reader = EntryReader::from_policies(Some(abstraction), policies.clone());
+        let mut entries = reader.entries;
+        self.write_entries(file_name, abstraction, &reader.counts, &mut entries)
+    }
+
+    fn read(&self, file_name: &str) -> Result<(Option<u64>, SavedPolicies), String> {
+        let mapped = MappedPolicies::open(file_name)?;
+        let policies = (0..mapped.num_players()).map(|player_num| mapped.iter(player_num).collect()).collect();
+        Ok((Some(mapped.abstraction()), policies))
+    }
+
+    fn open_entries(&self, file_name: &str) -> Result<EntryReader, String> {
+        let mapped = MappedPolicies::open(file_name)?;
+        let counts = (0..mapped.num_players()).map(|player_num| mapped.len(player_num)).collect();
+        Ok(EntryReader {
+            abstraction: Some(mapped.abstraction()),
+            counts,
+            entries: Box::new(MappedEntries {
+                mapped,
+                player_num: 0,
+                index: 0,
+            }),
+        })
+    }
+
+    /// Records are sorted by info set, so one player is held at a time
+    fn write_entries(
+        &self,
+        file_name: &str,
+        abstraction: u64,
+        counts: &[usize],
+        entries: &mut dyn Iterator<Item = Result<Entry, String>>,
+    ) -> Result<(), String> {
+        let mut header = Vec::with_capacity(MMAP_HEADER_LENGTH + 8 * counts.len());
+        header.extend_from_slice(MMAP_MAGIC);
+        header.extend_from_slice(&abstraction.to_le_bytes());
+        header.extend_from_slice(&(counts.len() as u64).to_le_bytes());
+        for count in counts {
+            header.extend_from_slice(&(*count as u64).to_le_bytes());
+        }
+
+        let mut writer = create(file_name)?;
+        let mut write = |bytes: &[u8]| writer.write_all(bytes).map_err(|error| format!("Could not write {}: {}", file_name, error));
+        write(&header)?;
+        let mut entries = PlayerEntries::new(file_name, counts, entries);
+        for (player_num, count) in counts.iter().enumerate() {
+            let mut player = (0..*count).map(|_| entries.next(player_num)).collect::<Result<Vec<_>, String>>()?;
+            player.sort_by_key(|(info_set, _)| *info_set);
+            for (info_set, policy) in player {
+                write(&record(info_set, &policy))?;
+            }
+        }
+        entries.finish()?;
+        writer.flush().map_err(|error| format!("Could not write {}: {}", file_name, error))
+    }
+}
+
+/// The bytes of a file saved by MmapStore: mapped where there is mmap,
+/// read whole where there isn't
+enum Mapping {
+    #[cfg(unix)]
+    Mapped { data: *const u8, length: usize },
+    #[cfg(not(unix))]
+    Read(Vec<u8>),
+}
+
+// The mapping is read only and lives until the Mapping is dropped
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    #[cfg(unix)]
+    fn open(file_name: &str, file: File, length: usize) -> Result<Mapping, String> {
+        let data = unsafe {
+            libc::mmap(std::ptr::null_mut(), length, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0)
+        };
+        if data == libc::MAP_FAILED {
+            return Err(format!("Could not map {}: {}", file_name, std::io::Error::last_os_error()));
+        }
+        Ok(Mapping::Mapped { data: data as *const u8, length })
+    }
+
+    #[cfg(not(unix))]
+    fn open(file_name: &str, _file: File, _length: usize) -> Result<Mapping, String> {
+        let bytes = std::fs::read(file_name).map_err(|error| format!("Could not read {}: {}", file_name, error))?;
+        Ok(Mapping::Read(bytes))
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match self {
+            #[cfg(unix)]
+            Mapping::Mapped { data, length } => unsafe { std::slice::from_raw_parts(*data, *length) },
+            #[cfg(not(unix))]
+            Mapping::Read(bytes) => bytes,
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        let Mapping::Mapped { data, length } = self;
+        unsafe {
+            libc::munmap(*data as *mut libc::c_void, *length);
+        }
+    }
+}
+
+/// A strategy saved by MmapStore, mapped into memory rather than read.
+/// Records are read in place as they are iterated, so opening even a huge
+/// blueprint is instant and converting it never holds it all at once
+pub struct MappedPolicies {
+    mapping: Mapping,
+    abstraction: u64,
+    /// Byte offset of the first record and number of records of every player
+    players: Vec<(usize, usize)>,
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+impl MappedPolicies {
+    pub fn open(file_name: &str) -> Result<MappedPolicies, String> {
+        let file = File::open(file_name).map_err(|error| format!("Could not open {}: {}", file_name, error))?;
+        let length = file.metadata().map_err(|error| format!("Could not read {}: {}", file_name, error))?.len() as usize;
+        if length < MMAP_HEADER_LENGTH {
+            return Err(format!("{} is too short to be a mapped strategy", file_name));
+        }
+        // Unmapped on drop if the header turns out to be wrong
+        let mapping = Mapping::open(file_name, file, length)?;
+        let bytes = mapping.bytes();
+        let length = bytes.len();
+        if &bytes[..8] != MMAP_MAGIC {
+            return Err(format!("{} is not a mapped strategy", file_name));
+        }
+        let abstraction = read_u64(bytes, 8);
+        let num_players = read_u64(bytes, 16) as usize;
+        let mut offset = match num_players.checked_mul(8).map(|counts| MMAP_HEADER_LENGTH + counts) {
+            Some(offset) if offset <= length => offset,
+            _ => return Err(format!("{} has a corrupt header", file_name)),
+        };
+        let mut players = vec![];
+        for player_num in 0..num_players {
+            let count = read_u64(bytes, MMAP_HEADER_LENGTH + 8 * player_num) as usize;
+            players.push((offset, count));
+            offset = count
+                .checked_mul(RECORD_LENGTH)
+                .and_then(|records| records.checked_add(offset))
+                .ok_or_else(|| format!("{} has a corrupt header", file_name))?;
+        }
+        if offset != length {
+            return Err(format!("{} should be {} bytes but is {}", file_name, offset, length));
+        }
+
+        Ok(MappedPolicies {
+            mapping,
+            abstraction,
+            players,
+        })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        self.mapping.bytes()
+    }
+
+    pub fn abstraction(&self) -> u64 {
+        self.abstraction
+    }
+
+    pub fn num_players(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn len(&self, player_num: usize) -> usize {
+        self.players[player_num].1
+    }
+
+    fn info_set(&self, player_num: usize, index: usize) -> CondensedInfoSet {
+        read_u64(self.bytes(), self.players[player_num].0 + index * RECORD_LENGTH)
+    }
+
+    fn policy(&self, player_num: usize, index: usize) -> CondensedPolicyDistribution {
+        let start = self.players[player_num].0 + index * RECORD_LENGTH;
+        from_record(&self.bytes()[start..start + RECORD_LENGTH]).1
+    }
+
+    pub fn iter(&self, player_num: usize) -> impl Iterator<Item = (CondensedInfoSet, CondensedPolicyDistribution)> + '_ {
+        (0..self.len(player_num)).map(move |index| (self.info_set(player_num, index), self.policy(player_num, index)))
+    }
+}
+
+/// The entries of a MappedPolicies, read in place
+struct MappedEntries {
+    mapped: MappedPolicies,
+    player_num: usize,
+    index: usize,
+}
+
+impl Iterator for MappedEntries {
+    type Item = Result<Entry, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.player_num == self.mapped.num_players() {
+                return None;
+            }
+            if self.index < self.mapped.len(self.player_num) {
+                break;
+            }
+            self.player_num += 1;
+            self.index = 0;
+        }
+        let entry = (self.player_num, self.mapped.info_set(self.player_num, self.index), self.mapped.policy(self.player_num, self.index));
+        self.index += 1;
+        Some(Ok(entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policies() -> SavedPolicies {
+        vec![
+            vec![(7, compress_policy(&vec![0.25, 0.75])), (3, compress_policy(&vec![1.0]))],
+            vec![(5, compress_policy(&vec![0.0, 0.0, 0.5, 0.5]))],
+        ]
+    }
+
+    fn round_trip(file_name: &str) -> (Option<u64>, SavedPolicies) {
+        let file_name = std::env::temp_dir().join(file_name);
+        let file_name = file_name.to_str().unwrap();
+        let store = store_for(file_name);
+        store.write(file_name, 42, &policies()).unwrap();
+        let result = store.read(file_name).unwrap();
+        std::fs::remove_file(file_name).unwrap();
+        result
+    }
+
+    #[test]
+    fn test_format_is_chosen_by_extension() {
+        assert_eq!(StoreFormat::from_extension("auction_poker_p0.json"), StoreFormat::Json);
+        assert_eq!(StoreFormat::from_extension("blueprint/flop.mmap"), StoreFormat::Mmap);
+        assert_eq!(StoreFormat::from_extension("auction_poker.bp"), StoreFormat::Bincode);
+        assert_eq!(StoreFormat::from_extension("no_extension"), StoreFormat::Bincode);
+    }
+
+    #[test]
+    fn test_every_store_round_trips() {
+        assert_eq!(round_trip("gtcogs_store_test.bp"), (Some(42), policies()));
+        assert_eq!(round_trip("gtcogs_store_test.json"), (Some(42), policies()));
+
+        // Mapped records come back sorted by info set
+        let mut sorted = policies();
+        sorted[0].sort_by_key(|(info_set, _)| *info_set);
+        assert_eq!(round_trip("gtcogs_store_test.mmap"), (Some(42), sorted));
+    }
+
+    #[test]
+    fn test_entries_stream_between_formats() {
+        let directory = std::env::temp_dir();
+        let bincode = directory.join("gtcogs_stream_test.bp");
+        let mapped = directory.join("gtcogs_stream_test.mmap");
+        let (bincode, mapped) = (bincode.to_str().unwrap(), mapped.to_str().unwrap());
+        BincodeStore.write(bincode, 42, &policies()).unwrap();
+
+        let reader = BincodeStore.open_entries(bincode).unwrap();
+        assert_eq!((reader.abstraction, &reader.counts), (Some(42), &vec![2, 1]));
+        let mut entries = reader.entries;
+        MmapStore.write_entries(mapped, 42, &reader.counts, &mut entries).unwrap();
+        let streamed: Vec<Entry> = MmapStore.open_entries(mapped).unwrap().entries.map(Result::unwrap).collect();
+        let (_, policies) = MmapStore.read(mapped).unwrap();
+        assert_eq!(streamed.len(), 3);
+        assert_eq!(streamed[2], (1, policies[1][0].0, policies[1][0].1));
+
+        // Entries have to come in the numbers promised
+        let mut entries = BincodeStore.open_entries(bincode).unwrap().entries;
+        assert!(BincodeStore.write_entries(mapped, 42, &[1, 2], &mut entries).is_err());
+        let mut entries = BincodeStore.open_entries(bincode).unwrap().entries;
+        assert!(MmapStore.write_entries(mapped, 42, &[2], &mut entries).is_err());
+        std::fs::remove_file(bincode).unwrap();
+        std::fs::remove_file(mapped).unwrap();
+    }
+
+    #[test]
+    fn test_json_reads_older_formats() {
+        let file_name = std::env::temp_dir().join("gtcogs_store_legacy_test.json");
+        let file_name = file_name.to_str().unwrap();
+        std::fs::write(file_name, "[[3, [1.0]]]").unwrap();
+        let (abstraction, policies) = JsonStore.read(file_name).unwrap();
+        assert_eq!(abstraction, None);
+        assert_eq!(policies, vec![vec![(3, compress_policy(&vec![1.0]))]]);
+
+        std::fs::write(file_name, r#"{"abstraction": 42, "information": [[3, [0.5, 0.5]]]}"#).unwrap();
+        let (abstraction, policies) = JsonStore.read(file_name).unwrap();
+        std::fs::remove_file(file_name).unwrap();
+        assert_eq!(abstraction, Some(42));
+        assert_eq!(policies, vec![vec![(3, compress_policy(&vec![0.5, 0.5]))]]);
+    }
+
+    #[test]
+    fn test_bincode_reads_headerless_blueprints() {
+        let file_name = std::env::temp_dir().join("gtcogs_store_legacy_test.bp");
+        let file_name = file_name.to_str().unwrap();
+        std::fs::write(file_name, bincode::serialize(&policies()).unwrap()).unwrap();
+        assert_eq!(BincodeStore.read(file_name).unwrap(), (None, policies()));
+
+        // Streamed out byte for byte as bincode would have written it
+        BincodeStore.write(file_name, 42, &policies()).unwrap();
+        assert_eq!(std::fs::read(file_name).unwrap(), bincode::serialize(&(42u64, policies())).unwrap());
+
+        std::fs::write(file_name, b"not a blueprint").unwrap();
+        let error = BincodeStore.read(file_name).unwrap_err();
+        std::fs::remove_file(file_name).unwrap();
+        assert!(error.contains("is not a saved strategy"), "{}", error);
+    }
+
+    #[test]
+    fn test_full_tables_are_not_rounded() {
+        let file_name = std::env::temp_dir().join("gtcogs_full_table_test.json");
+        let file_name = file_name.to_str().unwrap();
+        write_full_table(file_name, 42, &[(3, vec![0.12345, 0.87655, 0.0])]).unwrap();
+        assert!(std::fs::read_to_string(file_name).unwrap().contains("[3,[0.12345,0.87655]]"));
+
+        // Still a table everything else reads
+        let (abstraction, policies) = JsonStore.read(file_name).unwrap();
+        std::fs::remove_file(file_name).unwrap();
+        assert_eq!((abstraction, policies), (Some(42), vec![vec![(3, compress_policy(&vec![0.12345, 0.87655]))]]));
+
+        let too_long = vec![0.0; COMPRESSED_POLICY_LENGTH + 1];
+        assert!(std::panic::catch_unwind(|| compress_policy(&too_long)).is_err());
+    }
+
+    #[test]
+    fn test_parallel_chunks_keep_their_order() {
+        let items: Vec<u32> = (0..1000).collect();
+        let sums = parallel_chunks(&items, |chunk| chunk.to_vec());
+        assert_eq!(sums.concat(), items);
+        assert!(parallel_chunks(&Vec::<u32>::new(), |chunk| chunk.len()).is_empty());
+    }
+
+    #[test]
+    fn test_mapped_policies_are_read_in_place() {
+        let file_name = std::env::temp_dir().join("gtcogs_mapped_test.mmap");
+        let file_name = file_name.to_str().unwrap();
+        MmapStore.write(file_name, 42, &policies()).unwrap();
+        let mapped = MappedPolicies::open(file_name).unwrap();
+        assert_eq!(mapped.abstraction(), 42);
+        assert_eq!(mapped.num_players(), 2);
+        // Sorted by infoset on the way in
+        let first: Vec<_> = mapped.iter(0).collect();
+        assert_eq!(first, vec![(3, compress_policy(&vec![1.0])), (7, compress_policy(&vec![0.25, 0.75]))]);
+        assert_eq!(mapped.iter(1).collect::<Vec<_>>(), policies()[1]);
+        drop(mapped);
+
+        std::fs::write(file_name, b"GTCOGSMM but far too short").unwrap();
+        assert!(MappedPolicies::open(file_name).is_err());
+        std::fs::remove_file(file_name).unwrap();
+    }
+}
@@ -1,29 +1,115 @@
 use crate::constants::*;
 use crate::game_logic::action::{Action, ActionIndex};
+use crate::game_logic::schema::{ablate_history, FeatureSchema, ROUND_RADIX};
 use crate::game_logic::state::ActivePlayer;
+use crate::game_logic::strategy::intern::{is_interned, HISTORIES, INTERNED};
 use crate::game_logic::strategy::CondensedInfoSet;
+use serde::{Deserialize, Serialize};
 use std::{fmt::Debug, hash::Hash};
 use crate::implementations::auction::Card;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct History(pub Vec<ActionIndex>);
 
 pub static MAX_ACTIONS: CondensedInfoSet = 200;
+
+/// Most entries a History that isn't a feature history (see
+/// FeatureSchema::fits) can have and still fit in a CondensedInfoSet.
+/// Every entry is a digit in base MAX_ACTIONS under a leading 1, so n
+/// entries take values up to 2 * MAX_ACTIONS^n - 1, and the packing takes
+/// one more bit. The top bit marks interned histories (see strategy::intern),
+/// so the few longest histories that would reach it are interned too
+pub const fn max_features() -> usize {
+    let mut entries = 0;
+    let mut top: CondensedInfoSet = 1;
+    while top <= (CondensedInfoSet::MAX / 4) / MAX_ACTIONS {
+        top *= MAX_ACTIONS;
+        entries += 1;
+    }
+    entries
+}
+
+/// How a History is packed into a CondensedInfoSet, the lowest bit of
+/// which says which one it was.
+///
+/// A feature history that fits its FeatureSchema is packed in mixed radix,
+/// every slot taking just the values its schema allows (see
+/// FeatureSchema::radices), which fits any schema we have many times over.
+/// Anything else (the action histories of the toy games, or a feature
+/// history out of range) is packed in base MAX_ACTIONS under a leading 1.
+/// Either way the first entry is the least significant, so histories that
+/// only differ in their last entries are far apart. A history that can't
+/// be packed either way is interned, see strategy::intern
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Packing {
+    Uniform = 0,
+    Schema = 1,
+}
+
 impl History {
+    /// Packs the history into a single number, see Packing, interning it
+    /// rather than wrapping around into some other infoset if it can't
     pub fn into_condensed(self) -> CondensedInfoSet {
+        match self.try_into_condensed() {
+            Ok(condensed) => condensed,
+            Err(_) => HISTORIES.condense(&self),
+        }
+    }
+
+    /// Packs the history into a single number, failing if it is neither a
+    /// feature history nor short enough, see max_features
+    pub fn try_into_condensed(&self) -> Result<CondensedInfoSet, String> {
+        if let Some(schema) = FeatureSchema::for_history(self).filter(|schema| schema.fits(self)) {
+            let mut condensed = 0;
+            let radices: Vec<CondensedInfoSet> = schema.radices().collect();
+            for (action, radix) in self.0.iter().zip(radices).rev() {
+                condensed *= radix;
+                condensed += *action as CondensedInfoSet;
+            }
+            return Ok(condensed << 1 | Packing::Schema as CondensedInfoSet);
+        }
+        if self.0.len() > max_features() {
+            return Err(format!(
+                "{:?} has {} entries, a condensed infoset holds at most {}",
+                self.0,
+                self.0.len(),
+                max_features()
+            ));
+        }
+        if let Some(entry) = self.0.iter().find(|entry| **entry as CondensedInfoSet >= MAX_ACTIONS) {
+            return Err(format!("{:?} has an entry of {}, at most {} fit", self.0, entry, MAX_ACTIONS - 1));
+        }
         let mut condensed = 1;
         for action in self.0.iter().rev() {
             condensed *= MAX_ACTIONS;
             condensed += *action as CondensedInfoSet;
         }
-        condensed
+        if condensed >= INTERNED >> 1 {
+            return Err(format!("{:?} would take the bit of interned histories", self.0));
+        }
+        Ok(condensed << 1 | Packing::Uniform as CondensedInfoSet)
     }
 }
 
 impl From<CondensedInfoSet> for History {
+    /// Panics on an interned info set this process never interned
     fn from(condensed: CondensedInfoSet) -> Self {
+        if is_interned(condensed) {
+            return HISTORIES.history(condensed).expect("Info set interned by another process");
+        }
         let mut history = Vec::new();
-        let mut condensed = condensed;
+        let packing = condensed & 1;
+        let mut condensed = condensed >> 1;
+        if packing == Packing::Schema as CondensedInfoSet {
+            let round = condensed % ROUND_RADIX;
+            condensed /= ROUND_RADIX;
+            history.push(round as ActionIndex);
+            for radix in FeatureSchema::for_round((round as usize).into()).radices().skip(1) {
+                history.push((condensed % radix) as ActionIndex);
+                condensed /= radix;
+            }
+            return History(history);
+        }
         //while condensed > 0 {
         while condensed > 1 {
             history.push((condensed % MAX_ACTIONS) as ActionIndex);
@@ -54,7 +140,7 @@ pub struct ObservationTracker {
 ///     - it does require us to have a blazingly fast evaluator hehehehehhehe
 ///       (which we don't yet but I'd much rather work on that instead of this)
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Round {
     PreFlop,
     Auction,
@@ -87,13 +173,13 @@ impl From<usize> for Round {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum BidResult {
     Player(u8),
     Tie,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Feature {
     Suited(bool),        // True if the hand is suited
     Ranks(usize, usize), // Sorted from highest to lowest
@@ -103,12 +189,14 @@ pub enum Feature {
     Auction(BidResult),
     Stack(u8), // Stack as percentage of max scaled down (0-50)
     Aggression(usize),
+    WinValue(u16), // Marginal equity of winning the auction, scaled (0-30)
 }
 
 
 impl Feature {
+    /// Largest value a feature can take and still fit in a condensed infoset
     pub fn max_index() -> usize {
-        200
+        (MAX_ACTIONS - 1) as usize
     }
 }
 
@@ -130,6 +218,7 @@ impl Into<ActionIndex> for Feature {
             },
             Feature::Stack(x) => x as ActionIndex,
             Feature::Aggression(x) => x as ActionIndex,
+            Feature::WinValue(x) => x as ActionIndex,
         }
     }
 }
@@ -164,6 +253,32 @@ pub enum Observation<A: Action> {
     Shared(Information<A>, Vec<usize>), // A subset of players can see the action
 }
 
+impl<A: Action> Observation<A> {
+    /// Whether `player` sees this, when `acting` took the action
+    pub fn visible_to(&self, player: usize, acting: Option<usize>) -> bool {
+        match self {
+            Observation::Public(_) => true,
+            Observation::Private(_) => acting == Some(player),
+            Observation::Shared(_, players) => players.contains(&player),
+        }
+    }
+
+    pub fn information(&self) -> &Information<A> {
+        match self {
+            Observation::Public(info) | Observation::Private(info) | Observation::Shared(info, _) => info,
+        }
+    }
+}
+
+/// One observation as a single player received it. `sequence` is the
+/// number of actions played before the one it came from, so the
+/// observations of one action share it and the stream is ordered by it
+#[derive(Clone, Debug)]
+pub struct Observed<A> {
+    pub sequence: usize,
+    pub information: Information<A>,
+}
+
 impl ObservationTracker {
     pub fn new() -> Self {
         ObservationTracker {
@@ -175,7 +290,9 @@ impl ObservationTracker {
     pub fn get_history(&self, player: usize) -> History {
         if let Some(history) = &self.player_feature_sets[player] {
             let action_indices = history.iter().map(|action| action.clone().into()).collect();
-            History(action_indices)
+            let mut history = History(action_indices);
+            ablate_history(&mut history);
+            history
         } else {
             History(self.player_info_sets[player].clone())
         }
@@ -241,3 +358,55 @@ impl ObservationTracker {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_features_serialize() {
+        let history = History(vec![2, 40, 3, 1, 55, 12, 18]);
+        let json = serde_json::to_string(&history).unwrap();
+        assert_eq!(serde_json::from_str::<History>(&json).unwrap().0, history.0);
+
+        let features = vec![Feature::Order(Round::Turn), Feature::Ranks(12, 3), Feature::Auction(BidResult::Tie)];
+        let json = serde_json::to_string(&features).unwrap();
+        let parsed: Vec<Feature> = serde_json::from_str(&json).unwrap();
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", features));
+    }
+
+    #[test]
+    fn test_condensed_capacity() {
+        assert_eq!(max_features(), 8);
+
+        // The longest feature vectors of auction poker, every feature at its largest
+        for round in [Round::PreFlop, Round::Auction, Round::Flop, Round::Turn, Round::River] {
+            let schema = FeatureSchema::for_round(round);
+            assert!(schema.len() <= max_features());
+            let largest = History(schema.features.iter().map(|spec| spec.max).collect());
+            let condensed = largest.clone().try_into_condensed().unwrap();
+            assert_eq!(History::from(condensed).0, largest.0);
+        }
+
+        // Feature histories are packed by their schema, much tighter
+        let flop = History(vec![2, 50, 5, 2, 100, 30, 30]).into_condensed();
+        assert!(flop < 1 << 32);
+        let action_history = History(vec![2, 50, 5, 2, 100, 30, 31]);
+        assert_eq!(History::from(action_history.clone().into_condensed()).0, action_history.0);
+
+        let longest = History(vec![(MAX_ACTIONS - 1) as ActionIndex / 2; max_features()]);
+        let condensed = longest.clone().try_into_condensed().unwrap();
+        assert_eq!(History::from(condensed).0, longest.0);
+        assert!(!is_interned(condensed));
+
+        // Histories that can't be packed are interned instead
+        let largest = History(vec![(MAX_ACTIONS - 1) as ActionIndex; max_features()]);
+        assert!(largest.try_into_condensed().is_err());
+        let condensed = largest.clone().into_condensed();
+        assert!(is_interned(condensed));
+        assert_eq!(History::from(condensed).0, largest.0);
+
+        assert!(History(vec![0; max_features() + 1]).try_into_condensed().is_err());
+        assert!(History(vec![1, MAX_ACTIONS as ActionIndex]).try_into_condensed().is_err());
+    }
+}
@@ -1,14 +1,24 @@
 use crate::constants::*;
+use crate::context::{default_context, Context};
 use crate::distribution::Categorical;
-use crate::eval::rank::HandRanker;
+use crate::eval::bid::BidValue;
+use crate::eval::rank::{AuctionOutcome, HandRanker, RolloutKind, StreetRound};
+use crate::implementations::audit::{audit_by_default, PotAudit};
+use crate::implementations::ledger::{Award, Ledger};
 use crate::game_logic::action::*;
-use crate::game_logic::state::{ActivePlayer, State};
+use crate::game_logic::abstraction::AbstractionHasher;
+use crate::units::{Chips, PotFraction};
+use crate::game_logic::schema::validate_features;
+use crate::game_logic::state::{ActivePlayer, State, UtilityBounds};
 use crate::game_logic::visibility::*;
+pub use crate::implementations::cards::*;
 use rand::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
+use std::sync::{Arc, OnceLock};
 use std::time::{Duration, Instant};
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum RelativeSize {
     DeciPercent(u32),
     Amount(u32),
@@ -19,238 +29,30 @@ impl RelativeSize {
     pub fn to_percent(&self, pot: u32) -> u32 {
         let size = match self {
             DeciPercent(p) => *p,
-            Amount(a) => ((*a as f32 / pot as f32) * 1000.0).round() as u32,
+            Amount(a) => Chips(*a as f32).of_pot(pot).to_deci_percent(),
         };
         size
     }
 
     pub fn to_amount(&self, pot: u32) -> u32 {
         let size = match self {
-            DeciPercent(p) => (pot as f32 * (*p as f32 / 1000.0)).round() as u32,
+            DeciPercent(p) => PotFraction::from_deci_percent(*p).of(pot).round(),
             Amount(a) => *a,
         };
         size
     }
 }
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub enum Suit {
-    Hearts,
-    Diamonds,
-    Clubs,
-    Spades,
-}
-
-impl Parsable for Suit {
-    fn to_string(&self) -> Option<String> {
-        match self {
-            Suit::Hearts => Some("h".to_string()),
-            Suit::Diamonds => Some("d".to_string()),
-            Suit::Clubs => Some("c".to_string()),
-            Suit::Spades => Some("s".to_string()),
-        }
-    }
-    fn to_usize(&self) -> Option<usize> {
-        None
-    }
-}
-
-impl Suit {
-    fn new(s: String) -> Self {
-        match s.as_str() {
-            "h" => Suit::Hearts,
-            "d" => Suit::Diamonds,
-            "c" => Suit::Clubs,
-            "s" => Suit::Spades,
-            _ => panic!("Invalid suit string"),
-        }
-    }
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub enum Value {
-    Two,
-    Three,
-    Four,
-    Five,
-    Six,
-    Seven,
-    Eight,
-    Nine,
-    Ten,
-    Jack,
-    Queen,
-    King,
-    Ace,
-}
-
-impl Parsable for Value {
-    fn to_string(&self) -> Option<String> {
-        let result = match self {
-            Value::Two => "2".to_string(),
-            Value::Three => "3".to_string(),
-            Value::Four => "4".to_string(),
-            Value::Five => "5".to_string(),
-            Value::Six => "6".to_string(),
-            Value::Seven => "7".to_string(),
-            Value::Eight => "8".to_string(),
-            Value::Nine => "9".to_string(),
-            Value::Ten => "T".to_string(),
-            Value::Jack => "J".to_string(),
-            Value::Queen => "Q".to_string(),
-            Value::King => "K".to_string(),
-            Value::Ace => "A".to_string(),
-        };
-        Some(result)
-    }
-    fn to_usize(&self) -> Option<usize> {
-        let value = match self {
-            Value::Ace => 0,
-            Value::King => 1,
-            Value::Queen => 2,
-            Value::Jack => 3,
-            Value::Ten => 4,
-            Value::Nine => 5,
-            Value::Eight => 6,
-            Value::Seven => 7,
-            Value::Six => 8,
-            Value::Five => 9,
-            Value::Four => 10,
-            Value::Three => 11,
-            Value::Two => 12,
-        };
-        Some(value)
-    }
-}
-
-impl Value {
-    fn new(s: String) -> Self {
-        match s.as_str() {
-            "2" => Value::Two,
-            "3" => Value::Three,
-            "4" => Value::Four,
-            "5" => Value::Five,
-            "6" => Value::Six,
-            "7" => Value::Seven,
-            "8" => Value::Eight,
-            "9" => Value::Nine,
-            "T" => Value::Ten,
-            "J" => Value::Jack,
-            "Q" => Value::Queen,
-            "K" => Value::King,
-            "A" => Value::Ace,
-            _ => panic!("Invalid value string"),
-        }
-    }
-}
-impl From<usize> for Value {
-    fn from(value: usize) -> Self {
-        match value {
-            0 => Value::Ace,
-            1 => Value::King,
-            2 => Value::Queen,
-            3 => Value::Jack,
-            4 => Value::Ten,
-            5 => Value::Nine,
-            6 => Value::Eight,
-            7 => Value::Seven,
-            8 => Value::Six,
-            9 => Value::Five,
-            10 => Value::Four,
-            11 => Value::Three,
-            12 => Value::Two,
-            _ => panic!("Invalid suit index"),
-        }
-    }
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct Card {
-    pub value: Value,
-    pub suit: Suit,
-}
-
-impl Parsable for Card {
-    fn to_string(&self) -> Option<String> {
-        let string = format!(
-            "{}{}",
-            self.value.to_string().unwrap(),
-            self.suit.to_string().unwrap()
-        );
-        Some(string)
-    }
-    fn to_usize(&self) -> Option<usize> {
-        let suit = match self.suit {
-            Suit::Hearts => 0,
-            Suit::Diamonds => 1,
-            Suit::Clubs => 2,
-            Suit::Spades => 3,
-        };
-        let value = match self.value {
-            Value::Ace => 0,
-            Value::King => 1,
-            Value::Queen => 2,
-            Value::Jack => 3,
-            Value::Ten => 4,
-            Value::Nine => 5,
-            Value::Eight => 6,
-            Value::Seven => 7,
-            Value::Six => 8,
-            Value::Five => 9,
-            Value::Four => 10,
-            Value::Three => 11,
-            Value::Two => 12,
-        };
-        let index = suit + value * 4;
-        Some(index)
-    }
-}
-pub type CardIndex = usize;
-impl Card {
-    pub fn from_index(index: CardIndex) -> Self {
-        let suit = match index % 4 {
-            0 => Suit::Hearts,
-            1 => Suit::Diamonds,
-            2 => Suit::Clubs,
-            3 => Suit::Spades,
-            _ => panic!("Invalid value index"),
-        };
-        let value = match index / 4 {
-            0 => Value::Ace,
-            1 => Value::King,
-            2 => Value::Queen,
-            3 => Value::Jack,
-            4 => Value::Ten,
-            5 => Value::Nine,
-            6 => Value::Eight,
-            7 => Value::Seven,
-            8 => Value::Six,
-            9 => Value::Five,
-            10 => Value::Four,
-            11 => Value::Three,
-            12 => Value::Two,
-            _ => panic!("Invalid suit index"),
-        };
-        Card { value, suit }
-    }
-    pub fn new(s: &str) -> Self {
-        let mut chars = s.chars();
-        let value = Value::new(chars.next().unwrap().to_string());
-        let suit = Suit::new(chars.next().unwrap().to_string());
-        Card { value, suit }
-    }
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Hand {
     pub hand_size: usize,
-    pub cards: Vec<Card>,
+    pub cards: Deck,
 }
 
 impl Parsable for Hand {
     fn to_string(&self) -> Option<String> {
         // Sort the cards so that the order is always the same
         let mut s = String::new();
-        for card in self.cards.clone() {
+        for card in self.cards() {
             s.push_str(&card.to_string().unwrap());
         }
         Some(s)
@@ -264,17 +66,11 @@ impl Hand {
     fn new() -> Self {
         Hand {
             hand_size: 2,
-            cards: Vec::new(),
+            cards: Deck::empty(),
         }
     }
     fn add_card(&mut self, card: Card) {
-        self.cards.push(card);
-        self.cards.sort_by(|a, b| {
-            a.value
-                .to_string()
-                .unwrap()
-                .cmp(&b.value.to_string().unwrap())
-        });
+        self.cards.insert(card);
     }
 
     fn expand(&mut self) {
@@ -288,31 +84,25 @@ impl Hand {
         self.hand_size > self.cards.len()
     }
 
-    fn len(&self) -> usize {
-        self.cards.len()
+    pub fn as_u8(&self) -> Box<[u8]> {
+        self.cards.as_u8().into_boxed_slice()
     }
 
-    fn as_u8(&self) -> Box<[u8]> {
-        let mut result = Vec::new();
-        for card in self.cards.clone() {
-            result.push(card.to_usize().unwrap() as u8);
-        }
-        result.clone().into_boxed_slice()
-    }
-
-    fn cards(&self) -> Vec<Card> {
-        self.cards.clone()
+    /// Cards from highest to lowest (see the ordering on `Card`)
+    pub fn cards(&self) -> Vec<Card> {
+        let mut cards: Vec<Card> = self.cards.iter().collect();
+        cards.sort_by(|a, b| b.cmp(a));
+        cards
     }
 }
 
-fn card_features(cards: &Vec<Card>) -> Vec<Feature> {
+fn card_features(cards: &[Card]) -> Vec<Feature> {
     // See if the hand is suited (both cards are the same suit)
-    let suited = cards[0].suit == cards[1].suit;
+    let suited = cards[0].suit() == cards[1].suit();
     // Sort the cards ranks by value
     let mut value = cards
-        .clone()
         .iter()
-        .map(|card| card.value.to_usize().unwrap())
+        .map(|card| card.value_index())
         .collect::<Vec<usize>>();
     value.sort();
     let features = vec![
@@ -323,13 +113,13 @@ fn card_features(cards: &Vec<Card>) -> Vec<Feature> {
     features
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Winner {
     Player(usize),
     Tie,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum AuctionPokerAction {
     Fold,
     Call,
@@ -362,6 +152,104 @@ impl Parsable for AuctionPokerAction {
     }
 }
 
+/// Largest raise (DeciPercent of the pot) in each raise bucket, smallest
+/// first. Together with the bid buckets this is the whole betting abstraction,
+/// both directions of the ActionIndex conversion are generated from them
+/// so an index always decodes to an action of its own bucket.
+///
+/// Kept small so we can test whether the abstraction is working; a much
+/// finer grid (steps of 50 up to 1100) is the obvious next thing to try
+const RAISE_BUCKETS: [u32; 19] = [
+    300, 500, 600, 750, 1000, 1250, 1500, 1750, 2000, 3000, 4000, 5000, 10000, 20000, 30000, 40000, 50000, 75000,
+    // This is just ridiculous, but necessary to capture all-ins
+    // (all ins on preflop are ~13300% of pot)
+    1_000_000,
+];
+
+/// Largest bid (in chips) in each bid bucket, smallest first, unless
+/// bid_buckets in the abstraction config says otherwise
+pub const DEFAULT_BID_BUCKETS: [u32; 18] = [0, 10, 20, 30, 40, 50, 60, 70, 80, 90, 110, 133, 150, 186, 195, 230, 356, 400];
+
+// Fold, Call and Check come first, then the raise buckets, then the bids
+const FIRST_RAISE: ActionIndex = 3;
+const FIRST_BID: ActionIndex = FIRST_RAISE + RAISE_BUCKETS.len() as ActionIndex;
+/// Markers all have index 100, the bids have to stay below
+pub const MAX_BID_BUCKETS: usize = 100 - FIRST_BID as usize;
+
+static BID_BUCKETS: OnceLock<Vec<u32>> = OnceLock::new();
+
+/// The bid buckets in use, DEFAULT_BID_BUCKETS unless set_bid_buckets
+/// came first
+pub fn bid_buckets() -> &'static [u32] {
+    BID_BUCKETS.get_or_init(|| DEFAULT_BID_BUCKETS.to_vec())
+}
+
+/// Bucket the bids by `buckets` for the rest of the run. This has to
+/// happen before any bid is indexed, since indices (and the abstraction
+/// hash of every strategy saved) mean whatever the first buckets said
+pub fn set_bid_buckets(buckets: &[u32]) -> Result<(), String> {
+    check_bid_buckets(buckets)?;
+    if BID_BUCKETS.set(buckets.to_vec()).is_err() && bid_buckets() != buckets {
+        return Err(format!("Bids are already bucketed by {:?}", bid_buckets()));
+    }
+    Ok(())
+}
+
+/// Whether `buckets` can bucket every bid: largest bids of each bucket,
+/// strictly increasing up to a whole stack, and every bucket decoding to
+/// a bid of its own
+pub fn check_bid_buckets(buckets: &[u32]) -> Result<(), String> {
+    if buckets.is_empty() || buckets.len() > MAX_BID_BUCKETS {
+        return Err(format!("Need 1 to {} bid buckets, not {}", MAX_BID_BUCKETS, buckets.len()));
+    }
+    if let Some(pair) = buckets.windows(2).find(|pair| pair[0] >= pair[1]) {
+        return Err(format!("Bid buckets must increase, {} is followed by {}", pair[0], pair[1]));
+    }
+    if buckets[buckets.len() - 1] < STACK_SIZE {
+        return Err(format!("The largest bid bucket must hold a whole stack ({}), not {}", STACK_SIZE, buckets[buckets.len() - 1]));
+    }
+    for bucket in 0..buckets.len() as ActionIndex {
+        let middle = bucket_middle(buckets, bucket);
+        if bucket_of(buckets, middle) != Some(bucket) {
+            return Err(format!("Bid bucket {} decodes to {}, which is in bucket {:?}", bucket, middle, bucket_of(buckets, middle)));
+        }
+    }
+    Ok(())
+}
+
+/// `count` bid buckets holding about as many of `bids` each, the
+/// largest stretched to a whole stack. Fewer if the bids bunch up
+pub fn quantile_bid_buckets(bids: &[u32], count: usize) -> Vec<u32> {
+    let mut bids = bids.to_vec();
+    bids.sort_unstable();
+    let mut buckets: Vec<u32> = (1..count)
+        .filter_map(|bucket| bids.get(bucket * bids.len() / count).copied())
+        .filter(|largest| *largest < STACK_SIZE)
+        .collect();
+    buckets.dedup();
+    buckets.push(STACK_SIZE);
+    buckets
+}
+
+fn num_actions() -> ActionIndex {
+    FIRST_BID + bid_buckets().len() as ActionIndex
+}
+
+/// Which of `buckets` `size` falls in
+fn bucket_of(buckets: &[u32], size: u32) -> Option<ActionIndex> {
+    buckets.iter().position(|largest| size <= *largest).map(|bucket| bucket as ActionIndex)
+}
+
+/// The size a bucket decodes to, the middle of the bucket
+fn bucket_middle(buckets: &[u32], bucket: ActionIndex) -> u32 {
+    let bucket = bucket as usize;
+    let smallest = match bucket {
+        0 => 0,
+        _ => buckets[bucket - 1] + 1,
+    };
+    (smallest + buckets[bucket]) / 2
+}
+
 impl Into<ActionIndex> for AuctionPokerAction {
     fn into(self) -> ActionIndex {
         match self {
@@ -369,148 +257,21 @@ impl Into<ActionIndex> for AuctionPokerAction {
             AuctionPokerAction::Call => 1,
             AuctionPokerAction::Check => 2,
 
-            // We do a much smaller number of bet sizes
-            AuctionPokerAction::Raise(DeciPercent(size)) => {
-                match size {
-                    // SMALL ABSTRACTION SPACE SO WE CAN TEST
-                    // WHETHER THE ABSTRACTION IS WORKING
-                    0..=300 => 3,
-                    ..=500 => 4,
-                    ..=600 => 5,
-                    ..=750 => 6,
-                    ..=1000 => 7,
-                    ..=1250 => 8,
-                    ..=1500 => 9,
-                    ..=1750 => 10,
-                    ..=2000 => 11,
-                    ..=3000 => 12,
-                    ..=4000 => 13,
-                    ..=5000 => 14,
-                    ..=10000 => 15,
-                    ..=20000 => 16,
-                    ..=30000 => 17,
-                    ..=40000 => 18,
-                    ..=50000 => 19,
-                    ..=75000 => 20,
-                    ..=1000000 => 21,
-                    // LARGE ABSTRACTIONS
-                    //// Get really granular for the first several sizes of the pot
-                    //0..=50 => 3,
-                    //..=100 => 4,
-                    //..=150 => 5,
-                    //..=200 => 6,
-                    //..=250 => 7,
-                    //..=300 => 8,
-                    //..=350 => 9,
-                    //..=400 => 10,
-                    //..=450 => 11,
-                    //..=500 => 12,
-                    //..=550 => 13,
-                    //..=600 => 14,
-                    //..=650 => 15,
-                    //..=700 => 16,
-                    //..=750 => 17,
-                    //..=800 => 18,
-                    //..=850 => 19,
-                    //..=900 => 20,
-                    //..=950 => 21,
-                    //..=1000 => 22,
-                    //..=1050 => 23,
-                    //..=1100 => 24,
-                    //// Get less granular for the rest of the pot sizes
-                    //..=1200 => 25,
-                    //..=1500 => 26,
-                    //..=2000 => 27,
-                    //..=2500 => 28,
-                    //..=3000 => 29,
-                    //..=3500 => 30,
-                    //..=4000 => 31,
-                    //// Get wiggy with it
-                    //..=5000 => 32,
-                    //..=6000 => 33,
-                    //..=7000 => 34,
-                    //..=9000 => 35,
-                    //..=10000 => 36,
-                    //// Okay, now we're just being silly
-                    //..=15000 => 37,
-                    //..=25000 => 38,
-                    //..=50000 => 39,
-                    //..=100000 => 40,
-                    //// This is just ridiculous, but necessary to capture all-ins
-                    //// (all ins on preflop are ~13300% of pot)
-                    //..=1000000 => 41,
-                    _ => panic!("Well this is awkward... the bet size is too large!"),
-                }
-            }
+            AuctionPokerAction::Raise(DeciPercent(size)) => match bucket_of(&RAISE_BUCKETS, size) {
+                Some(bucket) => FIRST_RAISE + bucket,
+                None => panic!("Well this is awkward... the bet size is too large!"),
+            },
 
-            AuctionPokerAction::Raise(Amount(x)) => panic!(
+            AuctionPokerAction::Raise(Amount(_)) => panic!(
                 "Cannot convert raise size (amount) to action index! Convert to percent first!
                 {:#?}",
                 self.clone()
             ),
 
-            AuctionPokerAction::Bid(Amount(x)) => { match x {
-                0 => 22,
-                ..=10 => 23,
-                ..=20 => 24,
-                ..=30 => 25,
-                ..=40 => 26,
-                ..=50 => 27,
-                ..=60 => 28,
-                ..=70 => 29,
-                ..=80 => 30,
-                ..=90 => 31,
-                ..=110 => 32,
-                ..=133 => 33,
-                ..=150 => 34,
-                ..=186 => 35,
-                ..=195 => 36,
-                ..=230 => 37,
-                ..=356 => 38,
-                ..=400 => 39,
-                //0 => 42,
-                //1..=10 => 43,
-                //11..=20 => 44,
-                //21..=30 => 45,
-                //31..=40 => 46,
-                //41..=50 => 47,
-                //51..=60 => 48,
-                //61..=70 => 49,
-                //71..=80 => 50,
-                //81..=90 => 51,
-                //91..=100 => 52,
-                //101..=110 => 53,
-                //111..=120 => 54,
-                //121..=130 => 55,
-                //131..=140 => 56,
-                //141..=150 => 57,
-                //151..=160 => 58,
-                //161..=170 => 59,
-                //171..=180 => 60,
-                //181..=190 => 61,
-                //191..=200 => 62,
-                //201..=210 => 63,
-                //211..=220 => 64,
-                //221..=230 => 65,
-                //231..=240 => 66,
-                //241..=250 => 67,
-                //251..=260 => 68,
-                //261..=270 => 69,
-                //271..=280 => 70,
-                //281..=290 => 71,
-                //291..=300 => 72,
-                //301..=310 => 73,
-                //311..=320 => 74,
-                //321..=330 => 75,
-                //331..=340 => 76,
-                //341..=350 => 77,
-                //351..=360 => 78,
-                //361..=370 => 79,
-                //371..=380 => 80,
-                //381..=390 => 81,
-                //391..=400 => 82,
-                _ => panic!("Well this is awkward... the bid size is too large!"),
-            }}
+            AuctionPokerAction::Bid(Amount(x)) => match bucket_of(bid_buckets(), x) {
+                Some(bucket) => FIRST_BID + bucket,
+                None => panic!("Well this is awkward... the bid size is too large!"),
+            },
 
             AuctionPokerAction::Bid(DeciPercent(_)) => panic!(
                 "Cannot convert bid size (percent) to action index! Convert to amount first!"
@@ -537,93 +298,11 @@ impl From<ActionIndex> for AuctionPokerAction {
             0 => AuctionPokerAction::Fold,
             1 => AuctionPokerAction::Call,
             2 => AuctionPokerAction::Check,
-            3 => AuctionPokerAction::Raise(DeciPercent(30)),
-            4 => AuctionPokerAction::Raise(DeciPercent(80)),
-            5 => AuctionPokerAction::Raise(DeciPercent(130)),
-            6 => AuctionPokerAction::Raise(DeciPercent(180)),
-            7 => AuctionPokerAction::Raise(DeciPercent(230)),
-            8 => AuctionPokerAction::Raise(DeciPercent(280)),
-            9 => AuctionPokerAction::Raise(DeciPercent(330)),
-            10 => AuctionPokerAction::Raise(DeciPercent(380)),
-            11 => AuctionPokerAction::Raise(DeciPercent(430)),
-            12 => AuctionPokerAction::Raise(DeciPercent(480)),
-            13 => AuctionPokerAction::Raise(DeciPercent(530)),
-            14 => AuctionPokerAction::Raise(DeciPercent(580)),
-            15 => AuctionPokerAction::Raise(DeciPercent(630)),
-            16 => AuctionPokerAction::Raise(DeciPercent(680)),
-            17 => AuctionPokerAction::Raise(DeciPercent(730)),
-            18 => AuctionPokerAction::Raise(DeciPercent(780)),
-            19 => AuctionPokerAction::Raise(DeciPercent(830)),
-            20 => AuctionPokerAction::Raise(DeciPercent(880)),
-            21 => AuctionPokerAction::Raise(DeciPercent(930)),
-            22 => AuctionPokerAction::Raise(DeciPercent(980)),
-            23 => AuctionPokerAction::Raise(DeciPercent(1030)),
-            24 => AuctionPokerAction::Raise(DeciPercent(1080)),
-            // Get less granular for the rest of the pot sizes
-            25 => AuctionPokerAction::Raise(DeciPercent(1160)),
-            26 => AuctionPokerAction::Raise(DeciPercent(1360)),
-            27 => AuctionPokerAction::Raise(DeciPercent(1750)),
-            28 => AuctionPokerAction::Raise(DeciPercent(2250)),
-            29 => AuctionPokerAction::Raise(DeciPercent(2750)),
-            30 => AuctionPokerAction::Raise(DeciPercent(3250)),
-            31 => AuctionPokerAction::Raise(DeciPercent(3750)),
-            // Get wiggy with it
-            32 => AuctionPokerAction::Raise(DeciPercent(4500)),
-            33 => AuctionPokerAction::Raise(DeciPercent(5500)),
-            34 => AuctionPokerAction::Raise(DeciPercent(6500)),
-            35 => AuctionPokerAction::Raise(DeciPercent(8000)),
-            36 => AuctionPokerAction::Raise(DeciPercent(9500)),
-            // Okay, now we're just being silly
-            37 => AuctionPokerAction::Raise(DeciPercent(12500)),
-            38 => AuctionPokerAction::Raise(DeciPercent(20000)),
-            39 => AuctionPokerAction::Raise(DeciPercent(37500)),
-            40 => AuctionPokerAction::Raise(DeciPercent(75000)),
-            // This is just ridiculous, but necessary to capture all-ins
-            // (all ins on preflop are ~13300% of pot0)
-            41 => AuctionPokerAction::Raise(DeciPercent(500000)),
-            42 => AuctionPokerAction::Bid(Amount(0)),
-            43 => AuctionPokerAction::Bid(Amount(5)),
-            44 => AuctionPokerAction::Bid(Amount(15)),
-            45 => AuctionPokerAction::Bid(Amount(25)),
-            46 => AuctionPokerAction::Bid(Amount(36)),
-            47 => AuctionPokerAction::Bid(Amount(45)),
-            48 => AuctionPokerAction::Bid(Amount(55)),
-            49 => AuctionPokerAction::Bid(Amount(65)),
-            50 => AuctionPokerAction::Bid(Amount(75)),
-            51 => AuctionPokerAction::Bid(Amount(85)),
-            52 => AuctionPokerAction::Bid(Amount(95)),
-            53 => AuctionPokerAction::Bid(Amount(105)),
-            54 => AuctionPokerAction::Bid(Amount(115)),
-            55 => AuctionPokerAction::Bid(Amount(125)),
-            56 => AuctionPokerAction::Bid(Amount(135)),
-            57 => AuctionPokerAction::Bid(Amount(145)),
-            58 => AuctionPokerAction::Bid(Amount(155)),
-            59 => AuctionPokerAction::Bid(Amount(165)),
-            60 => AuctionPokerAction::Bid(Amount(175)),
-            61 => AuctionPokerAction::Bid(Amount(185)),
-            62 => AuctionPokerAction::Bid(Amount(195)),
-            63 => AuctionPokerAction::Bid(Amount(205)),
-            64 => AuctionPokerAction::Bid(Amount(215)),
-            65 => AuctionPokerAction::Bid(Amount(225)),
-            66 => AuctionPokerAction::Bid(Amount(235)),
-            67 => AuctionPokerAction::Bid(Amount(245)),
-            68 => AuctionPokerAction::Bid(Amount(255)),
-            69 => AuctionPokerAction::Bid(Amount(265)),
-            70 => AuctionPokerAction::Bid(Amount(275)),
-            71 => AuctionPokerAction::Bid(Amount(285)),
-            72 => AuctionPokerAction::Bid(Amount(295)),
-            73 => AuctionPokerAction::Bid(Amount(305)),
-            74 => AuctionPokerAction::Bid(Amount(315)),
-            75 => AuctionPokerAction::Bid(Amount(325)),
-            76 => AuctionPokerAction::Bid(Amount(335)),
-            77 => AuctionPokerAction::Bid(Amount(345)),
-            78 => AuctionPokerAction::Bid(Amount(355)),
-            79 => AuctionPokerAction::Bid(Amount(365)),
-            80 => AuctionPokerAction::Bid(Amount(375)),
-            81 => AuctionPokerAction::Bid(Amount(385)),
-            82 => AuctionPokerAction::Bid(Amount(395)),
-            83 => AuctionPokerAction::Bid(Amount(405)),
-            _ => panic!("No"),
+            index if index < FIRST_BID => {
+                AuctionPokerAction::Raise(DeciPercent(bucket_middle(&RAISE_BUCKETS, index - FIRST_RAISE)))
+            }
+            index if index < num_actions() => AuctionPokerAction::Bid(Amount(bucket_middle(bid_buckets(), index - FIRST_BID))),
+            _ => panic!("No auction action has index {}", index),
         }
     }
 }
@@ -631,30 +310,230 @@ impl From<ActionIndex> for AuctionPokerAction {
 impl Filterable for AuctionPokerAction {}
 impl Action for AuctionPokerAction {
     fn max_index() -> ActionIndex {
-        83
+        num_actions()
     }
     fn index(&self) -> ActionIndex {
         self.clone().into()
     }
+    /// The abstraction of the default context, see Context::abstraction_hash
+    fn abstraction_hash() -> u64 {
+        static HASH: OnceLock<u64> = OnceLock::new();
+        *HASH.get_or_init(|| default_context().abstraction_hash())
+    }
+    fn pot_fraction(&self) -> Option<f32> {
+        match self {
+            AuctionPokerAction::Raise(DeciPercent(size)) => Some(*size as f32 / 1000.0),
+            _ => None,
+        }
+    }
+}
+
+/// Hash of everything that decides what a strategy trained under `context`
+/// means: the raise and bid buckets, the feature layout and the rules used
+/// to scale them. The buckets are probed through the conversions so that
+/// editing either bucket table changes the hash
+pub fn abstraction_hash(context: &Context) -> u64 {
+    let game = &context.game;
+    let mut hasher = AbstractionHasher::new();
+
+    for index in 0..AuctionPokerAction::max_index() {
+        hasher.write_str(&format!("{:?}", AuctionPokerAction::from(index)));
+    }
+    for size in (0..=1_000_000).step_by(10) {
+        hasher.write_u64(AuctionPokerAction::Raise(DeciPercent(size)).index() as u64);
+    }
+    for amount in 0..=game.stack_size {
+        hasher.write_u64(AuctionPokerAction::Bid(Amount(amount)).index() as u64);
+    }
+
+    for round in [Round::PreFlop, Round::Auction, Round::Flop] {
+        for spec in context.schema(round).features {
+            hasher.write_str(spec.name);
+            hasher.write_str(&format!("{:?}", spec.kind));
+            hasher.write_u64(spec.min as u64);
+            hasher.write_u64(spec.max as u64);
+        }
+    }
+
+    // How infosets are packed, probed with the largest history of each round
+    for round in [Round::PreFlop, Round::Auction, Round::Flop] {
+        let schema = context.schema(round);
+        let largest = History(schema.features.iter().map(|spec| spec.max).collect());
+        hasher.write_u64(largest.into_condensed());
+    }
+
+    for constant in [game.stack_size, game.max_pot(), game.big_blind, game.little_blind] {
+        hasher.write_u64(constant as u64);
+    }
+    hasher.write_u64(game.aggression_limit as u64);
+    hasher.finish()
+}
+
+/// The raises a player may make, as what their pip is raised *to*
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RaiseInterval {
+    pub min: u32,
+    pub max: u32,
+    /// Going all in (or putting the opponent all in) is the only raise,
+    /// for less than a full raise
+    pub all_in_only: bool,
+}
+
+impl RaiseInterval {
+    pub fn contains(&self, to: u32) -> bool {
+        self.min <= to && to <= self.max
+    }
+}
+
+/// The legal raises of `player_num` given both pips and stacks and how far
+/// the last raise of the round went over the pip it raised (None if nobody
+/// raised yet), None if they can't raise at all. The aggression limit
+/// isn't part of this.
+///
+/// A raise is at least the last raise on top of the opponent's pip (a big
+/// blind if nobody raised), and no more than either player has behind (see
+/// variant rules). When that leaves no room for a full raise, going as far
+/// as the shorter stack allows is still a raise, as long as it is more
+/// than a call: one player or the other ends up all in
+pub fn raise_interval(pips: [u32; 2], stacks: [u32; 2], last_raise: Option<u32>, player_num: usize) -> Option<RaiseInterval> {
+    let opponent = player_num ^ 1;
+    let min = last_raise.unwrap_or(BIG_BLIND) + pips[opponent];
+    let max = (stacks[player_num] + pips[player_num]).min(stacks[opponent] + pips[opponent]);
+    if max <= pips[opponent] {
+        return None;
+    }
+    match min <= max {
+        true => Some(RaiseInterval { min, max, all_in_only: false }),
+        false => Some(RaiseInterval { min: max, max, all_in_only: true }),
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct AuctionPokerState {
-    card_bits: u64,
+    dealt: Deck,
     bids: [Option<u32>; 2],
     player_hands: [Hand; 2],
-    community_cards: Vec<Card>,
+    community_cards: Deck,
     pot: u32,
     pips: [u32; 2], // Amount of money each player has put into the pot per betting round
     stacks: [u32; 2],
-    raise: Option<u32>, // Cost of the last raise
+    raise: Option<u32>, // How far the last raise went over the opponent's pip
     active_player: ActivePlayer<AuctionPokerAction>,
     winner: Option<Winner>, // Winner of a bid
     cached_ev: [[Option<f32>; 2]; 5],
     aggression : usize,
+    /// Every change of the chips, see implementations::audit
+    audit: Option<Box<PotAudit>>,
+    /// The rules of the game, see State::with_context
+    context: Arc<Context>,
 }
 
 impl AuctionPokerState {
+    /// Result of the auction, None until both bids are resolved
+    pub fn winner(&self) -> Option<Winner> {
+        self.winner.clone()
+    }
+
+    pub fn pot(&self) -> u32 {
+        self.pot
+    }
+
+    pub fn stacks(&self) -> [u32; 2] {
+        self.stacks
+    }
+
+    pub fn pips(&self) -> [u32; 2] {
+        self.pips
+    }
+
+    /// Records every change of the chips from here on, see
+    /// implementations::audit
+    pub fn with_audit(self) -> Self {
+        let audit = PotAudit::new(self.context.game.stack_size, self.pot, self.stacks, self.pips);
+        AuctionPokerState {
+            audit: Some(Box::new(audit)),
+            ..self
+        }
+    }
+
+    pub fn audit(&self) -> Option<&PotAudit> {
+        self.audit.as_deref()
+    }
+
+    /// Panics if chips were made or lost, with the audit trail if there is
+    /// one. `audited` is the action that just moved chips, it is recorded
+    /// first so that the trail ends with it
+    fn assert_chips(&mut self, audited: &Option<AuctionPokerAction>) {
+        if let (Some(audit), Some(action)) = (self.audit.as_mut(), audited) {
+            audit.record(action, self.pot, self.stacks, self.pips);
+        }
+        let total = self.stacks[0] + self.stacks[1] + self.pot;
+        let max_pot = self.context.game.max_pot();
+        if total == max_pot {
+            return;
+        }
+        match &self.audit {
+            Some(audit) => panic!(
+                "{} chips in play instead of {}: {}\n{}",
+                total,
+                max_pot,
+                audit.verify().err().unwrap_or_default(),
+                audit
+            ),
+            None => panic!("{} chips in play instead of {} (GTCOGS_AUDIT=1 tells where)", total, max_pot),
+        }
+    }
+
+    /// assert_chips, in debug builds or when auditing
+    fn debug_assert_chips(&mut self, audited: &Option<AuctionPokerAction>) {
+        if cfg!(debug_assertions) || self.audit.is_some() {
+            self.assert_chips(audited);
+        }
+    }
+
+    /// Raises so far this round, no more are allowed at the aggression
+    /// limit of the context
+    pub fn aggression(&self) -> usize {
+        self.aggression
+    }
+
+    /// The rules this state plays by
+    pub fn context(&self) -> &Arc<Context> {
+        &self.context
+    }
+
+    /// The raises `player_num` may make now, see raise_interval
+    pub fn raise_interval(&self, player_num: usize) -> Option<RaiseInterval> {
+        let last_raise = self.raise.or(Some(self.context.game.big_blind));
+        raise_interval(self.pips, self.stacks, last_raise, player_num)
+    }
+
+    pub fn hand(&self, player_num: usize) -> &Hand {
+        &self.player_hands[player_num]
+    }
+
+    pub fn community_cards(&self) -> Deck {
+        self.community_cards
+    }
+
+    /// Cards dealt so far, to players or to the board
+    pub fn dealt(&self) -> Deck {
+        self.dealt
+    }
+
+    /// Replaces one of a player's hole cards with a card nobody has seen.
+    /// Used to repair hidden cards we had to guess once the real deck
+    /// tells us otherwise (e.g. the guess turns up on the board)
+    pub fn swap_hole_card(&mut self, player_num: usize, from: Card, to: Card) {
+        assert!(self.player_hands[player_num].cards.contains(from), "{:?} is not in the hand", from);
+        assert!(!self.dealt.contains(to), "{:?} has already been dealt", to);
+        self.player_hands[player_num].cards.remove(from);
+        self.player_hands[player_num].cards.insert(to);
+        self.dealt.remove(from);
+        self.dealt.insert(to);
+        self.cached_ev = [[None; 2]; 5];
+    }
+
     fn current_betting_round(&self) -> Round {
         match self.community_cards.len() {
             0 => Round::PreFlop,
@@ -665,50 +544,50 @@ impl AuctionPokerState {
         }
     }
     fn pre_bid_observations(&self) -> Vec<Observation<AuctionPokerAction>> {
-        let community_cards: Vec<u8> = self
-            .community_cards
-            .iter()
-            .map(|x| x.to_usize().unwrap() as u8)
-            .collect();
+        let community_cards = self.community_cards.as_u8();
+        let iterations = self.context.game.ev_iterations;
+
         let ranker = HandRanker::new();
-        let iterations = EV_ITERATIONS;
 
         // Calculate consequences if player 0 lost or
         // won the upcoming bid on the flop
-        let hand = self.player_hands[0].cards();
-        let hand: Vec<u8> = hand.iter().map(|x| x.to_usize().unwrap() as u8).collect();
-        let ev_win0 = ranker.rollout_bid_win(&hand, &community_cards, iterations);
-        let ev_loss0 = ranker.rollout_bid_loss(&hand, &community_cards, iterations);
+        let hand = self.player_hands[0].as_u8();
+        let value0 = BidValue::rollout(&ranker, &hand, &community_cards, iterations);
 
         // And the same for player 1
-        let hand = self.player_hands[1].cards();
-        let hand: Vec<u8> = hand.iter().map(|x| x.to_usize().unwrap() as u8).collect();
-        let ev_win1 = ranker.rollout_bid_win(&hand, &community_cards, iterations);
-        let ev_loss1 = ranker.rollout_bid_loss(&hand, &community_cards, iterations);
+        let hand = self.player_hands[1].as_u8();
+        let value1 = BidValue::rollout(&ranker, &hand, &community_cards, iterations);
 
         // ALWAYS truncate, it would be very bad
         // to think that we have the nuts when we don't
-        let ev_win0 = (ev_win0 * 30.0) as u16;
-        let ev_win1 = (ev_win1 * 30.0) as u16;
-        let ev_loss0 = (ev_loss0 * 30.0) as u16;
-        let ev_loss1 = (ev_loss1 * 30.0) as u16;
-
-        let pot = self.pot as f32 / MAX_POT as f32;
+        let ev_win0 = (value0.win * 30.0) as u16;
+        let ev_win1 = (value1.win * 30.0) as u16;
+        let ev_loss0 = (value0.loss * 30.0) as u16;
+        let ev_loss1 = (value1.loss * 30.0) as u16;
+        let win_value0 = (value0.marginal().max(0.0) * 30.0) as u16;
+        let win_value1 = (value1.marginal().max(0.0) * 30.0) as u16;
+
+        let pot = self.pot as f32 / self.context.game.max_pot() as f32;
         let pot = (pot * 20.0) as u8;
 
         let p0_features = vec![
             Feature::Order(Round::Auction),
             Feature::EV(ev_loss0),
             Feature::EV(ev_win0),
+            Feature::WinValue(win_value0),
             Feature::Pot(pot),
         ];
         let p1_features = vec![
             Feature::Order(Round::Auction),
             Feature::EV(ev_loss1),
             Feature::EV(ev_win1),
+            Feature::WinValue(win_value1),
             Feature::Pot(pot),
         ];
 
+        debug_assert_eq!(validate_features(&p0_features), Ok(()));
+        debug_assert_eq!(validate_features(&p1_features), Ok(()));
+
         vec![
             Observation::Shared(Information::Features(p0_features), vec![0]),
             Observation::Shared(Information::Features(p1_features), vec![1]),
@@ -748,58 +627,28 @@ impl AuctionPokerState {
             return ev;
         }
 
-        let ranker = HandRanker::new();
-        let iterations = EV_ITERATIONS;
-
-        let hand = self.player_hands[player_num].cards();
-
-        let hand: Vec<u8> = hand.iter().map(|x| x.to_usize().unwrap() as u8).collect();
-        let community_cards: Vec<u8> = self
-            .community_cards
-            .iter()
-            .map(|x| x.to_usize().unwrap() as u8)
-            .collect();
+        let street = match StreetRound::from_round(round) {
+            Some(street) => street,
+            None => panic!("Cannot evaluate ev on this round"),
+        };
+        let outcome = match self.winner {
+            Some(Winner::Player(winner_num)) if winner_num == player_num => AuctionOutcome::Won,
+            Some(Winner::Player(_)) => AuctionOutcome::Lost,
+            Some(Winner::Tie) => AuctionOutcome::Tie,
+            None => panic!("Winner was not set after auction"),
+        };
 
         const REDUCE: u32 = 2;
         // Note: The reason we divide by REDUCE on the river is
         // because accuracy can be sacrificed for speed
         // (fewer card possibilities to sample from)
-        let ev = match self.winner {
-            Some(Winner::Player(winner_num)) if winner_num == player_num => {
-                let ev_won = match round {
-                    Round::Flop => ranker.rollout_flop_won(&hand, &community_cards, iterations),
-                    Round::Turn => ranker.rollout_turn_won(&hand, &community_cards, iterations),
-                    Round::River => {
-                        ranker.rollout_river_won(&hand, &community_cards, iterations / REDUCE)
-                    }
-                    _ => panic!("Cannot evaluate ev on this round"),
-                };
-                ev_won
-            }
-            Some(Winner::Player(_)) => {
-                let ev_lost = match round {
-                    Round::Flop => ranker.rollout_flop_lost(&hand, &community_cards, iterations),
-                    Round::Turn => ranker.rollout_turn_lost(&hand, &community_cards, iterations),
-                    Round::River => {
-                        ranker.rollout_river_lost(&hand, &community_cards, iterations / REDUCE)
-                    }
-                    _ => panic!("Cannot evaluate ev on this round"),
-                };
-                ev_lost
-            }
-            Some(Winner::Tie) => {
-                let ev_tie = match round {
-                    Round::Flop => ranker.rollout_flop_tie(&hand, &community_cards, iterations),
-                    Round::Turn => ranker.rollout_turn_tie(&hand, &community_cards, iterations),
-                    Round::River => {
-                        ranker.rollout_river_tie(&hand, &community_cards, iterations / REDUCE)
-                    }
-                    _ => panic!("Cannot evaluate ev on this round"),
-                };
-                ev_tie
-            }
-            None => panic!("Winner was not set after auction"),
+        let iterations = match street {
+            StreetRound::River => self.context.game.ev_iterations / REDUCE,
+            _ => self.context.game.ev_iterations,
         };
+        let hand = self.player_hands[player_num].as_u8();
+        let community_cards = self.community_cards.as_u8();
+        let ev = HandRanker::new().rollout(RolloutKind::Street(street, outcome), &hand, &community_cards, iterations);
 
         let ev = ev as f32;
         self.cached_ev[round_index][player_num] = Some(ev);
@@ -817,12 +666,12 @@ impl AuctionPokerState {
             false => 1,
         };
 
-        let mut cards = Vec::new();
-        for i in 0..52 {
-            if self.card_bits & (1 << i) == 0 {
-                cards.push(AuctionPokerAction::DealHole(i, player_num));
-            }
-        }
+        let cards: Vec<_> = self
+            .dealt
+            .complement()
+            .iter()
+            .map(|card| AuctionPokerAction::DealHole(card.as_u8() as CardIndex, player_num))
+            .collect();
 
         ActivePlayer::Chance(Categorical::uniform(cards))
     }
@@ -846,12 +695,12 @@ impl AuctionPokerState {
     }
 
     fn deal(&self) -> ActivePlayer<AuctionPokerAction> {
-        let mut cards = Vec::new();
-        for i in 0..52 {
-            if self.card_bits & (1 << i) == 0 {
-                cards.push(AuctionPokerAction::DealCommunity(i));
-            }
-        }
+        let cards: Vec<_> = self
+            .dealt
+            .complement()
+            .iter()
+            .map(|card| AuctionPokerAction::DealCommunity(card.as_u8() as CardIndex))
+            .collect();
 
         ActivePlayer::Chance(Categorical::uniform(cards))
     }
@@ -874,33 +723,12 @@ impl AuctionPokerState {
     }
 
     fn betting_round(&self, player_num: usize) -> ActivePlayer<AuctionPokerAction> {
-        // Amount needed to bet/raise instead of call
-        // this represents the total amount of money needed
-        let min_raise = match self.raise {
-            Some(raise) => raise + self.pips[player_num ^ 1],
-            None => BIG_BLIND + self.pips[player_num ^ 1],
-        };
-
         let mut actions = Vec::new();
-
-        // See variant rules: cannot raise more than either player's stack + pip
-        let max_raise = (self.stacks[player_num] + self.pips[player_num]).min(self.stacks[player_num ^ 1] + self.pips[player_num ^ 1]);
-
-        for i in min_raise..=max_raise {
-            let raise_percent = Amount(i).to_percent(self.pot);
-            actions.push(AuctionPokerAction::Raise(DeciPercent(raise_percent)));
-        }
-
-        // See poker rules:
-        // even if the maximum raise is lower than the minimum raise,
-        // the player can still go all in
-        let current_stack = self.stacks[player_num];
-        if current_stack > 0
-            && actions.len() == 0
-            && self.stacks[player_num] <= self.stacks[player_num ^ 1]
-        {
-            let raise_percent = Amount(current_stack + self.pips[player_num]).to_percent(self.pot);
-            actions.push(AuctionPokerAction::Raise(DeciPercent(raise_percent)));
+        if let Some(interval) = self.raise_interval(player_num) {
+            for i in interval.min..=interval.max {
+                let raise_percent = Amount(i).to_percent(self.pot);
+                actions.push(AuctionPokerAction::Raise(DeciPercent(raise_percent)));
+            }
         }
 
         if self.pips[player_num] == self.pips[player_num ^ 1] {
@@ -913,7 +741,7 @@ impl AuctionPokerState {
             actions.push(AuctionPokerAction::Fold);
         }
 
-        if self.aggression == AGGRESSION_LIMIT {
+        if self.aggression >= self.context.game.aggression_limit {
             actions  = actions.into_iter().filter(|action| !matches!(action ,AuctionPokerAction::Raise(_))).collect();
         }
         ActivePlayer::Player(player_num as u32, actions)
@@ -948,65 +776,35 @@ impl AuctionPokerState {
         }
     }
 
+    /// What each player has put in the pot so far
+    pub fn ledger(&self) -> Ledger {
+        let ledger = Ledger::from_stacks(self.context.game.stack_size, self.stacks);
+        debug_assert_eq!(ledger.pot(), self.pot, "Pot does not match the stacks");
+        ledger
+    }
+
     /// One of the two players folded
     fn folded(&self, player_num: usize) -> ActivePlayer<AuctionPokerAction> {
-        let contribution = STACK_SIZE - self.stacks[player_num];
-        let delta = contribution as f32;
-        match player_num {
-            0 => ActivePlayer::Terminal(vec![-delta, delta]),
-            1 => ActivePlayer::Terminal(vec![delta, -delta]),
-            _ => panic!("Invalid player number"),
-        }
+        ActivePlayer::Terminal(self.ledger().utilities(Award::To(player_num ^ 1)))
     }
 
     /// The game is over, determine the winner
     fn showdown(&self) -> ActivePlayer<AuctionPokerAction> {
-        let mut player0 = self.player_hands[0].clone();
-        let mut player1 = self.player_hands[1].clone();
-
-        for card in &self.community_cards {
-            player0.add_card(card.clone());
-            player1.add_card(card.clone());
-        }
-
-        let player0_hand_len = player0.len();
-        let player1_hand_len = player1.len();
+        let board = self.community_cards.as_u8();
 
         let hand_ranker = HandRanker::new();
+        let ordering = hand_ranker.compare_showdown(
+            &self.player_hands[0].as_u8(),
+            &self.player_hands[1].as_u8(),
+            &board,
+        );
 
-        let player1_rank = match player1_hand_len {
-            8 => hand_ranker.rank8(&player1.as_u8()),
-            7 => hand_ranker.rank7(&player1.as_u8()),
-            _ => panic!("Invalid hand + community length"),
-        };
-
-        let player0_rank = match player0_hand_len {
-            8 => hand_ranker.rank8(&player0.as_u8()),
-            7 => hand_ranker.rank7(&player0.as_u8()),
-            _ => panic!("Invalid hand + community length"),
-        };
-
-        let contribution0 = STACK_SIZE - self.stacks[0];
-        let contribution1 = STACK_SIZE - self.stacks[1];
-
-        let contribution0 = contribution0 as f32;
-        let contribution1 = contribution1 as f32;
-
-        // See piazza: extra chip awarded to BB in an odd pot with a tie (BB always
-        // second to play)
-        let extra_chip = (self.pot % 2) as f32;
-        let half_pot = (self.pot as f32 - extra_chip) / 2.0;
-
-        let deltas = match player0_rank.cmp(&player1_rank) {
-            Ordering::Greater => vec![contribution1, -contribution1],
-            Ordering::Less => vec![-contribution0, contribution0],
-            Ordering::Equal => vec![
-                contribution0 - half_pot,
-                contribution1 - half_pot + extra_chip,
-            ],
+        let award = match ordering {
+            Ordering::Greater => Award::To(0),
+            Ordering::Less => Award::To(1),
+            Ordering::Equal => Award::Split,
         };
-
-        ActivePlayer::Terminal(deltas)
+        ActivePlayer::Terminal(self.ledger().utilities(award))
     }
 
     fn new_pot_after(&self, action: &AuctionPokerAction) -> u32 {
@@ -1032,24 +830,44 @@ impl AuctionPokerState {
 }
 
 impl State<AuctionPokerAction> for AuctionPokerState {
+    /// Nobody can lose more than their stack
+    fn utility_bounds(&self) -> Option<UtilityBounds> {
+        Some(UtilityBounds::zero_sum(Chips(self.context.game.stack_size as f32).utility()))
+    }
+
     fn new() -> Self {
-        AuctionPokerState {
-            card_bits: 0,
+        AuctionPokerState::with_context(&default_context())
+    }
+
+    fn abstraction_hash(&self) -> u64 {
+        self.context.abstraction_hash()
+    }
+
+    fn with_context(context: &Arc<Context>) -> Self {
+        let game = &context.game;
+        let state = AuctionPokerState {
+            dealt: Deck::empty(),
             bids: [None, None],
             player_hands: [Hand::new(), Hand::new()],
-            pot: LITTLE_BLIND + BIG_BLIND,
-            community_cards: Vec::new(),
-            stacks: [400 - LITTLE_BLIND, 400 - BIG_BLIND],
-            pips: [1, 2],
-            raise: Some(2),
+            pot: game.little_blind + game.big_blind,
+            community_cards: Deck::empty(),
+            stacks: [game.stack_size - game.little_blind, game.stack_size - game.big_blind],
+            pips: [game.little_blind, game.big_blind],
+            raise: Some(game.big_blind),
             active_player: AuctionPokerState::initial_node(),
             winner: None,
             cached_ev: [[None, None]; 5],
             aggression : 0,
+            audit: None,
+            context: context.clone(),
+        };
+        match audit_by_default() {
+            true => state.with_audit(),
+            false => state,
         }
     }
 
-    fn get_observations_after(
+    fn observe_before(
         &mut self,
         action: &AuctionPokerAction,
     ) -> Vec<Observation<AuctionPokerAction>> {
@@ -1096,12 +914,10 @@ impl State<AuctionPokerAction> for AuctionPokerState {
                 // PlayerActionEnd
 
                 let pot = self.pot;
-                let pot = pot as f32 / MAX_POT as f32;
+                let pot = pot as f32 / self.context.game.max_pot() as f32;
                 let scaled_pot = (pot * 100.0) as u8;
-                let stacks = [
-                    self.stacks[0] as f32 / STACK_SIZE as f32,
-                    self.stacks[1] as f32 / STACK_SIZE as f32,
-                ];
+                let stack_size = self.context.game.stack_size as f32;
+                let stacks = [self.stacks[0] as f32 / stack_size, self.stacks[1] as f32 / stack_size];
                 let scaled_stacks = [(stacks[0] * 30.0) as u8, (stacks[1] * 30.0) as u8];
 
                 let pot_and_stacks = [
@@ -1116,6 +932,11 @@ impl State<AuctionPokerAction> for AuctionPokerState {
 
                 features0.extend(pot_and_stacks.clone());
                 features1.extend(pot_and_stacks);
+                if !matches!(round, Round::PreFlop) {
+                    let schema = self.context.schema(round.clone());
+                    debug_assert_eq!(schema.validate(&features0), Ok(()));
+                    debug_assert_eq!(schema.validate(&features1), Ok(()));
+                }
 
                 let features1 = Information::Features(features1);
                 let features0 = Information::Features(features0);
@@ -1130,6 +951,8 @@ impl State<AuctionPokerAction> for AuctionPokerState {
                         features1.push(Feature::Aggression(self.aggression));
                         features0.push(Feature::Pot(scaled_pot));
                         features1.push(Feature::Pot(scaled_pot));
+                        debug_assert_eq!(validate_features(&features0), Ok(()));
+                        debug_assert_eq!(validate_features(&features1), Ok(()));
 
                         let features0 = Information::Features(features0);
                         let features1 = Information::Features(features1);
@@ -1152,7 +975,7 @@ impl State<AuctionPokerAction> for AuctionPokerState {
 
             AuctionPokerAction::BettingRoundEnd => {
                 // Sanity check
-                debug_assert!(self.pot + self.stacks[0] + self.stacks[1] == MAX_POT);
+                debug_assert!(self.pot + self.stacks[0] + self.stacks[1] == self.context.game.max_pot());
                 // TODO: I don't think there's anything to be done here but may be wrong
                 vec![Observation::Public(Information::Discard)]
             }
@@ -1164,6 +987,8 @@ impl State<AuctionPokerAction> for AuctionPokerState {
     }
 
     fn update(&mut self, action: AuctionPokerAction) {
+        // What the audit records the chips after
+        let audited = self.audit.as_ref().map(|_| action.clone());
         match action {
             AuctionPokerAction::Fold => {
                 let player_num = self.active_player.player_num() as usize;
@@ -1183,7 +1008,7 @@ impl State<AuctionPokerAction> for AuctionPokerState {
                 self.raise = None;
 
                 // Sanity check pot amounts
-                debug_assert_eq!(self.stacks[0] + self.stacks[1] + self.pot, 2 * STACK_SIZE);
+                self.debug_assert_chips(&audited);
 
                 self.active_player = self.betting_round_end();
             }
@@ -1202,12 +1027,12 @@ impl State<AuctionPokerAction> for AuctionPokerState {
                     }
                     _ => panic!("Cannot check during this round!"),
                 }
-                debug_assert_eq!(self.stacks[0] + self.stacks[1] + self.pot, 2 * STACK_SIZE);
+                self.debug_assert_chips(&audited);
             }
             AuctionPokerAction::DealHole(card_index, player_num) => {
                 let card = Card::from_index(card_index);
                 self.player_hands[player_num].add_card(card);
-                self.card_bits |= 1 << card_index;
+                self.dealt.insert(Card::from_index(card_index));
                 if self.needs_hole_cards() {
                     self.active_player = self.hole_card_dealer();
                 } else {
@@ -1217,8 +1042,8 @@ impl State<AuctionPokerAction> for AuctionPokerState {
             }
 
             AuctionPokerAction::DealCommunity(card_index) => {
-                self.community_cards.push(Card::from_index(card_index));
-                self.card_bits |= 1 << card_index;
+                self.community_cards.insert(Card::from_index(card_index));
+                self.dealt.insert(Card::from_index(card_index));
                 let street = self.community_cards.len();
                 let bidding_round_over = self.bids[1].is_some();
                 self.active_player = match (street, bidding_round_over) {
@@ -1241,15 +1066,12 @@ impl State<AuctionPokerAction> for AuctionPokerState {
                 self.pips[player_num] += cost;
                 self.stacks[player_num] -= cost;
 
-                // Opponent bet something - so this is a raise
-                if self.pips[player_num ^ 1] > 0 {
-                    self.raise = Some(cost);
-                } else {
-                    self.raise = None;
-                }
+                // What the next raise has to add on top of this one, a
+                // bet into an empty pip counts in full
+                self.raise = Some(amount - self.pips[player_num ^ 1]);
 
                 // Sanity check pot amounts
-                debug_assert_eq!(self.stacks[0] + self.stacks[1] + self.pot, 2 * STACK_SIZE);
+                self.debug_assert_chips(&audited);
 
                 self.aggression += 1;
                 // End the action, but not the round
@@ -1284,7 +1106,7 @@ impl State<AuctionPokerAction> for AuctionPokerState {
                 self.pot = self.new_pot_after(&AuctionPokerAction::Auction(winner));
 
                 // Sanity check pot amounts
-                debug_assert_eq!(self.stacks[0] + self.stacks[1] + self.pot, 2 * STACK_SIZE);
+                self.debug_assert_chips(&audited);
 
                 // Always needs to deal hole cards after an auction
                 self.active_player = self.hole_card_dealer();
@@ -1313,7 +1135,7 @@ impl State<AuctionPokerAction> for AuctionPokerState {
                 self.raise = None;
                 self.pips = [0, 0];
                 self.active_player = self.next_dealer();
-                assert_eq!(self.stacks[0] + self.stacks[1] + self.pot, 2 * STACK_SIZE);
+                self.assert_chips(&audited);
             }
 
             AuctionPokerAction::AuctionStart => {
@@ -1330,6 +1152,71 @@ impl State<AuctionPokerAction> for AuctionPokerState {
 mod tests {
     use super::*;
 
+    /// The raises the active player is offered, in chips
+    fn raise_amounts(state: &AuctionPokerState) -> Vec<u32> {
+        state
+            .active_player()
+            .actions()
+            .iter()
+            .filter_map(|action| match action {
+                AuctionPokerAction::Raise(size) => Some(size.to_amount(state.pot())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_action_indices_round_trip() {
+        assert_eq!(check_index_round_trip::<AuctionPokerAction>(), Ok(()));
+        assert_eq!(AuctionPokerAction::from(FIRST_BID), AuctionPokerAction::Bid(Amount(0)));
+        assert_eq!(AuctionPokerAction::from(FIRST_BID - 1).index(), FIRST_BID - 1);
+    }
+
+    #[test]
+    fn test_bid_buckets() {
+        assert_eq!(check_bid_buckets(&DEFAULT_BID_BUCKETS), Ok(()));
+        assert_eq!(bid_buckets().len() as ActionIndex, AuctionPokerAction::max_index() - FIRST_BID);
+        assert!(check_bid_buckets(&[0, 50, 40, STACK_SIZE]).unwrap_err().contains("increase"));
+        assert!(check_bid_buckets(&[0, 50, 100]).unwrap_err().contains("whole stack"));
+        assert!(check_bid_buckets(&[]).is_err());
+        assert!(check_bid_buckets(&(0..=MAX_BID_BUCKETS as u32).map(|bid| bid * 10 + STACK_SIZE).collect::<Vec<_>>()).is_err());
+
+        // Evenly spread bids get even buckets, bunched up ones fewer
+        let bids: Vec<u32> = (0..STACK_SIZE).collect();
+        assert_eq!(quantile_bid_buckets(&bids, 4), [100, 200, 300, STACK_SIZE]);
+        let bunched = [vec![0; 50], vec![20; 50], vec![350]].concat();
+        let buckets = quantile_bid_buckets(&bunched, 8);
+        assert_eq!(buckets, [0, 20, STACK_SIZE]);
+        assert_eq!(check_bid_buckets(&buckets), Ok(()));
+
+        // Setting the buckets in use again changes nothing
+        assert_eq!(set_bid_buckets(bid_buckets()), Ok(()));
+        assert!(set_bid_buckets(&buckets).is_err());
+    }
+
+    #[test]
+    fn test_game_objects_serialize() {
+        let actions = vec![
+            AuctionPokerAction::Raise(DeciPercent(500)),
+            AuctionPokerAction::Bid(Amount(40)),
+            AuctionPokerAction::DealHole(12, 1),
+            AuctionPokerAction::Auction(Winner::Tie),
+        ];
+        let json = serde_json::to_string(&actions).unwrap();
+        assert_eq!(serde_json::from_str::<Vec<AuctionPokerAction>>(&json).unwrap(), actions);
+
+        let mut state = AuctionPokerState::new();
+        let chance = state.active_player();
+        let json = serde_json::to_string(&chance).unwrap();
+        assert_eq!(serde_json::from_str::<ActivePlayer<AuctionPokerAction>>(&json).unwrap(), chance);
+        state.update(chance.actions()[0].clone());
+
+        let card = Card::from_index(51);
+        assert_eq!(serde_json::from_str::<Card>(&serde_json::to_string(&card).unwrap()).unwrap(), card);
+        let hand = state.hand(0);
+        assert_eq!(&serde_json::from_str::<Hand>(&serde_json::to_string(hand).unwrap()).unwrap(), hand);
+    }
+
     #[test]
     fn test_chance_transition() {
         let mut state = AuctionPokerState::new();
@@ -1369,7 +1256,7 @@ mod tests {
             _ => panic!("Expected chance transition."),
         }
         // Glass box testing
-        assert_eq!(state.card_bits.count_ones(), 3);
+        assert_eq!(state.dealt.len(), 3);
     }
 
     #[test]
@@ -1567,6 +1454,47 @@ mod tests {
     }
 
 
+    #[test]
+    fn test_auction_features_carry_the_value_of_winning() {
+        // A straight and flush draw against a set, checked to the auction
+        let mut cards = vec!["9h", "8h", "2c", "2d", "Th", "7c", "2h"];
+        let mut calls = vec![AuctionPokerAction::Call, AuctionPokerAction::Check];
+        let mut state = AuctionPokerState::new();
+        loop {
+            match state.active_player() {
+                ActivePlayer::Chance(distribution) => {
+                    let card = Card::new(cards.remove(0)).as_u8() as CardIndex;
+                    let deal = distribution.items().iter().find(|deal| {
+                        matches!(deal, AuctionPokerAction::DealHole(c, _) | AuctionPokerAction::DealCommunity(c) if *c == card)
+                    });
+                    state.update(deal.unwrap().clone());
+                }
+                ActivePlayer::Marker(AuctionPokerAction::AuctionStart) => break,
+                ActivePlayer::Marker(marker) => state.update(marker),
+                ActivePlayer::Player(..) => state.update(calls.remove(0)),
+                x => panic!("Expected to reach the auction. Got {:?}", x),
+            }
+        }
+
+        let observations = state.pre_bid_observations();
+        let win_values: Vec<u16> = observations
+            .iter()
+            .map(|observation| match observation {
+                Observation::Shared(Information::Features(features), _) => features
+                    .iter()
+                    .find_map(|feature| match feature {
+                        Feature::WinValue(value) => Some(*value),
+                        _ => None,
+                    })
+                    .unwrap(),
+                x => panic!("Expected auction features. Got {:?}", x),
+            })
+            .collect();
+        // The draw has much more to gain from a third card than the set
+        assert!(win_values[0] >= 6, "{:?}", win_values);
+        assert!(win_values[1] < win_values[0], "{:?}", win_values);
+    }
+
     #[test]
     fn test_flop_check_check() {
         let mut state = AuctionPokerState::new();
@@ -2008,28 +1936,114 @@ mod tests {
         // pot is 104 with player 1 having 102 contributed
         // so player 1 stack is 400 - 102 = 298
         state.update(AuctionPokerAction::BettingRoundStart);
-        state.update(AuctionPokerAction::Raise(Amount(10)));
+        state.update(AuctionPokerAction::Raise(Amount(10))); // p1 bets 10
         state.update(AuctionPokerAction::PlayerActionEnd(1));
-        state.update(AuctionPokerAction::Raise(Amount(100))); // p1 raises 90 more
+        state.update(AuctionPokerAction::Raise(Amount(100))); // p0 raises 90 more
         state.update(AuctionPokerAction::PlayerActionEnd(0));
-        state.update(AuctionPokerAction::Raise(Amount(200))); // p0 raises 100 more
+        state.update(AuctionPokerAction::Raise(Amount(200))); // p1 raises 100 more
         state.update(AuctionPokerAction::PlayerActionEnd(1));
-        assert!(state
-            .active_player()
-            .actions()
-            .contains(&AuctionPokerAction::Raise(Amount(298)))); // p1 should be able to
-                                                              //// raise all in even though
-                                                              //// they don't have enough
-                                                              //// to match p0's raise
-        // BUT there should be no other raise sizes
-        assert!(!state
-            .active_player()
-            .actions()
-            .iter()
-            .filter(|x| matches!(x, AuctionPokerAction::Raise(_)))
-            .any(|x| x != &AuctionPokerAction::Raise(Amount(298))));
+        // p1 has only 98 behind, so p0 can only put them all in, even
+        // though that is less than a full raise
+        assert_eq!(raise_amounts(&state), vec![298]);
+        state.update(AuctionPokerAction::Raise(Amount(298)));
+        state.update(AuctionPokerAction::PlayerActionEnd(0));
+        assert!(raise_amounts(&state).is_empty());
+        assert!(state.active_player().actions().contains(&AuctionPokerAction::Call));
+    }
 
-        state.update(AuctionPokerAction::Raise(Amount(298))); 
+    #[test]
+    fn test_audit_follows_the_chips() {
+        let mut state = AuctionPokerState::new().with_audit();
+        for card in [0, 2, 3, 4] {
+            let player_num = if card < 3 { 0 } else { 1 };
+            state.update(AuctionPokerAction::DealHole(card, player_num));
+        }
+        state.update(AuctionPokerAction::BettingRoundStart);
+        state.update(AuctionPokerAction::Raise(Amount(10)));
+        state.update(AuctionPokerAction::PlayerActionEnd(0));
+        state.update(AuctionPokerAction::Call);
+        state.update(AuctionPokerAction::BettingRoundEnd);
+        for card in [5, 6, 7] {
+            state.update(AuctionPokerAction::DealCommunity(card));
+        }
+        state.update(AuctionPokerAction::AuctionStart);
+        state.update(AuctionPokerAction::Bid(Amount(20)));
+        state.update(AuctionPokerAction::Bid(Amount(5)));
+        state.update(AuctionPokerAction::Auction(Winner::Player(1)));
+
+        let audit = state.audit().unwrap();
+        assert_eq!(audit.verify(), Ok(()));
+        let actions: Vec<_> = audit.entries().iter().filter_map(|entry| entry.action.clone()).collect();
+        assert_eq!(
+            actions,
+            vec![
+                AuctionPokerAction::Raise(Amount(10)),
+                AuctionPokerAction::Call,
+                AuctionPokerAction::Auction(Winner::Player(1)),
+            ]
+        );
+        assert_eq!(audit.entries().last().unwrap().pot, state.pot());
+        assert!(AuctionPokerState::new().audit().is_none() || audit_by_default());
+    }
+
+    #[test]
+    fn test_raise_interval_boundaries() {
+        // Exactly enough for the min raise is a full raise, one chip less
+        // is all in for less
+        let full = raise_interval([0, 10], [18, 390], Some(8), 0);
+        assert_eq!(full, Some(RaiseInterval { min: 18, max: 18, all_in_only: false }));
+        let short = raise_interval([0, 10], [17, 390], Some(8), 0);
+        assert_eq!(short, Some(RaiseInterval { min: 17, max: 17, all_in_only: true }));
+        // Nobody raised yet, the min raise is a big blind
+        assert_eq!(raise_interval([0, 0], [100, 100], None, 1).map(|i| i.min), Some(BIG_BLIND));
+        // Putting a short opponent all in is a raise, even for less
+        let covered = raise_interval([0, 10], [17, 5], Some(8), 0);
+        assert_eq!(covered, Some(RaiseInterval { min: 15, max: 15, all_in_only: true }));
+        // All in already, or only enough to call
+        assert_eq!(raise_interval([0, 10], [0, 390], Some(8), 0), None);
+        assert_eq!(raise_interval([0, 10], [10, 390], Some(8), 0), None);
+        // The opponent can only cover so much
+        assert_eq!(raise_interval([0, 10], [390, 50], Some(8), 0).map(|i| i.max), Some(60));
+
+        for stack in 0..=12 {
+            for other_stack in 0..=12 {
+                for pip in 0..=6 {
+                    for other_pip in pip..=6 {
+                        for last_raise in [None, Some(1), Some(2), Some(4)] {
+                            for player_num in 0..2 {
+                                let mut pips = [pip, other_pip];
+                                let mut stacks = [stack, other_stack];
+                                if player_num == 1 {
+                                    pips.swap(0, 1);
+                                    stacks.swap(0, 1);
+                                }
+                                let interval = match raise_interval(pips, stacks, last_raise, player_num) {
+                                    Some(interval) => interval,
+                                    None => {
+                                        // Only if nobody can put in more than a call
+                                        assert!((stack + pip).min(other_stack + other_pip) <= other_pip);
+                                        continue;
+                                    }
+                                };
+                                assert!(interval.min <= interval.max);
+                                // More than a call, never more than either player has
+                                assert!(interval.min > other_pip);
+                                assert!(interval.max <= stack + pip);
+                                assert!(interval.max <= other_stack + other_pip);
+                                match interval.all_in_only {
+                                    true => {
+                                        let all_in = (stack + pip).min(other_stack + other_pip);
+                                        assert_eq!((interval.min, interval.max), (all_in, all_in));
+                                        assert!(interval.min < last_raise.unwrap_or(BIG_BLIND) + other_pip);
+                                    }
+                                    false => assert_eq!(interval.min, last_raise.unwrap_or(BIG_BLIND) + other_pip),
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
     }
 
     #[test]
@@ -2063,47 +2077,21 @@ mod tests {
         state.update(AuctionPokerAction::BettingRoundStart);
         state.update(AuctionPokerAction::Raise(Amount(10)));
         state.update(AuctionPokerAction::PlayerActionEnd(0));
-        // TODO: tests are wrong, should be DeciPercent
-        assert!(!state
-            .active_player()
-            .actions()
-            .iter()
-            .any(|x| matches!(x, AuctionPokerAction::Raise(Amount(17)))));
-        assert!(state
-            .active_player()
-            .actions()
-            .iter()
-            .any(|x| matches!(x, AuctionPokerAction::Raise(Amount(18)))));
+        // A raise has to add at least the last raise on top of the bet
+        let raises = raise_amounts(&state);
+        assert!(!raises.contains(&17));
+        assert_eq!(raises.first(), Some(&18));
         state.update(AuctionPokerAction::Raise(Amount(100)));
-        state.update(AuctionPokerAction::PlayerActionEnd(0));
-        assert!(!state
-            .active_player()
-            .actions()
-            .iter()
-            .any(|x| matches!(x, AuctionPokerAction::Raise(Amount(189)))));
-        assert!(state
-            .active_player()
-            .actions()
-            .iter()
-            .any(|x| matches!(x, AuctionPokerAction::Raise(Amount(190)))));
-        // Can still raise after raising
-        assert!(state
-            .active_player()
-            .actions()
-            .iter()
-            .any(|x| matches!(x, AuctionPokerAction::Raise(_))));
-        state.update(AuctionPokerAction::Raise(Amount(200)));
         state.update(AuctionPokerAction::PlayerActionEnd(1));
-        assert!(!state
-            .active_player()
-            .actions()
-            .iter()
-            .any(|x| matches!(x, AuctionPokerAction::Raise(Amount(299)))));
-        assert!(!state
-            .active_player()
-            .actions()
-            .iter()
-            .any(|x| matches!(x, AuctionPokerAction::Raise(Amount(300)))));
+        let raises = raise_amounts(&state);
+        assert!(!raises.contains(&189));
+        assert_eq!(raises.first(), Some(&190));
+        state.update(AuctionPokerAction::Raise(Amount(200)));
+        state.update(AuctionPokerAction::PlayerActionEnd(0));
+        // Nobody can put in more than the shorter stack
+        let raises = raise_amounts(&state);
+        assert_eq!(raises.first(), Some(&300));
+        assert_eq!(raises.last(), Some(&STACK_SIZE));
     }
 
     #[test]
@@ -2142,32 +2130,45 @@ mod tests {
         state.update(AuctionPokerAction::DealHole(4, 1));
         state.update(AuctionPokerAction::BettingRoundStart);
 
-        assert_eq!(AGGRESSION_LIMIT, 6, "Test is invalid");
+        for _ in 0..AGGRESSION_LIMIT {
+            let player_num = state.active_player().player_num();
+            let raises = raise_amounts(&state);
+            assert!(!raises.is_empty());
+            state.update(AuctionPokerAction::Raise(Amount(raises[0])));
+            state.update(AuctionPokerAction::PlayerActionEnd(player_num));
+        }
 
-        state.update(AuctionPokerAction::Raise(Amount(10)));
-        state.update(AuctionPokerAction::PlayerActionEnd(0));
-        state.update(AuctionPokerAction::Raise(Amount(20)));
-        state.update(AuctionPokerAction::PlayerActionEnd(1));
-        state.update(AuctionPokerAction::Raise(Amount(30)));
-        state.update(AuctionPokerAction::PlayerActionEnd(0));
-        state.update(AuctionPokerAction::Raise(Amount(40)));
-        state.update(AuctionPokerAction::PlayerActionEnd(1));
-        state.update(AuctionPokerAction::Raise(Amount(50)));
-        state.update(AuctionPokerAction::PlayerActionEnd(0));
-        assert!(state
-            .active_player()
-            .actions()
-            .iter()
-            .any(|x| matches!(x, AuctionPokerAction::Raise(_))));
-        state.update(AuctionPokerAction::Raise(Amount(60)));
-        state.update(AuctionPokerAction::PlayerActionEnd(1));
+        assert!(raise_amounts(&state).is_empty());
+        assert!(state.active_player().actions().contains(&AuctionPokerAction::Call));
+    }
 
-        assert!(!state
-            .active_player()
-            .actions()
-            .iter()
-            .any(|x| matches!(x, AuctionPokerAction::Raise(_))));
+    #[test]
+    fn test_no_all_in_raise_for_less_than_a_call() {
+        let mut state = AuctionPokerState::new();
+        state.update(AuctionPokerAction::DealHole(0, 0));
+        state.update(AuctionPokerAction::DealHole(2, 0));
+        state.update(AuctionPokerAction::DealHole(3, 1));
+        state.update(AuctionPokerAction::DealHole(4, 1));
+        state.update(AuctionPokerAction::BettingRoundStart);
+        let raises = |state: &AuctionPokerState| match state.betting_round(0) {
+            ActivePlayer::Player(0, actions) => {
+                actions.iter().filter(|action| matches!(action, AuctionPokerAction::Raise(_))).count()
+            }
+            x => panic!("Expected player 0 to act. Got {:?}", x),
+        };
+
+        // Facing 10 with 17 behind, going all in is still a raise
+        state.pips = [0, 10];
+        state.raise = Some(8);
+        state.stacks = [17, 390];
+        assert_eq!(raises(&state), 1);
 
+        // With only 5 behind it would be less than a call. Played hands
+        // never get here, raises stop at what the opponent covers
+        state.stacks = [5, 390];
+        assert_eq!(raises(&state), 0);
+        assert!(state.betting_round(0).actions().contains(&AuctionPokerAction::Call));
+        assert!(state.betting_round(0).actions().contains(&AuctionPokerAction::Fold));
     }
 
     #[test]
@@ -2206,6 +2207,25 @@ mod tests {
             .all(|x| !matches!(x, AuctionPokerAction::Raise(_))));
     }
 
+    #[test]
+    fn test_hand_order_is_canonical() {
+        let mut hand = Hand::new();
+        hand.expand();
+        hand.add_card(Card::new("Th"));
+        hand.add_card(Card::new("2c"));
+        hand.add_card(Card::new("As"));
+        assert_eq!(hand.to_string().unwrap(), "AsTh2c");
+
+        // The order cards are dealt in should not matter
+        let mut other = Hand::new();
+        other.expand();
+        other.add_card(Card::new("As"));
+        other.add_card(Card::new("2c"));
+        other.add_card(Card::new("Th"));
+        assert_eq!(hand.to_string(), other.to_string());
+        assert_eq!(hand.cards(), other.cards());
+    }
+
     #[test]
     fn test_card_coherence() {
         let card_str = "9d";
@@ -0,0 +1,132 @@
+/// Auction-only sub-game of auction poker. Every betting decision is made by
+/// a fixed rule, so the only decisions left for MCCFR are the bids. Training
+/// this game produces a specialized auction policy indexed by the pre-bid
+/// features, at a fraction of the cost of training the full game.
+///
+/// Example usage:
+///
+/// let mut mcp = MCCFRParallel::<AuctionPokerAction, AuctionSubgameState>::new(12, Some("auction_only"));
+/// mcp.run_iterations(100_000, 0.2);
+///
+use crate::game_logic::state::{ActivePlayer, State, UtilityBounds};
+use crate::game_logic::visibility::Observation;
+use crate::implementations::auction::*;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+/// Chooses the betting action for a player whose betting
+/// decisions are not being trained
+pub trait FixedBettingPolicy: Clone + Debug {
+    fn choose(state: &AuctionPokerState, actions: &[AuctionPokerAction]) -> AuctionPokerAction;
+}
+
+/// Never puts more money in than needed to see a showdown:
+/// checks when possible and calls otherwise
+#[derive(Clone, Debug)]
+pub struct PassivePolicy;
+
+impl FixedBettingPolicy for PassivePolicy {
+    fn choose(_: &AuctionPokerState, actions: &[AuctionPokerAction]) -> AuctionPokerAction {
+        if actions.contains(&AuctionPokerAction::Check) {
+            AuctionPokerAction::Check
+        } else if actions.contains(&AuctionPokerAction::Call) {
+            AuctionPokerAction::Call
+        } else {
+            panic!("Passive policy needs either check or call: {:?}", actions)
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AuctionSubgameState<P: FixedBettingPolicy = PassivePolicy> {
+    state: AuctionPokerState,
+    policy: PhantomData<P>,
+}
+
+impl<P: FixedBettingPolicy> AuctionSubgameState<P> {
+    pub fn inner(&self) -> &AuctionPokerState {
+        &self.state
+    }
+}
+
+impl<P: FixedBettingPolicy> State<AuctionPokerAction> for AuctionSubgameState<P> {
+    fn utility_bounds(&self) -> Option<UtilityBounds> {
+        self.state.utility_bounds()
+    }
+
+    fn new() -> Self {
+        AuctionSubgameState {
+            state: AuctionPokerState::new(),
+            policy: PhantomData,
+        }
+    }
+
+    fn observe_before(
+        &mut self,
+        action: &AuctionPokerAction,
+    ) -> Vec<Observation<AuctionPokerAction>> {
+        // Nobody makes a trained decision after the auction, so skip
+        // the (expensive) post-auction feature rollouts entirely
+        if self.state.winner().is_some() {
+            return vec![];
+        }
+        self.state.observe_before(action)
+    }
+
+    fn observe_after(
+        &mut self,
+        action: &AuctionPokerAction,
+    ) -> Vec<Observation<AuctionPokerAction>> {
+        self.state.observe_after(action)
+    }
+
+    fn active_player(&self) -> ActivePlayer<AuctionPokerAction> {
+        match self.state.active_player() {
+            ActivePlayer::Player(player_num, actions) => {
+                let is_bidding = actions
+                    .iter()
+                    .all(|action| matches!(action, AuctionPokerAction::Bid(_)));
+                if is_bidding {
+                    ActivePlayer::Player(player_num, actions)
+                } else {
+                    // Betting decisions are fixed, so present them as a marker
+                    ActivePlayer::Marker(P::choose(&self.state, &actions))
+                }
+            }
+            active_player => active_player,
+        }
+    }
+
+    fn update(&mut self, action: AuctionPokerAction) {
+        self.state.update(action);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_bids_are_decisions() {
+        let mut state = AuctionSubgameState::<PassivePolicy>::new();
+        let mut saw_bid = false;
+        loop {
+            match state.active_player() {
+                ActivePlayer::Terminal(utilities) => {
+                    assert_eq!(utilities.len(), 2);
+                    break;
+                }
+                ActivePlayer::Player(_, actions) => {
+                    assert!(actions
+                        .iter()
+                        .all(|action| matches!(action, AuctionPokerAction::Bid(_))));
+                    saw_bid = true;
+                    state.update(actions[actions.len() / 2].clone());
+                }
+                ActivePlayer::Chance(dist) => state.update(dist.sample()),
+                ActivePlayer::Marker(action) => state.update(action),
+            }
+        }
+        assert!(saw_bid, "The auction should always be reached");
+    }
+}
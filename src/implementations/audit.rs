@@ -0,0 +1,150 @@
+/// An audit trail of the chips in an auction poker hand.
+///
+/// When auditing, AuctionPokerState records the pot, stacks and pips after
+/// every action that moved chips, together with that action. The trail can
+/// be replayed to reconstruct the pot one step at a time and check that
+/// every step is explained by its action, so a broken chip count names the
+/// action that broke it rather than whatever happened to assert next.
+///
+/// Auditing costs a clone of the trail with every clone of the state, so it
+/// is off unless asked for with AuctionPokerState::with_audit or, for whole
+/// training runs, GTCOGS_AUDIT=1
+use crate::implementations::auction::AuctionPokerAction;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// Whether GTCOGS_AUDIT asks for every new state to be audited
+pub fn audit_by_default() -> bool {
+    static AUDIT: OnceLock<bool> = OnceLock::new();
+    *AUDIT.get_or_init(|| std::env::var("GTCOGS_AUDIT").map(|value| value == "1").unwrap_or(false))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    /// None for the blinds, which are posted before any action
+    pub action: Option<AuctionPokerAction>,
+    pub pot: u32,
+    pub stacks: [u32; 2],
+    pub pips: [u32; 2],
+}
+
+impl fmt::Display for AuditEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let action = match &self.action {
+            Some(action) => format!("{:?}", action),
+            None => "Blinds".to_string(),
+        };
+        write!(f, "{}: pot {} stacks {:?} pips {:?}", action, self.pot, self.stacks, self.pips)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PotAudit {
+    /// What each player started the hand with
+    stack_size: u32,
+    entries: Vec<AuditEntry>,
+}
+
+impl PotAudit {
+    pub fn new(stack_size: u32, pot: u32, stacks: [u32; 2], pips: [u32; 2]) -> PotAudit {
+        PotAudit {
+            stack_size,
+            entries: vec![AuditEntry { action: None, pot, stacks, pips }],
+        }
+    }
+
+    /// Records the chips after `action`, if it moved any
+    pub fn record(&mut self, action: &AuctionPokerAction, pot: u32, stacks: [u32; 2], pips: [u32; 2]) {
+        let last = self.entries.last().unwrap();
+        if (last.pot, last.stacks, last.pips) == (pot, stacks, pips) {
+            return;
+        }
+        self.entries.push(AuditEntry {
+            action: Some(action.clone()),
+            pot,
+            stacks,
+            pips,
+        });
+    }
+
+    pub fn entries(&self) -> &[AuditEntry] {
+        &self.entries
+    }
+
+    /// Replays the trail, rebuilding the pot from what left the stacks.
+    /// Fails on the first step its action doesn't explain
+    pub fn verify(&self) -> Result<(), String> {
+        let first = &self.entries[0];
+        if first.pot + first.stacks[0] + first.stacks[1] != 2 * self.stack_size {
+            return Err(format!("The hand starts with the wrong chips, {}", first));
+        }
+        let mut pot = first.pot;
+        for (step, pair) in self.entries.windows(2).enumerate() {
+            let (before, after) = (&pair[0], &pair[1]);
+            let fail = |reason: &str| Err(format!("Step {} ({}): {}", step + 1, after, reason));
+            if after.stacks[0] > before.stacks[0] || after.stacks[1] > before.stacks[1] {
+                return fail("chips went back to a stack");
+            }
+            let paid = [before.stacks[0] - after.stacks[0], before.stacks[1] - after.stacks[1]];
+            pot += paid[0] + paid[1];
+            if pot != after.pot {
+                return fail(&format!("the pot should be {}", pot));
+            }
+            match &after.action {
+                Some(AuctionPokerAction::Raise(_)) => {
+                    let raised: Vec<usize> = (0..2).filter(|&player| paid[player] > 0).collect();
+                    let consistent = raised.len() == 1
+                        && after.pips[raised[0]] == before.pips[raised[0]] + paid[raised[0]]
+                        && after.pips[raised[0] ^ 1] == before.pips[raised[0] ^ 1];
+                    if !consistent {
+                        return fail("a raise moves one player's chips from their stack to their pip");
+                    }
+                }
+                Some(AuctionPokerAction::Call) => {
+                    let max_pip = before.pips[0].max(before.pips[1]);
+                    if paid != [max_pip - before.pips[0], max_pip - before.pips[1]] {
+                        return fail(&format!("a call of {:?} should pay the difference", before.pips));
+                    }
+                }
+                Some(AuctionPokerAction::Auction(_)) | Some(AuctionPokerAction::BettingRoundEnd) => {}
+                _ if paid != [0, 0] => return fail("chips moved on an action that moves none"),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PotAudit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (step, entry) in self.entries.iter().enumerate() {
+            writeln!(f, "{:>3} {}", step, entry)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::STACK_SIZE;
+    use crate::implementations::auction::RelativeSize::*;
+
+    #[test]
+    fn test_audit_finds_the_step_that_broke_the_pot() {
+        let mut audit = PotAudit::new(STACK_SIZE, 3, [STACK_SIZE - 1, STACK_SIZE - 2], [1, 2]);
+        audit.record(&AuctionPokerAction::Raise(Amount(10)), 12, [STACK_SIZE - 10, STACK_SIZE - 2], [10, 2]);
+        audit.record(&AuctionPokerAction::Call, 20, [STACK_SIZE - 10, STACK_SIZE - 10], [0, 0]);
+        // Nothing moved, nothing recorded
+        audit.record(&AuctionPokerAction::Check, 20, [STACK_SIZE - 10, STACK_SIZE - 10], [0, 0]);
+        assert_eq!(audit.entries().len(), 3);
+        assert_eq!(audit.verify(), Ok(()));
+
+        // A call that lost a chip on the way to the pot
+        let mut broken = audit.clone();
+        broken.record(&AuctionPokerAction::Raise(Amount(30)), 49, [STACK_SIZE - 10, STACK_SIZE - 40], [0, 30]);
+        broken.record(&AuctionPokerAction::Call, 79, [STACK_SIZE - 40, STACK_SIZE - 40], [0, 0]);
+        assert_eq!(broken.verify().unwrap_err().split(' ').take(2).collect::<Vec<_>>(), ["Step", "3"]);
+        assert!(broken.to_string().contains("Raise(Amount(30))"));
+    }
+}
@@ -0,0 +1,369 @@
+/// Compact card representations shared by the auction poker game and the
+/// hand evaluator. A card is a single byte `suit + value * 4` (the same index
+/// the evaluator library expects) and a set of cards is a 52-bit mask, so
+/// dealing, sorting and handing cards to the evaluator never allocates or
+/// goes through strings.
+use crate::game_logic::action::*;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+/// Suits are ordered only to break ties between cards of the same value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Suit {
+    Hearts,
+    Diamonds,
+    Clubs,
+    Spades,
+}
+
+impl Parsable for Suit {
+    fn to_string(&self) -> Option<String> {
+        match self {
+            Suit::Hearts => Some("h".to_string()),
+            Suit::Diamonds => Some("d".to_string()),
+            Suit::Clubs => Some("c".to_string()),
+            Suit::Spades => Some("s".to_string()),
+        }
+    }
+    fn to_usize(&self) -> Option<usize> {
+        None
+    }
+}
+
+
+/// Ordered by poker strength, Two is the lowest and Ace the highest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Value {
+    Two,
+    Three,
+    Four,
+    Five,
+    Six,
+    Seven,
+    Eight,
+    Nine,
+    Ten,
+    Jack,
+    Queen,
+    King,
+    Ace,
+}
+
+impl Parsable for Value {
+    fn to_string(&self) -> Option<String> {
+        let result = match self {
+            Value::Two => "2".to_string(),
+            Value::Three => "3".to_string(),
+            Value::Four => "4".to_string(),
+            Value::Five => "5".to_string(),
+            Value::Six => "6".to_string(),
+            Value::Seven => "7".to_string(),
+            Value::Eight => "8".to_string(),
+            Value::Nine => "9".to_string(),
+            Value::Ten => "T".to_string(),
+            Value::Jack => "J".to_string(),
+            Value::Queen => "Q".to_string(),
+            Value::King => "K".to_string(),
+            Value::Ace => "A".to_string(),
+        };
+        Some(result)
+    }
+    fn to_usize(&self) -> Option<usize> {
+        let value = match self {
+            Value::Ace => 0,
+            Value::King => 1,
+            Value::Queen => 2,
+            Value::Jack => 3,
+            Value::Ten => 4,
+            Value::Nine => 5,
+            Value::Eight => 6,
+            Value::Seven => 7,
+            Value::Six => 8,
+            Value::Five => 9,
+            Value::Four => 10,
+            Value::Three => 11,
+            Value::Two => 12,
+        };
+        Some(value)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(value: usize) -> Self {
+        match value {
+            0 => Value::Ace,
+            1 => Value::King,
+            2 => Value::Queen,
+            3 => Value::Jack,
+            4 => Value::Ten,
+            5 => Value::Nine,
+            6 => Value::Eight,
+            7 => Value::Seven,
+            8 => Value::Six,
+            9 => Value::Five,
+            10 => Value::Four,
+            11 => Value::Three,
+            12 => Value::Two,
+            _ => panic!("Invalid suit index"),
+        }
+    }
+}
+
+
+/// Suits in card index order
+pub const SUITS: [Suit; 4] = [Suit::Hearts, Suit::Diamonds, Suit::Clubs, Suit::Spades];
+
+/// Values in card index order (Aces have the lowest index)
+pub const VALUES: [Value; 13] = [
+    Value::Ace,
+    Value::King,
+    Value::Queen,
+    Value::Jack,
+    Value::Ten,
+    Value::Nine,
+    Value::Eight,
+    Value::Seven,
+    Value::Six,
+    Value::Five,
+    Value::Four,
+    Value::Three,
+    Value::Two,
+];
+
+pub const SUIT_CHARS: [char; 4] = ['h', 'd', 'c', 's'];
+pub const VALUE_CHARS: [char; 13] = [
+    'A', 'K', 'Q', 'J', 'T', '9', '8', '7', '6', '5', '4', '3', '2',
+];
+
+pub const DECK_SIZE: usize = 52;
+
+pub type CardIndex = usize;
+
+/// A single card stored as its index `suit + value * 4`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Card(pub u8);
+
+impl Card {
+    pub const fn from_index(index: CardIndex) -> Self {
+        debug_assert!(index < DECK_SIZE);
+        Card(index as u8)
+    }
+
+    pub fn new(s: &str) -> Self {
+        let mut chars = s.chars();
+        let value = chars.next().expect("Card string is empty");
+        let suit = chars.next().expect("Card string has no suit");
+        let value = VALUE_CHARS
+            .iter()
+            .position(|c| *c == value)
+            .expect("Invalid value string");
+        let suit = SUIT_CHARS
+            .iter()
+            .position(|c| *c == suit)
+            .expect("Invalid suit string");
+        Card((suit + value * 4) as u8)
+    }
+
+    /// Like new, but None for anything that isn't a card
+    pub fn parse(s: &str) -> Option<Self> {
+        let chars: Vec<char> = s.chars().collect();
+        match chars[..] {
+            [value, suit] => {
+                let value = VALUE_CHARS.iter().position(|c| *c == value)?;
+                let suit = SUIT_CHARS.iter().position(|c| *c == suit)?;
+                Some(Card((suit + value * 4) as u8))
+            }
+            _ => None,
+        }
+    }
+
+    pub const fn suit_index(&self) -> usize {
+        (self.0 % 4) as usize
+    }
+
+    pub const fn value_index(&self) -> usize {
+        (self.0 / 4) as usize
+    }
+
+    pub const fn suit(&self) -> Suit {
+        SUITS[self.suit_index()]
+    }
+
+    pub const fn value(&self) -> Value {
+        VALUES[self.value_index()]
+    }
+
+    pub const fn as_u8(&self) -> u8 {
+        self.0
+    }
+}
+
+impl Parsable for Card {
+    fn to_string(&self) -> Option<String> {
+        let mut s = String::with_capacity(2);
+        s.push(VALUE_CHARS[self.value_index()]);
+        s.push(SUIT_CHARS[self.suit_index()]);
+        Some(s)
+    }
+    fn to_usize(&self) -> Option<usize> {
+        Some(self.0 as usize)
+    }
+}
+
+/// A set of cards as a bitmask, bit `i` set means card index `i` is present
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Deck(pub u64);
+
+impl Deck {
+    const FULL: u64 = (1 << DECK_SIZE) - 1;
+
+    pub const fn empty() -> Self {
+        Deck(0)
+    }
+
+    pub const fn full() -> Self {
+        Deck(Self::FULL)
+    }
+
+    pub const fn contains(&self, card: Card) -> bool {
+        self.0 & (1 << card.0) != 0
+    }
+
+    pub fn insert(&mut self, card: Card) {
+        self.0 |= 1 << card.0;
+    }
+
+    pub fn remove(&mut self, card: Card) {
+        self.0 &= !(1 << card.0);
+    }
+
+    pub const fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub const fn union(&self, other: Deck) -> Deck {
+        Deck(self.0 | other.0)
+    }
+
+    /// Every card not in this set
+    pub const fn complement(&self) -> Deck {
+        Deck(!self.0 & Self::FULL)
+    }
+
+    /// Cards in ascending index order
+    pub fn iter(&self) -> DeckIter {
+        DeckIter(self.0)
+    }
+
+    /// Card indices in the format the hand evaluator expects
+    pub fn as_u8(&self) -> Vec<u8> {
+        self.iter().map(|card| card.0).collect()
+    }
+}
+
+pub struct DeckIter(u64);
+
+impl Iterator for DeckIter {
+    type Item = Card;
+
+    fn next(&mut self) -> Option<Card> {
+        if self.0 == 0 {
+            return None;
+        }
+        let index = self.0.trailing_zeros();
+        self.0 &= self.0 - 1;
+        Some(Card(index as u8))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.0.count_ones() as usize;
+        (len, Some(len))
+    }
+}
+
+/// Cards are ordered by value first and suit second, so that sorting
+/// a set of cards gives the same order no matter how they were dealt.
+/// Note this is *not* the index order, where Aces come first
+impl Ord for Card {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.value()
+            .cmp(&other.value())
+            .then_with(|| self.suit().cmp(&other.suit()))
+    }
+}
+
+impl PartialOrd for Card {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl FromIterator<Card> for Deck {
+    fn from_iter<I: IntoIterator<Item = Card>>(iter: I) -> Self {
+        let mut deck = Deck::empty();
+        for card in iter {
+            deck.insert(card);
+        }
+        deck
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_card_tables_match_enums() {
+        for index in 0..DECK_SIZE {
+            let card = Card::from_index(index);
+            let parsed = Card::new(&card.to_string().unwrap());
+            assert_eq!(parsed, card);
+            assert_eq!(card.value().to_usize().unwrap(), card.value_index());
+            assert_eq!(card.suit(), SUITS[index % 4]);
+        }
+    }
+
+    #[test]
+    fn test_deck_iterates_in_index_order() {
+        let deck: Deck = ["2h", "Ac", "Td"].iter().map(|c| Card::new(c)).collect();
+        assert_eq!(deck.len(), 3);
+        assert!(deck.contains(Card::new("Ac")));
+        assert_eq!(
+            deck.as_u8(),
+            vec![
+                Card::new("Ac").as_u8(),
+                Card::new("Td").as_u8(),
+                Card::new("2h").as_u8()
+            ]
+        );
+        assert_eq!(deck.complement().len(), DECK_SIZE - 3);
+        assert!(!deck.complement().contains(Card::new("Td")));
+    }
+
+    #[test]
+    fn test_card_ordering_by_value_then_suit() {
+        assert!(Card::new("Ah") > Card::new("Kh"));
+        assert!(Card::new("Ts") > Card::new("9h"));
+        assert!(Card::new("3c") > Card::new("2s"));
+        assert!(Card::new("Ks") > Card::new("Kh"));
+        assert!(Card::new("Kd") > Card::new("Kh"));
+
+        let mut cards = [
+            Card::new("2c"),
+            Card::new("As"),
+            Card::new("Td"),
+            Card::new("Th"),
+            Card::new("Kc"),
+        ];
+        cards.sort();
+        let sorted = cards
+            .iter()
+            .map(|c| c.to_string().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(sorted, vec!["2c", "Th", "Td", "Kc", "As"]);
+    }
+}
@@ -0,0 +1,168 @@
+/// Fuzzing auction poker for chips made or lost.
+///
+/// check_conservation plays random hands straight through the state
+/// machine with the audit on, and after every action checks that no
+/// player has staked more than their stack and the pot is exactly what
+/// left the stacks. Every finished hand has to pay out what was lost,
+/// and its audit trail has to explain every step. The bids are picked to
+/// tie now and then, since a tie (both pay) and a won auction (the
+/// winner pays the loser's bid) are the easy ones to get wrong. A panic
+/// anywhere in the hand is reported with the actions that led to it:
+///
+///     gtcogs conservation 100000
+use crate::game_logic::state::{ActivePlayer, State};
+use crate::implementations::auction::*;
+use crate::implementations::ledger::Ledger;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::fmt;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// How often the second bid copies the first
+const TIE_CHANCE: f32 = 0.25;
+
+#[derive(Debug, Clone, Default)]
+pub struct ConservationReport {
+    pub hands: usize,
+    pub showdowns: usize,
+    pub folds: usize,
+    pub auctions_won: usize,
+    pub auction_ties: usize,
+    pub problems: Vec<String>,
+}
+
+impl ConservationReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    pub fn into_result(self) -> Result<(), String> {
+        match self.is_ok() {
+            true => Ok(()),
+            false => Err(self.problems.join("\n")),
+        }
+    }
+}
+
+impl fmt::Display for ConservationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} hands ({} showdowns, {} folds, {} auctions won, {} tied), {} lost or made chips",
+            self.hands,
+            self.showdowns,
+            self.folds,
+            self.auctions_won,
+            self.auction_ties,
+            self.problems.len()
+        )?;
+        for problem in &self.problems {
+            write!(f, "\n  {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether the chips of `state` add up, a stack of its context a player
+fn check_chips(state: &AuctionPokerState) -> Result<(), String> {
+    let stacks = state.stacks();
+    let stack_size = state.context().game.stack_size;
+    if let Some(player_num) = (0..2).find(|&player_num| stacks[player_num] > stack_size) {
+        return Err(format!("player {} staked more than {}, stacks {:?}", player_num, stack_size, stacks));
+    }
+    let ledger = Ledger::from_stacks(stack_size, stacks);
+    if ledger.pot() != state.pot() {
+        return Err(format!("the pot is {} but {:?} left the stacks", state.pot(), ledger.contributions()));
+    }
+    Ok(())
+}
+
+/// A random legal action, the bid of the first bidder now and then for
+/// the second one
+fn pick(legal: &[AuctionPokerAction], first_bid: &Option<AuctionPokerAction>, rng: &mut StdRng) -> AuctionPokerAction {
+    if let Some(bid) = first_bid {
+        if legal.contains(bid) && rng.gen_range(0.0, 1.0) < TIE_CHANCE {
+            return bid.clone();
+        }
+    }
+    // u32 draws only, mixing in u64 ones trips up the StdRng of this rand
+    legal[rng.gen_range(0, legal.len() as u32) as usize].clone()
+}
+
+/// Plays one random hand, returning its actions and how it went wrong
+fn play_hand(rng: &mut StdRng, report: &mut ConservationReport) -> Result<(), (Vec<AuctionPokerAction>, String)> {
+    let mut state = AuctionPokerState::new().with_audit();
+    let mut actions: Vec<AuctionPokerAction> = vec![];
+    let mut first_bid = None;
+    loop {
+        let action = match state.active_player() {
+            ActivePlayer::Terminal(utilities) => {
+                let ledger = state.ledger();
+                let lost = utilities.iter().filter(|utility| **utility < 0.0).map(|utility| -utility).sum::<f32>();
+                let won = utilities.iter().filter(|utility| **utility > 0.0).sum::<f32>();
+                if won != lost {
+                    return Err((actions, format!("won {} but lost {}, utilities {:?}", won, lost, utilities)));
+                }
+                if let Some(utility) = utilities.iter().find(|utility| -**utility > ledger.pot() as f32) {
+                    return Err((actions, format!("lost {} from a pot of {}", -utility, ledger.pot())));
+                }
+                if let Some(Err(error)) = state.audit().map(|audit| audit.verify()) {
+                    return Err((actions, error));
+                }
+                match actions.last() {
+                    Some(AuctionPokerAction::Fold) => report.folds += 1,
+                    _ => report.showdowns += 1,
+                }
+                return Ok(());
+            }
+            ActivePlayer::Chance(chance) => chance.sample_rng(rng),
+            ActivePlayer::Marker(action) => action,
+            ActivePlayer::Player(_, legal) => {
+                let action = pick(&legal, &first_bid, rng);
+                if matches!(action, AuctionPokerAction::Bid(_)) && first_bid.is_none() {
+                    first_bid = Some(action.clone());
+                }
+                action
+            }
+        };
+        match &action {
+            AuctionPokerAction::Auction(Winner::Tie) => report.auction_ties += 1,
+            AuctionPokerAction::Auction(_) => report.auctions_won += 1,
+            _ => {}
+        }
+        actions.push(action.clone());
+        let updated = catch_unwind(AssertUnwindSafe(|| state.update(action)));
+        if updated.is_err() {
+            return Err((actions, "the state machine panicked".to_string()));
+        }
+        check_chips(&state).map_err(|error| (actions.clone(), error))?;
+    }
+}
+
+/// Plays `hands` random hands of auction poker, reporting every one where
+/// chips were made or lost
+pub fn check_conservation(hands: usize, seed: u64) -> ConservationReport {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut report = ConservationReport::default();
+    for hand in 0..hands {
+        if let Err((actions, error)) = play_hand(&mut rng, &mut report) {
+            report.problems.push(format!("Hand {}: {} after {:?}", hand, error, actions));
+        }
+        report.hands += 1;
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// GTCOGS_FUZZ_HANDS=100000 for a long run
+    #[test]
+    fn test_chips_are_conserved() {
+        let hands = std::env::var("GTCOGS_FUZZ_HANDS").ok().and_then(|hands| hands.parse().ok()).unwrap_or(200);
+        let report = check_conservation(hands, 0);
+        assert_eq!(report.hands, hands);
+        assert!(report.auction_ties > 0 && report.auctions_won > 0, "{}", report);
+        report.into_result().unwrap();
+    }
+}
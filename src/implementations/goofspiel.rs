@@ -1,7 +1,7 @@
 /// Implementation of Goofspiel, a simpler card game. Very useful for
 /// figuring out how to implement a game in this framework.
 use crate::game_logic::action::{Action, ActionIndex, Filterable, Parsable};
-use crate::game_logic::state::{ActivePlayer, State};
+use crate::game_logic::state::{ActivePlayer, State, UtilityBounds};
 use crate::game_logic::visibility::{Information, Observation};
 use crate::{Categorical, Utility};
 use bit_set::BitSet;
@@ -162,6 +162,20 @@ impl GoofspielState {
 }
 
 impl State<GoofspielAction> for GoofspielState {
+    fn utility_bounds(&self) -> Option<UtilityBounds> {
+        let won: Utility = self.internal.values.iter().filter(|value| **value > 0.0).sum();
+        let lost: Utility = self.internal.values.iter().filter(|value| **value < 0.0).sum();
+        Some(match self.internal.scoring {
+            Scoring::ZeroSum => UtilityBounds::zero_sum(won - lost),
+            Scoring::WinLoss => UtilityBounds::zero_sum(1.0),
+            Scoring::Absolute => UtilityBounds {
+                min: lost,
+                max: won,
+                zero_sum: false,
+            },
+        })
+    }
+
     fn new() -> Self {
         let internal = Goofspiel::new(7, Scoring::ZeroSum);
         let cards = [
@@ -191,7 +205,7 @@ impl State<GoofspielAction> for GoofspielState {
         self.active.clone()
     }
 
-    fn get_observations_after(&mut self, action: &GoofspielAction) -> Vec<Observation<GoofspielAction>> {
+    fn observe_before(&mut self, action: &GoofspielAction) -> Vec<Observation<GoofspielAction>> {
         let observation = match self.active_player() {
             ActivePlayer::Terminal(_) => panic!("Terminal state has no visibility"),
             ActivePlayer::Player(_, _) => Observation::Private(Information::Action(action.clone())),
@@ -0,0 +1,348 @@
+/// Small games described in JSON instead of written as a State, so a new
+/// toy game can be tried out without any new Rust.
+///
+/// A description names the actions and spells out the whole game tree:
+///
+///     {
+///         "players": 2,
+///         "actions": ["high", "low", "bet", "check", "call", "fold"],
+///         "root": {"type": "chance", "outcomes": [
+///             {"action": "high", "probability": 0.5, "visible_to": [0], "next":
+///                 {"type": "player", "player": 0, "moves": [
+///                     {"action": "bet", "next": ...},
+///                     {"action": "check", "next": {"type": "terminal", "payoffs": [1, -1]}}
+///                 ]}},
+///             ...
+///         ]}
+///     }
+///
+/// Player moves are seen by everyone, chance outcomes by the players in
+/// `visible_to` (everyone if it is left out). An action's index is its
+/// position in `actions`, so the policy of every info set is laid out in
+/// that order.
+///
+/// The State is built with State::new, so the description has to be
+/// reachable without arguments: implement JsonGameSource for a marker type
+/// (usually loading the file once into a OnceLock) and train
+/// JsonGameState<ThatType>
+use crate::constants::NUM_REGULAR_PLAYERS;
+use crate::distribution::Categorical;
+use crate::game_logic::action::*;
+use crate::game_logic::state::{ActivePlayer, State};
+use crate::game_logic::visibility::{Information, Observation};
+use crate::Utility;
+use serde::Deserialize;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+/// The most actions a described game may name
+pub const MAX_JSON_ACTIONS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct JsonAction(pub ActionIndex);
+
+impl Parsable for JsonAction {
+    fn to_string(&self) -> Option<String> {
+        None
+    }
+
+    fn to_usize(&self) -> Option<usize> {
+        Some(self.0 as usize)
+    }
+}
+
+impl From<JsonAction> for ActionIndex {
+    fn from(action: JsonAction) -> Self {
+        action.0
+    }
+}
+
+impl From<ActionIndex> for JsonAction {
+    fn from(index: ActionIndex) -> Self {
+        JsonAction(index)
+    }
+}
+
+impl Filterable for JsonAction {}
+impl Action for JsonAction {
+    fn max_index() -> ActionIndex {
+        MAX_JSON_ACTIONS as ActionIndex
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct GameDescription {
+    players: usize,
+    actions: Vec<String>,
+    root: NodeDescription,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum NodeDescription {
+    Chance { outcomes: Vec<OutcomeDescription> },
+    Player { player: usize, moves: Vec<MoveDescription> },
+    Terminal { payoffs: Vec<Utility> },
+}
+
+#[derive(Deserialize, Debug)]
+struct OutcomeDescription {
+    action: String,
+    probability: f32,
+    visible_to: Option<Vec<usize>>,
+    next: NodeDescription,
+}
+
+#[derive(Deserialize, Debug)]
+struct MoveDescription {
+    action: String,
+    next: NodeDescription,
+}
+
+#[derive(Debug, Clone)]
+pub enum Node {
+    Chance(Vec<Outcome>),
+    Player(usize, Vec<(JsonAction, Node)>),
+    Terminal(Vec<Utility>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Outcome {
+    pub action: JsonAction,
+    pub probability: f32,
+    /// None if every player sees the outcome
+    pub visible_to: Option<Vec<usize>>,
+    pub next: Node,
+}
+
+/// A validated game description, actions resolved to their indices
+#[derive(Debug, Clone)]
+pub struct GameSpec {
+    pub actions: Vec<String>,
+    pub root: Node,
+}
+
+impl GameSpec {
+    pub fn from_json(json: &str) -> Result<GameSpec, String> {
+        let description: GameDescription = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        if description.players != NUM_REGULAR_PLAYERS {
+            return Err(format!("Only {} player games are supported", NUM_REGULAR_PLAYERS));
+        }
+        if description.actions.len() > MAX_JSON_ACTIONS {
+            return Err(format!("At most {} actions are supported", MAX_JSON_ACTIONS));
+        }
+        let root = resolve(&description.actions, description.players, &description.root)?;
+        Ok(GameSpec {
+            actions: description.actions,
+            root,
+        })
+    }
+
+    pub fn load(file_name: &str) -> Result<GameSpec, String> {
+        let json = std::fs::read_to_string(file_name).map_err(|e| format!("{}: {}", file_name, e))?;
+        GameSpec::from_json(&json).map_err(|e| format!("{}: {}", file_name, e))
+    }
+
+    pub fn action_name(&self, action: JsonAction) -> &str {
+        &self.actions[action.0 as usize]
+    }
+}
+
+fn resolve_action(actions: &[String], name: &str) -> Result<JsonAction, String> {
+    actions
+        .iter()
+        .position(|action| action == name)
+        .map(|index| JsonAction(index as ActionIndex))
+        .ok_or_else(|| format!("Unknown action {}", name))
+}
+
+fn resolve(actions: &[String], players: usize, node: &NodeDescription) -> Result<Node, String> {
+    match node {
+        NodeDescription::Chance { outcomes } => {
+            if outcomes.is_empty() {
+                return Err("A chance node has no outcomes".to_string());
+            }
+            let total: f32 = outcomes.iter().map(|outcome| outcome.probability).sum();
+            if (total - 1.0).abs() > 1e-4 || outcomes.iter().any(|outcome| outcome.probability < 0.0) {
+                return Err(format!("Chance probabilities sum to {} instead of 1", total));
+            }
+            let mut resolved = vec![];
+            for outcome in outcomes {
+                if let Some(players_seeing) = &outcome.visible_to {
+                    if players_seeing.iter().any(|player| *player >= players) {
+                        return Err(format!("{} is visible to a player that doesn't exist", outcome.action));
+                    }
+                }
+                resolved.push(Outcome {
+                    action: resolve_action(actions, &outcome.action)?,
+                    probability: outcome.probability,
+                    visible_to: outcome.visible_to.clone(),
+                    next: resolve(actions, players, &outcome.next)?,
+                });
+            }
+            Ok(Node::Chance(resolved))
+        }
+        NodeDescription::Player { player, moves } => {
+            if *player >= players {
+                return Err(format!("Player {} doesn't exist", player));
+            }
+            if moves.is_empty() {
+                return Err(format!("Player {} has no moves", player));
+            }
+            let mut resolved = vec![];
+            for next_move in moves {
+                let action = resolve_action(actions, &next_move.action)?;
+                if resolved.iter().any(|(other, _)| *other == action) {
+                    return Err(format!("Player {} has the move {} twice", player, next_move.action));
+                }
+                resolved.push((action, resolve(actions, players, &next_move.next)?));
+            }
+            Ok(Node::Player(*player, resolved))
+        }
+        NodeDescription::Terminal { payoffs } => {
+            if payoffs.len() != players {
+                return Err(format!("Terminal with {} payoffs for {} players", payoffs.len(), players));
+            }
+            Ok(Node::Terminal(payoffs.clone()))
+        }
+    }
+}
+
+/// Where a JsonGameState finds its game
+pub trait JsonGameSource: Clone + Debug {
+    fn spec() -> &'static GameSpec;
+}
+
+#[derive(Debug, Clone)]
+pub struct JsonGameState<G: JsonGameSource> {
+    node: &'static Node,
+    source: PhantomData<G>,
+}
+
+impl<G: JsonGameSource> JsonGameState<G> {
+    fn next(&self, action: &JsonAction) -> &'static Node {
+        let node: &'static Node = self.node;
+        match node {
+            Node::Chance(outcomes) => outcomes.iter().find(|outcome| outcome.action == *action).map(|outcome| &outcome.next),
+            Node::Player(_, moves) => moves.iter().find(|(other, _)| other == action).map(|(_, next)| next),
+            Node::Terminal(_) => None,
+        }
+        .unwrap_or_else(|| panic!("{:?} is not legal here", action))
+    }
+}
+
+impl<G: JsonGameSource> State<JsonAction> for JsonGameState<G> {
+    fn new() -> Self {
+        JsonGameState {
+            node: &G::spec().root,
+            source: PhantomData,
+        }
+    }
+
+    fn observe_before(&mut self, action: &JsonAction) -> Vec<Observation<JsonAction>> {
+        let information = Information::Action(*action);
+        let visible_to = match self.node {
+            Node::Chance(outcomes) => outcomes
+                .iter()
+                .find(|outcome| outcome.action == *action)
+                .and_then(|outcome| outcome.visible_to.clone()),
+            _ => None,
+        };
+        match visible_to {
+            Some(players) => vec![Observation::Shared(information, players)],
+            None => vec![Observation::Public(information)],
+        }
+    }
+
+    fn active_player(&self) -> ActivePlayer<JsonAction> {
+        match self.node {
+            Node::Chance(outcomes) => ActivePlayer::Chance(Categorical::new_normalized(
+                outcomes.iter().map(|outcome| outcome.probability).collect::<Vec<_>>(),
+                outcomes.iter().map(|outcome| outcome.action).collect::<Vec<_>>(),
+            )),
+            Node::Player(player, moves) => {
+                ActivePlayer::Player(*player as u32, moves.iter().map(|(action, _)| *action).collect())
+            }
+            Node::Terminal(payoffs) => ActivePlayer::Terminal(payoffs.clone()),
+        }
+    }
+
+    fn update(&mut self, action: JsonAction) {
+        self.node = self.next(&action);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::mccfr::MCCFR;
+    use crate::game_logic::game::Game;
+    use crate::game_logic::strategy::RegretStrategy;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::sync::{Arc, OnceLock};
+
+    /// Player 0 is dealt high or low and may bet, player 1 calls or folds
+    const BLUFF_GAME: &str = r#"{
+        "players": 2,
+        "actions": ["high", "low", "bet", "check", "call", "fold"],
+        "root": {"type": "chance", "outcomes": [
+            {"action": "high", "probability": 0.5, "visible_to": [0], "next": {"type": "player", "player": 0, "moves": [
+                {"action": "bet", "next": {"type": "player", "player": 1, "moves": [
+                    {"action": "call", "next": {"type": "terminal", "payoffs": [2, -2]}},
+                    {"action": "fold", "next": {"type": "terminal", "payoffs": [1, -1]}}
+                ]}},
+                {"action": "check", "next": {"type": "terminal", "payoffs": [1, -1]}}
+            ]}},
+            {"action": "low", "probability": 0.5, "visible_to": [0], "next": {"type": "player", "player": 0, "moves": [
+                {"action": "bet", "next": {"type": "player", "player": 1, "moves": [
+                    {"action": "call", "next": {"type": "terminal", "payoffs": [-2, 2]}},
+                    {"action": "fold", "next": {"type": "terminal", "payoffs": [1, -1]}}
+                ]}},
+                {"action": "check", "next": {"type": "terminal", "payoffs": [-1, 1]}}
+            ]}}
+        ]}
+    }"#;
+
+    #[derive(Clone, Debug)]
+    struct BluffGame;
+
+    impl JsonGameSource for BluffGame {
+        fn spec() -> &'static GameSpec {
+            static SPEC: OnceLock<GameSpec> = OnceLock::new();
+            SPEC.get_or_init(|| GameSpec::from_json(BLUFF_GAME).unwrap())
+        }
+    }
+
+    #[test]
+    fn test_described_game_plays_and_trains() {
+        let mut game = Game::<JsonAction, JsonGameState<BluffGame>>::new();
+        assert!(matches!(game.active_player(), ActivePlayer::Chance(_)));
+        game.play(&JsonAction(1));
+        let low = game.get_information_set(0);
+        let nothing = game.get_information_set(1);
+        assert_eq!(game.active_player(), ActivePlayer::Player(0, vec![JsonAction(2), JsonAction(3)]));
+        game.play(&JsonAction(2));
+        game.play(&JsonAction(4));
+        assert_eq!(game.active_player(), ActivePlayer::Terminal(vec![-2.0, 2.0]));
+        assert_ne!(low, nothing);
+        assert_eq!(BluffGame::spec().action_name(JsonAction(4)), "call");
+
+        let strategies = vec![Arc::new(RegretStrategy::default()), Arc::new(RegretStrategy::default())];
+        let mut mccfr = MCCFR::new(Game::<JsonAction, JsonGameState<BluffGame>>::new(), strategies.clone());
+        mccfr.run_iterations(50, 0.6, &mut StdRng::seed_from_u64(1));
+        // Two info sets for player 0 (high or low), one for player 1
+        assert_eq!(strategies[0].size(), 2);
+        assert_eq!(strategies[1].size(), 1);
+    }
+
+    #[test]
+    fn test_broken_descriptions_are_rejected() {
+        let unknown = BLUFF_GAME.replace(r#""action": "call""#, r#""action": "raise""#);
+        assert_eq!(GameSpec::from_json(&unknown).unwrap_err(), "Unknown action raise");
+        let bad_odds = BLUFF_GAME.replace("0.5", "0.4");
+        assert!(GameSpec::from_json(&bad_odds).unwrap_err().contains("sum to"));
+        let short_payoffs = BLUFF_GAME.replace("[2, -2]", "[2]");
+        assert!(GameSpec::from_json(&short_payoffs).unwrap_err().contains("payoffs"));
+        assert!(GameSpec::from_json("{").is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use crate::distribution::Categorical;
 use crate::game_logic::action::*;
-use crate::game_logic::state::{ActivePlayer, State};
+use crate::game_logic::state::{ActivePlayer, State, UtilityBounds};
 use crate::game_logic::visibility::{Information, Observation};
 
 #[derive(Debug, Clone, PartialEq, Eq, Copy, Hash)]
@@ -97,6 +97,11 @@ impl KuhnPokerState {
 }
 
 impl State<KuhnPokerAction> for KuhnPokerState {
+    /// The ante and a bet at most
+    fn utility_bounds(&self) -> Option<UtilityBounds> {
+        Some(UtilityBounds::zero_sum(2.0))
+    }
+
     fn new() -> Self {
         let cards = vec![0, 1, 2];
         let active_player = KuhnPokerState::dealer(cards.clone());
@@ -107,7 +112,7 @@ impl State<KuhnPokerAction> for KuhnPokerState {
         }
     }
 
-    fn get_observations_after(&mut self, action: &KuhnPokerAction) -> Vec<Observation<KuhnPokerAction>> {
+    fn observe_before(&mut self, action: &KuhnPokerAction) -> Vec<Observation<KuhnPokerAction>> {
         let observation = match action {
             KuhnPokerAction::Fold => {
                 Observation::Public(Information::Action(KuhnPokerAction::Fold))
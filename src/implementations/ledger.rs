@@ -0,0 +1,111 @@
+/// Who gets which chips when an auction poker hand ends.
+///
+/// Everything a player puts in (blinds, bets and auction bids alike) is
+/// a contribution to the one pot, so the stack size less a stack is
+/// exactly what that player contributed. The whole pot goes to one player or is
+/// split between both, and a player's winnings are what they take from
+/// the pot less what they put in. The contributions don't have to match:
+/// the winner of an auction pays the loser's bid on top of the betting,
+/// and a split pot still splits the whole pot evenly. The odd chip of a
+/// split pot goes to the big blind (see piazza), who always acts second
+use crate::units::Chips;
+use crate::Utility;
+
+/// Who gets the odd chip of a split pot
+pub const ODD_CHIP_PLAYER: usize = 1;
+
+/// How the pot is awarded at the end of a hand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Award {
+    /// The whole pot to this player, whether by showdown or fold
+    To(usize),
+    /// Half each, the odd chip to ODD_CHIP_PLAYER
+    Split,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ledger {
+    contributions: [u32; 2],
+}
+
+impl Ledger {
+    pub fn new(contributions: [u32; 2]) -> Ledger {
+        Ledger { contributions }
+    }
+
+    /// The ledger of players who started with `stack_size` each and are
+    /// left with `stacks`, the rest in the pot
+    pub fn from_stacks(stack_size: u32, stacks: [u32; 2]) -> Ledger {
+        Ledger::new([stack_size - stacks[0], stack_size - stacks[1]])
+    }
+
+    pub fn contributions(&self) -> [u32; 2] {
+        self.contributions
+    }
+
+    pub fn pot(&self) -> u32 {
+        self.contributions[0] + self.contributions[1]
+    }
+
+    /// The chips each player takes from the pot
+    pub fn payouts(&self, award: Award) -> [u32; 2] {
+        let pot = self.pot();
+        let mut payouts = [0; 2];
+        match award {
+            Award::To(player_num) => payouts[player_num] = pot,
+            Award::Split => {
+                payouts = [pot / 2; 2];
+                payouts[ODD_CHIP_PLAYER] += pot % 2;
+            }
+        }
+        payouts
+    }
+
+    /// The chips each player ends the hand up, negative if down
+    pub fn winnings(&self, award: Award) -> [i64; 2] {
+        let payouts = self.payouts(award);
+        [0, 1].map(|player_num| payouts[player_num] as i64 - self.contributions[player_num] as i64)
+    }
+
+    /// The winnings as the utilities of a Terminal node
+    pub fn utilities(&self, award: Award) -> Vec<Utility> {
+        self.winnings(award).iter().map(|chips| Chips(*chips as f32).utility()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::STACK_SIZE;
+
+    #[test]
+    fn test_every_award_pays_out_the_pot() {
+        for contribution0 in 0..=24 {
+            for contribution1 in 0..=24 {
+                let ledger = Ledger::new([contribution0, contribution1]);
+                for award in [Award::To(0), Award::To(1), Award::Split] {
+                    let payouts = ledger.payouts(award);
+                    let winnings = ledger.winnings(award);
+                    assert_eq!(payouts[0] + payouts[1], ledger.pot(), "{:?} {:?}", ledger, award);
+                    assert_eq!(winnings[0] + winnings[1], 0, "{:?} {:?}", ledger, award);
+                }
+                // The winner takes what the loser put in
+                assert_eq!(ledger.winnings(Award::To(0)), [contribution1 as i64, -(contribution1 as i64)]);
+                assert_eq!(ledger.winnings(Award::To(1)), [-(contribution0 as i64), contribution0 as i64]);
+
+                // Split evenly, only the odd chip decides who is ahead
+                let split = ledger.payouts(Award::Split);
+                assert_eq!(split[ODD_CHIP_PLAYER] - split[ODD_CHIP_PLAYER ^ 1], ledger.pot() % 2);
+            }
+        }
+
+        // Even contributions split back to where they started, an odd pot
+        // gives the big blind the extra chip
+        assert_eq!(Ledger::new([10, 10]).winnings(Award::Split), [0, 0]);
+        assert_eq!(Ledger::new([5, 6]).winnings(Award::Split), [0, 0]);
+        assert_eq!(Ledger::new([6, 5]).winnings(Award::Split), [-1, 1]);
+        // Player 0 paid 5 for the auction and still only gets half the pot
+        assert_eq!(Ledger::new([15, 10]).winnings(Award::Split), [-3, 3]);
+        assert_eq!(Ledger::from_stacks(STACK_SIZE, [STACK_SIZE - 15, STACK_SIZE - 10]).utilities(Award::Split), [-3.0, 3.0]);
+    }
+}
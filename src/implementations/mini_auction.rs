@@ -0,0 +1,485 @@
+/// Auction poker shrunk until its whole tree can be enumerated, to measure
+/// what our abstraction choices cost.
+///
+/// Each player antes and gets one card from a deck of `DECK_SIZE` (two
+/// suits of every rank, suits don't matter). A betting round on a raise
+/// ladder follows, then the auction: sealed bids out of what is left of
+/// the stacks, both bids revealed once they are in, and the higher bidder
+/// pays the loser's bid for a second card. On a tie both pay and, unlike
+/// auction poker, nobody gets a card, which keeps the tree small. Another
+/// betting round and the best hand wins: a pair, then the high card, then
+/// the kicker.
+///
+/// A config can also abstract the game the way the production abstraction
+/// does: the players only see their hand strength in STRENGTH_BUCKETS
+/// buckets instead of their cards, and only the BID_BUCKETS bucket of a
+/// bid, bidding the middle of a bucket (see auction::bid_buckets). The
+/// actions are the same either way, so a strategy trained on an abstract
+/// config can be played, and best responded to, in the exact one. See
+/// algorithm::abstraction_loss
+use crate::distribution::Categorical;
+use crate::game_logic::action::*;
+use crate::game_logic::state::{ActivePlayer, State, UtilityBounds};
+use crate::game_logic::visibility::{Information, Observation};
+use crate::Utility;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+/// The largest number of distinct raise sizes a config may declare
+pub const MAX_RAISE_SIZES: usize = 5;
+/// The largest stack a config may have behind its ante, so both bids
+/// fit in a single revealed action
+pub const MAX_STACK: u32 = 7;
+
+pub trait MiniAuctionConfig: Clone + Debug {
+    /// Number of cards in the deck, two of every rank
+    const DECK_SIZE: u8;
+    /// Chips each player puts in the pot before the cards are dealt
+    const ANTE: u32;
+    /// Chips each player has behind the ante, at most MAX_STACK
+    const STACK: u32;
+    /// Available raise sizes in chips
+    const RAISE_SIZES: &'static [u32];
+    /// Raises allowed in each betting round
+    const MAX_RAISES: usize;
+    /// Buckets of hand strength the players see, 0 to see the cards
+    const STRENGTH_BUCKETS: u8 = 0;
+    /// Largest bid of each bid bucket, empty to see and make every bid
+    const BID_BUCKETS: &'static [u32] = &[];
+}
+
+/// A 20 card deck, small stacks and one raise size, the game
+/// `gtcogs abstraction-loss` measures
+#[derive(Clone, Debug)]
+pub struct ReducedConfig;
+
+impl MiniAuctionConfig for ReducedConfig {
+    const DECK_SIZE: u8 = 20;
+    const ANTE: u32 = 1;
+    const STACK: u32 = 4;
+    const RAISE_SIZES: &'static [u32] = &[2];
+    const MAX_RAISES: usize = 1;
+}
+
+/// ReducedConfig seen through the production abstraction choices: hand
+/// strength buckets and bid buckets
+#[derive(Clone, Debug)]
+pub struct ReducedAbstractConfig;
+
+impl MiniAuctionConfig for ReducedAbstractConfig {
+    const DECK_SIZE: u8 = ReducedConfig::DECK_SIZE;
+    const ANTE: u32 = ReducedConfig::ANTE;
+    const STACK: u32 = ReducedConfig::STACK;
+    const RAISE_SIZES: &'static [u32] = ReducedConfig::RAISE_SIZES;
+    const MAX_RAISES: usize = ReducedConfig::MAX_RAISES;
+    const STRENGTH_BUCKETS: u8 = 5;
+    const BID_BUCKETS: &'static [u32] = &[0, 2, 4];
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Copy, Hash)]
+pub enum MiniAuctionAction {
+    Fold,
+    Call,
+    Check,
+    Raise(u8), // Index into the config's raise ladder
+    Bid(u8),
+    Deal(u8),
+    /// What a player sees of their hand in an abstract config
+    Strength(u8),
+    /// Both bids, as seen once they are in
+    Reveal(u8),
+}
+
+impl Parsable for MiniAuctionAction {
+    fn to_string(&self) -> Option<String> {
+        None
+    }
+
+    fn to_usize(&self) -> Option<usize> {
+        None
+    }
+}
+
+const FIRST_RAISE: ActionIndex = 3;
+const FIRST_BID: ActionIndex = FIRST_RAISE + MAX_RAISE_SIZES as ActionIndex;
+// Observations only, never played by a player
+const FIRST_DEAL: ActionIndex = 50;
+const FIRST_STRENGTH: ActionIndex = 100;
+const FIRST_REVEAL: ActionIndex = 130;
+
+impl From<MiniAuctionAction> for ActionIndex {
+    fn from(action: MiniAuctionAction) -> Self {
+        match action {
+            MiniAuctionAction::Fold => 0,
+            MiniAuctionAction::Call => 1,
+            MiniAuctionAction::Check => 2,
+            MiniAuctionAction::Raise(rung) => FIRST_RAISE + rung,
+            MiniAuctionAction::Bid(bid) => FIRST_BID + bid,
+            MiniAuctionAction::Deal(card) => FIRST_DEAL + card,
+            MiniAuctionAction::Strength(bucket) => FIRST_STRENGTH + bucket,
+            MiniAuctionAction::Reveal(bids) => FIRST_REVEAL + bids,
+        }
+    }
+}
+
+impl From<ActionIndex> for MiniAuctionAction {
+    fn from(index: ActionIndex) -> Self {
+        match index {
+            0 => MiniAuctionAction::Fold,
+            1 => MiniAuctionAction::Call,
+            2 => MiniAuctionAction::Check,
+            index if index < FIRST_BID => MiniAuctionAction::Raise(index - FIRST_RAISE),
+            index if index < FIRST_DEAL => MiniAuctionAction::Bid(index - FIRST_BID),
+            index if index < FIRST_STRENGTH => MiniAuctionAction::Deal(index - FIRST_DEAL),
+            index if index < FIRST_REVEAL => MiniAuctionAction::Strength(index - FIRST_STRENGTH),
+            index => MiniAuctionAction::Reveal(index - FIRST_REVEAL),
+        }
+    }
+}
+
+impl Filterable for MiniAuctionAction {}
+impl Action for MiniAuctionAction {
+    fn max_index() -> ActionIndex {
+        FIRST_BID + MAX_STACK as ActionIndex + 1
+    }
+}
+
+fn rank(card: u8) -> u8 {
+    card / 2
+}
+
+/// Higher is better: a pair beats any high card, then the high card and
+/// the kicker decide, and any kicker beats none
+pub fn hand_value(cards: &[u8]) -> u32 {
+    let mut ranks: Vec<u32> = cards.iter().map(|card| rank(*card) as u32).collect();
+    ranks.sort_unstable_by(|a, b| b.cmp(a));
+    match ranks.as_slice() {
+        [high, low] if high == low => 1000 + high,
+        [high, low] => 10 * high + low + 1,
+        [high] => 10 * high,
+        _ => 0,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MiniAuctionState<C: MiniAuctionConfig> {
+    deck: Vec<u8>,
+    cards: [Vec<u8>; 2],
+    contributions: [u32; 2],
+    stacks: [u32; 2],
+    /// Chips bet in this betting round
+    pips: [u32; 2],
+    /// Betting rounds over so far, the auction comes after the first
+    betting_rounds: usize,
+    raises: usize,
+    bids: [Option<u32>; 2],
+    active_player: ActivePlayer<MiniAuctionAction>,
+    config: PhantomData<C>,
+}
+
+impl<C: MiniAuctionConfig> MiniAuctionState<C> {
+    fn dealer(deck: &[u8]) -> ActivePlayer<MiniAuctionAction> {
+        ActivePlayer::Chance(Categorical::uniform(deck.iter().map(|card| MiniAuctionAction::Deal(*card)).collect::<Vec<_>>()))
+    }
+
+    /// The hand strength bucket of `cards`, a pair counting for more than
+    /// any high card
+    pub fn strength(cards: &[u8]) -> u8 {
+        let ranks = (C::DECK_SIZE / 2) as u32;
+        let coarse = match hand_value(cards) {
+            value if value >= 1000 => ranks + value - 1000,
+            value => value / 10,
+        };
+        (coarse * C::STRENGTH_BUCKETS as u32 / (2 * ranks)) as u8
+    }
+
+    fn bid_bucket(bid: u32) -> u32 {
+        match C::BID_BUCKETS.iter().position(|largest| bid <= *largest) {
+            Some(bucket) => bucket as u32,
+            None => bid,
+        }
+    }
+
+    /// What a player sees of a bid
+    fn seen_bid(bid: u32) -> u32 {
+        match C::BID_BUCKETS.is_empty() {
+            true => bid,
+            false => Self::bid_bucket(bid),
+        }
+    }
+
+    /// The bids the config lets a player with `stack` make: all of them,
+    /// or the middle of every bucket the stack reaches, the stack itself
+    /// standing in for a middle beyond it
+    pub fn bids(stack: u32) -> Vec<u32> {
+        if C::BID_BUCKETS.is_empty() {
+            return (0..=stack).collect();
+        }
+        let mut bids: Vec<u32> = (0..=stack)
+            .map(|bid| {
+                let bucket = Self::bid_bucket(bid) as usize;
+                let smallest = match bucket {
+                    0 => 0,
+                    _ => C::BID_BUCKETS[bucket - 1] + 1,
+                };
+                ((smallest + C::BID_BUCKETS[bucket]) / 2).min(stack)
+            })
+            .collect();
+        bids.dedup();
+        bids
+    }
+
+    fn to_call(&self, player_num: usize) -> u32 {
+        self.pips[player_num ^ 1] - self.pips[player_num]
+    }
+
+    fn betting(&self, player_num: usize) -> ActivePlayer<MiniAuctionAction> {
+        let to_call = self.to_call(player_num);
+        let mut actions = match to_call {
+            0 => vec![MiniAuctionAction::Check],
+            _ => vec![MiniAuctionAction::Fold, MiniAuctionAction::Call],
+        };
+        if self.raises < C::MAX_RAISES {
+            for (rung, size) in C::RAISE_SIZES.iter().enumerate() {
+                if self.stacks[player_num] >= to_call + size && self.stacks[player_num ^ 1] >= *size {
+                    actions.push(MiniAuctionAction::Raise(rung as u8));
+                }
+            }
+        }
+        ActivePlayer::Player(player_num as u32, actions)
+    }
+
+    fn bidding(&self, player_num: usize) -> ActivePlayer<MiniAuctionAction> {
+        let bids = Self::bids(self.stacks[player_num]).into_iter().map(|bid| MiniAuctionAction::Bid(bid as u8));
+        ActivePlayer::Player(player_num as u32, bids.collect())
+    }
+
+    fn pay(&mut self, player_num: usize, chips: u32) {
+        self.stacks[player_num] -= chips;
+        self.contributions[player_num] += chips;
+    }
+
+    fn bet(&mut self, player_num: usize, chips: u32) {
+        self.pay(player_num, chips);
+        self.pips[player_num] += chips;
+    }
+
+    /// The betting round is over, on to the auction or the showdown
+    fn end_round(&mut self) -> ActivePlayer<MiniAuctionAction> {
+        self.betting_rounds += 1;
+        self.raises = 0;
+        self.pips = [0; 2];
+        match self.betting_rounds {
+            1 => self.bidding(0),
+            _ => self.showdown(),
+        }
+    }
+
+    fn auction(&mut self) -> ActivePlayer<MiniAuctionAction> {
+        let (bid0, bid1) = (self.bids[0].unwrap(), self.bids[1].unwrap());
+        if bid0 == bid1 {
+            self.pay(0, bid0);
+            self.pay(1, bid1);
+            return self.betting(0);
+        }
+        let winner = match bid0 > bid1 {
+            true => 0,
+            false => 1,
+        };
+        self.pay(winner, bid0.min(bid1));
+        Self::dealer(&self.deck)
+    }
+
+    fn folded(&self, player_num: usize) -> ActivePlayer<MiniAuctionAction> {
+        let lost = self.contributions[player_num] as Utility;
+        let mut utilities = vec![lost; 2];
+        utilities[player_num] = -lost;
+        ActivePlayer::Terminal(utilities)
+    }
+
+    fn showdown(&self) -> ActivePlayer<MiniAuctionAction> {
+        let values = [hand_value(&self.cards[0]), hand_value(&self.cards[1])];
+        let contributions = self.contributions.map(|chips| chips as Utility);
+        let pot = contributions[0] + contributions[1];
+        let payouts = match values[0].cmp(&values[1]) {
+            std::cmp::Ordering::Greater => [pot, 0.0],
+            std::cmp::Ordering::Less => [0.0, pot],
+            std::cmp::Ordering::Equal => [pot / 2.0; 2],
+        };
+        ActivePlayer::Terminal(vec![payouts[0] - contributions[0], payouts[1] - contributions[1]])
+    }
+
+    /// Who the next card goes to
+    fn dealt_to(&self) -> usize {
+        match (self.cards[0].len(), self.cards[1].len(), self.bids) {
+            (0, _, _) => 0,
+            (_, 0, _) => 1,
+            (_, _, [Some(bid0), Some(bid1)]) if bid0 > bid1 => 0,
+            _ => 1,
+        }
+    }
+
+    pub fn contributions(&self) -> [u32; 2] {
+        self.contributions
+    }
+
+    pub fn stacks(&self) -> [u32; 2] {
+        self.stacks
+    }
+}
+
+impl<C: MiniAuctionConfig> State<MiniAuctionAction> for MiniAuctionState<C> {
+    /// Nobody can lose more than they have
+    fn utility_bounds(&self) -> Option<UtilityBounds> {
+        Some(UtilityBounds::zero_sum((C::ANTE + C::STACK) as Utility))
+    }
+
+    fn new() -> Self {
+        debug_assert!(C::STACK <= MAX_STACK && C::RAISE_SIZES.len() <= MAX_RAISE_SIZES);
+        let deck: Vec<u8> = (0..C::DECK_SIZE).collect();
+        let active_player = Self::dealer(&deck);
+        MiniAuctionState {
+            deck,
+            cards: [vec![], vec![]],
+            contributions: [C::ANTE; 2],
+            stacks: [C::STACK; 2],
+            pips: [0; 2],
+            betting_rounds: 0,
+            raises: 0,
+            bids: [None, None],
+            active_player,
+            config: PhantomData,
+        }
+    }
+
+    fn observe_before(&mut self, action: &MiniAuctionAction) -> Vec<Observation<MiniAuctionAction>> {
+        match action {
+            MiniAuctionAction::Deal(card) => {
+                let player_num = self.dealt_to();
+                let seen = match C::STRENGTH_BUCKETS {
+                    0 => *action,
+                    _ => {
+                        let mut cards = self.cards[player_num].clone();
+                        cards.push(*card);
+                        MiniAuctionAction::Strength(Self::strength(&cards))
+                    }
+                };
+                vec![Observation::Shared(Information::Action(seen), vec![player_num])]
+            }
+            // Sealed until player 1's bid is in
+            MiniAuctionAction::Bid(_) if self.bids[0].is_none() => vec![],
+            MiniAuctionAction::Bid(bid1) => {
+                let bid0 = self.bids[0].unwrap();
+                let seen = Self::seen_bid(bid0) * (MAX_STACK + 1) + Self::seen_bid(*bid1 as u32);
+                vec![Observation::Public(Information::Action(MiniAuctionAction::Reveal(seen as u8)))]
+            }
+            _ => vec![Observation::Public(Information::Action(*action))],
+        }
+    }
+
+    fn active_player(&self) -> ActivePlayer<MiniAuctionAction> {
+        self.active_player.clone()
+    }
+
+    fn update(&mut self, action: MiniAuctionAction) {
+        let player_num = match &self.active_player {
+            ActivePlayer::Player(player_num, _) => *player_num as usize,
+            _ => self.dealt_to(),
+        };
+        self.active_player = match action {
+            MiniAuctionAction::Deal(card) => {
+                self.deck.retain(|c| *c != card);
+                self.cards[player_num].push(card);
+                match (self.cards[0].len(), self.cards[1].len()) {
+                    (1, 0) => Self::dealer(&self.deck),
+                    (1, 1) => self.betting(0),
+                    // The auction winner's card
+                    _ => self.betting(0),
+                }
+            }
+            MiniAuctionAction::Fold => self.folded(player_num),
+            MiniAuctionAction::Call => {
+                self.bet(player_num, self.to_call(player_num));
+                self.end_round()
+            }
+            MiniAuctionAction::Check => match player_num {
+                0 => self.betting(1),
+                _ => self.end_round(),
+            },
+            MiniAuctionAction::Raise(rung) => {
+                self.bet(player_num, self.to_call(player_num) + C::RAISE_SIZES[rung as usize]);
+                self.raises += 1;
+                self.betting(player_num ^ 1)
+            }
+            MiniAuctionAction::Bid(bid) => {
+                self.bids[player_num] = Some(bid as u32);
+                match player_num {
+                    0 => self.bidding(1),
+                    _ => self.auction(),
+                }
+            }
+            x => panic!("{:?} is only ever observed", x),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Debug)]
+    struct AbstractTiny;
+
+    impl MiniAuctionConfig for AbstractTiny {
+        const DECK_SIZE: u8 = 6;
+        const ANTE: u32 = 1;
+        const STACK: u32 = 4;
+        const RAISE_SIZES: &'static [u32] = &[2];
+        const MAX_RAISES: usize = 1;
+        const STRENGTH_BUCKETS: u8 = 2;
+        const BID_BUCKETS: &'static [u32] = &[0, 2, 4];
+    }
+
+    #[test]
+    fn test_auction_winner_pays_for_a_second_card() {
+        assert_eq!(check_index_round_trip::<MiniAuctionAction>(), Ok(()));
+
+        let mut state = MiniAuctionState::<ReducedConfig>::new();
+        state.update(MiniAuctionAction::Deal(19));
+        state.update(MiniAuctionAction::Deal(0));
+        state.update(MiniAuctionAction::Check);
+        state.update(MiniAuctionAction::Check);
+        assert_eq!(state.active_player().actions().len(), 5);
+        state.update(MiniAuctionAction::Bid(1));
+        state.update(MiniAuctionAction::Bid(3));
+        // Player 1 won and pays player 0's bid, then gets their card
+        assert_eq!(state.stacks(), [4, 3]);
+        state.update(MiniAuctionAction::Deal(1));
+        assert_eq!(state.cards[1], [0, 1]);
+        state.update(MiniAuctionAction::Check);
+        state.update(MiniAuctionAction::Check);
+        // A pair of the lowest rank beats the highest card
+        assert_eq!(state.active_player(), ActivePlayer::Terminal(vec![-1.0, 1.0]));
+
+        // Bids tie, both pay and the hand goes on without a card
+        let mut state = MiniAuctionState::<AbstractTiny>::new();
+        state.update(MiniAuctionAction::Deal(5));
+        state.update(MiniAuctionAction::Deal(4));
+        state.update(MiniAuctionAction::Raise(0));
+        state.update(MiniAuctionAction::Call);
+        // Only the middles of the buckets the remaining 2 chips reach
+        assert_eq!(state.active_player().actions(), &[MiniAuctionAction::Bid(0), MiniAuctionAction::Bid(1)]);
+        state.update(MiniAuctionAction::Bid(1));
+        state.update(MiniAuctionAction::Bid(1));
+        assert_eq!(state.stacks(), [1, 1]);
+        assert_eq!(state.active_player().actions(), &[MiniAuctionAction::Check]);
+        state.update(MiniAuctionAction::Check);
+        state.update(MiniAuctionAction::Check);
+        assert_eq!(state.active_player(), ActivePlayer::Terminal(vec![0.0, 0.0]));
+
+        // The abstraction only sees two strengths, a pair being the top one
+        assert_eq!(MiniAuctionState::<AbstractTiny>::strength(&[0]), 0);
+        assert_eq!(MiniAuctionState::<AbstractTiny>::strength(&[5]), 0);
+        assert_eq!(MiniAuctionState::<AbstractTiny>::strength(&[4, 5]), 1);
+    }
+}
@@ -1,3 +1,12 @@
 pub mod auction;
+pub mod auction_subgame;
+pub mod audit;
+pub mod cards;
+pub mod conservation;
 pub mod goofspiel;
+pub mod json_game;
 pub mod kuhn_poker;
+pub mod ledger;
+pub mod mini_auction;
+pub mod notation;
+pub mod one_card_poker;
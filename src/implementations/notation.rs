@@ -0,0 +1,222 @@
+/// A one line notation for auction poker hands, so a situation can be
+/// pasted into a test or a bug report and replayed exactly.
+///
+///     AsKs|4h5h r6 c / QsJsTs b10 b3 +9c k r4 c / 2h k k / 3d k
+///
+/// The hole cards come first, player 0's then player 1's. Then every
+/// action in the order it was played, separated by spaces:
+///
+///     f c k       fold, call, check
+///     r<chips>    raise *to* chips (the pip, like the engine's R)
+///     b<chips>    a bid, player 1 bids first
+///     +<card>     the third hole card after the auction (two after a tie,
+///                 player 0's first)
+///     / <cards>   the next street's board cards
+///
+/// Markers (round starts and ends, the auction result) are left out and
+/// filled back in when parsing. A hand can stop anywhere, cards dealt to
+/// nobody yet are just left out
+use crate::game_logic::action::Parsable;
+use crate::game_logic::state::{ActivePlayer, State};
+use crate::implementations::auction::*;
+use crate::implementations::auction::RelativeSize::*;
+
+fn card_name(card: CardIndex) -> String {
+    Card::from_index(card).to_string().unwrap()
+}
+
+fn parse_cards(cards: &str) -> Result<Vec<Card>, String> {
+    if !cards.len().is_multiple_of(2) {
+        return Err(format!("{} is not a list of cards", cards));
+    }
+    (0..cards.len())
+        .step_by(2)
+        .map(|i| Card::parse(&cards[i..i + 2]).ok_or_else(|| format!("{} is not a card", &cards[i..i + 2])))
+        .collect()
+}
+
+/// Plays markers until the state needs a deal or a decision
+fn play_markers(state: &mut AuctionPokerState, actions: &mut Vec<AuctionPokerAction>) {
+    while let ActivePlayer::Marker(marker) = state.active_player() {
+        actions.push(marker.clone());
+        state.update(marker);
+    }
+}
+
+/// The notation of `history`, which may leave out markers. Fails on the
+/// first action that isn't legal where it is played
+pub fn format_hand(history: &[AuctionPokerAction]) -> Result<String, String> {
+    let mut state = AuctionPokerState::new();
+    let mut holes = [String::new(), String::new()];
+    let mut tokens: Vec<String> = vec![];
+    let mut board_token: Option<usize> = None;
+    for action in history {
+        if matches!(state.active_player(), ActivePlayer::Marker(_)) && !is_marker(action) {
+            play_markers(&mut state, &mut vec![]);
+        }
+        let legal = match state.active_player() {
+            ActivePlayer::Player(_, actions) => actions,
+            ActivePlayer::Chance(distribution) => distribution.items().clone(),
+            ActivePlayer::Marker(marker) => vec![marker],
+            ActivePlayer::Terminal(_) => vec![],
+        };
+        if !legal.contains(action) {
+            return Err(format!("{:?} is not legal after {}", action, tokens.join(" ")));
+        }
+        let pot = state.pot();
+        match action {
+            AuctionPokerAction::DealHole(card, player_num) if holes[*player_num].len() < 4 => {
+                holes[*player_num].push_str(&card_name(*card))
+            }
+            AuctionPokerAction::DealHole(card, _) => tokens.push(format!("+{}", card_name(*card))),
+            AuctionPokerAction::DealCommunity(card) => match board_token {
+                Some(index) => tokens[index].push_str(&card_name(*card)),
+                None => {
+                    tokens.push("/".to_string());
+                    tokens.push(card_name(*card));
+                    board_token = Some(tokens.len() - 1);
+                }
+            },
+            AuctionPokerAction::Fold => tokens.push("f".to_string()),
+            AuctionPokerAction::Call => tokens.push("c".to_string()),
+            AuctionPokerAction::Check => tokens.push("k".to_string()),
+            AuctionPokerAction::Raise(size) => tokens.push(format!("r{}", size.to_amount(pot))),
+            AuctionPokerAction::Bid(size) => tokens.push(format!("b{}", size.to_amount(pot))),
+            _ => {}
+        }
+        if !matches!(action, AuctionPokerAction::DealCommunity(_)) {
+            board_token = None;
+        }
+        state.update(action.clone());
+    }
+    let mut line = format!("{}|{}", holes[0], holes[1]);
+    for token in tokens {
+        line.push(' ');
+        line.push_str(&token);
+    }
+    Ok(line)
+}
+
+/// The history written as `line` (see format_hand), markers included, so
+/// that it can be played on an AuctionPokerState as it is
+pub fn parse_hand(line: &str) -> Result<Vec<AuctionPokerAction>, String> {
+    let mut tokens = line.split_whitespace();
+    let holes = tokens.next().ok_or("A hand starts with the hole cards")?;
+    let (first, second) = holes.split_once('|').ok_or_else(|| format!("{} is not two players' hole cards", holes))?;
+    let mut deals = vec![];
+    for (player_num, cards) in [first, second].into_iter().enumerate() {
+        for card in parse_cards(cards)? {
+            deals.push(AuctionPokerAction::DealHole(card.as_u8() as CardIndex, player_num));
+        }
+    }
+
+    let mut state = AuctionPokerState::new();
+    let mut actions = vec![];
+    for token in tokens {
+        let pot = state.pot();
+        let parsed: Vec<AuctionPokerAction> = match token {
+            "/" => continue,
+            "f" => vec![AuctionPokerAction::Fold],
+            "c" => vec![AuctionPokerAction::Call],
+            "k" => vec![AuctionPokerAction::Check],
+            _ if token.starts_with('r') || token.starts_with('b') => {
+                let chips: u32 = token[1..].parse().map_err(|_| format!("{} has no amount", token))?;
+                match token.starts_with('r') {
+                    true => vec![AuctionPokerAction::Raise(DeciPercent(Amount(chips).to_percent(pot)))],
+                    false => vec![AuctionPokerAction::Bid(Amount(chips))],
+                }
+            }
+            _ if token.starts_with('+') => {
+                // Whoever the state deals to next, the auction decides that
+                let card = Card::parse(&token[1..]).ok_or_else(|| format!("{} is not a card", token))?;
+                play_markers(&mut state, &mut actions);
+                let player_num = match state.active_player() {
+                    ActivePlayer::Chance(distribution) => match distribution.items().first() {
+                        Some(AuctionPokerAction::DealHole(_, player_num)) => *player_num,
+                        _ => return Err(format!("{} comes when nobody is dealt a hole card", token)),
+                    },
+                    _ => return Err(format!("{} comes when nobody is dealt a hole card", token)),
+                };
+                vec![AuctionPokerAction::DealHole(card.as_u8() as CardIndex, player_num)]
+            }
+            _ => parse_cards(token)?
+                .into_iter()
+                .map(|card| AuctionPokerAction::DealCommunity(card.as_u8() as CardIndex))
+                .collect(),
+        };
+        for action in deals.drain(..).chain(parsed) {
+            play(&mut state, &mut actions, action, token)?;
+        }
+    }
+    for deal in deals {
+        play(&mut state, &mut actions, deal, holes)?;
+    }
+    play_markers(&mut state, &mut actions);
+    Ok(actions)
+}
+
+/// Plays `action`, written as `token`, after any markers before it
+fn play(
+    state: &mut AuctionPokerState,
+    actions: &mut Vec<AuctionPokerAction>,
+    action: AuctionPokerAction,
+    token: &str,
+) -> Result<(), String> {
+    play_markers(state, actions);
+    let legal = match state.active_player() {
+        ActivePlayer::Player(_, actions) => actions,
+        ActivePlayer::Chance(distribution) => distribution.items().clone(),
+        _ => vec![],
+    };
+    if !legal.contains(&action) {
+        return Err(format!("{} ({:?}) is not legal here", token, action));
+    }
+    actions.push(action.clone());
+    state.update(action);
+    Ok(())
+}
+
+fn is_marker(action: &AuctionPokerAction) -> bool {
+    matches!(
+        action,
+        AuctionPokerAction::BettingRoundStart
+            | AuctionPokerAction::BettingRoundEnd
+            | AuctionPokerAction::AuctionStart
+            | AuctionPokerAction::PlayerActionEnd(_)
+            | AuctionPokerAction::Auction(_)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hands_round_trip() {
+        let line = "AsKs|4h5h r6 c / QsJsTs b10 b3 +9c k r4 c / 2h k k / 3d k";
+        let history = parse_hand(line).unwrap();
+        assert_eq!(format_hand(&history).unwrap(), line);
+
+        let mut state = AuctionPokerState::new();
+        for action in &history {
+            state.update(action.clone());
+        }
+        assert_eq!(state.community_cards().len(), 5);
+        assert_eq!(state.winner(), Some(Winner::Player(1)));
+        assert_eq!(state.hand(1).cards.len(), 3);
+        assert!(matches!(state.active_player(), ActivePlayer::Player(0, _)));
+
+        // Without the markers the notation is the same
+        let decisions: Vec<_> = history.iter().filter(|action| !is_marker(action)).cloned().collect();
+        assert_eq!(format_hand(&decisions).unwrap(), line);
+
+        // Partial and tied hands
+        assert_eq!(format_hand(&parse_hand("As|").unwrap()).unwrap(), "As|");
+        let tie = "AsKs|4h5h c / QsJsTs b5 b5 +9c +8c";
+        assert_eq!(format_hand(&parse_hand(tie).unwrap()).unwrap(), tie);
+
+        assert!(parse_hand("AsKs|4h5h k").is_err());
+        assert!(parse_hand("AsKs|4h5h c / QsJsTs b5 b5 +Qs").is_err());
+        assert!(parse_hand("AsKx|4h5h").is_err());
+    }
+}
@@ -0,0 +1,328 @@
+//! A configurable family of "one-card poker" toy games. Each player antes,
+//! receives a single private card from a deck of `DECK_SIZE` ranks, and
+//! the players take turns raising by any of a fixed set of chip amounts.
+//!
+//! Kuhn poker is the smallest member of the family, but larger decks and
+//! more raise sizes let us study how the bet abstraction affects the
+//! trained strategy long before paying for a full auction poker run.
+use crate::distribution::Categorical;
+use crate::game_logic::action::*;
+use crate::game_logic::state::{ActivePlayer, State, UtilityBounds};
+use crate::game_logic::visibility::{Information, Observation};
+use crate::Utility;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+/// The largest number of distinct raise sizes a config may declare
+pub const MAX_RAISE_SIZES: usize = 16;
+
+/// Describes a single member of the one-card poker family
+pub trait OneCardPokerConfig: Clone + Debug {
+    /// Number of distinct cards (ranks) in the deck
+    const DECK_SIZE: u8;
+    /// Maximum number of raises allowed before players may only call or fold
+    const MAX_RAISES: usize;
+    /// Chips each player puts in the pot before the cards are dealt
+    const ANTE: u32;
+    /// Available raise sizes, in chips on top of the opponent's
+    /// contribution. Any of them can be chosen at every raise
+    const RAISE_SIZES: &'static [u32];
+}
+
+/// Kuhn poker expressed as a one-card poker config
+#[derive(Clone, Debug)]
+pub struct KuhnConfig;
+
+impl OneCardPokerConfig for KuhnConfig {
+    const DECK_SIZE: u8 = 3;
+    const MAX_RAISES: usize = 1;
+    const ANTE: u32 = 1;
+    const RAISE_SIZES: &'static [u32] = &[1];
+}
+
+/// A bigger deck with raises of 2, 4 or 8 chips and re-raises. Over the
+/// antes alone those are half, one and two pots, after a raise they are
+/// less
+#[derive(Clone, Debug)]
+pub struct LadderConfig;
+
+impl OneCardPokerConfig for LadderConfig {
+    const DECK_SIZE: u8 = 13;
+    const MAX_RAISES: usize = 3;
+    const ANTE: u32 = 2;
+    const RAISE_SIZES: &'static [u32] = &[2, 4, 8];
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Copy, Hash)]
+pub enum OneCardPokerAction {
+    Fold,
+    Call,
+    Check,
+    Raise(u8), // Index into the config's raise ladder
+    Deal(u8),  // Card dealt to the next player without one
+}
+
+impl Parsable for OneCardPokerAction {
+    fn to_string(&self) -> Option<String> {
+        None
+    }
+
+    fn to_usize(&self) -> Option<usize> {
+        match self {
+            OneCardPokerAction::Raise(rung) => Some(*rung as usize),
+            _ => None,
+        }
+    }
+}
+
+impl From<OneCardPokerAction> for ActionIndex {
+    fn from(action: OneCardPokerAction) -> Self {
+        match action {
+            OneCardPokerAction::Fold => 0,
+            OneCardPokerAction::Call => 1,
+            OneCardPokerAction::Check => 2,
+            OneCardPokerAction::Raise(rung) => 3 + rung,
+            // Chance actions never index a policy, the deals get what is
+            // left above 127 (widened first, a deck may not fit)
+            OneCardPokerAction::Deal(card) => ActionIndex::try_from(128 + card as u16)
+                .unwrap_or_else(|_| panic!("Card {} is past the last action index", card)),
+        }
+    }
+}
+
+impl From<ActionIndex> for OneCardPokerAction {
+    fn from(index: ActionIndex) -> Self {
+        match index {
+            0 => OneCardPokerAction::Fold,
+            1 => OneCardPokerAction::Call,
+            2 => OneCardPokerAction::Check,
+            3..=127 => OneCardPokerAction::Raise(index - 3),
+            _ => OneCardPokerAction::Deal(index - 128),
+        }
+    }
+}
+
+impl Filterable for OneCardPokerAction {}
+impl Action for OneCardPokerAction {
+    fn max_index() -> ActionIndex {
+        3 + MAX_RAISE_SIZES as ActionIndex
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OneCardPokerState<C: OneCardPokerConfig> {
+    deck: Vec<u8>,
+    players_cards: [Option<u8>; 2],
+    contributions: [u32; 2],
+    raises: usize,
+    active_player: ActivePlayer<OneCardPokerAction>,
+    config: PhantomData<C>,
+}
+
+pub type KuhnLikeState = OneCardPokerState<KuhnConfig>;
+pub type LadderPokerState = OneCardPokerState<LadderConfig>;
+
+impl<C: OneCardPokerConfig> OneCardPokerState<C> {
+    fn dealer(deck: &[u8]) -> ActivePlayer<OneCardPokerAction> {
+        let deals = deck
+            .iter()
+            .map(|card| OneCardPokerAction::Deal(*card))
+            .collect::<Vec<_>>();
+        ActivePlayer::Chance(Categorical::uniform(deals))
+    }
+
+    /// Legal actions for `player_num` given the current contributions
+    fn betting(&self, player_num: usize) -> ActivePlayer<OneCardPokerAction> {
+        debug_assert!(C::RAISE_SIZES.len() <= MAX_RAISE_SIZES);
+        let mut actions = Vec::new();
+        let facing_raise = self.contributions[player_num] < self.contributions[player_num ^ 1];
+        if facing_raise {
+            actions.push(OneCardPokerAction::Fold);
+            actions.push(OneCardPokerAction::Call);
+        } else {
+            actions.push(OneCardPokerAction::Check);
+        }
+        if self.raises < C::MAX_RAISES {
+            for rung in 0..C::RAISE_SIZES.len() {
+                actions.push(OneCardPokerAction::Raise(rung as u8));
+            }
+        }
+        ActivePlayer::Player(player_num as u32, actions)
+    }
+
+    fn folded(&self, player_num: usize) -> ActivePlayer<OneCardPokerAction> {
+        let delta = self.contributions[player_num] as Utility;
+        match player_num {
+            0 => ActivePlayer::Terminal(vec![-delta, delta]),
+            1 => ActivePlayer::Terminal(vec![delta, -delta]),
+            _ => panic!("Invalid player number"),
+        }
+    }
+
+    fn showdown(&self) -> ActivePlayer<OneCardPokerAction> {
+        debug_assert_eq!(self.contributions[0], self.contributions[1]);
+        let delta = self.contributions[0] as Utility;
+        let card0 = self.players_cards[0].expect("Player 0 has no card at showdown");
+        let card1 = self.players_cards[1].expect("Player 1 has no card at showdown");
+        if card0 > card1 {
+            ActivePlayer::Terminal(vec![delta, -delta])
+        } else {
+            ActivePlayer::Terminal(vec![-delta, delta])
+        }
+    }
+
+    pub fn contributions(&self) -> [u32; 2] {
+        self.contributions
+    }
+}
+
+impl<C: OneCardPokerConfig> State<OneCardPokerAction> for OneCardPokerState<C> {
+    /// The ante and every raise at the largest size at most
+    fn utility_bounds(&self) -> Option<UtilityBounds> {
+        let largest = C::RAISE_SIZES.iter().max().copied().unwrap_or(0);
+        Some(UtilityBounds::zero_sum((C::ANTE + C::MAX_RAISES as u32 * largest) as Utility))
+    }
+
+    fn new() -> Self {
+        let deck = (0..C::DECK_SIZE).collect::<Vec<u8>>();
+        let active_player = Self::dealer(&deck);
+        OneCardPokerState {
+            deck,
+            players_cards: [None, None],
+            contributions: [C::ANTE, C::ANTE],
+            raises: 0,
+            active_player,
+            config: PhantomData,
+        }
+    }
+
+    fn observe_before(
+        &mut self,
+        action: &OneCardPokerAction,
+    ) -> Vec<Observation<OneCardPokerAction>> {
+        let observation = match action {
+            OneCardPokerAction::Deal(_) => {
+                let player_num = match self.players_cards {
+                    [None, _] => 0,
+                    [Some(_), None] => 1,
+                    _ => panic!("Both players already have a card!"),
+                };
+                Observation::Shared(Information::Action(*action), vec![player_num])
+            }
+            _ => Observation::Public(Information::Action(*action)),
+        };
+        vec![observation]
+    }
+
+    fn active_player(&self) -> ActivePlayer<OneCardPokerAction> {
+        self.active_player.clone()
+    }
+
+    fn update(&mut self, action: OneCardPokerAction) {
+        match action {
+            OneCardPokerAction::Deal(card) => {
+                self.deck.retain(|c| *c != card);
+                if self.players_cards[0].is_none() {
+                    self.players_cards[0] = Some(card);
+                    self.active_player = Self::dealer(&self.deck);
+                } else {
+                    self.players_cards[1] = Some(card);
+                    self.active_player = self.betting(0);
+                }
+            }
+            OneCardPokerAction::Fold => {
+                let player_num = self.active_player.player_num();
+                self.active_player = self.folded(player_num);
+            }
+            OneCardPokerAction::Call => {
+                let player_num = self.active_player.player_num();
+                self.contributions[player_num] = self.contributions[player_num ^ 1];
+                self.active_player = self.showdown();
+            }
+            OneCardPokerAction::Check => {
+                let player_num = self.active_player.player_num();
+                self.active_player = match player_num {
+                    0 => self.betting(1),
+                    _ => self.showdown(),
+                };
+            }
+            OneCardPokerAction::Raise(rung) => {
+                let player_num = self.active_player.player_num();
+                let size = C::RAISE_SIZES[rung as usize];
+                self.contributions[player_num] = self.contributions[player_num ^ 1] + size;
+                self.raises += 1;
+                self.active_player = self.betting(player_num ^ 1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_action_indices_round_trip() {
+        assert_eq!(check_index_round_trip::<OneCardPokerAction>(), Ok(()));
+        assert_eq!(OneCardPokerAction::Deal(127).index(), 255);
+    }
+
+    #[test]
+    #[should_panic(expected = "past the last action index")]
+    fn test_deals_past_the_index_space_panic() {
+        OneCardPokerAction::Deal(128).index();
+    }
+
+    #[test]
+    fn test_kuhn_config_matches_kuhn_poker() {
+        let mut state = KuhnLikeState::new();
+        state.update(OneCardPokerAction::Deal(2));
+        state.update(OneCardPokerAction::Deal(0));
+        assert_eq!(
+            state.active_player().actions(),
+            &[OneCardPokerAction::Check, OneCardPokerAction::Raise(0)]
+        );
+        state.update(OneCardPokerAction::Raise(0));
+        // Only a single raise is allowed in Kuhn poker
+        assert_eq!(
+            state.active_player().actions(),
+            &[OneCardPokerAction::Fold, OneCardPokerAction::Call]
+        );
+        state.update(OneCardPokerAction::Call);
+        assert_eq!(state.active_player(), ActivePlayer::Terminal(vec![2.0, -2.0]));
+    }
+
+    #[test]
+    fn test_raise_ladder_depth() {
+        let mut state = LadderPokerState::new();
+        state.update(OneCardPokerAction::Deal(3));
+        state.update(OneCardPokerAction::Deal(7));
+        for _ in 0..LadderConfig::MAX_RAISES {
+            assert!(state
+                .active_player()
+                .actions()
+                .contains(&OneCardPokerAction::Raise(2)));
+            state.update(OneCardPokerAction::Raise(2));
+        }
+        assert!(!state
+            .active_player()
+            .actions()
+            .iter()
+            .any(|x| matches!(x, OneCardPokerAction::Raise(_))));
+        assert_eq!(state.contributions(), [2 + 8 + 8 + 8, 2 + 8 + 8]);
+        state.update(OneCardPokerAction::Fold);
+        assert_eq!(state.active_player(), ActivePlayer::Terminal(vec![18.0, -18.0]));
+    }
+
+    #[test]
+    fn test_deck_shrinks_after_deal() {
+        let mut state = LadderPokerState::new();
+        assert_eq!(state.active_player().actions().len(), 13);
+        state.update(OneCardPokerAction::Deal(5));
+        assert_eq!(state.active_player().actions().len(), 12);
+        assert!(!state
+            .active_player()
+            .actions()
+            .contains(&OneCardPokerAction::Deal(5)));
+    }
+}
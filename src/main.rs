@@ -1,32 +1,648 @@
 mod algorithm;
+mod bot;
+mod config;
 mod constants;
+mod context;
 mod distribution;
 mod eval;
 mod game_logic;
 pub mod implementations;
+mod repl;
+mod smoke;
+mod toml;
+mod units;
 mod util;
 
 pub use self::algorithm::mccfr_parallel::MCCFRParallel;
-pub use self::constants::HOT_ENCODING_SIZE;
 pub use self::distribution::Categorical;
 pub use self::game_logic::game::Game;
 use crate::implementations::auction::*;
+use crate::implementations::conservation::*;
+use crate::implementations::notation::parse_hand;
+use crate::game_logic::action::{Action, GameMapper};
 use crate::implementations::kuhn_poker::*;
+use crate::implementations::mini_auction::*;
 
 
+use crate::game_logic::constraint_check::*;
 use crate::game_logic::strategy::blueprint::*;
+use crate::game_logic::strategy::convert::convert_pipeline;
+use crate::game_logic::strategy::import::{import_with, ImportFormat, ImportMapping};
+use crate::game_logic::strategy::report;
+use crate::game_logic::visibility::Round;
+use crate::algorithm::abstraction_loss::{measure_abstraction_loss, Training};
+use crate::algorithm::distributed::*;
+use crate::algorithm::dry_run::dry_run as run_dry_run;
+use crate::algorithm::estimate::*;
+use crate::bot::ablation::*;
+use crate::bot::acpc::{AcpcClient, AcpcGame};
+use crate::bot::agent::Agent;
+use crate::bot::auction_values::*;
+use crate::bot::decision::DecisionEngine;
+use crate::bot::lbr::*;
+use crate::bot::match_context::MatchContext;
+use crate::bot::player::AuctionBot;
+use crate::bot::ranges::write_ranges;
+use crate::bot::runner::Runner;
+use crate::bot::sanity::check_sanity;
+use crate::bot::streets::*;
+use crate::bot::what_if::what_if;
+use crate::config::BotConfig;
+use crate::constants::NUM_REGULAR_PLAYERS;
+use crate::context::Context;
+use rand::{rngs::StdRng, FromEntropy};
+use std::time::Duration;
 
+/// The one numeric type of utilities, and so of the regrets and values
+/// computed from them. Everything that handles a utility names it as this
+/// rather than f32, so changing the precision is a one line change
 pub type Utility = f32;
 
+/// `gtcogs convert <input>[,<input>...] <output> [<output>...]` converts
+/// strategy files without training anything, see strategy::convert. The
+/// last output is also split into the per round shards match_bot loads
+fn convert(args : &[String]) {
+    if args.len() < 2 {
+        println!("Usage: gtcogs convert <input>[,<input>...] <output> [<output>...]");
+        std::process::exit(1);
+    }
+    let inputs : Vec<String> = args[0].split(',').map(|s| s.to_string()).collect();
+    if let Err(error) = convert_pipeline(&inputs, &args[1..], AuctionPokerAction::abstraction_hash()) {
+        println!("[ERROR] {}", error);
+        std::process::exit(1);
+    }
+    let blueprint = &args[args.len() - 1];
+    if !shards_are_current(blueprint) {
+        BlueprintStrategy::load(blueprint).save_sharded(&shard_directory(blueprint));
+    }
+}
+
+fn parse_or_exit<T: std::str::FromStr>(arg : Option<&String>, usage : &str) -> T {
+    match arg.and_then(|arg| arg.parse().ok()) {
+        Some(value) => value,
+        None => {
+            println!("Usage: {}", usage);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// `gtcogs import <openspiel|acpc> <strategy file> <mapping file> <output>`
+/// turns another tool's strategy into a blueprint, see strategy::import
+fn import(args : &[String]) {
+    if args.len() < 4 {
+        println!("Usage: gtcogs import <openspiel|acpc> <strategy file> <mapping file> <output>");
+        std::process::exit(1);
+    }
+    let result = ImportFormat::parse(&args[0]).and_then(|format| {
+        let mapping = ImportMapping::load(&args[2])?;
+        import_with(format, &args[1], NUM_REGULAR_PLAYERS, &mapping)
+    });
+    match result {
+        Ok(strategy) => strategy.save(&args[3]),
+        Err(error) => {
+            println!("[ERROR] {}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// `gtcogs coordinator <address> <workers> [<file name>]` merges the rounds
+/// of distributed workers, see algorithm::distributed
+fn coordinator(args : &[String]) {
+    let usage = "gtcogs coordinator <address> <workers> [<file name>]";
+    let workers : usize = parse_or_exit(args.get(1), usage);
+    let result = Coordinator::bind(&args[0], workers, 2).and_then(|mut coordinator| {
+        // The port the workers need when bound to port 0
+        println!("Waiting for {} workers on {}", workers, coordinator.local_addr()?);
+        coordinator.run::<AuctionPokerAction>(args.get(2).map(|s| s.as_str()))
+    });
+    match result {
+        Ok(rounds) => println!("All workers done after {} rounds", rounds),
+        Err(error) => {
+            println!("[ERROR] {}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// `gtcogs worker <coordinator address> <rounds> <iterations per round>` or
+/// `gtcogs worker --dir <directory> <worker> <workers> <rounds> <iterations per round> [<timeout seconds>]`
+/// trains as one of several distributed workers
+fn worker(args : &[String]) {
+    let usage = "gtcogs worker (<coordinator address> | --dir <directory> <worker> <workers>) <rounds> <iterations> [<timeout seconds>]";
+    let config = bot_config();
+    check_limits(&config);
+    let (exchange, rest) : (Result<Box<dyn Exchange>, String>, &[String]) = match args.first().map(|s| s.as_str()) {
+        Some("--dir") if args.len() >= 4 => {
+            let worker : usize = parse_or_exit(args.get(2), usage);
+            let workers : usize = parse_or_exit(args.get(3), usage);
+            let mut exchange = FileExchange::new(&args[1], worker, workers);
+            if args.get(6).is_some() {
+                let seconds : u64 = parse_or_exit(args.get(6), usage);
+                exchange = exchange.map(|e| e.with_timeout(Duration::from_secs(seconds)));
+            }
+            (exchange.map(|e| Box::new(e) as Box<dyn Exchange>), &args[4..])
+        }
+        Some(address) => (TcpExchange::connect(address).map(|e| Box::new(e) as Box<dyn Exchange>), &args[1..]),
+        None => (Err(usage.to_string()), args),
+    };
+    let rounds : usize = parse_or_exit(rest.first(), usage);
+    let iterations : usize = parse_or_exit(rest.get(1), usage);
+    // Without a coordinator to save the strategies the first worker does
+    let saves = args.first().map(|s| s.as_str()) == Some("--dir") && args.get(2).map(|s| s.as_str()) == Some("0");
+    let result = exchange.and_then(|mut exchange| {
+        let mut mcp = MCCFRParallel::<AuctionPokerAction, AuctionPokerState>::new(config.training.threads, None)
+            .with_context(Context::from_config(&config)?);
+        mcp.run_distributed(rounds, iterations, config.training.epsilon, exchange.as_mut())?;
+        if saves {
+            mcp.write_to(&config.paths.strategy);
+        }
+        Ok(())
+    });
+    if let Err(error) = result {
+        println!("[ERROR] {}", error);
+        std::process::exit(1);
+    }
+}
+
+/// The bot `play` and `acpc` put in front of an engine, with the blueprint
+/// in `file_name` and everything else from the config
+fn match_bot(config : &BotConfig, file_name : &str) -> AuctionBot {
+    let evaluator = config.evaluator().expect("validated with the config");
+    let blueprint = match BlueprintStrategy::load_lazily(file_name).with_evaluator(evaluator) {
+        Ok(blueprint) => blueprint.with_cutoff(config.play.blueprint_cutoff),
+        Err(error) => {
+            println!("[ERROR] {}", error);
+            std::process::exit(1);
+        }
+    };
+    let mut engine = DecisionEngine::new(blueprint).with_bid_sizer(config.bid_sizer());
+    engine.exploit_bids = config.play.exploit_bids;
+    engine.continuous_sizing = config.play.continuous_sizing;
+    if config.time.prefetch_branches > 0 {
+        engine = engine.with_prefetch(config.time.prefetch_branches);
+    }
+    if let Some(interval) = config.reload_interval() {
+        engine = engine.with_blueprint_watch(file_name, interval);
+    }
+    let mut bot = AuctionBot::with_agent(Agent::new(engine).with_config(config.agent_config()));
+    bot.budget = config.decision_budget();
+    bot.raise_jitter = config.play.raise_jitter;
+    bot
+}
+
+/// `gtcogs play <engine address> [<blueprint file>]` plays against the engine
+/// at the address with the blueprint and the resolver, see bot::agent
+fn play(args : &[String]) {
+    if args.is_empty() {
+        println!("Usage: gtcogs play <engine address> [<blueprint file>]");
+        std::process::exit(1);
+    }
+    let config = bot_config();
+    let file_name = args.get(1).unwrap_or(&config.paths.blueprint);
+    let mut runner = Runner::connect(match_bot(&config, file_name), &args[0]);
+    runner.run();
+    print_match_summary(&runner.context, runner.bot());
+}
+
+/// `gtcogs acpc <host> <port> [<blueprint file>]` plays the same bot against
+/// an ACPC dealer (no limit, reverse blinds), see bot::acpc
+fn acpc(args : &[String]) {
+    let usage = "gtcogs acpc <host> <port> [<blueprint file>]";
+    if args.is_empty() {
+        println!("Usage: {}", usage);
+        std::process::exit(1);
+    }
+    let port : u16 = parse_or_exit(args.get(1), usage);
+    let config = bot_config();
+    let file_name = args.get(2).unwrap_or(&config.paths.blueprint);
+    let address = format!("{}:{}", args[0], port);
+    let mut client = AcpcClient::connect(match_bot(&config, file_name), AcpcGame::nolimit_reverse_blinds(), &address);
+    client.run();
+    print_match_summary(&client.runner().context, client.runner().bot());
+}
+
+/// What play and acpc print once the match is over
+fn print_match_summary(context : &MatchContext, bot : &AuctionBot) {
+    println!("Played {}", context.win_rate());
+    let memo = bot.engine().memo();
+    println!("The memo answered {} of {} blueprint lookups", memo.hits(), memo.lookups());
+}
+
+/// `gtcogs report <blueprint file> <round> <feature>[,<feature>...] [<width>]`
+/// prints what the blueprint does per bucket of the features, see strategy::report
+fn report(args : &[String]) {
+    let usage = "gtcogs report <blueprint file> <round> <feature>[,<feature>...] [<width>]";
+    if args.len() < 3 {
+        println!("Usage: {}", usage);
+        std::process::exit(1);
+    }
+    let round = match SHARD_NAMES.iter().position(|name| *name == args[1]) {
+        Some(round) => Round::from(round),
+        None => {
+            println!("[ERROR] {} is not a round, one of {:?}", args[1], SHARD_NAMES);
+            std::process::exit(1);
+        }
+    };
+    let width : u8 = match args.get(3) {
+        Some(_) => parse_or_exit(args.get(3), usage),
+        None => 1,
+    };
+    let features : Vec<&str> = args[2].split(',').collect();
+    let blueprint = BlueprintStrategy::load(&args[0]);
+    for player_num in 0..2 {
+        match report::report(&blueprint, player_num, round.clone(), &features, width) {
+            Ok(report) => println!("Player {}, {}", player_num, report),
+            Err(error) => {
+                println!("[ERROR] {}", error);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// `gtcogs ablate <iterations> <hands> [<time bank seconds>]` trains the
+/// abstraction without each of a few features and plays the results against
+/// the full one, see bot::ablation
+fn ablate(args : &[String]) {
+    let usage = "gtcogs ablate <iterations> <hands> [<time bank seconds>]";
+    let mut config = AblationConfig {
+        iterations : parse_or_exit(args.first(), usage),
+        hands : parse_or_exit(args.get(1), usage),
+        ..Default::default()
+    };
+    if args.get(2).is_some() {
+        let seconds : u64 = parse_or_exit(args.get(2), usage);
+        config.time_bank = Duration::from_secs(seconds);
+    }
+    let results = run_ablation(&config, &default_variants());
+    println!("All variants done");
+    for result in results {
+        println!("{}", result);
+    }
+}
+
+/// `gtcogs streets <blueprint> [<hands>] [<samples>] [<opponent blueprint>]`
+/// splits what the blueprint wins by street, see bot::streets
+fn streets(args : &[String]) {
+    let usage = "gtcogs streets <blueprint> [<hands>] [<samples>] [<opponent blueprint>]";
+    if args.is_empty() {
+        println!("Usage: {}", usage);
+        std::process::exit(1);
+    }
+    let defaults = StreetConfig::default();
+    let config = StreetConfig {
+        hands : args.get(1).map(|_| parse_or_exit(args.get(1), usage)).unwrap_or(defaults.hands),
+        samples : args.get(2).map(|_| parse_or_exit(args.get(2), usage)).unwrap_or(defaults.samples),
+        ..defaults
+    };
+    let blueprint = BlueprintStrategy::load(&args[0]);
+    let opponent = match args.get(3) {
+        Some(file_name) => BlueprintStrategy::load(file_name),
+        None => blueprint.clone(),
+    };
+    println!("{}", street_values([&blueprint, &opponent], &config));
+}
+
+/// `gtcogs auction-values <blueprint> [<hands>] [<samples>] [<opponent
+/// blueprint>]` values the auction per hand class under the blueprints'
+/// postflop play, see bot::auction_values
+fn auction_value(args : &[String]) {
+    let usage = "gtcogs auction-values <blueprint> [<hands>] [<samples>] [<opponent blueprint>]";
+    if args.is_empty() {
+        println!("Usage: {}", usage);
+        std::process::exit(1);
+    }
+    let defaults = AuctionValueConfig::default();
+    let config = AuctionValueConfig {
+        hands : args.get(1).map(|_| parse_or_exit(args.get(1), usage)).unwrap_or(defaults.hands),
+        samples : args.get(2).map(|_| parse_or_exit(args.get(2), usage)).unwrap_or(defaults.samples),
+        ..defaults
+    };
+    let blueprint = BlueprintStrategy::load(&args[0]);
+    let opponent = match args.get(3) {
+        Some(file_name) => BlueprintStrategy::load(file_name),
+        None => blueprint.clone(),
+    };
+    println!("{}", auction_values([&blueprint, &opponent], &config));
+}
+
+/// `gtcogs ranges <blueprint> <json file> <line>...` exports the range and
+/// action frequencies after every line for the range explorer, see
+/// bot::ranges
+fn ranges(args : &[String]) {
+    if args.len() < 3 {
+        println!("Usage: gtcogs ranges <blueprint> <json file> <line>...");
+        std::process::exit(1);
+    }
+    let blueprint = BlueprintStrategy::load(&args[0]);
+    match write_ranges(&blueprint, &args[2..], &args[1]) {
+        Ok(exported) => println!("Wrote {} public states to {}", exported, args[1]),
+        Err(error) => {
+            println!("[ERROR] {}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// `gtcogs what-if <blueprint> <hands file> [<samples>]` re-evaluates the
+/// decisions of played hands under a new blueprint, see bot::what_if
+fn what_if_report(args : &[String]) {
+    let usage = "gtcogs what-if <blueprint> <hands file> [<samples>]";
+    if args.len() < 2 {
+        println!("Usage: {}", usage);
+        std::process::exit(1);
+    }
+    let samples : usize = match args.get(2) {
+        Some(_) => parse_or_exit(args.get(2), usage),
+        None => 8,
+    };
+    let blueprint = BlueprintStrategy::load(&args[0]);
+    let report = std::fs::read_to_string(&args[1])
+        .map_err(|error| format!("Could not read {}: {}", args[1], error))
+        .and_then(|contents| what_if(&blueprint, &contents, samples, 0));
+    match report {
+        Ok(report) => println!("{}", report),
+        Err(error) => {
+            println!("[ERROR] {}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// `gtcogs lbr <blueprint> [<hands>] [<particles>]` estimates a lower
+/// bound on the blueprint's exploitability, see bot::lbr
+fn lbr(args : &[String]) {
+    let usage = "gtcogs lbr <blueprint> [<hands>] [<particles>]";
+    if args.is_empty() {
+        println!("Usage: {}", usage);
+        std::process::exit(1);
+    }
+    let defaults = LbrConfig::default();
+    let config = LbrConfig {
+        hands : args.get(1).map(|_| parse_or_exit(args.get(1), usage)).unwrap_or(defaults.hands),
+        particles : args.get(2).map(|_| parse_or_exit(args.get(2), usage)).unwrap_or(defaults.particles),
+        ..defaults
+    };
+    let blueprint = BlueprintStrategy::load(&args[0]);
+    println!("{}", local_best_response(&blueprint, &config));
+}
+
+/// `gtcogs sanity <blueprint> [<hands>]` checks the blueprint against a
+/// few rules no strategy should break, see bot::sanity
+fn sanity(args : &[String]) {
+    let usage = "gtcogs sanity <blueprint> [<hands>]";
+    if args.is_empty() {
+        println!("Usage: {}", usage);
+        std::process::exit(1);
+    }
+    let hands : usize = match args.get(1) {
+        Some(_) => parse_or_exit(args.get(1), usage),
+        None => 1000,
+    };
+    let blueprint = BlueprintStrategy::load(&args[0]);
+    let report = check_sanity(&blueprint, hands, 0);
+    println!("{}", report);
+    if !report.is_ok() {
+        std::process::exit(1);
+    }
+}
+
+/// `gtcogs abstraction-loss <iterations> [<seed>] [<max deals>]
+/// [--sample-opponents]` measures what the production abstraction gives up
+/// on the reduced auction poker, see algorithm::abstraction_loss
+fn abstraction_loss(args : &[String]) {
+    let usage = "gtcogs abstraction-loss <iterations> [<seed>] [<max deals>] [--sample-opponents]";
+    let sample_opponents = args.iter().any(|arg| arg == "--sample-opponents");
+    let args : Vec<String> = args.iter().filter(|arg| *arg != "--sample-opponents").cloned().collect();
+    let iterations : usize = parse_or_exit(args.first(), usage);
+    let seed : u64 = match args.get(1) {
+        Some(_) => parse_or_exit(args.get(1), usage),
+        None => 0,
+    };
+    let mut training = Training::new(iterations, seed);
+    if args.get(2).is_some() {
+        training.max_deals = parse_or_exit(args.get(2), usage);
+    }
+    training.sample_opponents = sample_opponents;
+    let loss = measure_abstraction_loss::<MiniAuctionAction, MiniAuctionState<ReducedConfig>, MiniAuctionState<ReducedAbstractConfig>>(training);
+    println!("{}", loss);
+}
+
+/// `gtcogs constraints <games>` lists the limits on the legal actions the
+/// infosets of auction poker can't see, see game_logic::constraint_check
+fn constraints(args : &[String]) {
+    let games : usize = parse_or_exit(args.first(), "gtcogs constraints <games>");
+    let report = check_constraints::<AuctionPokerAction, AuctionPokerState>(games, 0, auction_constraints);
+    println!("{}", report);
+}
+
+/// `gtcogs conservation <hands> [seed]` plays random hands of auction
+/// poker looking for chips made or lost, see
+/// implementations::conservation
+fn conservation(args : &[String]) {
+    let usage = "gtcogs conservation <hands> [seed]";
+    let hands : usize = parse_or_exit(args.first(), usage);
+    let seed : u64 = match args.get(1) {
+        Some(_) => parse_or_exit(args.get(1), usage),
+        None => 0,
+    };
+    let report = check_conservation(hands, seed);
+    println!("{}", report);
+    if !report.is_ok() {
+        std::process::exit(1);
+    }
+}
+
+/// `gtcogs bid-buckets <buckets> <hands file>` fits the bid buckets to
+/// the bids of a file of hands, one in notation::parse_hand's notation a
+/// line, and prints them for the abstraction section of bot.toml
+fn bid_buckets_from(args : &[String]) {
+    let usage = "gtcogs bid-buckets <buckets> <hands file>";
+    let count : usize = parse_or_exit(args.first(), usage);
+    let file_name : String = parse_or_exit(args.get(1), usage);
+    let text = match std::fs::read_to_string(&file_name) {
+        Ok(text) => text,
+        Err(error) => {
+            println!("[ERROR] Cannot read {}: {}", file_name, error);
+            std::process::exit(1);
+        }
+    };
+    let mut bids = vec![];
+    for (number, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        match parse_hand(line.trim()) {
+            Ok(actions) => bids.extend(actions.iter().filter_map(|action| match action {
+                AuctionPokerAction::Bid(RelativeSize::Amount(chips)) => Some(*chips),
+                _ => None,
+            })),
+            Err(error) => println!("[WARNING] Skipping line {}: {}", number + 1, error),
+        }
+    }
+    let buckets = quantile_bid_buckets(&bids, count);
+    println!("{} bids in {} buckets", bids.len(), buckets.len());
+    println!("bid_buckets = {:?}", buckets);
+}
+
+/// Buckets the bids as the config says, before anything is indexed
+fn use_bid_buckets() {
+    if let Err(error) = set_bid_buckets(&bot_config().abstraction.bid_buckets) {
+        println!("[ERROR] {}", error);
+        std::process::exit(1);
+    }
+}
+
+/// `gtcogs estimate` prints how big the abstraction makes a strategy, see
+/// algorithm::estimate
+fn estimate() {
+    let estimate = AbstractionEstimate::current();
+    println!("{}", estimate);
+    if let Err(error) = estimate.check(&bot_config().limits()) {
+        println!("[WARNING] {}", error);
+    }
+}
+
+/// `gtcogs dry-run [<paths>]` samples paths through abstracted auction poker
+/// to count the infosets training will find, see algorithm::dry_run
+fn dry_run(args : &[String]) {
+    let usage = "gtcogs dry-run [<paths>]";
+    let iterations : usize = match args.first() {
+        Some(_) => parse_or_exit(args.first(), usage),
+        None => 10_000,
+    };
+    let game_mapper = GameMapper::new(None);
+    let mut rng = StdRng::from_entropy();
+    let report = run_dry_run::<AuctionPokerAction, AuctionPokerState, _>(&game_mapper, iterations, &mut rng);
+    println!("{}", report);
+}
+
+/// The config file, see config::BotConfig. Exits if it can't be used
+fn bot_config() -> BotConfig {
+    match BotConfig::from_environment() {
+        Ok(config) => config,
+        Err(error) => {
+            println!("[ERROR] {}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Exits before training an abstraction that won't fit
+fn check_limits(config : &BotConfig) {
+    if let Err(error) = AbstractionEstimate::current().check(&config.limits()) {
+        if !ignore_limits() && !config.abstraction.ignore_limits {
+            println!("[ERROR] {} (ignore_limits in the abstraction config or GTCOGS_IGNORE_LIMITS=1 to train anyway)", error);
+            std::process::exit(1);
+        }
+        println!("[WARNING] {}", error);
+    }
+}
+
+/// `gtcogs --smoke` trains, converts and plays on a toy scale to check the
+/// whole pipeline still works, see smoke
+fn smoke() {
+    // Every step is printed as it finishes
+    let report = smoke::run_smoke(&smoke::SmokeConfig::default());
+    if !report.passed() {
+        println!("Smoke test FAILED");
+        std::process::exit(1);
+    }
+    println!("Smoke test passed");
+}
+
+/// `gtcogs repl [<blueprint file>]` steps through hands interactively, see repl
+fn repl(args : &[String]) {
+    let blueprint = match args.first() {
+        Some(file_name) => BlueprintStrategy::load(file_name),
+        None => BlueprintStrategy::empty(),
+    };
+    repl::Repl::new(blueprint).run();
+}
+
 pub fn main() -> () {
-    let mut mcp = MCCFRParallel::<AuctionPokerAction, AuctionPokerState>::new(12, Some("auction_poker"));
-    mcp.run_iterations(110_000, 0.2);
-    mcp.write_to("auction_poker");
+    let args : Vec<String> = std::env::args().collect();
+    use_bid_buckets();
+    if args.get(1).map(|s| s.as_str()) == Some("convert") {
+        return convert(&args[2..]);
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("import") {
+        return import(&args[2..]);
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("coordinator") {
+        return coordinator(&args[2..]);
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("worker") {
+        return worker(&args[2..]);
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("play") {
+        return play(&args[2..]);
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("acpc") {
+        return acpc(&args[2..]);
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("ablate") {
+        return ablate(&args[2..]);
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("report") {
+        return report(&args[2..]);
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("streets") {
+        return streets(&args[2..]);
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("repl") {
+        return repl(&args[2..]);
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("auction-values") {
+        return auction_value(&args[2..]);
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("ranges") {
+        return ranges(&args[2..]);
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("what-if") {
+        return what_if_report(&args[2..]);
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("lbr") {
+        return lbr(&args[2..]);
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("sanity") {
+        return sanity(&args[2..]);
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("abstraction-loss") {
+        return abstraction_loss(&args[2..]);
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("constraints") {
+        return constraints(&args[2..]);
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("conservation") {
+        return conservation(&args[2..]);
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("bid-buckets") {
+        return bid_buckets_from(&args[2..]);
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("dry-run") {
+        return dry_run(&args[2..]);
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("estimate") {
+        return estimate();
+    }
+    if args.get(1).map(|s| s.as_str()) == Some("--smoke") {
+        return smoke();
+    }
+
+    let config = bot_config();
+    check_limits(&config);
+
+    let strategy = &config.paths.strategy;
+    let context = Context::from_config(&config).expect("validated with the config");
+    let mut mcp = MCCFRParallel::<AuctionPokerAction, AuctionPokerState>::new(config.training.threads, Some(strategy))
+        .with_context(context)
+        .with_checkpoint_ratio(config.training.checkpoint_ratio)
+        .with_max_depth(config.training.max_depth);
+    mcp.run_iterations(config.training.iterations, config.training.epsilon);
+    mcp.write_to(strategy);
     
-    let strat = BlueprintStrategy::load_from_json("auction_poker_p0.json",
-                                                  "auction_poker_p1.json");
-    strat.save_bincode("auction_poker.bp"); 
-    //let strat = BlueprintStrategy::load_bincode("auction_poker.bp");
+    convert(&[format!("{}_p0.json,{}_p1.json", strategy, strategy), config.paths.blueprint.clone()]);
+    //let strat = BlueprintStrategy::load("auction_poker.bp");
     ////loop {
         
     //}
@@ -36,10 +652,8 @@ pub fn main() -> () {
     //let mut mcp = MCCFRParallel::<KuhnPokerAction, KuhnPokerState>::new(10);
     //mcp.run_iterations(10_000, 0.2);
     //mcp.write_to("kuhn_poker");
-    //let strat = BlueprintStrategy::load_from_json("kuhn_poker_p0.json",
-                                                  //"kuhn_poker_p1.json");
-    //strat.save_bincode("kuhn_poker.bp"); 
-    //let strat = BlueprintStrategy::load_bincode("kuhn_poker.bp");
+    //convert(&["kuhn_poker_p0.json,kuhn_poker_p1.json".to_string(), "kuhn_poker.bp".to_string()]);
+    //let strat = BlueprintStrategy::load("kuhn_poker.bp");
     //loop {
         
     //}
@@ -0,0 +1,375 @@
+/// `gtcogs repl [<blueprint file>]` steps through an auction poker hand
+/// one action at a time and shows what the blueprint sees at every step:
+/// the info set, its features (EV features called out), and the policy
+/// stored for it (or the best fit if there is none).
+///
+/// Actions are typed in the hand notation of implementations::notation,
+/// starting with the hole cards (e.g. AsKs|4h5h), and tab completes the
+/// legal ones. Besides actions there are
+///
+///     undo        take back the last action
+///     line        print the hand so far, to paste into a test
+///     hand <line> start over from a whole hand
+///     deal        let chance deal the next card
+///     quit
+use crate::game_logic::action::{ActionIndex, Parsable};
+use crate::game_logic::game::Game;
+use crate::game_logic::schema::{FeatureKind, FeatureSchema};
+use crate::game_logic::state::ActivePlayer;
+use crate::game_logic::strategy::blueprint::BlueprintStrategy;
+use crate::game_logic::visibility::History;
+use crate::implementations::auction::*;
+use crate::implementations::notation::parse_hand;
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+use std::io::{self, Read, Write};
+
+type AuctionGame = Game<AuctionPokerAction, AuctionPokerState>;
+
+pub struct Repl {
+    blueprint: BlueprintStrategy,
+    /// What was typed so far, in notation
+    tokens: Vec<String>,
+}
+
+impl Repl {
+    pub fn new(blueprint: BlueprintStrategy) -> Self {
+        Repl { blueprint, tokens: vec![] }
+    }
+
+    pub fn line(&self) -> String {
+        self.tokens.join(" ")
+    }
+
+    fn game(&self) -> Result<AuctionGame, String> {
+        let mut game = AuctionGame::new();
+        if self.tokens.is_empty() {
+            return Ok(game);
+        }
+        for action in parse_hand(&self.line())? {
+            game.play(&action);
+        }
+        Ok(game)
+    }
+
+    /// Appends `token` to the hand if it is legal there
+    pub fn push(&mut self, token: &str) -> Result<(), String> {
+        self.tokens.push(token.to_string());
+        if let Err(error) = self.game() {
+            self.tokens.pop();
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    /// Handles one line of input, false once it's time to quit
+    pub fn command(&mut self, input: &str) -> bool {
+        let mut words = input.split_whitespace();
+        let result = match words.next() {
+            None => Ok(()),
+            Some("quit") | Some("exit") => return false,
+            Some("undo") => {
+                self.tokens.pop();
+                Ok(())
+            }
+            Some("line") => {
+                println!("{}", self.line());
+                return true;
+            }
+            Some("hand") => {
+                let line: Vec<String> = words.map(|word| word.to_string()).collect();
+                match parse_hand(&line.join(" ")) {
+                    Ok(_) => {
+                        self.tokens = line;
+                        Ok(())
+                    }
+                    Err(error) => Err(error),
+                }
+            }
+            Some("deal") => self.deal(),
+            Some(_) => input.split_whitespace().try_for_each(|token| self.push(token)),
+        };
+        match result {
+            Ok(()) => println!("{}", self.describe()),
+            Err(error) => println!("[ERROR] {}", error),
+        }
+        true
+    }
+
+    /// Plays a random deal if chance is next (hole cards are dealt all at once)
+    fn deal(&mut self) -> Result<(), String> {
+        let mut deck = Deck::empty().complement();
+        if let Ok(game) = self.game() {
+            deck = game.state().dealt().complement();
+        }
+        let mut cards: Vec<String> = deck.iter().map(|card| card.to_string().unwrap()).collect();
+        cards.shuffle(&mut thread_rng());
+        let mut cards = cards.into_iter();
+        let mut next = || cards.next().ok_or("The deck is empty".to_string());
+        if self.tokens.is_empty() {
+            let holes = format!("{}{}|{}{}", next()?, next()?, next()?, next()?);
+            return self.push(&holes);
+        }
+        match self.game()?.active_player() {
+            ActivePlayer::Chance(distribution) => {
+                let action = distribution.sample().clone();
+                match action {
+                    AuctionPokerAction::DealHole(card, _) => self.push(&format!("+{}", Card::from_index(card).to_string().unwrap())),
+                    AuctionPokerAction::DealCommunity(card) => self.push(&Card::from_index(card).to_string().unwrap()),
+                    x => Err(format!("Chance can't deal {:?}", x)),
+                }
+            }
+            _ => Err("It isn't chance's turn".to_string()),
+        }
+    }
+
+    /// The tokens that may come next
+    pub fn completions(&self) -> Vec<String> {
+        let game = match (self.tokens.is_empty(), self.game()) {
+            (false, Ok(game)) => game,
+            _ => return vec![],
+        };
+        let pot = game.state().pot();
+        game.active_player()
+            .actions()
+            .iter()
+            .filter_map(|action| match action {
+                AuctionPokerAction::Fold => Some("f".to_string()),
+                AuctionPokerAction::Call => Some("c".to_string()),
+                AuctionPokerAction::Check => Some("k".to_string()),
+                AuctionPokerAction::Raise(size) => Some(format!("r{}", size.to_amount(pot))),
+                AuctionPokerAction::Bid(size) => Some(format!("b{}", size.to_amount(pot))),
+                AuctionPokerAction::DealHole(card, _) => Some(format!("+{}", Card::from_index(*card).to_string().unwrap())),
+                AuctionPokerAction::DealCommunity(card) => Card::from_index(*card).to_string(),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// What the player to act sees and what the blueprint would do
+    pub fn describe(&self) -> String {
+        let game = match self.game() {
+            Ok(game) => game,
+            Err(error) => return format!("[ERROR] {}", error),
+        };
+        let mut lines = vec![format!("> {}", self.line())];
+        let state = game.state();
+        lines.push(format!("pot {} stacks {:?} pips {:?}", state.pot(), state.stacks(), state.pips()));
+        match game.active_player() {
+            ActivePlayer::Player(player_num, _) => {
+                let player_num = player_num as usize;
+                let info_set = game.get_information_set(player_num);
+                let history: History = info_set.into();
+                lines.push(format!("player {} info set {} {:?}", player_num, info_set, history.0));
+                if let Some(schema) = FeatureSchema::for_history(&history) {
+                    lines.push(format!("features {}", schema.pretty(&history)));
+                    let evs: Vec<String> = schema
+                        .features
+                        .iter()
+                        .zip(history.0.iter())
+                        .filter(|(spec, _)| matches!(spec.kind, FeatureKind::EV | FeatureKind::WinValue))
+                        .map(|(spec, value)| format!("{}={}", spec.name, value))
+                        .collect();
+                    if !evs.is_empty() {
+                        lines.push(format!("ev features {}", evs.join(" ")));
+                    }
+                }
+                lines.push(self.policy(&game, player_num));
+            }
+            ActivePlayer::Chance(_) if self.tokens.is_empty() => lines.push("hole cards next, e.g. AsKs|4h5h".to_string()),
+            ActivePlayer::Chance(_) => lines.push("chance deals next".to_string()),
+            ActivePlayer::Terminal(payoffs) => lines.push(format!("hand over, payoffs {:?}", payoffs)),
+            ActivePlayer::Marker(marker) => lines.push(format!("marker {:?}", marker)),
+        }
+        lines.join("\n")
+    }
+
+    fn policy(&self, game: &AuctionGame, player_num: usize) -> String {
+        let (source, policy) = match self.blueprint.get_exact_policy(game, player_num) {
+            Some(policy) => ("exact", Some(policy)),
+            None => ("best fit", self.blueprint.get_best_policy(game, player_num)),
+        };
+        let policy = match policy {
+            Some(policy) => policy,
+            None => return "no policy in the blueprint".to_string(),
+        };
+        let mut line = format!("{} policy", source);
+        for (index, probability) in policy {
+            if probability > 1e-3 {
+                let action: AuctionPokerAction = (index as ActionIndex).into();
+                line.push_str(&format!(" {}:{:.3}", action.to_string().unwrap_or(format!("{:?}", action)), probability));
+            }
+        }
+        line
+    }
+
+    /// Reads commands until quit or the end of input
+    pub fn run(&mut self) {
+        println!("{}", self.describe());
+        loop {
+            let input = match read_line("repl> ", &|| self.completions()) {
+                Some(input) => input,
+                None => return,
+            };
+            if !self.command(&input) {
+                return;
+            }
+        }
+    }
+}
+
+/// Puts the terminal in raw mode for as long as it lives
+#[cfg(unix)]
+struct RawMode {
+    original: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawMode {
+    fn enable() -> Option<RawMode> {
+        unsafe {
+            if libc::isatty(0) != 1 {
+                return None;
+            }
+            let mut original: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(0, &mut original) != 0 {
+                return None;
+            }
+            let mut raw = original;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            libc::tcsetattr(0, libc::TCSANOW, &raw);
+            Some(RawMode { original })
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(0, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+/// Without termios lines are read plainly, without completion
+#[cfg(not(unix))]
+struct RawMode;
+
+#[cfg(not(unix))]
+impl RawMode {
+    fn enable() -> Option<RawMode> {
+        None
+    }
+}
+
+/// The longest prefix all of `words` share
+fn common_prefix(words: &[&String]) -> String {
+    let first = match words.first() {
+        Some(first) => first.as_str(),
+        None => return String::new(),
+    };
+    let mut length = first.len();
+    for word in words {
+        length = length.min(first.bytes().zip(word.bytes()).take_while(|(a, b)| a == b).count());
+    }
+    first[..length].to_string()
+}
+
+/// One line from stdin, tab completing the last word from `completions`.
+/// None at the end of input
+fn read_line(prompt: &str, completions: &dyn Fn() -> Vec<String>) -> Option<String> {
+    print!("{}", prompt);
+    io::stdout().flush().ok();
+    let raw = match RawMode::enable() {
+        Some(raw) => raw,
+        None => {
+            let mut line = String::new();
+            return match io::stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => None,
+                Ok(_) => Some(line.trim().to_string()),
+            };
+        }
+    };
+    let mut line = String::new();
+    let mut byte = [0u8];
+    loop {
+        if io::stdin().read(&mut byte).ok()? == 0 {
+            return None;
+        }
+        match byte[0] {
+            b'\n' | b'\r' => {
+                println!();
+                break;
+            }
+            // Ctrl-D on an empty line, Ctrl-C
+            4 if line.is_empty() => return None,
+            3 => return None,
+            // Backspace
+            127 | 8 if !line.is_empty() => {
+                line.pop();
+                print!("\u{8} \u{8}");
+            }
+            b'\t' => {
+                let start = line.rfind(' ').map(|space| space + 1).unwrap_or(0);
+                let all = completions();
+                let matching: Vec<&String> = all.iter().filter(|word| word.starts_with(&line[start..])).collect();
+                let prefix = common_prefix(&matching);
+                if matching.len() == 1 {
+                    let rest = format!("{} ", &prefix[line.len() - start..]);
+                    print!("{}", rest);
+                    line.push_str(&rest);
+                } else if prefix.len() > line.len() - start {
+                    let rest = prefix[line.len() - start..].to_string();
+                    print!("{}", rest);
+                    line.push_str(&rest);
+                } else if !matching.is_empty() {
+                    let shown: Vec<&str> = matching.iter().take(40).map(|word| word.as_str()).collect();
+                    let more = if matching.len() > shown.len() { " ..." } else { "" };
+                    print!("\n{}{}\n{}{}", shown.join(" "), more, prompt, line);
+                }
+            }
+            byte if byte.is_ascii() && !byte.is_ascii_control() => {
+                line.push(byte as char);
+                print!("{}", byte as char);
+            }
+            _ => {}
+        }
+        io::stdout().flush().ok();
+    }
+    drop(raw);
+    Some(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repl_steps_through_a_hand() {
+        let mut repl = Repl::new(BlueprintStrategy::empty());
+        assert!(repl.completions().is_empty());
+        assert!(repl.command("AsKs|4h5h"));
+        let completions = repl.completions();
+        assert!(completions.contains(&"c".to_string()) && completions.contains(&"f".to_string()));
+        assert!(!completions.contains(&"k".to_string()));
+        assert!(completions.iter().any(|token| token.starts_with('r')));
+
+        // Illegal tokens are turned down, legal ones move the hand along
+        assert!(repl.push("k").is_err());
+        repl.push("c").unwrap();
+        repl.push("QsJsTs").unwrap();
+        assert!(repl.completions().iter().all(|token| token.starts_with('b')));
+        let description = repl.describe();
+        assert!(description.contains("player 1 info set"));
+        assert!(description.contains("ev features ev_if_lost="));
+        assert_eq!(repl.line(), "AsKs|4h5h c QsJsTs");
+
+        assert!(repl.command("undo"));
+        assert_eq!(repl.line(), "AsKs|4h5h c");
+        assert!(!repl.command("quit"));
+        assert_eq!(common_prefix(&[&"r10".to_string(), &"r12".to_string()]), "r1");
+    }
+}
@@ -0,0 +1,268 @@
+/// `gtcogs --smoke` runs the whole pipeline on a toy scale in under a
+/// minute, to check nothing broke after a refactor:
+///
+/// - train Kuhn poker for a few seconds
+/// - save the per player strategies and read them back
+/// - convert them into a blueprint file and read that back
+/// - play 100 hands of Kuhn from the blueprint's policies against a
+///   player choosing at random
+/// - play 100 hands of auction poker between two AuctionBots on the
+///   LocalEngine, through the agent and the decision engine
+///
+/// The auction bots play an empty blueprint, so every decision goes
+/// through the fallbacks, which is what a refactor is most likely to
+/// break. Every step reports what it did and how long it took, and the
+/// first one that fails ends the run
+use crate::algorithm::mccfr_parallel::MCCFRParallel;
+use crate::bot::ablation::blueprint_bot;
+use crate::bot::local_engine::LocalEngine;
+use crate::game_logic::action::{Action, ActionIndex};
+use crate::game_logic::game::Game;
+use crate::game_logic::state::ActivePlayer;
+use crate::game_logic::strategy::blueprint::{decompress_policy, BlueprintStrategy, SavedPolicies};
+use crate::game_logic::strategy::convert::convert_pipeline;
+use crate::game_logic::strategy::store::store_for;
+use crate::game_logic::strategy::{CondensedInfoSet, PolicyDistribution};
+use crate::implementations::one_card_poker::*;
+use crate::Utility;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+type Kuhn = Game<OneCardPokerAction, OneCardPokerState<KuhnConfig>>;
+
+#[derive(Debug, Clone)]
+pub struct SmokeConfig {
+    /// How long Kuhn trains for
+    pub training: Duration,
+    pub hands: usize,
+    /// Where the files go, removed when done
+    pub directory: std::path::PathBuf,
+    pub seed: u64,
+}
+
+impl Default for SmokeConfig {
+    fn default() -> Self {
+        SmokeConfig {
+            training: Duration::from_secs(3),
+            hands: 100,
+            directory: std::env::temp_dir().join("gtcogs_smoke"),
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub name: &'static str,
+    pub result: Result<String, String>,
+    pub time: Duration,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SmokeReport {
+    pub steps: Vec<Step>,
+}
+
+impl SmokeReport {
+    pub fn passed(&self) -> bool {
+        self.steps.iter().all(|step| step.result.is_ok())
+    }
+
+    /// Runs `step` unless an earlier one failed
+    fn run<T>(&mut self, name: &'static str, step: impl FnOnce() -> Result<(T, String), String>) -> Option<T> {
+        if !self.passed() {
+            return None;
+        }
+        let start = Instant::now();
+        let (value, result) = match step() {
+            Ok((value, summary)) => (Some(value), Ok(summary)),
+            Err(error) => (None, Err(error)),
+        };
+        let step = Step {
+            name,
+            result,
+            time: start.elapsed(),
+        };
+        println!("{}", step);
+        self.steps.push(step);
+        value
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.result {
+            Ok(summary) => write!(f, "[OK] {:<10} {} ({:.1?})", self.name, summary, self.time),
+            Err(error) => write!(f, "[FAILED] {:<10} {} ({:.1?})", self.name, error, self.time),
+        }
+    }
+}
+
+impl fmt::Display for SmokeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for step in &self.steps {
+            writeln!(f, "{}", step)?;
+        }
+        match self.passed() {
+            true => write!(f, "Smoke test passed"),
+            false => write!(f, "Smoke test FAILED"),
+        }
+    }
+}
+
+fn info_sets(policies: &SavedPolicies) -> Vec<usize> {
+    policies.iter().map(|player| player.len()).collect()
+}
+
+/// The average utility of `policies` over `hands` hands against a player
+/// choosing uniformly at random, and how many decisions they had a policy for
+fn play_kuhn(policies: &[BTreeMap<CondensedInfoSet, PolicyDistribution>], hands: usize, seed: u64) -> (Utility, usize) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut total = 0.0;
+    let mut looked_up = 0;
+    for hand in 0..hands {
+        // Take turns at being the first to act
+        let seat = hand % 2;
+        let mut game = Kuhn::new();
+        loop {
+            let action = match game.active_player() {
+                ActivePlayer::Terminal(utilities) => {
+                    total += utilities[seat];
+                    break;
+                }
+                ActivePlayer::Chance(chance) => chance.sample_rng(&mut rng),
+                ActivePlayer::Marker(action) => action,
+                ActivePlayer::Player(player_num, actions) => {
+                    let policy = match player_num as usize == seat {
+                        true => policies[seat].get(&game.get_information_set(seat)),
+                        false => None,
+                    };
+                    let weights: Vec<f32> = match policy {
+                        Some(policy) => {
+                            looked_up += 1;
+                            actions.iter().map(|action| policy[Into::<ActionIndex>::into(*action) as usize]).collect()
+                        }
+                        None => vec![1.0; actions.len()],
+                    };
+                    let total_weight: f32 = weights.iter().sum();
+                    let mut pick = rng.gen_range(0.0, total_weight.max(f32::MIN_POSITIVE));
+                    let index = weights.iter().position(|weight| {
+                        pick -= weight;
+                        pick < 0.0
+                    });
+                    actions[index.unwrap_or(actions.len() - 1)]
+                }
+            };
+            game.play(&action);
+        }
+    }
+    (total / hands.max(1) as Utility, looked_up)
+}
+
+pub fn run_smoke(config: &SmokeConfig) -> SmokeReport {
+    let mut report = SmokeReport::default();
+    let directory = &config.directory;
+    let _ = std::fs::remove_dir_all(directory);
+    if let Err(error) = std::fs::create_dir_all(directory) {
+        report.run("setup", || Err::<((), String), String>(format!("Cannot create {}: {}", directory.display(), error)));
+        return report;
+    }
+    let prefix = directory.join("kuhn").to_string_lossy().to_string();
+    let players = [format!("{}_p0.json", prefix), format!("{}_p1.json", prefix)];
+    let blueprint = format!("{}.bp", prefix);
+    let abstraction = OneCardPokerAction::abstraction_hash();
+
+    let trained = report.run("train", || {
+        let mut trainer = MCCFRParallel::<OneCardPokerAction, OneCardPokerState<KuhnConfig>>::new(2, None);
+        let start = Instant::now();
+        let mut iterations = 0;
+        while start.elapsed() < config.training {
+            trainer.run_iterations(2000, 0.2);
+            iterations += 2000;
+        }
+        let sizes: Vec<usize> = trainer.strategies().iter().map(|strategy| strategy.size()).collect();
+        if sizes.contains(&0) {
+            return Err(format!("Nothing trained after {} iterations", iterations));
+        }
+        let summary = format!("{} iterations, {:?} infosets", iterations, sizes);
+        Ok(((trainer, sizes), summary))
+    });
+
+    let saved = trained.and_then(|(trainer, sizes)| {
+        report.run("save", || {
+            trainer.write_to(&prefix);
+            let mut saved = vec![];
+            for file_name in &players {
+                let (_, policies) = store_for(file_name).read(file_name)?;
+                saved.extend(policies);
+            }
+            if info_sets(&saved) != sizes {
+                return Err(format!("Read back {:?} infosets, trained {:?}", info_sets(&saved), sizes));
+            }
+            Ok((saved, format!("{} read back", players.join(", "))))
+        })
+    });
+
+    let converted = saved.and_then(|saved| {
+        report.run("convert", || {
+            convert_pipeline(&players, std::slice::from_ref(&blueprint), abstraction)?;
+            let (found, converted) = store_for(&blueprint).read(&blueprint)?;
+            if found != Some(abstraction) || info_sets(&converted) != info_sets(&saved) {
+                return Err(format!("{} does not match the strategies it was converted from", blueprint));
+            }
+            Ok((converted, format!("{} read back", blueprint)))
+        })
+    });
+
+    converted.and_then(|converted| {
+        report.run("kuhn", || {
+            let policies: Vec<BTreeMap<CondensedInfoSet, PolicyDistribution>> = converted
+                .iter()
+                .map(|player| player.iter().map(|(info_set, policy)| (*info_set, decompress_policy(policy))).collect())
+                .collect();
+            let (utility, looked_up) = play_kuhn(&policies, config.hands, config.seed);
+            if looked_up == 0 {
+                return Err("The blueprint had no policy for any decision".to_string());
+            }
+            Ok(((), format!("{} hands, {} lookups, {:+.3} a hand against random", config.hands, looked_up, utility)))
+        })
+    });
+
+    report.run("arena", || {
+        let bot = || blueprint_bot(BlueprintStrategy::from_policies(vec![BTreeMap::new(), BTreeMap::new()]));
+        let result = LocalEngine::new(bot(), bot()).with_seed(config.seed).play_match(config.hands);
+        if result.hands != config.hands || result.panics != [0, 0] || result.illegal_actions != [0, 0] {
+            return Err(format!(
+                "Played {} of {} hands with {:?} panics and {:?} illegal actions",
+                result.hands, config.hands, result.panics, result.illegal_actions
+            ));
+        }
+        Ok(((), format!("auction, bot 0 won {}", result.win_rate(0))))
+    });
+
+    let _ = std::fs::remove_dir_all(directory);
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smoke_steps() {
+        let mut report = SmokeReport::default();
+        assert_eq!(report.run("first", || Ok((1, "done".to_string()))), Some(1));
+        assert_eq!(report.run("second", || Err::<((), String), String>("broken".to_string())), None);
+        // Nothing runs after a failure
+        assert_eq!(report.run("third", || Ok((3, "done".to_string()))), None);
+        assert_eq!(report.steps.len(), 2);
+        assert!(!report.passed());
+        assert!(report.to_string().contains("[FAILED] second"));
+
+        let policies = vec![BTreeMap::new(), BTreeMap::new()];
+        let (_, looked_up) = play_kuhn(&policies, 10, 0);
+        assert_eq!(looked_up, 0);
+    }
+}
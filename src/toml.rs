@@ -0,0 +1,339 @@
+/// Reads the part of TOML a config file needs into a serde_json::Value, so
+/// the config structs deserialize from TOML with the serde derives they
+/// already have (see config::BotConfig::parse).
+///
+/// Supported: `[table]` and `[dotted.table]` headers, bare, quoted and
+/// dotted keys, basic and literal strings, integers, floats, booleans,
+/// arrays (over several lines too) and inline tables, and `#` comments.
+/// Array of tables (`[[table]]`), multi-line strings and dates are not,
+/// and are turned down with the line they are on, as are keys given twice
+use serde_json::{Map, Value};
+
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser { chars: text.chars().collect(), position: 0, line: 1 };
+    let mut root = Map::new();
+    let mut table: Vec<String> = vec![];
+    let mut headers: Vec<Vec<String>> = vec![];
+    loop {
+        parser.skip_blank_lines();
+        match parser.peek() {
+            None => return Ok(Value::Object(root)),
+            Some('[') => {
+                parser.next();
+                if parser.peek() == Some('[') {
+                    return Err(parser.error("arrays of tables are not supported"));
+                }
+                table = parser.key()?;
+                parser.expect(']')?;
+                if headers.contains(&table) {
+                    return Err(parser.error(&format!("table [{}] is defined twice", table.join("."))));
+                }
+                descend(&mut root, &table, parser.line)?;
+                headers.push(table.clone());
+            }
+            Some(_) => {
+                let line = parser.line;
+                let key = parser.key()?;
+                parser.expect('=')?;
+                let value = parser.value()?;
+                let (last, parents) = key.split_last().unwrap();
+                let path: Vec<String> = table.iter().chain(parents).cloned().collect();
+                insert(descend(&mut root, &path, line)?, last, value, line)?;
+            }
+        }
+        parser.end_of_line()?;
+    }
+}
+
+/// The table at `path` under `root`, created if it isn't there yet
+fn descend<'a>(root: &'a mut Map<String, Value>, path: &[String], line: usize) -> Result<&'a mut Map<String, Value>, String> {
+    let mut table = root;
+    for key in path {
+        let entry = table.entry(key.clone()).or_insert_with(|| Value::Object(Map::new()));
+        table = match entry {
+            Value::Object(inner) => inner,
+            _ => return Err(format!("line {}: {} is not a table", line, key)),
+        };
+    }
+    Ok(table)
+}
+
+fn insert(table: &mut Map<String, Value>, key: &str, value: Value, line: usize) -> Result<(), String> {
+    if table.contains_key(key) {
+        return Err(format!("line {}: {} is given twice", line, key));
+    }
+    table.insert(key.to_string(), value);
+    Ok(())
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let next = self.peek()?;
+        self.position += 1;
+        if next == '\n' {
+            self.line += 1;
+        }
+        Some(next)
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("line {}: {}", self.line, message)
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.next();
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.next();
+            }
+        }
+    }
+
+    /// Whitespace, comments and newlines, as between the lines of a file
+    /// or the values of an array
+    fn skip_blank_lines(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n' | '\r') => {
+                    self.next();
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_spaces();
+        self.skip_comment();
+        if self.peek() == Some('\r') {
+            self.next();
+        }
+        match self.peek() {
+            None | Some('\n') => Ok(()),
+            Some(found) => Err(self.error(&format!("expected the end of the line, found '{}'", found))),
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_spaces();
+        match self.peek() {
+            Some(found) if found == expected => {
+                self.next();
+                Ok(())
+            }
+            Some(found) => Err(self.error(&format!("expected '{}', found '{}'", expected, found))),
+            None => Err(self.error(&format!("expected '{}', found the end of the file", expected))),
+        }
+    }
+
+    /// A possibly dotted key, as its parts
+    fn key(&mut self) -> Result<Vec<String>, String> {
+        let mut parts = vec![];
+        loop {
+            self.skip_spaces();
+            let part = match self.peek() {
+                Some('"') => self.basic_string()?,
+                Some('\'') => self.literal_string()?,
+                _ => {
+                    let start = self.position;
+                    while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                        self.next();
+                    }
+                    if start == self.position {
+                        return Err(self.error("expected a key"));
+                    }
+                    self.chars[start..self.position].iter().collect()
+                }
+            };
+            parts.push(part);
+            self.skip_spaces();
+            if self.peek() != Some('.') {
+                return Ok(parts);
+            }
+            self.next();
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_spaces();
+        match self.peek() {
+            Some('"') => Ok(Value::String(self.basic_string()?)),
+            Some('\'') => Ok(Value::String(self.literal_string()?)),
+            Some('[') => self.array(),
+            Some('{') => self.inline_table(),
+            Some(_) => self.scalar(),
+            None => Err(self.error("expected a value")),
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, String> {
+        self.next();
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => string.push(match self.next() {
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    other => return Err(self.error(&format!("unsupported escape \\{}", other.unwrap_or(' ')))),
+                }),
+                Some('\n') | None => return Err(self.error("unterminated string")),
+                Some(c) => string.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, String> {
+        self.next();
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('\'') => return Ok(string),
+                Some('\n') | None => return Err(self.error("unterminated string")),
+                Some(c) => string.push(c),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.next();
+        let mut values = vec![];
+        loop {
+            self.skip_blank_lines();
+            if self.peek() == Some(']') {
+                self.next();
+                return Ok(Value::Array(values));
+            }
+            values.push(self.value()?);
+            self.skip_blank_lines();
+            match self.next() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(values)),
+                _ => return Err(self.error("expected ',' or ']' in an array")),
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value, String> {
+        self.next();
+        let mut table = Map::new();
+        self.skip_spaces();
+        if self.peek() == Some('}') {
+            self.next();
+            return Ok(Value::Object(table));
+        }
+        loop {
+            let line = self.line;
+            let key = self.key()?;
+            self.expect('=')?;
+            let value = self.value()?;
+            let (last, parents) = key.split_last().unwrap();
+            insert(descend(&mut table, parents, line)?, last, value, line)?;
+            self.skip_spaces();
+            match self.next() {
+                Some(',') => {}
+                Some('}') => return Ok(Value::Object(table)),
+                _ => return Err(self.error("expected ',' or '}' in an inline table")),
+            }
+        }
+    }
+
+    /// A boolean or a number, anything else is an error
+    fn scalar(&mut self) -> Result<Value, String> {
+        let start = self.position;
+        while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+' | '.')) {
+            self.next();
+        }
+        let word: String = self.chars[start..self.position].iter().collect();
+        match word.as_str() {
+            "true" => return Ok(Value::Bool(true)),
+            "false" => return Ok(Value::Bool(false)),
+            _ => {}
+        }
+        let number = word.replace('_', "");
+        if let Ok(integer) = number.parse::<i64>() {
+            return Ok(Value::from(integer));
+        }
+        match number.parse::<f64>() {
+            Ok(float) if float.is_finite() => Ok(Value::from(float)),
+            _ if word.is_empty() => Err(self.error(&format!("expected a value, found '{}'", self.peek().unwrap_or(' ')))),
+            _ => Err(self.error(&format!("'{}' is not a value", word))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_config_like_toml() {
+        let text = r#"
+            # Comments go anywhere
+            top = "level"
+
+            [training]
+            threads = 8 # Trailing comments too
+            epsilon = 0.2
+            iterations = 110_000
+            resume = false
+
+            [evaluator.auction]
+            fit = [
+                "exact",
+                'range -2 2',
+            ]
+            weights = [1, 2.5]
+            options = { top_k = 3, shape = "capped 0.5" }
+            "quoted key".dotted = -1
+        "#;
+        assert_eq!(
+            parse(text).unwrap(),
+            json!({
+                "top": "level",
+                "training": { "threads": 8, "epsilon": 0.2, "iterations": 110000, "resume": false },
+                "evaluator": { "auction": {
+                    "fit": ["exact", "range -2 2"],
+                    "weights": [1, 2.5],
+                    "options": { "top_k": 3, "shape": "capped 0.5" },
+                    "quoted key": { "dotted": -1 }
+                } }
+            })
+        );
+        assert_eq!(parse("").unwrap(), json!({}));
+    }
+
+    #[test]
+    fn test_mistakes_give_the_line() {
+        let error = |text: &str| parse(text).unwrap_err();
+        assert!(error("[training]\nthreads = 4\nthreads = 5").starts_with("line 3"));
+        assert!(error("[training]\n\nthreads = four").starts_with("line 3"));
+        assert!(error("a = 1\n[a]").contains("not a table"));
+        assert!(error("[a]\n[a]").contains("twice"));
+        assert!(error("[[a]]").contains("not supported"));
+        assert!(error("a = \"open").contains("unterminated"));
+        assert!(error("a = 1 2").starts_with("line 1"));
+        assert!(error("a = [1, 2").contains("array"));
+    }
+}
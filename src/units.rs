@@ -0,0 +1,108 @@
+/// The units amounts of chips show up in, and the conversions between them.
+///
+/// Terminal utilities are chip deltas, bet sizes are fractions of the pot
+/// (or DeciPercent, thousandths of it), and results are best read in big
+/// blinds per 100 hands. Converting through these types instead of by hand
+/// keeps a number from silently changing meaning between two modules
+use crate::constants::BIG_BLIND;
+use crate::Utility;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+pub struct Chips(pub f32);
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+pub struct BigBlinds(pub f32);
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+pub struct PotFraction(pub f32);
+
+impl Chips {
+    pub fn to_big_blinds(self) -> BigBlinds {
+        BigBlinds(self.0 / BIG_BLIND as f32)
+    }
+
+    /// As a share of a pot of `pot` chips
+    pub fn of_pot(self, pot: u32) -> PotFraction {
+        PotFraction(self.0 / pot as f32)
+    }
+
+    /// The utility of winning (or losing, if negative) this many chips.
+    /// Utilities are always in chips
+    pub fn utility(self) -> Utility {
+        self.0 as Utility
+    }
+
+    pub fn round(self) -> u32 {
+        self.0.round() as u32
+    }
+}
+
+impl PotFraction {
+    pub fn from_deci_percent(deci_percent: u32) -> Self {
+        PotFraction(deci_percent as f32 / 1000.0)
+    }
+
+    pub fn to_deci_percent(self) -> u32 {
+        (self.0 * 1000.0).round() as u32
+    }
+
+    /// That share of a pot of `pot` chips
+    pub fn of(self, pot: u32) -> Chips {
+        Chips(pot as f32 * self.0)
+    }
+}
+
+/// Chips won over some number of hands
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct WinRate {
+    pub chips: i64,
+    pub hands: usize,
+}
+
+impl WinRate {
+    pub fn new(chips: i64, hands: usize) -> Self {
+        WinRate { chips, hands }
+    }
+
+    pub fn big_blinds_per_hand(&self) -> f32 {
+        if self.hands == 0 {
+            return 0.0;
+        }
+        Chips(self.chips as f32).to_big_blinds().0 / self.hands as f32
+    }
+
+    /// Big blinds won per 100 hands (bb/100)
+    pub fn bb_per_100(&self) -> f32 {
+        100.0 * self.big_blinds_per_hand()
+    }
+
+    /// Thousandths of a big blind won per hand (mbb/hand)
+    pub fn mbb_per_hand(&self) -> f32 {
+        1000.0 * self.big_blinds_per_hand()
+    }
+}
+
+impl fmt::Display for WinRate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} hands, {:+} chips ({:+.1} bb/100)", self.hands, self.chips, self.bb_per_100())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(Chips(10.0).to_big_blinds(), BigBlinds(5.0));
+        assert_eq!(Chips(15.0).of_pot(10).to_deci_percent(), 1500);
+        assert_eq!(PotFraction::from_deci_percent(750).of(20), Chips(15.0));
+
+        let rate = WinRate::new(-40, 200);
+        assert_eq!(rate.bb_per_100(), -10.0);
+        assert_eq!(rate.mbb_per_hand(), -100.0);
+        assert_eq!(WinRate::default().bb_per_100(), 0.0);
+        assert_eq!(rate.to_string(), "200 hands, -40 chips (-10.0 bb/100)");
+    }
+}